@@ -0,0 +1,273 @@
+//! `AsyncDatabase`, the database for asyncio. Its calls run on the blocking
+//! threads of the tokio runtime `pyo3_async_runtimes` keeps, so the event
+//! loop never waits on a read from disk or a WAL sync.
+
+use crate::{close_handle, to_py_err, with_db, CoreDatabase, CoreWriteBatch, Handle};
+use crate::{Database, DatabaseStats, ScanIterator, WriteBatch, SCAN_BATCH_SIZE};
+use crate::{DatabaseClosedError, IOError, InvalidArgumentError};
+use pyo3::exceptions::{PyStopAsyncIteration, PyTypeError, PyValueError};
+use pyo3::panic::PanicException;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use std::sync::{Arc, Mutex};
+use tokio::sync::Semaphore;
+
+/// Calls an `AsyncDatabase` runs at once, unless told otherwise.
+const MAX_IN_FLIGHT: usize = 64;
+
+fn closed() -> PyErr {
+    DatabaseClosedError::new_err("Database is closed")
+}
+
+/// An awaitable for `f`, run on a blocking thread once a permit from
+/// `in_flight` is free. Fails with `DatabaseClosedError` if the semaphore
+/// is closed first.
+fn run<'py, T>(
+    py: Python<'py>,
+    in_flight: &Arc<Semaphore>,
+    f: impl FnOnce() -> PyResult<T> + Send + 'static,
+) -> PyResult<Bound<'py, PyAny>>
+where
+    T: for<'a> IntoPyObject<'a> + Send + 'static,
+{
+    let in_flight = Arc::clone(in_flight);
+    pyo3_async_runtimes::tokio::future_into_py(py, async move {
+        let _permit = in_flight.acquire_owned().await.map_err(|_| closed())?;
+        tokio::task::spawn_blocking(f)
+            .await
+            .map_err(|e| PanicException::new_err(e.to_string()))?
+    })
+}
+
+/// An awaitable done already, holding `value`, for what needs no thread.
+fn ready<'py, T: IntoPyObject<'py>>(py: Python<'py>, value: T) -> PyResult<Bound<'py, PyAny>> {
+    let future = pyo3_async_runtimes::get_running_loop(py)?.call_method0("create_future")?;
+    future.call_method1("set_result", (value,))?;
+    Ok(future)
+}
+
+/// A `Database` whose methods return awaitables. `db` is a path, opened
+/// with `options` as `Database` takes them, or a `Database` already open.
+/// At most `max_in_flight` calls run at once; more wait their turn without
+/// holding a thread.
+#[pyclass]
+pub(crate) struct AsyncDatabase {
+    /// The database wrapped, for what it has that this doesn't.
+    #[pyo3(get)]
+    db: Py<Database>,
+    handle: Handle,
+    read_only: bool,
+    /// A permit for each call running. Closing the database closes it,
+    /// failing the calls still waiting for one.
+    in_flight: Arc<Semaphore>,
+}
+
+impl AsyncDatabase {
+    /// An awaitable for `f`, run on the database as `run` runs it.
+    fn call<'py, T>(
+        &self,
+        py: Python<'py>,
+        f: impl FnOnce(&CoreDatabase) -> PyResult<T> + Send + 'static,
+    ) -> PyResult<Bound<'py, PyAny>>
+    where
+        T: for<'a> IntoPyObject<'a> + Send + 'static,
+    {
+        let handle = Arc::clone(&self.handle);
+        run(py, &self.in_flight, move || with_db(&handle, f))
+    }
+    
+    fn check_writable(&self) -> PyResult<()> {
+        match self.read_only {
+            true => Err(IOError::new_err("Database is read-only")),
+            false => Ok(()),
+        }
+    }
+}
+
+#[pymethods]
+impl AsyncDatabase {
+    #[new]
+    #[pyo3(signature = (db, *, max_in_flight=MAX_IN_FLIGHT, **options))]
+    fn new(
+        py: Python<'_>,
+        db: &Bound<'_, PyAny>,
+        max_in_flight: usize,
+        options: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<Self> {
+        if max_in_flight == 0 {
+            return Err(PyValueError::new_err("max_in_flight must be at least 1"));
+        }
+        let db = match db.downcast::<Database>() {
+            Ok(_) if options.is_some_and(|options| !options.is_empty()) => {
+                return Err(PyTypeError::new_err(
+                    "options can't be given for a database already open",
+                ));
+            }
+            Ok(db) => db.clone().unbind(),
+            Err(_) => Py::new(py, Database::new(db.extract()?, options)?)?,
+        };
+        let (handle, read_only) = {
+            let db = db.borrow(py);
+            (Arc::clone(&db.db), db.read_only)
+        };
+        let permits = max_in_flight.min(Semaphore::MAX_PERMITS);
+        
+        Ok(AsyncDatabase { db, handle, read_only, in_flight: Arc::new(Semaphore::new(permits)) })
+    }
+    
+    fn get<'py>(&self, py: Python<'py>, key: Vec<u8>) -> PyResult<Bound<'py, PyAny>> {
+        self.call(py, move |db| db.get(&key).map_err(to_py_err))
+    }
+    
+    fn put<'py>(
+        &self,
+        py: Python<'py>,
+        key: Vec<u8>,
+        value: Vec<u8>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        self.check_writable()?;
+        self.call(py, move |db| db.put(key, value).map_err(to_py_err))
+    }
+    
+    fn delete<'py>(&self, py: Python<'py>, key: Vec<u8>) -> PyResult<Bound<'py, PyAny>> {
+        self.check_writable()?;
+        self.call(py, move |db| db.delete(key).map_err(to_py_err))
+    }
+    
+    /// `Database.write`, marking the batch written once it is.
+    fn write<'py>(&self, py: Python<'py>, batch: Py<WriteBatch>) -> PyResult<Bound<'py, PyAny>> {
+        self.check_writable()?;
+        let ops = {
+            let batch = batch.borrow(py);
+            if batch.batch.is_empty() {
+                return ready(py, ());
+            }
+            if batch.written {
+                return Err(InvalidArgumentError::new_err(
+                    "Batch already written; clear() it to use it again",
+                ));
+            }
+            batch.batch.clone()
+        };
+        let handle = Arc::clone(&self.handle);
+        run(py, &self.in_flight, move || {
+            with_db(&handle, |db| db.write(ops).map_err(to_py_err))?;
+            Python::with_gil(|py| {
+                batch.try_borrow_mut(py)?.written = true;
+                Ok(())
+            })
+        })
+    }
+    
+    fn write_many<'py>(
+        &self,
+        py: Python<'py>,
+        items: Vec<(Vec<u8>, Vec<u8>)>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        self.check_writable()?;
+        let mut batch = CoreWriteBatch::new();
+        for (key, value) in items {
+            batch.put(key, value);
+        }
+        self.call(py, move |db| db.write(batch).map_err(to_py_err))
+    }
+    
+    /// `(key, value)` pairs as `Database.scan` yields them, for `async for`.
+    /// Each batch of `batch_size` is read on a blocking thread.
+    #[pyo3(signature = (start=None, end=None, reverse=false, batch_size=SCAN_BATCH_SIZE))]
+    fn scan(
+        &self,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+        reverse: bool,
+        batch_size: usize,
+    ) -> PyResult<AsyncScanIterator> {
+        if batch_size == 0 {
+            return Err(InvalidArgumentError::new_err("batch_size must be at least 1"));
+        }
+        let (start, end) = (start.unwrap_or_default(), end.unwrap_or_default());
+        let mut scan = ScanIterator::new(
+            Arc::clone(&self.handle),
+            false,
+            start.to_vec(),
+            end.to_vec(),
+            reverse,
+        );
+        scan.batch_size = batch_size;
+        
+        Ok(AsyncScanIterator {
+            scan: Arc::new(Mutex::new(scan)),
+            in_flight: Arc::clone(&self.in_flight),
+        })
+    }
+    
+    fn stats(&self, py: Python<'_>) -> PyResult<DatabaseStats> {
+        self.db.borrow(py).stats()
+    }
+    
+    /// Close the database once the calls already running finish. Calls
+    /// still waiting for a permit fail with `DatabaseClosedError`, as do
+    /// those made after. Closing again does nothing.
+    fn close<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        self.in_flight.close();
+        let (handle, read_only) = (Arc::clone(&self.handle), self.read_only);
+        pyo3_async_runtimes::tokio::future_into_py(py, async move {
+            tokio::task::spawn_blocking(move || close_handle(&handle, read_only))
+                .await
+                .map_err(|e| PanicException::new_err(e.to_string()))?
+        })
+    }
+    
+    fn __aenter__<'py>(slf: Bound<'py, Self>) -> PyResult<Bound<'py, PyAny>> {
+        ready(slf.py(), slf)
+    }
+    
+    fn __aexit__<'py>(
+        &self,
+        py: Python<'py>,
+        _exc_type: PyObject,
+        _exc_value: PyObject,
+        _traceback: PyObject,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        self.close(py)
+    }
+}
+
+/// A range of the database for `async for`, yielded as `(key, value)`
+/// tuples of bytes.
+#[pyclass]
+pub(crate) struct AsyncScanIterator {
+    scan: Arc<Mutex<ScanIterator>>,
+    in_flight: Arc<Semaphore>,
+}
+
+#[pymethods]
+impl AsyncScanIterator {
+    fn __aiter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+    
+    fn __anext__<'py>(&self, py: Python<'py>) -> PyResult<Option<Bound<'py, PyAny>>> {
+        // Only reading a batch is worth a thread; entries read already are
+        // handed back at once.
+        let buffered = {
+            let mut scan = self.scan.lock().unwrap();
+            match scan.buffer.is_empty() && !scan.done {
+                true => None,
+                false => Some(scan.next_entry()?),
+            }
+        };
+        match buffered {
+            Some(None) => Ok(None),
+            Some(Some(entry)) => ready(py, entry).map(Some),
+            None => {
+                let scan = Arc::clone(&self.scan);
+                run(py, &self.in_flight, move || {
+                    scan.lock().unwrap().next_entry()?
+                        .ok_or_else(|| PyStopAsyncIteration::new_err(()))
+                })
+                .map(Some)
+            }
+        }
+    }
+}
@@ -1,86 +1,937 @@
-use middb_core::{Config, Database as CoreDatabase};
-use pyo3::exceptions::{PyIOError, PyRuntimeError};
+use middb_core::{CompactionStyle, Config, Database as CoreDatabase, Error as CoreError};
+use middb_core::{Level, TxnId, WriteBatch as CoreWriteBatch};
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyKeyError, PyTypeError};
 use pyo3::prelude::*;
-use pyo3::types::PyBytes;
+use pyo3::types::{PyBytes, PyDict, PyList, PyTuple, PyType};
+use std::collections::VecDeque;
 use std::path::PathBuf;
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+mod aio;
+
+/// Entries a scan reads from the database at a time.
+const SCAN_BATCH_SIZE: usize = 256;
+
+create_exception!(middb, Error, PyException, "Base class of every error MidDB raises.");
+create_exception!(middb, CorruptionError, Error, "A database file can't be read as written.");
+create_exception!(middb, InvalidArgumentError, Error, "An argument or option MidDB can't use.");
+create_exception!(middb, DatabaseClosedError, Error, "The database was used after it was closed.");
+create_exception!(middb, TxnConflictError, Error, "A transaction clashed with another; retry it.");
+create_exception!(middb, IOError, Error, "Reading or writing the database's files failed.");
+
+/// The Python exception for `e`, with its message. Transaction failures
+/// reach here already folded into `CoreError` by the database.
+fn to_py_err(e: CoreError) -> PyErr {
+    let message = e.to_string();
+    match e {
+        CoreError::Corruption(_) | CoreError::Serialization(_) => {
+            CorruptionError::new_err(message)
+        }
+        CoreError::InvalidArgument(_) | CoreError::InvalidConfig(_) => {
+            InvalidArgumentError::new_err(message)
+        }
+        CoreError::TransactionConflict
+        | CoreError::TransactionTimedOut
+        | CoreError::SnapshotTooOld => TxnConflictError::new_err(message),
+        CoreError::Io(_) | CoreError::StorageFull | CoreError::Locked(_) => {
+            IOError::new_err(message)
+        }
+        CoreError::KeyNotFound | CoreError::SnapshotRequired(_) | CoreError::Internal(_) => {
+            Error::new_err(message)
+        }
+    }
+}
+
+/// The open database, shared with the iterators reading it; `None` once
+/// it's closed.
+type Handle = Arc<RwLock<Option<CoreDatabase>>>;
+
+/// Run `f` on the database `handle` holds, or fail if it's been closed.
+fn with_db<T>(handle: &Handle, f: impl FnOnce(&CoreDatabase) -> PyResult<T>) -> PyResult<T> {
+    let guard = handle.read().unwrap();
+    let db = guard.as_ref()
+        .ok_or_else(|| DatabaseClosedError::new_err("Database is closed"))?;
+    f(db)
+}
+
+/// Close the database `handle` holds, if it's still open. Iterators still
+/// open hold the handle, not the database, so it closes all the same and
+/// they fail from then on. Calls from other threads finish first.
+/// Read-only, it's dropped rather than closed, which would flush what the
+/// WAL held.
+fn close_handle(handle: &Handle, read_only: bool) -> PyResult<()> {
+    match handle.write().unwrap().take().filter(|_| !read_only) {
+        Some(db) => db.close().map_err(to_py_err),
+        None => Ok(()),
+    }
+}
+
+/// `with_db`, with the GIL released unless `release_gil` is off, so other
+/// Python threads run while this one waits on the database. `f` mustn't
+/// touch Python objects; copy keys and values out first.
+fn with_db_nogil<T: Send>(
+    py: Python<'_>,
+    release_gil: bool,
+    handle: &Handle,
+    f: impl FnOnce(&CoreDatabase) -> PyResult<T> + Send,
+) -> PyResult<T> {
+    match release_gil {
+        true => py.allow_threads(|| with_db(handle, f)),
+        false => with_db(handle, f),
+    }
+}
+
+/// `obj`'s bytes, for a key or value, or a `TypeError` saying what it was
+/// instead.
+fn bytes_arg(what: &str, obj: &Bound<'_, PyAny>) -> PyResult<Vec<u8>> {
+    match obj.downcast::<PyBytes>() {
+        Ok(bytes) => Ok(bytes.as_bytes().to_vec()),
+        Err(_) => {
+            let type_name = obj.get_type().name()?;
+            Err(PyTypeError::new_err(format!("{} must be bytes, not {}", what, type_name)))
+        }
+    }
+}
+
+/// Set `config`'s option `name`, given as a keyword argument to `Database`,
+/// to `value`. A value of the wrong type or out of range names the option.
+fn set_option(config: &mut Config, name: &str, value: &Bound<'_, PyAny>) -> PyResult<()> {
+    let invalid = |e: PyErr| InvalidArgumentError::new_err(format!("{}: {}", name, e));
+    let choice = |choices: &[&str]| -> PyResult<String> {
+        let choice: String = value.extract().map_err(invalid)?;
+        match choices.contains(&choice.as_str()) {
+            true => Ok(choice),
+            false => Err(InvalidArgumentError::new_err(format!(
+                "{}: {:?} isn't one of {:?}", name, choice, choices
+            ))),
+        }
+    };
+    match name {
+        "memtable_size" => config.memtable_size = value.extract().map_err(invalid)?,
+        "block_size" => config.block_size = value.extract().map_err(invalid)?,
+        "bloom_bits_per_key" => config.bloom_bits_per_key = value.extract().map_err(invalid)?,
+        "use_compression" => config.use_compression = value.extract().map_err(invalid)?,
+        "level0_file_num_compaction_trigger" => {
+            config.level0_file_num_compaction_trigger = value.extract().map_err(invalid)?
+        }
+        "max_bytes_for_level_base" => {
+            config.max_bytes_for_level_base = value.extract().map_err(invalid)?
+        }
+        "compaction_style" => {
+            config.compaction_style = match choice(&["leveled", "universal"])?.as_str() {
+                "leveled" => CompactionStyle::Leveled,
+                _ => CompactionStyle::Universal,
+            }
+        }
+        "wal_sync" => config.sync_writes = choice(&["always", "never"])? == "always",
+        "txn_timeout" => {
+            let secs: f64 = value.extract().map_err(invalid)?;
+            config.txn_timeout = Duration::try_from_secs_f64(secs)
+                .map_err(|e| InvalidArgumentError::new_err(format!("{}: {}", name, e)))?;
+        }
+        "wal_dir" => config.wal_dir = value.extract().map_err(invalid)?,
+        _ => {
+            return Err(PyTypeError::new_err(format!(
+                "Database() got an unexpected keyword argument '{}'", name
+            )))
+        }
+    }
+    Ok(())
+}
+
+/// `config`'s options, named as `Database`'s keyword arguments are, with
+/// the path as `data_dir`.
+fn config_dict<'py>(
+    py: Python<'py>,
+    config: &Config,
+    read_only: bool,
+    release_gil: bool,
+) -> PyResult<Bound<'py, PyDict>> {
+    let dict = PyDict::new(py);
+    dict.set_item("data_dir", config.data_dir.to_string_lossy())?;
+    dict.set_item("wal_dir", config.wal_dir.to_string_lossy())?;
+    dict.set_item("memtable_size", config.memtable_size)?;
+    dict.set_item("block_size", config.block_size)?;
+    dict.set_item("bloom_bits_per_key", config.bloom_bits_per_key)?;
+    dict.set_item("use_compression", config.use_compression)?;
+    dict.set_item("level0_file_num_compaction_trigger", config.level0_file_num_compaction_trigger)?;
+    dict.set_item("max_bytes_for_level_base", config.max_bytes_for_level_base)?;
+    let compaction_style = match config.compaction_style {
+        CompactionStyle::Leveled => "leveled",
+        CompactionStyle::Universal => "universal",
+    };
+    dict.set_item("compaction_style", compaction_style)?;
+    dict.set_item("wal_sync", if config.sync_writes { "always" } else { "never" })?;
+    dict.set_item("txn_timeout", config.txn_timeout.as_secs_f64())?;
+    dict.set_item("read_only", read_only)?;
+    dict.set_item("release_gil", release_gil)?;
+    Ok(dict)
+}
 
 #[pyclass]
 struct Database {
-    db: Option<Arc<CoreDatabase>>,
+    db: Handle,
+    config: Config,
+    /// Writes are refused, and closing writes nothing.
+    read_only: bool,
+    /// Whether calls into the database release the GIL. Only worth turning
+    /// off to compare.
+    release_gil: bool,
+}
+
+impl Database {
+    fn with_db<T: Send>(
+        &self,
+        py: Python<'_>,
+        f: impl FnOnce(&CoreDatabase) -> PyResult<T> + Send,
+    ) -> PyResult<T> {
+        with_db_nogil(py, self.release_gil, &self.db, f)
+    }
+    
+    fn check_writable(&self) -> PyResult<()> {
+        match self.read_only {
+            true => Err(IOError::new_err("Database is read-only")),
+            false => Ok(()),
+        }
+    }
+    
+    fn iter_prefix(
+        &self,
+        prefix: Option<&[u8]>,
+        batch_size: usize,
+        yields: Yields,
+    ) -> PyResult<ScanIterator> {
+        if batch_size == 0 {
+            return Err(InvalidArgumentError::new_err("batch_size must be at least 1"));
+        }
+        let prefix = prefix.unwrap_or_default();
+        let end = prefix_end(prefix);
+        let mut iter = ScanIterator::new(
+            Arc::clone(&self.db),
+            self.release_gil,
+            prefix.to_vec(),
+            end,
+            false,
+        );
+        iter.batch_size = batch_size;
+        iter.yields = yields;
+        Ok(iter)
+    }
 }
 
 #[pymethods]
 impl Database {
+    /// Open the database at `path`, creating it unless `read_only`. Other
+    /// keyword arguments set options, named as `config`'s keys are.
     #[new]
-    fn new(path: String) -> PyResult<Self> {
-        let config = Config::new(PathBuf::from(path));
-        let db = CoreDatabase::open(config)
-            .map_err(|e| PyRuntimeError::new_err(format!("Failed to open database: {}", e)))?;
+    #[pyo3(signature = (path, **options))]
+    fn new(path: String, options: Option<&Bound<'_, PyDict>>) -> PyResult<Self> {
+        let mut config = Config::new(PathBuf::from(path));
+        let (mut read_only, mut release_gil) = (false, true);
+        for (name, value) in options.into_iter().flatten() {
+            let name: String = name.extract()?;
+            let flag = || {
+                value.extract()
+                    .map_err(|e| InvalidArgumentError::new_err(format!("{}: {}", name, e)))
+            };
+            match name.as_str() {
+                "read_only" => read_only = flag()?,
+                "release_gil" => release_gil = flag()?,
+                _ => set_option(&mut config, &name, &value)?,
+            }
+        }
+        config.validate().map_err(|e| to_py_err(CoreError::InvalidConfig(e)))?;
+        if read_only && !config.data_dir.is_dir() {
+            let path = config.data_dir.display();
+            return Err(IOError::new_err(format!("No database at {}", path)));
+        }
+        let db = CoreDatabase::open(config.clone()).map_err(to_py_err)?;
         
-        Ok(Database { db: Some(Arc::new(db)) })
+        Ok(Database { db: Arc::new(RwLock::new(Some(db))), config, read_only, release_gil })
     }
     
-    fn put(&self, key: &[u8], value: &[u8]) -> PyResult<()> {
-        let db = self.db.as_ref()
-            .ok_or_else(|| PyRuntimeError::new_err("Database is closed"))?;
-        
-        db.put(key.to_vec(), value.to_vec())
-            .map_err(|e| PyIOError::new_err(format!("Put failed: {}", e)))
+    /// The same as `Database(path, **options)`.
+    #[classmethod]
+    #[pyo3(signature = (path, **options))]
+    fn open(
+        _cls: &Bound<'_, PyType>,
+        path: String,
+        options: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<Self> {
+        Self::new(path, options)
+    }
+    
+    /// The options the database was opened with, as a dict.
+    #[getter]
+    fn config<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        config_dict(py, &self.config, self.read_only, self.release_gil)
+    }
+    
+    fn put(&self, py: Python<'_>, key: &[u8], value: &[u8]) -> PyResult<()> {
+        self.check_writable()?;
+        let (key, value) = (key.to_vec(), value.to_vec());
+        self.with_db(py, |db| db.put(key, value).map_err(to_py_err))
     }
     
     fn get<'py>(&self, py: Python<'py>, key: &[u8]) -> PyResult<Option<Bound<'py, PyBytes>>> {
-        let db = self.db.as_ref()
-            .ok_or_else(|| PyRuntimeError::new_err("Database is closed"))?;
-        
-        match db.get(&key.to_vec()) {
-            Ok(Some(value)) => Ok(Some(PyBytes::new_bound(py, &value))),
-            Ok(None) => Ok(None),
-            Err(e) => Err(PyIOError::new_err(format!("Get failed: {}", e))),
+        let key = key.to_vec();
+        let value = self.with_db(py, |db| db.get(&key).map_err(to_py_err))?;
+        Ok(value.map(|value| PyBytes::new(py, &value)))
+    }
+    
+    fn delete(&self, py: Python<'_>, key: &[u8]) -> PyResult<()> {
+        self.check_writable()?;
+        let key = key.to_vec();
+        self.with_db(py, |db| db.delete(key).map_err(to_py_err))
+    }
+    
+    /// Make a batch's writes at once, with one sync: readers, and the
+    /// database reopened after a crash, see all of them or none. The batch
+    /// keeps its writes, and can't be written again or added to until it's
+    /// cleared. Writing an empty batch does nothing.
+    fn write(&self, py: Python<'_>, batch: &mut WriteBatch) -> PyResult<()> {
+        self.check_writable()?;
+        if batch.batch.is_empty() {
+            return Ok(());
         }
+        if batch.written {
+            return Err(InvalidArgumentError::new_err(
+                "Batch already written; clear() it to use it again",
+            ));
+        }
+        let ops = batch.batch.clone();
+        self.with_db(py, |db| db.write(ops).map_err(to_py_err))?;
+        batch.written = true;
+        Ok(())
+    }
+    
+    /// Put each `(key, value)` pair in `items`, all at once as `write` does.
+    fn write_many(&self, py: Python<'_>, items: Vec<(Vec<u8>, Vec<u8>)>) -> PyResult<()> {
+        self.check_writable()?;
+        let mut batch = CoreWriteBatch::new();
+        for (key, value) in items {
+            batch.put(key, value);
+        }
+        self.with_db(py, |db| db.write(batch).map_err(to_py_err))
+    }
+    
+    /// Write the memtable out to an SSTable and, with `sync`, sync that and
+    /// the WAL to disk. Returns the SSTable's `file_id`, `entries` and
+    /// `bytes`, or `None` if the memtable was empty.
+    #[pyo3(signature = (sync=true))]
+    fn flush<'py>(&self, py: Python<'py>, sync: bool) -> PyResult<Option<Bound<'py, PyDict>>> {
+        self.check_writable()?;
+        let flushed = self.with_db(py, |db| db.flush_sstable(sync).map_err(to_py_err))?;
+        flushed
+            .map(|sst| {
+                let dict = PyDict::new(py);
+                dict.set_item("file_id", sst.file_id)?;
+                dict.set_item("entries", sst.num_entries)?;
+                dict.set_item("bytes", sst.file_size)?;
+                Ok(dict)
+            })
+            .transpose()
     }
     
-    fn delete(&self, key: &[u8]) -> PyResult<()> {
-        let db = self.db.as_ref()
-            .ok_or_else(|| PyRuntimeError::new_err("Database is closed"))?;
+    /// Flush, then merge the keys in `[start, end)`, or all of them, down
+    /// to the deepest level holding any, returning once that's done. Each
+    /// compaction run is listed under `passes`, and their sizes totalled.
+    #[pyo3(signature = (start=None, end=None))]
+    fn compact_range<'py>(
+        &self,
+        py: Python<'py>,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        self.check_writable()?;
+        let start = start.unwrap_or_default().to_vec();
+        let end = end.unwrap_or_default().to_vec();
+        let passes = self.with_db(py, |db| {
+            db.compact_range(&start, &end, |_| {}).map_err(to_py_err)
+        })?;
         
-        db.delete(key.to_vec())
-            .map_err(|e| PyIOError::new_err(format!("Delete failed: {}", e)))
+        let listed = PyList::empty(py);
+        for pass in &passes {
+            let dict = PyDict::new(py);
+            dict.set_item("level", pass.level)?;
+            dict.set_item("output_level", pass.output_level)?;
+            dict.set_item("input_files", pass.input_files)?;
+            dict.set_item("bytes_read", pass.bytes_read)?;
+            dict.set_item("bytes_written", pass.bytes_written)?;
+            listed.append(dict)?;
+        }
+        let summary = PyDict::new(py);
+        summary.set_item("passes", listed)?;
+        summary.set_item("input_files", passes.iter().map(|p| p.input_files).sum::<usize>())?;
+        summary.set_item("bytes_read", passes.iter().map(|p| p.bytes_read).sum::<u64>())?;
+        summary.set_item("bytes_written", passes.iter().map(|p| p.bytes_written).sum::<u64>())?;
+        Ok(summary)
     }
     
-    fn close(&mut self) -> PyResult<()> {
-        if let Some(db) = self.db.take() {
-            if let Ok(db_owned) = Arc::try_unwrap(db) {
-                db_owned.close()
-                    .map_err(|e| PyIOError::new_err(format!("Close failed: {}", e)))?;
-            }
+    /// Wait for a compaction another thread set off, then run any the
+    /// levels still call for.
+    fn wait_for_compaction(&self, py: Python<'_>) -> PyResult<()> {
+        self.check_writable()?;
+        self.with_db(py, |db| db.wait_for_compaction().map_err(to_py_err))
+    }
+    
+    /// Iterate over `(key, value)` pairs with keys in `[start, end)`, in key
+    /// order or, with `reverse`, from the end back. Either bound may be
+    /// left out. Entries are read a batch at a time as the iterator goes.
+    #[pyo3(signature = (start=None, end=None, reverse=false))]
+    fn scan(&self, start: Option<&[u8]>, end: Option<&[u8]>, reverse: bool) -> ScanIterator {
+        let start = start.unwrap_or_default().to_vec();
+        let end = end.unwrap_or_default().to_vec();
+        ScanIterator::new(Arc::clone(&self.db), self.release_gil, start, end, reverse)
+    }
+    
+    /// Iterate over the `(key, value)` pairs whose keys start with `prefix`,
+    /// in key order.
+    fn scan_prefix(&self, prefix: &[u8]) -> ScanIterator {
+        let end = prefix_end(prefix);
+        ScanIterator::new(Arc::clone(&self.db), self.release_gil, prefix.to_vec(), end, false)
+    }
+    
+    /// Iterate over the keys starting with `prefix`, or all of them, in key
+    /// order, reading `batch_size` at a time.
+    #[pyo3(signature = (prefix=None, batch_size=SCAN_BATCH_SIZE))]
+    fn keys(&self, prefix: Option<&[u8]>, batch_size: usize) -> PyResult<ScanIterator> {
+        self.iter_prefix(prefix, batch_size, Yields::Keys)
+    }
+    
+    /// `keys`, but their values.
+    #[pyo3(signature = (prefix=None, batch_size=SCAN_BATCH_SIZE))]
+    fn values(&self, prefix: Option<&[u8]>, batch_size: usize) -> PyResult<ScanIterator> {
+        self.iter_prefix(prefix, batch_size, Yields::Values)
+    }
+    
+    /// `keys`, but `(key, value)` pairs.
+    #[pyo3(signature = (prefix=None, batch_size=SCAN_BATCH_SIZE))]
+    fn items(&self, prefix: Option<&[u8]>, batch_size: usize) -> PyResult<ScanIterator> {
+        self.iter_prefix(prefix, batch_size, Yields::Items)
+    }
+    
+    fn close(&self, py: Python<'_>) -> PyResult<()> {
+        match self.release_gil {
+            true => py.allow_threads(|| close_handle(&self.db, self.read_only)),
+            false => close_handle(&self.db, self.read_only),
         }
-        Ok(())
     }
     
     fn stats(&self) -> PyResult<DatabaseStats> {
-        let db = self.db.as_ref()
-            .ok_or_else(|| PyRuntimeError::new_err("Database is closed"))?;
-        
-        let stats = db.stats();
-        Ok(DatabaseStats {
-            memtable_size: stats.memtable_size,
-            memtable_entries: stats.memtable_entries,
-            num_sstables: stats.num_sstables,
-            sequence_number: stats.sequence_number,
+        with_db(&self.db, |db| {
+            let stats = db.stats();
+            Ok(DatabaseStats {
+                memtable_size: stats.memtable_size,
+                memtable_entries: stats.memtable_entries,
+                num_sstables: stats.num_sstables,
+                sstable_bytes: stats.sstable_bytes(),
+                sequence_number: stats.sequence_number,
+                l0_file_count: stats.l0_file_count,
+                levels: stats.levels.iter().map(|l| (l.level, l.files, l.bytes)).collect(),
+                wal_size: stats.wal_size,
+                estimated_keys: stats.estimated_keys,
+                compactions: stats.compaction.compactions,
+                compaction_bytes_read: stats.compaction.bytes_read,
+                compaction_bytes_written: stats.compaction.bytes_written,
+                bloom_probes: stats.bloom_probes,
+                bloom_negatives: stats.bloom_negatives,
+                active_transactions: stats.txn.active,
+                oldest_active_version: stats.oldest_active_version,
+                txn_retained_versions: stats.txn_retained_versions,
+                txn_retained_bytes: stats.txn_retained_bytes,
+            })
+        })
+    }
+    
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+    
+    fn __exit__(
+        &self,
+        py: Python<'_>,
+        _exc_type: PyObject,
+        _exc_value: PyObject,
+        _traceback: PyObject,
+    ) -> PyResult<bool> {
+        self.close(py)?;
+        Ok(false)
+    }
+    
+    /// The value of `key`, raising `KeyError` if it has none.
+    fn __getitem__<'py>(
+        &self,
+        py: Python<'py>,
+        key: &Bound<'py, PyAny>,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        let bytes = bytes_arg("key", key)?;
+        match self.with_db(py, |db| db.get(&bytes).map_err(to_py_err))? {
+            Some(value) => Ok(PyBytes::new(py, &value)),
+            None => Err(PyKeyError::new_err(key.clone().unbind())),
+        }
+    }
+    
+    fn __setitem__(
+        &self,
+        py: Python<'_>,
+        key: &Bound<'_, PyAny>,
+        value: &Bound<'_, PyAny>,
+    ) -> PyResult<()> {
+        self.check_writable()?;
+        let (key, value) = (bytes_arg("key", key)?, bytes_arg("value", value)?);
+        self.with_db(py, |db| db.put(key, value).map_err(to_py_err))
+    }
+    
+    /// Delete `key`, raising `KeyError` if it has no value.
+    fn __delitem__(&self, py: Python<'_>, key: &Bound<'_, PyAny>) -> PyResult<()> {
+        self.check_writable()?;
+        let bytes = bytes_arg("key", key)?;
+        let found = self.with_db(py, |db| {
+            let found = db.contains_key(&bytes).map_err(to_py_err)?;
+            if found {
+                db.delete(bytes).map_err(to_py_err)?;
+            }
+            Ok(found)
+        })?;
+        match found {
+            true => Ok(()),
+            false => Err(PyKeyError::new_err(key.clone().unbind())),
+        }
+    }
+    
+    fn __contains__(&self, py: Python<'_>, key: &Bound<'_, PyAny>) -> PyResult<bool> {
+        let key = bytes_arg("key", key)?;
+        self.with_db(py, |db| db.contains_key(&key).map_err(to_py_err))
+    }
+    
+    /// Roughly how many keys the database holds: entries in the memtable
+    /// and SSTables, so a key written twice, or deleted, may count more
+    /// than once until compaction.
+    fn __len__(&self) -> PyResult<usize> {
+        with_db(&self.db, |db| Ok(db.stats().estimated_keys as usize))
+    }
+    
+    /// Begin a transaction. It reads the database as it is now, plus its
+    /// own writes, and its writes are made when it commits, unless another
+    /// transaction committed a clashing write first.
+    fn transaction(&self) -> PyResult<Transaction> {
+        let id = with_db(&self.db, |db| match self.read_only {
+            true => Ok(db.begin_read_only_txn()),
+            false => Ok(db.begin_txn()),
+        })?;
+        Ok(Transaction {
+            db: Arc::clone(&self.db),
+            id,
+            read_only: self.read_only,
+            release_gil: self.release_gil,
+            state: TxnState::Active,
         })
     }
     
+    /// Take a snapshot: reads through it see the database as it is now,
+    /// whatever is written after. Old versions of keys written meanwhile
+    /// are kept for it until it's released.
+    fn snapshot(&self) -> PyResult<Snapshot> {
+        let id = with_db(&self.db, |db| Ok(db.begin_read_only_txn()))?;
+        Ok(Snapshot {
+            db: Arc::clone(&self.db),
+            id,
+            release_gil: self.release_gil,
+            released: false,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TxnState {
+    Active,
+    Committed,
+    Aborted,
+}
+
+/// A transaction begun by `Database.transaction`. As a context manager it
+/// commits when the block ends, or aborts if it raised.
+#[pyclass]
+struct Transaction {
+    db: Handle,
+    id: TxnId,
+    read_only: bool,
+    release_gil: bool,
+    state: TxnState,
+}
+
+impl Transaction {
+    fn with_db<T: Send>(
+        &self,
+        py: Python<'_>,
+        f: impl FnOnce(&CoreDatabase) -> PyResult<T> + Send,
+    ) -> PyResult<T> {
+        with_db_nogil(py, self.release_gil, &self.db, f)
+    }
+    
+    fn check_active(&self) -> PyResult<()> {
+        let state = match self.state {
+            TxnState::Active => return Ok(()),
+            TxnState::Committed => "committed",
+            TxnState::Aborted => "aborted",
+        };
+        Err(InvalidArgumentError::new_err(format!("Transaction already {}", state)))
+    }
+    
+    fn check_writable(&self) -> PyResult<()> {
+        self.check_active()?;
+        match self.read_only {
+            true => Err(IOError::new_err("Database is read-only")),
+            false => Ok(()),
+        }
+    }
+}
+
+#[pymethods]
+impl Transaction {
+    fn get<'py>(&self, py: Python<'py>, key: &[u8]) -> PyResult<Option<Bound<'py, PyBytes>>> {
+        self.check_active()?;
+        let key = key.to_vec();
+        let value = self.with_db(py, |db| db.get_txn(self.id, &key).map_err(to_py_err))?;
+        Ok(value.map(|value| PyBytes::new(py, &value)))
+    }
+    
+    fn put(&self, py: Python<'_>, key: &[u8], value: &[u8]) -> PyResult<()> {
+        self.check_writable()?;
+        let (key, value) = (key.to_vec(), value.to_vec());
+        self.with_db(py, |db| db.put_txn(self.id, key, value).map_err(to_py_err))
+    }
+    
+    fn delete(&self, py: Python<'_>, key: &[u8]) -> PyResult<()> {
+        self.check_writable()?;
+        let key = key.to_vec();
+        self.with_db(py, |db| db.delete_txn(self.id, key).map_err(to_py_err))
+    }
+    
+    /// Make the transaction's writes. Raises `TxnConflictError` if another
+    /// transaction got there first, and the transaction is aborted.
+    fn commit(&mut self, py: Python<'_>) -> PyResult<()> {
+        self.check_active()?;
+        let result = self.with_db(py, |db| db.commit_txn(self.id).map_err(to_py_err));
+        self.state = match result {
+            Ok(()) => TxnState::Committed,
+            Err(_) => TxnState::Aborted,
+        };
+        result
+    }
+    
+    /// Drop the transaction's writes.
+    fn abort(&mut self) -> PyResult<()> {
+        self.check_active()?;
+        self.state = TxnState::Aborted;
+        with_db(&self.db, |db| db.abort_txn(self.id).map_err(to_py_err))
+    }
+    
     fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
         slf
     }
     
-    fn __exit__(&mut self, _exc_type: PyObject, _exc_value: PyObject, _traceback: PyObject) -> PyResult<bool> {
-        self.close()?;
+    /// Commit, or abort if the block raised, unless that was done already.
+    fn __exit__(
+        &mut self,
+        exc_type: &Bound<'_, PyAny>,
+        _exc_value: PyObject,
+        _traceback: PyObject,
+    ) -> PyResult<bool> {
+        if self.state == TxnState::Active {
+            match exc_type.is_none() {
+                true => self.commit(exc_type.py())?,
+                false => self.abort()?,
+            }
+        }
         Ok(false)
     }
 }
 
+impl Drop for Transaction {
+    /// Abort a transaction left open rather than keep it until it times out.
+    fn drop(&mut self) {
+        if self.state == TxnState::Active {
+            if let Some(db) = self.db.read().unwrap().as_ref() {
+                let _ = db.abort_txn(self.id);
+            }
+        }
+    }
+}
+
+/// A snapshot taken by `Database.snapshot`, released by `release`, when
+/// its `with` block ends, or once it's garbage collected. It holds the
+/// database open, but fails once that's closed.
+#[pyclass]
+struct Snapshot {
+    db: Handle,
+    /// The read-only transaction it reads through.
+    id: TxnId,
+    release_gil: bool,
+    released: bool,
+}
+
+impl Snapshot {
+    fn with_db<T: Send>(
+        &self,
+        py: Python<'_>,
+        f: impl FnOnce(&CoreDatabase) -> PyResult<T> + Send,
+    ) -> PyResult<T> {
+        self.check_live()?;
+        with_db_nogil(py, self.release_gil, &self.db, f)
+    }
+    
+    fn check_live(&self) -> PyResult<()> {
+        match self.released {
+            true => Err(InvalidArgumentError::new_err("Snapshot already released")),
+            false => Ok(()),
+        }
+    }
+}
+
+#[pymethods]
+impl Snapshot {
+    fn get<'py>(&self, py: Python<'py>, key: &[u8]) -> PyResult<Option<Bound<'py, PyBytes>>> {
+        let key = key.to_vec();
+        let value = self.with_db(py, |db| db.get_txn(self.id, &key).map_err(to_py_err))?;
+        Ok(value.map(|value| PyBytes::new(py, &value)))
+    }
+    
+    /// `Database.scan` as of the snapshot. The range is read whole, up
+    /// front.
+    #[pyo3(signature = (start=None, end=None, reverse=false))]
+    fn scan(
+        &self,
+        py: Python<'_>,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+        reverse: bool,
+    ) -> PyResult<ScanIterator> {
+        let start = start.unwrap_or_default().to_vec();
+        let end = end.unwrap_or_default().to_vec();
+        let mut entries = self.with_db(py, |db| {
+            db.scan_range_txn(self.id, &start, &end).map_err(to_py_err)
+        })?;
+        if reverse {
+            entries.reverse();
+        }
+        Ok(ScanIterator::read(Arc::clone(&self.db), self.release_gil, entries))
+    }
+    
+    /// Release the snapshot, and drop the old versions no other reader
+    /// needs. Releasing it again does nothing.
+    fn release(&mut self) -> PyResult<()> {
+        if self.released {
+            return Ok(());
+        }
+        self.released = true;
+        match self.db.read().unwrap().as_ref() {
+            Some(db) => {
+                db.commit_txn(self.id).map_err(to_py_err)?;
+                db.gc_versions();
+                Ok(())
+            }
+            // Closing released it.
+            None => Ok(()),
+        }
+    }
+    
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+    
+    fn __exit__(
+        &mut self,
+        _exc_type: PyObject,
+        _exc_value: PyObject,
+        _traceback: PyObject,
+    ) -> PyResult<bool> {
+        self.release()?;
+        Ok(false)
+    }
+}
+
+impl Drop for Snapshot {
+    fn drop(&mut self) {
+        let _ = self.release();
+    }
+}
+
+/// Puts and deletes to make together with `Database.write`. Later writes to
+/// a key in the batch win.
+#[pyclass]
+struct WriteBatch {
+    batch: CoreWriteBatch,
+    /// Whether it's been written since it was last cleared.
+    written: bool,
+}
+
+impl WriteBatch {
+    fn check_unwritten(&self) -> PyResult<()> {
+        match self.written {
+            true => Err(InvalidArgumentError::new_err("Batch already written; clear() it first")),
+            false => Ok(()),
+        }
+    }
+}
+
+#[pymethods]
+impl WriteBatch {
+    #[new]
+    fn new() -> Self {
+        WriteBatch { batch: CoreWriteBatch::new(), written: false }
+    }
+    
+    fn put(&mut self, key: &[u8], value: &[u8]) -> PyResult<()> {
+        self.check_unwritten()?;
+        self.batch.put(key.to_vec(), value.to_vec());
+        Ok(())
+    }
+    
+    fn delete(&mut self, key: &[u8]) -> PyResult<()> {
+        self.check_unwritten()?;
+        self.batch.delete(key.to_vec());
+        Ok(())
+    }
+    
+    /// Empty the batch, ready to use again.
+    fn clear(&mut self) {
+        self.batch.clear();
+        self.written = false;
+    }
+    
+    /// Writes in the batch, counting each write to the same key.
+    fn len(&self) -> usize {
+        self.batch.len()
+    }
+    
+    fn __len__(&self) -> usize {
+        self.batch.len()
+    }
+}
+
+/// The first key after every key starting with `prefix`, or empty, for no
+/// bound, if there's none.
+fn prefix_end(prefix: &[u8]) -> Vec<u8> {
+    let mut end = prefix.to_vec();
+    while let Some(last) = end.pop() {
+        if last < u8::MAX {
+            end.push(last + 1);
+            return end;
+        }
+    }
+    end
+}
+
+/// What a `ScanIterator` yields for each entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Yields {
+    Items,
+    Keys,
+    Values,
+}
+
+/// A range of the database, yielded as `(key, value)` tuples of bytes, or
+/// just the keys or values.
+#[pyclass]
+struct ScanIterator {
+    db: Handle,
+    release_gil: bool,
+    /// Where the next batch starts; its end stays put.
+    start: Vec<u8>,
+    end: Vec<u8>,
+    reverse: bool,
+    batch_size: usize,
+    yields: Yields,
+    buffer: VecDeque<(Vec<u8>, Vec<u8>)>,
+    done: bool,
+}
+
+impl ScanIterator {
+    fn new(db: Handle, release_gil: bool, start: Vec<u8>, end: Vec<u8>, reverse: bool) -> Self {
+        ScanIterator {
+            db,
+            release_gil,
+            start,
+            end,
+            reverse,
+            batch_size: SCAN_BATCH_SIZE,
+            yields: Yields::Items,
+            buffer: VecDeque::new(),
+            done: false,
+        }
+    }
+    
+    /// An iterator over `entries`, read already.
+    fn read(db: Handle, release_gil: bool, entries: Vec<(Vec<u8>, Vec<u8>)>) -> Self {
+        let mut iter = ScanIterator::new(db, release_gil, Vec::new(), Vec::new(), false);
+        iter.buffer = entries.into();
+        iter.done = true;
+        iter
+    }
+    
+    /// The next entry, reading another batch if those read are used up.
+    /// Fails once the database is closed, even with entries read.
+    fn next_entry(&mut self) -> PyResult<Option<(Vec<u8>, Vec<u8>)>> {
+        let db = Arc::clone(&self.db);
+        with_db(&db, |db| {
+            // A batch can be empty with more to follow, where all its keys
+            // were deleted.
+            while self.buffer.is_empty() && !self.done {
+                self.fill(db)?;
+            }
+            Ok(self.buffer.pop_front())
+        })
+    }
+    
+    /// Read the next batch. Read from the end back, the range is read
+    /// whole, as the core has no reverse scan.
+    fn fill(&mut self, db: &CoreDatabase) -> PyResult<()> {
+        if self.reverse {
+            let entries = db.scan_range(&self.start, &self.end).map_err(to_py_err)?;
+            self.buffer.extend(entries.into_iter().rev());
+            self.done = true;
+            return Ok(());
+        }
+        let (entries, resume) = db.scan_range_page(&self.start, &self.end, self.batch_size)
+            .map_err(to_py_err)?;
+        self.buffer.extend(entries);
+        match resume {
+            Some(next) => self.start = next,
+            None => self.done = true,
+        }
+        Ok(())
+    }
+}
+
+#[pymethods]
+impl ScanIterator {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+    
+    fn __next__(&mut self, py: Python<'_>) -> PyResult<Option<PyObject>> {
+        // Only reading a batch is worth releasing the GIL for.
+        let entry = match self.release_gil && self.buffer.is_empty() && !self.done {
+            true => py.allow_threads(|| self.next_entry())?,
+            false => self.next_entry()?,
+        };
+        let Some((key, value)) = entry else {
+            return Ok(None);
+        };
+        let bytes = |b: &[u8]| PyBytes::new(py, b);
+        let item = match self.yields {
+            Yields::Items => PyTuple::new(py, [bytes(&key), bytes(&value)])?.into_any(),
+            Yields::Keys => bytes(&key).into_any(),
+            Yields::Values => bytes(&value).into_any(),
+        };
+        Ok(Some(item.unbind()))
+    }
+}
+
 #[pyclass]
 #[derive(Clone)]
 struct DatabaseStats {
@@ -91,13 +942,58 @@ struct DatabaseStats {
     #[pyo3(get)]
     num_sstables: usize,
     #[pyo3(get)]
+    sstable_bytes: u64,
+    #[pyo3(get)]
     sequence_number: u64,
+    #[pyo3(get)]
+    l0_file_count: usize,
+    /// `(level, files, bytes)` for every level from L0 down, empty ones
+    /// included.
+    #[pyo3(get)]
+    levels: Vec<(Level, usize, u64)>,
+    #[pyo3(get)]
+    wal_size: u64,
+    #[pyo3(get)]
+    estimated_keys: u64,
+    #[pyo3(get)]
+    compactions: u64,
+    #[pyo3(get)]
+    compaction_bytes_read: u64,
+    #[pyo3(get)]
+    compaction_bytes_written: u64,
+    #[pyo3(get)]
+    bloom_probes: u64,
+    #[pyo3(get)]
+    bloom_negatives: u64,
+    #[pyo3(get)]
+    active_transactions: usize,
+    /// Start version of the oldest open transaction or snapshot.
+    #[pyo3(get)]
+    oldest_active_version: Option<u64>,
+    /// Old versions of keys kept for open transactions and snapshots.
+    #[pyo3(get)]
+    txn_retained_versions: usize,
+    #[pyo3(get)]
+    txn_retained_bytes: u64,
 }
 
 #[pymodule]
 fn middb_python(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<Database>()?;
     m.add_class::<DatabaseStats>()?;
+    m.add_class::<ScanIterator>()?;
+    m.add_class::<WriteBatch>()?;
+    m.add_class::<Transaction>()?;
+    m.add_class::<Snapshot>()?;
+    m.add_class::<aio::AsyncDatabase>()?;
+    m.add_class::<aio::AsyncScanIterator>()?;
+    let py = m.py();
+    m.add("Error", py.get_type::<Error>())?;
+    m.add("CorruptionError", py.get_type::<CorruptionError>())?;
+    m.add("InvalidArgumentError", py.get_type::<InvalidArgumentError>())?;
+    m.add("DatabaseClosedError", py.get_type::<DatabaseClosedError>())?;
+    m.add("TxnConflictError", py.get_type::<TxnConflictError>())?;
+    m.add("IOError", py.get_type::<IOError>())?;
     Ok(())
 }
 
@@ -110,7 +1006,134 @@ mod tests {
         let temp_dir = std::env::temp_dir().join("middb_test_py");
         let _ = std::fs::remove_dir_all(&temp_dir);
         
-        let db = Database::new(temp_dir.to_string_lossy().to_string()).unwrap();
-        assert!(db.db.is_some());
+        let db = Database::new(temp_dir.to_string_lossy().to_string(), None).unwrap();
+        assert!(db.db.read().unwrap().is_some());
+    }
+    
+    /// A new database called `name`, with Python ready for `with_gil`.
+    fn open(name: &str) -> Database {
+        pyo3::prepare_freethreaded_python();
+        let temp_dir = std::env::temp_dir().join(name);
+        let _ = std::fs::remove_dir_all(&temp_dir);
+        Database::new(temp_dir.to_string_lossy().to_string(), None).unwrap()
+    }
+    
+    fn keys(scan: &mut ScanIterator) -> Vec<Vec<u8>> {
+        std::iter::from_fn(|| scan.next_entry().unwrap()).map(|(key, _)| key).collect()
+    }
+    
+    #[test]
+    fn test_scan_batches() {
+        let db = open("middb_test_py_scan");
+        Python::with_gil(|py| {
+            let count = SCAN_BATCH_SIZE * 2 + 3;
+            for i in 0..count {
+                db.put(py, format!("k{:05}", i).as_bytes(), b"v").unwrap();
+            }
+            db.delete(py, b"k00000").unwrap();
+            
+            let all = keys(&mut db.scan(None, None, false));
+            assert_eq!(all.len(), count - 1);
+            assert_eq!(all[0], b"k00001");
+            assert!(all.windows(2).all(|w| w[0] < w[1]));
+            
+            let bounded = keys(&mut db.scan(Some(b"k00010"), Some(b"k00013"), false));
+            assert_eq!(bounded, [b"k00010", b"k00011", b"k00012"]);
+            let reversed = keys(&mut db.scan(Some(b"k00010"), Some(b"k00013"), true));
+            assert_eq!(reversed, [b"k00012", b"k00011", b"k00010"]);
+            assert_eq!(keys(&mut db.scan_prefix(b"k0001")).len(), 10);
+        });
+    }
+    
+    #[test]
+    fn test_scan_after_close() {
+        let db = open("middb_test_py_scan_close");
+        Python::with_gil(|py| {
+            db.put(py, b"a", b"1").unwrap();
+            db.put(py, b"b", b"2").unwrap();
+            
+            let mut scan = db.scan(None, None, false);
+            assert_eq!(scan.next_entry().unwrap().unwrap().0, b"a");
+            db.close(py).unwrap();
+            assert!(scan.next_entry().is_err());
+        });
+    }
+    
+    #[test]
+    fn test_write_batch() {
+        let db = open("middb_test_py_batch");
+        Python::with_gil(|py| {
+            db.put(py, b"gone", b"1").unwrap();
+            
+            let mut batch = WriteBatch::new();
+            batch.put(b"a", b"1").unwrap();
+            batch.put(b"b", b"2").unwrap();
+            batch.delete(b"gone").unwrap();
+            batch.put(b"a", b"3").unwrap();
+            assert_eq!(batch.len(), 4);
+            db.write(py, &mut batch).unwrap();
+            
+            let first = db.scan(None, None, false).next_entry().unwrap();
+            assert_eq!(first, Some((b"a".to_vec(), b"3".to_vec())));
+            assert_eq!(keys(&mut db.scan(None, None, false)), [b"a", b"b"]);
+            
+            // Written, it's kept but closed to more until cleared.
+            assert_eq!(batch.len(), 4);
+            assert!(batch.put(b"c", b"3").is_err());
+            assert!(db.write(py, &mut batch).is_err());
+            batch.clear();
+            assert_eq!(batch.len(), 0);
+            db.write(py, &mut batch).unwrap();
+            batch.put(b"c", b"3").unwrap();
+            
+            let items = vec![(b"x".to_vec(), b"1".to_vec()), (b"y".to_vec(), b"2".to_vec())];
+            db.write_many(py, items).unwrap();
+            assert_eq!(keys(&mut db.scan_prefix(b"")), [b"a", b"b", b"x", b"y"]);
+        });
+    }
+    
+    #[test]
+    fn test_transaction_dropped_open_aborts() {
+        let db = open("middb_test_py_txn_drop");
+        Python::with_gil(|py| {
+            let active = || db.db.read().unwrap().as_ref().unwrap().list_active_txns().len();
+            
+            let mut txn = db.transaction().unwrap();
+            txn.put(py, b"a", b"1").unwrap();
+            assert_eq!(active(), 1);
+            drop(txn);
+            assert_eq!(active(), 0);
+            
+            txn = db.transaction().unwrap();
+            txn.commit(py).unwrap();
+            assert!(txn.commit(py).is_err());
+            assert!(txn.abort().is_err());
+            drop(txn);
+            assert_eq!(active(), 0);
+        });
+    }
+    
+    #[test]
+    fn test_snapshot_dropped_releases() {
+        let db = open("middb_test_py_snapshot_drop");
+        Python::with_gil(|py| {
+            let active = || db.db.read().unwrap().as_ref().unwrap().list_active_txns().len();
+            db.put(py, b"a", b"1").unwrap();
+            
+            let snapshot = db.snapshot().unwrap();
+            db.put(py, b"a", b"2").unwrap();
+            assert_eq!(active(), 1);
+            assert_eq!(db.stats().unwrap().txn_retained_versions, 2);
+            drop(snapshot);
+            assert_eq!(active(), 0);
+            assert_eq!(db.stats().unwrap().txn_retained_versions, 0);
+        });
+    }
+    
+    #[test]
+    fn test_prefix_end() {
+        assert_eq!(prefix_end(b"ab"), b"ac");
+        assert_eq!(prefix_end(b"a\xff"), b"b");
+        assert!(prefix_end(b"\xff\xff").is_empty());
     }
 }
@@ -0,0 +1,119 @@
+import asyncio
+import shutil
+import tempfile
+import time
+import pytest
+import middb
+
+@pytest.mark.asyncio
+async def test_concurrent_gets_and_puts():
+    temp_dir = tempfile.mkdtemp()
+    
+    try:
+        async with middb.AsyncDatabase(temp_dir) as db:
+            keys = [f"key{i:03d}".encode() for i in range(200)]
+            await asyncio.gather(*(db.put(key, key + b"-value") for key in keys))
+            values = await asyncio.gather(*(db.get(key) for key in keys))
+            assert values == [key + b"-value" for key in keys]
+            
+            await asyncio.gather(*(db.delete(key) for key in keys[100:]))
+            batch = middb.WriteBatch()
+            batch.put(b"batched", b"1")
+            await db.write(batch)
+            with pytest.raises(middb.InvalidArgumentError):
+                await db.write(batch)
+            await db.write_many([(b"many", b"2")])
+            
+            scanned = [key async for key, _ in db.scan(batch_size=16)]
+            assert scanned == sorted(keys[:100] + [b"batched", b"many"])
+            backwards = [key async for key, _ in db.scan(end=b"key002", reverse=True)]
+            assert backwards == [b"key001", b"key000", b"batched"]
+            assert await db.get(keys[150]) is None
+    finally:
+        shutil.rmtree(temp_dir)
+
+@pytest.mark.asyncio
+async def test_event_loop_runs_during_synced_writes():
+    temp_dir = tempfile.mkdtemp()
+    
+    try:
+        async with middb.AsyncDatabase(temp_dir, wal_sync="always") as db:
+            writing = True
+            ticks = 0
+            
+            async def heartbeat():
+                nonlocal ticks
+                while writing:
+                    ticks += 1
+                    await asyncio.sleep(0)
+            
+            beat = asyncio.ensure_future(heartbeat())
+            start = time.monotonic()
+            for i in range(50):
+                await db.put(f"key{i}".encode(), b"value")
+            writing = False
+            await beat
+            # Each put awaited a blocking thread, so the loop kept running
+            # between.
+            assert ticks >= 50, (ticks, time.monotonic() - start)
+    finally:
+        shutil.rmtree(temp_dir)
+
+@pytest.mark.asyncio
+async def test_core_error_raised_in_coroutine():
+    temp_dir = tempfile.mkdtemp()
+    db = middb.AsyncDatabase(temp_dir, memtable_size=1 << 20)
+    # The memtable's flush can't make its SSTable with the directory gone.
+    shutil.rmtree(temp_dir)
+    
+    with pytest.raises(middb.IOError, match="No such file"):
+        for i in range(100):
+            await db.put(f"key{i}".encode(), b"x" * 100_000)
+    await db.close()
+
+@pytest.mark.asyncio
+async def test_close_fails_pending_ops():
+    temp_dir = tempfile.mkdtemp()
+    
+    try:
+        db = middb.AsyncDatabase(temp_dir, max_in_flight=1, wal_sync="always")
+        puts = [db.put(f"key{i}".encode(), b"value") for i in range(200)]
+        await db.close()
+        results = await asyncio.gather(*puts, return_exceptions=True)
+        
+        # Each synced put waits on the disk, so most were still waiting for
+        # their turn when the close came.
+        failed = [r for r in results if isinstance(r, middb.DatabaseClosedError)]
+        assert len(failed) >= 100
+        assert all(r is None for r in results if r not in failed)
+        with pytest.raises(middb.DatabaseClosedError):
+            await db.get(b"key0")
+        await db.close()
+        
+        db = middb.Database(temp_dir)
+        written = sum(db.get(f"key{i}".encode()) is not None for i in range(200))
+        assert written == len(results) - len(failed)
+        db.close()
+    finally:
+        shutil.rmtree(temp_dir)
+
+@pytest.mark.asyncio
+async def test_wraps_open_database():
+    temp_dir = tempfile.mkdtemp()
+    
+    try:
+        db = middb.Database(temp_dir)
+        db.put(b"key", b"value")
+        async with middb.AsyncDatabase(db) as adb:
+            assert await adb.get(b"key") == b"value"
+            assert adb.stats().memtable_entries == 1
+            assert adb.db is db
+        with pytest.raises(middb.DatabaseClosedError):
+            db.get(b"key")
+        
+        with pytest.raises(TypeError):
+            middb.AsyncDatabase(db, wal_sync="never")
+        with pytest.raises(ValueError):
+            middb.AsyncDatabase(db, max_in_flight=0)
+    finally:
+        shutil.rmtree(temp_dir)
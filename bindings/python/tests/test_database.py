@@ -1,6 +1,9 @@
+import itertools
 import tempfile
 import os
 import shutil
+import threading
+import time
 import middb
 
 def test_basic_operations():
@@ -91,3 +94,729 @@ def test_update():
         db.close()
     finally:
         shutil.rmtree(temp_dir)
+
+def test_scan():
+    temp_dir = tempfile.mkdtemp()
+    
+    try:
+        db = middb.Database(temp_dir)
+        
+        for i in range(600):
+            db.put(f"key{i:03d}".encode(), f"value{i}".encode())
+        db.put(b"other", b"x")
+        db.delete(b"key001")
+        
+        entries = list(db.scan())
+        assert len(entries) == 600
+        assert entries[0] == (b"key000", b"value0")
+        assert entries[1] == (b"key002", b"value2")
+        assert entries[-1] == (b"other", b"x")
+        assert [k for k, _ in entries] == sorted(k for k, _ in entries)
+        
+        assert list(db.scan(b"key597", b"key599")) == [
+            (b"key597", b"value597"),
+            (b"key598", b"value598"),
+        ]
+        assert [k for k, _ in db.scan(start=b"key598")] == [b"key598", b"key599", b"other"]
+        assert [k for k, _ in db.scan(end=b"key002")] == [b"key000"]
+        assert [k for k, _ in db.scan(b"key597", b"key599", reverse=True)] == [b"key598", b"key597"]
+        assert list(db.scan(b"zzz")) == []
+        
+        assert len(list(db.scan_prefix(b"key5"))) == 100
+        assert list(db.scan_prefix(b"oth")) == [(b"other", b"x")]
+        
+        db.close()
+    finally:
+        shutil.rmtree(temp_dir)
+
+def test_scan_after_close():
+    temp_dir = tempfile.mkdtemp()
+    
+    try:
+        db = middb.Database(temp_dir)
+        db.put(b"a", b"1")
+        db.put(b"b", b"2")
+        
+        scan = db.scan()
+        assert next(scan) == (b"a", b"1")
+        db.close()
+        
+        try:
+            next(scan)
+            assert False, "expected DatabaseClosedError"
+        except middb.DatabaseClosedError as e:
+            assert "closed" in str(e)
+    finally:
+        shutil.rmtree(temp_dir)
+
+def test_write_batch():
+    temp_dir = tempfile.mkdtemp()
+    
+    try:
+        db = middb.Database(temp_dir)
+        db.put(b"stale", b"old")
+        
+        batch = middb.WriteBatch()
+        batch.put(b"a", b"1")
+        batch.put(b"b", b"2")
+        batch.delete(b"stale")
+        batch.put(b"a", b"3")
+        assert batch.len() == 4
+        assert len(batch) == 4
+        db.write(batch)
+        
+        assert db.get(b"a") == b"3"
+        assert db.get(b"b") == b"2"
+        assert db.get(b"stale") is None
+        
+        # A written batch can't be reused until it's cleared.
+        for reuse in (lambda: batch.put(b"c", b"4"), lambda: db.write(batch)):
+            try:
+                reuse()
+                assert False, "expected InvalidArgumentError"
+            except middb.InvalidArgumentError:
+                pass
+        batch.clear()
+        assert len(batch) == 0
+        db.write(batch)
+        batch.delete(b"a")
+        db.write(batch)
+        assert db.get(b"a") is None
+        
+        db.write_many([(b"x", b"1"), (b"y", b"2")])
+        assert db.get(b"x") == b"1"
+        assert db.get(b"y") == b"2"
+        
+        db.close()
+    finally:
+        shutil.rmtree(temp_dir)
+
+def test_write_batch_faster_than_puts():
+    temp_dir = tempfile.mkdtemp()
+    
+    try:
+        items = [(f"key{i:05d}".encode(), b"value") for i in range(10000)]
+        
+        db = middb.Database(os.path.join(temp_dir, "looped"))
+        started = time.perf_counter()
+        for key, value in items:
+            db.put(key, value)
+        looped = time.perf_counter() - started
+        db.close()
+        
+        db = middb.Database(os.path.join(temp_dir, "batched"))
+        started = time.perf_counter()
+        batch = middb.WriteBatch()
+        for key, value in items:
+            batch.put(key, value)
+        db.write(batch)
+        batched = time.perf_counter() - started
+        
+        assert batched < looped, f"batch {batched:.3f}s, puts {looped:.3f}s"
+        assert len(list(db.scan())) == 10000
+        db.close()
+    finally:
+        shutil.rmtree(temp_dir)
+
+def test_write_batch_is_atomic():
+    temp_dir = tempfile.mkdtemp()
+    
+    try:
+        db = middb.Database(temp_dir)
+        db.put(b"before", b"1")
+        batch = middb.WriteBatch()
+        for i in range(100):
+            batch.put(f"key{i:03d}".encode(), b"value")
+        db.write(batch)
+        db.close()
+        
+        # A crash part way through writing the batch's WAL record.
+        wal = os.path.join(temp_dir, "wal", "wal.log")
+        with open(wal, "r+b") as f:
+            f.truncate(os.path.getsize(wal) - 10)
+        
+        db = middb.Database(temp_dir)
+        assert db.get(b"before") == b"1"
+        assert list(db.scan_prefix(b"key")) == []
+        db.close()
+    finally:
+        shutil.rmtree(temp_dir)
+
+def test_exceptions():
+    temp_dir = tempfile.mkdtemp()
+    
+    def raised(action):
+        try:
+            action()
+        except middb.Error as e:
+            return e
+        assert False, "expected middb.Error"
+    
+    try:
+        path = os.path.join(temp_dir, "db")
+        db = middb.Database(path)
+        for i in range(10):
+            db.put(f"key{i}".encode(), b"value")
+        
+        e = raised(lambda: middb.Database(path))
+        assert isinstance(e, middb.IOError)
+        assert "locked" in str(e)
+        e = raised(lambda: middb.Database(os.path.join(path, "LOCK")))
+        assert isinstance(e, middb.IOError)
+        
+        batch = middb.WriteBatch()
+        batch.put(b"a", b"1")
+        db.write(batch)
+        e = raised(lambda: db.write(batch))
+        assert isinstance(e, middb.InvalidArgumentError)
+        
+        db.close()
+        e = raised(lambda: db.get(b"key1"))
+        assert isinstance(e, middb.DatabaseClosedError)
+        assert "closed" in str(e)
+        
+        wal = os.path.join(path, "wal", "wal.log")
+        with open(wal, "r+b") as f:
+            f.seek(20)
+            byte = f.read(1)
+            f.seek(20)
+            f.write(bytes([byte[0] ^ 0xff]))
+        e = raised(lambda: middb.Database(path))
+        assert isinstance(e, middb.CorruptionError)
+        assert "CRC mismatch" in str(e)
+        
+        for cls in (middb.CorruptionError, middb.InvalidArgumentError,
+                    middb.DatabaseClosedError, middb.TxnConflictError, middb.IOError):
+            assert issubclass(cls, middb.Error)
+    finally:
+        shutil.rmtree(temp_dir)
+
+def test_config_options():
+    temp_dir = tempfile.mkdtemp()
+    
+    try:
+        small = os.path.join(temp_dir, "small")
+        db = middb.Database.open(small, memtable_size=1 << 20, block_size=4096,
+                                 bloom_bits_per_key=4, wal_sync="never")
+        config = db.config
+        assert config["memtable_size"] == 1 << 20
+        assert config["block_size"] == 4096
+        assert config["bloom_bits_per_key"] == 4
+        assert config["wal_sync"] == "never"
+        assert config["use_compression"] is False
+        assert config["read_only"] is False
+        assert config["data_dir"] == small
+        
+        big = os.path.join(temp_dir, "big")
+        default = middb.Database(big)
+        assert default.config["memtable_size"] == 64 << 20
+        assert default.config["wal_sync"] == "always"
+        
+        # 2 MB of values flushes the small memtable, not the default one.
+        for i in range(2048):
+            db.put(f"key{i:05d}".encode(), b"x" * 1024)
+            default.put(f"key{i:05d}".encode(), b"x" * 1024)
+        assert db.stats().num_sstables > 0
+        assert default.stats().num_sstables == 0
+        db.close()
+        default.close()
+        
+        # The settings reported open the database again as they were.
+        options = dict(config)
+        del options["data_dir"]
+        db = middb.Database(small, **options)
+        assert db.config == config
+        assert db.get(b"key00007") == b"x" * 1024
+        db.close()
+    finally:
+        shutil.rmtree(temp_dir)
+
+def test_read_only():
+    temp_dir = tempfile.mkdtemp()
+    
+    try:
+        try:
+            middb.Database(os.path.join(temp_dir, "missing"), read_only=True)
+            assert False, "expected IOError"
+        except middb.IOError:
+            pass
+        assert not os.path.exists(os.path.join(temp_dir, "missing"))
+        
+        # Dropped rather than closed, so the put is only in the WAL.
+        db = middb.Database(temp_dir)
+        db.put(b"a", b"1")
+        del db
+        files = sorted(os.listdir(temp_dir))
+        
+        db = middb.Database(temp_dir, read_only=True)
+        assert db.config["read_only"] is True
+        assert db.get(b"a") == b"1"
+        for write in (lambda: db.put(b"b", b"2"), lambda: db.delete(b"a"),
+                      lambda: db.write_many([(b"b", b"2")])):
+            try:
+                write()
+                assert False, "expected IOError"
+            except middb.IOError as e:
+                assert "read-only" in str(e)
+        db.close()
+        
+        # Closing wrote nothing: there's no new SSTable.
+        assert sorted(os.listdir(temp_dir)) == files
+    finally:
+        shutil.rmtree(temp_dir)
+
+def test_invalid_options():
+    temp_dir = tempfile.mkdtemp()
+    
+    try:
+        for options, field in [
+            (dict(memtable_size=1024), "memtable_size"),
+            (dict(block_size=1024), "block_size"),
+            (dict(bloom_bits_per_key=0), "bloom_bits_per_key"),
+            (dict(bloom_bits_per_key=-1), "bloom_bits_per_key"),
+            (dict(block_size="big"), "block_size"),
+            (dict(wal_sync="sometimes"), "wal_sync"),
+            (dict(compaction_style="tiered"), "compaction_style"),
+            (dict(txn_timeout=-1.0), "txn_timeout"),
+        ]:
+            try:
+                middb.Database(temp_dir, **options)
+                assert False, f"expected InvalidArgumentError for {options}"
+            except middb.InvalidArgumentError as e:
+                assert field in str(e), str(e)
+        
+        try:
+            middb.Database(temp_dir, memtable_sise=1 << 20)
+            assert False, "expected TypeError"
+        except TypeError as e:
+            assert "memtable_sise" in str(e)
+    finally:
+        shutil.rmtree(temp_dir)
+
+def test_transaction_commit_and_abort():
+    temp_dir = tempfile.mkdtemp()
+    
+    try:
+        db = middb.Database(temp_dir)
+        db.put(b"gone", b"1")
+        
+        txn = db.transaction()
+        txn.put(b"a", b"1")
+        txn.delete(b"gone")
+        # Its own writes, but nobody else's yet.
+        assert txn.get(b"a") == b"1"
+        assert txn.get(b"gone") is None
+        assert db.get(b"a") is None
+        assert db.get(b"gone") == b"1"
+        txn.commit()
+        assert db.get(b"a") == b"1"
+        assert db.get(b"gone") is None
+        
+        txn = db.transaction()
+        txn.put(b"b", b"2")
+        txn.abort()
+        assert db.get(b"b") is None
+        
+        for finished in (lambda: txn.get(b"a"), lambda: txn.put(b"c", b"3"), txn.commit, txn.abort):
+            try:
+                finished()
+                assert False, "expected InvalidArgumentError"
+            except middb.InvalidArgumentError as e:
+                assert "already aborted" in str(e)
+        db.close()
+    finally:
+        shutil.rmtree(temp_dir)
+
+def test_transaction_context_manager():
+    temp_dir = tempfile.mkdtemp()
+    
+    try:
+        db = middb.Database(temp_dir)
+        with db.transaction() as txn:
+            txn.put(b"a", b"1")
+        assert db.get(b"a") == b"1"
+        
+        try:
+            with db.transaction() as txn:
+                txn.put(b"b", b"2")
+                raise ValueError("oops")
+        except ValueError:
+            pass
+        assert db.get(b"b") is None
+        try:
+            txn.put(b"b", b"2")
+            assert False, "expected InvalidArgumentError"
+        except middb.InvalidArgumentError:
+            pass
+        
+        # Committed inside the block, so nothing's left to do at its end.
+        with db.transaction() as txn:
+            txn.put(b"c", b"3")
+            txn.commit()
+        assert db.get(b"c") == b"3"
+        db.close()
+    finally:
+        shutil.rmtree(temp_dir)
+
+def test_transaction_conflict():
+    temp_dir = tempfile.mkdtemp()
+    
+    try:
+        db = middb.Database(temp_dir)
+        db.put(b"counter", b"0")
+        
+        first = db.transaction()
+        second = db.transaction()
+        for txn in (first, second):
+            count = int(txn.get(b"counter"))
+            txn.put(b"counter", str(count + 1).encode())
+        first.commit()
+        try:
+            second.commit()
+            assert False, "expected TxnConflictError"
+        except middb.TxnConflictError:
+            pass
+        assert db.get(b"counter") == b"1"
+        
+        def increment(interfere):
+            while True:
+                try:
+                    with db.transaction() as txn:
+                        count = int(txn.get(b"counter"))
+                        if interfere:
+                            interfere = False
+                            with db.transaction() as other:
+                                other.put(b"counter", b"10")
+                        txn.put(b"counter", str(count + 1).encode())
+                    return
+                except middb.TxnConflictError:
+                    continue
+        
+        increment(interfere=True)
+        assert db.get(b"counter") == b"11"
+        db.close()
+    finally:
+        shutil.rmtree(temp_dir)
+
+def test_gil_released_during_reads():
+    temp_dir = tempfile.mkdtemp()
+    
+    try:
+        db = middb.Database(temp_dir, wal_sync="never")
+        db.write_many([(f"key{i:06d}".encode(), b"v" * 100) for i in range(5000)])
+        db.close()
+        
+        def stalled(release_gil):
+            """How long ten 1 ms sleeps take while four threads read."""
+            db = middb.Database(temp_dir, release_gil=release_gil)
+            stop = threading.Event()
+            
+            def reader():
+                while not stop.is_set():
+                    # Reading from the end back reads the range in one call.
+                    next(db.scan(reverse=True))
+                    db.get(b"key001234")
+            
+            threads = [threading.Thread(target=reader) for _ in range(4)]
+            for thread in threads:
+                thread.start()
+            started = time.perf_counter()
+            for _ in range(10):
+                time.sleep(0.001)
+            elapsed = time.perf_counter() - started
+            stop.set()
+            for thread in threads:
+                thread.join()
+            db.close()
+            return elapsed
+        
+        held = stalled(release_gil=False)
+        released = stalled(release_gil=True)
+        assert released * 2 < held, f"released {released:.3f}s, held {held:.3f}s"
+    finally:
+        shutil.rmtree(temp_dir)
+
+def test_concurrent_readers_and_writer():
+    temp_dir = tempfile.mkdtemp()
+    
+    try:
+        db = middb.Database(temp_dir, wal_sync="never")
+        keys = [f"key{i:05d}".encode() for i in range(2000)]
+        errors = []
+        done = threading.Event()
+        
+        def writer():
+            try:
+                for i in range(0, len(keys), 100):
+                    for key in keys[i:i + 50]:
+                        db.put(key, key[::-1])
+                    db.write_many([(key, key[::-1]) for key in keys[i + 50:i + 100]])
+            except Exception as e:
+                errors.append(e)
+            finally:
+                done.set()
+        
+        def reader():
+            try:
+                while not done.is_set():
+                    for key in keys[::97]:
+                        assert db.get(key) in (None, key[::-1])
+                    entries = list(db.scan())
+                    assert [key for key, _ in entries] == sorted(key for key, _ in entries)
+                    assert all(value == key[::-1] for key, value in entries)
+            except Exception as e:
+                errors.append(e)
+        
+        threads = [threading.Thread(target=writer)]
+        threads += [threading.Thread(target=reader) for _ in range(4)]
+        for thread in threads:
+            thread.start()
+        for thread in threads:
+            thread.join()
+        
+        assert errors == []
+        assert list(db.scan()) == [(key, key[::-1]) for key in keys]
+        db.close()
+    finally:
+        shutil.rmtree(temp_dir)
+
+def test_mapping_methods():
+    temp_dir = tempfile.mkdtemp()
+    
+    try:
+        db = middb.Database(temp_dir)
+        db[b"a"] = b"1"
+        assert db[b"a"] == b"1"
+        assert db.get(b"a") == b"1"
+        assert b"a" in db
+        
+        # Unlike get, a missing key raises.
+        assert db.get(b"missing") is None
+        assert b"missing" not in db
+        try:
+            db[b"missing"]
+            assert False, "expected KeyError"
+        except KeyError as e:
+            assert e.args == (b"missing",)
+        
+        del db[b"a"]
+        assert b"a" not in db
+        assert db.get(b"a") is None
+        try:
+            del db[b"a"]
+            assert False, "expected KeyError"
+        except KeyError:
+            pass
+        
+        for misuse in (lambda: db["a"], lambda: db.__setitem__(b"a", "1"),
+                       lambda: 1 in db, lambda: db.__delitem__(None)):
+            try:
+                misuse()
+                assert False, "expected TypeError"
+            except TypeError as e:
+                assert "must be bytes, not" in str(e)
+        db.close()
+    finally:
+        shutil.rmtree(temp_dir)
+
+def test_len_is_approximate():
+    temp_dir = tempfile.mkdtemp()
+    
+    try:
+        db = middb.Database(temp_dir, memtable_size=1 << 20)
+        assert len(db) == 0
+        
+        # Never shrinks as keys are added, through flushes too.
+        lengths = []
+        for i in range(3000):
+            db[f"key{i:05d}".encode()] = b"x" * 500
+            lengths.append(len(db))
+        assert db.stats().num_sstables > 0
+        assert lengths == sorted(lengths)
+        assert lengths[-1] >= 3000
+        db.close()
+    finally:
+        shutil.rmtree(temp_dir)
+
+def test_snapshot_reads_as_of_creation():
+    temp_dir = tempfile.mkdtemp()
+    
+    try:
+        db = middb.Database(temp_dir)
+        db.put(b"a", b"1")
+        db.put(b"b", b"2")
+        
+        with db.snapshot() as snap:
+            db.put(b"a", b"changed")
+            db.delete(b"b")
+            db[b"c"] = b"new"
+            with db.transaction() as txn:
+                txn.put(b"d", b"4")
+            
+            assert snap.get(b"a") == b"1"
+            assert snap.get(b"b") == b"2"
+            assert snap.get(b"c") is None
+            assert snap.get(b"d") is None
+            assert list(snap.scan()) == [(b"a", b"1"), (b"b", b"2")]
+            assert list(snap.scan(b"b", reverse=True)) == [(b"b", b"2")]
+            
+            assert db.get(b"a") == b"changed"
+            assert db.get(b"b") is None
+            assert [key for key, _ in db.scan()] == [b"a", b"c", b"d"]
+        db.close()
+    finally:
+        shutil.rmtree(temp_dir)
+
+def test_snapshot_release():
+    temp_dir = tempfile.mkdtemp()
+    
+    try:
+        db = middb.Database(temp_dir)
+        for i in range(100):
+            db.put(f"key{i:03d}".encode(), b"old")
+        
+        snap = db.snapshot()
+        for i in range(100):
+            db.put(f"key{i:03d}".encode(), b"new")
+        # The old and new version of each key.
+        assert db.stats().txn_retained_versions == 200
+        assert db.stats().txn_retained_bytes > 0
+        
+        snap.release()
+        assert db.stats().txn_retained_versions == 0
+        assert db.stats().txn_retained_bytes == 0
+        snap.release()
+        for released in (lambda: snap.get(b"key000"), lambda: snap.scan()):
+            try:
+                released()
+                assert False, "expected InvalidArgumentError"
+            except middb.InvalidArgumentError as e:
+                assert "already released" in str(e)
+        
+        # It keeps the database open, but not past close.
+        snap = db.snapshot()
+        del db
+        assert snap.get(b"key000") == b"new"
+        del snap
+        
+        db = middb.Database.open(temp_dir)
+        snap = db.snapshot()
+        db.close()
+        try:
+            snap.get(b"key000")
+            assert False, "expected DatabaseClosedError"
+        except middb.DatabaseClosedError:
+            pass
+        snap.release()
+    finally:
+        shutil.rmtree(temp_dir)
+
+def test_keys_values_items():
+    temp_dir = tempfile.mkdtemp()
+    
+    try:
+        db = middb.Database(temp_dir)
+        expected = {}
+        for prefix in (b"user:", b"order:"):
+            for i in range(5):
+                key = prefix + str(i).encode()
+                expected[key] = key.upper()
+                db.put(key, key.upper())
+        db.delete(b"user:3")
+        del expected[b"user:3"]
+        
+        assert dict(db.items()) == expected
+        assert list(db.keys()) == sorted(expected)
+        assert list(db.values()) == [expected[key] for key in sorted(expected)]
+        assert list(db.keys(b"user:")) == [b"user:0", b"user:1", b"user:2", b"user:4"]
+        assert list(db.values(prefix=b"order:4")) == [b"ORDER:4"]
+        assert dict(db.items(b"nothing")) == {}
+        assert list(itertools.islice(db.keys(b"order:"), 2)) == [b"order:0", b"order:1"]
+        
+        try:
+            db.keys(batch_size=0)
+            assert False, "expected InvalidArgumentError"
+        except middb.InvalidArgumentError:
+            pass
+        db.close()
+    finally:
+        shutil.rmtree(temp_dir)
+
+def test_keys_across_batches():
+    temp_dir = tempfile.mkdtemp()
+    
+    try:
+        db = middb.Database(temp_dir)
+        batch_size = 8
+        for count in (batch_size - 1, batch_size, batch_size + 1, 3 * batch_size):
+            prefix = f"n{count:02d}:".encode()
+            keys = [prefix + f"{i:03d}".encode() for i in range(count)]
+            for key in keys:
+                db.put(key, b"v")
+            # A batch of nothing but deleted keys still leads on to the rest.
+            db.put(prefix + b"999", b"v")
+            for key in keys[batch_size:2 * batch_size]:
+                db.delete(key)
+            del keys[batch_size:2 * batch_size]
+            
+            assert list(db.keys(prefix, batch_size=batch_size)) == keys + [prefix + b"999"]
+            items = list(db.items(prefix, batch_size=batch_size))
+            assert len(items) == len(keys) + 1
+        
+        keys = db.keys(batch_size=batch_size)
+        for _ in range(batch_size):
+            next(keys)
+        db.close()
+        try:
+            next(keys)
+            assert False, "expected DatabaseClosedError"
+        except middb.DatabaseClosedError:
+            pass
+    finally:
+        shutil.rmtree(temp_dir)
+
+def test_flush_and_compact_range():
+    temp_dir = tempfile.mkdtemp()
+    
+    try:
+        db = middb.Database(temp_dir, level0_file_num_compaction_trigger=100)
+        assert db.flush() is None
+        
+        for i in range(500):
+            db.put(f"key{i:03d}".encode(), b"x" * 1000)
+        flushed = db.flush()
+        stats = db.stats()
+        assert stats.num_sstables == 1
+        assert flushed["entries"] == 500
+        assert flushed["bytes"] == stats.sstable_bytes
+        assert stats.levels[0] == (0, 1, flushed["bytes"])
+        
+        for i in range(400):
+            db.delete(f"key{i:03d}".encode())
+        assert db.flush(sync=False)["entries"] == 400
+        assert db.stats().num_sstables == 2
+        
+        before = db.stats().sstable_bytes
+        summary = db.compact_range()
+        stats = db.stats()
+        assert stats.sstable_bytes < before
+        assert [(p["level"], p["output_level"]) for p in summary["passes"]] == [(0, 1)]
+        assert summary["input_files"] == 2
+        assert summary["bytes_read"] == before
+        assert summary["bytes_written"] == stats.sstable_bytes
+        assert stats.compactions == 1
+        assert stats.l0_file_count == 0
+        assert len(list(db.keys())) == 100
+        
+        assert db.compact_range(b"key450", b"key460")["passes"] == []
+        db.wait_for_compaction()
+        db.close()
+        
+        for closed in (db.flush, db.compact_range, db.wait_for_compaction):
+            try:
+                closed()
+                assert False, "expected DatabaseClosedError"
+            except middb.DatabaseClosedError:
+                pass
+    finally:
+        shutil.rmtree(temp_dir)
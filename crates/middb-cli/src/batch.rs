@@ -0,0 +1,112 @@
+//! Running the REPLs' commands without a prompt, from `--exec` or from
+//! standard input when it isn't a terminal.
+
+use anyhow::Result;
+use std::io::{self, IsTerminal, Read, Write};
+
+#[derive(Debug, Clone, Default, clap::Args)]
+pub struct BatchArgs {
+    /// Run these commands, separated by semicolons, and exit.
+    #[arg(short, long)]
+    pub exec: Option<String>,
+
+    /// Go on past a command that fails, rather than stop there. The exit
+    /// status is still non-zero.
+    #[arg(long)]
+    pub continue_on_error: bool,
+}
+
+impl BatchArgs {
+    /// The commands to run without a prompt: those given with `--exec`, or
+    /// else those piped in. None if standard input is a terminal, for the
+    /// REPL to read from instead.
+    pub fn commands(&self) -> Result<Option<Vec<String>>> {
+        if let Some(exec) = &self.exec {
+            return Ok(Some(split_commands(exec)));
+        }
+        if io::stdin().is_terminal() {
+            return Ok(None);
+        }
+        let mut text = String::new();
+        io::stdin().read_to_string(&mut text)?;
+        Ok(Some(split_commands(&text)))
+    }
+}
+
+/// The commands in `text`, separated by semicolons or newlines, with blank
+/// ones left out.
+pub fn split_commands(text: &str) -> Vec<String> {
+    text.split([';', '\n'])
+        .map(str::trim)
+        .filter(|command| !command.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Whether `command` ends the session, as it would at the prompt.
+pub fn is_quit(command: &str) -> bool {
+    command == "quit" || command == "exit"
+}
+
+/// How a run of commands is going.
+pub struct Batch {
+    continue_on_error: bool,
+    failed: usize,
+}
+
+impl Batch {
+    pub fn new(continue_on_error: bool) -> Self {
+        Batch { continue_on_error, failed: 0 }
+    }
+
+    /// Note how a command went, writing its error to `err` as the REPL
+    /// would. Returns whether to run the next.
+    pub fn record(&mut self, result: Result<()>, err: &mut impl Write) -> bool {
+        match result {
+            Ok(()) => true,
+            Err(e) => {
+                let _ = writeln!(err, "Error: {}", e);
+                self.failed += 1;
+                self.continue_on_error
+            }
+        }
+    }
+
+    /// Fine if no command failed, and an error saying how many did if any.
+    pub fn finish(self) -> Result<()> {
+        match self.failed {
+            0 => Ok(()),
+            1 => anyhow::bail!("1 command failed"),
+            failed => anyhow::bail!("{} commands failed", failed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_commands() {
+        let commands = split_commands("put a 1; get a;scan --limit 10");
+        assert_eq!(commands, ["put a 1", "get a", "scan --limit 10"]);
+        assert_eq!(split_commands("get a\n\n  ;\nget b;\n"), ["get a", "get b"]);
+        assert!(split_commands(" ; ").is_empty());
+    }
+
+    #[test]
+    fn test_batch_stops_at_failure() {
+        let mut err = Vec::new();
+        let mut batch = Batch::new(false);
+        assert!(batch.record(Ok(()), &mut err));
+        assert!(!batch.record(Err(anyhow::anyhow!("Usage: get <key>")), &mut err));
+        assert_eq!(String::from_utf8(err).unwrap(), "Error: Usage: get <key>\n");
+        assert_eq!(batch.finish().unwrap_err().to_string(), "1 command failed");
+
+        let mut batch = Batch::new(true);
+        assert!(batch.record(Err(anyhow::anyhow!("one")), &mut Vec::new()));
+        assert!(batch.record(Err(anyhow::anyhow!("two")), &mut Vec::new()));
+        assert_eq!(batch.finish().unwrap_err().to_string(), "2 commands failed");
+        assert!(Batch::new(false).finish().is_ok());
+    }
+}
@@ -0,0 +1,506 @@
+//! Workloads timed against a `Database`, for `middb bench`.
+
+use anyhow::{Context, Result};
+use middb_core::{Database, DatabaseStats, WriteBatch};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Workload {
+    /// Each thread writes its keys in order.
+    Fillseq,
+    /// Each thread writes keys picked at random from its own.
+    Fillrandom,
+    /// Reads of keys picked at random from all of them.
+    Readrandom,
+    /// Each thread reads its keys in order, a page at a time.
+    Readseq,
+    /// Reads of any key and writes of a thread's own, mixed.
+    Mixed,
+}
+
+impl Workload {
+    fn name(self) -> &'static str {
+        match self {
+            Workload::Fillseq => "fillseq",
+            Workload::Fillrandom => "fillrandom",
+            Workload::Readrandom => "readrandom",
+            Workload::Readseq => "readseq",
+            Workload::Mixed => "mixed",
+        }
+    }
+    
+    /// Whether the keys have to be there before the timing starts.
+    fn reads_existing(self) -> bool {
+        matches!(self, Workload::Readrandom | Workload::Readseq | Workload::Mixed)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BenchOptions {
+    pub workload: Workload,
+    /// Operations in all, and keys in the key space.
+    pub num: u64,
+    pub value_size: usize,
+    pub threads: usize,
+    /// Of `mixed`'s operations, the percentage that are reads.
+    pub read_percent: u32,
+    pub seed: u64,
+}
+
+/// Entries `readseq` reads at a time.
+const READSEQ_PAGE: usize = 100;
+
+/// Keys written at a time when filling the key space for a read workload.
+const PREFILL_BATCH: u64 = 1000;
+
+/// How a run went.
+#[derive(Debug, Clone)]
+pub struct BenchReport {
+    pub workload: Workload,
+    pub ops: u64,
+    pub reads: u64,
+    /// Reads that found their key.
+    pub found: u64,
+    pub writes: u64,
+    pub elapsed: Duration,
+    pub latencies: Latencies,
+    pub stats: DatabaseStats,
+}
+
+impl BenchReport {
+    pub fn ops_per_sec(&self) -> f64 {
+        self.ops as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+/// Latencies counted into buckets a sixteenth of a power of two wide, so a
+/// percentile is within about 6% of the true figure.
+#[derive(Debug, Clone)]
+pub struct Latencies {
+    counts: Vec<u64>,
+    total: u64,
+    max: Duration,
+}
+
+impl Default for Latencies {
+    fn default() -> Self {
+        Latencies { counts: vec![0; 61 * 16], total: 0, max: Duration::ZERO }
+    }
+}
+
+impl Latencies {
+    fn bucket(nanos: u64) -> usize {
+        if nanos < 16 {
+            return nanos as usize;
+        }
+        let exp = 63 - nanos.leading_zeros() as usize;
+        let sub = (nanos >> (exp - 4)) as usize & 15;
+        (exp - 3) * 16 + sub
+    }
+    
+    /// The largest latency `bucket` counts.
+    fn upper_bound(bucket: usize) -> u64 {
+        if bucket < 16 {
+            return bucket as u64;
+        }
+        let (exp, sub) = (bucket / 16 + 3, bucket as u64 % 16);
+        let width = 1u64 << (exp - 4);
+        (16 + sub) * width + (width - 1)
+    }
+    
+    pub fn record(&mut self, latency: Duration) {
+        let nanos = latency.as_nanos().min(u64::MAX as u128) as u64;
+        self.counts[Self::bucket(nanos)] += 1;
+        self.total += 1;
+        self.max = self.max.max(latency);
+    }
+    
+    pub fn merge(&mut self, other: &Latencies) {
+        for (count, other) in self.counts.iter_mut().zip(&other.counts) {
+            *count += other;
+        }
+        self.total += other.total;
+        self.max = self.max.max(other.max);
+    }
+    
+    pub fn count(&self) -> u64 {
+        self.total
+    }
+    
+    pub fn max(&self) -> Duration {
+        self.max
+    }
+    
+    /// The latency `percentile` percent of those recorded are no more
+    /// than, at its bucket's upper bound.
+    pub fn percentile(&self, percentile: f64) -> Duration {
+        let rank = ((percentile / 100.0 * self.total as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (bucket, &count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                let nanos = Self::upper_bound(bucket);
+                return Duration::from_nanos(nanos).min(self.max);
+            }
+        }
+        self.max
+    }
+}
+
+/// A small, fast generator, seeded so runs can be repeated.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Rng(seed)
+    }
+    
+    /// The next of a splitmix64 sequence.
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+    
+    fn below(&mut self, bound: u64) -> u64 {
+        self.next_u64() % bound.max(1)
+    }
+}
+
+fn key(i: u64) -> Vec<u8> {
+    format!("{:016}", i).into_bytes()
+}
+
+/// The keys thread `thread` of `threads` writes: an equal share of `num`,
+/// the last thread taking what's left over.
+fn key_range(num: u64, threads: usize, thread: usize) -> (u64, u64) {
+    let share = num / threads as u64;
+    let start = share * thread as u64;
+    let end = if thread + 1 == threads { num } else { start + share };
+    (start, end)
+}
+
+/// What one thread did.
+#[derive(Default)]
+struct ThreadReport {
+    reads: u64,
+    found: u64,
+    writes: u64,
+    latencies: Latencies,
+}
+
+/// Run `options.workload` on `db` and report on it. Read workloads first
+/// fill the key space if it isn't already, untimed.
+pub fn run(db: &Database, options: &BenchOptions) -> Result<BenchReport> {
+    let threads = options.threads.max(1);
+    if options.workload.reads_existing() {
+        prefill(db, options)?;
+    }
+    
+    let started = Instant::now();
+    let reports = std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..threads)
+            .map(|thread| scope.spawn(move || run_thread(db, options, threads, thread)))
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("bench thread panicked"))
+            .collect::<Result<Vec<_>>>()
+    })?;
+    let elapsed = started.elapsed();
+    
+    let mut report = BenchReport {
+        workload: options.workload,
+        ops: 0,
+        reads: 0,
+        found: 0,
+        writes: 0,
+        elapsed,
+        latencies: Latencies::default(),
+        stats: db.stats(),
+    };
+    for thread in reports {
+        report.reads += thread.reads;
+        report.found += thread.found;
+        report.writes += thread.writes;
+        report.latencies.merge(&thread.latencies);
+    }
+    report.ops = report.reads + report.writes;
+    Ok(report)
+}
+
+/// Write the whole key space in batches, unless its last key is there.
+fn prefill(db: &Database, options: &BenchOptions) -> Result<()> {
+    if options.num == 0 || db.get(&key(options.num - 1))?.is_some() {
+        return Ok(());
+    }
+    let value = vec![b'v'; options.value_size];
+    let mut batch = WriteBatch::new();
+    for i in 0..options.num {
+        batch.put(key(i), value.clone());
+        if batch.len() as u64 == PREFILL_BATCH {
+            db.write(std::mem::take(&mut batch))?;
+        }
+    }
+    db.write(batch)?;
+    Ok(())
+}
+
+fn run_thread(
+    db: &Database,
+    options: &BenchOptions,
+    threads: usize,
+    thread: usize,
+) -> Result<ThreadReport> {
+    let mut rng = Rng::new(options.seed.wrapping_add(thread as u64));
+    let (start, end) = key_range(options.num, threads, thread);
+    let value: Vec<u8> = (0..options.value_size).map(|_| rng.next_u64() as u8).collect();
+    let mut report = ThreadReport::default();
+    
+    let write = |report: &mut ThreadReport, i: u64| -> Result<()> {
+        let started = Instant::now();
+        db.put(key(i), value.clone())?;
+        report.latencies.record(started.elapsed());
+        report.writes += 1;
+        Ok(())
+    };
+    let read = |report: &mut ThreadReport, i: u64| -> Result<()> {
+        let started = Instant::now();
+        let found = db.get(&key(i))?.is_some();
+        report.latencies.record(started.elapsed());
+        report.reads += 1;
+        report.found += found as u64;
+        Ok(())
+    };
+    
+    match options.workload {
+        Workload::Fillseq => {
+            for i in start..end {
+                write(&mut report, i)?;
+            }
+        }
+        Workload::Fillrandom => {
+            for _ in start..end {
+                write(&mut report, start + rng.below(end - start))?;
+            }
+        }
+        Workload::Readrandom => {
+            for _ in start..end {
+                read(&mut report, rng.below(options.num))?;
+            }
+        }
+        Workload::Readseq => {
+            let (mut from, to) = (key(start), key(end));
+            loop {
+                let started = Instant::now();
+                let (page, resume) = db.scan_range_page(&from, &to, READSEQ_PAGE)?;
+                // Each entry is counted as an equal share of its page's time.
+                let each = started.elapsed() / page.len().max(1) as u32;
+                for _ in &page {
+                    report.latencies.record(each);
+                }
+                report.reads += page.len() as u64;
+                report.found += page.len() as u64;
+                match resume {
+                    Some(next) => from = next,
+                    None => break,
+                }
+            }
+        }
+        Workload::Mixed => {
+            for _ in start..end {
+                if rng.below(100) < options.read_percent as u64 {
+                    read(&mut report, rng.below(options.num))?;
+                } else {
+                    write(&mut report, start + rng.below(end - start))?;
+                }
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// Print a summary of `report`, then the database's stats.
+pub fn print_report(out: &mut impl Write, report: &BenchReport) -> Result<()> {
+    writeln!(
+        out,
+        "{}: {} ops in {:.2}s, {:.0} ops/sec",
+        report.workload.name(),
+        report.ops,
+        report.elapsed.as_secs_f64(),
+        report.ops_per_sec()
+    )?;
+    if report.reads > 0 {
+        writeln!(out, "Reads: {} ({} found)", report.reads, report.found)?;
+    }
+    if report.writes > 0 {
+        writeln!(out, "Writes: {}", report.writes)?;
+    }
+    let micros = |d: Duration| d.as_secs_f64() * 1e6;
+    let latencies = &report.latencies;
+    if latencies.count() > 0 {
+        writeln!(
+            out,
+            "Latency (us): p50 {:.1}, p95 {:.1}, p99 {:.1}, max {:.1}",
+            micros(latencies.percentile(50.0)),
+            micros(latencies.percentile(95.0)),
+            micros(latencies.percentile(99.0)),
+            micros(latencies.max())
+        )?;
+    }
+    writeln!(out)?;
+    write!(out, "{}", crate::stats::render(&report.stats))?;
+    Ok(())
+}
+
+const CSV_HEADER: &str = "timestamp,workload,num,value_size,threads,sync,ops,seconds,\
+    ops_per_sec,p50_us,p95_us,p99_us";
+
+/// Add a line for `report` to the CSV file at `path`, starting the file
+/// with a header if it's new.
+pub fn append_csv(
+    path: &Path,
+    options: &BenchOptions,
+    sync: bool,
+    report: &BenchReport,
+) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open {:?}", path))?;
+    if file.metadata()?.len() == 0 {
+        writeln!(file, "{}", CSV_HEADER)?;
+    }
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let micros = |percentile| report.latencies.percentile(percentile).as_secs_f64() * 1e6;
+    writeln!(
+        file,
+        "{},{},{},{},{},{},{},{:.3},{:.0},{:.1},{:.1},{:.1}",
+        timestamp.as_secs(),
+        report.workload.name(),
+        options.num,
+        options.value_size,
+        options.threads,
+        sync,
+        report.ops,
+        report.elapsed.as_secs_f64(),
+        report.ops_per_sec(),
+        micros(50.0),
+        micros(95.0),
+        micros(99.0)
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use middb_core::Config;
+    use tempfile::TempDir;
+    
+    fn options(workload: Workload) -> BenchOptions {
+        BenchOptions { workload, num: 200, value_size: 16, threads: 3, read_percent: 50, seed: 7 }
+    }
+    
+    fn open(dir: &TempDir) -> Database {
+        let mut config = Config::new(dir.path());
+        config.sync_writes = false;
+        Database::open(config).unwrap()
+    }
+    
+    #[test]
+    fn test_workloads_run_to_completion() {
+        let dir = TempDir::new().unwrap();
+        let db = open(&dir);
+        
+        let report = run(&db, &options(Workload::Fillseq)).unwrap();
+        assert_eq!((report.ops, report.writes, report.reads), (200, 200, 0));
+        assert_eq!(report.latencies.count(), 200);
+        assert_eq!(report.stats.memtable_entries, 200);
+        
+        let report = run(&db, &options(Workload::Fillrandom)).unwrap();
+        assert_eq!((report.ops, report.writes), (200, 200));
+        assert_eq!(db.stats().memtable_entries, 200);
+        
+        for workload in [Workload::Readrandom, Workload::Readseq] {
+            let report = run(&db, &options(workload)).unwrap();
+            assert_eq!((report.ops, report.reads, report.found), (200, 200, 200));
+            assert_eq!(report.latencies.count(), 200);
+        }
+        
+        let report = run(&db, &options(Workload::Mixed)).unwrap();
+        assert_eq!(report.ops, 200);
+        assert_eq!(report.reads + report.writes, 200);
+        assert_eq!(report.found, report.reads);
+        assert!(report.reads > 50 && report.writes > 50);
+    }
+    
+    #[test]
+    fn test_reads_fill_an_empty_database_first() {
+        let dir = TempDir::new().unwrap();
+        let db = open(&dir);
+        let report = run(&db, &options(Workload::Readrandom)).unwrap();
+        assert_eq!(report.found, 200);
+        assert_eq!(report.writes, 0);
+    }
+    
+    #[test]
+    fn test_runs_repeat_with_a_seed() {
+        let keys = |seed| {
+            let dir = TempDir::new().unwrap();
+            let db = open(&dir);
+            let options =
+                BenchOptions { num: 50, threads: 1, seed, ..options(Workload::Fillrandom) };
+            run(&db, &options).unwrap();
+            db.scan_range(b"", b"").unwrap().into_iter().map(|(k, _)| k).collect::<Vec<_>>()
+        };
+        assert_eq!(keys(1), keys(1));
+        assert_ne!(keys(1), keys(2));
+    }
+    
+    #[test]
+    fn test_latency_percentiles() {
+        let mut latencies = Latencies::default();
+        for micros in 1..=100 {
+            latencies.record(Duration::from_micros(micros));
+        }
+        let within = |percentile: f64, micros: f64| {
+            let got = latencies.percentile(percentile).as_secs_f64() * 1e6;
+            (got - micros).abs() / micros < 0.07
+        };
+        assert!(within(50.0, 50.0));
+        assert!(within(95.0, 95.0));
+        assert!(within(99.0, 99.0));
+        assert_eq!(latencies.percentile(100.0), Duration::from_micros(100));
+        for nanos in [0, 15, 16, 17, 1000, 123_456_789, u64::MAX] {
+            let bucket = Latencies::bucket(nanos);
+            assert!(Latencies::upper_bound(bucket) >= nanos);
+            assert!(bucket == 0 || Latencies::upper_bound(bucket - 1) < nanos);
+        }
+    }
+    
+    #[test]
+    fn test_csv_appends_under_one_header() {
+        let dir = TempDir::new().unwrap();
+        let db = open(&dir);
+        let path = dir.path().join("results.csv");
+        let options = options(Workload::Fillseq);
+        for _ in 0..2 {
+            let report = run(&db, &options).unwrap();
+            append_csv(&path, &options, false, &report).unwrap();
+        }
+        let text = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], CSV_HEADER);
+        let fields: Vec<&str> = lines[2].split(',').collect();
+        assert_eq!(fields.len(), CSV_HEADER.split(',').count());
+        assert_eq!(&fields[1..7], ["fillseq", "200", "16", "3", "false", "200"]);
+    }
+}
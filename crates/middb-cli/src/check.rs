@@ -0,0 +1,160 @@
+//! Checking a database's files for damage and salvaging what's readable,
+//! for `middb check` and `middb repair`.
+
+use crate::stats::human_bytes;
+use anyhow::Result;
+use middb_core::{Config, Database, FileKind, IntegrityReport, RepairReport};
+use std::io::Write;
+use std::path::Path;
+
+/// Check every file of the database `config` names, writing a line for
+/// each and its problems beneath. Returns how many problems there were.
+pub fn check(config: &Config, out: &mut impl Write) -> Result<usize> {
+    let report = Database::verify_integrity(config)?;
+    write_report(&report, &config.data_dir, out)?;
+    Ok(report.problems())
+}
+
+fn write_report(report: &IntegrityReport, data_dir: &Path, out: &mut impl Write) -> Result<()> {
+    for file in &report.files {
+        let name = file.path.strip_prefix(data_dir).unwrap_or(&file.path);
+        let noun = match file.kind {
+            FileKind::Wal => "records",
+            FileKind::SSTable => "entries",
+        };
+        let verdict = match file.problems.len() {
+            0 => "OK".to_string(),
+            1 => "1 problem".to_string(),
+            count => format!("{} problems", count),
+        };
+        writeln!(
+            out,
+            "{}: {} {}, {}: {}",
+            name.display(),
+            file.entries,
+            noun,
+            human_bytes(file.size),
+            verdict
+        )?;
+        for problem in &file.problems {
+            writeln!(out, "  {}", problem)?;
+        }
+    }
+    match report.problems() {
+        0 => writeln!(out, "{} files checked, no problems", report.files.len())?,
+        count => writeln!(out, "{} files checked, {} problems", report.files.len(), count)?,
+    }
+    Ok(())
+}
+
+/// Salvage the database `config` names, writing what was moved aside, then
+/// open it to show it does. The database is dropped rather than closed,
+/// so nothing more is written.
+pub fn repair(config: &Config, out: &mut impl Write) -> Result<RepairReport> {
+    let report = Database::repair(config)?;
+    write_repair(&report, out)?;
+
+    let db = Database::open(config.clone())?;
+    writeln!(out, "Database opens, with {} keys", db.stats().estimated_keys)?;
+    Ok(report)
+}
+
+fn write_repair(report: &RepairReport, out: &mut impl Write) -> Result<()> {
+    if report.is_empty() {
+        writeln!(out, "Nothing to repair")?;
+        return Ok(());
+    }
+    if let Some((offset, end, moved)) = &report.wal_tail {
+        writeln!(
+            out,
+            "WAL cut back to {} bytes; {} from there moved to {}",
+            offset,
+            human_bytes(end - offset),
+            moved.display()
+        )?;
+    }
+    for (from, to) in &report.quarantined {
+        writeln!(out, "{} moved to {}", from.display(), to.display())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    /// A closed database with two SSTables, the first damaged, and a WAL
+    /// torn mid-record.
+    fn damaged_db(dir: &TempDir) -> Config {
+        let config = Config::new(dir.path());
+        let db = Database::open(config.clone()).unwrap();
+        for value in ["v0", "v1"] {
+            db.put(b"a".to_vec(), value.into()).unwrap();
+            db.put(b"b".to_vec(), value.into()).unwrap();
+            db.flush().unwrap();
+        }
+        db.close().unwrap();
+
+        let sst = dir.path().join("sst_00000001.sst");
+        let mut bytes = fs::read(&sst).unwrap();
+        bytes[0] ^= 0xff;
+        fs::write(&sst, bytes).unwrap();
+        let wal = config.wal_dir.join("wal.log");
+        let mut bytes = fs::read(&wal).unwrap();
+        bytes.extend_from_slice(&[1, 2, 3, 4, 200, 0, 0, 0, 9]);
+        fs::write(&wal, bytes).unwrap();
+        config
+    }
+
+    fn to_lines(out: Vec<u8>) -> Vec<String> {
+        String::from_utf8(out).unwrap().lines().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn test_check_lists_failures() {
+        let dir = TempDir::new().unwrap();
+        let config = damaged_db(&dir);
+
+        let mut out = Vec::new();
+        let problems = check(&config, &mut out).unwrap();
+        let lines = to_lines(out);
+        assert_eq!(problems, 2);
+        assert!(lines[0].starts_with("wal/wal.log: 4 records, "), "{}", lines[0]);
+        assert!(lines[0].ends_with(": 1 problem"));
+        assert_eq!(lines[1], "  torn record: 9 bytes after the last whole one");
+        assert!(lines[2].starts_with("sst_00000001.sst: ") && lines[2].contains("problem"));
+        let sound =
+            |l: &String| l.starts_with("sst_00000002.sst: 2 entries, ") && l.ends_with(": OK");
+        assert!(lines.iter().any(sound));
+        assert_eq!(lines.last().unwrap(), "3 files checked, 2 problems");
+    }
+
+    #[test]
+    fn test_repair_quarantines() {
+        let dir = TempDir::new().unwrap();
+        let config = damaged_db(&dir);
+
+        let mut out = Vec::new();
+        let report = repair(&config, &mut out).unwrap();
+        let lines = to_lines(out);
+        assert!(lines[0].starts_with("WAL cut back to "), "{}", lines[0]);
+        assert!(lines[0].contains("9 B from there moved to "));
+        assert!(lines[1].ends_with("lost/sst_00000001.sst"));
+        assert_eq!(lines[2], "Database opens, with 2 keys");
+
+        let lost = dir.path().join("lost");
+        assert!(lost.join("sst_00000001.sst").exists());
+        assert_eq!(report.quarantined.len(), 1);
+        assert_eq!(check(&config, &mut Vec::new()).unwrap(), 0);
+
+        let db = Database::open(config.clone()).unwrap();
+        assert_eq!(db.get(&b"a".to_vec()).unwrap(), Some(b"v1".to_vec()));
+        drop(db);
+
+        let mut out = Vec::new();
+        repair(&config, &mut out).unwrap();
+        assert_eq!(to_lines(out)[0], "Nothing to repair");
+    }
+}
@@ -0,0 +1,235 @@
+//! Compacting a database by hand, for `middb compact`.
+
+use crate::stats::{human_bytes, render_levels};
+use anyhow::Result;
+use middb_core::compaction::CompactionTask;
+use middb_core::sstable::SSTableMetadata;
+use middb_core::{CompactionPass, Database, Level};
+use std::io::Write;
+use std::time::Instant;
+
+pub struct CompactOptions {
+    /// The first key to compact; empty for the first there is.
+    pub start: Vec<u8>,
+    /// The key to stop before; empty for none.
+    pub end: Vec<u8>,
+    /// Merge only this level into the one below, rather than the range all
+    /// the way down.
+    pub level: Option<Level>,
+    /// Say what would be merged, and merge nothing.
+    pub dry_run: bool,
+}
+
+/// Compact `db` as `options` ask, writing the levels before, each pass as
+/// it finishes, and the levels after. Returns the passes run.
+pub fn compact(
+    db: &Database,
+    options: &CompactOptions,
+    out: &mut impl Write,
+) -> Result<Vec<CompactionPass>> {
+    if options.dry_run {
+        plan(db, options, out)?;
+        return Ok(Vec::new());
+    }
+    
+    // Flushed first, so the levels before show what's about to be merged.
+    db.flush()?;
+    writeln!(out, "Before:")?;
+    write!(out, "{}", render_levels(&db.stats()))?;
+    writeln!(out)?;
+    
+    let started = Instant::now();
+    // The first error writing progress, kept until compaction is done.
+    let mut written = Ok(());
+    let mut on_pass = |pass: &CompactionPass| {
+        if written.is_ok() {
+            written = write_pass(out, pass);
+        }
+    };
+    let passes = match options.level {
+        Some(level) => {
+            let pass = db.compact_level(level, &options.start, &options.end)?;
+            pass.iter().for_each(&mut on_pass);
+            pass.into_iter().collect()
+        }
+        None => db.compact_range(&options.start, &options.end, &mut on_pass)?,
+    };
+    written?;
+    
+    if passes.is_empty() {
+        writeln!(out, "Nothing to compact")?;
+    } else {
+        let files: usize = passes.iter().map(|p| p.input_files).sum();
+        let read: u64 = passes.iter().map(|p| p.bytes_read).sum();
+        let bytes_written: u64 = passes.iter().map(|p| p.bytes_written).sum();
+        writeln!(
+            out,
+            "{} {} in {:.2}s: {} files merged, {} read, {} written",
+            passes.len(),
+            if passes.len() == 1 { "pass" } else { "passes" },
+            started.elapsed().as_secs_f64(),
+            files,
+            human_bytes(read),
+            human_bytes(bytes_written)
+        )?;
+    }
+    writeln!(out)?;
+    writeln!(out, "After:")?;
+    write!(out, "{}", render_levels(&db.stats()))?;
+    Ok(passes)
+}
+
+fn write_pass(out: &mut impl Write, pass: &CompactionPass) -> std::io::Result<()> {
+    writeln!(
+        out,
+        "L{} -> L{}: merged {} {}, {} read, {} written",
+        pass.level,
+        pass.output_level,
+        pass.input_files,
+        if pass.input_files == 1 { "file" } else { "files" },
+        human_bytes(pass.bytes_read),
+        human_bytes(pass.bytes_written)
+    )
+}
+
+/// Write what compacting would merge at each level, as things stand.
+fn plan(db: &Database, options: &CompactOptions, out: &mut impl Write) -> Result<()> {
+    let memtable_entries = db.stats().memtable_entries;
+    if memtable_entries > 0 {
+        writeln!(out, "Would flush {} memtable entries to L0 first", memtable_entries)?;
+    }
+    let tasks: Vec<CompactionTask> = match options.level {
+        Some(level) => {
+            db.plan_compaction(level, &options.start, &options.end).into_iter().collect()
+        }
+        None => db.plan_compact_range(&options.start, &options.end),
+    };
+    if tasks.is_empty() {
+        writeln!(out, "Nothing to compact")?;
+    }
+    for task in &tasks {
+        let bytes: u64 = task.all_input_files().map(|f| f.file_size).sum();
+        writeln!(
+            out,
+            "L{} -> L{}: would merge {} in L{} and {} in L{}, {}",
+            task.level,
+            task.output_level,
+            describe_files(&task.input_files),
+            task.level,
+            describe_files(&task.target_files),
+            task.output_level,
+            human_bytes(bytes)
+        )?;
+    }
+    Ok(())
+}
+
+/// "2 files (sst 3, 4)", or "no files".
+fn describe_files(files: &[SSTableMetadata]) -> String {
+    if files.is_empty() {
+        return "no files".to_string();
+    }
+    let ids: Vec<String> = files.iter().map(|f| f.file_id.to_string()).collect();
+    let noun = if files.len() == 1 { "file" } else { "files" };
+    format!("{} {} (sst {})", files.len(), noun, ids.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use middb_core::Config;
+    use tempfile::TempDir;
+    
+    /// A database with four overlapping files in L0, left there by a
+    /// trigger too high to reach.
+    fn l0_db(dir: &TempDir) -> Database {
+        let mut config = Config::new(dir.path());
+        config.level0_file_num_compaction_trigger = 100;
+        let db = Database::open(config).unwrap();
+        for round in 0..4 {
+            for i in 0..50 {
+                let key = format!("key{:03}", i * 4 + round % 2);
+                db.put(key.into_bytes(), vec![b'v'; 100]).unwrap();
+            }
+            db.flush().unwrap();
+        }
+        db
+    }
+    
+    fn options(level: Option<Level>, dry_run: bool) -> CompactOptions {
+        CompactOptions { start: Vec::new(), end: Vec::new(), level, dry_run }
+    }
+    
+    fn run(db: &Database, options: &CompactOptions) -> (Vec<CompactionPass>, Vec<String>) {
+        let mut out = Vec::new();
+        let passes = compact(db, options, &mut out).unwrap();
+        let lines = String::from_utf8(out).unwrap().lines().map(str::to_string).collect();
+        (passes, lines)
+    }
+    
+    #[test]
+    fn test_compact_collapses_l0() {
+        let dir = TempDir::new().unwrap();
+        let db = l0_db(&dir);
+        let before = db.stats();
+        assert_eq!(before.levels[0].files, 4);
+        
+        let (passes, lines) = run(&db, &options(None, false));
+        assert_eq!(passes.len(), 1);
+        let pass = &passes[0];
+        assert_eq!(pass.input_files, 4);
+        assert_eq!(pass.bytes_read, before.sstable_bytes());
+        // Half the entries were overwrites, and are gone.
+        assert!(pass.bytes_written < pass.bytes_read * 3 / 4);
+        
+        let after = db.stats();
+        assert_eq!((after.levels[0].files, after.levels[1].files), (0, 1));
+        assert_eq!(after.sstable_bytes(), pass.bytes_written);
+        assert_eq!(after.estimated_keys, 100);
+        
+        let words = |line: &str| line.split_whitespace().take(2).collect::<Vec<_>>().join(" ");
+        assert_eq!(lines[0], "Before:");
+        assert_eq!(words(&lines[2]), "L0 4");
+        let merged = |l: &&String| l.starts_with("L0 -> L1: merged 4 files");
+        let progress = lines.iter().position(|l| merged(&l)).unwrap();
+        assert!(lines[progress + 1].starts_with("1 pass in "));
+        assert!(lines[progress + 1].contains(": 4 files merged,"));
+        let after_at = lines.iter().position(|l| l == "After:").unwrap();
+        assert_eq!(words(&lines[after_at + 2]), "L0 0");
+        assert_eq!(words(&lines[after_at + 3]), "L1 1");
+        
+        let (passes, lines) = run(&db, &options(None, false));
+        assert!(passes.is_empty());
+        assert!(lines.contains(&"Nothing to compact".to_string()));
+    }
+    
+    #[test]
+    fn test_compact_one_level() {
+        let dir = TempDir::new().unwrap();
+        let db = l0_db(&dir);
+        run(&db, &options(None, false));
+        
+        let (passes, _) = run(&db, &options(Some(1), false));
+        assert_eq!((passes[0].level, passes[0].output_level), (1, 2));
+        let stats = db.stats();
+        assert_eq!((stats.levels[1].files, stats.levels[2].files), (0, 1));
+        assert_eq!(db.get(&b"key001".to_vec()).unwrap(), Some(vec![b'v'; 100]));
+        assert!(run(&db, &options(Some(1), false)).0.is_empty());
+    }
+    
+    #[test]
+    fn test_dry_run_changes_nothing() {
+        let dir = TempDir::new().unwrap();
+        let db = l0_db(&dir);
+        db.put(b"key999".to_vec(), b"v".to_vec()).unwrap();
+        
+        let (passes, lines) = run(&db, &options(None, true));
+        assert!(passes.is_empty());
+        assert_eq!(lines[0], "Would flush 1 memtable entries to L0 first");
+        assert!(lines[1].starts_with("L0 -> L1: would merge 4 files (sst 1, 2, 3, 4) in L0"));
+        assert!(lines[1].contains(" and no files in L1, "));
+        let stats = db.stats();
+        assert_eq!((stats.levels[0].files, stats.memtable_entries), (4, 1));
+        assert_eq!(stats.compaction.compactions, 0);
+    }
+}
@@ -0,0 +1,257 @@
+//! A database's tuning options from a TOML file and the command line, for
+//! the subcommands that open one.
+
+use anyhow::{anyhow, Context, Result};
+use middb_core::Config;
+use std::path::{Path, PathBuf};
+
+/// The options whose values are byte counts, which a file may also write
+/// as sizes like "64MB".
+const SIZE_KEYS: [&str; 4] =
+    ["memtable_size", "block_size", "max_bytes_for_level_base", "txn_version_cache_bytes"];
+
+// Where a database's options come from: defaults, overridden by a file,
+// overridden by flags. Not a doc comment, which clap would take as the help
+// text of every subcommand that flattens this in.
+#[derive(Debug, Clone, Default, clap::Args)]
+pub struct ConfigArgs {
+    /// A TOML file of options, named as `Config`'s fields, e.g.
+    /// `memtable_size = "128MB"`.
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+
+    /// Bytes the memtable holds before it's flushed, e.g. 128MB.
+    #[arg(long, value_parser = parse_size)]
+    pub memtable_size: Option<u64>,
+
+    /// Bytes in an SSTable data block, e.g. 64KB.
+    #[arg(long, value_parser = parse_size)]
+    pub block_size: Option<u64>,
+
+    /// Bloom filter bits per key in new SSTables, e.g. 10; more bits mean
+    /// fewer wasted reads for keys a file doesn't hold.
+    #[arg(long)]
+    pub bloom_bits_per_key: Option<usize>,
+
+    /// Files in L0 that start a compaction.
+    #[arg(long)]
+    pub level0_compaction_trigger: Option<usize>,
+
+    /// Bytes L1 holds before compacting into L2, e.g. 10MB.
+    #[arg(long, value_parser = parse_size)]
+    pub level_base_size: Option<u64>,
+
+    /// Print the options the database is opened with.
+    #[arg(long)]
+    pub verbose: bool,
+}
+
+impl ConfigArgs {
+    /// The options for the database at `data_dir`, warning of anything in
+    /// the file they don't use, and checked.
+    pub fn load(&self, data_dir: &Path) -> Result<Config> {
+        let mut config = match &self.config {
+            Some(path) => {
+                let text = std::fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read {:?}", path))?;
+                let (config, warnings) =
+                    from_toml(&text, data_dir).with_context(|| format!("In {:?}", path))?;
+                for warning in warnings {
+                    eprintln!("Warning: {:?}: {}", path, warning);
+                }
+                config
+            }
+            None => Config::new(data_dir),
+        };
+        self.apply(&mut config)?;
+        config.validate().map_err(|e| anyhow!("Invalid configuration: {}", e))?;
+
+        if self.verbose {
+            println!("Configuration:");
+            print!("{}", toml::to_string(&config)?);
+            println!();
+        }
+        Ok(config)
+    }
+
+    /// Set the options given as flags.
+    fn apply(&self, config: &mut Config) -> Result<()> {
+        let usize_size = |bytes: u64, flag: &str| {
+            usize::try_from(bytes).map_err(|_| anyhow!("--{} is too large", flag))
+        };
+        if let Some(bytes) = self.memtable_size {
+            config.memtable_size = usize_size(bytes, "memtable-size")?;
+        }
+        if let Some(bytes) = self.block_size {
+            config.block_size = usize_size(bytes, "block-size")?;
+        }
+        if let Some(bits) = self.bloom_bits_per_key {
+            config.bloom_bits_per_key = bits;
+        }
+        if let Some(files) = self.level0_compaction_trigger {
+            config.level0_file_num_compaction_trigger = files;
+        }
+        if let Some(bytes) = self.level_base_size {
+            config.max_bytes_for_level_base = bytes;
+        }
+        Ok(())
+    }
+}
+
+/// The options a TOML file sets, over the defaults for `data_dir`, and
+/// warnings for the keys it has that aren't options. The database is
+/// always at `data_dir`, whatever the file says; its WAL is too, unless
+/// the file sets `wal_dir`.
+pub fn from_toml(text: &str, data_dir: &Path) -> Result<(Config, Vec<String>)> {
+    let mut table: toml::Table = text.parse()?;
+    let known = toml::Table::try_from(Config::default())?;
+    let mut warnings = Vec::new();
+
+    table.retain(|key, _| {
+        let is_known = known.contains_key(key);
+        if !is_known {
+            warnings.push(format!("unknown option {:?} ignored", key));
+        }
+        is_known
+    });
+    if table.remove("data_dir").is_some() {
+        warnings.push("data_dir ignored; the database is at --data-dir".to_string());
+    }
+    for key in SIZE_KEYS {
+        if let Some(toml::Value::String(size)) = table.get(key) {
+            let bytes = parse_size(size).map_err(|e| anyhow!("{}: {}", key, e))?;
+            let bytes = i64::try_from(bytes).map_err(|_| anyhow!("{}: too large", key))?;
+            table.insert(key.to_string(), toml::Value::Integer(bytes));
+        }
+    }
+
+    let has_wal_dir = table.contains_key("wal_dir");
+    let mut config: Config = table.try_into()?;
+    config.data_dir = data_dir.to_path_buf();
+    if !has_wal_dir {
+        config.wal_dir = Config::new(data_dir).wal_dir;
+    }
+    Ok((config, warnings))
+}
+
+/// A number of bytes, with an optional unit: B, KB, MB, GB or TB, each
+/// 1024 of the one before, in either case and with or without an `i`.
+pub fn parse_size(text: &str) -> Result<u64, String> {
+    let text = text.trim();
+    let digits = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+    let (number, unit) = text.split_at(digits);
+    let number: u64 = number.parse().map_err(|_| format!("{:?} isn't a size, like 64MB", text))?;
+
+    let shift = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 0,
+        "k" | "kb" | "kib" => 10,
+        "m" | "mb" | "mib" => 20,
+        "g" | "gb" | "gib" => 30,
+        "t" | "tb" | "tib" => 40,
+        _ => return Err(format!("unknown unit {:?} in {:?}: use B, KB, MB, GB or TB", unit, text)),
+    };
+    number.checked_mul(1 << shift).ok_or_else(|| format!("{:?} is too large", text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        config: ConfigArgs,
+    }
+
+    fn args(flags: &[&str]) -> ConfigArgs {
+        Cli::try_parse_from(std::iter::once("middb").chain(flags.iter().copied())).unwrap().config
+    }
+
+    const SAMPLE: &str = r#"
+memtable_size = "8MB"
+block_size = 16384
+bloom_bits_per_key = 12
+compaction_style = "universal"
+txn_timeout = 1.5
+use_compresion = true
+data_dir = "/elsewhere"
+"#;
+
+    #[test]
+    fn test_parse_sample_file() {
+        let (config, warnings) = from_toml(SAMPLE, Path::new("db")).unwrap();
+        assert_eq!(config.memtable_size, 8 << 20);
+        assert_eq!(config.block_size, 16384);
+        assert_eq!(config.bloom_bits_per_key, 12);
+        assert_eq!(config.compaction_style, middb_core::config::CompactionStyle::Universal);
+        assert_eq!(config.txn_timeout, Duration::from_millis(1500));
+        // What the file leaves out keeps its default.
+        assert_eq!(config.level0_file_num_compaction_trigger, 4);
+        assert_eq!((config.data_dir, config.wal_dir), ("db".into(), "db/wal".into()));
+
+        // Misspelt, so a warning rather than a failure.
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].contains("\"use_compresion\""));
+        assert!(warnings[1].starts_with("data_dir ignored"));
+
+        let (config, _) = from_toml("wal_dir = \"/fast/wal\"", Path::new("db")).unwrap();
+        assert_eq!(config.wal_dir, PathBuf::from("/fast/wal"));
+    }
+
+    #[test]
+    fn test_flags_beat_file_beat_defaults() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("middb.toml");
+        std::fs::write(&path, SAMPLE).unwrap();
+        let path = path.to_str().unwrap();
+
+        let config = args(&["--config", path, "--memtable-size", "2MiB"]).load(dir.path()).unwrap();
+        assert_eq!(config.memtable_size, 2 << 20);
+        assert_eq!(config.block_size, 16384);
+        assert_eq!(config.max_bytes_for_level_base, Config::default().max_bytes_for_level_base);
+
+        let config = args(&["--level-base-size", "1g", "--bloom-bits-per-key", "4"])
+            .load(dir.path())
+            .unwrap();
+        assert_eq!((config.max_bytes_for_level_base, config.bloom_bits_per_key), (1 << 30, 4));
+        assert_eq!(config.memtable_size, Config::default().memtable_size);
+        assert_eq!(config.wal_dir, dir.path().join("wal"));
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("4096"), Ok(4096));
+        assert_eq!(parse_size("64KB"), Ok(64 << 10));
+        assert_eq!(parse_size("128MB"), Ok(128 << 20));
+        assert_eq!(parse_size("128 mib"), Ok(128 << 20));
+        assert_eq!(parse_size("2G"), Ok(2 << 30));
+        assert_eq!(parse_size("1tb"), Ok(1 << 40));
+        assert_eq!(parse_size("10b"), Ok(10));
+
+        assert!(parse_size("MB").unwrap_err().contains("isn't a size"));
+        assert!(parse_size("1.5MB").unwrap_err().contains("unknown unit"));
+        assert!(parse_size("12XB").unwrap_err().contains("unknown unit"));
+        assert!(parse_size("99999999999TB").unwrap_err().contains("too large"));
+        assert!(Cli::try_parse_from(["middb", "--block-size", "lots"]).is_err());
+    }
+
+    #[test]
+    fn test_invalid_options_name_the_key() {
+        let dir = TempDir::new().unwrap();
+        let err = args(&["--memtable-size", "1KB"]).load(dir.path()).unwrap_err();
+        assert!(err.to_string().contains("memtable_size"), "{}", err);
+
+        let err = from_toml("block_size = \"64XB\"", dir.path()).unwrap_err();
+        assert!(err.to_string().starts_with("block_size: unknown unit"), "{}", err);
+        let err = from_toml("bloom_bits_per_key = \"ten\"", dir.path()).unwrap_err();
+        assert!(format!("{:#}", err).contains("bloom_bits_per_key"), "{:#}", err);
+
+        let path = dir.path().join("bad.toml");
+        std::fs::write(&path, "level0_file_num_compaction_trigger = 1").unwrap();
+        let err = args(&["--config", path.to_str().unwrap()]).load(dir.path()).unwrap_err();
+        assert!(err.to_string().contains("level0_file_num_compaction_trigger"), "{}", err);
+    }
+}
@@ -0,0 +1,493 @@
+//! Key/value records as lines of text, for `middb import` and `middb
+//! export`.
+
+use anyhow::{Context, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use middb_core::{Database, WriteBatch};
+use std::io::{BufRead, Write};
+use std::time::{Duration, Instant};
+
+/// How records are written, one to a line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    /// The key, a tab, and the value, with `\t`, `\n`, `\r`, `\\` and
+    /// `\xHH` escapes.
+    Tsv,
+    /// Two comma-separated fields, quoted where they need to be.
+    Csv,
+    /// An object with `key` and `value` strings, or `key_base64` and
+    /// `value_base64` for bytes that aren't text.
+    Json,
+}
+
+type Record = (Vec<u8>, Vec<u8>);
+type JsonObject = serde_json::Map<String, serde_json::Value>;
+
+/// Read one record from a line, or say what's wrong with it.
+pub fn parse_record(format: Format, line: &str) -> Result<Record, String> {
+    match format {
+        Format::Tsv => {
+            let (key, value) = line.split_once('\t').ok_or("no tab between key and value")?;
+            if value.contains('\t') {
+                return Err("more than two fields".to_string());
+            }
+            Ok((unescape(key)?, unescape(value)?))
+        }
+        Format::Csv => match csv_fields(line)?.as_slice() {
+            [key, value] => Ok((key.as_bytes().to_vec(), value.as_bytes().to_vec())),
+            fields => Err(format!("{} fields, not 2", fields.len())),
+        },
+        Format::Json => {
+            let object: JsonObject = serde_json::from_str(line).map_err(|e| e.to_string())?;
+            Ok((json_bytes(&object, "key")?, json_bytes(&object, "value")?))
+        }
+    }
+}
+
+/// A record as a line, without its newline, that `parse_record` reads
+/// back as the same bytes. CSV can only hold text, so bytes that aren't
+/// UTF-8 fail there.
+pub fn format_record(format: Format, key: &[u8], value: &[u8]) -> Result<String, String> {
+    match format {
+        Format::Tsv => Ok(format!("{}\t{}", escape(key), escape(value))),
+        Format::Csv => {
+            let field = |bytes| match std::str::from_utf8(bytes) {
+                Ok(text) if text.contains(['\n', '\r']) => Err("a line break in CSV"),
+                Ok(text) if text.contains([',', '"']) => {
+                    Ok(format!("\"{}\"", text.replace('"', "\"\"")))
+                }
+                Ok(text) => Ok(text.to_string()),
+                Err(_) => Err("bytes that aren't UTF-8 in CSV"),
+            };
+            Ok(format!("{},{}", field(key)?, field(value)?))
+        }
+        Format::Json => {
+            let mut object = JsonObject::new();
+            json_insert(&mut object, "key", key);
+            json_insert(&mut object, "value", value);
+            Ok(serde_json::Value::Object(object).to_string())
+        }
+    }
+}
+
+/// Bytes for TSV: text as it is, but for tabs, line breaks and
+/// backslashes, and anything else unprintable or not UTF-8 as `\xHH`.
+fn escape(bytes: &[u8]) -> String {
+    let mut escaped = String::with_capacity(bytes.len());
+    for chunk in bytes.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                '\t' => escaped.push_str("\\t"),
+                '\n' => escaped.push_str("\\n"),
+                '\r' => escaped.push_str("\\r"),
+                '\\' => escaped.push_str("\\\\"),
+                c if c.is_control() => {
+                    let mut buf = [0; 4];
+                    for byte in c.encode_utf8(&mut buf).bytes() {
+                        escaped.push_str(&format!("\\x{:02x}", byte));
+                    }
+                }
+                c => escaped.push(c),
+            }
+        }
+        for byte in chunk.invalid() {
+            escaped.push_str(&format!("\\x{:02x}", byte));
+        }
+    }
+    escaped
+}
+
+fn unescape(field: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::with_capacity(field.len());
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buf = [0; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+        match chars.next() {
+            Some('t') => bytes.push(b'\t'),
+            Some('n') => bytes.push(b'\n'),
+            Some('r') => bytes.push(b'\r'),
+            Some('\\') => bytes.push(b'\\'),
+            Some('x') => {
+                let hex: String = chars.by_ref().take(2).collect();
+                let byte = match hex.len() {
+                    2 => u8::from_str_radix(&hex, 16).ok(),
+                    _ => None,
+                };
+                bytes.push(byte.ok_or_else(|| format!("bad escape \\x{}", hex))?);
+            }
+            Some(c) => return Err(format!("unknown escape \\{}", c)),
+            None => return Err("a lone backslash at the end".to_string()),
+        }
+    }
+    Ok(bytes)
+}
+
+fn csv_fields(line: &str) -> Result<Vec<String>, String> {
+    let mut fields = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        let mut field = String::new();
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            loop {
+                match chars.next() {
+                    Some('"') if chars.peek() == Some(&'"') => {
+                        chars.next();
+                        field.push('"');
+                    }
+                    Some('"') => break,
+                    Some(c) => field.push(c),
+                    None => return Err("an unterminated quoted field".to_string()),
+                }
+            }
+        } else {
+            while let Some(&c) = chars.peek() {
+                match c {
+                    ',' => break,
+                    '"' => return Err("a quote inside an unquoted field".to_string()),
+                    _ => field.push(c),
+                }
+                chars.next();
+            }
+        }
+        fields.push(field);
+        match chars.next() {
+            Some(',') => {}
+            None => return Ok(fields),
+            Some(c) => return Err(format!("{:?} after a quoted field", c)),
+        }
+    }
+}
+
+/// `bytes` under `name` as text, or under `name_base64` if they aren't.
+fn json_insert(object: &mut JsonObject, name: &str, bytes: &[u8]) {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => object.insert(name.to_string(), text.into()),
+        Err(_) => object.insert(format!("{}_base64", name), BASE64.encode(bytes).into()),
+    };
+}
+
+fn json_bytes(object: &JsonObject, name: &str) -> Result<Vec<u8>, String> {
+    let encoded = format!("{}_base64", name);
+    match (object.get(name), object.get(&encoded)) {
+        (Some(serde_json::Value::String(text)), None) => Ok(text.as_bytes().to_vec()),
+        (None, Some(serde_json::Value::String(text))) => {
+            BASE64.decode(text).map_err(|e| format!("{}: {}", encoded, e))
+        }
+        (None, None) => Err(format!("no {} or {}", name, encoded)),
+        (Some(_), Some(_)) => Err(format!("both {} and {}", name, encoded)),
+        _ => Err(format!("{} is not a string", name)),
+    }
+}
+
+pub struct ImportOptions {
+    pub format: Format,
+    /// Records written to the database at a time, in one batch.
+    pub batch_size: usize,
+    /// Records between progress reports.
+    pub progress_every: usize,
+    /// Report malformed lines and go on, rather than stop at the first.
+    pub skip_errors: bool,
+}
+
+#[derive(Debug)]
+pub struct ImportSummary {
+    pub records: u64,
+    /// Bytes of keys and values written.
+    pub bytes: u64,
+    pub skipped: u64,
+    pub elapsed: Duration,
+}
+
+impl ImportSummary {
+    /// Records written a second.
+    pub fn rate(&self) -> f64 {
+        self.records as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+/// Write the records read from `input` into `db`, reporting progress and
+/// malformed lines to `log`. Stopping at a malformed line, everything
+/// before it has been written.
+pub fn import(
+    db: &Database,
+    mut input: impl BufRead,
+    options: &ImportOptions,
+    log: &mut impl Write,
+) -> Result<ImportSummary> {
+    let started = Instant::now();
+    let mut summary = ImportSummary { records: 0, bytes: 0, skipped: 0, elapsed: Duration::ZERO };
+    let mut batch = WriteBatch::new();
+    let mut line = Vec::new();
+    let mut number = 0;
+    loop {
+        line.clear();
+        if input.read_until(b'\n', &mut line).context("Failed to read the input")? == 0 {
+            break;
+        }
+        number += 1;
+        let text = std::str::from_utf8(&line).map_err(|_| "not UTF-8".to_string());
+        let text = text.map(|text| text.trim_end_matches(['\n', '\r']));
+        if text.as_ref().is_ok_and(|text| text.is_empty()) {
+            continue;
+        }
+        let (key, value) = match text.and_then(|text| parse_record(options.format, text)) {
+            Ok(record) => record,
+            Err(e) if options.skip_errors => {
+                writeln!(log, "line {}: {} (skipped)", number, e)?;
+                summary.skipped += 1;
+                continue;
+            }
+            Err(e) => {
+                db.write(batch)?;
+                anyhow::bail!("line {}: {}", number, e);
+            }
+        };
+        
+        summary.records += 1;
+        summary.bytes += (key.len() + value.len()) as u64;
+        batch.put(key, value);
+        if batch.len() >= options.batch_size {
+            db.write(std::mem::take(&mut batch))?;
+        }
+        let every = options.progress_every as u64;
+        if every > 0 && summary.records.is_multiple_of(every) {
+            writeln!(log, "{} records imported", summary.records)?;
+        }
+    }
+    db.write(batch)?;
+    summary.elapsed = started.elapsed();
+    Ok(summary)
+}
+
+#[derive(Debug)]
+pub struct ExportSummary {
+    pub records: u64,
+    /// Bytes of keys and values read.
+    pub bytes: u64,
+    pub elapsed: Duration,
+}
+
+/// Entries read from the database at a time while exporting.
+const EXPORT_PAGE: usize = 1000;
+
+/// Write the entries with keys in `[start, end)` to `out`, a line each.
+/// The range is read a page at a time, so memory stays bounded however
+/// much there is.
+pub fn export(
+    db: &Database,
+    start: &[u8],
+    end: &[u8],
+    format: Format,
+    out: &mut impl Write,
+) -> Result<ExportSummary> {
+    let started = Instant::now();
+    let mut summary = ExportSummary { records: 0, bytes: 0, elapsed: Duration::ZERO };
+    let mut start = start.to_vec();
+    loop {
+        let (page, resume) = db.scan_range_page(&start, end, EXPORT_PAGE)?;
+        for (key, value) in page {
+            let line = format_record(format, &key, &value)
+                .map_err(|e| anyhow::anyhow!("key {:?}: {}", String::from_utf8_lossy(&key), e))?;
+            writeln!(out, "{}", line)?;
+            summary.records += 1;
+            summary.bytes += (key.len() + value.len()) as u64;
+        }
+        match resume {
+            Some(next) => start = next,
+            None => break,
+        }
+    }
+    out.flush()?;
+    summary.elapsed = started.elapsed();
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use middb_core::Config;
+    use std::fs::File;
+    use std::io::BufReader;
+    use tempfile::TempDir;
+    
+    fn options(format: Format, skip_errors: bool) -> ImportOptions {
+        ImportOptions { format, batch_size: 100, progress_every: 0, skip_errors }
+    }
+    
+    #[test]
+    fn test_parse_tsv() {
+        let record = parse_record(Format::Tsv, "a\\tb\tline\\none\\\\\\x00").unwrap();
+        assert_eq!(record, (b"a\tb".to_vec(), b"line\none\\\x00".to_vec()));
+        assert!(parse_record(Format::Tsv, "no tab").is_err());
+        assert!(parse_record(Format::Tsv, "a\tb\tc").is_err());
+        assert!(parse_record(Format::Tsv, "a\t\\q").is_err());
+        assert!(parse_record(Format::Tsv, "a\t\\x4").is_err());
+    }
+    
+    #[test]
+    fn test_parse_csv() {
+        assert_eq!(parse_record(Format::Csv, "k,v").unwrap(), (b"k".to_vec(), b"v".to_vec()));
+        let record = parse_record(Format::Csv, "\"a,b\",\"say \"\"hi\"\"\"").unwrap();
+        assert_eq!(record, (b"a,b".to_vec(), b"say \"hi\"".to_vec()));
+        assert_eq!(parse_record(Format::Csv, "k,").unwrap(), (b"k".to_vec(), Vec::new()));
+        assert!(parse_record(Format::Csv, "k,v,w").is_err());
+        assert!(parse_record(Format::Csv, "\"k,v").is_err());
+        assert!(parse_record(Format::Csv, "k\"q,v").is_err());
+    }
+    
+    #[test]
+    fn test_parse_json() {
+        let record = parse_record(Format::Json, r#"{"key": "k", "value_base64": "AP8="}"#).unwrap();
+        assert_eq!(record, (b"k".to_vec(), vec![0, 0xff]));
+        assert!(parse_record(Format::Json, r#"{"key": "k"}"#).is_err());
+        assert!(parse_record(Format::Json, r#"{"key": 1, "value": "v"}"#).is_err());
+        assert!(parse_record(Format::Json, r#"{"key": "k", "value_base64": "!"}"#).is_err());
+        assert!(parse_record(Format::Json, "not json").is_err());
+    }
+    
+    /// Keys and values needing every kind of escape.
+    fn awkward_entries() -> Vec<Record> {
+        vec![
+            (b"plain".to_vec(), b"text value".to_vec()),
+            (b"tab\tkey".to_vec(), b"line\nbreak\r\n".to_vec()),
+            (b"back\\slash".to_vec(), b"\\x41 is not an escape".to_vec()),
+            (vec![0, 0xff, b'b', 0x7f], vec![0xc3, 0x28, 0x01]),
+            ("h\u{e9}llo, \"quoted\"".as_bytes().to_vec(), Vec::new()),
+        ]
+    }
+    
+    #[test]
+    fn test_records_round_trip() {
+        for format in [Format::Tsv, Format::Json] {
+            for (key, value) in awkward_entries() {
+                let line = format_record(format, &key, &value).unwrap();
+                assert!(!line.contains('\n'), "{:?} spans lines", line);
+                assert_eq!(parse_record(format, &line).unwrap(), (key, value), "{:?}", line);
+            }
+        }
+        
+        let key = "h\u{e9}llo, \"quoted\"".as_bytes();
+        let line = format_record(Format::Csv, key, b"v").unwrap();
+        assert_eq!(line, "\"h\u{e9}llo, \"\"quoted\"\"\",v");
+        assert_eq!(parse_record(Format::Csv, &line).unwrap().0, key);
+        assert!(format_record(Format::Csv, &[0xff], b"v").is_err());
+        assert!(format_record(Format::Csv, b"k", b"two\nlines").is_err());
+    }
+    
+    #[test]
+    fn test_export_import_round_trip() {
+        let dir = TempDir::new().unwrap();
+        let db = Database::open(Config::new(dir.path().join("source"))).unwrap();
+        let mut entries = awkward_entries();
+        entries.extend((0..2500).map(|i| (format!("key:{:04}", i).into_bytes(), vec![i as u8; 3])));
+        for (key, value) in &entries {
+            db.put(key.clone(), value.clone()).unwrap();
+        }
+        db.put(b"deleted".to_vec(), b"x".to_vec()).unwrap();
+        db.delete(b"deleted".to_vec()).unwrap();
+        entries.sort();
+        
+        for (i, format) in [Format::Tsv, Format::Json].into_iter().enumerate() {
+            let mut dumped = Vec::new();
+            let summary = export(&db, b"", b"", format, &mut dumped).unwrap();
+            assert_eq!(summary.records, entries.len() as u64);
+            
+            let copy = Database::open(Config::new(dir.path().join(format!("copy{}", i)))).unwrap();
+            let options = options(format, false);
+            import(&copy, &dumped[..], &options, &mut Vec::new()).unwrap();
+            assert_eq!(copy.scan_range(b"", b"").unwrap(), entries);
+        }
+    }
+    
+    #[test]
+    fn test_export_range() {
+        let dir = TempDir::new().unwrap();
+        let db = Database::open(Config::new(dir.path())).unwrap();
+        for key in ["a", "b", "c", "d"] {
+            db.put(key.as_bytes().to_vec(), b"v".to_vec()).unwrap();
+        }
+        
+        let mut dumped = Vec::new();
+        let summary = export(&db, b"b", b"d", Format::Tsv, &mut dumped).unwrap();
+        assert_eq!(summary.records, 2);
+        assert_eq!(String::from_utf8(dumped).unwrap(), "b\tv\nc\tv\n");
+        
+        let mut dumped = Vec::new();
+        export(&db, b"c", b"", Format::Json, &mut dumped).unwrap();
+        let expected = "{\"key\":\"c\",\"value\":\"v\"}\n{\"key\":\"d\",\"value\":\"v\"}\n";
+        assert_eq!(String::from_utf8(dumped).unwrap(), expected);
+    }
+    
+    #[test]
+    fn test_import_file() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("dump.tsv");
+        let mut file = File::create(&path).unwrap();
+        for i in 0..10_000 {
+            writeln!(file, "key:{:05}\tvalue\\t{}", i, i).unwrap();
+        }
+        drop(file);
+        
+        let data = dir.path().join("data");
+        {
+            let db = Database::open(Config::new(&data)).unwrap();
+            let mut options = options(Format::Tsv, false);
+            options.progress_every = 4000;
+            let mut log = Vec::new();
+            let input = BufReader::new(File::open(&path).unwrap());
+            let summary = import(&db, input, &options, &mut log).unwrap();
+            assert_eq!(summary.records, 10_000);
+            assert_eq!(summary.skipped, 0);
+            let log = String::from_utf8(log).unwrap();
+            assert_eq!(log, "4000 records imported\n8000 records imported\n");
+            db.close().unwrap();
+        }
+        
+        let db = Database::open(Config::new(&data)).unwrap();
+        for i in (0..10_000).step_by(997) {
+            let value = db.get(&format!("key:{:05}", i).into_bytes()).unwrap();
+            assert_eq!(value, Some(format!("value\t{}", i).into_bytes()));
+        }
+        assert_eq!(db.scan_prefix(b"key:").unwrap().len(), 10_000);
+    }
+    
+    #[test]
+    fn test_import_stops_at_malformed_line() {
+        let dir = TempDir::new().unwrap();
+        let db = Database::open(Config::new(dir.path())).unwrap();
+        let input = "a\t1\nb\t2\n\nbroken\nc\t3\n";
+        
+        let options = options(Format::Tsv, false);
+        let error = import(&db, input.as_bytes(), &options, &mut Vec::new()).unwrap_err();
+        assert_eq!(error.to_string(), "line 4: no tab between key and value");
+        // What came before it was written; what came after wasn't.
+        assert_eq!(db.get(&b"b".to_vec()).unwrap(), Some(b"2".to_vec()));
+        assert_eq!(db.get(&b"c".to_vec()).unwrap(), None);
+    }
+    
+    #[test]
+    fn test_import_skips_malformed_lines() {
+        let dir = TempDir::new().unwrap();
+        let db = Database::open(Config::new(dir.path())).unwrap();
+        let input: &[u8] = b"{\"key\": \"a\", \"value\": \"1\"}\r\n\
+            {\"key\": \"b\"}\n\
+            \xff\n\
+            {\"key\": \"c\", \"value\": \"3\"}\n";
+        
+        let mut log = Vec::new();
+        let summary = import(&db, input, &options(Format::Json, true), &mut log).unwrap();
+        assert_eq!((summary.records, summary.skipped), (2, 2));
+        assert_eq!(summary.bytes, 4);
+        let log = String::from_utf8(log).unwrap();
+        let skipped = ["line 2: no value or value_base64 (skipped)", "line 3: not UTF-8 (skipped)"];
+        assert_eq!(log.lines().collect::<Vec<_>>(), skipped);
+        assert_eq!(db.get(&b"a".to_vec()).unwrap(), Some(b"1".to_vec()));
+        assert_eq!(db.get(&b"c".to_vec()).unwrap(), Some(b"3".to_vec()));
+    }
+}
@@ -0,0 +1,150 @@
+//! How the REPLs read keys and values from a command line, and how they
+//! print them back.
+
+use anyhow::{Context, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Encoding {
+    /// Arguments as typed; results as text where they're printable, and in
+    /// hex where not.
+    #[default]
+    Utf8,
+    /// Two hex digits a byte, with or without a leading `0x`.
+    Hex,
+    /// Standard, padded base64.
+    Base64,
+}
+
+impl Encoding {
+    /// The bytes `arg` stands for.
+    pub fn decode(self, arg: &str) -> Result<Vec<u8>> {
+        match self {
+            Encoding::Utf8 => Ok(arg.as_bytes().to_vec()),
+            Encoding::Hex => {
+                let digits = arg.strip_prefix("0x").unwrap_or(arg);
+                parse_hex(digits).with_context(|| {
+                    format!("{:?} isn't hex: it needs an even number of hex digits", arg)
+                })
+            }
+            Encoding::Base64 => {
+                BASE64.decode(arg).with_context(|| format!("{:?} isn't base64", arg))
+            }
+        }
+    }
+
+    /// `bytes` written out for printing, in a form `decode` reads back.
+    pub fn encode(self, bytes: &[u8]) -> String {
+        match self {
+            Encoding::Utf8 => display_bytes(bytes),
+            Encoding::Hex => to_hex(bytes),
+            Encoding::Base64 => BASE64.encode(bytes),
+        }
+    }
+
+    fn parse(name: &str) -> Result<Self> {
+        match name {
+            "utf8" => Ok(Encoding::Utf8),
+            "hex" => Ok(Encoding::Hex),
+            "base64" => Ok(Encoding::Base64),
+            _ => anyhow::bail!("Unknown encoding {:?}: use utf8, hex or base64", name),
+        }
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Encoding::Utf8 => "utf8",
+            Encoding::Hex => "hex",
+            Encoding::Base64 => "base64",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Run the REPLs' `:encoding [<name>]`, which switches `encoding` to the
+/// one named, or with no name says which is in use. Returns what to print.
+pub fn meta(encoding: &mut Encoding, args: &[&str]) -> Result<String> {
+    match args {
+        [] => Ok(format!("Encoding: {}", encoding)),
+        [name] => {
+            *encoding = Encoding::parse(name)?;
+            Ok(format!("Encoding set to {}", encoding))
+        }
+        _ => anyhow::bail!("Usage: :encoding [utf8|hex|base64]"),
+    }
+}
+
+/// Bytes as text where they're printable UTF-8, and in hex where not.
+pub fn display_bytes(bytes: &[u8]) -> String {
+    match std::str::from_utf8(bytes) {
+        Ok(text) if !text.chars().any(char::is_control) => text.to_string(),
+        _ => format!("0x{}", to_hex(bytes)),
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn parse_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) || !text.is_ascii() {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let bytes = [0x00, 0xff, b'a', b'\n'];
+        for encoding in [Encoding::Utf8, Encoding::Hex, Encoding::Base64] {
+            let encoded = encoding.encode(&bytes);
+            let decoded = match encoding {
+                // Unprintable bytes are shown in hex, which utf8 takes as typed.
+                Encoding::Utf8 => Encoding::Hex.decode(&encoded).unwrap(),
+                _ => encoding.decode(&encoded).unwrap(),
+            };
+            assert_eq!(decoded, bytes, "{}", encoding);
+        }
+        assert_eq!(Encoding::Hex.encode(b"\x00ab"), "006162");
+        assert_eq!(Encoding::Base64.encode(b"\x00ab"), "AGFi");
+    }
+
+    #[test]
+    fn test_hex_prefix_optional() {
+        assert_eq!(Encoding::Hex.decode("0x00ff").unwrap(), [0x00, 0xff]);
+        assert_eq!(Encoding::Hex.decode("00FF").unwrap(), [0x00, 0xff]);
+        assert!(Encoding::Hex.decode("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_malformed_input() {
+        for bad in ["abc", "0xzz", "0x0", "é1"] {
+            let err = Encoding::Hex.decode(bad).unwrap_err();
+            assert!(err.to_string().contains("isn't hex"), "{}", err);
+        }
+        assert!(Encoding::Base64.decode("A").is_err());
+        assert!(Encoding::Base64.decode("!!!!").is_err());
+    }
+
+    #[test]
+    fn test_meta() {
+        let mut encoding = Encoding::default();
+        assert_eq!(meta(&mut encoding, &[]).unwrap(), "Encoding: utf8");
+        assert_eq!(meta(&mut encoding, &["base64"]).unwrap(), "Encoding set to base64");
+        assert_eq!(encoding, Encoding::Base64);
+        assert!(meta(&mut encoding, &["rot13"]).is_err());
+        assert!(meta(&mut encoding, &["hex", "utf8"]).is_err());
+        assert_eq!(encoding, Encoding::Base64);
+    }
+}
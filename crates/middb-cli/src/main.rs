@@ -1,83 +1,481 @@
+mod batch;
+mod bench;
+mod check;
+mod compact;
+mod config;
+mod dump;
+mod encoding;
+mod output;
+mod repl;
+mod sql;
+mod sst_dump;
+mod stats;
+
 use anyhow::{Context, Result};
 use clap::{Parser, Subcommand};
+use batch::BatchArgs;
+use config::ConfigArgs;
+use encoding::Encoding;
 use middb_core::{Config, Database};
-use middb_network::{Client, Server};
+use output::{plural, Printer};
+use middb_network::{
+    Client, ClientOptions, ReconnectPolicy, Replica, Server, ServerConfig,
+};
 use middb_query::{BinaryOperator, Executor, Expr, Planner, Row, Table, Value};
 use rustyline::error::ReadlineError;
-use rustyline::DefaultEditor;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
 
 #[derive(Parser)]
 #[command(name = "middb")]
 #[command(about = "MidDB command-line interface")]
 struct Cli {
+    /// Where the REPLs keep their history; ~/.middb_history if not given.
+    #[arg(long, global = true)]
+    history_file: Option<PathBuf>,
+    
+    /// Lines of REPL history kept; 0 for none.
+    #[arg(long, global = true, default_value_t = 1000)]
+    history_size: usize,
+    
     #[command(subcommand)]
     command: Commands,
 }
 
 #[derive(Subcommand)]
 enum Commands {
+    /// Serve a database over the network.
     Server {
         #[arg(short, long, default_value = "./data")]
         data_dir: PathBuf,
         
         #[arg(short, long, default_value = "127.0.0.1:7878")]
         bind: String,
+        
+        /// Serve Prometheus metrics over HTTP at this address, e.g.
+        /// 127.0.0.1:9187.
+        #[arg(long)]
+        metrics_bind: Option<String>,
+        
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+    
+    /// Follow a leader's writes and serve them read-only.
+    Replica {
+        #[arg(short, long, default_value = "./data")]
+        data_dir: PathBuf,
+        
+        #[arg(short, long, default_value = "127.0.0.1:7879")]
+        bind: String,
+        
+        #[arg(short, long, default_value = "127.0.0.1:7878")]
+        leader: String,
+        
+        /// Serve Prometheus metrics over HTTP at this address.
+        #[arg(long)]
+        metrics_bind: Option<String>,
     },
     
     Client {
         #[arg(short, long, default_value = "127.0.0.1:7878")]
         server: String,
+        
+        /// How keys and values are typed and shown; `:encoding` changes it.
+        #[arg(long, value_enum, default_value = "utf8")]
+        encoding: Encoding,
+        
+        /// How results are printed; `:output` changes it.
+        #[arg(short, long, value_enum, default_value = "table")]
+        output: output::Format,
+        
+        #[command(flatten)]
+        batch: BatchArgs,
     },
     
+    /// Open a database in this process and run commands on it, from a REPL
+    /// or `--exec`.
     Local {
         #[arg(short, long, default_value = "./data")]
         data_dir: PathBuf,
+        
+        /// How keys and values are typed and shown; `:encoding` changes it.
+        #[arg(long, value_enum, default_value = "utf8")]
+        encoding: Encoding,
+        
+        /// How results are printed; `:output` changes it.
+        #[arg(short, long, value_enum, default_value = "table")]
+        output: output::Format,
+        
+        #[command(flatten)]
+        batch: BatchArgs,
+        
+        #[command(flatten)]
+        config: ConfigArgs,
     },
     
     Query {
         #[arg(short, long, default_value = "./data")]
         data_dir: PathBuf,
     },
+    
+    /// Time a workload against a database.
+    Bench {
+        #[arg(short, long, default_value = "./bench")]
+        data_dir: PathBuf,
+        
+        #[arg(short, long, value_enum, default_value = "fillseq")]
+        workload: bench::Workload,
+        
+        /// Operations to run, and keys in the key space.
+        #[arg(short, long, default_value_t = 1_000_000)]
+        num: u64,
+        
+        #[arg(long, default_value_t = 100)]
+        value_size: usize,
+        
+        #[arg(short, long, default_value_t = 1)]
+        threads: usize,
+        
+        /// Sync each write to disk before it returns.
+        #[arg(long)]
+        sync: bool,
+        
+        /// Of `mixed`'s operations, the percentage that are reads.
+        #[arg(long, default_value_t = 50, value_parser = clap::value_parser!(u32).range(0..=100))]
+        read_percent: u32,
+        
+        #[arg(long, default_value_t = 0x6d69_6464_62)]
+        seed: u64,
+        
+        /// Append the results to this CSV file.
+        #[arg(long)]
+        csv: Option<PathBuf>,
+        
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+    
+    /// Run SQL against a database's tables.
+    Sql {
+        #[arg(short, long, default_value = "./data")]
+        data_dir: PathBuf,
+        
+        /// How query results are printed; `:output` changes it.
+        #[arg(short, long, value_enum, default_value = "table")]
+        output: output::Format,
+        
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+    
+    /// Load key/value records from a file, one to a line.
+    Import {
+        #[arg(short, long, default_value = "./data")]
+        data_dir: PathBuf,
+        
+        #[arg(short, long)]
+        file: PathBuf,
+        
+        #[arg(long, value_enum, default_value = "tsv")]
+        format: dump::Format,
+        
+        /// Records written at a time, in one batch.
+        #[arg(long, default_value_t = 1000)]
+        batch_size: usize,
+        
+        /// Records between progress reports; 0 for none.
+        #[arg(long, default_value_t = 100_000)]
+        progress_every: usize,
+        
+        /// Report malformed lines and go on, rather than stop at the first.
+        #[arg(long)]
+        skip_errors: bool,
+    },
+    
+    /// Write a database's entries to a file, one to a line, in a form
+    /// `import` reads back. The database itself is left as it was.
+    Export {
+        #[arg(short, long, default_value = "./data")]
+        data_dir: PathBuf,
+        
+        /// Where to write; standard output if not given.
+        #[arg(short, long)]
+        out: Option<PathBuf>,
+        
+        #[arg(long, value_enum, default_value = "tsv")]
+        format: dump::Format,
+        
+        /// The first key to export.
+        #[arg(long)]
+        start: Option<String>,
+        
+        /// The key to stop before.
+        #[arg(long)]
+        end: Option<String>,
+    },
+    
+    /// Show a database's levels, sizes and counters, leaving it as it was.
+    Stats {
+        #[arg(short, long, default_value = "./data")]
+        data_dir: PathBuf,
+        
+        #[arg(short, long, value_enum, default_value = "table")]
+        output: output::Format,
+        
+        /// The same as `--output json`.
+        #[arg(long)]
+        json: bool,
+    },
+    
+    /// Compact a database's files, merging a key range down its levels.
+    Compact {
+        #[arg(short, long, default_value = "./data")]
+        data_dir: PathBuf,
+        
+        /// The first key to compact.
+        #[arg(long)]
+        start: Option<String>,
+        
+        /// The key to stop before.
+        #[arg(long)]
+        end: Option<String>,
+        
+        /// Merge only this level into the one below.
+        #[arg(long, value_parser = clap::value_parser!(u32).range(0..6))]
+        level: Option<u32>,
+        
+        /// Wait for another process to close the database, rather than
+        /// refuse to run.
+        #[arg(long)]
+        wait: bool,
+        
+        /// Show what would be merged, and merge nothing.
+        #[arg(long)]
+        dry_run: bool,
+        
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+    
+    /// Check every file of a database for damage, changing nothing.
+    Check {
+        #[arg(short, long, default_value = "./data")]
+        data_dir: PathBuf,
+        
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+    
+    /// Salvage a damaged database, moving what can't be read into `lost/`.
+    Repair {
+        #[arg(short, long, default_value = "./data")]
+        data_dir: PathBuf,
+        
+        /// Don't ask before starting.
+        #[arg(short, long)]
+        yes: bool,
+        
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+    
+    /// Show what's inside one SSTable file, or check it for damage.
+    SstDump {
+        file: PathBuf,
+        
+        #[arg(short, long, value_enum, default_value = "footer")]
+        mode: sst_dump::Mode,
+        
+        /// The first key to scan.
+        #[arg(long)]
+        start: Option<String>,
+        
+        /// The key to stop a scan before.
+        #[arg(long)]
+        end: Option<String>,
+        
+        /// Entries a scan shows at most.
+        #[arg(short, long, default_value_t = 100)]
+        limit: usize,
+    },
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
+    let history = repl::HistoryOptions { file: cli.history_file, size: cli.history_size };
     
     match cli.command {
-        Commands::Server { data_dir, bind } => {
-            run_server(data_dir, bind).await
+        Commands::Server { data_dir, bind, metrics_bind, config } => {
+            run_server(config.load(&data_dir)?, bind, metrics_bind).await
+        }
+        Commands::Replica { data_dir, bind, leader, metrics_bind } => {
+            run_replica(data_dir, bind, leader, metrics_bind).await
+        }
+        Commands::Client { server, encoding, output, batch } => {
+            run_client(&server, Printer::new(output, encoding), &history, &batch).await
+        }
+        Commands::Local { data_dir, encoding, output, batch, config } => {
+            let printer = Printer::new(output, encoding);
+            run_local(config.load(&data_dir)?, printer, &history, &batch)
+        }
+        Commands::Import { data_dir, file, format, batch_size, progress_every, skip_errors } => {
+            let options = dump::ImportOptions {
+                format,
+                batch_size: batch_size.max(1),
+                progress_every,
+                skip_errors,
+            };
+            run_import(data_dir, file, options)
         }
-        Commands::Client { server } => {
-            run_client(&server).await
+        Commands::Export { data_dir, out, format, start, end } => {
+            run_export(data_dir, out, format, start.unwrap_or_default(), end.unwrap_or_default())
         }
-        Commands::Local { data_dir } => {
-            run_local(data_dir)
+        Commands::Stats { data_dir, output, json } => {
+            run_stats(data_dir, if json { output::Format::Json } else { output })
         }
         Commands::Query { data_dir } => {
-            run_query(data_dir)
+            run_query(data_dir, &history)
+        }
+        Commands::Bench {
+            data_dir,
+            workload,
+            num,
+            value_size,
+            threads,
+            sync,
+            read_percent,
+            seed,
+            csv,
+            config,
+        } => {
+            let options = bench::BenchOptions {
+                workload,
+                num,
+                value_size,
+                threads: threads.max(1),
+                read_percent,
+                seed,
+            };
+            run_bench(config.load(&data_dir)?, options, sync, csv)
+        }
+        Commands::Sql { data_dir, output, config } => {
+            run_sql(config.load(&data_dir)?, output, &history)
+        }
+        Commands::Compact { data_dir, start, end, level, wait, dry_run, config } => {
+            let options = compact::CompactOptions {
+                start: start.unwrap_or_default().into_bytes(),
+                end: end.unwrap_or_default().into_bytes(),
+                level,
+                dry_run,
+            };
+            run_compact(config.load(&data_dir)?, options, wait)
+        }
+        Commands::Check { data_dir, config } => {
+            run_check(config.load(&data_dir)?)
+        }
+        Commands::Repair { data_dir, yes, config } => {
+            run_repair(config.load(&data_dir)?, yes)
+        }
+        Commands::SstDump { file, mode, start, end, limit } => {
+            let options = sst_dump::DumpOptions {
+                mode,
+                start: start.unwrap_or_default().into_bytes(),
+                end: end.unwrap_or_default().into_bytes(),
+                limit,
+            };
+            run_sst_dump(file, options)
         }
     }
 }
 
-async fn run_server(data_dir: PathBuf, bind: String) -> Result<()> {
+async fn run_server(config: Config, bind: String, metrics_bind: Option<String>) -> Result<()> {
     println!("Starting MidDB server");
-    println!("Data directory: {:?}", data_dir);
+    println!("Data directory: {:?}", config.data_dir);
     println!("Binding to: {}", bind);
     
-    let config = Config::new(data_dir);
     let db = Database::open(config).context("Failed to open database")?;
     
-    let server = Server::new(db, bind.clone());
+    let config = ServerConfig { metrics_addr: metrics_bind, ..ServerConfig::default() };
+    let server = Server::with_config(db, bind.clone(), config);
     println!("Server listening on {}", bind);
     
-    server.run().await.context("Server error")?;
+    let ctrl_c = async {
+        let _ = tokio::signal::ctrl_c().await;
+        println!("Shutting down");
+    };
+    server.run_with_shutdown(ctrl_c).await.context("Server error")?;
     
     Ok(())
 }
 
-async fn run_client(server: &str) -> Result<()> {
+async fn run_replica(
+    data_dir: PathBuf,
+    bind: String,
+    leader: String,
+    metrics_bind: Option<String>,
+) -> Result<()> {
+    println!("Starting MidDB replica of {}", leader);
+    println!("Data directory: {:?}", data_dir);
+    println!("Binding to: {}", bind);
+    
+    let config = Config::new(data_dir);
+    let db = Arc::new(Database::open(config).context("Failed to open database")?);
+    println!("Following from sequence {}", db.next_sequence());
+    
+    let options = ClientOptions {
+        reconnect: Some(ReconnectPolicy::default()),
+        ..ClientOptions::default()
+    };
+    let replica = Replica::spawn(Arc::clone(&db), leader, options);
+    
+    let config = ServerConfig {
+        read_only: true,
+        metrics_addr: metrics_bind,
+        ..ServerConfig::default()
+    };
+    let server = Server::with_shared_db(db, bind.clone(), config);
+    println!("Server listening on {}", bind);
+    
+    let ctrl_c = async {
+        let _ = tokio::signal::ctrl_c().await;
+        println!("Shutting down");
+    };
+    let served = server.run_with_shutdown(ctrl_c).await.context("Server error");
+    replica.stop().await.context("Replication failed")?;
+    served
+}
+
+/// What the client REPL completes.
+const CLIENT_COMMANDS: &[&str] = &[
+    "get", "mget", "put", "delete", "del", "scan", "ping", "stats", "metrics", "property",
+    ":encoding", ":output", "quit", "exit",
+];
+const CLIENT_FLAGS: &[(&str, &[&str])] = &[("scan", &["-r"])];
+
+async fn run_client(
+    server: &str,
+    mut printer: Printer,
+    history: &repl::HistoryOptions,
+    batch: &BatchArgs,
+) -> Result<()> {
+    if let Some(commands) = batch.commands()? {
+        let mut client = Client::connect(server).await.context("Failed to connect to server")?;
+        let mut run = batch::Batch::new(batch.continue_on_error);
+        for command in commands.iter().take_while(|c| !batch::is_quit(c)) {
+            let result = handle_client_command(&mut client, command, &mut printer).await;
+            if !run.record(result, &mut io::stderr()) {
+                break;
+            }
+        }
+        return run.finish();
+    }
+    
     println!("Connecting to {}", server);
     
     let mut client = Client::connect(server)
@@ -85,12 +483,14 @@ async fn run_client(server: &str) -> Result<()> {
         .context("Failed to connect to server")?;
     
     client.ping().await.context("Ping failed")?;
-    println!("Connected to server\n");
+    println!("Connected to server (protocol version {})\n", client.server_version());
     
-    let mut rl = DefaultEditor::new()?;
+    let helper = repl::ReplHelper::new(CLIENT_COMMANDS).with_flags(CLIENT_FLAGS);
+    let mut rl = repl::editor(helper, history)?;
+    rl.helper_mut().unwrap().encoding = printer.encoding;
     
     println!("MidDB Client REPL");
-    println!("Commands: get <key>, put <key> <value>, delete <key>, quit");
+    println!("Commands: get <key>, mget <key>..., put <key> <value>, delete <key>, scan [-r] [<start> [<end>]], stats, metrics, property <name>, :encoding [utf8|hex|base64], :output [table|json|csv], quit");
     println!();
     
     loop {
@@ -110,9 +510,10 @@ async fn run_client(server: &str) -> Result<()> {
                     break;
                 }
                 
-                if let Err(e) = handle_client_command(&mut client, line).await {
+                if let Err(e) = handle_client_command(&mut client, line, &mut printer).await {
                     eprintln!("Error: {}", e);
                 }
+                rl.helper_mut().unwrap().encoding = printer.encoding;
             }
             Err(ReadlineError::Interrupted) => {
                 println!("Interrupted");
@@ -128,32 +529,52 @@ async fn run_client(server: &str) -> Result<()> {
         }
     }
     
+    repl::save_history(&mut rl, history);
     println!("Goodbye");
     Ok(())
 }
 
-async fn handle_client_command(client: &mut Client, line: &str) -> Result<()> {
+async fn handle_client_command(
+    client: &mut Client,
+    line: &str,
+    printer: &mut Printer,
+) -> Result<()> {
     let parts: Vec<&str> = line.split_whitespace().collect();
     
     if parts.is_empty() {
         return Ok(());
     }
     
+    let encoding = printer.encoding;
+    let out = &mut io::stdout();
+    
     match parts[0] {
         "get" => {
             if parts.len() != 2 {
                 anyhow::bail!("Usage: get <key>");
             }
             
-            let key = parts[1].as_bytes();
-            match client.get(key).await? {
-                Some(value) => {
-                    println!("{}", String::from_utf8_lossy(&value));
-                }
-                None => {
-                    println!("(nil)");
-                }
+            let key = encoding.decode(parts[1])?;
+            let value = client.get(&key).await?;
+            printer.value(out, "value", value.map(|v| encoding.encode(&v)))?;
+        }
+        
+        "mget" => {
+            if parts.len() < 2 {
+                anyhow::bail!("Usage: mget <key>...");
             }
+            
+            let keys = parts[1..]
+                .iter()
+                .map(|k| encoding.decode(k))
+                .collect::<Result<Vec<_>>>()?;
+            let key_refs: Vec<&[u8]> = keys.iter().map(Vec::as_slice).collect();
+            let rows: Vec<Row> = keys
+                .iter()
+                .zip(client.multi_get(&key_refs).await?)
+                .map(|(key, value)| printer.entry(key, value.as_deref()))
+                .collect();
+            printer.rows(out, &output::entry_columns(), &rows)?;
         }
         
         "put" => {
@@ -161,11 +582,11 @@ async fn handle_client_command(client: &mut Client, line: &str) -> Result<()> {
                 anyhow::bail!("Usage: put <key> <value>");
             }
             
-            let key = parts[1].as_bytes();
-            let value = parts[2..].join(" ");
+            let key = encoding.decode(parts[1])?;
+            let value = encoding.decode(&parts[2..].join(" "))?;
             
-            client.put(key, value.as_bytes()).await?;
-            println!("OK");
+            client.put(&key, &value).await?;
+            printer.status(out, "OK")?;
         }
         
         "delete" | "del" => {
@@ -173,14 +594,66 @@ async fn handle_client_command(client: &mut Client, line: &str) -> Result<()> {
                 anyhow::bail!("Usage: delete <key>");
             }
             
-            let key = parts[1].as_bytes();
-            client.delete(key).await?;
-            println!("OK");
+            let key = encoding.decode(parts[1])?;
+            client.delete(&key).await?;
+            printer.status(out, "OK")?;
+        }
+        
+        "scan" => {
+            let reverse = parts.get(1) == Some(&"-r");
+            let bounds = &parts[1 + reverse as usize..];
+            if bounds.len() > 2 {
+                anyhow::bail!("Usage: scan [-r] [<start> [<end>]]");
+            }
+            
+            let start = bounds.first().map(|s| encoding.decode(s)).transpose()?;
+            let end = bounds.get(1).map(|s| encoding.decode(s)).transpose()?;
+            let mut scan = client.scan_stream(start.as_deref(), end.as_deref());
+            if reverse {
+                scan = scan.reverse();
+            }
+            
+            let mut rows = Vec::new();
+            while let Some((key, value)) = scan.next().await? {
+                rows.push(printer.entry(&key, Some(&value)));
+            }
+            printer.rows(out, &output::entry_columns(), &rows)?;
+            printer.footer(out, &format!("({} {})", rows.len(), plural(rows.len(), "entry")))?;
         }
         
         "ping" => {
             client.ping().await?;
-            println!("PONG");
+            printer.status(out, "PONG")?;
+        }
+        
+        "stats" => {
+            let stats = client.stats().await?;
+            printer.figures(out, &stats::render_server(&stats), &serde_json::to_value(&stats)?)?;
+        }
+        
+        "metrics" => {
+            print!("{}", client.metrics().await?);
+        }
+        
+        "property" => {
+            if parts.len() != 2 {
+                anyhow::bail!("Usage: property <name>");
+            }
+            
+            match client.property(parts[1]).await? {
+                None if printer.format == output::Format::Table => {
+                    writeln!(out, "(unknown property)")?;
+                }
+                value => printer.value(out, parts[1], value)?,
+            }
+        }
+        
+        ":encoding" => {
+            writeln!(out, "{}", encoding::meta(&mut printer.encoding, &parts[1..])?)?;
+        }
+        
+        ":output" => {
+            writeln!(out, "{}", output::meta(&mut printer.format, &parts[1..])?)?;
         }
         
         _ => {
@@ -191,18 +664,46 @@ async fn handle_client_command(client: &mut Client, line: &str) -> Result<()> {
     Ok(())
 }
 
-fn run_local(data_dir: PathBuf) -> Result<()> {
-    println!("Opening local database at {:?}", data_dir);
+/// What the local REPL completes; `get` and `delete` complete keys too.
+const LOCAL_COMMANDS: &[&str] = &[
+    "get", "put", "delete", "del", "scan", "keys", "stats", ":encoding", ":output", "quit",
+    "exit",
+];
+const LOCAL_FLAGS: &[(&str, &[&str])] = &[
+    ("scan", &["--limit", "--reverse"]),
+    ("keys", &["--limit"]),
+    ("stats", &["--json"]),
+];
+
+fn run_local(
+    config: Config,
+    mut printer: Printer,
+    history: &repl::HistoryOptions,
+    batch: &BatchArgs,
+) -> Result<()> {
+    if let Some(commands) = batch.commands()? {
+        let db = Database::open(config).context("Failed to open database")?;
+        let mut out = io::stdout().lock();
+        let mut err = io::stderr();
+        let ran = run_local_batch(&db, &commands, &mut printer, batch, &mut out, &mut err);
+        db.close().context("Failed to close database")?;
+        return ran;
+    }
+    
+    println!("Opening local database at {:?}", config.data_dir);
     
-    let config = Config::new(data_dir);
     let db = Database::open(config).context("Failed to open database")?;
     
     println!("Database opened\n");
     
-    let mut rl = DefaultEditor::new()?;
+    let helper = repl::ReplHelper::new(LOCAL_COMMANDS)
+        .with_flags(LOCAL_FLAGS)
+        .with_keys(&["get", "delete", "del"], &db);
+    let mut rl = repl::editor(helper, history)?;
+    rl.helper_mut().unwrap().encoding = printer.encoding;
     
     println!("MidDB Local REPL");
-    println!("Commands: get <key>, put <key> <value>, delete <key>, stats, quit");
+    println!("Commands: get <key>, put <key> <value>, delete <key>, scan [<start> [<end>]] [--limit N] [--reverse], keys <prefix>, stats [--json], :encoding [utf8|hex|base64], :output [table|json|csv], quit");
     println!();
     
     loop {
@@ -222,9 +723,10 @@ fn run_local(data_dir: PathBuf) -> Result<()> {
                     break;
                 }
                 
-                if let Err(e) = handle_local_command(&db, line) {
+                if let Err(e) = handle_local_command(&db, line, &mut printer, &mut io::stdout()) {
                     eprintln!("Error: {}", e);
                 }
+                rl.helper_mut().unwrap().encoding = printer.encoding;
             }
             Err(ReadlineError::Interrupted) => {
                 println!("Interrupted");
@@ -240,6 +742,9 @@ fn run_local(data_dir: PathBuf) -> Result<()> {
         }
     }
     
+    repl::save_history(&mut rl, history);
+    // It borrows the database to complete keys.
+    drop(rl);
     println!("Closing database");
     db.close().context("Failed to close database")?;
     
@@ -247,28 +752,49 @@ fn run_local(data_dir: PathBuf) -> Result<()> {
     Ok(())
 }
 
-fn handle_local_command(db: &Database, line: &str) -> Result<()> {
+/// Run `commands` as the local REPL would, with no prompt, stopping at
+/// `quit` or, unless told to go on, at the first that fails.
+fn run_local_batch(
+    db: &Database,
+    commands: &[String],
+    printer: &mut Printer,
+    batch: &BatchArgs,
+    out: &mut impl Write,
+    err: &mut impl Write,
+) -> Result<()> {
+    let mut run = batch::Batch::new(batch.continue_on_error);
+    for command in commands.iter().take_while(|c| !batch::is_quit(c)) {
+        let result = handle_local_command(db, command, printer, out);
+        if !run.record(result, err) {
+            break;
+        }
+    }
+    run.finish()
+}
+
+fn handle_local_command(
+    db: &Database,
+    line: &str,
+    printer: &mut Printer,
+    out: &mut impl Write,
+) -> Result<()> {
     let parts: Vec<&str> = line.split_whitespace().collect();
     
     if parts.is_empty() {
         return Ok(());
     }
     
+    let encoding = printer.encoding;
+    
     match parts[0] {
         "get" => {
             if parts.len() != 2 {
                 anyhow::bail!("Usage: get <key>");
             }
             
-            let key = parts[1].as_bytes().to_vec();
-            match db.get(&key)? {
-                Some(value) => {
-                    println!("{}", String::from_utf8_lossy(&value));
-                }
-                None => {
-                    println!("(nil)");
-                }
-            }
+            let key = encoding.decode(parts[1])?;
+            let value = db.get(&key)?;
+            printer.value(out, "value", value.map(|v| encoding.encode(&v)))?;
         }
         
         "put" => {
@@ -276,11 +802,11 @@ fn handle_local_command(db: &Database, line: &str) -> Result<()> {
                 anyhow::bail!("Usage: put <key> <value>");
             }
             
-            let key = parts[1].as_bytes().to_vec();
-            let value = parts[2..].join(" ");
+            let key = encoding.decode(parts[1])?;
+            let value = encoding.decode(&parts[2..].join(" "))?;
             
-            db.put(key, value.as_bytes().to_vec())?;
-            println!("OK");
+            db.put(key, value)?;
+            printer.status(out, "OK")?;
         }
         
         "delete" | "del" => {
@@ -288,17 +814,70 @@ fn handle_local_command(db: &Database, line: &str) -> Result<()> {
                 anyhow::bail!("Usage: delete <key>");
             }
             
-            let key = parts[1].as_bytes().to_vec();
+            let key = encoding.decode(parts[1])?;
             db.delete(key)?;
-            println!("OK");
+            printer.status(out, "OK")?;
+        }
+        
+        "scan" => {
+            let args = ScanArgs::parse(&parts[1..])?;
+            if args.bounds.len() > 2 {
+                anyhow::bail!("Usage: scan [<start> [<end>]] [--limit N] [--reverse]");
+            }
+            
+            let start = args.bounds.first().map(|s| encoding.decode(s)).transpose()?;
+            let end = args.bounds.get(1).map(|s| encoding.decode(s)).transpose()?;
+            let (start, end) = (start.unwrap_or_default(), end.unwrap_or_default());
+            // One more than is shown, to tell whether there are more.
+            let wanted = args.limit + 1;
+            let entries = match args.reverse {
+                false => scan_forward(db, &start, &end, wanted)?,
+                true => db.scan_range(&start, &end)?.into_iter().rev().take(wanted).collect(),
+            };
+            let shown = entries.len().min(args.limit);
+            let rows: Vec<Row> = entries[..shown]
+                .iter()
+                .map(|(key, value)| printer.entry(key, Some(value)))
+                .collect();
+            printer.rows(out, &output::entry_columns(), &rows)?;
+            write_scan_footer(printer, out, shown, entries.len() > shown)?;
+        }
+        
+        "keys" => {
+            let args = ScanArgs::parse(&parts[1..])?;
+            let [prefix] = args.bounds[..] else {
+                anyhow::bail!("Usage: keys <prefix> [--limit N]");
+            };
+            
+            let entries = db.scan_prefix(&encoding.decode(prefix)?)?;
+            let shown = entries.len().min(args.limit);
+            let rows: Vec<Row> = entries[..shown]
+                .iter()
+                .map(|(key, _)| {
+                    let key = Value::String(encoding.encode(key));
+                    Row::new_with_values(vec![("key".to_string(), key)])
+                })
+                .collect();
+            printer.rows(out, &["key".to_string()], &rows)?;
+            write_scan_footer(printer, out, shown, entries.len() > shown)?;
         }
         
         "stats" => {
             let stats = db.stats();
-            println!("MemTable size: {} bytes", stats.memtable_size);
-            println!("MemTable entries: {}", stats.memtable_entries);
-            println!("SSTables: {}", stats.num_sstables);
-            println!("Sequence: {}", stats.sequence_number);
+            let printer = match parts[1..] {
+                [] => *printer,
+                ["--json"] => Printer { format: output::Format::Json, ..*printer },
+                _ => anyhow::bail!("Usage: stats [--json]"),
+            };
+            printer.figures(out, &stats::render(&stats), &stats::to_json(&stats)?)?;
+        }
+        
+        ":encoding" => {
+            writeln!(out, "{}", encoding::meta(&mut printer.encoding, &parts[1..])?)?;
+        }
+        
+        ":output" => {
+            writeln!(out, "{}", output::meta(&mut printer.format, &parts[1..])?)?;
         }
         
         _ => {
@@ -309,7 +888,315 @@ fn handle_local_command(db: &Database, line: &str) -> Result<()> {
     Ok(())
 }
 
-fn run_query(_data_dir: PathBuf) -> Result<()> {
+/// Entries the local `scan` and `keys` print unless given `--limit`.
+const DEFAULT_SCAN_LIMIT: usize = 100;
+
+/// The arguments of a local `scan` or `keys`: its positional ones, and
+/// its options.
+struct ScanArgs<'a> {
+    bounds: Vec<&'a str>,
+    limit: usize,
+    reverse: bool,
+}
+
+impl<'a> ScanArgs<'a> {
+    fn parse(args: &[&'a str]) -> Result<Self> {
+        let mut parsed = ScanArgs {
+            bounds: Vec::new(),
+            limit: DEFAULT_SCAN_LIMIT,
+            reverse: false,
+        };
+        let mut args = args.iter();
+        while let Some(&arg) = args.next() {
+            match arg {
+                "--reverse" | "-r" => parsed.reverse = true,
+                "--limit" | "-n" => {
+                    let limit = args.next().context("--limit needs a number")?;
+                    parsed.limit = limit.parse().context("--limit needs a number")?;
+                    if parsed.limit == 0 {
+                        anyhow::bail!("--limit must be at least 1");
+                    }
+                }
+                _ => parsed.bounds.push(arg),
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+/// Up to `limit` entries from the start of `[start, end)`, read a page at
+/// a time so a short scan of a long range reads no more than it shows.
+fn scan_forward(
+    db: &Database,
+    start: &[u8],
+    end: &[u8],
+    limit: usize,
+) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+    let mut entries = Vec::new();
+    let mut start = start.to_vec();
+    while entries.len() < limit {
+        let (page, resume) = db.scan_range_page(&start, end, limit - entries.len())?;
+        entries.extend(page);
+        match resume {
+            Some(next) => start = next,
+            None => break,
+        }
+    }
+    Ok(entries)
+}
+
+fn write_scan_footer(
+    printer: &Printer,
+    out: &mut impl Write,
+    shown: usize,
+    truncated: bool,
+) -> Result<()> {
+    if truncated {
+        printer.footer(out, &format!("(truncated, {} shown)", shown))
+    } else {
+        printer.footer(out, &format!("({} {})", shown, plural(shown, "entry")))
+    }
+}
+
+fn run_import(data_dir: PathBuf, file: PathBuf, options: dump::ImportOptions) -> Result<()> {
+    let input = File::open(&file).with_context(|| format!("Failed to open {:?}", file))?;
+    let db = Database::open(Config::new(data_dir)).context("Failed to open database")?;
+    
+    let imported = dump::import(&db, BufReader::new(input), &options, &mut io::stderr());
+    db.close().context("Failed to close database")?;
+    let summary = imported?;
+    println!(
+        "Imported {} records ({} bytes) in {:.2}s, {:.0} records/s",
+        summary.records,
+        summary.bytes,
+        summary.elapsed.as_secs_f64(),
+        summary.rate()
+    );
+    if summary.skipped > 0 {
+        println!("Skipped {} malformed lines", summary.skipped);
+    }
+    Ok(())
+}
+
+fn run_export(
+    data_dir: PathBuf,
+    out: Option<PathBuf>,
+    format: dump::Format,
+    start: String,
+    end: String,
+) -> Result<()> {
+    if format == dump::Format::Csv {
+        anyhow::bail!("CSV can't hold every key and value; export as tsv or json");
+    }
+    if !data_dir.is_dir() {
+        anyhow::bail!("No database at {:?}", data_dir);
+    }
+    // Dropped rather than closed: closing flushes the memtable, and an
+    // export shouldn't change anything.
+    let db = Database::open(Config::new(data_dir)).context("Failed to open database")?;
+    
+    let (start, end) = (start.as_bytes(), end.as_bytes());
+    let summary = match &out {
+        Some(path) => {
+            let file = File::create(path).with_context(|| format!("Failed to create {:?}", path))?;
+            dump::export(&db, start, end, format, &mut BufWriter::new(file))?
+        }
+        None => dump::export(&db, start, end, format, &mut io::stdout().lock())?,
+    };
+    eprintln!(
+        "Exported {} records ({} bytes) in {:.2}s",
+        summary.records,
+        summary.bytes,
+        summary.elapsed.as_secs_f64()
+    );
+    Ok(())
+}
+
+fn run_stats(data_dir: PathBuf, format: output::Format) -> Result<()> {
+    if !data_dir.is_dir() {
+        anyhow::bail!("No database at {:?}", data_dir);
+    }
+    // Dropped rather than closed, as in `run_export`.
+    let db = Database::open(Config::new(data_dir)).context("Failed to open database")?;
+    let stats = db.stats();
+    let printer = Printer { format, ..Printer::default() };
+    printer.figures(&mut io::stdout(), &stats::render(&stats), &stats::to_json(&stats)?)
+}
+
+fn run_compact(config: Config, options: compact::CompactOptions, wait: bool) -> Result<()> {
+    if !config.data_dir.is_dir() {
+        anyhow::bail!("No database at {:?}", config.data_dir);
+    }
+    let mut waiting = false;
+    let db = loop {
+        match Database::open(config.clone()) {
+            Err(middb_core::Error::Locked(_)) if wait => {
+                if !waiting {
+                    eprintln!("Waiting for another process to close {:?}", config.data_dir);
+                    waiting = true;
+                }
+                thread::sleep(Duration::from_millis(500));
+            }
+            Err(middb_core::Error::Locked(msg)) => {
+                anyhow::bail!("{}; stop it first, or pass --wait", msg)
+            }
+            opened => break opened.context("Failed to open database")?,
+        }
+    };
+    
+    compact::compact(&db, &options, &mut io::stdout())?;
+    if options.dry_run {
+        // Closing would flush the memtable.
+        drop(db);
+    } else {
+        db.close().context("Failed to close database")?;
+    }
+    Ok(())
+}
+
+fn run_check(config: Config) -> Result<()> {
+    if !config.data_dir.is_dir() {
+        anyhow::bail!("No database at {:?}", config.data_dir);
+    }
+    let problems = check::check(&config, &mut io::stdout())?;
+    if problems > 0 {
+        anyhow::bail!("{:?} failed its check", config.data_dir);
+    }
+    Ok(())
+}
+
+fn run_repair(config: Config, yes: bool) -> Result<()> {
+    if !config.data_dir.is_dir() {
+        anyhow::bail!("No database at {:?}", config.data_dir);
+    }
+    if !yes {
+        print!(
+            "Repair {:?}, moving what can't be read into {:?}? [y/N] ",
+            config.data_dir,
+            config.data_dir.join(middb_core::integrity::LOST_DIR)
+        );
+        io::stdout().flush()?;
+        let mut answer = String::new();
+        io::stdin().read_line(&mut answer)?;
+        if !matches!(answer.trim(), "y" | "Y" | "yes") {
+            println!("Nothing done");
+            return Ok(());
+        }
+    }
+    match check::repair(&config, &mut io::stdout()) {
+        Err(e) if matches!(e.downcast_ref(), Some(middb_core::Error::Locked(_))) => {
+            anyhow::bail!("{}; stop it first", e)
+        }
+        repaired => repaired.map(drop),
+    }
+}
+
+fn run_sst_dump(file: PathBuf, options: sst_dump::DumpOptions) -> Result<()> {
+    let problems = sst_dump::dump(&file, &options, &mut io::stdout())?;
+    if problems > 0 {
+        anyhow::bail!("{:?} failed verification", file);
+    }
+    Ok(())
+}
+
+fn run_bench(
+    mut config: Config,
+    options: bench::BenchOptions,
+    sync: bool,
+    csv: Option<PathBuf>,
+) -> Result<()> {
+    config.sync_writes = sync;
+    let db = Database::open(config).context("Failed to open database")?;
+    
+    let report = bench::run(&db, &options)?;
+    bench::print_report(&mut io::stdout(), &report)?;
+    if let Some(path) = csv {
+        bench::append_csv(&path, &options, sync, &report)?;
+    }
+    
+    db.close().context("Failed to close database")?;
+    Ok(())
+}
+
+fn run_sql(config: Config, format: output::Format, history: &repl::HistoryOptions) -> Result<()> {
+    println!("Opening database at {:?}", config.data_dir);
+    
+    let db = Arc::new(Database::open(config).context("Failed to open database")?);
+    let mut session = sql::Session::new(Arc::clone(&db))?;
+    session.printer.format = format;
+    
+    println!("MidDB SQL REPL");
+    println!("Statements end with ';'. \\dt lists tables, \\d <table> describes one, quit leaves.");
+    println!(":output [table|json|csv] sets how results are printed.");
+    println!();
+    
+    let commands = &["\\dt", "\\d", ":output", "quit", "exit"];
+    let mut rl = repl::editor(repl::ReplHelper::new(commands), history)?;
+    // Lines of a statement not yet ended by a ';'.
+    let mut pending = String::new();
+    
+    loop {
+        let prompt = if pending.is_empty() { "sql> " } else { "...> " };
+        match rl.readline(prompt) {
+            Ok(line) => {
+                let trimmed = line.trim();
+                if trimmed.is_empty() {
+                    continue;
+                }
+                
+                rl.add_history_entry(trimmed)?;
+                
+                if pending.is_empty() {
+                    if trimmed == "quit" || trimmed == "exit" {
+                        break;
+                    }
+                    if trimmed.starts_with(['\\', ':']) {
+                        if let Err(e) = session.meta(trimmed, &mut io::stdout()) {
+                            eprintln!("Error: {}", e);
+                        }
+                        continue;
+                    }
+                }
+                
+                pending.push_str(&line);
+                pending.push('\n');
+                let (statements, rest) = sql::split_statements(&pending);
+                pending = rest;
+                for statement in statements {
+                    if let Err(e) = session.execute(&statement, &mut io::stdout()) {
+                        eprintln!("Error: {}", e);
+                    }
+                }
+            }
+            Err(ReadlineError::Interrupted) => {
+                if pending.is_empty() {
+                    println!("Interrupted");
+                    break;
+                }
+                pending.clear();
+            }
+            Err(ReadlineError::Eof) => {
+                break;
+            }
+            Err(err) => {
+                eprintln!("Error: {}", err);
+                break;
+            }
+        }
+    }
+    
+    repl::save_history(&mut rl, history);
+    drop(session);
+    if let Ok(db) = Arc::try_unwrap(db) {
+        println!("Closing database");
+        db.close().context("Failed to close database")?;
+    }
+    
+    println!("Goodbye");
+    Ok(())
+}
+
+fn run_query(_data_dir: PathBuf, history: &repl::HistoryOptions) -> Result<()> {
     println!("Query mode (in-memory tables for demonstration)\n");
     
     let mut executor = Executor::new();
@@ -335,10 +1222,11 @@ fn run_query(_data_dir: PathBuf) -> Result<()> {
     
     println!("Registered table 'users' with 3 rows\n");
     
-    let mut rl = DefaultEditor::new()?;
+    let helper = repl::ReplHelper::new(&["scan", "project", "filter", "quit", "exit"]);
+    let mut rl = repl::editor(helper, history)?;
     
     println!("Query REPL");
-    println!("Commands: scan <table>, filter <table> <column> <op> <value>, quit");
+    println!("Commands: scan <table>, project <table> <col,...>, filter <table> <column> <op> <value>, quit");
     println!("Example: filter users age > 25\n");
     
     let planner = Planner::new();
@@ -378,6 +1266,7 @@ fn run_query(_data_dir: PathBuf) -> Result<()> {
         }
     }
     
+    repl::save_history(&mut rl, history);
     println!("Goodbye");
     Ok(())
 }
@@ -409,6 +1298,26 @@ fn handle_query_command(executor: &Executor, planner: &Planner, line: &str) -> R
             }
         }
         
+        "project" => {
+            if parts.len() != 3 {
+                anyhow::bail!("Usage: project <table> <col,...>");
+            }
+            
+            let columns = parts[2].split(',').map(|c| c.trim().to_string()).collect();
+            let logical = planner.plan_select(parts[1].to_string(), Some(columns), None);
+            let physical = planner.to_physical(logical);
+            
+            match executor.execute(physical) {
+                Ok(rows) => {
+                    println!("{} rows", rows.len());
+                    for row in rows {
+                        println!("{:?}", row);
+                    }
+                }
+                Err(e) => anyhow::bail!("Query error: {}", e),
+            }
+        }
+        
         "filter" => {
             if parts.len() < 5 {
                 anyhow::bail!("Usage: filter <table> <column> <op> <value>");
@@ -462,3 +1371,217 @@ fn handle_query_command(executor: &Executor, planner: &Planner, line: &str) -> R
     
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+    
+    fn open_db(dir: &TempDir) -> Database {
+        let db = Database::open(Config::new(dir.path())).unwrap();
+        for (key, value) in [("a", "1"), ("b", "2"), ("c", "3"), ("d", "4")] {
+            db.put(key.as_bytes().to_vec(), value.as_bytes().to_vec()).unwrap();
+        }
+        db
+    }
+    
+    /// What `line` prints, a line at a time.
+    fn run(db: &Database, line: &str) -> Vec<String> {
+        run_with(db, line, &mut Printer::default())
+    }
+    
+    fn run_with(db: &Database, line: &str, printer: &mut Printer) -> Vec<String> {
+        let mut out = Vec::new();
+        handle_local_command(db, line, printer, &mut out).unwrap();
+        String::from_utf8(out).unwrap().lines().map(str::to_string).collect()
+    }
+    
+    /// The entries a table of keys and values shows, as `key => value`.
+    fn entries(lines: &[String]) -> Vec<String> {
+        assert_eq!(lines[0].split(" | ").map(str::trim).collect::<Vec<_>>(), ["key", "value"]);
+        lines[2..lines.len() - 1]
+            .iter()
+            .map(|line| {
+                let (key, value) = line.split_once(" | ").unwrap();
+                format!("{} => {}", key.trim_end(), value)
+            })
+            .chain(lines.last().cloned())
+            .collect()
+    }
+    
+    fn scan(db: &Database, line: &str) -> Vec<String> {
+        entries(&run(db, line))
+    }
+    
+    fn fails(db: &Database, line: &str) -> bool {
+        handle_local_command(db, line, &mut Printer::default(), &mut Vec::new()).is_err()
+    }
+    
+    #[test]
+    fn test_local_scan() {
+        let dir = TempDir::new().unwrap();
+        let db = open_db(&dir);
+        db.delete(b"c".to_vec()).unwrap();
+        
+        assert_eq!(scan(&db, "scan"), ["a => 1", "b => 2", "d => 4", "(3 entries)"]);
+        assert_eq!(scan(&db, "scan b"), ["b => 2", "d => 4", "(2 entries)"]);
+        assert_eq!(scan(&db, "scan a c"), ["a => 1", "b => 2", "(2 entries)"]);
+        assert_eq!(scan(&db, "scan --reverse"), ["d => 4", "b => 2", "a => 1", "(3 entries)"]);
+        assert_eq!(scan(&db, "scan a d --reverse"), ["b => 2", "a => 1", "(2 entries)"]);
+    }
+    
+    #[test]
+    fn test_local_scan_limited() {
+        let dir = TempDir::new().unwrap();
+        let db = open_db(&dir);
+        
+        assert_eq!(scan(&db, "scan --limit 2"), ["a => 1", "b => 2", "(truncated, 2 shown)"]);
+        assert_eq!(scan(&db, "scan -r --limit 1"), ["d => 4", "(truncated, 1 shown)"]);
+        // Exactly as many as the limit isn't truncated.
+        assert_eq!(scan(&db, "scan b d --limit 2"), ["b => 2", "c => 3", "(2 entries)"]);
+        
+        assert!(fails(&db, "scan --limit 0"));
+        assert!(fails(&db, "scan --limit"));
+        assert!(fails(&db, "scan a b c"));
+    }
+    
+    #[test]
+    fn test_local_scan_empty_range() {
+        let dir = TempDir::new().unwrap();
+        let db = open_db(&dir);
+        
+        assert_eq!(scan(&db, "scan x"), ["(0 entries)"]);
+        assert_eq!(scan(&db, "scan c c"), ["(0 entries)"]);
+        assert_eq!(run(&db, "keys z"), ["key", "---", "(0 entries)"]);
+    }
+    
+    #[test]
+    fn test_local_keys() {
+        let dir = TempDir::new().unwrap();
+        let db = open_db(&dir);
+        db.put(b"user:1".to_vec(), b"x".to_vec()).unwrap();
+        db.put(b"user:2".to_vec(), b"y".to_vec()).unwrap();
+        
+        let keys = run(&db, "keys user:");
+        assert_eq!(keys, ["key", "------", "user:1", "user:2", "(2 entries)"]);
+        assert_eq!(run(&db, "keys user: --limit 1")[2..], ["user:1", "(truncated, 1 shown)"]);
+    }
+    
+    #[test]
+    fn test_unprintable_bytes_shown_in_hex() {
+        let dir = TempDir::new().unwrap();
+        let db = open_db(&dir);
+        db.put(vec![b'e', 0xff], b"tab\there".to_vec()).unwrap();
+        
+        assert_eq!(scan(&db, "scan e"), ["0x65ff => 0x7461620968657265", "(1 entry)"]);
+        assert_eq!(encoding::display_bytes("héllo".as_bytes()), "héllo");
+    }
+    
+    #[test]
+    fn test_local_encodings() {
+        let dir = TempDir::new().unwrap();
+        let db = open_db(&dir);
+        let mut printer = Printer::new(output::Format::Table, Encoding::Hex);
+        
+        assert_eq!(run_with(&db, "put 0x00ff 610062", &mut printer), ["OK"]);
+        assert_eq!(db.get(&vec![0x00, 0xff]).unwrap(), Some(b"a\0b".to_vec()));
+        assert_eq!(run_with(&db, "get 00FF", &mut printer), ["610062"]);
+        
+        assert_eq!(run_with(&db, ":encoding base64", &mut printer), ["Encoding set to base64"]);
+        assert_eq!(run_with(&db, "get AP8=", &mut printer), ["YQBi"]);
+        assert_eq!(run_with(&db, "keys AA==", &mut printer)[2..], ["AP8=", "(1 entry)"]);
+        let scanned = entries(&run_with(&db, "scan YQ== Yg==", &mut printer));
+        assert_eq!(scanned, ["YQ== => MQ==", "(1 entry)"]);
+        
+        let mut out = Vec::new();
+        let mut hex = Printer::new(output::Format::Table, Encoding::Hex);
+        let err = handle_local_command(&db, "put 0xabc 00", &mut hex, &mut out).unwrap_err();
+        assert!(err.to_string().contains("\"0xabc\" isn't hex"), "{}", err);
+        assert!(handle_local_command(&db, "get zz", &mut hex, &mut out).is_err());
+        assert!(handle_local_command(&db, ":encoding rot13", &mut hex, &mut out).is_err());
+        assert_eq!(hex.encoding, Encoding::Hex);
+        assert!(out.is_empty());
+    }
+    
+    /// What running `commands` wrote to standard output and error, and
+    /// how it ended.
+    fn run_batch(
+        db: &Database,
+        commands: &str,
+        continue_on_error: bool,
+    ) -> (String, String, Result<()>) {
+        let batch = BatchArgs { exec: Some(commands.to_string()), continue_on_error };
+        let commands = batch.commands().unwrap().unwrap();
+        let (mut out, mut err) = (Vec::new(), Vec::new());
+        let mut printer = Printer::default();
+        let result = run_local_batch(db, &commands, &mut printer, &batch, &mut out, &mut err);
+        (String::from_utf8(out).unwrap(), String::from_utf8(err).unwrap(), result)
+    }
+    
+    #[test]
+    fn test_local_exec() {
+        let dir = TempDir::new().unwrap();
+        let db = open_db(&dir);
+        
+        let (out, err, result) = run_batch(&db, "put e 5; get e; keys d --limit 10", false);
+        assert_eq!(out, "OK\n5\nkey\n---\nd\n(1 entry)\n");
+        assert_eq!(err, "");
+        assert!(result.is_ok());
+        
+        // A failure stops the run, and its status says so.
+        let (out, err, result) = run_batch(&db, "get a; get; put f 6; get f", false);
+        assert_eq!(out, "1\n");
+        assert_eq!(err, "Error: Usage: get <key>\n");
+        assert_eq!(result.unwrap_err().to_string(), "1 command failed");
+        assert_eq!(db.get(&b"f".to_vec()).unwrap(), None);
+        
+        let (out, err, result) = run_batch(&db, "get a; get; put f 6; bogus; get f", true);
+        assert_eq!(out, "1\nOK\n6\n");
+        assert_eq!(err, "Error: Usage: get <key>\nError: Unknown command: bogus\n");
+        assert_eq!(result.unwrap_err().to_string(), "2 commands failed");
+        
+        let (out, _, result) = run_batch(&db, "get a; quit; get b", false);
+        assert_eq!(out, "1\n");
+        assert!(result.is_ok());
+    }
+    
+    #[test]
+    fn test_local_output_formats() {
+        let dir = TempDir::new().unwrap();
+        let db = open_db(&dir);
+        db.put(b"q\"uote".to_vec(), b"1,2".to_vec()).unwrap();
+        let mut printer = Printer::default();
+        
+        assert_eq!(run_with(&db, ":output json", &mut printer), ["Output set to json"]);
+        let scanned = run_with(&db, "scan d --limit 1", &mut printer);
+        assert_eq!(scanned, [r#"{"key":"d","value":"4"}"#]);
+        assert_eq!(run_with(&db, "get a", &mut printer), [r#"{"value":"1"}"#]);
+        assert_eq!(run_with(&db, "get zz", &mut printer), [r#"{"value":null}"#]);
+        assert_eq!(run_with(&db, "put e 5", &mut printer), [r#"{"status":"OK"}"#]);
+        let stats = run_with(&db, "stats", &mut printer).join("\n");
+        let stats: serde_json::Value = serde_json::from_str(&stats).unwrap();
+        assert_eq!(stats["estimated_keys"], 6);
+        
+        run_with(&db, ":output csv", &mut printer);
+        let scanned = run_with(&db, "scan d", &mut printer);
+        assert_eq!(scanned, ["key,value", "d,4", "e,5", "\"q\"\"uote\",\"1,2\""]);
+        assert_eq!(run_with(&db, "keys z", &mut printer), ["key"]);
+        assert_eq!(run_with(&db, ":output", &mut printer), ["Output: csv"]);
+        assert!(fails(&db, ":output xml"));
+    }
+    
+    #[test]
+    fn test_local_stats() {
+        let dir = TempDir::new().unwrap();
+        let db = open_db(&dir);
+        db.flush().unwrap();
+        
+        let table = run(&db, "stats");
+        assert!(table[1].starts_with("L0  "));
+        assert!(table.iter().any(|line| line == "Estimated keys  4"));
+        let json = run(&db, "stats --json").join("\n");
+        let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(json["levels"][0]["files"], 1);
+        assert!(fails(&db, "stats --yaml"));
+    }
+}
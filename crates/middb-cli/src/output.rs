@@ -0,0 +1,372 @@
+//! How the REPLs and `middb stats` print results: as aligned tables for
+//! people, or as JSON or CSV for scripts.
+
+use crate::encoding::Encoding;
+use anyhow::Result;
+use middb_query::{rows_to_ndjson, Row, Value};
+use serde_json::{json, Value as Json};
+use std::fmt;
+use std::io::Write;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    /// Columns lined up under a header, with a count after.
+    #[default]
+    Table,
+    /// A JSON object to a line: one per row, or one for a single value.
+    Json,
+    /// A header line, then a line per row, quoted where a field needs it.
+    Csv,
+}
+
+impl Format {
+    fn parse(name: &str) -> Result<Self> {
+        match name {
+            "table" => Ok(Format::Table),
+            "json" => Ok(Format::Json),
+            "csv" => Ok(Format::Csv),
+            _ => anyhow::bail!("Unknown output format {:?}: use table, json or csv", name),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Format::Table => "table",
+            Format::Json => "json",
+            Format::Csv => "csv",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Run the REPLs' `:output [<format>]`, which switches `format` to the one
+/// named, or with no name says which is in use. Returns what to print.
+pub fn meta(format: &mut Format, args: &[&str]) -> Result<String> {
+    match args {
+        [] => Ok(format!("Output: {}", format)),
+        [name] => {
+            *format = Format::parse(name)?;
+            Ok(format!("Output set to {}", format))
+        }
+        _ => anyhow::bail!("Usage: :output [table|json|csv]"),
+    }
+}
+
+/// `noun`, or its plural unless `count` is one. Only regular plurals: a
+/// consonant and `y` become `ies`, so `entries` but `keys`; else add `s`.
+pub fn plural(count: usize, noun: &str) -> String {
+    if count == 1 {
+        return noun.to_string();
+    }
+    match noun.strip_suffix('y') {
+        Some(stem) if !stem.ends_with(['a', 'e', 'i', 'o', 'u']) => format!("{}ies", stem),
+        _ => format!("{}s", noun),
+    }
+}
+
+/// The columns of a key/value entry's row.
+pub fn entry_columns() -> Vec<String> {
+    vec!["key".to_string(), "value".to_string()]
+}
+
+/// What a REPL prints results in, and how it writes keys and values.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Printer {
+    pub format: Format,
+    pub encoding: Encoding,
+}
+
+impl Printer {
+    pub fn new(format: Format, encoding: Encoding) -> Self {
+        Printer { format, encoding }
+    }
+
+    /// A key/value entry as a row, its bytes written in the encoding, and
+    /// NULL for a value that isn't there.
+    pub fn entry(&self, key: &[u8], value: Option<&[u8]>) -> Row {
+        let key = Value::String(self.encoding.encode(key));
+        let value = value.map_or(Value::Null, |v| Value::String(self.encoding.encode(v)));
+        Row::new_with_values(entry_columns().into_iter().zip([key, value]).collect())
+    }
+
+    /// `rows` under `columns`, in order. A column a row lacks is NULL.
+    pub fn rows(&self, out: &mut impl Write, columns: &[String], rows: &[Row]) -> Result<()> {
+        match self.format {
+            Format::Table => {
+                let cells: Vec<Vec<String>> = rows
+                    .iter()
+                    .map(|row| columns.iter().map(|c| table_cell(&column_of(row, c))).collect())
+                    .collect();
+                write_aligned(out, columns, &cells)?;
+            }
+            Format::Json => rows_to_ndjson(&mut *out, columns, rows.iter().cloned())?,
+            Format::Csv => {
+                let header: Vec<String> = columns.iter().map(|c| csv_quote(c)).collect();
+                writeln!(out, "{}", header.join(","))?;
+                for row in rows {
+                    let fields: Vec<String> =
+                        columns.iter().map(|c| csv_field(&column_of(row, c))).collect();
+                    writeln!(out, "{}", fields.join(","))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// A line under a table, such as how many rows it had. The other
+    /// formats have only the rows.
+    pub fn footer(&self, out: &mut impl Write, text: &str) -> Result<()> {
+        if self.format == Format::Table {
+            writeln!(out, "{}", text)?;
+        }
+        Ok(())
+    }
+
+    /// A single value named `name`: in a table just the value, or `(nil)`
+    /// if there's none.
+    pub fn value(&self, out: &mut impl Write, name: &str, value: Option<String>) -> Result<()> {
+        match self.format {
+            Format::Table => writeln!(out, "{}", value.as_deref().unwrap_or("(nil)"))?,
+            Format::Json => writeln!(out, "{}", json!({ name: value }))?,
+            Format::Csv => {
+                let field = value.map_or(String::new(), |v| csv_field(&Value::String(v)));
+                writeln!(out, "{}\n{}", csv_quote(name), field)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// What a command that returns nothing else did, such as "OK".
+    pub fn status(&self, out: &mut impl Write, message: &str) -> Result<()> {
+        self.value(out, "status", Some(message.to_string()))
+    }
+
+    /// Figures such as stats: `table` as it is, `figures` as one JSON
+    /// object, or for CSV each of its leaves as a `name,value` line, named
+    /// by its path, such as `levels.0.files`.
+    pub fn figures(&self, out: &mut impl Write, table: &str, figures: &Json) -> Result<()> {
+        match self.format {
+            Format::Table => write!(out, "{}", table)?,
+            Format::Json => writeln!(out, "{}", serde_json::to_string_pretty(figures)?)?,
+            Format::Csv => {
+                let mut leaves = Vec::new();
+                flatten("", figures, &mut leaves);
+                writeln!(out, "name,value")?;
+                for (name, value) in &leaves {
+                    writeln!(out, "{},{}", csv_quote(name), csv_quote(value))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn column_of(row: &Row, column: &str) -> Value {
+    row.get_column(column).unwrap_or(Value::Null)
+}
+
+/// A value in a table, on one line: line breaks are written as `\n` and
+/// `\r`.
+fn table_cell(value: &Value) -> String {
+    let text = value.to_string();
+    if text.contains(['\n', '\r']) {
+        text.replace('\n', "\\n").replace('\r', "\\r")
+    } else {
+        text
+    }
+}
+
+/// A value as a CSV field: NULL as an empty one.
+fn csv_field(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        value => csv_quote(&value.to_string()),
+    }
+}
+
+/// `text` in double quotes, with its own doubled, if it has a comma, a
+/// quote or a line break; as it is otherwise.
+fn csv_quote(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+/// The leaves of `json` with their paths from `prefix`, objects' keys
+/// and arrays' indexes joined by dots.
+fn flatten(prefix: &str, json: &Json, leaves: &mut Vec<(String, String)>) {
+    let path = |name: &str| match prefix {
+        "" => name.to_string(),
+        prefix => format!("{}.{}", prefix, name),
+    };
+    match json {
+        Json::Object(object) => {
+            for (name, value) in object {
+                flatten(&path(name), value, leaves);
+            }
+        }
+        Json::Array(values) => {
+            for (i, value) in values.iter().enumerate() {
+                flatten(&path(&i.to_string()), value, leaves);
+            }
+        }
+        Json::String(text) => leaves.push((prefix.to_string(), text.clone())),
+        Json::Null => leaves.push((prefix.to_string(), String::new())),
+        other => leaves.push((prefix.to_string(), other.to_string())),
+    }
+}
+
+/// A header, a rule under it, and the rows, each column as wide as its
+/// widest cell.
+pub fn write_aligned(out: &mut impl Write, header: &[String], rows: &[Vec<String>]) -> Result<()> {
+    let mut widths: Vec<usize> = header.iter().map(|h| h.chars().count()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let line = |cells: &[String]| {
+        let padded: Vec<String> = cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, &width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        padded.join(" | ").trim_end().to_string()
+    };
+    writeln!(out, "{}", line(header))?;
+    let rule: Vec<String> = widths.iter().map(|&w| "-".repeat(w)).collect();
+    writeln!(out, "{}", rule.join("-+-"))?;
+    for row in rows {
+        writeln!(out, "{}", line(row))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Rows with a NULL, a comma, quotes, a line break, and bytes that
+    /// aren't UTF-8, as `encoding` writes them.
+    fn awkward_rows(encoding: Encoding) -> (Vec<String>, Vec<Row>) {
+        let columns = ["id", "name", "note"].map(String::from).to_vec();
+        let row = |id, name: &str, note| {
+            let values = [Value::Int(id), Value::String(name.to_string()), note];
+            Row::new_with_values(columns.iter().cloned().zip(values).collect())
+        };
+        let rows = vec![
+            row(1, "plain", Value::Null),
+            row(2, "a, b", Value::String("say \"hi\"".to_string())),
+            row(3, "two\nlines", Value::String(encoding.encode(&[0x66, 0xff]))),
+        ];
+        (columns, rows)
+    }
+
+    fn print(format: Format, encoding: Encoding) -> String {
+        let (columns, rows) = awkward_rows(encoding);
+        let mut out = Vec::new();
+        let printer = Printer::new(format, encoding);
+        printer.rows(&mut out, &columns, &rows).unwrap();
+        printer.footer(&mut out, "(3 rows)").unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_plural() {
+        assert_eq!(plural(1, "entry"), "entry");
+        assert_eq!(plural(0, "entry"), "entries");
+        assert_eq!(plural(2, "key"), "keys");
+        assert_eq!(plural(3, "row"), "rows");
+    }
+
+    #[test]
+    fn test_table() {
+        let expected = "\
+id | name       | note
+---+------------+---------
+1  | plain      | NULL
+2  | a, b       | say \"hi\"
+3  | two\\nlines | 0x66ff
+(3 rows)
+";
+        assert_eq!(print(Format::Table, Encoding::Utf8), expected);
+    }
+
+    #[test]
+    fn test_json() {
+        let expected = r#"{"id":1,"name":"plain","note":null}
+{"id":2,"name":"a, b","note":"say \"hi\""}
+{"id":3,"name":"two\nlines","note":"0x66ff"}
+"#;
+        assert_eq!(print(Format::Json, Encoding::Utf8), expected);
+        assert!(print(Format::Json, Encoding::Base64).ends_with(r#""note":"Zv8="}
+"#));
+    }
+
+    #[test]
+    fn test_csv() {
+        let expected = "\
+id,name,note
+1,plain,
+2,\"a, b\",\"say \"\"hi\"\"\"
+3,\"two
+lines\",0x66ff
+";
+        assert_eq!(print(Format::Csv, Encoding::Utf8), expected);
+        assert!(print(Format::Csv, Encoding::Hex).ends_with("lines\",66ff\n"));
+    }
+
+    #[test]
+    fn test_entries_and_values() {
+        let printer = Printer::new(Format::Json, Encoding::Hex);
+        let rows = [printer.entry(b"k", Some(b"\xff")), printer.entry(b"m", None)];
+        let mut out = Vec::new();
+        printer.rows(&mut out, &entry_columns(), &rows).unwrap();
+        printer.value(&mut out, "value", None).unwrap();
+        printer.status(&mut out, "OK").unwrap();
+        let expected = "{\"key\":\"6b\",\"value\":\"ff\"}\n{\"key\":\"6d\",\"value\":null}\n\
+                        {\"value\":null}\n{\"status\":\"OK\"}\n";
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+
+        let mut out = Vec::new();
+        let csv = Printer::new(Format::Csv, Encoding::Utf8);
+        csv.value(&mut out, "value", Some("1,2".to_string())).unwrap();
+        csv.status(&mut out, "OK").unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "value\n\"1,2\"\nstatus\nOK\n");
+
+        let mut out = Vec::new();
+        let table = Printer::default();
+        table.value(&mut out, "value", None).unwrap();
+        table.status(&mut out, "OK").unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "(nil)\nOK\n");
+    }
+
+    #[test]
+    fn test_figures() {
+        let figures = json!({ "levels": [{ "files": 2 }], "name": "a,b", "idle": null });
+        let print = |format| {
+            let mut out = Vec::new();
+            Printer::new(format, Encoding::Utf8).figures(&mut out, "as text\n", &figures).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(print(Format::Table), "as text\n");
+        assert_eq!(print(Format::Csv), "name,value\nidle,\nlevels.0.files,2\nname,\"a,b\"\n");
+        let parsed: Json = serde_json::from_str(&print(Format::Json)).unwrap();
+        assert_eq!(parsed, figures);
+    }
+
+    #[test]
+    fn test_meta() {
+        let mut format = Format::Table;
+        assert_eq!(meta(&mut format, &[]).unwrap(), "Output: table");
+        assert_eq!(meta(&mut format, &["csv"]).unwrap(), "Output set to csv");
+        assert_eq!(format, Format::Csv);
+        assert!(meta(&mut format, &["yaml"]).is_err());
+        assert!(meta(&mut format, &["json", "csv"]).is_err());
+        assert_eq!(format, Format::Csv);
+    }
+}
@@ -0,0 +1,297 @@
+//! Line editing shared by the REPLs: history kept between sessions, and tab
+//! completion of commands, flags and keys.
+
+use crate::encoding::Encoding;
+use anyhow::Result;
+use middb_core::Database;
+use rustyline::completion::{Completer, Pair};
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::FileHistory;
+use rustyline::validate::Validator;
+use rustyline::{CompletionType, Config, Context, Editor, Helper};
+use std::path::{Path, PathBuf};
+
+/// Keys offered for one completion at most, so a prefix matching millions
+/// of keys still completes at once.
+pub const MAX_KEY_CANDIDATES: usize = 50;
+
+/// Where history goes, and how much of it is kept.
+#[derive(Debug, Clone)]
+pub struct HistoryOptions {
+    /// The file, or `~/.middb_history` if not given.
+    pub file: Option<PathBuf>,
+    /// Lines kept; 0 keeps no history at all.
+    pub size: usize,
+}
+
+impl HistoryOptions {
+    /// The file history is read from and written to, if there's one to use.
+    pub fn path(&self) -> Option<PathBuf> {
+        if self.size == 0 {
+            return None;
+        }
+        match &self.file {
+            Some(file) => Some(file.clone()),
+            None => std::env::var_os("HOME").map(|home| Path::new(&home).join(".middb_history")),
+        }
+    }
+}
+
+/// Where completion finds the keys that start with what's been typed.
+pub trait KeySource {
+    /// Up to `limit` keys starting with `prefix`, in key order.
+    fn keys_with_prefix(&self, prefix: &[u8], limit: usize) -> Vec<Vec<u8>>;
+}
+
+impl KeySource for Database {
+    fn keys_with_prefix(&self, prefix: &[u8], limit: usize) -> Vec<Vec<u8>> {
+        // One page from the prefix on, however many keys share it: a page
+        // holding other keys past the prefix, or deleted ones, just offers
+        // fewer.
+        match self.scan_range_page(prefix, &[], limit) {
+            Ok((page, _)) => page
+                .into_iter()
+                .map(|(key, _)| key)
+                .take_while(|key| key.starts_with(prefix))
+                .collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+}
+
+/// What a REPL completes: its commands, each one's flags, and, given a
+/// key source, the first argument of the commands that take a key.
+pub struct ReplHelper<'a> {
+    commands: &'static [&'static str],
+    flags: &'static [(&'static str, &'static [&'static str])],
+    key_commands: &'static [&'static str],
+    keys: Option<&'a dyn KeySource>,
+    /// How typed keys are read and offered ones written; the REPL keeps it
+    /// in step with `:encoding`.
+    pub encoding: Encoding,
+}
+
+impl<'a> ReplHelper<'a> {
+    pub fn new(commands: &'static [&'static str]) -> Self {
+        ReplHelper { commands, flags: &[], key_commands: &[], keys: None, encoding: Encoding::Utf8 }
+    }
+
+    /// Complete these flags after these commands.
+    pub fn with_flags(mut self, flags: &'static [(&'static str, &'static [&'static str])]) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Complete the first argument of `commands` with keys from `keys`.
+    pub fn with_keys(mut self, commands: &'static [&'static str], keys: &'a dyn KeySource) -> Self {
+        self.key_commands = commands;
+        self.keys = Some(keys);
+        self
+    }
+
+    /// Where the word under the cursor at `pos` starts, and what it could
+    /// be completed to.
+    pub fn candidates(&self, line: &str, pos: usize) -> (usize, Vec<String>) {
+        let start = line[..pos].rfind(char::is_whitespace).map_or(0, |i| i + 1);
+        let word = &line[start..pos];
+        let before: Vec<&str> = line[..start].split_whitespace().collect();
+
+        let matching = |options: &[&str]| {
+            options.iter().filter(|o| o.starts_with(word)).map(|o| o.to_string()).collect()
+        };
+        let candidates = match before[..] {
+            [] => matching(self.commands),
+            [command, ..] if word.starts_with('-') => {
+                let flags = self.flags.iter().find(|(c, _)| *c == command);
+                flags.map_or_else(Vec::new, |(_, flags)| matching(flags))
+            }
+            [command] if self.key_commands.contains(&command) => self.key_candidates(word),
+            _ => Vec::new(),
+        };
+        (start, candidates)
+    }
+
+    fn key_candidates(&self, word: &str) -> Vec<String> {
+        let (Some(keys), Ok(prefix)) = (self.keys, self.encoding.decode(word)) else {
+            return Vec::new();
+        };
+        keys.keys_with_prefix(&prefix, MAX_KEY_CANDIDATES)
+            .iter()
+            .map(|key| self.encoding.encode(key))
+            // Keys with spaces in them can't be typed as one argument.
+            .filter(|key| !key.contains(char::is_whitespace))
+            .collect()
+    }
+}
+
+impl Completer for ReplHelper<'_> {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let (start, candidates) = self.candidates(line, pos);
+        let pairs = candidates
+            .into_iter()
+            .map(|c| Pair { display: c.clone(), replacement: c })
+            .collect();
+        Ok((start, pairs))
+    }
+}
+
+impl Hinter for ReplHelper<'_> {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper<'_> {}
+
+impl Validator for ReplHelper<'_> {}
+
+impl Helper for ReplHelper<'_> {}
+
+pub type ReplEditor<'a> = Editor<ReplHelper<'a>, FileHistory>;
+
+/// An editor completing with `helper`, holding the history `history` names
+/// if there's any yet.
+pub fn editor<'a>(helper: ReplHelper<'a>, history: &HistoryOptions) -> Result<ReplEditor<'a>> {
+    let config = Config::builder()
+        .max_history_size(history.size.max(1))?
+        .history_ignore_dups(true)?
+        .completion_type(CompletionType::List)
+        .build();
+    let mut rl = Editor::with_config(config)?;
+    rl.set_helper(Some(helper));
+    if let Some(path) = history.path() {
+        // There's none the first time.
+        if path.exists() {
+            if let Err(e) = rl.load_history(&path) {
+                eprintln!("Warning: couldn't read history from {:?}: {}", path, e);
+            }
+        }
+    }
+    Ok(rl)
+}
+
+/// Write `rl`'s history where `history` says, warning rather than failing
+/// if it can't be, as the session is already over.
+pub fn save_history(rl: &mut ReplEditor<'_>, history: &HistoryOptions) {
+    if let Some(path) = history.path() {
+        if let Err(e) = rl.save_history(&path) {
+            eprintln!("Warning: couldn't save history to {:?}: {}", path, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustyline::history::History;
+    use std::collections::BTreeSet;
+    use tempfile::TempDir;
+
+    struct Keys(BTreeSet<Vec<u8>>);
+
+    impl KeySource for Keys {
+        fn keys_with_prefix(&self, prefix: &[u8], limit: usize) -> Vec<Vec<u8>> {
+            self.0.iter().filter(|k| k.starts_with(prefix)).take(limit).cloned().collect()
+        }
+    }
+
+    fn keys() -> Keys {
+        let keys = ["user:1", "user:2", "order:1", "with space", "\0bin"];
+        Keys(keys.iter().map(|k| k.as_bytes().to_vec()).collect())
+    }
+
+    const COMMANDS: &[&str] = &["get", "delete", "del", "scan", "stats"];
+    const FLAGS: &[(&str, &[&str])] = &[("scan", &["--limit", "--reverse"])];
+
+    #[test]
+    fn test_complete_command_names() {
+        let helper = ReplHelper::new(COMMANDS);
+        assert_eq!(helper.candidates("de", 2), (0, vec!["delete".to_string(), "del".to_string()]));
+        assert_eq!(helper.candidates("s", 1).1, ["scan", "stats"]);
+        assert_eq!(helper.candidates("", 0).1.len(), COMMANDS.len());
+        assert!(helper.candidates("x", 1).1.is_empty());
+    }
+
+    #[test]
+    fn test_complete_keys() {
+        let keys = keys();
+        let helper = ReplHelper::new(COMMANDS).with_keys(&["get", "delete"], &keys);
+        let users = vec!["user:1".to_string(), "user:2".to_string()];
+        assert_eq!(helper.candidates("get user", 8), (4, users));
+        assert_eq!(helper.candidates("delete o", 8).1, ["order:1"]);
+        assert_eq!(helper.candidates("get w", 5).1, Vec::<String>::new());
+        // Only the first argument is a key, and only for the commands given.
+        assert!(helper.candidates("get user:1 u", 12).1.is_empty());
+        assert!(helper.candidates("scan u", 6).1.is_empty());
+        // The cursor needn't be at the end.
+        assert_eq!(helper.candidates("get us xyz", 6).1.len(), 2);
+    }
+
+    #[test]
+    fn test_complete_keys_encoded() {
+        let keys = keys();
+        let mut helper = ReplHelper::new(COMMANDS).with_keys(&["get"], &keys);
+        helper.encoding = Encoding::Hex;
+        assert_eq!(helper.candidates("get 0x00", 8).1, ["0062696e"]);
+        assert!(helper.candidates("get zz", 6).1.is_empty());
+    }
+
+    #[test]
+    fn test_complete_keys_capped() {
+        let many = Keys((0..1000).map(|i| format!("k{:04}", i).into_bytes()).collect());
+        let helper = ReplHelper::new(COMMANDS).with_keys(&["get"], &many);
+        assert_eq!(helper.candidates("get k", 5).1.len(), MAX_KEY_CANDIDATES);
+    }
+
+    #[test]
+    fn test_complete_flags() {
+        let helper = ReplHelper::new(COMMANDS).with_flags(FLAGS);
+        assert_eq!(helper.candidates("scan a --l", 10), (7, vec!["--limit".to_string()]));
+        assert_eq!(helper.candidates("scan -", 6).1.len(), 2);
+        assert!(helper.candidates("stats --", 8).1.is_empty());
+    }
+
+    #[test]
+    fn test_database_key_source() {
+        let dir = TempDir::new().unwrap();
+        let db = Database::open(middb_core::Config::new(dir.path())).unwrap();
+        for key in ["a", "b1", "b2", "b3", "c"] {
+            db.put(key.as_bytes().to_vec(), b"v".to_vec()).unwrap();
+        }
+        assert_eq!(db.keys_with_prefix(b"b", 10), [b"b1", b"b2", b"b3"]);
+        assert_eq!(db.keys_with_prefix(b"b", 2), [b"b1", b"b2"]);
+        assert!(db.keys_with_prefix(b"z", 10).is_empty());
+    }
+
+    #[test]
+    fn test_history_round_trip() {
+        let home = TempDir::new().unwrap();
+        // The only test that reads HOME.
+        std::env::set_var("HOME", home.path());
+        let history = HistoryOptions { file: None, size: 2 };
+        assert_eq!(history.path().unwrap(), home.path().join(".middb_history"));
+
+        let mut rl = editor(ReplHelper::new(COMMANDS), &history).unwrap();
+        for line in ["put a 1", "get a", "scan"] {
+            rl.add_history_entry(line).unwrap();
+        }
+        save_history(&mut rl, &history);
+
+        let rl = editor(ReplHelper::new(COMMANDS), &history).unwrap();
+        let lines: Vec<&String> = rl.history().iter().collect();
+        assert_eq!(lines, ["get a", "scan"]);
+        assert_eq!(rl.history().len(), 2);
+
+        let off = HistoryOptions { file: None, size: 0 };
+        assert!(off.path().is_none());
+        let elsewhere = home.path().join("elsewhere");
+        let custom = HistoryOptions { file: Some(elsewhere.clone()), size: 10 };
+        assert_eq!(custom.path(), Some(elsewhere));
+    }
+}
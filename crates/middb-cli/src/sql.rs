@@ -0,0 +1,298 @@
+//! SQL statements against a database's tables, for `middb sql`.
+
+use crate::output::{self, plural, write_aligned, Printer};
+use anyhow::{anyhow, Result};
+use middb_core::Database;
+use middb_query::sql::{self, Statement};
+use middb_query::{ExecutionResult, Executor, Planner, Row, Value};
+use std::io::Write;
+use std::sync::Arc;
+
+/// An executor and planner over a database's catalog and tables.
+pub struct Session {
+    db: Arc<Database>,
+    executor: Executor,
+    planner: Planner,
+    /// How query results and counts are printed.
+    pub printer: Printer,
+}
+
+impl Session {
+    pub fn new(db: Arc<Database>) -> Result<Self> {
+        let executor = Executor::with_database(Arc::clone(&db)).map_err(|e| anyhow!(e))?;
+        let planner = Planner::with_catalog(db.catalog());
+        Ok(Session { db, executor, planner, printer: Printer::default() })
+    }
+
+    /// Run one statement, writing a query's rows and how many rows anything
+    /// else touched, as the printer says.
+    pub fn execute(&mut self, statement: &str, out: &mut impl Write) -> Result<()> {
+        // What a count of rows is a count of.
+        let mut touched = "";
+        let plan = match sql::parse(statement)? {
+            Statement::Select { plan, limit } => match limit {
+                Some(count) => self.planner.plan_limit(plan, count),
+                None => plan,
+            },
+            Statement::CreateTable { schema, if_not_exists } => {
+                self.planner.plan_create_table(schema, if_not_exists)
+            }
+            Statement::DropTable { name, if_exists } => {
+                self.planner.plan_drop_table(name, if_exists)
+            }
+            Statement::Insert { table, columns, rows } => {
+                touched = "inserted";
+                let rows = self.insert_rows(&table, columns, rows)?;
+                self.planner.plan_insert(table, rows)
+            }
+            Statement::Delete { table, filter } => {
+                touched = "deleted";
+                self.planner.plan_delete(table, filter)
+            }
+        };
+
+        let plan = self.planner.to_physical(plan);
+        let columns = self.executor.columns(&plan);
+        match self.executor.run(plan).map_err(|e| anyhow!(e))? {
+            ExecutionResult::Rows(rows) => write_rows(&self.printer, out, columns, &rows)?,
+            ExecutionResult::Count(count) => {
+                let message = format!("{} {} {}", count, plural(count, "row"), touched);
+                self.printer.status(out, &message)?;
+            }
+            ExecutionResult::Done => self.printer.status(out, "OK")?,
+        }
+        Ok(())
+    }
+
+    /// INSERT's values as rows, named by the statement's columns or, if it
+    /// names none, by the table's in order.
+    fn insert_rows(
+        &self,
+        table: &str,
+        columns: Option<Vec<String>>,
+        rows: Vec<Vec<Value>>,
+    ) -> Result<Vec<Row>> {
+        let columns = match columns {
+            Some(columns) => columns,
+            None => {
+                let schema = self
+                    .db
+                    .get_schema(table)
+                    .ok_or_else(|| anyhow!("Table not found: {}", table))?;
+                schema.columns.into_iter().map(|c| c.name).collect()
+            }
+        };
+        rows.into_iter()
+            .map(|values| {
+                if values.len() != columns.len() {
+                    anyhow::bail!("{} values for {} columns", values.len(), columns.len());
+                }
+                Ok(Row::new_with_values(columns.iter().cloned().zip(values).collect()))
+            })
+            .collect()
+    }
+
+    /// Run a `\` command: `\dt` lists the tables, `\d` does too or, given
+    /// a table, describes it. `:output` sets how results are printed.
+    pub fn meta(&mut self, command: &str, out: &mut impl Write) -> Result<()> {
+        let parts: Vec<&str> = command.split_whitespace().collect();
+        match parts[..] {
+            ["\\dt"] | ["\\d"] => {
+                let mut tables = self.db.list_tables();
+                tables.sort();
+                let rows: Vec<Vec<String>> = tables.iter().map(|t| vec![t.clone()]).collect();
+                write_aligned(out, &["Table".to_string()], &rows)?;
+                writeln!(out, "({} {})", rows.len(), plural(rows.len(), "table"))?;
+            }
+            ["\\d", table] => {
+                let schema = self
+                    .db
+                    .get_schema(table)
+                    .ok_or_else(|| anyhow!("Table not found: {}", table))?;
+                let header = ["Column", "Type", "Nullable", "Default"].map(String::from);
+                let rows: Vec<Vec<String>> = schema
+                    .columns
+                    .iter()
+                    .map(|c| {
+                        let default = c.default.clone().map(|d| Value::from(d).to_string());
+                        vec![
+                            c.name.clone(),
+                            c.data_type.to_string(),
+                            if c.nullable { "yes" } else { "no" }.to_string(),
+                            default.unwrap_or_default(),
+                        ]
+                    })
+                    .collect();
+                write_aligned(out, &header, &rows)?;
+                if let Some(primary_key) = schema.primary_key() {
+                    writeln!(out, "Primary key: ({})", primary_key.join(", "))?;
+                }
+                for unique in schema.unique_constraints() {
+                    writeln!(out, "Unique: ({})", unique.join(", "))?;
+                }
+            }
+            [":output", ref args @ ..] => {
+                writeln!(out, "{}", output::meta(&mut self.printer.format, args)?)?;
+            }
+            _ => anyhow::bail!("Unknown command: {} (try \\d [table] or \\dt)", command),
+        }
+        Ok(())
+    }
+}
+
+/// The complete statements at the front of `buffer`, each ended by a `;`
+/// outside quotes, and what follows the last of them.
+pub fn split_statements(buffer: &str) -> (Vec<String>, String) {
+    let mut statements = Vec::new();
+    let mut start = 0;
+    let mut quote = None;
+    for (i, c) in buffer.char_indices() {
+        match (quote, c) {
+            (None, '\'' | '"') => quote = Some(c),
+            (Some(q), c) if c == q => quote = None,
+            (None, ';') => {
+                let statement = buffer[start..=i].trim();
+                if statement != ";" {
+                    statements.push(statement.to_string());
+                }
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    (statements, buffer[start..].trim_start().to_string())
+}
+
+/// Rows under `columns` if the order is known, else under their own
+/// columns sorted, then in a table how many there were.
+fn write_rows(
+    printer: &Printer,
+    out: &mut impl Write,
+    columns: Option<Vec<String>>,
+    rows: &[Row],
+) -> Result<()> {
+    let columns = columns.unwrap_or_else(|| match rows.first() {
+        Some(row) => row.column_names().into_iter().map(String::from).collect(),
+        None => Vec::new(),
+    });
+    printer.rows(out, &columns, rows)?;
+    printer.footer(out, &format!("({} {})", rows.len(), plural(rows.len(), "row")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use middb_core::Config;
+    use tempfile::TempDir;
+
+    /// What `statement` prints, a line at a time.
+    fn run(session: &mut Session, statement: &str) -> Vec<String> {
+        let mut out = Vec::new();
+        session.execute(statement, &mut out).unwrap();
+        String::from_utf8(out).unwrap().lines().map(str::to_string).collect()
+    }
+
+    fn open(dir: &TempDir) -> (Arc<Database>, Session) {
+        let db = Arc::new(Database::open(Config::new(dir.path())).unwrap());
+        let session = Session::new(Arc::clone(&db)).unwrap();
+        (db, session)
+    }
+
+    #[test]
+    fn test_statements_survive_restart() {
+        let dir = TempDir::new().unwrap();
+        let (db, mut session) = open(&dir);
+        let create = "CREATE TABLE pets (id INT PRIMARY KEY, name TEXT NOT NULL, age INT)";
+        assert_eq!(run(&mut session, create), ["OK"]);
+        let insert = "INSERT INTO pets VALUES (1, 'rex', 3), (2, 'tom', 7), (3, 'kit', 1)";
+        assert_eq!(run(&mut session, insert), ["3 rows inserted"]);
+        let insert = "INSERT INTO pets (name, id) VALUES ('bo', 4)";
+        assert_eq!(run(&mut session, insert), ["1 row inserted"]);
+
+        let select = "SELECT name, age FROM pets WHERE id > 1 ORDER BY age DESC LIMIT 2;";
+        let expected = [
+            "name | age",
+            "-----+----",
+            "tom  | 7",
+            "kit  | 1",
+            "(2 rows)",
+        ];
+        assert_eq!(run(&mut session, select), expected);
+        assert_eq!(run(&mut session, "DELETE FROM pets WHERE age IS NULL"), ["1 row deleted"]);
+
+        drop(session);
+        Arc::try_unwrap(db).ok().unwrap().close().unwrap();
+        let (_db, mut session) = open(&dir);
+        assert_eq!(run(&mut session, select), expected);
+        let all = run(&mut session, "SELECT * FROM pets");
+        assert_eq!(all[0], "id | name | age");
+        assert_eq!(all.last().unwrap(), "(3 rows)");
+    }
+
+    #[test]
+    fn test_output_formats() {
+        let dir = TempDir::new().unwrap();
+        let (_db, mut session) = open(&dir);
+        run(&mut session, "CREATE TABLE t (id INT PRIMARY KEY, note TEXT)");
+        run(&mut session, "INSERT INTO t VALUES (1, 'a, \"b\"'), (2, NULL)");
+
+        let mut out = Vec::new();
+        session.meta(":output json", &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "Output set to json\n");
+        let rows = run(&mut session, "SELECT id, note FROM t");
+        assert_eq!(rows, [r#"{"id":1,"note":"a, \"b\""}"#, r#"{"id":2,"note":null}"#]);
+        let count = run(&mut session, "DELETE FROM t WHERE id = 2");
+        assert_eq!(count, [r#"{"status":"1 row deleted"}"#]);
+
+        session.meta(":output csv", &mut Vec::new()).unwrap();
+        let rows = run(&mut session, "SELECT * FROM t");
+        assert_eq!(rows, ["id,note", "1,\"a, \"\"b\"\"\""]);
+    }
+
+    #[test]
+    fn test_statement_errors() {
+        let dir = TempDir::new().unwrap();
+        let (_db, mut session) = open(&dir);
+        run(&mut session, "CREATE TABLE t (id INT PRIMARY KEY)");
+        let mut out = Vec::new();
+        assert!(session.execute("SELEC * FROM t", &mut out).is_err());
+        assert!(session.execute("SELECT * FROM nowhere", &mut out).is_err());
+        let err = session.execute("INSERT INTO t VALUES (1, 2)", &mut out).unwrap_err();
+        assert_eq!(err.to_string(), "2 values for 1 columns");
+        run(&mut session, "INSERT INTO t VALUES (1)");
+        assert!(session.execute("INSERT INTO t VALUES (1)", &mut out).is_err());
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn test_meta_commands() {
+        let dir = TempDir::new().unwrap();
+        let (_db, mut session) = open(&dir);
+        run(&mut session, "CREATE TABLE b (id INT PRIMARY KEY, note TEXT)");
+        run(&mut session, "CREATE TABLE a (x INT NOT NULL)");
+
+        let mut meta = |command: &str| {
+            let mut out = Vec::new();
+            session.meta(command, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(meta("\\dt"), "Table\n-----\na\nb\n(2 tables)\n");
+        let described = meta("\\d b");
+        assert!(described.starts_with("Column | Type"));
+        assert!(described.contains("\nnote   | "));
+        assert!(described.ends_with("Primary key: (id)\n"));
+        assert!(session.meta("\\d nowhere", &mut Vec::new()).is_err());
+        assert!(session.meta("\\x", &mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_split_statements() {
+        let (statements, rest) = split_statements("SELECT 1;\nSELECT ';'; SELECT");
+        assert_eq!(statements, ["SELECT 1;", "SELECT ';';"]);
+        assert_eq!(rest, "SELECT");
+        let (statements, rest) = split_statements("INSERT INTO t VALUES ('a;\n");
+        assert!(statements.is_empty());
+        assert_eq!(rest, "INSERT INTO t VALUES ('a;\n");
+        assert_eq!(split_statements(";;").0, Vec::<String>::new());
+    }
+}
@@ -0,0 +1,274 @@
+//! What's inside one SSTable file, for `middb sst-dump`.
+
+use crate::encoding::display_bytes;
+use crate::stats::human_bytes;
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use middb_core::sstable::{SSTableProblem, SSTableReader, FOOTER_SIZE};
+use std::io::Write;
+use std::path::Path;
+
+/// Longest a value is shown in a scan before it's cut short.
+const VALUE_WIDTH: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Mode {
+    /// The footer's handles and version, and the bloom filter's figures.
+    Footer,
+    /// Each data block's separator key and where the block lies.
+    Index,
+    /// Entries in key order.
+    Scan,
+    /// Read every block and report anything inconsistent.
+    Verify,
+}
+
+pub struct DumpOptions {
+    pub mode: Mode,
+    /// The first key to scan; empty for the first in the file.
+    pub start: Vec<u8>,
+    /// The key to stop a scan before; empty for none.
+    pub end: Vec<u8>,
+    /// Entries a scan shows at most.
+    pub limit: usize,
+}
+
+/// Write what `options.mode` asks for about the file at `path`. Returns the
+/// problems verifying found, none for the other modes.
+pub fn dump(path: &Path, options: &DumpOptions, out: &mut impl Write) -> Result<usize> {
+    if options.mode == Mode::Verify {
+        return verify(path, out);
+    }
+    let reader = SSTableReader::open(path).with_context(|| format!("Failed to open {:?}", path))?;
+    match options.mode {
+        Mode::Footer => write_footer(&reader, out)?,
+        Mode::Index => write_index(&reader, out)?,
+        Mode::Scan => write_scan(&reader, options, out)?,
+        Mode::Verify => unreachable!(),
+    }
+    Ok(0)
+}
+
+fn write_footer(reader: &SSTableReader, out: &mut impl Write) -> Result<()> {
+    let footer = reader.footer();
+    let properties = reader.properties();
+    let handle = |offset: u64, size: u64| format!("offset {}, size {}", offset, size);
+    let mut figures = vec![
+        ("File size", format!("{} ({})", properties.file_size, human_bytes(properties.file_size))),
+        ("Version", footer.version.to_string()),
+        ("Index block", handle(footer.index_handle.offset, footer.index_handle.size)),
+        ("Bloom filter", handle(footer.bloom_handle.offset, footer.bloom_handle.size)),
+        ("Data blocks", reader.index_entries()?.len().to_string()),
+    ];
+    match properties.bloom {
+        Some(bloom) => {
+            figures.push(("Bloom bits", bloom.num_bits.to_string()));
+            figures.push(("Bloom hashes", bloom.num_hash_funcs.to_string()));
+            figures.push(("Bloom fill", format!("{:.1}%", bloom.fill_ratio * 100.0)));
+            let fp_rate = format!("{:.2}%", bloom.estimated_fp_rate * 100.0);
+            figures.push(("Bloom FP rate", fp_rate));
+        }
+        None => figures.push(("Bloom bits", "none".to_string())),
+    }
+    
+    let width = figures.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+    for (name, value) in figures {
+        writeln!(out, "{:<width$}  {}", name, value, width = width)?;
+    }
+    Ok(())
+}
+
+fn write_index(reader: &SSTableReader, out: &mut impl Write) -> Result<()> {
+    let entries = reader.index_entries()?;
+    let rows: Vec<[String; 3]> = entries
+        .iter()
+        .map(|(separator, handle)| {
+            [display_bytes(separator), handle.offset.to_string(), handle.size.to_string()]
+        })
+        .collect();
+    let header = ["Separator".to_string(), "Offset".to_string(), "Size".to_string()];
+    let mut widths = [0; 3];
+    for row in std::iter::once(&header).chain(&rows) {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    for [separator, offset, size] in std::iter::once(&header).chain(&rows) {
+        writeln!(
+            out,
+            "{:<w0$}  {:>w1$}  {:>w2$}",
+            separator,
+            offset,
+            size,
+            w0 = widths[0],
+            w1 = widths[1],
+            w2 = widths[2]
+        )?;
+    }
+    writeln!(out, "({} blocks)", entries.len())?;
+    Ok(())
+}
+
+fn write_scan(reader: &SSTableReader, options: &DumpOptions, out: &mut impl Write) -> Result<()> {
+    let mut iter = reader.iter()?;
+    iter.seek(&options.start)?;
+    let mut shown = 0;
+    while let (Some(key), Some(value)) = (iter.key(), iter.value()) {
+        if !options.end.is_empty() && key >= options.end.as_slice() {
+            break;
+        }
+        if shown == options.limit {
+            writeln!(out, "({} entries, limit reached)", shown)?;
+            return Ok(());
+        }
+        writeln!(out, "{} => {}", display_bytes(key), truncate(&display_bytes(value)))?;
+        shown += 1;
+        iter.next()?;
+    }
+    writeln!(out, "({} entries)", shown)?;
+    Ok(())
+}
+
+/// `text` cut to `VALUE_WIDTH` characters, marked if anything was cut.
+fn truncate(text: &str) -> String {
+    match text.char_indices().nth(VALUE_WIDTH) {
+        Some((end, _)) => format!("{}... ({} chars)", &text[..end], text.chars().count()),
+        None => text.to_string(),
+    }
+}
+
+/// Check the file and write each problem with its offset. A file that
+/// won't open at all is one problem, at its footer.
+fn verify(path: &Path, out: &mut impl Write) -> Result<usize> {
+    let problems = match SSTableReader::open(path) {
+        Ok(reader) => reader.verify()?,
+        Err(e) => {
+            let size = std::fs::metadata(path)
+                .with_context(|| format!("Failed to open {:?}", path))?
+                .len();
+            let offset = size.saturating_sub(FOOTER_SIZE as u64);
+            vec![SSTableProblem { offset, message: e.to_string() }]
+        }
+    };
+    for problem in &problems {
+        writeln!(out, "offset {}: {}", problem.offset, problem.message)?;
+    }
+    match problems.len() {
+        0 => writeln!(out, "OK")?,
+        1 => writeln!(out, "1 problem")?,
+        count => writeln!(out, "{} problems", count)?,
+    }
+    Ok(problems.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use middb_core::sstable::SSTableWriter;
+    use tempfile::TempDir;
+    
+    /// An SSTable of `key000` to `key199`, in several blocks, with one
+    /// long value and one binary one.
+    fn write_table(dir: &TempDir) -> std::path::PathBuf {
+        let path = dir.path().join("000001.sst");
+        let mut writer = SSTableWriter::create(&path, 512).unwrap();
+        for i in 0..200 {
+            let key = format!("key{:03}", i);
+            let value = match i {
+                7 => "x".repeat(100).into_bytes(),
+                8 => vec![0, 1, 0xff],
+                _ => format!("value{}", i).into_bytes(),
+            };
+            writer.add(key.as_bytes(), &value).unwrap();
+        }
+        writer.finish(1, 0).unwrap();
+        path
+    }
+    
+    fn run(path: &Path, mode: Mode, start: &str, end: &str, limit: usize) -> (usize, Vec<String>) {
+        let options = DumpOptions {
+            mode,
+            start: start.as_bytes().to_vec(),
+            end: end.as_bytes().to_vec(),
+            limit,
+        };
+        let mut out = Vec::new();
+        let problems = dump(path, &options, &mut out).unwrap();
+        let lines = String::from_utf8(out).unwrap().lines().map(str::to_string).collect();
+        (problems, lines)
+    }
+    
+    #[test]
+    fn test_footer_and_index() {
+        let dir = TempDir::new().unwrap();
+        let path = write_table(&dir);
+        let reader = SSTableReader::open(&path).unwrap();
+        let footer = reader.footer().clone();
+        let blocks = reader.index_entries().unwrap();
+        assert!(blocks.len() > 2);
+        
+        let (_, lines) = run(&path, Mode::Footer, "", "", 0);
+        assert_eq!(lines[1], "Version        1");
+        let index = footer.index_handle;
+        let expected = format!("Index block    offset {}, size {}", index.offset, index.size);
+        assert_eq!(lines[2], expected);
+        assert!(lines.contains(&format!("Data blocks    {}", blocks.len())));
+        let bloom = reader.properties().bloom.unwrap();
+        assert!(lines.contains(&format!("Bloom hashes   {}", bloom.num_hash_funcs)));
+        assert!(lines.iter().any(|line| line.starts_with("Bloom fill     ")));
+        
+        let (_, lines) = run(&path, Mode::Index, "", "", 0);
+        assert!(lines[0].starts_with("Separator"));
+        assert_eq!(lines.len(), blocks.len() + 2);
+        let first: Vec<&str> = lines[1].split_whitespace().collect();
+        assert_eq!(first, [display_bytes(&blocks[0].0), "0".into(), blocks[0].1.size.to_string()]);
+        assert_eq!(lines.last().unwrap(), &format!("({} blocks)", blocks.len()));
+    }
+    
+    #[test]
+    fn test_scan_bounds() {
+        let dir = TempDir::new().unwrap();
+        let path = write_table(&dir);
+        
+        let (_, lines) = run(&path, Mode::Scan, "key006", "key010", 100);
+        assert_eq!(lines[0], "key006 => value6");
+        assert_eq!(lines[1], format!("key007 => {}... (100 chars)", "x".repeat(VALUE_WIDTH)));
+        assert_eq!(lines[2], "key008 => 0x0001ff");
+        assert_eq!(lines[3], "key009 => value9");
+        assert_eq!(lines[4], "(4 entries)");
+        
+        let (_, lines) = run(&path, Mode::Scan, "key150", "", 3);
+        assert_eq!(lines[..3], ["key150 => value150", "key151 => value151", "key152 => value152"]);
+        assert_eq!(lines[3], "(3 entries, limit reached)");
+        let (_, lines) = run(&path, Mode::Scan, "key198", "", 3);
+        assert_eq!(lines.last().unwrap(), "(2 entries)");
+    }
+    
+    #[test]
+    fn test_verify_reports_corruption() {
+        let dir = TempDir::new().unwrap();
+        let path = write_table(&dir);
+        assert_eq!(run(&path, Mode::Verify, "", "", 0), (0, vec!["OK".to_string()]));
+        
+        // Garble the first entry of the second data block.
+        let second = SSTableReader::open(&path).unwrap().index_entries().unwrap()[1].1;
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[second.offset as usize..][..10].fill(0xff);
+        let corrupt = dir.path().join("corrupt.sst");
+        std::fs::write(&corrupt, &bytes).unwrap();
+        let (problems, lines) = run(&corrupt, Mode::Verify, "", "", 0);
+        assert_eq!(problems, 1);
+        let expected = format!("offset {}: data block entry can't be decoded", second.offset);
+        assert_eq!(lines, [expected, "1 problem".to_string()]);
+        
+        // A broken magic number keeps the file from opening at all.
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        std::fs::write(&corrupt, &bytes).unwrap();
+        let (problems, lines) = run(&corrupt, Mode::Verify, "", "", 0);
+        assert_eq!(problems, 1);
+        let footer = bytes.len() - FOOTER_SIZE;
+        assert!(lines[0].starts_with(&format!("offset {}: ", footer)));
+        assert!(lines[0].contains("magic"));
+    }
+}
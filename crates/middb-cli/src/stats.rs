@@ -0,0 +1,193 @@
+//! A database's figures as a table for people, or as JSON for scripts, for
+//! `middb stats` and the REPLs' `stats`.
+
+use anyhow::Result;
+use middb_core::DatabaseStats;
+use middb_network::ServerStats;
+
+/// `bytes` in the largest binary unit that keeps it at least 1, to one
+/// decimal place.
+pub fn human_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// The levels from L0 to the deepest one holding files, then the rest of
+/// the figures, one to a line.
+pub fn render(stats: &DatabaseStats) -> String {
+    let mut text = render_levels(stats);
+    
+    let bloom = match stats.bloom_probes {
+        0 => "no probes".to_string(),
+        probes => format!(
+            "{:.1}% ({} of {} probes ruled out)",
+            stats.bloom_effectiveness() * 100.0,
+            stats.bloom_negatives,
+            probes
+        ),
+    };
+    let memtable = format!(
+        "{} entries, {}",
+        stats.memtable_entries,
+        human_bytes(stats.memtable_size as u64)
+    );
+    let compaction = &stats.compaction;
+    let compactions = format!(
+        "{} ({} files, {} read, {} written)",
+        compaction.compactions,
+        compaction.input_files,
+        human_bytes(compaction.bytes_read),
+        human_bytes(compaction.bytes_written)
+    );
+    let figures = [
+        ("MemTable", memtable),
+        ("WAL", human_bytes(stats.wal_size)),
+        ("Estimated keys", stats.estimated_keys.to_string()),
+        ("Bloom hit rate", bloom),
+        ("Compactions", compactions),
+        ("Sequence", stats.sequence_number.to_string()),
+        ("Transactions", format!("{} active", stats.txn.active)),
+    ];
+    let width = figures.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+    text.push('\n');
+    for (name, value) in figures {
+        text.push_str(&format!("{:<width$}  {}\n", name, value, width = width));
+    }
+    text
+}
+
+/// A server's own counts, one to a line, then its database's figures as
+/// `render` writes them.
+pub fn render_server(stats: &ServerStats) -> String {
+    let mut text = format!("Uptime: {}s\n", stats.uptime.as_secs());
+    text.push_str(&format!("Connections: {}\n", stats.active_connections));
+    text.push_str(&format!("Requests in flight: {}\n", stats.requests_in_flight));
+    for (kind, count) in &stats.requests {
+        text.push_str(&format!("Requests ({}): {}\n", kind, count));
+    }
+    text.push_str(&format!("Bytes in: {}\n", stats.bytes_in));
+    text.push_str(&format!("Bytes out: {}\n", stats.bytes_out));
+    text.push('\n');
+    text.push_str(&render(&stats.db));
+    text
+}
+
+/// Files and sizes from L0 to the deepest level holding files, then their
+/// total.
+pub fn render_levels(stats: &DatabaseStats) -> String {
+    let deepest = stats.levels.iter().rposition(|l| l.files > 0).unwrap_or(0);
+    let mut levels = vec![["Level".to_string(), "Files".to_string(), "Size".to_string()]];
+    for level in stats.levels.iter().take(deepest + 1) {
+        let name = format!("L{}", level.level);
+        levels.push([name, level.files.to_string(), human_bytes(level.bytes)]);
+    }
+    let files: usize = stats.levels.iter().map(|l| l.files).sum();
+    levels.push(["Total".to_string(), files.to_string(), human_bytes(stats.sstable_bytes())]);
+    
+    let mut widths = [0; 3];
+    for row in &levels {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+    let mut text = String::new();
+    for [name, files, size] in &levels {
+        text.push_str(&format!(
+            "{:<w0$}  {:>w1$}  {:>w2$}\n",
+            name,
+            files,
+            size,
+            w0 = widths[0],
+            w1 = widths[1],
+            w2 = widths[2]
+        ));
+    }
+    text
+}
+
+/// The same figures as JSON, every one `DatabaseStats` has.
+pub fn to_json(stats: &DatabaseStats) -> Result<serde_json::Value> {
+    Ok(serde_json::to_value(stats)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use middb_core::{Config, Database};
+    use tempfile::TempDir;
+    
+    /// A database with two files in L1, from four flushes and two
+    /// compactions, and one entry in the memtable.
+    fn flushed_db(dir: &TempDir) -> Database {
+        let mut config = Config::new(dir.path());
+        config.level0_file_num_compaction_trigger = 2;
+        let db = Database::open(config).unwrap();
+        for key in ["a", "b", "c", "d"] {
+            db.put(key.as_bytes().to_vec(), b"value".to_vec()).unwrap();
+            db.flush().unwrap();
+        }
+        db.put(b"e".to_vec(), b"value".to_vec()).unwrap();
+        db
+    }
+    
+    #[test]
+    fn test_human_bytes() {
+        assert_eq!(human_bytes(0), "0 B");
+        assert_eq!(human_bytes(1023), "1023 B");
+        assert_eq!(human_bytes(1024), "1.0 KiB");
+        assert_eq!(human_bytes(1536), "1.5 KiB");
+        assert_eq!(human_bytes(5 * 1024 * 1024), "5.0 MiB");
+        assert_eq!(human_bytes(3 << 30), "3.0 GiB");
+        assert_eq!(human_bytes(2048 << 40), "2048.0 TiB");
+    }
+    
+    #[test]
+    fn test_render_levels() {
+        let dir = TempDir::new().unwrap();
+        let db = flushed_db(&dir);
+        let stats = db.stats();
+        assert_eq!((stats.levels[0].files, stats.levels[1].files), (0, 2));
+        
+        let text = render(&stats);
+        let lines: Vec<&str> = text.lines().collect();
+        let words = |line: &str| line.split_whitespace().take(2).collect::<Vec<_>>().join(" ");
+        assert_eq!(words(lines[0]), "Level Files");
+        assert_eq!(words(lines[1]), "L0 0");
+        assert_eq!(words(lines[2]), "L1 2");
+        assert_eq!(words(lines[3]), "Total 2");
+        assert_eq!(lines[4], "");
+        assert!(lines[2].ends_with(&human_bytes(stats.levels[1].bytes)));
+        // The columns line up.
+        assert!(lines[..4].iter().all(|line| line.len() == lines[0].len()));
+        assert!(text.contains("Compactions     2 (4 files,"));
+        assert!(text.contains("Estimated keys  5\n"));
+        assert!(text.contains("Bloom hit rate  no probes\n"));
+    }
+    
+    #[test]
+    fn test_json_matches_stats() {
+        let dir = TempDir::new().unwrap();
+        let db = flushed_db(&dir);
+        let stats = db.stats();
+        
+        let json = to_json(&stats).unwrap();
+        assert_eq!(json["levels"][1]["files"], 2);
+        assert_eq!(json["levels"][1]["bytes"], stats.levels[1].bytes);
+        assert_eq!(json["compaction"]["compactions"], 2);
+        assert_eq!(json["estimated_keys"], 5);
+        assert_eq!(json["wal_size"], stats.wal_size);
+        assert_eq!(json["memtable_entries"], 1);
+        let parsed: DatabaseStats = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed.levels, stats.levels);
+        assert_eq!(parsed.compaction, stats.compaction);
+    }
+}
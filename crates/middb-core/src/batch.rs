@@ -0,0 +1,74 @@
+use crate::{Key, Value};
+
+/// Writes to make together with `Database::write`: logged as one WAL
+/// record with one sync, and applied under one lock, so readers see all
+/// of them or none. Later writes to a key in the batch win.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WriteBatch {
+    ops: Vec<(Key, Option<Value>)>,
+    bytes: usize,
+}
+
+impl WriteBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn put(&mut self, key: Key, value: Value) -> &mut Self {
+        self.bytes += key.len() + value.len();
+        self.ops.push((key, Some(value)));
+        self
+    }
+
+    pub fn delete(&mut self, key: Key) -> &mut Self {
+        self.bytes += key.len();
+        self.ops.push((key, None));
+        self
+    }
+
+    pub fn clear(&mut self) {
+        self.ops.clear();
+        self.bytes = 0;
+    }
+
+    /// Writes in the batch, counting each write to the same key.
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    /// Bytes of keys and values in the batch.
+    pub fn size_bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// The writes in order, `None` values being deletes.
+    pub fn ops(&self) -> &[(Key, Option<Value>)] {
+        &self.ops
+    }
+
+    pub(crate) fn into_ops(self) -> Vec<(Key, Option<Value>)> {
+        self.ops
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_batch_counts() {
+        let mut batch = WriteBatch::new();
+        batch.put(b"a".to_vec(), b"1".to_vec()).delete(b"bb".to_vec());
+        assert_eq!(batch.len(), 2);
+        assert_eq!(batch.size_bytes(), 4);
+        assert_eq!(batch.ops()[1], (b"bb".to_vec(), None));
+
+        batch.clear();
+        assert!(batch.is_empty());
+        assert_eq!(batch.size_bytes(), 0);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::{Error, Result};
 use std::hash::{Hash, Hasher};
 
 #[derive(Clone)]
@@ -38,7 +39,10 @@ impl BloomFilter {
     }
     
     pub fn insert(&mut self, key: &[u8]) {
-        let h = hash(key);
+        self.insert_hash(hash(key));
+    }
+    
+    fn insert_hash(&mut self, h: u64) {
         let delta = (h >> 17) | (h << 15);
         
         for i in 0..self.num_hash_funcs {
@@ -69,6 +73,40 @@ impl BloomFilter {
         self.num_hash_funcs
     }
     
+    pub fn num_bits(&self) -> usize {
+        self.num_bits
+    }
+    
+    pub fn approx_memory(&self) -> usize {
+        std::mem::size_of::<Self>() + self.bits.capacity()
+    }
+    
+    /// Fraction of bits in the filter that are set.
+    pub fn fill_ratio(&self) -> f64 {
+        if self.num_bits == 0 {
+            return 0.0;
+        }
+        
+        let set_bits: u64 = self.bits.iter().map(|b| b.count_ones() as u64).sum();
+        set_bits as f64 / self.num_bits as f64
+    }
+    
+    /// Estimated false-positive rate: a probe for an absent key passes only if
+    /// all `k` probed bits happen to be set, i.e. `fill_ratio ^ k`.
+    pub fn estimated_fp_rate(&self) -> f64 {
+        self.fill_ratio().powi(self.num_hash_funcs as i32)
+    }
+    
+    pub fn stats(&self) -> BloomFilterStats {
+        BloomFilterStats {
+            num_bits: self.num_bits,
+            num_hash_funcs: self.num_hash_funcs,
+            fill_ratio: self.fill_ratio(),
+            estimated_fp_rate: self.estimated_fp_rate(),
+            approx_memory: self.approx_memory(),
+        }
+    }
+    
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut bytes = Vec::with_capacity(12 + self.bits.len());
         bytes.extend_from_slice(&self.num_hash_funcs.to_le_bytes());
@@ -96,6 +134,60 @@ impl BloomFilter {
         })
     }
     
+    /// A filter holding the keys of both `self` and `other`: the bitwise OR
+    /// of the two. Both must have the same `num_bits` and `num_hash_funcs`,
+    /// so that a key probes the same bits in either.
+    pub fn union(&self, other: &BloomFilter) -> Result<BloomFilter> {
+        if self.num_bits != other.num_bits || self.num_hash_funcs != other.num_hash_funcs {
+            return Err(Error::InvalidArgument(format!(
+                "cannot union bloom filters of {} bits/{} hashes and {} bits/{} hashes",
+                self.num_bits, self.num_hash_funcs, other.num_bits, other.num_hash_funcs
+            )));
+        }
+        
+        let bits = self.bits.iter().zip(&other.bits).map(|(a, b)| a | b).collect();
+        Ok(BloomFilter {
+            bits,
+            num_hash_funcs: self.num_hash_funcs,
+            num_bits: self.num_bits,
+        })
+    }
+    
+    /// Like `union`, but also accepts filters whose sizes differ when the
+    /// smaller `num_bits` divides the larger. The larger filter is folded
+    /// down first: a probe at `h % large` lands on `h % small` once reduced,
+    /// so no key is lost. Different hash counts, or sizes that don't divide,
+    /// are still rejected.
+    pub fn try_union_resize(&self, other: &BloomFilter) -> Result<BloomFilter> {
+        let (small, large) = if self.num_bits <= other.num_bits {
+            (self, other)
+        } else {
+            (other, self)
+        };
+        
+        if small.num_hash_funcs != large.num_hash_funcs
+            || small.num_bits == 0
+            || large.num_bits % small.num_bits != 0
+        {
+            return Err(Error::InvalidArgument(format!(
+                "cannot union bloom filters of {} bits/{} hashes and {} bits/{} hashes",
+                self.num_bits, self.num_hash_funcs, other.num_bits, other.num_hash_funcs
+            )));
+        }
+        
+        if small.num_bits == large.num_bits {
+            return small.union(large);
+        }
+        
+        let mut folded = small.clone();
+        for pos in 0..large.num_bits {
+            if large.get_bit(pos) {
+                folded.set_bit(pos % small.num_bits);
+            }
+        }
+        Ok(folded)
+    }
+    
     fn set_bit(&mut self, pos: usize) {
         let byte_index = pos / 8;
         let bit_index = pos % 8;
@@ -109,6 +201,15 @@ impl BloomFilter {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BloomFilterStats {
+    pub num_bits: usize,
+    pub num_hash_funcs: u32,
+    pub fill_ratio: f64,
+    pub estimated_fp_rate: f64,
+    pub approx_memory: usize,
+}
+
 fn hash(data: &[u8]) -> u64 {
     let mut hasher = FnvHasher::new();
     data.hash(&mut hasher);
@@ -140,28 +241,46 @@ impl Hasher for FnvHasher {
     }
 }
 
+/// Collects key hashes until the final key count is known. Only the 64-bit
+/// hash of each key is kept, so building costs 8 bytes per key regardless of
+/// key length.
 pub struct BloomFilterBuilder {
-    keys: Vec<Vec<u8>>,
+    hashes: Vec<u64>,
     bits_per_key: usize,
 }
 
 impl BloomFilterBuilder {
     pub fn new(bits_per_key: usize) -> Self {
         BloomFilterBuilder {
-            keys: Vec::new(),
+            hashes: Vec::new(),
+            bits_per_key,
+        }
+    }
+    
+    pub fn with_expected_keys(bits_per_key: usize, expected_keys: usize) -> Self {
+        BloomFilterBuilder {
+            hashes: Vec::with_capacity(expected_keys),
             bits_per_key,
         }
     }
     
     pub fn add_key(&mut self, key: &[u8]) {
-        self.keys.push(key.to_vec());
+        self.hashes.push(hash(key));
+    }
+    
+    pub fn num_keys(&self) -> usize {
+        self.hashes.len()
+    }
+    
+    pub fn approx_memory(&self) -> usize {
+        self.hashes.capacity() * std::mem::size_of::<u64>()
     }
     
     pub fn build(self) -> BloomFilter {
-        let mut filter = BloomFilter::new(self.keys.len(), self.bits_per_key);
+        let mut filter = BloomFilter::new(self.hashes.len(), self.bits_per_key);
         
-        for key in &self.keys {
-            filter.insert(key);
+        for &h in &self.hashes {
+            filter.insert_hash(h);
         }
         
         filter
@@ -243,6 +362,66 @@ mod tests {
         assert_eq!(restored.num_hash_funcs(), filter.num_hash_funcs());
     }
     
+    fn measured_fp_rate(filter: &BloomFilter, num_keys: usize, num_checks: usize) -> f64 {
+        let mut false_positives = 0;
+        for i in num_keys..num_keys + num_checks {
+            let key = format!("key{:08}", i);
+            if filter.may_contain(key.as_bytes()) {
+                false_positives += 1;
+            }
+        }
+        false_positives as f64 / num_checks as f64
+    }
+    
+    #[test]
+    fn test_bloom_filter_accessors() {
+        let filter = BloomFilter::new(100, 10);
+        assert_eq!(filter.num_bits(), 1000);
+        assert_eq!(filter.fill_ratio(), 0.0);
+        assert_eq!(filter.estimated_fp_rate(), 0.0);
+        assert!(filter.approx_memory() >= 125);
+    }
+    
+    #[test]
+    fn test_bloom_filter_estimated_fp_rate_tracks_measured() {
+        let num_keys = 5000;
+        
+        for bits_per_key in [4, 8, 12, 16] {
+            let mut filter = BloomFilter::new(num_keys, bits_per_key);
+            for i in 0..num_keys {
+                let key = format!("key{:08}", i);
+                filter.insert(key.as_bytes());
+            }
+            
+            let fill = filter.fill_ratio();
+            assert!(fill > 0.0 && fill < 1.0);
+            
+            let estimated = filter.estimated_fp_rate();
+            let measured = measured_fp_rate(&filter, num_keys, 50_000);
+            
+            assert!(
+                (estimated - measured).abs() <= estimated * 0.5 + 0.002,
+                "bits_per_key={}: estimated {} vs measured {}",
+                bits_per_key,
+                estimated,
+                measured
+            );
+        }
+    }
+    
+    #[test]
+    fn test_bloom_filter_overfull_estimate() {
+        let mut filter = BloomFilter::new(100, 10);
+        for i in 0..5000 {
+            let key = format!("key{:08}", i);
+            filter.insert(key.as_bytes());
+        }
+        
+        assert!(filter.fill_ratio() > 0.95);
+        assert!(filter.estimated_fp_rate() > 0.7);
+        assert!(measured_fp_rate(&filter, 5000, 1000) > 0.7);
+    }
+    
     #[test]
     fn test_bloom_filter_builder() {
         let mut builder = BloomFilterBuilder::new(10);
@@ -257,5 +436,124 @@ mod tests {
         assert!(filter.may_contain(b"key2"));
         assert!(filter.may_contain(b"key3"));
     }
+    
+    #[test]
+    fn test_bloom_filter_builder_matches_direct_insert() {
+        let mut builder = BloomFilterBuilder::new(10);
+        let mut direct = BloomFilter::new(500, 10);
+        
+        for i in 0..500 {
+            let key = format!("key{:06}", i);
+            builder.add_key(key.as_bytes());
+            direct.insert(key.as_bytes());
+        }
+        
+        let built = builder.build();
+        assert_eq!(built.as_bytes(), direct.as_bytes());
+        assert_eq!(built.num_hash_funcs(), direct.num_hash_funcs());
+    }
+    
+    #[test]
+    fn test_bloom_filter_builder_memory_bounded() {
+        let num_keys = 1_000_000;
+        
+        for mut builder in [
+            BloomFilterBuilder::new(8),
+            BloomFilterBuilder::with_expected_keys(8, num_keys),
+        ] {
+            for i in 0..num_keys {
+                let key = format!("some/longer/synthetic/key/prefix/{:010}", i);
+                builder.add_key(key.as_bytes());
+            }
+            
+            assert_eq!(builder.num_keys(), num_keys);
+            assert!(
+                builder.approx_memory() <= 8 * 1024 * 1024,
+                "builder used {} bytes",
+                builder.approx_memory()
+            );
+            
+            let filter = builder.build();
+            for i in (0..num_keys).step_by(997) {
+                let key = format!("some/longer/synthetic/key/prefix/{:010}", i);
+                assert!(filter.may_contain(key.as_bytes()));
+            }
+            
+            let mut false_positives = 0;
+            for i in num_keys..num_keys + 10_000 {
+                let key = format!("some/longer/synthetic/key/prefix/{:010}", i);
+                if filter.may_contain(key.as_bytes()) {
+                    false_positives += 1;
+                }
+            }
+            assert!(false_positives < 400, "false positives: {}", false_positives);
+        }
+    }
+    
+    fn filter_of(range: std::ops::Range<usize>, num_keys: usize) -> BloomFilter {
+        let mut filter = BloomFilter::new(num_keys, 10);
+        for i in range {
+            filter.insert(format!("key{:08}", i).as_bytes());
+        }
+        filter
+    }
+    
+    #[test]
+    fn test_bloom_filter_union() {
+        let a = filter_of(0..500, 1000);
+        let b = filter_of(500..1000, 1000);
+        
+        let union = a.union(&b).unwrap();
+        for i in 0..1000 {
+            assert!(union.may_contain(format!("key{:08}", i).as_bytes()));
+        }
+        assert!(union.fill_ratio() >= a.fill_ratio().max(b.fill_ratio()));
+        assert!(measured_fp_rate(&union, 1000, 10_000) < 0.02);
+        
+        let restored = BloomFilter::from_bytes_with_meta(&union.to_bytes()).unwrap();
+        assert_eq!(restored.as_bytes(), union.as_bytes());
+        assert_eq!(restored.num_bits(), union.num_bits());
+        assert_eq!(restored.num_hash_funcs(), union.num_hash_funcs());
+        for i in 0..1000 {
+            assert!(restored.may_contain(format!("key{:08}", i).as_bytes()));
+        }
+    }
+    
+    #[test]
+    fn test_bloom_filter_union_incompatible() {
+        let a = filter_of(0..100, 100);
+        
+        assert!(matches!(a.union(&filter_of(0..100, 200)), Err(Error::InvalidArgument(_))));
+        
+        let mut fewer_hashes = BloomFilter::new(100, 4);
+        fewer_hashes.num_bits = a.num_bits();
+        fewer_hashes.bits = vec![0u8; a.as_bytes().len()];
+        assert!(a.union(&fewer_hashes).is_err());
+        assert!(a.try_union_resize(&fewer_hashes).is_err());
+        
+        // 1000 bits doesn't divide 1500.
+        assert!(a.try_union_resize(&filter_of(0..100, 150)).is_err());
+    }
+    
+    #[test]
+    fn test_bloom_filter_try_union_resize() {
+        let small = filter_of(0..300, 300);
+        let large = filter_of(300..600, 600);
+        
+        for union in [small.try_union_resize(&large).unwrap(), large.try_union_resize(&small).unwrap()] {
+            assert_eq!(union.num_bits(), small.num_bits());
+            for i in 0..600 {
+                assert!(union.may_contain(format!("key{:08}", i).as_bytes()));
+            }
+            
+            let restored = BloomFilter::from_bytes_with_meta(&union.to_bytes()).unwrap();
+            assert_eq!(restored.as_bytes(), union.as_bytes());
+        }
+        
+        let same = filter_of(300..600, 300);
+        assert_eq!(
+            small.try_union_resize(&same).unwrap().as_bytes(),
+            small.union(&same).unwrap().as_bytes()
+        );
+    }
 }
-
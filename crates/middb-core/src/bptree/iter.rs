@@ -65,16 +65,17 @@ impl<const FANOUT: usize, K: Ord + Clone, V: Clone> Iterator for BPTreeIter<FANO
 pub struct RangeIter<const FANOUT: usize, K, V> {
     current_leaf: Option<NodePtr<FANOUT, K, V>>,
     current_idx: usize,
-    end: K,
+    /// Exclusive upper bound; `None` runs to the last key.
+    end: Option<K>,
 }
 
 impl<const FANOUT: usize, K: Ord + Clone, V: Clone> RangeIter<FANOUT, K, V> {
-    pub fn new(root: &NodePtr<FANOUT, K, V>, start: &K, end: &K) -> Self {
+    pub fn new(root: &NodePtr<FANOUT, K, V>, start: &K, end: Option<&K>) -> Self {
         let (current_leaf, start_idx) = Self::find_start_position(root, start);
         RangeIter {
             current_leaf,
             current_idx: start_idx,
-            end: end.clone(),
+            end: end.cloned(),
         }
     }
 
@@ -126,7 +127,7 @@ impl<const FANOUT: usize, K: Ord + Clone, V: Clone> Iterator for RangeIter<FANOU
 
             if self.current_idx < keys.len() {
                 let key = &keys[self.current_idx];
-                if key >= &self.end {
+                if self.end.as_ref().is_some_and(|end| key >= end) {
                     return None;
                 }
                 let result = (key.clone(), values[self.current_idx].clone());
@@ -121,7 +121,12 @@ impl<const FANOUT: usize, K: Ord + Clone, V: Clone> BPTree<FANOUT, K, V> {
     }
 
     pub fn range(&self, start: &K, end: &K) -> RangeIter<FANOUT, K, V> {
-        RangeIter::new(&self.root, start, end)
+        RangeIter::new(&self.root, start, Some(end))
+    }
+
+    /// Entries from `start` onwards.
+    pub fn range_from(&self, start: &K) -> RangeIter<FANOUT, K, V> {
+        RangeIter::new(&self.root, start, None)
     }
 }
 
@@ -199,6 +204,10 @@ mod tests {
 
         let items: Vec<_> = tree.range(&3, &7).collect();
         assert_eq!(items, vec![(3, 30), (4, 40), (5, 50), (6, 60)]);
+
+        let keys: Vec<_> = tree.range_from(&7).map(|(k, _)| k).collect();
+        assert_eq!(keys, vec![7, 8, 9]);
+        assert_eq!(tree.range_from(&10).count(), 0);
     }
 
     #[test]
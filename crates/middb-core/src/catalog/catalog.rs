@@ -1,4 +1,7 @@
-use super::schema::TableSchema;
+use super::index::IndexDef;
+use super::schema::{Column, TableSchema};
+use super::stats::TableStats;
+use std::borrow::Cow;
 use std::collections::HashMap;
 
 #[derive(Debug)]
@@ -6,6 +9,24 @@ pub enum CatalogError {
     TableNotFound(String),
     TableAlreadyExists(String),
     ColumnNotFound { table: String, column: String },
+    ColumnAlreadyExists { table: String, column: String },
+    IndexNotFound { table: String, index: String },
+    IndexAlreadyExists { table: String, index: String },
+    NamespaceNotFound(String),
+    NamespaceAlreadyExists(String),
+    /// Dropping a namespace that still holds tables without `cascade`.
+    NamespaceNotEmpty(String),
+    /// A table or namespace name that can't be used, such as one containing
+    /// the `.` qualifier separator.
+    InvalidName(String),
+    /// A primary key or unique constraint is malformed, or an ALTER would
+    /// break one.
+    InvalidConstraint(String),
+    /// A column default does not match the column's type or nullability.
+    InvalidDefault(String),
+    /// The database backing the catalog failed, or held an entry that could
+    /// not be decoded.
+    Storage(String),
 }
 
 impl std::fmt::Display for CatalogError {
@@ -16,6 +37,24 @@ impl std::fmt::Display for CatalogError {
             CatalogError::ColumnNotFound { table, column } => {
                 write!(f, "column '{}' not found in table '{}'", column, table)
             }
+            CatalogError::ColumnAlreadyExists { table, column } => {
+                write!(f, "column '{}' already exists in table '{}'", column, table)
+            }
+            CatalogError::IndexNotFound { table, index } => {
+                write!(f, "index '{}' not found on table '{}'", index, table)
+            }
+            CatalogError::IndexAlreadyExists { table, index } => {
+                write!(f, "index '{}' already exists on table '{}'", index, table)
+            }
+            CatalogError::NamespaceNotFound(name) => write!(f, "namespace not found: {}", name),
+            CatalogError::NamespaceAlreadyExists(name) => {
+                write!(f, "namespace already exists: {}", name)
+            }
+            CatalogError::NamespaceNotEmpty(name) => write!(f, "namespace is not empty: {}", name),
+            CatalogError::InvalidName(msg) => write!(f, "invalid name: {}", msg),
+            CatalogError::InvalidConstraint(msg) => write!(f, "invalid constraint: {}", msg),
+            CatalogError::InvalidDefault(msg) => write!(f, "invalid default: {}", msg),
+            CatalogError::Storage(msg) => write!(f, "catalog storage error: {}", msg),
         }
     }
 }
@@ -24,47 +63,492 @@ impl std::error::Error for CatalogError {}
 
 pub type CatalogResult<T> = Result<T, CatalogError>;
 
+/// Namespace that unqualified table names resolve against.
+pub const DEFAULT_NAMESPACE: &str = "public";
+
+/// Split `namespace.table` into its parts; unqualified names have no namespace.
+pub fn split_qualified(name: &str) -> (Option<&str>, &str) {
+    match name.split_once('.') {
+        Some((namespace, table)) => (Some(namespace), table),
+        None => (None, name),
+    }
+}
+
+/// Key prefix under which `Database` persists one entry per table schema,
+/// `__catalog/<table>` keyed by the table's `Catalog::stored_name`.
+pub const CATALOG_KEY_PREFIX: &[u8] = b"__catalog/";
+
+/// Key prefix for persisted index definitions, `__catalog_index/<table>/<index>`.
+pub const INDEX_KEY_PREFIX: &[u8] = b"__catalog_index/";
+
+/// Key prefix for persisted table statistics, `__catalog_stats/<table>`.
+pub const STATS_KEY_PREFIX: &[u8] = b"__catalog_stats/";
+
+/// Key prefix for persisted namespaces, `__catalog_ns/<namespace>`. The
+/// default namespace always exists, and isn't stored.
+pub const NAMESPACE_KEY_PREFIX: &[u8] = b"__catalog_ns/";
+
+pub fn namespace_key(namespace: &str) -> Vec<u8> {
+    let mut key = NAMESPACE_KEY_PREFIX.to_vec();
+    key.extend_from_slice(namespace.as_bytes());
+    key
+}
+
+pub fn catalog_key(table: &str) -> Vec<u8> {
+    let mut key = CATALOG_KEY_PREFIX.to_vec();
+    key.extend_from_slice(table.as_bytes());
+    key
+}
+
+pub fn stats_key(table: &str) -> Vec<u8> {
+    let mut key = STATS_KEY_PREFIX.to_vec();
+    key.extend_from_slice(table.as_bytes());
+    key
+}
+
+pub fn index_key(table: &str, index: &str) -> Vec<u8> {
+    let mut key = INDEX_KEY_PREFIX.to_vec();
+    key.extend_from_slice(table.as_bytes());
+    key.push(b'/');
+    key.extend_from_slice(index.as_bytes());
+    key
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AlterOp {
+    AddColumn(Column),
+    DropColumn(String),
+    RenameColumn { from: String, to: String },
+}
+
+/// How table and column names are matched.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IdentifierCasing {
+    /// Names match byte for byte.
+    #[default]
+    Sensitive,
+    /// Names are compared after lowercasing, so `Users` finds `users`.
+    /// Quoted identifiers bypass folding through the `*_exact` lookups.
+    LowercaseFold,
+}
+
+impl IdentifierCasing {
+    pub fn fold<'a>(&self, ident: &'a str) -> Cow<'a, str> {
+        match self {
+            IdentifierCasing::Sensitive => Cow::Borrowed(ident),
+            IdentifierCasing::LowercaseFold => Cow::Owned(ident.to_lowercase()),
+        }
+    }
+
+    pub fn matches(&self, a: &str, b: &str) -> bool {
+        a == b || self.fold(a) == self.fold(b)
+    }
+}
+
+/// Table metadata. Tables live in namespaces; every lookup that takes a table
+/// name also accepts `namespace.table`, and unqualified names resolve against
+/// `DEFAULT_NAMESPACE`.
 pub struct Catalog {
+    /// Folded namespace name to the name it was created with.
+    namespaces: HashMap<String, String>,
+    /// Keyed by `namespace.table`, both folded under the casing policy.
     tables: HashMap<String, TableSchema>,
+    indexes: HashMap<String, Vec<IndexDef>>,
+    stats: HashMap<String, TableStats>,
+    casing: IdentifierCasing,
 }
 
 impl Catalog {
     pub fn new() -> Self {
+        Self::with_identifier_casing(IdentifierCasing::Sensitive)
+    }
+
+    /// A catalog resolving identifiers under `casing`. The policy is fixed for
+    /// the catalog's lifetime so names registered earlier stay reachable.
+    pub fn with_identifier_casing(casing: IdentifierCasing) -> Self {
+        let mut namespaces = HashMap::new();
+        namespaces.insert(
+            casing.fold(DEFAULT_NAMESPACE).into_owned(),
+            DEFAULT_NAMESPACE.to_string(),
+        );
         Catalog {
+            namespaces,
             tables: HashMap::new(),
+            indexes: HashMap::new(),
+            stats: HashMap::new(),
+            casing,
+        }
+    }
+
+    pub fn identifier_casing(&self) -> IdentifierCasing {
+        self.casing
+    }
+
+    /// Map key for a possibly qualified table name under the casing policy.
+    fn key(&self, name: &str) -> String {
+        let (namespace, table) = split_qualified(name);
+        self.qualified_key(namespace.unwrap_or(DEFAULT_NAMESPACE), table)
+    }
+
+    fn qualified_key(&self, namespace: &str, table: &str) -> String {
+        format!("{}.{}", self.casing.fold(namespace), self.casing.fold(table))
+    }
+
+    pub fn create_namespace(&mut self, name: &str) -> CatalogResult<()> {
+        if name.is_empty() || name.contains('.') {
+            return Err(CatalogError::InvalidName(format!(
+                "namespace '{}' must be non-empty and contain no '.'",
+                name
+            )));
         }
+        let key = self.casing.fold(name).into_owned();
+        if self.namespaces.contains_key(&key) {
+            return Err(CatalogError::NamespaceAlreadyExists(name.to_string()));
+        }
+        self.namespaces.insert(key, name.to_string());
+        Ok(())
+    }
+
+    /// Drop a namespace. A namespace that still holds tables is refused
+    /// unless `cascade` is set, in which case its tables (and their indexes
+    /// and statistics) are dropped and returned. The default namespace can't
+    /// be dropped.
+    pub fn drop_namespace(&mut self, name: &str, cascade: bool) -> CatalogResult<Vec<TableSchema>> {
+        let tables = self.check_drop_namespace(name, cascade)?;
+        let mut dropped = Vec::with_capacity(tables.len());
+        for table in tables {
+            dropped.push(self.drop_table(&table)?);
+        }
+        self.namespaces.remove(self.casing.fold(name).as_ref());
+        Ok(dropped)
+    }
+
+    /// Whether `drop_namespace(name, cascade)` would succeed, leaving the
+    /// catalog as it is. Returns the stored names of the tables it would
+    /// drop.
+    pub fn check_drop_namespace(&self, name: &str, cascade: bool) -> CatalogResult<Vec<String>> {
+        let key = self.casing.fold(name);
+        if key == self.casing.fold(DEFAULT_NAMESPACE) {
+            return Err(CatalogError::InvalidName(format!(
+                "the default namespace '{}' cannot be dropped",
+                DEFAULT_NAMESPACE
+            )));
+        }
+        let namespace = self
+            .namespaces
+            .get(key.as_ref())
+            .ok_or_else(|| CatalogError::NamespaceNotFound(name.to_string()))?;
+
+        let prefix = format!("{}.", key);
+        let tables: Vec<String> = self
+            .tables
+            .iter()
+            .filter(|(k, _)| k.starts_with(&prefix))
+            .map(|(_, schema)| format!("{}.{}", namespace, schema.name))
+            .collect();
+        if !tables.is_empty() && !cascade {
+            return Err(CatalogError::NamespaceNotEmpty(name.to_string()));
+        }
+        Ok(tables)
+    }
+
+    /// A namespace's name as it was created, found under the casing policy.
+    pub fn namespace_name(&self, name: &str) -> Option<&str> {
+        self.namespaces.get(self.casing.fold(name).as_ref()).map(|s| s.as_str())
+    }
+
+    pub fn namespace_exists(&self, name: &str) -> bool {
+        self.namespaces.contains_key(self.casing.fold(name).as_ref())
     }
 
+    pub fn list_namespaces(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.namespaces.values().map(|s| s.as_str()).collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// Register a table in the default namespace.
     pub fn register_table(&mut self, schema: TableSchema) -> CatalogResult<()> {
-        if self.tables.contains_key(&schema.name) {
+        self.register_table_in(DEFAULT_NAMESPACE, schema)
+    }
+
+    pub fn register_table_in(&mut self, namespace: &str, schema: TableSchema) -> CatalogResult<()> {
+        if !self.namespace_exists(namespace) {
+            return Err(CatalogError::NamespaceNotFound(namespace.to_string()));
+        }
+        if schema.name.contains('.') {
+            return Err(CatalogError::InvalidName(format!(
+                "table '{}' must not contain '.'",
+                schema.name
+            )));
+        }
+        let key = self.qualified_key(namespace, &schema.name);
+        if self.tables.contains_key(&key) {
             return Err(CatalogError::TableAlreadyExists(schema.name.clone()));
         }
-        self.tables.insert(schema.name.clone(), schema);
+        for (i, col) in schema.columns.iter().enumerate() {
+            if schema.columns[..i].iter().any(|c| self.casing.matches(&c.name, &col.name)) {
+                return Err(CatalogError::ColumnAlreadyExists {
+                    table: schema.name.clone(),
+                    column: col.name.clone(),
+                });
+            }
+        }
+        schema.validate()?;
+        self.tables.insert(key, schema);
         Ok(())
     }
 
     pub fn get_table(&self, name: &str) -> Option<&TableSchema> {
-        self.tables.get(name)
+        self.tables.get(&self.key(name))
+    }
+
+    pub fn get_table_qualified(&self, namespace: &str, table: &str) -> Option<&TableSchema> {
+        self.tables.get(&self.qualified_key(namespace, table))
+    }
+
+    /// Lookup that never folds, for quoted identifiers.
+    pub fn get_table_exact(&self, name: &str) -> Option<&TableSchema> {
+        let (_, table) = split_qualified(name);
+        self.get_table(name).filter(|schema| schema.name == table)
+    }
+
+    /// The name a table's persisted entries are keyed by, spelled as it was
+    /// created: `namespace.table`, or just `table` in the default
+    /// namespace, as entries were keyed before there were namespaces.
+    pub fn stored_name(&self, name: &str) -> Option<String> {
+        let schema = self.get_table(name)?;
+        let default = self.casing.fold(DEFAULT_NAMESPACE);
+        match split_qualified(name).0.filter(|ns| self.casing.fold(ns) != default) {
+            Some(namespace) => Some(format!("{}.{}", self.namespace_name(namespace)?, schema.name)),
+            None => Some(schema.name.clone()),
+        }
     }
 
     pub fn get_table_mut(&mut self, name: &str) -> Option<&mut TableSchema> {
-        self.tables.get_mut(name)
+        let key = self.key(name);
+        self.tables.get_mut(&key)
+    }
+
+    /// Resolve a column of a table under the casing policy.
+    pub fn get_column(&self, table: &str, column: &str) -> Option<&Column> {
+        self.get_table(table)?.find_column(column, self.casing)
     }
 
+    /// Remove a table along with every index defined on it.
     pub fn drop_table(&mut self, name: &str) -> CatalogResult<TableSchema> {
-        self.tables
-            .remove(name)
-            .ok_or_else(|| CatalogError::TableNotFound(name.to_string()))
+        let key = self.key(name);
+        let schema = self
+            .tables
+            .remove(&key)
+            .ok_or_else(|| CatalogError::TableNotFound(name.to_string()))?;
+        self.indexes.remove(&key);
+        self.stats.remove(&key);
+        Ok(schema)
+    }
+
+    /// Replace the statistics recorded for a table.
+    pub fn update_stats(&mut self, table: &str, stats: TableStats) -> CatalogResult<()> {
+        let key = self.key(table);
+        if !self.tables.contains_key(&key) {
+            return Err(CatalogError::TableNotFound(table.to_string()));
+        }
+        self.stats.insert(key, stats);
+        Ok(())
+    }
+
+    /// Statistics from the last analyze of `table`, if any.
+    pub fn get_stats(&self, table: &str) -> Option<&TableStats> {
+        self.stats.get(&self.key(table))
     }
 
+    pub fn create_index(&mut self, mut index: IndexDef) -> CatalogResult<()> {
+        let key = self.key(&index.table);
+        let schema = self
+            .tables
+            .get(&key)
+            .ok_or_else(|| CatalogError::TableNotFound(index.table.clone()))?;
+
+        if index.columns.is_empty() {
+            return Err(CatalogError::InvalidConstraint(format!(
+                "index '{}' has no columns",
+                index.name
+            )));
+        }
+        for column in index.columns.iter_mut() {
+            match schema.find_column(column, self.casing) {
+                Some(col) => *column = col.name.clone(),
+                None => {
+                    return Err(CatalogError::ColumnNotFound {
+                        table: index.table.clone(),
+                        column: column.clone(),
+                    })
+                }
+            }
+        }
+
+        let casing = self.casing;
+        let indexes = self.indexes.entry(key).or_default();
+        if indexes.iter().any(|i| casing.matches(&i.name, &index.name)) {
+            return Err(CatalogError::IndexAlreadyExists {
+                table: index.table,
+                index: index.name,
+            });
+        }
+        indexes.push(index);
+        Ok(())
+    }
+
+    pub fn drop_index(&mut self, table: &str, name: &str) -> CatalogResult<IndexDef> {
+        let not_found = || CatalogError::IndexNotFound {
+            table: table.to_string(),
+            index: name.to_string(),
+        };
+        let (key, casing) = (self.key(table), self.casing);
+        let indexes = self.indexes.get_mut(&key).ok_or_else(not_found)?;
+        let pos = indexes
+            .iter()
+            .position(|i| casing.matches(&i.name, name))
+            .ok_or_else(not_found)?;
+        Ok(indexes.remove(pos))
+    }
+
+    pub fn indexes_for_table(&self, table: &str) -> &[IndexDef] {
+        self.indexes
+            .get(&self.key(table))
+            .map(|v| v.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Apply `op` to the named table, renumber its columns, bump its schema
+    /// version, and record the change in the schema's history. The schema is
+    /// left untouched if validation fails.
+    pub fn alter_table(&mut self, name: &str, op: AlterOp) -> CatalogResult<&TableSchema> {
+        let (key, casing) = (self.key(name), self.casing);
+        let schema = self
+            .tables
+            .get_mut(&key)
+            .ok_or_else(|| CatalogError::TableNotFound(name.to_string()))?;
+
+        let column_exists =
+            |schema: &TableSchema, column: &str| schema.find_column(column, casing).is_some();
+        // Spell existing columns the way they were registered, so the
+        // recorded change matches stored rows.
+        let resolve = |schema: &TableSchema, column: String| {
+            schema
+                .find_column(&column, casing)
+                .map(|col| col.name.clone())
+                .unwrap_or(column)
+        };
+        let op = match op {
+            AlterOp::DropColumn(column) => AlterOp::DropColumn(resolve(schema, column)),
+            AlterOp::RenameColumn { from, to } => AlterOp::RenameColumn {
+                from: resolve(schema, from),
+                to,
+            },
+            op => op,
+        };
+        let recorded = op.clone();
+
+        match op {
+            AlterOp::AddColumn(column) => {
+                if column_exists(schema, &column.name) {
+                    return Err(CatalogError::ColumnAlreadyExists {
+                        table: name.to_string(),
+                        column: column.name,
+                    });
+                }
+                column.validate_default()?;
+                // Rows already written would read as NULL in it.
+                if !column.nullable && column.default.is_none() {
+                    return Err(CatalogError::InvalidConstraint(format!(
+                        "cannot add non-nullable column '{}' to '{}' without a default",
+                        column.name, name
+                    )));
+                }
+                schema.columns.push(column);
+            }
+            AlterOp::DropColumn(column) => {
+                if schema.is_constrained(&column) {
+                    return Err(CatalogError::InvalidConstraint(format!(
+                        "cannot drop column '{}' of '{}': it is part of a key",
+                        column, name
+                    )));
+                }
+                if let Some(index) = self
+                    .indexes
+                    .get(&key)
+                    .and_then(|v| v.iter().find(|i| i.covers(&column)))
+                {
+                    return Err(CatalogError::InvalidConstraint(format!(
+                        "cannot drop column '{}' of '{}': it is used by index '{}'",
+                        column, name, index.name
+                    )));
+                }
+                let idx = schema.get_column_index(&column).ok_or_else(|| {
+                    CatalogError::ColumnNotFound {
+                        table: name.to_string(),
+                        column: column.clone(),
+                    }
+                })?;
+                schema.columns.remove(idx);
+                if let Some(stats) = self.stats.get_mut(&key) {
+                    stats.column_stats.remove(&column);
+                }
+            }
+            AlterOp::RenameColumn { from, to } => {
+                if schema.find_column(&to, casing).is_some_and(|c| c.name != from) {
+                    return Err(CatalogError::ColumnAlreadyExists {
+                        table: name.to_string(),
+                        column: to,
+                    });
+                }
+                let idx = schema.get_column_index(&from).ok_or_else(|| {
+                    CatalogError::ColumnNotFound {
+                        table: name.to_string(),
+                        column: from.clone(),
+                    }
+                })?;
+                schema.rename_in_constraints(&from, &to);
+                for index in self.indexes.get_mut(&key).into_iter().flatten() {
+                    for col in index.columns.iter_mut().filter(|c| **c == from) {
+                        *col = to.clone();
+                    }
+                }
+                if let Some(stats) = self.stats.get_mut(&key) {
+                    if let Some(col) = stats.column_stats.remove(&from) {
+                        stats.column_stats.insert(to.clone(), col);
+                    }
+                }
+                schema.columns[idx].name = to;
+            }
+        }
+
+        schema.renumber_columns();
+        schema.schema_version += 1;
+        schema.record_change(recorded);
+        Ok(schema)
+    }
+
+    /// Tables in the default namespace.
     pub fn list_tables(&self) -> Vec<&str> {
-        self.tables.keys().map(|s| s.as_str()).collect()
+        self.list_tables_in(DEFAULT_NAMESPACE)
+    }
+
+    pub fn list_tables_in(&self, namespace: &str) -> Vec<&str> {
+        let prefix = format!("{}.", self.casing.fold(namespace));
+        self.tables
+            .iter()
+            .filter(|(key, _)| key.starts_with(&prefix))
+            .map(|(_, schema)| schema.name.as_str())
+            .collect()
     }
 
     pub fn table_exists(&self, name: &str) -> bool {
-        self.tables.contains_key(name)
+        self.get_table(name).is_some()
     }
 
+    /// Number of tables across all namespaces.
     pub fn table_count(&self) -> usize {
         self.tables.len()
     }
@@ -79,7 +563,7 @@ impl Default for Catalog {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::catalog::schema::{DataType, TableSchemaBuilder};
+    use crate::catalog::schema::{DataType, Datum, TableSchemaBuilder};
 
     #[test]
     fn test_register_and_get_table() {
@@ -136,10 +620,363 @@ mod tests {
         assert_eq!(tables, vec!["a", "b"]);
     }
 
+    fn orders_catalog() -> Catalog {
+        let mut catalog = Catalog::new();
+        catalog
+            .register_table(
+                TableSchemaBuilder::new("orders")
+                    .column("id", DataType::Int64, false)
+                    .column("item", DataType::String, false)
+                    .column("qty", DataType::Int64, true)
+                    .build(),
+            )
+            .unwrap();
+        catalog
+    }
+
+    #[test]
+    fn test_alter_add_column() {
+        let mut catalog = orders_catalog();
+        let schema = catalog
+            .alter_table("orders", AlterOp::AddColumn(Column::new("note", DataType::String)))
+            .unwrap();
+
+        assert_eq!(schema.column_names(), vec!["id", "item", "qty", "note"]);
+        assert_eq!(schema.get_column("note").unwrap().position, 3);
+        assert_eq!(schema.schema_version, 2);
+    }
+
+    #[test]
+    fn test_alter_add_non_null_column_needs_default() {
+        let mut catalog = orders_catalog();
+        let bare = Column::non_null("note", DataType::String);
+        let result = catalog.alter_table("orders", AlterOp::AddColumn(bare.clone()));
+        assert!(matches!(result, Err(CatalogError::InvalidConstraint(_))));
+        assert_eq!(catalog.get_table("orders").unwrap().schema_version, 1);
+
+        let schema = catalog
+            .alter_table(
+                "orders",
+                AlterOp::AddColumn(bare.with_default(Datum::String("none".to_string()))),
+            )
+            .unwrap();
+        assert!(!schema.get_column("note").unwrap().nullable);
+    }
+
+    #[test]
+    fn test_alter_drop_column_renumbers() {
+        let mut catalog = orders_catalog();
+        let schema = catalog
+            .alter_table("orders", AlterOp::DropColumn("item".to_string()))
+            .unwrap();
+
+        assert_eq!(schema.column_names(), vec!["id", "qty"]);
+        assert_eq!(schema.get_column("qty").unwrap().position, 1);
+        assert_eq!(schema.schema_version, 2);
+    }
+
+    #[test]
+    fn test_alter_rename_column() {
+        let mut catalog = orders_catalog();
+        catalog
+            .alter_table(
+                "orders",
+                AlterOp::RenameColumn {
+                    from: "qty".to_string(),
+                    to: "quantity".to_string(),
+                },
+            )
+            .unwrap();
+
+        let schema = catalog.get_table("orders").unwrap();
+        assert!(schema.get_column("qty").is_none());
+        assert_eq!(schema.get_column("quantity").unwrap().position, 2);
+        assert!(schema.get_column("quantity").unwrap().nullable);
+    }
+
+    #[test]
+    fn test_alter_rejections_leave_schema_unchanged() {
+        let mut catalog = orders_catalog();
+
+        let dup = catalog.alter_table("orders", AlterOp::AddColumn(Column::new("id", DataType::Int64)));
+        assert!(matches!(dup, Err(CatalogError::ColumnAlreadyExists { .. })));
+
+        let rename = catalog.alter_table(
+            "orders",
+            AlterOp::RenameColumn {
+                from: "qty".to_string(),
+                to: "item".to_string(),
+            },
+        );
+        assert!(matches!(rename, Err(CatalogError::ColumnAlreadyExists { .. })));
+
+        let missing = catalog.alter_table("orders", AlterOp::DropColumn("nope".to_string()));
+        assert!(matches!(missing, Err(CatalogError::ColumnNotFound { .. })));
+
+        let no_table = catalog.alter_table("ghost", AlterOp::DropColumn("id".to_string()));
+        assert!(matches!(no_table, Err(CatalogError::TableNotFound(_))));
+
+        let schema = catalog.get_table("orders").unwrap();
+        assert_eq!(schema.column_names(), vec!["id", "item", "qty"]);
+        assert_eq!(schema.schema_version, 1);
+    }
+
+    #[test]
+    fn test_alter_respects_key_columns() {
+        let mut catalog = Catalog::new();
+        catalog
+            .register_table(
+                TableSchemaBuilder::new("orders")
+                    .column("id", DataType::Int64, false)
+                    .column("sku", DataType::String, false)
+                    .primary_key(vec!["id"])
+                    .unique(vec!["sku"])
+                    .build(),
+            )
+            .unwrap();
+
+        for column in ["id", "sku"] {
+            let result = catalog.alter_table("orders", AlterOp::DropColumn(column.to_string()));
+            assert!(matches!(result, Err(CatalogError::InvalidConstraint(_))));
+        }
+
+        let schema = catalog
+            .alter_table(
+                "orders",
+                AlterOp::RenameColumn {
+                    from: "id".to_string(),
+                    to: "order_id".to_string(),
+                },
+            )
+            .unwrap();
+        assert_eq!(schema.primary_key(), Some(&["order_id".to_string()][..]));
+    }
+
+    #[test]
+    fn test_create_list_drop_index() {
+        let mut catalog = orders_catalog();
+        catalog
+            .create_index(IndexDef::new("by_item", "orders", vec!["item"], false))
+            .unwrap();
+        catalog
+            .create_index(IndexDef::new("by_item_qty", "orders", vec!["item", "qty"], true))
+            .unwrap();
+
+        let names: Vec<&str> = catalog
+            .indexes_for_table("orders")
+            .iter()
+            .map(|i| i.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["by_item", "by_item_qty"]);
+
+        let dropped = catalog.drop_index("orders", "by_item").unwrap();
+        assert_eq!(dropped.columns, vec!["item".to_string()]);
+        assert_eq!(catalog.indexes_for_table("orders").len(), 1);
+        assert!(matches!(
+            catalog.drop_index("orders", "by_item"),
+            Err(CatalogError::IndexNotFound { .. })
+        ));
+        assert!(catalog.indexes_for_table("missing").is_empty());
+    }
+
+    #[test]
+    fn test_index_validation() {
+        let mut catalog = orders_catalog();
+        catalog
+            .create_index(IndexDef::new("by_item", "orders", vec!["item"], false))
+            .unwrap();
+
+        let dup = catalog.create_index(IndexDef::new("by_item", "orders", vec!["qty"], false));
+        assert!(matches!(dup, Err(CatalogError::IndexAlreadyExists { .. })));
+
+        let dangling = catalog.create_index(IndexDef::new("by_price", "orders", vec!["price"], false));
+        assert!(matches!(dangling, Err(CatalogError::ColumnNotFound { .. })));
+
+        let no_table = catalog.create_index(IndexDef::new("x", "ghost", vec!["id"], false));
+        assert!(matches!(no_table, Err(CatalogError::TableNotFound(_))));
+
+        let drop_col = catalog.alter_table("orders", AlterOp::DropColumn("item".to_string()));
+        assert!(matches!(drop_col, Err(CatalogError::InvalidConstraint(_))));
+
+        catalog
+            .alter_table(
+                "orders",
+                AlterOp::RenameColumn {
+                    from: "item".to_string(),
+                    to: "product".to_string(),
+                },
+            )
+            .unwrap();
+        assert!(catalog.indexes_for_table("orders")[0].covers("product"));
+    }
+
+    #[test]
+    fn test_drop_table_cascades_indexes() {
+        let mut catalog = orders_catalog();
+        catalog
+            .create_index(IndexDef::new("by_item", "orders", vec!["item"], false))
+            .unwrap();
+
+        catalog.drop_table("orders").unwrap();
+        assert!(catalog.indexes_for_table("orders").is_empty());
+
+        catalog.register_table(orders_catalog().drop_table("orders").unwrap()).unwrap();
+        assert!(catalog.indexes_for_table("orders").is_empty());
+    }
+
     #[test]
     fn test_drop_nonexistent_table() {
         let mut catalog = Catalog::new();
         let result = catalog.drop_table("nonexistent");
         assert!(matches!(result, Err(CatalogError::TableNotFound(_))));
     }
+
+    fn mixed_case_users() -> TableSchema {
+        TableSchemaBuilder::new("Users")
+            .column("Id", DataType::Int64, false)
+            .column("email", DataType::String, true)
+            .build()
+    }
+
+    #[test]
+    fn test_case_sensitive_lookups() {
+        let mut catalog = Catalog::new();
+        catalog.register_table(mixed_case_users()).unwrap();
+
+        assert!(catalog.table_exists("Users"));
+        assert!(!catalog.table_exists("users"));
+        assert!(catalog.get_column("Users", "Id").is_some());
+        assert!(catalog.get_column("Users", "id").is_none());
+
+        // Distinct names under byte-exact matching.
+        catalog
+            .register_table(TableSchemaBuilder::new("users").build())
+            .unwrap();
+        assert_eq!(catalog.table_count(), 2);
+    }
+
+    #[test]
+    fn test_lowercase_fold_lookups() {
+        let mut catalog = Catalog::with_identifier_casing(IdentifierCasing::LowercaseFold);
+        catalog.register_table(mixed_case_users()).unwrap();
+
+        for name in ["Users", "users", "USERS"] {
+            assert_eq!(catalog.get_table(name).unwrap().name, "Users");
+        }
+        assert_eq!(catalog.get_column("users", "ID").unwrap().name, "Id");
+        assert_eq!(catalog.get_column("USERS", "Email").unwrap().name, "email");
+        assert!(catalog.get_table_exact("Users").is_some());
+        assert!(catalog.get_table_exact("users").is_none());
+        assert_eq!(catalog.list_tables(), vec!["Users"]);
+
+        let dup = catalog.register_table(TableSchemaBuilder::new("users").build());
+        assert!(matches!(dup, Err(CatalogError::TableAlreadyExists(_))));
+
+        let dup_cols = catalog.register_table(
+            TableSchemaBuilder::new("orders")
+                .column("Total", DataType::Int64, true)
+                .column("total", DataType::Int64, true)
+                .build(),
+        );
+        assert!(matches!(dup_cols, Err(CatalogError::ColumnAlreadyExists { .. })));
+
+        catalog
+            .alter_table("USERS", AlterOp::DropColumn("EMAIL".to_string()))
+            .unwrap();
+        let users = catalog.get_table("users").unwrap();
+        assert!(users.get_column("email").is_none());
+        assert!(matches!(
+            users.changes_since(1).next(),
+            Some(AlterOp::DropColumn(name)) if name == "email"
+        ));
+
+        catalog.drop_table("uSeRs").unwrap();
+        assert_eq!(catalog.table_count(), 0);
+    }
+
+    fn events(column: &str) -> TableSchema {
+        TableSchemaBuilder::new("events")
+            .column(column, DataType::Int64, false)
+            .build()
+    }
+
+    #[test]
+    fn test_same_table_in_two_namespaces() {
+        let mut catalog = Catalog::new();
+        catalog.create_namespace("analytics").unwrap();
+        catalog.register_table(events("id")).unwrap();
+        catalog.register_table_in("analytics", events("session")).unwrap();
+
+        assert!(catalog.get_table("events").unwrap().get_column("id").is_some());
+        assert!(catalog.get_table("public.events").unwrap().get_column("id").is_some());
+        let analytics = catalog.get_table_qualified("analytics", "events").unwrap();
+        assert!(analytics.get_column("session").is_some());
+        assert!(catalog.get_column("analytics.events", "session").is_some());
+
+        assert_eq!(catalog.list_namespaces(), vec!["analytics", "public"]);
+        assert_eq!(catalog.list_tables(), vec!["events"]);
+        assert_eq!(catalog.list_tables_in("analytics"), vec!["events"]);
+        assert_eq!(catalog.table_count(), 2);
+        assert_eq!(catalog.stored_name("public.events").as_deref(), Some("events"));
+        assert_eq!(catalog.stored_name("analytics.events").as_deref(), Some("analytics.events"));
+        assert_eq!(catalog.stored_name("analytics.missing"), None);
+
+        assert!(matches!(
+            catalog.register_table_in("missing", events("id")),
+            Err(CatalogError::NamespaceNotFound(_))
+        ));
+        assert!(matches!(
+            catalog.create_namespace("analytics"),
+            Err(CatalogError::NamespaceAlreadyExists(_))
+        ));
+        assert!(matches!(
+            catalog.create_namespace("a.b"),
+            Err(CatalogError::InvalidName(_))
+        ));
+    }
+
+    #[test]
+    fn test_stored_name_spelled_as_created() {
+        let mut catalog = Catalog::with_identifier_casing(IdentifierCasing::LowercaseFold);
+        catalog.create_namespace("Analytics").unwrap();
+        catalog.register_table_in("Analytics", events("id")).unwrap();
+
+        assert_eq!(catalog.stored_name("ANALYTICS.Events").as_deref(), Some("Analytics.events"));
+        assert_eq!(catalog.namespace_name("analytics"), Some("Analytics"));
+        assert_eq!(
+            catalog.check_drop_namespace("analytics", true).unwrap(),
+            vec!["Analytics.events".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_drop_namespace_cascade() {
+        let mut catalog = Catalog::new();
+        catalog.create_namespace("analytics").unwrap();
+        catalog.register_table_in("analytics", events("id")).unwrap();
+        catalog
+            .create_index(IndexDef::new("by_id", "analytics.events", vec!["id"], false))
+            .unwrap();
+        catalog.register_table(events("id")).unwrap();
+
+        assert!(matches!(
+            catalog.drop_namespace("analytics", false),
+            Err(CatalogError::NamespaceNotEmpty(_))
+        ));
+        assert!(catalog.get_table("analytics.events").is_some());
+
+        let dropped = catalog.drop_namespace("analytics", true).unwrap();
+        assert_eq!(dropped.len(), 1);
+        assert!(!catalog.namespace_exists("analytics"));
+        assert!(catalog.get_table("analytics.events").is_none());
+        assert!(catalog.indexes_for_table("analytics.events").is_empty());
+        assert!(catalog.get_table("events").is_some());
+
+        catalog.create_namespace("empty").unwrap();
+        assert!(catalog.drop_namespace("empty", false).unwrap().is_empty());
+        assert!(matches!(
+            catalog.drop_namespace(DEFAULT_NAMESPACE, true),
+            Err(CatalogError::InvalidName(_))
+        ));
+    }
 }
@@ -0,0 +1,68 @@
+use super::schema::{put_str, put_str_list, SchemaReader};
+
+/// A secondary index over one or more columns of a table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexDef {
+    pub name: String,
+    pub table: String,
+    pub columns: Vec<String>,
+    pub unique: bool,
+}
+
+impl IndexDef {
+    pub fn new<S: Into<String>>(
+        name: impl Into<String>,
+        table: impl Into<String>,
+        columns: Vec<S>,
+        unique: bool,
+    ) -> Self {
+        IndexDef {
+            name: name.into(),
+            table: table.into(),
+            columns: columns.into_iter().map(Into::into).collect(),
+            unique,
+        }
+    }
+
+    pub fn covers(&self, column: &str) -> bool {
+        self.columns.iter().any(|c| c == column)
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        put_str(&mut buf, &self.name);
+        put_str(&mut buf, &self.table);
+        put_str_list(&mut buf, &self.columns);
+        buf.push(self.unique as u8);
+        buf
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Self, String> {
+        let mut reader = SchemaReader::new(data);
+        let index = IndexDef {
+            name: reader.string()?,
+            table: reader.string()?,
+            columns: reader.string_list()?,
+            unique: reader.u8()? != 0,
+        };
+        if !reader.is_empty() {
+            return Err("trailing bytes after index definition".to_string());
+        }
+        Ok(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_index_def_round_trip() {
+        let index = IndexDef::new("by_email", "users", vec!["email", "id"], true);
+        let decoded = IndexDef::decode(&index.encode()).unwrap();
+        assert_eq!(decoded, index);
+        assert!(decoded.covers("email"));
+        assert!(!decoded.covers("name"));
+        assert!(IndexDef::decode(&index.encode()[..5]).is_err());
+    }
+}
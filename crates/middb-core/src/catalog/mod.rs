@@ -1,5 +1,13 @@
 mod schema;
+mod index;
+mod stats;
 mod catalog;
 
-pub use schema::{Column, DataType, TableSchema, TableSchemaBuilder};
-pub use catalog::{Catalog, CatalogError, CatalogResult};
+pub use schema::{Column, DataType, Datum, SchemaChange, TableSchema, TableSchemaBuilder};
+pub use index::IndexDef;
+pub use stats::{ColumnStats, TableStats};
+pub use catalog::{
+    catalog_key, index_key, namespace_key, split_qualified, stats_key, AlterOp, Catalog,
+    CatalogError, CatalogResult, IdentifierCasing, CATALOG_KEY_PREFIX, DEFAULT_NAMESPACE,
+    INDEX_KEY_PREFIX, NAMESPACE_KEY_PREFIX, STATS_KEY_PREFIX,
+};
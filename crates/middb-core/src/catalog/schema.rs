@@ -1,4 +1,7 @@
+use super::catalog::{AlterOp, CatalogError, CatalogResult, IdentifierCasing};
+use crate::types::Timestamp;
 use std::fmt;
+use std::hash::{Hash, Hasher};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum DataType {
@@ -6,11 +9,67 @@ pub enum DataType {
     String,
     Bytes,
     Bool,
+    Float64,
+    /// Microseconds since the Unix epoch.
+    Timestamp,
+    /// Fixed-point number with `precision` total digits, `scale` of them
+    /// after the decimal point.
+    Decimal { precision: u8, scale: u8 },
 }
 
 impl DataType {
+    /// Largest precision a `Decimal` can carry; values are stored as i128.
+    pub const MAX_DECIMAL_PRECISION: u8 = 38;
+
+    pub fn is_numeric(&self) -> bool {
+        matches!(self, DataType::Int64 | DataType::Float64 | DataType::Decimal { .. })
+    }
+
+    /// Whether values of the two types can be compared with each other.
+    /// Numeric types compare across each other; everything else only with itself.
     pub fn is_compatible(&self, other: &DataType) -> bool {
-        self == other
+        match (self, other) {
+            (DataType::Decimal { .. }, DataType::Decimal { .. }) => true,
+            (a, b) if a.is_numeric() && b.is_numeric() => true,
+            (a, b) => a == b,
+        }
+    }
+
+    fn encode_into(&self, buf: &mut Vec<u8>) {
+        match self {
+            DataType::Int64 => buf.push(1),
+            DataType::String => buf.push(2),
+            DataType::Bytes => buf.push(3),
+            DataType::Bool => buf.push(4),
+            DataType::Float64 => buf.push(5),
+            DataType::Timestamp => buf.push(6),
+            DataType::Decimal { precision, scale } => {
+                buf.push(7);
+                buf.push(*precision);
+                buf.push(*scale);
+            }
+        }
+    }
+
+    fn decode_from(reader: &mut SchemaReader<'_>) -> Result<Self, String> {
+        let tag = reader.u8()?;
+        match tag {
+            1 => Ok(DataType::Int64),
+            2 => Ok(DataType::String),
+            3 => Ok(DataType::Bytes),
+            4 => Ok(DataType::Bool),
+            5 => Ok(DataType::Float64),
+            6 => Ok(DataType::Timestamp),
+            7 => {
+                let precision = reader.u8()?;
+                let scale = reader.u8()?;
+                if precision == 0 || precision > Self::MAX_DECIMAL_PRECISION || scale > precision {
+                    return Err(format!("invalid DECIMAL({}, {})", precision, scale));
+                }
+                Ok(DataType::Decimal { precision, scale })
+            }
+            _ => Err(format!("unknown data type tag {}", tag)),
+        }
     }
 }
 
@@ -21,6 +80,139 @@ impl fmt::Display for DataType {
             DataType::String => write!(f, "STRING"),
             DataType::Bytes => write!(f, "BYTES"),
             DataType::Bool => write!(f, "BOOL"),
+            DataType::Float64 => write!(f, "FLOAT64"),
+            DataType::Timestamp => write!(f, "TIMESTAMP"),
+            DataType::Decimal { precision, scale } => write!(f, "DECIMAL({}, {})", precision, scale),
+        }
+    }
+}
+
+/// A constant stored in the catalog: a column default, or a bound recorded
+/// in table statistics.
+#[derive(Debug, Clone)]
+pub enum Datum {
+    Null,
+    Int(i64),
+    Float(f64),
+    String(String),
+    Bytes(Vec<u8>),
+    Bool(bool),
+    Timestamp(Timestamp),
+    Decimal { value: i128, scale: u8 },
+}
+
+impl Datum {
+    /// Whether the value can be stored in a column of `data_type`. NULL fits
+    /// every type; nullability is checked separately.
+    pub fn fits(&self, data_type: &DataType) -> bool {
+        match (self, data_type) {
+            (Datum::Null, _) => true,
+            (Datum::Int(_), t) => t.is_numeric(),
+            (Datum::Float(_), DataType::Float64) => true,
+            (Datum::String(_), DataType::String) => true,
+            (Datum::Bytes(_), DataType::Bytes) => true,
+            (Datum::Bool(_), DataType::Bool) => true,
+            (Datum::Timestamp(_), DataType::Timestamp) => true,
+            (Datum::Decimal { scale, .. }, DataType::Decimal { scale: col_scale, .. }) => {
+                scale <= col_scale
+            }
+            _ => false,
+        }
+    }
+
+    pub(super) fn encode_into(&self, buf: &mut Vec<u8>) {
+        match self {
+            Datum::Null => buf.push(0),
+            Datum::Int(v) => {
+                buf.push(1);
+                buf.extend_from_slice(&v.to_le_bytes());
+            }
+            Datum::Float(v) => {
+                buf.push(2);
+                buf.extend_from_slice(&v.to_bits().to_le_bytes());
+            }
+            Datum::String(v) => {
+                buf.push(3);
+                put_str(buf, v);
+            }
+            Datum::Bytes(v) => {
+                buf.push(4);
+                buf.extend_from_slice(&(v.len() as u32).to_le_bytes());
+                buf.extend_from_slice(v);
+            }
+            Datum::Bool(v) => {
+                buf.push(5);
+                buf.push(*v as u8);
+            }
+            Datum::Timestamp(v) => {
+                buf.push(6);
+                buf.extend_from_slice(&v.to_le_bytes());
+            }
+            Datum::Decimal { value, scale } => {
+                buf.push(7);
+                buf.extend_from_slice(&value.to_le_bytes());
+                buf.push(*scale);
+            }
+        }
+    }
+
+    pub(super) fn decode_from(reader: &mut SchemaReader<'_>) -> Result<Self, String> {
+        let tag = reader.u8()?;
+        Ok(match tag {
+            0 => Datum::Null,
+            1 => Datum::Int(reader.u64()? as i64),
+            2 => Datum::Float(f64::from_bits(reader.u64()?)),
+            3 => Datum::String(reader.string()?),
+            4 => Datum::Bytes(reader.bytes()?),
+            5 => Datum::Bool(reader.u8()? != 0),
+            6 => Datum::Timestamp(reader.u64()?),
+            7 => Datum::Decimal {
+                value: reader.i128()?,
+                scale: reader.u8()?,
+            },
+            _ => return Err(format!("unknown default value tag {}", tag)),
+        })
+    }
+}
+
+// Floats compare and hash bitwise so that `Datum` can be `Eq` and used as a
+// set key.
+impl PartialEq for Datum {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Datum::Null, Datum::Null) => true,
+            (Datum::Int(a), Datum::Int(b)) => a == b,
+            (Datum::Float(a), Datum::Float(b)) => a.to_bits() == b.to_bits(),
+            (Datum::String(a), Datum::String(b)) => a == b,
+            (Datum::Bytes(a), Datum::Bytes(b)) => a == b,
+            (Datum::Bool(a), Datum::Bool(b)) => a == b,
+            (Datum::Timestamp(a), Datum::Timestamp(b)) => a == b,
+            (
+                Datum::Decimal { value: a, scale: sa },
+                Datum::Decimal { value: b, scale: sb },
+            ) => a == b && sa == sb,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Datum {}
+
+impl Hash for Datum {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Datum::Null => {}
+            Datum::Int(v) => v.hash(state),
+            Datum::Float(v) => v.to_bits().hash(state),
+            Datum::String(v) => v.hash(state),
+            Datum::Bytes(v) => v.hash(state),
+            Datum::Bool(v) => v.hash(state),
+            Datum::Timestamp(v) => v.hash(state),
+            Datum::Decimal { value, scale } => {
+                value.hash(state);
+                scale.hash(state);
+            }
         }
     }
 }
@@ -31,6 +223,7 @@ pub struct Column {
     pub data_type: DataType,
     pub nullable: bool,
     pub position: usize,
+    pub default: Option<Datum>,
 }
 
 impl Column {
@@ -40,6 +233,7 @@ impl Column {
             data_type,
             nullable: true,
             position: 0,
+            default: None,
         }
     }
 
@@ -49,9 +243,37 @@ impl Column {
             data_type,
             nullable: false,
             position: 0,
+            default: None,
         }
     }
 
+    pub fn with_default(mut self, default: Datum) -> Self {
+        self.default = Some(default);
+        self
+    }
+
+    /// Checks that the default, if any, matches the column's type and
+    /// nullability.
+    pub fn validate_default(&self) -> CatalogResult<()> {
+        let default = match &self.default {
+            Some(d) => d,
+            None => return Ok(()),
+        };
+        if matches!(default, Datum::Null) && !self.nullable {
+            return Err(CatalogError::InvalidDefault(format!(
+                "non-nullable column '{}' cannot default to NULL",
+                self.name
+            )));
+        }
+        if !default.fits(&self.data_type) {
+            return Err(CatalogError::InvalidDefault(format!(
+                "default {:?} does not fit column '{}' of type {}",
+                default, self.name, self.data_type
+            )));
+        }
+        Ok(())
+    }
+
     pub fn with_position(mut self, position: usize) -> Self {
         self.position = position;
         self
@@ -61,12 +283,58 @@ impl Column {
         self.nullable = nullable;
         self
     }
+
+    fn encode_into(&self, buf: &mut Vec<u8>) {
+        put_str(buf, &self.name);
+        self.data_type.encode_into(buf);
+        buf.push(self.nullable as u8);
+        buf.extend_from_slice(&(self.position as u32).to_le_bytes());
+        match &self.default {
+            Some(default) => {
+                buf.push(1);
+                default.encode_into(buf);
+            }
+            None => buf.push(0),
+        }
+    }
+
+    fn decode_from(reader: &mut SchemaReader<'_>) -> Result<Self, String> {
+        let name = reader.string()?;
+        let data_type = DataType::decode_from(reader)?;
+        let nullable = reader.u8()? != 0;
+        let position = reader.u32()? as usize;
+        let default = match reader.u8()? {
+            0 => None,
+            _ => Some(Datum::decode_from(reader)?),
+        };
+        Ok(Column {
+            name,
+            data_type,
+            nullable,
+            position,
+            default,
+        })
+    }
 }
 
-#[derive(Debug, Clone)]
+/// One applied ALTER, kept so rows written under an older version can be
+/// brought up to date.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchemaChange {
+    /// The schema version this change produced.
+    pub version: u64,
+    pub op: AlterOp,
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct TableSchema {
     pub name: String,
     pub columns: Vec<Column>,
+    /// Starts at 1 and is bumped by every `Catalog::alter_table`.
+    pub schema_version: u64,
+    primary_key: Option<Vec<String>>,
+    unique: Vec<Vec<String>>,
+    history: Vec<SchemaChange>,
 }
 
 impl TableSchema {
@@ -82,6 +350,10 @@ impl TableSchema {
         TableSchema {
             name: name.into(),
             columns,
+            schema_version: 1,
+            primary_key: None,
+            unique: Vec::new(),
+            history: Vec::new(),
         }
     }
 
@@ -89,6 +361,10 @@ impl TableSchema {
         TableSchema {
             name: name.into(),
             columns: Vec::new(),
+            schema_version: 1,
+            primary_key: None,
+            unique: Vec::new(),
+            history: Vec::new(),
         }
     }
 
@@ -105,18 +381,294 @@ impl TableSchema {
         self.columns.iter().find(|c| c.name == name)
     }
 
+    /// Column lookup under a casing policy; an exact match always wins.
+    pub fn find_column(&self, name: &str, casing: IdentifierCasing) -> Option<&Column> {
+        self.get_column(name)
+            .or_else(|| self.columns.iter().find(|c| casing.matches(&c.name, name)))
+    }
+
     pub fn get_column_index(&self, name: &str) -> Option<usize> {
         self.columns.iter().position(|c| c.name == name)
     }
 
+    pub fn primary_key(&self) -> Option<&[String]> {
+        self.primary_key.as_deref()
+    }
+
+    pub fn unique_constraints(&self) -> &[Vec<String>] {
+        &self.unique
+    }
+
+    /// True if the column is part of the primary key or a unique constraint.
+    pub fn is_constrained(&self, column: &str) -> bool {
+        self.primary_key
+            .iter()
+            .chain(self.unique.iter())
+            .any(|cols| cols.iter().any(|c| c == column))
+    }
+
+    pub(crate) fn rename_in_constraints(&mut self, from: &str, to: &str) {
+        for cols in self.primary_key.iter_mut().chain(self.unique.iter_mut()) {
+            for col in cols.iter_mut().filter(|c| c.as_str() == from) {
+                *col = to.to_string();
+            }
+        }
+    }
+
+    /// Check that constrained columns exist, are listed once per constraint,
+    /// and that primary key columns are non-nullable.
+    /// Checks column defaults and key constraints.
+    pub fn validate(&self) -> CatalogResult<()> {
+        for col in &self.columns {
+            col.validate_default()?;
+        }
+        self.validate_constraints()
+    }
+
+    pub fn validate_constraints(&self) -> CatalogResult<()> {
+        let invalid = |msg: String| Err(CatalogError::InvalidConstraint(msg));
+
+        if let Some(pk) = &self.primary_key {
+            if pk.is_empty() {
+                return invalid(format!("primary key of '{}' has no columns", self.name));
+            }
+            for col in pk {
+                match self.get_column(col) {
+                    None => {
+                        return Err(CatalogError::ColumnNotFound {
+                            table: self.name.clone(),
+                            column: col.clone(),
+                        })
+                    }
+                    Some(c) if c.nullable => {
+                        return invalid(format!(
+                            "primary key column '{}' of '{}' must be non-nullable",
+                            col, self.name
+                        ))
+                    }
+                    Some(_) => {}
+                }
+            }
+        }
+
+        for cols in self.primary_key.iter().chain(self.unique.iter()) {
+            for (i, col) in cols.iter().enumerate() {
+                if self.get_column(col).is_none() {
+                    return Err(CatalogError::ColumnNotFound {
+                        table: self.name.clone(),
+                        column: col.clone(),
+                    });
+                }
+                if cols[..i].contains(col) {
+                    return invalid(format!("column '{}' repeated in constraint on '{}'", col, self.name));
+                }
+            }
+            if cols.is_empty() {
+                return invalid(format!("unique constraint on '{}' has no columns", self.name));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reassign positions to match the order of `columns`.
+    /// The ALTERs applied after `version`, oldest first.
+    pub fn changes_since(&self, version: u64) -> impl Iterator<Item = &AlterOp> {
+        self.history
+            .iter()
+            .filter(move |change| change.version > version)
+            .map(|change| &change.op)
+    }
+
+    pub(crate) fn record_change(&mut self, op: AlterOp) {
+        self.history.push(SchemaChange {
+            version: self.schema_version,
+            op,
+        });
+    }
+
+    pub fn renumber_columns(&mut self) {
+        for (i, col) in self.columns.iter_mut().enumerate() {
+            col.position = i;
+        }
+    }
+
     pub fn column_names(&self) -> Vec<&str> {
         self.columns.iter().map(|c| c.name.as_str()).collect()
     }
+
+    /// Binary encoding used to persist the schema in the catalog key range.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = vec![SCHEMA_FORMAT_VERSION];
+        put_str(&mut buf, &self.name);
+        buf.extend_from_slice(&self.schema_version.to_le_bytes());
+        buf.extend_from_slice(&(self.columns.len() as u32).to_le_bytes());
+        for col in &self.columns {
+            col.encode_into(&mut buf);
+        }
+        match &self.primary_key {
+            Some(pk) => {
+                buf.push(1);
+                put_str_list(&mut buf, pk);
+            }
+            None => buf.push(0),
+        }
+        buf.extend_from_slice(&(self.unique.len() as u32).to_le_bytes());
+        for cols in &self.unique {
+            put_str_list(&mut buf, cols);
+        }
+        buf.extend_from_slice(&(self.history.len() as u32).to_le_bytes());
+        for change in &self.history {
+            buf.extend_from_slice(&change.version.to_le_bytes());
+            match &change.op {
+                AlterOp::AddColumn(col) => {
+                    buf.push(1);
+                    col.encode_into(&mut buf);
+                }
+                AlterOp::DropColumn(name) => {
+                    buf.push(2);
+                    put_str(&mut buf, name);
+                }
+                AlterOp::RenameColumn { from, to } => {
+                    buf.push(3);
+                    put_str(&mut buf, from);
+                    put_str(&mut buf, to);
+                }
+            }
+        }
+        buf
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Self, String> {
+        let mut reader = SchemaReader::new(data);
+
+        let version = reader.u8()?;
+        if version != SCHEMA_FORMAT_VERSION {
+            return Err(format!("unsupported schema format version {}", version));
+        }
+
+        let name = reader.string()?;
+        let schema_version = reader.u64()?;
+        let column_count = reader.u32()? as usize;
+        let mut columns = Vec::with_capacity(column_count.min(1024));
+        for _ in 0..column_count {
+            columns.push(Column::decode_from(&mut reader)?);
+        }
+
+        let primary_key = match reader.u8()? {
+            0 => None,
+            _ => Some(reader.string_list()?),
+        };
+        let unique_count = reader.u32()? as usize;
+        let mut unique = Vec::with_capacity(unique_count.min(1024));
+        for _ in 0..unique_count {
+            unique.push(reader.string_list()?);
+        }
+        let change_count = reader.u32()? as usize;
+        let mut history = Vec::with_capacity(change_count.min(1024));
+        for _ in 0..change_count {
+            let version = reader.u64()?;
+            let op = match reader.u8()? {
+                1 => AlterOp::AddColumn(Column::decode_from(&mut reader)?),
+                2 => AlterOp::DropColumn(reader.string()?),
+                3 => AlterOp::RenameColumn {
+                    from: reader.string()?,
+                    to: reader.string()?,
+                },
+                tag => return Err(format!("unknown schema change tag {}", tag)),
+            };
+            history.push(SchemaChange { version, op });
+        }
+
+        if !reader.is_empty() {
+            return Err("trailing bytes after schema".to_string());
+        }
+
+        Ok(TableSchema {
+            name,
+            columns,
+            schema_version,
+            primary_key,
+            unique,
+            history,
+        })
+    }
+}
+
+const SCHEMA_FORMAT_VERSION: u8 = 1;
+
+pub(super) fn put_str(buf: &mut Vec<u8>, s: &str) {
+    buf.extend_from_slice(&(s.len() as u32).to_le_bytes());
+    buf.extend_from_slice(s.as_bytes());
+}
+
+pub(super) fn put_str_list(buf: &mut Vec<u8>, items: &[String]) {
+    buf.extend_from_slice(&(items.len() as u32).to_le_bytes());
+    for item in items {
+        put_str(buf, item);
+    }
+}
+
+pub(super) struct SchemaReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> SchemaReader<'a> {
+    pub(super) fn new(data: &'a [u8]) -> Self {
+        SchemaReader { data, pos: 0 }
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.pos == self.data.len()
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.data.len() - self.pos < len {
+            return Err("schema encoding truncated".to_string());
+        }
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    pub(super) fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(super) fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub(super) fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn i128(&mut self) -> Result<i128, String> {
+        Ok(i128::from_le_bytes(self.take(16)?.try_into().unwrap()))
+    }
+
+    fn bytes(&mut self) -> Result<Vec<u8>, String> {
+        let len = self.u32()? as usize;
+        Ok(self.take(len)?.to_vec())
+    }
+
+    pub(super) fn string(&mut self) -> Result<String, String> {
+        let len = self.u32()? as usize;
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|e| e.to_string())
+    }
+
+    pub(super) fn string_list(&mut self) -> Result<Vec<String>, String> {
+        let count = self.u32()? as usize;
+        (0..count).map(|_| self.string()).collect()
+    }
 }
 
 pub struct TableSchemaBuilder {
     name: String,
     columns: Vec<Column>,
+    primary_key: Option<Vec<String>>,
+    unique: Vec<Vec<String>>,
 }
 
 impl TableSchemaBuilder {
@@ -124,9 +676,21 @@ impl TableSchemaBuilder {
         TableSchemaBuilder {
             name: name.into(),
             columns: Vec::new(),
+            primary_key: None,
+            unique: Vec::new(),
         }
     }
 
+    pub fn primary_key<S: Into<String>>(mut self, columns: Vec<S>) -> Self {
+        self.primary_key = Some(columns.into_iter().map(Into::into).collect());
+        self
+    }
+
+    pub fn unique<S: Into<String>>(mut self, columns: Vec<S>) -> Self {
+        self.unique.push(columns.into_iter().map(Into::into).collect());
+        self
+    }
+
     pub fn column(mut self, name: impl Into<String>, data_type: DataType, nullable: bool) -> Self {
         let col = if nullable {
             Column::new(name, data_type)
@@ -137,8 +701,35 @@ impl TableSchemaBuilder {
         self
     }
 
+    pub fn column_with_default(
+        self,
+        name: impl Into<String>,
+        data_type: DataType,
+        nullable: bool,
+        default: Datum,
+    ) -> Self {
+        let mut builder = self.column(name, data_type, nullable);
+        if let Some(col) = builder.columns.last_mut() {
+            col.default = Some(default);
+        }
+        builder
+    }
+
+    /// Build the schema, panicking if a constraint or default is invalid. Use
+    /// `try_build` to handle that case.
     pub fn build(self) -> TableSchema {
-        TableSchema::new(self.name, self.columns)
+        match self.try_build() {
+            Ok(schema) => schema,
+            Err(e) => panic!("invalid table schema: {}", e),
+        }
+    }
+
+    pub fn try_build(self) -> CatalogResult<TableSchema> {
+        let mut schema = TableSchema::new(self.name, self.columns);
+        schema.primary_key = self.primary_key;
+        schema.unique = self.unique;
+        schema.validate()?;
+        Ok(schema)
     }
 }
 
@@ -185,6 +776,134 @@ mod tests {
         assert_eq!(schema.get_column_index("unknown"), None);
     }
 
+    #[test]
+    fn test_schema_encode_round_trip() {
+        let schema = TableSchemaBuilder::new("orders")
+            .column("id", DataType::Int64, false)
+            .column("note", DataType::String, true)
+            .column("blob", DataType::Bytes, true)
+            .column("paid", DataType::Bool, false)
+            .build();
+
+        let decoded = TableSchema::decode(&schema.encode()).unwrap();
+        assert_eq!(decoded.name, "orders");
+        assert_eq!(decoded.columns, schema.columns);
+        assert_eq!(decoded.schema_version, 1);
+
+        let encoded = schema.encode();
+        assert!(TableSchema::decode(&encoded[..encoded.len() - 1]).is_err());
+        assert!(TableSchema::decode(&[99]).is_err());
+    }
+
+    #[test]
+    fn test_decimal_type_round_trip() {
+        let price = DataType::Decimal { precision: 12, scale: 2 };
+        let schema = TableSchemaBuilder::new("ledger")
+            .column("amount", price, false)
+            .column("rate", DataType::Float64, true)
+            .column("at", DataType::Timestamp, false)
+            .build();
+
+        let decoded = TableSchema::decode(&schema.encode()).unwrap();
+        assert_eq!(decoded.get_column("amount").unwrap().data_type, price);
+        assert_eq!(decoded.columns, schema.columns);
+        assert_eq!(price.to_string(), "DECIMAL(12, 2)");
+
+        let mut bad = Vec::new();
+        DataType::Decimal { precision: 4, scale: 5 }.encode_into(&mut bad);
+        assert!(DataType::decode_from(&mut SchemaReader::new(&bad)).is_err());
+    }
+
+    #[test]
+    fn test_default_type_checked_at_build() {
+        let err = TableSchemaBuilder::new("t")
+            .column_with_default("n", DataType::Int64, false, Datum::String("x".into()))
+            .try_build();
+        assert!(matches!(err, Err(CatalogError::InvalidDefault(_))));
+
+        let err = TableSchemaBuilder::new("t")
+            .column_with_default("n", DataType::Int64, false, Datum::Null)
+            .try_build();
+        assert!(matches!(err, Err(CatalogError::InvalidDefault(_))));
+
+        let schema = TableSchemaBuilder::new("t")
+            .column_with_default("ratio", DataType::Float64, false, Datum::Int(1))
+            .column_with_default(
+                "price",
+                DataType::Decimal { precision: 8, scale: 2 },
+                true,
+                Datum::Decimal { value: 5, scale: 1 },
+            )
+            .try_build()
+            .unwrap();
+        let decoded = TableSchema::decode(&schema.encode()).unwrap();
+        assert_eq!(decoded.columns, schema.columns);
+    }
+
+    #[test]
+    fn test_numeric_compatibility() {
+        let dec = DataType::Decimal { precision: 10, scale: 3 };
+        assert!(DataType::Int64.is_compatible(&DataType::Float64));
+        assert!(DataType::Int64.is_compatible(&dec));
+        assert!(dec.is_compatible(&DataType::Decimal { precision: 5, scale: 0 }));
+        assert!(!DataType::Timestamp.is_compatible(&DataType::Int64));
+        assert!(!DataType::String.is_compatible(&DataType::Float64));
+    }
+
+    #[test]
+    fn test_primary_key_validation() {
+        let schema = TableSchemaBuilder::new("accounts")
+            .column("region", DataType::String, false)
+            .column("id", DataType::Int64, false)
+            .column("email", DataType::String, true)
+            .primary_key(vec!["region", "id"])
+            .unique(vec!["email"])
+            .try_build()
+            .unwrap();
+        assert_eq!(
+            schema.primary_key(),
+            Some(&["region".to_string(), "id".to_string()][..])
+        );
+        assert_eq!(schema.unique_constraints(), &[vec!["email".to_string()]]);
+        assert!(schema.is_constrained("email"));
+
+        let nullable_pk = TableSchemaBuilder::new("t")
+            .column("id", DataType::Int64, true)
+            .primary_key(vec!["id"])
+            .try_build();
+        assert!(matches!(nullable_pk, Err(CatalogError::InvalidConstraint(_))));
+
+        let missing = TableSchemaBuilder::new("t")
+            .column("id", DataType::Int64, false)
+            .unique(vec!["nope"])
+            .try_build();
+        assert!(matches!(missing, Err(CatalogError::ColumnNotFound { .. })));
+
+        let repeated = TableSchemaBuilder::new("t")
+            .column("id", DataType::Int64, false)
+            .primary_key(vec!["id", "id"])
+            .try_build();
+        assert!(repeated.is_err());
+    }
+
+    #[test]
+    fn test_composite_key_encode_round_trip() {
+        let schema = TableSchemaBuilder::new("accounts")
+            .column("region", DataType::String, false)
+            .column("id", DataType::Int64, false)
+            .column("email", DataType::String, true)
+            .primary_key(vec!["region", "id"])
+            .unique(vec!["email"])
+            .build();
+
+        let decoded = TableSchema::decode(&schema.encode()).unwrap();
+        assert_eq!(decoded.primary_key(), schema.primary_key());
+        assert_eq!(decoded.unique_constraints(), schema.unique_constraints());
+
+        let plain = TableSchemaBuilder::new("plain").build();
+        assert_eq!(TableSchema::decode(&plain.encode()).unwrap().primary_key(), None);
+    }
+
     #[test]
     fn test_data_type_display() {
         assert_eq!(format!("{}", DataType::Int64), "INT64");
@@ -0,0 +1,128 @@
+use super::schema::{put_str, Datum, SchemaReader};
+use std::collections::HashMap;
+
+/// Per-column statistics gathered by an analyze pass.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ColumnStats {
+    /// Number of distinct non-NULL values.
+    pub distinct_count: u64,
+    pub null_count: u64,
+    pub min: Option<Datum>,
+    pub max: Option<Datum>,
+}
+
+/// Table statistics used by the planner. They are a snapshot taken when the
+/// table was last analyzed and are not kept up to date by writes.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TableStats {
+    pub row_count: u64,
+    pub total_bytes: u64,
+    pub column_stats: HashMap<String, ColumnStats>,
+}
+
+impl TableStats {
+    pub fn column(&self, name: &str) -> Option<&ColumnStats> {
+        self.column_stats.get(name)
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&self.row_count.to_le_bytes());
+        buf.extend_from_slice(&self.total_bytes.to_le_bytes());
+
+        let mut columns: Vec<_> = self.column_stats.iter().collect();
+        columns.sort_by(|a, b| a.0.cmp(b.0));
+        buf.extend_from_slice(&(columns.len() as u32).to_le_bytes());
+        for (name, stats) in columns {
+            put_str(&mut buf, name);
+            buf.extend_from_slice(&stats.distinct_count.to_le_bytes());
+            buf.extend_from_slice(&stats.null_count.to_le_bytes());
+            for bound in [&stats.min, &stats.max] {
+                match bound {
+                    Some(datum) => {
+                        buf.push(1);
+                        datum.encode_into(&mut buf);
+                    }
+                    None => buf.push(0),
+                }
+            }
+        }
+        buf
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Self, String> {
+        let mut reader = SchemaReader::new(data);
+        let row_count = reader.u64()?;
+        let total_bytes = reader.u64()?;
+
+        let count = reader.u32()? as usize;
+        let mut column_stats = HashMap::with_capacity(count.min(1024));
+        for _ in 0..count {
+            let name = reader.string()?;
+            let distinct_count = reader.u64()?;
+            let null_count = reader.u64()?;
+            let mut bound = || -> Result<Option<Datum>, String> {
+                match reader.u8()? {
+                    0 => Ok(None),
+                    _ => Datum::decode_from(&mut reader).map(Some),
+                }
+            };
+            let min = bound()?;
+            let max = bound()?;
+            column_stats.insert(
+                name,
+                ColumnStats {
+                    distinct_count,
+                    null_count,
+                    min,
+                    max,
+                },
+            );
+        }
+
+        if !reader.is_empty() {
+            return Err("trailing bytes after table stats".to_string());
+        }
+        Ok(TableStats {
+            row_count,
+            total_bytes,
+            column_stats,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_table_stats_round_trip() {
+        let mut stats = TableStats {
+            row_count: 4,
+            total_bytes: 96,
+            column_stats: HashMap::new(),
+        };
+        stats.column_stats.insert(
+            "id".to_string(),
+            ColumnStats {
+                distinct_count: 4,
+                null_count: 0,
+                min: Some(Datum::Int(1)),
+                max: Some(Datum::Int(4)),
+            },
+        );
+        stats.column_stats.insert(
+            "note".to_string(),
+            ColumnStats {
+                distinct_count: 0,
+                null_count: 4,
+                min: None,
+                max: None,
+            },
+        );
+
+        let decoded = TableStats::decode(&stats.encode()).unwrap();
+        assert_eq!(decoded, stats);
+        assert!(TableStats::decode(&stats.encode()[..20]).is_err());
+    }
+}
@@ -4,4 +4,4 @@ mod worker;
 
 pub use version::{LevelFiles, Version, VersionEdit, VersionSet};
 pub use picker::{CompactionPicker, CompactionTask};
-pub use worker::{CompactionRunner, CompactionWorker};
+pub use worker::{CompactionCounters, CompactionRunner, CompactionStats, CompactionWorker};
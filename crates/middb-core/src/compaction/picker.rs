@@ -60,6 +60,43 @@ impl CompactionPicker {
         None
     }
 
+    /// The files at `level` holding keys in `start..end`, an empty bound
+    /// being open, and those they overlap a level down. Any overlap in L0
+    /// takes all of it, as its files overlap each other.
+    pub fn pick_range(
+        &self,
+        version: &Version,
+        level: Level,
+        start: &[u8],
+        end: &[u8],
+    ) -> Option<CompactionTask> {
+        let files = version.level(level)?;
+        let in_range = files.files_in_range(start, end);
+        if in_range.is_empty() {
+            return None;
+        }
+        let input_files: Vec<_> = if level == 0 {
+            files.files.clone()
+        } else {
+            in_range.into_iter().cloned().collect()
+        };
+
+        let (smallest, largest) = Self::key_range(&input_files);
+        let target_files: Vec<_> = version
+            .level(level + 1)?
+            .find_overlapping(&smallest, &largest)
+            .into_iter()
+            .cloned()
+            .collect();
+
+        Some(CompactionTask {
+            level,
+            input_files,
+            output_level: level + 1,
+            target_files,
+        })
+    }
+
     fn pick_l0_compaction(&self, version: &Version) -> Option<CompactionTask> {
         let l0 = version.level(0)?;
 
@@ -203,6 +240,36 @@ mod tests {
         assert_eq!(task.target_files.len(), 2);
     }
 
+    #[test]
+    fn test_pick_range() {
+        let config = make_config();
+        let picker = CompactionPicker::new(&config);
+        let mut vs = VersionSet::new();
+
+        vs.add_file(0, make_file(1, b"a", b"c", 1000));
+        vs.add_file(0, make_file(2, b"x", b"z", 1000));
+        vs.add_file(1, make_file(10, b"a", b"f", 1000));
+        vs.add_file(1, make_file(11, b"g", b"m", 1000));
+        vs.add_file(1, make_file(12, b"n", b"z", 1000));
+        vs.add_file(2, make_file(20, b"h", b"p", 1000));
+
+        let version = vs.current();
+        // Below the trigger, but asked for; all of L0 goes.
+        let task = picker.pick_range(&version, 0, b"b", b"c").unwrap();
+        assert_eq!(task.input_files.len(), 2);
+        assert_eq!(task.target_files.len(), 3);
+
+        let task = picker.pick_range(&version, 1, b"h", b"o").unwrap();
+        let inputs: Vec<u64> = task.input_files.iter().map(|f| f.file_id).collect();
+        assert_eq!(inputs, [11, 12]);
+        assert_eq!(task.target_files[0].file_id, 20);
+
+        // The end is exclusive.
+        assert!(picker.pick_range(&version, 2, b"a", b"h").is_none());
+        assert_eq!(picker.pick_range(&version, 2, b"", b"").unwrap().input_files.len(), 1);
+        assert!(picker.pick_range(&version, 3, b"", b"").is_none());
+    }
+
     #[test]
     fn test_version_edit_from_task() {
         let task = CompactionTask {
@@ -50,6 +50,17 @@ impl LevelFiles {
             .collect()
     }
 
+    /// The files holding keys in `start..end`, an empty bound being open.
+    pub fn files_in_range(&self, start: &[u8], end: &[u8]) -> Vec<&SSTableMetadata> {
+        self.files
+            .iter()
+            .filter(|f| {
+                let below_end = end.is_empty() || f.smallest_key.as_slice() < end;
+                f.largest_key.as_slice() >= start && below_end
+            })
+            .collect()
+    }
+
     fn ranges_overlap(a_min: &[u8], a_max: &[u8], b_min: &[u8], b_max: &[u8]) -> bool {
         a_min <= b_max && b_min <= a_max
     }
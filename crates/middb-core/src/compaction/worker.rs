@@ -1,15 +1,61 @@
 use super::picker::{CompactionPicker, CompactionTask};
 use super::version::VersionSet;
+use crate::bloom::BloomFilter;
 use crate::config::Config;
-use crate::sstable::{MergeIterator, SSTableReader, SSTableWriter};
+use crate::sstable::{BloomCounters, MergeIterator, SSTableReader, SSTableWriter};
 use crate::Result;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::{Arc, RwLock};
 use std::thread::{self, JoinHandle};
 use std::time::Duration;
 
+/// What compactions have done since the database was opened.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompactionStats {
+    pub compactions: u64,
+    pub input_files: u64,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+}
+
+/// Running totals behind `CompactionStats`, shared by the runners that add
+/// to them.
+#[derive(Debug, Default)]
+pub struct CompactionCounters {
+    compactions: AtomicU64,
+    input_files: AtomicU64,
+    bytes_read: AtomicU64,
+    bytes_written: AtomicU64,
+}
+
+impl CompactionCounters {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn record(&self, task: &CompactionTask, bytes_written: u64) {
+        let (files, bytes_read) = task
+            .all_input_files()
+            .fold((0, 0), |(files, bytes), f| (files + 1, bytes + f.file_size));
+        self.compactions.fetch_add(1, Ordering::Relaxed);
+        self.input_files.fetch_add(files, Ordering::Relaxed);
+        self.bytes_read.fetch_add(bytes_read, Ordering::Relaxed);
+        self.bytes_written.fetch_add(bytes_written, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> CompactionStats {
+        CompactionStats {
+            compactions: self.compactions.load(Ordering::Relaxed),
+            input_files: self.input_files.load(Ordering::Relaxed),
+            bytes_read: self.bytes_read.load(Ordering::Relaxed),
+            bytes_written: self.bytes_written.load(Ordering::Relaxed),
+        }
+    }
+}
+
 pub struct CompactionWorker {
     handle: Option<JoinHandle<()>>,
     shutdown: Arc<AtomicBool>,
@@ -48,6 +94,7 @@ impl CompactionWorker {
         shutdown: Arc<AtomicBool>,
     ) {
         let picker = CompactionPicker::new(&config);
+        let retired_bloom = BloomCounters::new();
 
         while !shutdown.load(Ordering::SeqCst) {
             let task = {
@@ -57,7 +104,9 @@ impl CompactionWorker {
             };
 
             if let Some(task) = task {
-                if let Err(e) = Self::run_compaction(&task, &version_set, &readers, &config) {
+                let result =
+                    Self::run_compaction(&task, &version_set, &readers, &config, &retired_bloom);
+                if let Err(e) = result {
                     eprintln!("compaction failed: {}", e);
                 }
             }
@@ -66,12 +115,15 @@ impl CompactionWorker {
         }
     }
 
+    /// Run `task`, returning the size of the file it wrote. The input
+    /// readers' bloom counts go to `retired_bloom` as they're dropped.
     fn run_compaction(
         task: &CompactionTask,
         version_set: &Arc<RwLock<VersionSet>>,
         readers: &Arc<RwLock<HashMap<u64, SSTableReader>>>,
         config: &Config,
-    ) -> Result<()> {
+        retired_bloom: &BloomCounters,
+    ) -> Result<u64> {
         let file_id = {
             let vs = version_set.read().unwrap();
             vs.next_file_id()
@@ -79,31 +131,49 @@ impl CompactionWorker {
 
         let output_path = config.data_dir.join(format!("sst_{:08}.sst", file_id));
         
-        let iters = {
+        let (iters, bloom) = {
             let readers_guard = readers.read().unwrap();
             let mut iters = Vec::new();
 
-            for file in task.all_input_files() {
+            // Newest first, as the merge takes the first of equal keys: L0's
+            // later files before its earlier ones, then the level below.
+            for file in task.input_files.iter().rev().chain(&task.target_files) {
                 if let Some(reader) = readers_guard.get(&file.file_id) {
                     iters.push(reader.iter()?);
                 }
             }
-            iters
+            let bloom = Self::union_input_blooms(task, &readers_guard);
+            (iters, bloom)
         };
 
         let mut merge_iter = MergeIterator::new(iters);
         merge_iter.seek_to_first()?;
 
-        let mut writer = SSTableWriter::create(&output_path, config.block_size)?;
+        let expected_entries: u64 = task.all_input_files().map(|f| f.num_entries).sum();
+        let mut writer = SSTableWriter::create_with_expected_entries(
+            &output_path,
+            config.block_size,
+            config.bloom_bits_per_key,
+            expected_entries as usize,
+        )?;
+        if let Some(bloom) = bloom {
+            writer.use_bloom_filter(bloom);
+        }
 
+        let mut last_key: Option<Vec<u8>> = None;
         while merge_iter.valid() {
             if let (Some(key), Some(value)) = (merge_iter.key(), merge_iter.value()) {
-                writer.add(key, value)?;
+                // Older copies of a key follow its newest, and are dropped.
+                if last_key.as_deref() != Some(key) {
+                    writer.add(key, value)?;
+                    last_key = Some(key.to_vec());
+                }
             }
             merge_iter.next()?;
         }
 
         let metadata = writer.finish(file_id, task.output_level)?;
+        let output_size = metadata.file_size;
 
         let new_reader = SSTableReader::open(&output_path)?;
         {
@@ -120,7 +190,9 @@ impl CompactionWorker {
         {
             let mut readers_guard = readers.write().unwrap();
             for file in task.all_input_files() {
-                readers_guard.remove(&file.file_id);
+                if let Some(reader) = readers_guard.remove(&file.file_id) {
+                    reader.retire_into(retired_bloom);
+                }
             }
         }
 
@@ -129,7 +201,27 @@ impl CompactionWorker {
             let _ = fs::remove_file(path);
         }
 
-        Ok(())
+        Ok(output_size)
+    }
+
+    /// The output's keys are a subset of the inputs', so the union of the
+    /// input filters covers them. Used in place of rebuilding the filter
+    /// when every input has one, their shapes combine, and the result is no
+    /// more than half full, which is where a filter sized for its keys
+    /// ends up.
+    fn union_input_blooms(
+        task: &CompactionTask,
+        readers: &HashMap<u64, SSTableReader>,
+    ) -> Option<BloomFilter> {
+        let mut union: Option<BloomFilter> = None;
+        for file in task.all_input_files() {
+            let bloom = readers.get(&file.file_id)?.bloom_filter()?;
+            union = Some(match union {
+                None => bloom.clone(),
+                Some(acc) => acc.try_union_resize(bloom).ok()?,
+            });
+        }
+        union.filter(|bloom| bloom.fill_ratio() <= 0.5)
     }
 }
 
@@ -144,6 +236,8 @@ pub struct CompactionRunner {
     readers: Arc<RwLock<HashMap<u64, SSTableReader>>>,
     config: Config,
     picker: CompactionPicker,
+    counters: Arc<CompactionCounters>,
+    retired_bloom: Arc<BloomCounters>,
 }
 
 impl CompactionRunner {
@@ -158,9 +252,24 @@ impl CompactionRunner {
             readers,
             config,
             picker,
+            counters: Arc::new(CompactionCounters::new()),
+            retired_bloom: Arc::new(BloomCounters::new()),
         }
     }
 
+    /// Add the compactions this runner does to `counters`.
+    pub fn with_counters(mut self, counters: Arc<CompactionCounters>) -> Self {
+        self.counters = counters;
+        self
+    }
+
+    /// Add the bloom counts of the readers this runner's compactions drop
+    /// to `retired`.
+    pub fn with_retired_bloom(mut self, retired: Arc<BloomCounters>) -> Self {
+        self.retired_bloom = retired;
+        self
+    }
+
     pub fn maybe_compact(&self) -> Result<bool> {
         let task = {
             let vs = self.version_set.read().unwrap();
@@ -170,17 +279,26 @@ impl CompactionRunner {
 
         match task {
             Some(task) => {
-                CompactionWorker::run_compaction(
-                    &task,
-                    &self.version_set,
-                    &self.readers,
-                    &self.config,
-                )?;
+                self.run(&task)?;
                 Ok(true)
             }
             None => Ok(false),
         }
     }
+
+    /// Run `task`, whatever the picker would choose, returning the size of
+    /// the file it wrote.
+    pub fn run(&self, task: &CompactionTask) -> Result<u64> {
+        let written = CompactionWorker::run_compaction(
+            task,
+            &self.version_set,
+            &self.readers,
+            &self.config,
+            &self.retired_bloom,
+        )?;
+        self.counters.record(task, written);
+        Ok(written)
+    }
 }
 
 #[cfg(test)]
@@ -224,17 +342,120 @@ mod tests {
         let version_set = Arc::new(RwLock::new(vs));
         let readers = Arc::new(RwLock::new(readers));
 
+        let counters = Arc::new(CompactionCounters::new());
         let runner = CompactionRunner::new(
             Arc::clone(&version_set),
             Arc::clone(&readers),
             config,
-        );
+        )
+        .with_counters(Arc::clone(&counters));
 
         let compacted = runner.maybe_compact().unwrap();
         assert!(compacted);
 
         let vs = version_set.read().unwrap();
         assert_eq!(vs.l0_file_count(), 0);
-        assert_eq!(vs.current().level(1).unwrap().file_count(), 1);
+        let l1 = vs.current().level(1).unwrap().clone();
+        assert_eq!(l1.file_count(), 1);
+
+        let stats = counters.snapshot();
+        assert_eq!(stats.compactions, 1);
+        assert_eq!(stats.input_files, 2);
+        assert!(stats.bytes_read > 0);
+        assert_eq!(stats.bytes_written, l1.total_size());
+    }
+
+    /// Compacts two L0 tables whose filters are sized for `bloom_keys`
+    /// keys each. Returns the union of their filters and the output table.
+    fn compact_two(
+        first: &[(Vec<u8>, Vec<u8>)],
+        second: &[(Vec<u8>, Vec<u8>)],
+        bloom_keys: usize,
+    ) -> (TempDir, BloomFilter, SSTableReader) {
+        let temp_dir = TempDir::new().unwrap();
+        let mut config = Config::new(temp_dir.path());
+        config.level0_file_num_compaction_trigger = 2;
+
+        let mut vs = VersionSet::new();
+        let mut readers = HashMap::new();
+
+        // Take ids 1 and 2 so the output doesn't reuse one.
+        for (id, data) in [(1, first), (2, second)] {
+            vs.next_file_id();
+            let path = temp_dir.path().join(format!("sst_{:08}.sst", id));
+            let mut writer = SSTableWriter::create(&path, 4096).unwrap();
+            let mut bloom = BloomFilter::new(bloom_keys, 10);
+            for (k, v) in data {
+                writer.add(k, v).unwrap();
+                bloom.insert(k);
+            }
+            writer.use_bloom_filter(bloom);
+            vs.add_file(0, writer.finish(id, 0).unwrap());
+            readers.insert(id, SSTableReader::open(&path).unwrap());
+        }
+
+        let union = readers[&1].bloom_filter().unwrap()
+            .union(readers[&2].bloom_filter().unwrap())
+            .unwrap();
+
+        let version_set = Arc::new(RwLock::new(vs));
+        let readers = Arc::new(RwLock::new(readers));
+        let runner = CompactionRunner::new(Arc::clone(&version_set), Arc::clone(&readers), config);
+        assert!(runner.maybe_compact().unwrap());
+
+        let mut readers = readers.write().unwrap();
+        assert_eq!(readers.len(), 1);
+        let output = readers.drain().next().unwrap().1;
+        (temp_dir, union, output)
+    }
+
+    fn keys(prefix: &str) -> Vec<(Vec<u8>, Vec<u8>)> {
+        (0..50).map(|i| (format!("{}{:04}", prefix, i).into_bytes(), b"v".to_vec())).collect()
+    }
+
+    #[test]
+    fn test_compaction_keeps_newest_value() {
+        let first = [(b"a".to_vec(), b"old".to_vec()), (b"b".to_vec(), b"only".to_vec())];
+        let second = [(b"a".to_vec(), b"new".to_vec()), (b"c".to_vec(), b"only".to_vec())];
+        let (_dir, _, output) = compact_two(&first, &second, 10);
+        assert_eq!(output.get(b"a").unwrap(), Some(b"new".to_vec()));
+        assert_eq!(output.get(b"b").unwrap(), Some(b"only".to_vec()));
+        assert_eq!(output.verify().unwrap(), []);
+
+        let mut iter = output.iter().unwrap();
+        let mut count = 0;
+        while iter.valid() {
+            count += 1;
+            iter.next().unwrap();
+        }
+        assert_eq!(count, 3);
+    }
+
+    #[test]
+    fn test_compaction_unions_input_blooms() {
+        // Filters with room to spare: their union stays under half full and
+        // is written as is.
+        let (_dir, union, output) = compact_two(&keys("a"), &keys("b"), 200);
+        assert!(union.fill_ratio() <= 0.5);
+        let bloom = output.bloom_filter().unwrap();
+        assert_eq!(bloom.num_bits(), 2000);
+        assert_eq!(bloom.as_bytes(), union.as_bytes());
+        for (key, value) in keys("a").into_iter().chain(keys("b")) {
+            assert_eq!(output.get(&key).unwrap(), Some(value));
+        }
+    }
+
+    #[test]
+    fn test_compaction_rebuilds_overfull_bloom_union() {
+        // Filters sized for their own keys: the union would be over half
+        // full, so the output's filter is built from its keys instead.
+        let (_dir, union, output) = compact_two(&keys("a"), &keys("b"), 50);
+        assert!(union.fill_ratio() > 0.5);
+        let bloom = output.bloom_filter().unwrap();
+        assert_eq!(bloom.num_bits(), 1000);
+        assert!(bloom.fill_ratio() <= 0.5);
+        for (key, value) in keys("a").into_iter().chain(keys("b")) {
+            assert_eq!(output.get(&key).unwrap(), Some(value));
+        }
     }
 }
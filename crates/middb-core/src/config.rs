@@ -1,6 +1,10 @@
+use crate::transaction::manager::{DEFAULT_GC_THRESHOLD, DEFAULT_VERSION_CACHE_BYTES};
+use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
+use std::time::Duration;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum CompactionStyle {
     Leveled,
     Universal,
@@ -12,7 +16,9 @@ impl Default for CompactionStyle {
     }
 }
 
-#[derive(Debug, Clone)]
+/// Fields left out when deserializing take their defaults.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Config {
     pub memtable_size: usize,
     pub wal_dir: PathBuf,
@@ -25,6 +31,19 @@ pub struct Config {
     pub level0_file_num_compaction_trigger: usize,
     pub max_bytes_for_level_base: u64,
     pub max_bytes_for_level_multiplier: u64,
+    /// Transactions still open this long after they began are aborted.
+    /// Serialized as seconds.
+    #[serde(with = "secs")]
+    pub txn_timeout: Duration,
+    /// Committed versions retained for snapshot reads before a commit
+    /// triggers garbage collection.
+    pub txn_gc_threshold: usize,
+    /// Cap on key and value bytes held by those versions.
+    pub txn_version_cache_bytes: u64,
+    /// Sync each write's WAL record to disk before the write returns. Off,
+    /// records are only handed to the OS, so a machine crash can lose the
+    /// latest writes though a process crash can't.
+    pub sync_writes: bool,
 }
 
 impl Default for Config {
@@ -41,6 +60,10 @@ impl Default for Config {
             level0_file_num_compaction_trigger: 4,
             max_bytes_for_level_base: 10 * 1024 * 1024,
             max_bytes_for_level_multiplier: 10,
+            txn_timeout: Duration::from_secs(300),
+            txn_gc_threshold: DEFAULT_GC_THRESHOLD,
+            txn_version_cache_bytes: DEFAULT_VERSION_CACHE_BYTES,
+            sync_writes: true,
         }
     }
 }
@@ -74,10 +97,29 @@ impl Config {
             return Err("level0_file_num_compaction_trigger must be at least 2".to_string());
         }
         
+        if self.txn_timeout.is_zero() {
+            return Err("txn_timeout must be greater than 0".to_string());
+        }
+        
         Ok(())
     }
 }
 
+/// A `Duration` as a number of seconds, whole or not.
+mod secs {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(duration.as_secs_f64())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        let secs = f64::deserialize(deserializer)?;
+        Duration::try_from_secs_f64(secs).map_err(serde::de::Error::custom)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
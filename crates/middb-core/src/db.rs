@@ -1,16 +1,74 @@
-use crate::catalog::{Catalog, CatalogError, TableSchema};
-use crate::compaction::{CompactionRunner, VersionSet};
+use crate::batch::WriteBatch;
+use crate::catalog::{
+    catalog_key, index_key, namespace_key, split_qualified, stats_key, AlterOp, Catalog,
+    CatalogError, IndexDef, TableSchema, TableStats, CATALOG_KEY_PREFIX, DEFAULT_NAMESPACE,
+    INDEX_KEY_PREFIX, NAMESPACE_KEY_PREFIX, STATS_KEY_PREFIX,
+};
+use crate::compaction::{
+    CompactionCounters, CompactionPicker, CompactionRunner, CompactionStats, CompactionTask,
+    VersionSet,
+};
 use crate::config::Config;
-use crate::memtable::MemTable;
-use crate::sstable::SSTableReader;
-use crate::transaction::{TransactionManager, TxnError, TxnId, WriteOp};
-use crate::wal::{WalEntry, WalReader, WalWriter};
-use crate::{Error, Key, Result, SequenceNumber, Value};
-use std::collections::HashMap;
-use std::fs;
+use crate::memtable::{MemTable, ValueEntry};
+use crate::sstable::{BloomCounters, SSTableMetadata, SSTableReader};
+use crate::transaction::{
+    AppendOperator, MergeOperator, TransactionManager, TxnError, TxnId, TxnInfo, TxnMetrics,
+    TxnOptions, WriteOp,
+};
+use crate::transaction::Version;
+use crate::wal::{EntryType, WalBatch, WalEntry, WalReader, WalWriter};
+use crate::{Error, Key, Level, Result, SequenceNumber, Value};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
 use std::path::PathBuf;
 use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex, RwLock};
+
+/// One page of a range scan: the live pairs read, and the key the next
+/// page starts from if the range may hold more.
+pub type ScanPage = (Vec<(Key, Value)>, Option<Key>);
+
+/// A write as it's applied: the key and its new value, or `None` for a
+/// deletion.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WriteEvent {
+    pub key: Key,
+    pub value: Option<Value>,
+}
+
+/// Called with each applied write, or a committed transaction's writes
+/// together. It runs on the writer's thread with the memtable locked, so it
+/// should hand the events off rather than do work of its own.
+pub type WriteListener = Box<dyn Fn(&[WriteEvent]) + Send + Sync>;
+
+/// Called with each record as it's logged to the WAL, in sequence order,
+/// with the WAL locked; like a `WriteListener`, it should hand the record
+/// off.
+pub type WalListener = Box<dyn Fn(&WalEntry) + Send + Sync>;
+
+/// What a compare-and-swap found: whether the value was as expected and
+/// so replaced, and the key's value now.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CasOutcome {
+    pub succeeded: bool,
+    pub current: Option<Value>,
+}
+
+/// One compaction `compact_range` ran: the level merged down, and how much
+/// it read and wrote.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompactionPass {
+    pub level: Level,
+    pub output_level: Level,
+    /// Files merged, from both levels.
+    pub input_files: usize,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+}
+
+/// How many of the most conflicted keys `stats` reports.
+const STATS_TOP_CONFLICT_KEYS: usize = 10;
 
 pub struct Database {
     config: Config,
@@ -21,26 +79,53 @@ pub struct Database {
     catalog: Arc<RwLock<Catalog>>,
     sequence: Arc<AtomicU64>,
     txn_manager: Arc<TransactionManager>,
+    /// Serializes commits so write sets reach the WAL and memtable in
+    /// commit-version order.
+    commit_lock: Mutex<()>,
+    write_listeners: RwLock<Vec<WriteListener>>,
+    wal_listeners: RwLock<Vec<WalListener>>,
+    compaction_counters: Arc<CompactionCounters>,
+    /// Bloom counts of the SSTable readers compactions have dropped, so
+    /// the totals `stats` reports never go down.
+    retired_bloom: Arc<BloomCounters>,
+    /// Keeps compactions from picking the same files at once.
+    compaction_lock: Mutex<()>,
+    /// The `LOCK` file, locked for as long as the database is open so no
+    /// other process opens it too.
+    _lock_file: File,
 }
 
 impl Database {
     pub fn open(config: Config) -> Result<Self> {
+        Self::open_with_merge_operator(config, Arc::new(AppendOperator))
+    }
+
+    /// Open with `operator` folding the operands of transactional merges.
+    pub fn open_with_merge_operator(
+        config: Config,
+        operator: Arc<dyn MergeOperator>,
+    ) -> Result<Self> {
         config.validate().map_err(|e| Error::InvalidConfig(e))?;
 
         fs::create_dir_all(&config.data_dir)?;
         fs::create_dir_all(&config.wal_dir)?;
+        let lock_file = Self::lock_data_dir(&config)?;
 
         let wal_path = config.wal_dir.join("wal.log");
-        let wal = WalWriter::create(&wal_path)?;
+        let mut memtable = MemTable::with_threshold(config.memtable_size);
+        let (sequence, last_commit) = Self::recover_from_wal(&wal_path, &mut memtable)?;
 
-        let memtable = MemTable::with_threshold(config.memtable_size);
+        let wal = WalWriter::create(&wal_path)?;
 
         let version_set = VersionSet::new();
         let sstable_readers = HashMap::new();
+        let txn_manager = TransactionManager::with_timeout(config.txn_timeout)
+            .with_gc_threshold(config.txn_gc_threshold)
+            .with_version_cache_bytes(config.txn_version_cache_bytes)
+            .with_merge_operator(operator);
+        txn_manager.recover_version(last_commit);
 
-        let sequence = Self::recover_from_wal(&wal_path, &memtable)?;
-
-        Ok(Database {
+        let db = Database {
             config,
             memtable: Arc::new(RwLock::new(memtable)),
             wal: Arc::new(RwLock::new(wal)),
@@ -48,76 +133,651 @@ impl Database {
             sstable_readers: Arc::new(RwLock::new(sstable_readers)),
             catalog: Arc::new(RwLock::new(Catalog::new())),
             sequence: Arc::new(AtomicU64::new(sequence)),
-            txn_manager: Arc::new(TransactionManager::new()),
-        })
+            txn_manager: Arc::new(txn_manager),
+            commit_lock: Mutex::new(()),
+            write_listeners: RwLock::new(Vec::new()),
+            wal_listeners: RwLock::new(Vec::new()),
+            compaction_counters: Arc::new(CompactionCounters::new()),
+            retired_bloom: Arc::new(BloomCounters::new()),
+            compaction_lock: Mutex::new(()),
+            _lock_file: lock_file,
+        };
+
+        db.load_catalog()?;
+
+        if db.memtable.read().unwrap().should_flush() {
+            db.flush_memtable(false)?;
+        }
+
+        Ok(db)
+    }
+
+    /// Open and lock `data_dir/LOCK`, failing with `Error::Locked` if
+    /// another process holds it.
+    pub(crate) fn lock_data_dir(config: &Config) -> Result<File> {
+        let path = config.data_dir.join("LOCK");
+        let file = fs::OpenOptions::new().create(true).truncate(false).write(true).open(&path)?;
+        match file.try_lock() {
+            Ok(()) => Ok(file),
+            Err(fs::TryLockError::WouldBlock) => {
+                Err(Error::Locked(format!("{} is held by another process", path.display())))
+            }
+            Err(fs::TryLockError::Error(e)) => Err(e.into()),
+        }
+    }
+
+    /// Have `listener` told of every write from now on, in the order they
+    /// are applied. Writes replayed from the WAL at open aren't reported.
+    pub fn on_write(&self, listener: WriteListener) {
+        self.write_listeners.write().unwrap().push(listener);
+    }
+
+    /// Have `listener` told of every WAL record logged from now on.
+    pub fn on_wal_append(&self, listener: WalListener) {
+        self.wal_listeners.write().unwrap().push(listener);
+    }
+
+    /// Log the record `build` makes with the next sequence number, synced.
+    /// The number is taken with the WAL locked, so records are logged in
+    /// sequence order.
+    fn log(&self, build: impl FnOnce(SequenceNumber) -> WalEntry) -> Result<()> {
+        let mut wal = self.wal.write().unwrap();
+        let entry = build(self.sequence.fetch_add(1, Ordering::SeqCst));
+        self.append_to_wal(&mut wal, &entry)
+    }
+
+    fn append_to_wal(&self, wal: &mut WalWriter, entry: &WalEntry) -> Result<()> {
+        wal.append(entry)?;
+        if self.config.sync_writes {
+            wal.sync()?;
+        } else {
+            wal.flush()?;
+        }
+        for listener in self.wal_listeners.read().unwrap().iter() {
+            listener(entry);
+        }
+        Ok(())
+    }
+
+    /// The WAL records from sequence `from` on, in order, for a replica
+    /// catching up. Fails with `Error::SnapshotRequired` if the WAL no
+    /// longer starts early enough, or `from` is past anything logged: the
+    /// replica can't be brought level from here.
+    pub fn wal_since(&self, from: SequenceNumber) -> Result<Vec<WalEntry>> {
+        // Held so no record is half-written while the file is read.
+        let wal = self.wal.read().unwrap();
+        let next = self.sequence.load(Ordering::SeqCst);
+        let mut entries = WalReader::open(wal.path())?.read_all()?;
+        let oldest = entries.first().map_or(next, |e| e.sequence_number);
+        if from < oldest || from > next {
+            return Err(Error::SnapshotRequired(format!(
+                "sequence {} is not in the WAL, which holds {} to {}",
+                from, oldest, next
+            )));
+        }
+        entries.retain(|e| e.sequence_number >= from);
+        Ok(entries)
+    }
+
+    /// Apply a WAL record shipped from a leader, logging it under the
+    /// leader's sequence number. Records already applied are skipped, so a
+    /// replica resumes by asking for its next sequence number.
+    pub fn apply_replicated(&self, entry: WalEntry) -> Result<()> {
+        {
+            let mut wal = self.wal.write().unwrap();
+            if entry.sequence_number < self.sequence.load(Ordering::SeqCst) {
+                return Ok(());
+            }
+            self.append_to_wal(&mut wal, &entry)?;
+            self.sequence.store(entry.sequence_number + 1, Ordering::SeqCst);
+        }
+
+        let (ops, commit_version) = Self::entry_ops(entry)?;
+        if let Some(version) = commit_version {
+            self.txn_manager.recover_version(version);
+        }
+        let mut memtable = self.memtable.write().unwrap();
+        let events = self.write_events(|| {
+            ops.iter()
+                .map(|(key, value)| WriteEvent { key: key.clone(), value: value.clone() })
+                .collect()
+        });
+        Self::apply_ops(&mut memtable, ops)?;
+        self.notify_write(events);
+        if memtable.should_flush() {
+            drop(memtable);
+            self.flush_memtable(false)?;
+        }
+        Ok(())
+    }
+
+    /// The sequence number the next write will be logged under.
+    pub fn next_sequence(&self) -> SequenceNumber {
+        self.sequence.load(Ordering::SeqCst)
+    }
+
+    /// The events for a write about to be applied, built only if anyone is
+    /// listening. Built before, since applying the write consumes it.
+    fn write_events(&self, build: impl FnOnce() -> Vec<WriteEvent>) -> Option<Vec<WriteEvent>> {
+        match self.write_listeners.read().unwrap().is_empty() {
+            true => None,
+            false => Some(build()),
+        }
+    }
+
+    /// Tell the listeners of a write once it's applied.
+    fn notify_write(&self, events: Option<Vec<WriteEvent>>) {
+        if let Some(events) = events {
+            for listener in self.write_listeners.read().unwrap().iter() {
+                listener(&events);
+            }
+        }
     }
 
     pub fn begin_txn(&self) -> TxnId {
         self.txn_manager.begin()
     }
 
+    /// Begin a transaction that can only read. It never conflicts and its
+    /// commit doesn't wait on writers.
+    pub fn begin_read_only_txn(&self) -> TxnId {
+        self.txn_manager.begin_read_only()
+    }
+
+    pub fn begin_txn_with_options(&self, options: TxnOptions) -> TxnId {
+        self.txn_manager.begin_with_options(options)
+    }
+
     pub fn get_txn(&self, txn_id: TxnId, key: &Key) -> Result<Option<Value>> {
-        if let Ok(Some(op)) = self.txn_manager.get_local(txn_id, key) {
-            return Ok(match op {
-                WriteOp::Put(v) => Some(v.clone()),
-                WriteOp::Delete => None,
-            });
-        }
+        let local = self.txn_manager.get_local(txn_id, key).ok().flatten();
+        let merge = match local {
+            Some(WriteOp::Merge(_)) => local,
+            Some(op) => return Ok(op.apply(key, None, self.txn_manager.merge_operator())),
+            None => None,
+        };
 
         self.txn_manager.record_read(txn_id, key.clone())
-            .map_err(|_| Error::TransactionConflict)?;
+            .map_err(Self::txn_op_error)?;
+
+        let start_version = self.txn_manager.get_start_version(txn_id)
+            .map_err(|e| Error::Internal(e.to_string()))?;
+        // Read the store first; see `TransactionManager::visible_or`.
+        let current = self.get(key)?;
+        let visible = self.txn_manager.visible_or(key, start_version, current)
+            .map_err(Self::txn_op_error)?;
+        Ok(match merge {
+            Some(op) => op.apply(key, visible.as_deref(), self.txn_manager.merge_operator()),
+            None => visible,
+        })
+    }
 
-        if let Some(start_version) = self.txn_manager.get_start_version(txn_id).ok() {
-            if let Some(value) = self.txn_manager.get_visible_value(key, start_version) {
-                return Ok(Some(value));
+    /// `scan_prefix` as seen by a transaction: its snapshot plus its own
+    /// writes. The prefix is recorded as a range read, so a concurrent
+    /// insert under it makes the transaction conflict.
+    pub fn scan_prefix_txn(&self, txn_id: TxnId, prefix: &[u8]) -> Result<Vec<(Key, Value)>> {
+        self.scan_range_txn(txn_id, prefix, &prefix_end(prefix))
+    }
+
+    /// `scan_range` as seen by a transaction, recorded as a range read as
+    /// in `scan_prefix_txn`.
+    pub fn scan_range_txn(
+        &self,
+        txn_id: TxnId,
+        start: &[u8],
+        end: &[u8],
+    ) -> Result<Vec<(Key, Value)>> {
+        self.txn_manager.record_range_read(txn_id, start.to_vec(), end.to_vec())
+            .map_err(Self::txn_op_error)?;
+
+        let start_version = self.txn_manager.get_start_version(txn_id)
+            .map_err(Self::txn_op_error)?;
+        // As in `get_txn`, read the store before the retained versions.
+        let mut merged: BTreeMap<Key, Value> = self.scan_range(start, end)?.into_iter().collect();
+        let visible = self.txn_manager.visible_range(start, end, start_version)
+            .map_err(Self::txn_op_error)?;
+        for (key, value) in visible {
+            match value {
+                Some(value) => merged.insert(key, value),
+                None => merged.remove(&key),
+            };
+        }
+        for (key, op) in self.txn_manager.local_range(txn_id, start, end)
+            .map_err(Self::txn_op_error)?
+        {
+            if let WriteOp::DeleteRange(range_end) = &op {
+                merged.retain(|k, _| k < &key || (!range_end.is_empty() && k >= range_end));
+                continue;
             }
+            let existing = merged.get(&key).map(|v| v.as_slice());
+            match op.apply(&key, existing, self.txn_manager.merge_operator()) {
+                Some(value) => merged.insert(key, value),
+                None => merged.remove(&key),
+            };
         }
 
-        self.get(key)
+        Ok(merged.into_iter().collect())
     }
 
     pub fn put_txn(&self, txn_id: TxnId, key: Key, value: Value) -> Result<()> {
         self.txn_manager.record_write(txn_id, key, Some(value))
-            .map_err(|_| Error::TransactionConflict)
+            .map_err(Self::txn_op_error)
     }
 
     pub fn delete_txn(&self, txn_id: TxnId, key: Key) -> Result<()> {
         self.txn_manager.record_write(txn_id, key, None)
-            .map_err(|_| Error::TransactionConflict)
+            .map_err(Self::txn_op_error)
+    }
+
+    /// Buffer a merge operand on `key`, applied by the database's merge
+    /// operator when the key is read or the transaction commits.
+    pub fn merge_txn(&self, txn_id: TxnId, key: Key, operand: Value) -> Result<()> {
+        self.txn_manager.record_op(txn_id, key, WriteOp::Merge(operand))
+            .map_err(Self::txn_op_error)
+    }
+
+    /// Delete every key in `[start, end)` when the transaction commits. An
+    /// empty `end` has no upper bound.
+    pub fn delete_range_txn(&self, txn_id: TxnId, start: Key, end: Key) -> Result<()> {
+        self.txn_manager.record_op(txn_id, start, WriteOp::DeleteRange(end))
+            .map_err(Self::txn_op_error)
     }
 
+    fn txn_op_error(e: TxnError) -> Error {
+        match e {
+            TxnError::ReadOnly(_) => Error::InvalidArgument(e.to_string()),
+            TxnError::TimedOut(_) => Error::TransactionTimedOut,
+            TxnError::SnapshotTooOld(_) => Error::SnapshotTooOld,
+            _ => Error::TransactionConflict,
+        }
+    }
+
+    /// Commit a transaction and apply its write set to the WAL and memtable
+    /// as one batch, so the data is durable and visible to plain `get`.
     pub fn commit_txn(&self, txn_id: TxnId) -> Result<()> {
-        let (_version, writes) = self.txn_manager.commit(txn_id)
-            .map_err(|e| match e {
-                TxnError::Conflict(_) => Error::TransactionConflict,
-                _ => Error::Internal(e.to_string()),
-            })?;
+        if self.txn_manager.is_read_only(txn_id) {
+            return self.txn_manager.commit(txn_id).map(|_| ()).map_err(Self::txn_error);
+        }
+
+        let _guard = self.commit_lock.lock().unwrap();
+
+        // With commits serialized, nothing else changes these keys through a
+        // transaction until the batch below is applied.
+        let mut base = HashMap::new();
+        for key in self.txn_manager.write_keys(txn_id).map_err(Self::txn_error)? {
+            let value = self.get(&key)?;
+            base.insert(key, value);
+        }
+        // And what the store holds in the ranges it deletes.
+        let mut live = BTreeMap::new();
+        for (start, end) in self.txn_manager.delete_ranges(txn_id).map_err(Self::txn_error)? {
+            live.extend(self.scan_range(&start, &end)?);
+        }
+
+        let (version, writes) = self
+            .txn_manager
+            .commit_with_base(
+                txn_id,
+                |key| base.get(key).cloned().flatten(),
+                |start, end| {
+                    live.iter()
+                        .filter(|(k, _)| *k >= start && (end.is_empty() || *k < end))
+                        .map(|(k, v)| (k.clone(), v.clone()))
+                        .collect()
+                },
+            )
+            .map_err(Self::txn_error)?;
+
+        self.apply_batch(version, writes)
+    }
 
-        for (key, op) in writes {
-            match op {
-                WriteOp::Put(value) => self.put(key, value)?,
-                WriteOp::Delete => self.delete(key)?,
+    fn txn_error(e: TxnError) -> Error {
+        match e {
+            TxnError::Conflict(_) | TxnError::SerializationFailure(_) => {
+                Error::TransactionConflict
             }
+            TxnError::TimedOut(_) => Error::TransactionTimedOut,
+            TxnError::SnapshotTooOld(_) => Error::SnapshotTooOld,
+            _ => Error::Internal(e.to_string()),
+        }
+    }
+
+    /// Log a commit's write set as one WAL record and sync it, then apply it
+    /// to the memtable under one lock so readers never observe part of it.
+    /// A crash before the sync loses the whole commit; one after it replays
+    /// the whole commit.
+    fn apply_batch(&self, commit_version: Version, writes: Vec<(Key, WriteOp)>) -> Result<()> {
+        if writes.is_empty() {
+            return Ok(());
+        }
+        let batch = WalBatch {
+            commit_version,
+            ops: writes
+                .into_iter()
+                .map(|(key, op)| match op {
+                    WriteOp::Put(value) => (key, Some(value)),
+                    WriteOp::Delete => (key, None),
+                    WriteOp::Merge(_) | WriteOp::DeleteRange(_) => {
+                        unreachable!("commit resolves merges and range deletes")
+                    }
+                })
+                .collect(),
+        };
+        self.log_batch(batch)
+    }
+
+    /// Make a batch's writes at once: see `WriteBatch`. An empty batch
+    /// does nothing.
+    pub fn write(&self, batch: WriteBatch) -> Result<()> {
+        if batch.is_empty() {
+            return Ok(());
+        }
+        let ops = batch.into_ops();
+        let _guard = self.commit_lock.lock().unwrap();
+        let writes: Vec<_> = ops.iter().map(|(key, value)| (key, value.as_ref())).collect();
+        self.version_untracked(&writes)?;
+        // Not a transaction's, so it claims no commit version.
+        self.log_batch(WalBatch { commit_version: 0, ops })
+    }
+
+    /// Before plain writes of `ops` reach the store, with `commit_lock`
+    /// held: if a transaction is open, keep them as a version its snapshot
+    /// doesn't see, and one its own writes to those keys conflict with.
+    /// Otherwise drop any versions they'd outdate. A transaction that
+    /// begins between this and the write reads the store, and sees it.
+    fn version_untracked(&self, ops: &[(&Key, Option<&Value>)]) -> Result<()> {
+        if self.txn_manager.oldest_active_version().is_none() {
+            self.txn_manager.forget(ops.iter().map(|&(key, _)| key));
+            return Ok(());
+        }
+
+        // The last write to a key in a batch is the one that sticks.
+        let mut writes = BTreeMap::new();
+        for &(key, value) in ops {
+            let op = match value {
+                Some(value) => WriteOp::Put(value.clone()),
+                None => WriteOp::Delete,
+            };
+            writes.insert(key.clone(), op);
+        }
+        let mut base = HashMap::new();
+        for key in writes.keys() {
+            base.insert(key.clone(), self.get(key)?);
+        }
+        let writes: Vec<_> = writes.into_iter().collect();
+        self.txn_manager.record_untracked(&writes, |key| base.get(key).cloned().flatten());
+        Ok(())
+    }
+
+    /// Drop the versions kept for snapshot reads that no open transaction
+    /// can read any more, such as once the last snapshot has been released,
+    /// instead of waiting for enough commits to collect them.
+    pub fn gc_versions(&self) {
+        // A commit between claiming its version and applying its writes
+        // still needs them.
+        let _guard = self.commit_lock.lock().unwrap();
+        self.txn_manager.gc(self.txn_manager.current_version());
+    }
+
+    fn log_batch(&self, batch: WalBatch) -> Result<()> {
+        self.log(|seq| WalEntry::batch(seq, &batch))?;
+
+        let mut memtable = self.memtable.write().unwrap();
+        let events = self.write_events(|| {
+            batch.ops.iter()
+                .map(|(key, value)| WriteEvent { key: key.clone(), value: value.clone() })
+                .collect()
+        });
+        Self::apply_ops(&mut memtable, batch.ops)?;
+        self.notify_write(events);
+        if memtable.should_flush() {
+            drop(memtable);
+            self.flush_memtable(false)?;
         }
 
         Ok(())
     }
 
+    /// Open transactions, for an admin view.
+    pub fn list_active_txns(&self) -> Vec<TxnInfo> {
+        self.txn_manager.list_active()
+    }
+
     pub fn abort_txn(&self, txn_id: TxnId) -> Result<()> {
         self.txn_manager.abort(txn_id)
-            .map_err(|e| Error::Internal(e.to_string()))
+            .map_err(Self::txn_error)
+    }
+
+    /// Create a namespace and persist it under `NAMESPACE_KEY_PREFIX`, so
+    /// it is reloaded by the next `open`.
+    pub fn create_namespace(&self, name: &str) -> std::result::Result<(), CatalogError> {
+        let mut catalog = self.catalog.write().unwrap();
+        catalog.create_namespace(name)?;
+
+        if let Err(e) = self.put(namespace_key(name), Vec::new()) {
+            let _ = catalog.drop_namespace(name, false);
+            return Err(CatalogError::Storage(e.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Drop a namespace as `Catalog::drop_namespace` does, deleting its
+    /// persisted entry and those of the tables `cascade` drops with it.
+    pub fn drop_namespace(
+        &self,
+        name: &str,
+        cascade: bool,
+    ) -> std::result::Result<Vec<TableSchema>, CatalogError> {
+        let mut catalog = self.catalog.write().unwrap();
+        // Tables go first so a crash part way never leaves a table whose
+        // namespace is gone.
+        for table in catalog.check_drop_namespace(name, cascade)? {
+            self.delete_table_entries(&catalog, &table)?;
+        }
+        let namespace = catalog.namespace_name(name).unwrap_or(name).to_string();
+        self.delete(namespace_key(&namespace))
+            .map_err(|e| CatalogError::Storage(e.to_string()))?;
+        catalog.drop_namespace(name, cascade)
+    }
+
+    pub fn list_namespaces(&self) -> Vec<String> {
+        let catalog = self.catalog.read().unwrap();
+        catalog.list_namespaces().into_iter().map(|s| s.to_string()).collect()
+    }
+
+    /// Register a table and persist its schema with a single put under
+    /// `CATALOG_KEY_PREFIX`, so it is reloaded by the next `open`. A
+    /// `namespace.table` name puts it in that namespace, which must exist.
+    pub fn create_table(&self, mut schema: TableSchema) -> std::result::Result<(), CatalogError> {
+        let (namespace, table) = split_qualified(&schema.name);
+        let namespace = namespace.unwrap_or(DEFAULT_NAMESPACE).to_string();
+        schema.name = table.to_string();
+        self.create_table_in(&namespace, schema)
     }
 
-    pub fn create_table(&self, schema: TableSchema) -> std::result::Result<(), CatalogError> {
+    /// `create_table` with the table in `namespace`.
+    pub fn create_table_in(
+        &self,
+        namespace: &str,
+        schema: TableSchema,
+    ) -> std::result::Result<(), CatalogError> {
         let mut catalog = self.catalog.write().unwrap();
-        catalog.register_table(schema)
+        let name = format!("{}.{}", namespace, schema.name);
+        let encoded = schema.encode();
+        catalog.register_table_in(namespace, schema)?;
+
+        let stored = catalog.stored_name(&name).unwrap_or_default();
+        if let Err(e) = self.put(catalog_key(&stored), encoded) {
+            let _ = catalog.drop_table(&name);
+            return Err(CatalogError::Storage(e.to_string()));
+        }
+        Ok(())
     }
 
     pub fn drop_table(&self, name: &str) -> std::result::Result<TableSchema, CatalogError> {
         let mut catalog = self.catalog.write().unwrap();
+        if !catalog.table_exists(name) {
+            return Err(CatalogError::TableNotFound(name.to_string()));
+        }
+
+        self.delete_table_entries(&catalog, name)?;
         catalog.drop_table(name)
     }
 
+    /// Delete the persisted schema of the table `name` and its indexes and
+    /// statistics.
+    fn delete_table_entries(
+        &self,
+        catalog: &Catalog,
+        name: &str,
+    ) -> std::result::Result<(), CatalogError> {
+        let stored = catalog
+            .stored_name(name)
+            .ok_or_else(|| CatalogError::TableNotFound(name.to_string()))?;
+        let storage = |e: Error| CatalogError::Storage(e.to_string());
+
+        // Indexes go first so a crash part way never leaves an index whose
+        // table is gone.
+        for index in catalog.indexes_for_table(name) {
+            self.delete(index_key(&stored, &index.name)).map_err(storage)?;
+        }
+        if catalog.get_stats(name).is_some() {
+            self.delete(stats_key(&stored)).map_err(storage)?;
+        }
+        self.delete(catalog_key(&stored)).map_err(storage)
+    }
+
+    pub fn create_index(&self, index: IndexDef) -> std::result::Result<(), CatalogError> {
+        let mut catalog = self.catalog.write().unwrap();
+        let (table, name) = (index.table.clone(), index.name.clone());
+        let encoded = index.encode();
+        catalog.create_index(index)?;
+
+        let stored = catalog.stored_name(&table).unwrap_or_default();
+        if let Err(e) = self.put(index_key(&stored, &name), encoded) {
+            let _ = catalog.drop_index(&table, &name);
+            return Err(CatalogError::Storage(e.to_string()));
+        }
+        Ok(())
+    }
+
+    pub fn drop_index(&self, table: &str, name: &str) -> std::result::Result<IndexDef, CatalogError> {
+        let mut catalog = self.catalog.write().unwrap();
+        if !catalog.indexes_for_table(table).iter().any(|i| i.name == name) {
+            return Err(CatalogError::IndexNotFound {
+                table: table.to_string(),
+                index: name.to_string(),
+            });
+        }
+
+        let stored = catalog.stored_name(table).unwrap_or_default();
+        self.delete(index_key(&stored, name))
+            .map_err(|e| CatalogError::Storage(e.to_string()))?;
+        catalog.drop_index(table, name)
+    }
+
+    pub fn indexes_for_table(&self, table: &str) -> Vec<IndexDef> {
+        let catalog = self.catalog.read().unwrap();
+        catalog.indexes_for_table(table).to_vec()
+    }
+
+    /// Record statistics for a table and persist them under `STATS_KEY_PREFIX`.
+    pub fn update_stats(&self, table: &str, stats: TableStats) -> std::result::Result<(), CatalogError> {
+        let mut catalog = self.catalog.write().unwrap();
+        let stored = catalog
+            .stored_name(table)
+            .ok_or_else(|| CatalogError::TableNotFound(table.to_string()))?;
+
+        self.put(stats_key(&stored), stats.encode())
+            .map_err(|e| CatalogError::Storage(e.to_string()))?;
+        catalog.update_stats(table, stats)
+    }
+
+    pub fn get_stats(&self, table: &str) -> Option<TableStats> {
+        let catalog = self.catalog.read().unwrap();
+        catalog.get_stats(table).cloned()
+    }
+
+    /// Alter a table and rewrite its persisted schema with a single put. The
+    /// in-memory schema is rolled back if the write fails.
+    pub fn alter_table(&self, name: &str, op: AlterOp) -> std::result::Result<TableSchema, CatalogError> {
+        let mut catalog = self.catalog.write().unwrap();
+        let previous = catalog
+            .get_table(name)
+            .cloned()
+            .ok_or_else(|| CatalogError::TableNotFound(name.to_string()))?;
+
+        let stored = catalog.stored_name(name).unwrap_or_default();
+
+        let renames_column = matches!(op, AlterOp::RenameColumn { .. });
+        let altered = catalog.alter_table(name, op)?.clone();
+        if let Err(e) = self.put(catalog_key(&stored), altered.encode()) {
+            if let Some(schema) = catalog.get_table_mut(name) {
+                *schema = previous;
+            }
+            return Err(CatalogError::Storage(e.to_string()));
+        }
+
+        if renames_column {
+            for index in catalog.indexes_for_table(name) {
+                self.put(index_key(&stored, &index.name), index.encode())
+                    .map_err(|e| CatalogError::Storage(e.to_string()))?;
+            }
+        }
+        if let Some(stats) = catalog.get_stats(name) {
+            self.put(stats_key(&stored), stats.encode())
+                .map_err(|e| CatalogError::Storage(e.to_string()))?;
+        }
+
+        Ok(altered)
+    }
+
+    fn load_catalog(&self) -> Result<()> {
+        let mut catalog = self.catalog.write().unwrap();
+        // Namespaces first, for the tables in them.
+        for (key, _) in self.scan_prefix(NAMESPACE_KEY_PREFIX)? {
+            let namespace = String::from_utf8_lossy(&key[NAMESPACE_KEY_PREFIX.len()..]);
+            catalog
+                .create_namespace(&namespace)
+                .map_err(|e| Error::Corruption(e.to_string()))?;
+        }
+        for (key, value) in self.scan_prefix(CATALOG_KEY_PREFIX)? {
+            let schema = TableSchema::decode(&value).map_err(|e| {
+                Error::Corruption(format!(
+                    "catalog entry {}: {}",
+                    String::from_utf8_lossy(&key),
+                    e
+                ))
+            })?;
+            let stored = String::from_utf8_lossy(&key[CATALOG_KEY_PREFIX.len()..]);
+            let namespace = split_qualified(&stored).0.unwrap_or(DEFAULT_NAMESPACE);
+            catalog
+                .register_table_in(namespace, schema)
+                .map_err(|e| Error::Corruption(e.to_string()))?;
+        }
+        for (key, value) in self.scan_prefix(INDEX_KEY_PREFIX)? {
+            let index = IndexDef::decode(&value).map_err(|e| {
+                Error::Corruption(format!(
+                    "index entry {}: {}",
+                    String::from_utf8_lossy(&key),
+                    e
+                ))
+            })?;
+            catalog
+                .create_index(index)
+                .map_err(|e| Error::Corruption(e.to_string()))?;
+        }
+        for (key, value) in self.scan_prefix(STATS_KEY_PREFIX)? {
+            let table = String::from_utf8_lossy(&key[STATS_KEY_PREFIX.len()..]).into_owned();
+            let stats = TableStats::decode(&value).map_err(|e| {
+                Error::Corruption(format!("stats entry {}: {}", table, e))
+            })?;
+            catalog
+                .update_stats(&table, stats)
+                .map_err(|e| Error::Corruption(e.to_string()))?;
+        }
+        Ok(())
+    }
+
     pub fn get_schema(&self, name: &str) -> Option<TableSchema> {
         let catalog = self.catalog.read().unwrap();
         catalog.get_table(name).cloned()
@@ -133,22 +793,21 @@ impl Database {
     }
 
     pub fn put(&self, key: Key, value: Value) -> Result<()> {
-        let seq = self.sequence.fetch_add(1, Ordering::SeqCst);
-
-        {
-            let mut wal = self.wal.write().unwrap();
-            let entry = WalEntry::put(seq, key.clone(), value.clone());
-            wal.append(&entry)?;
-            wal.sync()?;
-        }
+        let _guard = self.commit_lock.lock().unwrap();
+        self.version_untracked(&[(&key, Some(&value))])?;
+        self.log(|seq| WalEntry::put(seq, key.clone(), value.clone()))?;
 
         {
             let mut memtable = self.memtable.write().unwrap();
+            let events = self.write_events(|| {
+                vec![WriteEvent { key: key.clone(), value: Some(value.clone()) }]
+            });
             memtable.put(key, value).map_err(|e| Error::Internal(e))?;
+            self.notify_write(events);
 
             if memtable.should_flush() {
                 drop(memtable);
-                self.flush_memtable()?;
+                self.flush_memtable(false)?;
             }
         }
 
@@ -158,8 +817,10 @@ impl Database {
     pub fn get(&self, key: &Key) -> Result<Option<Value>> {
         {
             let memtable = self.memtable.read().unwrap();
-            if let Some(value) = memtable.get(key) {
-                return Ok(Some(value.clone()));
+            match memtable.get_entry(key) {
+                Some(ValueEntry::Value(value)) => return Ok(Some(value.clone())),
+                Some(ValueEntry::Tombstone) => return Ok(None),
+                None => {}
             }
         }
 
@@ -181,30 +842,184 @@ impl Database {
         Ok(None)
     }
 
-    pub fn delete(&self, key: Key) -> Result<()> {
-        let seq = self.sequence.fetch_add(1, Ordering::SeqCst);
+    /// Whether `key` has a value. Unlike `get`, a value found in the
+    /// memtable isn't copied out.
+    pub fn contains_key(&self, key: &Key) -> Result<bool> {
+        match self.memtable.read().unwrap().get_entry(key) {
+            Some(ValueEntry::Value(_)) => return Ok(true),
+            Some(ValueEntry::Tombstone) => return Ok(false),
+            None => {}
+        }
+        Ok(self.get(key)?.is_some())
+    }
+
+    /// All live key/value pairs whose key starts with `prefix`, in key order.
+    pub fn scan_prefix(&self, prefix: &[u8]) -> Result<Vec<(Key, Value)>> {
+        self.scan_range(prefix, &prefix_end(prefix))
+    }
+
+    /// All live key/value pairs with keys in `[start, end)`, in key order. An
+    /// empty `end` has no upper bound.
+    pub fn scan_range(&self, start: &[u8], end: &[u8]) -> Result<Vec<(Key, Value)>> {
+        Ok(self
+            .merge_range(start, end, usize::MAX)?
+            .into_iter()
+            .filter_map(|(k, v)| v.map(|v| (k, v)))
+            .collect())
+    }
+
+    /// Like `scan_range`, but reading at most `limit` keys, deleted ones
+    /// included, so a long range can be read a page at a time.
+    pub fn scan_range_page(&self, start: &[u8], end: &[u8], limit: usize) -> Result<ScanPage> {
+        let limit = limit.max(1);
+        let merged = self.merge_range(start, end, limit)?;
+        let resume = match merged.keys().next_back() {
+            Some(last) if merged.len() == limit => {
+                let mut next = last.clone();
+                next.push(0);
+                Some(next)
+            }
+            _ => None,
+        };
+        let page = merged
+            .into_iter()
+            .filter_map(|(k, v)| v.map(|v| (k, v)))
+            .collect();
+        Ok((page, resume))
+    }
+
+    /// The first `limit` keys in `[start, end)` across all sources, each
+    /// with its newest value, or `None` where that is a deletion. Each
+    /// source is read no further than its own first `limit` keys in range,
+    /// which is as far as any of the first `limit` overall can be.
+    fn merge_range(
+        &self,
+        start: &[u8],
+        end: &[u8],
+        limit: usize,
+    ) -> Result<BTreeMap<Key, Option<Value>>> {
+        let in_range = |key: &[u8]| key >= start && (end.is_empty() || key < end);
+        // Sources are visited newest first; the first entry seen for a key wins.
+        let mut merged: BTreeMap<Key, Option<Value>> = BTreeMap::new();
 
         {
-            let mut wal = self.wal.write().unwrap();
-            let entry = WalEntry::delete(seq, key.clone());
-            wal.append(&entry)?;
-            wal.sync()?;
+            let memtable = self.memtable.read().unwrap();
+            let entries = memtable.iter().filter(|(k, _)| in_range(k)).take(limit);
+            for (key, entry) in entries {
+                let value = match entry {
+                    ValueEntry::Value(v) => Some(v.clone()),
+                    ValueEntry::Tombstone => None,
+                };
+                merged.entry(key.clone()).or_insert(value);
+            }
         }
 
+        let sstable_readers = self.sstable_readers.read().unwrap();
+        let version_set = self.version_set.read().unwrap();
+        let version = version_set.current();
+
+        let l0 = version.levels.first().into_iter().flat_map(|l| l.files.iter().rev());
+        let deeper = version.levels.iter().skip(1).flat_map(|l| l.files.iter());
+
+        for metadata in l0.chain(deeper) {
+            let reader = match sstable_readers.get(&metadata.file_id) {
+                Some(reader) => reader,
+                None => continue,
+            };
+            let mut iter = reader.iter()?;
+            iter.seek(start)?;
+            let mut read = 0;
+            while iter.valid() && read < limit {
+                let (key, value) = match (iter.key(), iter.value()) {
+                    (Some(k), Some(v)) => (k, v),
+                    _ => break,
+                };
+                if !in_range(key) {
+                    break;
+                }
+                let value = (value != b"\x00TOMBSTONE").then(|| value.to_vec());
+                merged.entry(key.to_vec()).or_insert(value);
+                read += 1;
+                iter.next()?;
+            }
+        }
+
+        while merged.len() > limit {
+            merged.pop_last();
+        }
+        Ok(merged)
+    }
+
+    pub fn delete(&self, key: Key) -> Result<()> {
+        let _guard = self.commit_lock.lock().unwrap();
+        self.version_untracked(&[(&key, None)])?;
+        self.log(|seq| WalEntry::delete(seq, key.clone()))?;
+
         {
             let mut memtable = self.memtable.write().unwrap();
+            let events = self.write_events(|| vec![WriteEvent { key: key.clone(), value: None }]);
             memtable.delete(key).map_err(|e| Error::Internal(e))?;
+            self.notify_write(events);
 
             if memtable.should_flush() {
                 drop(memtable);
-                self.flush_memtable()?;
+                self.flush_memtable(false)?;
             }
         }
 
         Ok(())
     }
 
-    fn flush_memtable(&self) -> Result<()> {
+    /// Replace `key`'s value with `new`, or delete it for `None`, if it's
+    /// `expected` now; `None` expects the key to be absent. The check and
+    /// the write are one transaction, retried if another commit gets in
+    /// between, so the outcome is as if nothing else ran meanwhile.
+    pub fn compare_and_swap(
+        &self,
+        key: Key,
+        expected: Option<&[u8]>,
+        new: Option<Value>,
+    ) -> Result<CasOutcome> {
+        loop {
+            let txn = self.begin_txn();
+            let current = self.get_txn(txn, &key)?;
+            if current.as_deref() != expected {
+                self.abort_txn(txn)?;
+                return Ok(CasOutcome { succeeded: false, current });
+            }
+            match &new {
+                Some(value) => self.put_txn(txn, key.clone(), value.clone())?,
+                None => self.delete_txn(txn, key.clone())?,
+            }
+            match self.commit_txn(txn) {
+                Ok(()) => return Ok(CasOutcome { succeeded: true, current: new }),
+                Err(Error::TransactionConflict) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Write the memtable out to an SSTable, if it holds anything, and
+    /// compact as that calls for.
+    pub fn flush(&self) -> Result<()> {
+        self.flush_sstable(false).map(|_| ())
+    }
+
+    /// `flush`, returning the SSTable it wrote, if any. With `sync`, that
+    /// file and the WAL are synced to disk before it returns.
+    pub fn flush_sstable(&self, sync: bool) -> Result<Option<SSTableMetadata>> {
+        let empty = self.memtable.read().unwrap().is_empty();
+        let flushed = match empty {
+            true => None,
+            false => Some(self.flush_memtable(sync)?),
+        };
+        if sync {
+            self.wal.write().unwrap().sync()?;
+        }
+        Ok(flushed)
+    }
+
+    fn flush_memtable(&self, sync: bool) -> Result<SSTableMetadata> {
         let file_id = {
             let vs = self.version_set.read().unwrap();
             vs.next_file_id()
@@ -224,11 +1039,15 @@ impl Database {
             self.config.block_size,
         )?;
 
+        // Before compaction can merge it away.
+        if sync {
+            File::open(&sstable_path)?.sync_all()?;
+        }
         let reader = SSTableReader::open(&sstable_path)?;
 
         {
             let mut vs = self.version_set.write().unwrap();
-            vs.add_file(0, metadata);
+            vs.add_file(0, metadata.clone());
         }
 
         {
@@ -238,55 +1057,257 @@ impl Database {
 
         self.maybe_compact()?;
 
-        Ok(())
+        Ok(metadata)
     }
 
-    fn maybe_compact(&self) -> Result<()> {
-        let runner = CompactionRunner::new(
+    fn compaction_runner(&self) -> CompactionRunner {
+        CompactionRunner::new(
             Arc::clone(&self.version_set),
             Arc::clone(&self.sstable_readers),
             self.config.clone(),
-        );
+        )
+        .with_counters(Arc::clone(&self.compaction_counters))
+        .with_retired_bloom(Arc::clone(&self.retired_bloom))
+    }
+
+    fn maybe_compact(&self) -> Result<()> {
+        let _guard = self.compaction_lock.lock().unwrap();
+        let runner = self.compaction_runner();
 
         while runner.maybe_compact()? {}
 
         Ok(())
     }
 
+    /// Wait for a compaction running on another thread, then run any the
+    /// levels still call for. Compactions run on the thread whose flush set
+    /// them off, so none is left pending once this returns.
+    pub fn wait_for_compaction(&self) -> Result<()> {
+        self.maybe_compact()
+    }
+
+    /// What compacting `level`'s files with keys in `start..end` into the
+    /// level below would merge, without doing it. An empty bound is open.
+    pub fn plan_compaction(
+        &self,
+        level: Level,
+        start: &[u8],
+        end: &[u8],
+    ) -> Option<CompactionTask> {
+        let version = self.version_set.read().unwrap().current();
+        CompactionPicker::new(&self.config).pick_range(&version, level, start, end)
+    }
+
+    /// Merge `level`'s files with keys in `start..end` into the level
+    /// below, or do nothing if it has none.
+    pub fn compact_level(
+        &self,
+        level: Level,
+        start: &[u8],
+        end: &[u8],
+    ) -> Result<Option<CompactionPass>> {
+        let _guard = self.compaction_lock.lock().unwrap();
+        let task = match self.plan_compaction(level, start, end) {
+            Some(task) => task,
+            None => return Ok(None),
+        };
+        let bytes_written = self.compaction_runner().run(&task)?;
+        Ok(Some(CompactionPass {
+            level,
+            output_level: task.output_level,
+            input_files: task.all_input_files().count(),
+            bytes_read: task.all_input_files().map(|f| f.file_size).sum(),
+            bytes_written,
+        }))
+    }
+
+    /// Flush the memtable, then merge the keys in `start..end` down a level
+    /// at a time to the deepest level holding any of them, or L1 if only
+    /// L0 does. `on_pass` is told of each compaction as it finishes.
+    pub fn compact_range(
+        &self,
+        start: &[u8],
+        end: &[u8],
+        mut on_pass: impl FnMut(&CompactionPass),
+    ) -> Result<Vec<CompactionPass>> {
+        self.flush()?;
+        let bottom = match self.range_bottom(start, end) {
+            Some(bottom) => bottom,
+            None => return Ok(Vec::new()),
+        };
+
+        let mut passes = Vec::new();
+        for level in 0..bottom {
+            if let Some(pass) = self.compact_level(level, start, end)? {
+                on_pass(&pass);
+                passes.push(pass);
+            }
+        }
+        Ok(passes)
+    }
+
+    /// What `compact_range` would merge at each level, as the files stand
+    /// now; each pass also takes the output of the ones before it. The
+    /// memtable, which it flushes first, isn't counted.
+    pub fn plan_compact_range(&self, start: &[u8], end: &[u8]) -> Vec<CompactionTask> {
+        match self.range_bottom(start, end) {
+            Some(bottom) => (0..bottom)
+                .filter_map(|level| self.plan_compaction(level, start, end))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// The level `compact_range` merges `start..end` down to: the deepest
+    /// holding any of its keys, but at least L1. None if no file does.
+    fn range_bottom(&self, start: &[u8], end: &[u8]) -> Option<Level> {
+        let version = self.version_set.read().unwrap().current();
+        let deepest = version
+            .levels
+            .iter()
+            .rposition(|level| !level.files_in_range(start, end).is_empty())?;
+        Some(deepest.max(1) as Level)
+    }
+
+    fn apply_ops(
+        memtable: &mut MemTable<Key, Value>,
+        ops: Vec<(Key, Option<Value>)>,
+    ) -> Result<()> {
+        for (key, value) in ops {
+            match value {
+                Some(value) => memtable.put(key, value),
+                None => memtable.delete(key),
+            }
+            .map_err(Error::Internal)?;
+        }
+        Ok(())
+    }
+
+    /// Replay the WAL into `memtable`, returning the next sequence number and
+    /// the highest transaction commit version logged.
     fn recover_from_wal(
         wal_path: &PathBuf,
-        _memtable: &MemTable<Key, Value>,
-    ) -> Result<SequenceNumber> {
+        memtable: &mut MemTable<Key, Value>,
+    ) -> Result<(SequenceNumber, Version)> {
         if !wal_path.exists() {
-            return Ok(0);
+            return Ok((0, 0));
         }
 
         let mut reader = WalReader::open(wal_path)?;
         let entries = reader.read_all()?;
 
-        let mut max_seq = 0;
+        // Cut off a record torn by a crash so new appends follow the last
+        // complete one.
+        let file = fs::OpenOptions::new().write(true).open(wal_path)?;
+        if file.metadata()?.len() > reader.offset() {
+            file.set_len(reader.offset())?;
+            file.sync_all()?;
+        }
+
+        let mut next_seq = 0;
+        let mut max_commit = 0;
 
         for entry in entries {
-            max_seq = max_seq.max(entry.sequence_number);
+            next_seq = next_seq.max(entry.sequence_number + 1);
+            let (ops, commit_version) = Self::entry_ops(entry)?;
+            max_commit = max_commit.max(commit_version.unwrap_or(0));
+            Self::apply_ops(memtable, ops)?;
         }
 
-        Ok(max_seq + 1)
+        Ok((next_seq, max_commit))
     }
 
-    pub fn stats(&self) -> DatabaseStats {
-        let memtable = self.memtable.read().unwrap();
-        let version_set = self.version_set.read().unwrap();
-        let version = version_set.current();
+    /// The writes a WAL record holds, and a batch's commit version.
+    fn entry_ops(entry: WalEntry) -> Result<(Vec<(Key, Option<Value>)>, Option<Version>)> {
+        Ok(match entry.entry_type {
+            EntryType::Put => (vec![(entry.key, Some(entry.value.unwrap_or_default()))], None),
+            EntryType::Delete => (vec![(entry.key, None)], None),
+            EntryType::Batch => {
+                let batch = entry.decode_batch()?;
+                (batch.ops, Some(batch.commit_version))
+            }
+        })
+    }
 
-        let num_sstables = version.all_files().count();
+    pub fn stats(&self) -> DatabaseStats {
+        // Each lock is held only for its own figures, so writers wait on
+        // stats no longer than on a single read.
+        let (memtable_size, memtable_entries) = {
+            let memtable = self.memtable.read().unwrap();
+            (memtable.approx_size(), memtable.len())
+        };
+        let version = self.version_set.read().unwrap().current();
+
+        let num_sstables = version.all_files().count();
+        let levels = version
+            .levels
+            .iter()
+            .map(|l| LevelStats {
+                level: l.level,
+                files: l.file_count(),
+                bytes: l.total_size(),
+            })
+            .collect();
+        let estimated_keys =
+            memtable_entries as u64 + version.all_files().map(|f| f.num_entries).sum::<u64>();
+        let wal_size = {
+            let wal = self.wal.read().unwrap();
+            fs::metadata(wal.path()).map(|m| m.len()).unwrap_or(0)
+        };
+
+        let (bloom_probes, bloom_negatives) = {
+            // Readers are retired under the write lock, so under the read
+            // lock each probe is counted once, live or retired.
+            let readers = self.sstable_readers.read().unwrap();
+            readers.values().fold(self.retired_bloom.load(), |(probes, negatives), reader| {
+                let props = reader.properties();
+                (probes + props.bloom_probes, negatives + props.bloom_negatives)
+            })
+        };
 
         DatabaseStats {
-            memtable_size: memtable.approx_size(),
-            memtable_entries: memtable.len(),
+            memtable_size,
+            memtable_entries,
             num_sstables,
             sequence_number: self.sequence.load(Ordering::SeqCst),
             l0_file_count: version.l0_file_count(),
+            levels,
+            wal_size,
+            estimated_keys,
+            compaction: self.compaction_counters.snapshot(),
+            bloom_probes,
+            bloom_negatives,
+            oldest_active_version: self.txn_manager.oldest_active_version(),
+            txn_retained_versions: self.txn_manager.retained_versions(),
+            txn_retained_bytes: self.txn_manager.retained_bytes(),
+            txn: self.txn_manager.metrics(STATS_TOP_CONFLICT_KEYS),
+        }
+    }
+
+    /// One of the figures `stats` reports, by name, as text. Unknown names
+    /// give `None`.
+    pub fn property(&self, name: &str) -> Option<String> {
+        let stats = || self.stats();
+        if let Some(level) = name.strip_prefix("middb.num-files-at-level") {
+            let level: usize = level.parse().ok()?;
+            return stats().levels.get(level).map(|l| l.files.to_string());
         }
+        let value = match name {
+            "middb.memtable-size" => stats().memtable_size.to_string(),
+            "middb.memtable-entries" => stats().memtable_entries.to_string(),
+            "middb.num-sstables" => stats().num_sstables.to_string(),
+            "middb.l0-file-count" => stats().l0_file_count.to_string(),
+            "middb.total-sst-size" => stats().sstable_bytes().to_string(),
+            "middb.wal-size" => stats().wal_size.to_string(),
+            "middb.estimate-num-keys" => stats().estimated_keys.to_string(),
+            "middb.compactions" => stats().compaction.compactions.to_string(),
+            "middb.compaction-bytes-written" => stats().compaction.bytes_written.to_string(),
+            "middb.sequence-number" => stats().sequence_number.to_string(),
+            "middb.bloom-effectiveness" => stats().bloom_effectiveness().to_string(),
+            "middb.active-transactions" => stats().txn.active.to_string(),
+            _ => return None,
+        };
+        Some(value)
     }
 
     pub fn close(self) -> Result<()> {
@@ -294,7 +1315,7 @@ impl Database {
             let memtable = self.memtable.read().unwrap();
             if !memtable.is_empty() {
                 drop(memtable);
-                self.flush_memtable()?;
+                self.flush_memtable(false)?;
             }
         }
 
@@ -307,19 +1328,70 @@ impl Database {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct DatabaseStats {
     pub memtable_size: usize,
     pub memtable_entries: usize,
     pub num_sstables: usize,
     pub sequence_number: u64,
     pub l0_file_count: usize,
+    /// Every level, from L0 down, empty ones included.
+    pub levels: Vec<LevelStats>,
+    pub wal_size: u64,
+    /// Memtable entries plus SSTable entries; overwritten and deleted keys
+    /// count more than once until compaction merges them.
+    pub estimated_keys: u64,
+    pub compaction: CompactionStats,
+    pub bloom_probes: u64,
+    pub bloom_negatives: u64,
+    /// Start version of the oldest open transaction.
+    pub oldest_active_version: Option<u64>,
+    /// Committed versions kept for snapshot reads, and their size.
+    pub txn_retained_versions: usize,
+    pub txn_retained_bytes: u64,
+    pub txn: TxnMetrics,
+}
+
+/// The SSTables in one level.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LevelStats {
+    pub level: Level,
+    pub files: usize,
+    pub bytes: u64,
+}
+
+impl DatabaseStats {
+    /// Size of the SSTables across all levels.
+    pub fn sstable_bytes(&self) -> u64 {
+        self.levels.iter().map(|l| l.bytes).sum()
+    }
+
+    /// Fraction of SSTable bloom probes that ruled a key out without a block read.
+    pub fn bloom_effectiveness(&self) -> f64 {
+        if self.bloom_probes == 0 {
+            return 0.0;
+        }
+        self.bloom_negatives as f64 / self.bloom_probes as f64
+    }
+}
+
+/// The smallest key greater than every key starting with `prefix`, or empty
+/// if there is none.
+fn prefix_end(prefix: &[u8]) -> Vec<u8> {
+    let mut end = prefix.to_vec();
+    while let Some(last) = end.pop() {
+        if last < u8::MAX {
+            end.push(last + 1);
+            return end;
+        }
+    }
+    end
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::catalog::{DataType, TableSchemaBuilder};
+    use crate::catalog::{DataType, Datum, TableSchemaBuilder};
     use tempfile::TempDir;
 
     #[test]
@@ -340,6 +1412,27 @@ mod tests {
         assert_eq!(db.get(&b"key1".to_vec()).unwrap(), None);
     }
 
+    #[test]
+    fn test_contains_key() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::open(Config::new(temp_dir.path())).unwrap();
+
+        db.put(b"flushed".to_vec(), b"1".to_vec()).unwrap();
+        db.put(b"deleted".to_vec(), b"1".to_vec()).unwrap();
+        db.flush().unwrap();
+        db.put(b"memtable".to_vec(), b"1".to_vec()).unwrap();
+        db.delete(b"deleted".to_vec()).unwrap();
+
+        assert!(db.contains_key(&b"flushed".to_vec()).unwrap());
+        assert!(db.contains_key(&b"memtable".to_vec()).unwrap());
+        assert!(!db.contains_key(&b"deleted".to_vec()).unwrap());
+        assert!(!db.contains_key(&b"missing".to_vec()).unwrap());
+
+        db.flush().unwrap();
+        assert!(!db.contains_key(&b"deleted".to_vec()).unwrap());
+        assert!(db.contains_key(&b"memtable".to_vec()).unwrap());
+    }
+
     #[test]
     fn test_database_stats() {
         let temp_dir = TempDir::new().unwrap();
@@ -354,6 +1447,199 @@ mod tests {
         assert!(stats.memtable_size > 0);
     }
 
+    #[test]
+    fn test_database_stats_levels_and_compactions() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut config = Config::new(temp_dir.path());
+        config.level0_file_num_compaction_trigger = 2;
+        let db = Database::open(config).unwrap();
+
+        db.put(b"a".to_vec(), b"1".to_vec()).unwrap();
+        db.flush().unwrap();
+        let stats = db.stats();
+        assert_eq!(stats.levels.len(), 7);
+        assert_eq!(stats.levels[0].files, 1);
+        assert!(stats.levels[0].bytes > 0);
+        assert_eq!(stats.compaction.compactions, 0);
+        assert!(stats.wal_size > 0);
+
+        // The second L0 file sets off a compaction into L1.
+        db.put(b"b".to_vec(), b"2".to_vec()).unwrap();
+        db.flush().unwrap();
+        db.put(b"c".to_vec(), b"3".to_vec()).unwrap();
+        let stats = db.stats();
+        assert_eq!(stats.levels[0].files, 0);
+        assert_eq!(stats.levels[1].files, 1);
+        assert_eq!(stats.compaction.compactions, 1);
+        assert_eq!(stats.compaction.input_files, 2);
+        assert_eq!(stats.compaction.bytes_written, stats.levels[1].bytes);
+        assert_eq!(stats.estimated_keys, 3);
+        assert_eq!(stats.sstable_bytes(), stats.levels[1].bytes);
+
+        assert_eq!(db.property("middb.num-files-at-level1"), Some("1".to_string()));
+        assert_eq!(db.property("middb.num-files-at-level9"), None);
+        assert_eq!(db.property("middb.compactions"), Some("1".to_string()));
+        assert_eq!(db.property("middb.estimate-num-keys"), Some("3".to_string()));
+    }
+
+    #[test]
+    fn test_flush_sstable() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::open(Config::new(temp_dir.path())).unwrap();
+        assert!(db.flush_sstable(true).unwrap().is_none());
+
+        db.put(b"a".to_vec(), b"1".to_vec()).unwrap();
+        db.delete(b"b".to_vec()).unwrap();
+        let flushed = db.flush_sstable(true).unwrap().unwrap();
+        assert_eq!(flushed.num_entries, 2);
+        assert_eq!(flushed.smallest_key, b"a");
+        assert_eq!(flushed.largest_key, b"b");
+        assert_eq!(flushed.file_size, db.stats().levels[0].bytes);
+        assert!(temp_dir.path().join(format!("sst_{:08}.sst", flushed.file_id)).exists());
+        assert!(db.flush_sstable(false).unwrap().is_none());
+
+        db.wait_for_compaction().unwrap();
+        assert_eq!(db.stats().levels[0].files, 1);
+    }
+
+    #[test]
+    fn test_compact_range() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut config = Config::new(temp_dir.path());
+        config.level0_file_num_compaction_trigger = 100;
+        let db = Database::open(config).unwrap();
+
+        for round in 0..4 {
+            for key in [b"a", b"m", b"z"] {
+                db.put(key.to_vec(), format!("{}", round).into_bytes()).unwrap();
+            }
+            db.flush().unwrap();
+        }
+        db.delete(b"m".to_vec()).unwrap();
+        assert_eq!(db.stats().levels[0].files, 4);
+
+        // Nothing there to compact.
+        assert!(db.compact_level(0, b"0", b"a").unwrap().is_none());
+        let plan = db.plan_compact_range(b"", b"");
+        assert_eq!(plan.len(), 1);
+        assert_eq!(plan[0].input_files.len(), 4);
+
+        let mut heard = Vec::new();
+        let passes = db.compact_range(b"", b"", |pass| heard.push(pass.clone())).unwrap();
+        assert_eq!(passes, heard);
+        assert_eq!(passes.len(), 1);
+        assert_eq!((passes[0].level, passes[0].output_level), (0, 1));
+        // The flushed memtable holding the deletion makes a fifth file.
+        assert_eq!(passes[0].input_files, 5);
+        assert!(passes[0].bytes_written > 0);
+        assert!(passes[0].bytes_written < passes[0].bytes_read);
+
+        let stats = db.stats();
+        assert_eq!((stats.levels[0].files, stats.levels[1].files), (0, 1));
+        assert_eq!(db.get(&b"a".to_vec()).unwrap(), Some(b"3".to_vec()));
+        assert_eq!(db.get(&b"m".to_vec()).unwrap(), None);
+
+        // Already at the bottom, so the range stays put unless a level is
+        // asked for.
+        assert!(db.compact_range(b"a", b"b", |_| {}).unwrap().is_empty());
+        let pass = db.compact_level(1, b"a", b"b").unwrap().unwrap();
+        assert_eq!((pass.output_level, pass.input_files), (2, 1));
+        assert!(db.compact_range(b"", b"", |_| {}).unwrap().is_empty());
+        assert_eq!(db.stats().levels[2].files, 1);
+    }
+
+    #[test]
+    fn test_database_lock_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::open(Config::new(temp_dir.path())).unwrap();
+        assert!(temp_dir.path().join("LOCK").exists());
+        let err = Database::open(Config::new(temp_dir.path())).err().unwrap();
+        assert!(matches!(err, Error::Locked(_)));
+
+        db.close().unwrap();
+        Database::open(Config::new(temp_dir.path())).unwrap();
+    }
+
+    #[test]
+    fn test_write_listener() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::open(Config::new(temp_dir.path())).unwrap();
+        db.put(b"before".to_vec(), b"v".to_vec()).unwrap();
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&seen);
+        db.on_write(Box::new(move |events| sink.lock().unwrap().push(events.to_vec())));
+
+        db.put(b"a".to_vec(), b"1".to_vec()).unwrap();
+        db.delete(b"a".to_vec()).unwrap();
+        let txn = db.begin_txn();
+        db.put_txn(txn, b"b".to_vec(), b"2".to_vec()).unwrap();
+        db.put_txn(txn, b"c".to_vec(), b"3".to_vec()).unwrap();
+        db.commit_txn(txn).unwrap();
+        // An aborted transaction applies nothing.
+        let txn = db.begin_txn();
+        db.put_txn(txn, b"d".to_vec(), b"4".to_vec()).unwrap();
+        db.abort_txn(txn).unwrap();
+
+        let event = |key: &[u8], value: Option<&[u8]>| WriteEvent {
+            key: key.to_vec(),
+            value: value.map(<[u8]>::to_vec),
+        };
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec![
+                vec![event(b"a", Some(b"1"))],
+                vec![event(b"a", None)],
+                vec![event(b"b", Some(b"2")), event(b"c", Some(b"3"))],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_database_property() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::open(Config::new(temp_dir.path())).unwrap();
+
+        db.put(b"key1".to_vec(), b"value1".to_vec()).unwrap();
+        db.put(b"key2".to_vec(), b"value2".to_vec()).unwrap();
+        let _txn = db.begin_txn();
+
+        assert_eq!(db.property("middb.memtable-entries"), Some("2".to_string()));
+        assert_eq!(db.property("middb.num-sstables"), Some("0".to_string()));
+        assert_eq!(db.property("middb.active-transactions"), Some("1".to_string()));
+        assert_eq!(db.property("middb.no-such-thing"), None);
+    }
+
+    #[test]
+    fn test_database_bloom_stats() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut config = Config::new(temp_dir.path());
+        config.memtable_size = 1024 * 1024;
+        let db = Database::open(config).unwrap();
+
+        let value = vec![b'x'; 16 * 1024];
+        for i in 0..80 {
+            db.put(format!("key{:03}", i).into_bytes(), value.clone()).unwrap();
+        }
+        assert!(db.stats().num_sstables > 0);
+
+        for i in 0..100 {
+            db.get(&format!("key{:03}x", i % 60).into_bytes()).unwrap();
+        }
+
+        let stats = db.stats();
+        assert!(stats.bloom_probes >= 100);
+        assert!(stats.bloom_negatives > 0);
+        assert!(stats.bloom_effectiveness() > 0.5);
+
+        // Compaction drops the readers that counted those probes.
+        let passes = db.compact_range(b"", b"", |_| {}).unwrap();
+        assert!(!passes.is_empty());
+        let after = db.stats();
+        assert!(after.bloom_probes >= stats.bloom_probes);
+        assert!(after.bloom_negatives >= stats.bloom_negatives);
+    }
+
     #[test]
     fn test_database_catalog() {
         let temp_dir = TempDir::new().unwrap();
@@ -378,6 +1664,303 @@ mod tests {
         assert!(db.get_schema("users").is_none());
     }
 
+    #[test]
+    fn test_database_catalog_persists_across_reopen() {
+        let temp_dir = TempDir::new().unwrap();
+
+        {
+            let db = Database::open(Config::new(temp_dir.path())).unwrap();
+            db.create_table(
+                TableSchemaBuilder::new("users")
+                    .column("id", DataType::Int64, false)
+                    .column("email", DataType::String, true)
+                    .build(),
+            )
+            .unwrap();
+            db.create_table(
+                TableSchemaBuilder::new("events")
+                    .column("payload", DataType::Bytes, false)
+                    .build(),
+            )
+            .unwrap();
+            db.create_table(TableSchemaBuilder::new("scratch").build()).unwrap();
+            db.drop_table("scratch").unwrap();
+            db.close().unwrap();
+        }
+
+        let db = Database::open(Config::new(temp_dir.path())).unwrap();
+        let mut tables = db.list_tables();
+        tables.sort();
+        assert_eq!(tables, vec!["events", "users"]);
+
+        let users = db.get_schema("users").unwrap();
+        let email = users.get_column("email").unwrap();
+        assert_eq!(email.data_type, DataType::String);
+        assert!(email.nullable);
+        assert_eq!(email.position, 1);
+        assert!(!users.get_column("id").unwrap().nullable);
+
+        assert!(matches!(
+            db.create_table(TableSchemaBuilder::new("users").build()),
+            Err(CatalogError::TableAlreadyExists(_))
+        ));
+        db.drop_table("events").unwrap();
+        drop(db);
+
+        let db = Database::open(Config::new(temp_dir.path())).unwrap();
+        assert_eq!(db.list_tables(), vec!["users"]);
+    }
+
+    #[test]
+    fn test_column_defaults_persist_across_reopen() {
+        let temp_dir = TempDir::new().unwrap();
+
+        {
+            let db = Database::open(Config::new(temp_dir.path())).unwrap();
+            db.create_table(
+                TableSchemaBuilder::new("jobs")
+                    .column("id", DataType::Int64, false)
+                    .column_with_default("state", DataType::String, false, Datum::String("queued".into()))
+                    .column_with_default("attempts", DataType::Int64, true, Datum::Int(0))
+                    .build(),
+            )
+            .unwrap();
+            db.close().unwrap();
+        }
+
+        let db = Database::open(Config::new(temp_dir.path())).unwrap();
+        let jobs = db.get_schema("jobs").unwrap();
+        assert_eq!(jobs.get_column("id").unwrap().default, None);
+        assert_eq!(
+            jobs.get_column("state").unwrap().default,
+            Some(Datum::String("queued".into()))
+        );
+        assert_eq!(jobs.get_column("attempts").unwrap().default, Some(Datum::Int(0)));
+    }
+
+    #[test]
+    fn test_database_alter_table_persists() {
+        let temp_dir = TempDir::new().unwrap();
+
+        {
+            let db = Database::open(Config::new(temp_dir.path())).unwrap();
+            db.create_table(
+                TableSchemaBuilder::new("users")
+                    .column("id", DataType::Int64, false)
+                    .column("name", DataType::String, false)
+                    .build(),
+            )
+            .unwrap();
+            db.alter_table(
+                "users",
+                AlterOp::AddColumn(crate::catalog::Column::new("age", DataType::Int64)),
+            )
+            .unwrap();
+            let schema = db
+                .alter_table(
+                    "users",
+                    AlterOp::RenameColumn {
+                        from: "name".to_string(),
+                        to: "full_name".to_string(),
+                    },
+                )
+                .unwrap();
+            assert_eq!(schema.schema_version, 3);
+        }
+
+        let db = Database::open(Config::new(temp_dir.path())).unwrap();
+        let schema = db.get_schema("users").unwrap();
+        assert_eq!(schema.column_names(), vec!["id", "full_name", "age"]);
+        assert_eq!(schema.schema_version, 3);
+        assert!(db.alter_table("users", AlterOp::DropColumn("name".to_string())).is_err());
+    }
+
+    #[test]
+    fn test_database_composite_primary_key_persists() {
+        let temp_dir = TempDir::new().unwrap();
+
+        {
+            let db = Database::open(Config::new(temp_dir.path())).unwrap();
+            let schema = TableSchemaBuilder::new("memberships")
+                .column("org", DataType::String, false)
+                .column("user", DataType::Int64, false)
+                .column("role", DataType::String, true)
+                .primary_key(vec!["org", "user"])
+                .build();
+            db.create_table(schema).unwrap();
+        }
+
+        let db = Database::open(Config::new(temp_dir.path())).unwrap();
+        let schema = db.get_schema("memberships").unwrap();
+        assert_eq!(
+            schema.primary_key(),
+            Some(&["org".to_string(), "user".to_string()][..])
+        );
+    }
+
+    #[test]
+    fn test_database_namespaces_persist() {
+        let temp_dir = TempDir::new().unwrap();
+        let table = |name: &str| {
+            TableSchemaBuilder::new(name)
+                .column("id", DataType::Int64, false)
+                .build()
+        };
+
+        {
+            let db = Database::open(Config::new(temp_dir.path())).unwrap();
+            db.create_namespace("analytics").unwrap();
+            db.create_table(table("events")).unwrap();
+            db.create_table(table("analytics.events")).unwrap();
+            db.create_table_in("analytics", table("clicks")).unwrap();
+            db.create_index(IndexDef::new("by_id", "analytics.events", vec!["id"], true))
+                .unwrap();
+            let stats = TableStats { row_count: 3, ..TableStats::default() };
+            db.update_stats("analytics.events", stats).unwrap();
+
+            db.create_namespace("scratch").unwrap();
+            db.create_table_in("scratch", table("tmp")).unwrap();
+            assert!(matches!(
+                db.drop_namespace("scratch", false),
+                Err(CatalogError::NamespaceNotEmpty(_))
+            ));
+            assert_eq!(db.drop_namespace("scratch", true).unwrap().len(), 1);
+            db.close().unwrap();
+        }
+
+        let db = Database::open(Config::new(temp_dir.path())).unwrap();
+        assert_eq!(db.list_namespaces(), vec!["analytics", "public"]);
+        assert_eq!(db.list_tables(), vec!["events"]);
+        assert!(db.get_schema("analytics.clicks").is_some());
+        assert_eq!(db.indexes_for_table("analytics.events").len(), 1);
+        assert!(db.indexes_for_table("events").is_empty());
+        assert_eq!(db.get_stats("analytics.events").unwrap().row_count, 3);
+        assert!(db.get_stats("events").is_none());
+
+        // The two `events` are kept apart, so dropping one leaves the other.
+        db.drop_table("events").unwrap();
+        drop(db);
+
+        let db = Database::open(Config::new(temp_dir.path())).unwrap();
+        assert!(db.get_schema("events").is_none());
+        assert!(db.get_schema("analytics.events").is_some());
+        assert!(db.get_schema("scratch.tmp").is_none());
+    }
+
+    #[test]
+    fn test_database_indexes_persist() {
+        let temp_dir = TempDir::new().unwrap();
+
+        {
+            let db = Database::open(Config::new(temp_dir.path())).unwrap();
+            for table in ["users", "posts"] {
+                db.create_table(
+                    TableSchemaBuilder::new(table)
+                        .column("id", DataType::Int64, false)
+                        .column("author", DataType::String, true)
+                        .build(),
+                )
+                .unwrap();
+            }
+            db.create_index(IndexDef::new("by_author", "users", vec!["author"], false)).unwrap();
+            db.create_index(IndexDef::new("by_id", "users", vec!["id"], true)).unwrap();
+            db.create_index(IndexDef::new("by_author", "posts", vec!["author"], false)).unwrap();
+            db.drop_index("users", "by_id").unwrap();
+        }
+
+        let db = Database::open(Config::new(temp_dir.path())).unwrap();
+        let users = db.indexes_for_table("users");
+        assert_eq!(users.len(), 1);
+        assert_eq!(users[0].columns, vec!["author".to_string()]);
+        assert_eq!(db.indexes_for_table("posts").len(), 1);
+
+        db.drop_table("posts").unwrap();
+        drop(db);
+
+        let db = Database::open(Config::new(temp_dir.path())).unwrap();
+        assert!(db.indexes_for_table("posts").is_empty());
+        assert_eq!(db.indexes_for_table("users").len(), 1);
+
+        db.alter_table(
+            "users",
+            AlterOp::RenameColumn {
+                from: "author".to_string(),
+                to: "writer".to_string(),
+            },
+        )
+        .unwrap();
+        drop(db);
+
+        let db = Database::open(Config::new(temp_dir.path())).unwrap();
+        assert!(db.indexes_for_table("users")[0].covers("writer"));
+    }
+
+    #[test]
+    fn test_database_scan_prefix() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut config = Config::new(temp_dir.path());
+        config.memtable_size = 1024 * 1024;
+        let db = Database::open(config).unwrap();
+
+        let value = vec![b'v'; 8 * 1024];
+        for i in 0..200 {
+            db.put(format!("a/{:03}", i).into_bytes(), value.clone()).unwrap();
+            db.put(format!("b/{:03}", i).into_bytes(), value.clone()).unwrap();
+        }
+        assert!(db.stats().num_sstables > 0);
+
+        db.delete(b"a/005".to_vec()).unwrap();
+        db.put(b"a/007".to_vec(), b"new".to_vec()).unwrap();
+
+        let rows = db.scan_prefix(b"a/").unwrap();
+        assert_eq!(rows.len(), 199);
+        assert!(rows.windows(2).all(|w| w[0].0 < w[1].0));
+        assert!(rows.iter().all(|(k, _)| k.starts_with(b"a/")));
+        assert!(!rows.iter().any(|(k, _)| k == b"a/005"));
+        assert_eq!(
+            rows.iter().find(|(k, _)| k == b"a/007").unwrap().1,
+            b"new".to_vec()
+        );
+        assert_eq!(db.get(&b"a/005".to_vec()).unwrap(), None);
+    }
+
+    #[test]
+    fn test_database_scan_range_page() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut config = Config::new(temp_dir.path());
+        config.memtable_size = 1024 * 1024;
+        let db = Database::open(config).unwrap();
+
+        let value = vec![b'v'; 8 * 1024];
+        for i in 0..200 {
+            db.put(format!("a/{:03}", i).into_bytes(), value.clone()).unwrap();
+        }
+        assert!(db.stats().num_sstables > 0);
+        // Deletions and overwrites in the memtable shadow the tables.
+        for i in (0..200).step_by(3) {
+            db.delete(format!("a/{:03}", i).into_bytes()).unwrap();
+        }
+        db.put(b"a/100".to_vec(), b"new".to_vec()).unwrap();
+
+        let mut paged = Vec::new();
+        let mut start = b"a/".to_vec();
+        let mut pages = 0;
+        loop {
+            let (page, resume) = db.scan_range_page(&start, b"a0", 16).unwrap();
+            assert!(page.len() <= 16);
+            paged.extend(page);
+            pages += 1;
+            match resume {
+                Some(next) => start = next,
+                None => break,
+            }
+        }
+        assert!(pages > 1);
+        assert_eq!(paged, db.scan_range(b"a/", b"a0").unwrap());
+        assert_eq!(paged.len(), 133);
+        assert!(paged.contains(&(b"a/100".to_vec(), b"new".to_vec())));
+    }
+
     #[test]
     fn test_database_transaction_commit() {
         let temp_dir = TempDir::new().unwrap();
@@ -429,4 +2012,548 @@ mod tests {
 
         assert!(db.get(&b"key1".to_vec()).unwrap().is_none());
     }
+
+    #[test]
+    fn test_database_transaction_commit_survives_reopen() {
+        let temp_dir = TempDir::new().unwrap();
+
+        {
+            let db = Database::open(Config::new(temp_dir.path())).unwrap();
+            let txn = db.begin_txn();
+            db.put_txn(txn, b"a".to_vec(), b"1".to_vec()).unwrap();
+            db.put_txn(txn, b"b".to_vec(), b"2".to_vec()).unwrap();
+            db.commit_txn(txn).unwrap();
+        }
+
+        let db = Database::open(Config::new(temp_dir.path())).unwrap();
+        assert_eq!(db.get(&b"a".to_vec()).unwrap(), Some(b"1".to_vec()));
+        assert_eq!(db.get(&b"b".to_vec()).unwrap(), Some(b"2".to_vec()));
+    }
+
+    #[test]
+    fn test_database_transaction_reads_its_snapshot() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::open(Config::new(temp_dir.path())).unwrap();
+        let key = b"key".to_vec();
+        db.put(key.clone(), b"old".to_vec()).unwrap();
+
+        let reader = db.begin_txn();
+        let writer = db.begin_txn();
+        db.put_txn(writer, key.clone(), b"new".to_vec()).unwrap();
+        db.commit_txn(writer).unwrap();
+
+        assert_eq!(db.get(&key).unwrap(), Some(b"new".to_vec()));
+        assert_eq!(db.get_txn(reader, &key).unwrap(), Some(b"old".to_vec()));
+
+        let later = db.begin_txn();
+        assert_eq!(db.get_txn(later, &key).unwrap(), Some(b"new".to_vec()));
+    }
+
+    #[test]
+    fn test_database_concurrent_transfers_keep_invariant() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(Database::open(Config::new(temp_dir.path())).unwrap());
+        let (a, b) = (b"a".to_vec(), b"b".to_vec());
+        let read = |db: &Database, txn, key: &Key| -> i64 {
+            let bytes = db.get_txn(txn, key).unwrap().unwrap();
+            i64::from_le_bytes(bytes.try_into().unwrap())
+        };
+
+        db.put(a.clone(), 100i64.to_le_bytes().to_vec()).unwrap();
+        db.put(b.clone(), 100i64.to_le_bytes().to_vec()).unwrap();
+
+        let writers: Vec<_> = (0..4)
+            .map(|_| {
+                let db = Arc::clone(&db);
+                let (a, b) = (a.clone(), b.clone());
+                std::thread::spawn(move || {
+                    let mut done = 0;
+                    while done < 25 {
+                        let txn = db.begin_txn();
+                        let from = read(&db, txn, &a);
+                        let to = read(&db, txn, &b);
+                        db.put_txn(txn, a.clone(), (from - 1).to_le_bytes().to_vec()).unwrap();
+                        db.put_txn(txn, b.clone(), (to + 1).to_le_bytes().to_vec()).unwrap();
+                        match db.commit_txn(txn) {
+                            Ok(()) => done += 1,
+                            Err(Error::TransactionConflict) => {}
+                            Err(e) => panic!("unexpected error: {}", e),
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        let reader = {
+            let db = Arc::clone(&db);
+            let (a, b) = (a.clone(), b.clone());
+            std::thread::spawn(move || {
+                for _ in 0..200 {
+                    let txn = db.begin_txn();
+                    assert_eq!(read(&db, txn, &a) + read(&db, txn, &b), 200);
+                    db.abort_txn(txn).unwrap();
+                }
+            })
+        };
+
+        for handle in writers {
+            handle.join().unwrap();
+        }
+        reader.join().unwrap();
+
+        let txn = db.begin_txn();
+        assert_eq!(read(&db, txn, &a), 0);
+        assert_eq!(read(&db, txn, &b), 200);
+    }
+
+    #[test]
+    fn test_database_read_only_transaction() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::open(Config::new(temp_dir.path())).unwrap();
+        let key = b"key".to_vec();
+        db.put(key.clone(), b"v1".to_vec()).unwrap();
+
+        let reader = db.begin_read_only_txn();
+        assert!(matches!(
+            db.put_txn(reader, key.clone(), b"x".to_vec()),
+            Err(Error::InvalidArgument(_))
+        ));
+
+        let writer = db.begin_txn();
+        db.put_txn(writer, key.clone(), b"v2".to_vec()).unwrap();
+        db.commit_txn(writer).unwrap();
+
+        assert_eq!(db.get_txn(reader, &key).unwrap(), Some(b"v1".to_vec()));
+        db.commit_txn(reader).unwrap();
+    }
+
+    #[test]
+    fn test_database_snapshot_ignores_plain_writes() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::open(Config::new(temp_dir.path())).unwrap();
+        db.put(b"a".to_vec(), b"1".to_vec()).unwrap();
+        db.put(b"b".to_vec(), b"2".to_vec()).unwrap();
+
+        let reader = db.begin_read_only_txn();
+        db.put(b"a".to_vec(), b"changed".to_vec()).unwrap();
+        db.delete(b"b".to_vec()).unwrap();
+        let mut batch = WriteBatch::new();
+        batch.put(b"c".to_vec(), b"x".to_vec());
+        batch.put(b"c".to_vec(), b"y".to_vec());
+        db.write(batch).unwrap();
+
+        assert_eq!(db.get_txn(reader, &b"a".to_vec()).unwrap(), Some(b"1".to_vec()));
+        assert_eq!(db.get_txn(reader, &b"b".to_vec()).unwrap(), Some(b"2".to_vec()));
+        assert!(db.get_txn(reader, &b"c".to_vec()).unwrap().is_none());
+        assert_eq!(db.get(&b"c".to_vec()).unwrap(), Some(b"y".to_vec()));
+        assert_eq!(
+            db.scan_range_txn(reader, b"", b"").unwrap(),
+            vec![(b"a".to_vec(), b"1".to_vec()), (b"b".to_vec(), b"2".to_vec())]
+        );
+
+        let later = db.begin_read_only_txn();
+        assert_eq!(db.get_txn(later, &b"a".to_vec()).unwrap(), Some(b"changed".to_vec()));
+        assert!(db.get_txn(later, &b"b".to_vec()).unwrap().is_none());
+        assert_eq!(db.get_txn(later, &b"c".to_vec()).unwrap(), Some(b"y".to_vec()));
+    }
+
+    #[test]
+    fn test_database_plain_write_conflicts_with_transaction() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::open(Config::new(temp_dir.path())).unwrap();
+        let key = b"key".to_vec();
+
+        let txn = db.begin_txn();
+        db.get_txn(txn, &key).unwrap();
+        db.put_txn(txn, key.clone(), b"txn".to_vec()).unwrap();
+        db.put(key.clone(), b"plain".to_vec()).unwrap();
+
+        assert!(matches!(db.commit_txn(txn), Err(Error::TransactionConflict)));
+        assert_eq!(db.get(&key).unwrap(), Some(b"plain".to_vec()));
+    }
+
+    #[test]
+    fn test_database_gc_versions_after_snapshot_released() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::open(Config::new(temp_dir.path())).unwrap();
+        db.put(b"key".to_vec(), b"old".to_vec()).unwrap();
+
+        let reader = db.begin_read_only_txn();
+        db.put(b"key".to_vec(), b"new".to_vec()).unwrap();
+        db.gc_versions();
+        assert_eq!(db.stats().txn_retained_versions, 2);
+
+        db.commit_txn(reader).unwrap();
+        db.gc_versions();
+        assert_eq!(db.stats().txn_retained_versions, 0);
+        assert_eq!(db.stats().txn_retained_bytes, 0);
+
+        let later = db.begin_read_only_txn();
+        assert_eq!(db.get_txn(later, &b"key".to_vec()).unwrap(), Some(b"new".to_vec()));
+    }
+
+    #[test]
+    fn test_database_transaction_timeout() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut config = Config::new(temp_dir.path());
+        config.txn_timeout = std::time::Duration::from_millis(20);
+        let db = Database::open(config).unwrap();
+
+        let txn = db.begin_txn();
+        db.put_txn(txn, b"key".to_vec(), b"value".to_vec()).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(40));
+
+        assert!(matches!(db.commit_txn(txn), Err(Error::TransactionTimedOut)));
+        assert!(db.get(&b"key".to_vec()).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_database_scan_prefix_txn() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::open(Config::new(temp_dir.path())).unwrap();
+        db.put(b"user:1".to_vec(), b"ann".to_vec()).unwrap();
+        db.put(b"user:2".to_vec(), b"bob".to_vec()).unwrap();
+
+        let t1 = db.begin_txn();
+        db.delete_txn(t1, b"user:2".to_vec()).unwrap();
+        db.put_txn(t1, b"user:3".to_vec(), b"cy".to_vec()).unwrap();
+
+        let t2 = db.begin_txn();
+        db.put_txn(t2, b"user:4".to_vec(), b"dee".to_vec()).unwrap();
+        db.put_txn(t2, b"other".to_vec(), b"x".to_vec()).unwrap();
+        db.commit_txn(t2).unwrap();
+
+        let keys: Vec<Key> = db
+            .scan_prefix_txn(t1, b"user:")
+            .unwrap()
+            .into_iter()
+            .map(|(k, _)| k)
+            .collect();
+        assert_eq!(keys, vec![b"user:1".to_vec(), b"user:3".to_vec()]);
+
+        // t2's insert landed in the range t1 scanned.
+        assert!(matches!(db.commit_txn(t1), Err(Error::TransactionConflict)));
+    }
+
+    #[test]
+    fn test_database_merge_txn() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::open(Config::new(temp_dir.path())).unwrap();
+        db.put(b"log".to_vec(), b"x".to_vec()).unwrap();
+
+        let t1 = db.begin_txn();
+        db.merge_txn(t1, b"log".to_vec(), b"a".to_vec()).unwrap();
+        db.merge_txn(t1, b"log".to_vec(), b"b".to_vec()).unwrap();
+        assert_eq!(db.get_txn(t1, &b"log".to_vec()).unwrap(), Some(b"xab".to_vec()));
+        db.commit_txn(t1).unwrap();
+
+        let t2 = db.begin_txn();
+        db.merge_txn(t2, b"log".to_vec(), b"c".to_vec()).unwrap();
+        db.merge_txn(t2, b"new".to_vec(), b"c".to_vec()).unwrap();
+        db.commit_txn(t2).unwrap();
+
+        assert_eq!(db.get(&b"log".to_vec()).unwrap(), Some(b"xabc".to_vec()));
+        assert_eq!(db.get(&b"new".to_vec()).unwrap(), Some(b"c".to_vec()));
+    }
+
+    #[test]
+    fn test_database_delete_range_txn() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::open(Config::new(temp_dir.path())).unwrap();
+        for key in [b"a", b"b", b"c", b"d"] {
+            db.put(key.to_vec(), b"v".to_vec()).unwrap();
+        }
+        let snapshot = db.begin_read_only_txn();
+
+        let t1 = db.begin_txn();
+        db.delete_range_txn(t1, b"b".to_vec(), b"d".to_vec()).unwrap();
+        db.put_txn(t1, b"c".to_vec(), b"new".to_vec()).unwrap();
+        assert_eq!(db.get_txn(t1, &b"b".to_vec()).unwrap(), None);
+        assert_eq!(db.scan_prefix_txn(t1, b"").unwrap().len(), 3);
+        db.commit_txn(t1).unwrap();
+
+        let keys: Vec<Key> = db.scan_prefix(b"").unwrap().into_iter().map(|(k, _)| k).collect();
+        assert_eq!(keys, vec![b"a".to_vec(), b"c".to_vec(), b"d".to_vec()]);
+        assert_eq!(db.get(&b"c".to_vec()).unwrap(), Some(b"new".to_vec()));
+        // A snapshot from before the commit still sees the deleted key.
+        assert_eq!(db.get_txn(snapshot, &b"b".to_vec()).unwrap(), Some(b"v".to_vec()));
+    }
+
+    #[test]
+    fn test_compare_and_swap() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::open(Config::new(temp_dir.path())).unwrap();
+        let key = b"k".to_vec();
+
+        let outcome = db.compare_and_swap(key.clone(), None, Some(b"1".to_vec())).unwrap();
+        assert_eq!(outcome, CasOutcome { succeeded: true, current: Some(b"1".to_vec()) });
+        let outcome = db.compare_and_swap(key.clone(), None, Some(b"2".to_vec())).unwrap();
+        assert_eq!(outcome, CasOutcome { succeeded: false, current: Some(b"1".to_vec()) });
+        let outcome = db.compare_and_swap(key.clone(), Some(b"2"), None).unwrap();
+        assert!(!outcome.succeeded);
+        let outcome = db.compare_and_swap(key.clone(), Some(b"1"), None).unwrap();
+        assert_eq!(outcome, CasOutcome { succeeded: true, current: None });
+        assert_eq!(db.get(&key).unwrap(), None);
+    }
+
+    #[test]
+    fn test_compare_and_swap_concurrent_increments() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(Database::open(Config::new(temp_dir.path())).unwrap());
+        db.put(b"n".to_vec(), b"0".to_vec()).unwrap();
+
+        let workers: Vec<_> = (0..4)
+            .map(|_| {
+                let db = Arc::clone(&db);
+                std::thread::spawn(move || {
+                    for _ in 0..25 {
+                        let mut current = db.get(&b"n".to_vec()).unwrap();
+                        loop {
+                            let n: u64 = std::str::from_utf8(current.as_deref().unwrap())
+                                .unwrap()
+                                .parse()
+                                .unwrap();
+                            let next = (n + 1).to_string().into_bytes();
+                            let outcome = db
+                                .compare_and_swap(b"n".to_vec(), current.as_deref(), Some(next))
+                                .unwrap();
+                            if outcome.succeeded {
+                                break;
+                            }
+                            current = outcome.current;
+                        }
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+        assert_eq!(db.get(&b"n".to_vec()).unwrap(), Some(b"100".to_vec()));
+    }
+
+    #[test]
+    fn test_wal_since() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::open(Config::new(temp_dir.path())).unwrap();
+        assert!(db.wal_since(0).unwrap().is_empty());
+        db.put(b"a".to_vec(), b"1".to_vec()).unwrap();
+        db.delete(b"a".to_vec()).unwrap();
+        let txn = db.begin_txn();
+        db.put_txn(txn, b"b".to_vec(), b"2".to_vec()).unwrap();
+        db.commit_txn(txn).unwrap();
+
+        let sequences: Vec<_> = db.wal_since(1).unwrap().iter().map(|e| e.sequence_number).collect();
+        assert_eq!(sequences, [1, 2]);
+        assert_eq!(db.next_sequence(), 3);
+        assert!(db.wal_since(3).unwrap().is_empty());
+        assert!(matches!(db.wal_since(4), Err(Error::SnapshotRequired(_))));
+    }
+
+    #[test]
+    fn test_wal_since_gap() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = Config::new(temp_dir.path());
+        fs::create_dir_all(&config.wal_dir).unwrap();
+        {
+            // A WAL whose records before 10 are gone.
+            let mut wal = WalWriter::create(config.wal_dir.join("wal.log")).unwrap();
+            wal.append(&WalEntry::put(10, b"a".to_vec(), b"1".to_vec())).unwrap();
+            wal.sync().unwrap();
+        }
+        let db = Database::open(config).unwrap();
+
+        assert!(matches!(db.wal_since(0), Err(Error::SnapshotRequired(_))));
+        assert_eq!(db.wal_since(10).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_apply_replicated() {
+        let leader_dir = TempDir::new().unwrap();
+        let replica_dir = TempDir::new().unwrap();
+        let leader = Database::open(Config::new(leader_dir.path())).unwrap();
+        let shipped = Arc::new(Mutex::new(Vec::new()));
+        let log = Arc::clone(&shipped);
+        leader.on_wal_append(Box::new(move |entry| log.lock().unwrap().push(entry.clone())));
+
+        leader.put(b"a".to_vec(), b"1".to_vec()).unwrap();
+        let txn = leader.begin_txn();
+        leader.put_txn(txn, b"b".to_vec(), b"2".to_vec()).unwrap();
+        leader.delete_txn(txn, b"a".to_vec()).unwrap();
+        leader.commit_txn(txn).unwrap();
+        let entries = shipped.lock().unwrap().clone();
+        assert_eq!(entries.len(), 2);
+
+        {
+            let replica = Database::open(Config::new(replica_dir.path())).unwrap();
+            replica.close().unwrap();
+            let replica = Database::open(Config::new(replica_dir.path())).unwrap();
+            assert_eq!(replica.next_sequence(), 0);
+            replica.apply_replicated(entries[0].clone()).unwrap();
+            assert_eq!(replica.next_sequence(), 1);
+            replica.close().unwrap();
+        }
+        // Reopened, it carries on from where it got to, and skips what it
+        // has already.
+        let replica = Database::open(Config::new(replica_dir.path())).unwrap();
+        assert_eq!(replica.next_sequence(), 1);
+        for entry in entries {
+            replica.apply_replicated(entry).unwrap();
+        }
+        assert_eq!(replica.get(&b"a".to_vec()).unwrap(), None);
+        assert_eq!(replica.get(&b"b".to_vec()).unwrap(), Some(b"2".to_vec()));
+        assert_eq!(replica.next_sequence(), leader.next_sequence());
+        assert_eq!(replica.wal_since(0).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_prefix_end() {
+        assert_eq!(prefix_end(b"ab"), b"ac".to_vec());
+        assert_eq!(prefix_end(b"a\xff"), b"b".to_vec());
+        assert!(prefix_end(b"\xff\xff").is_empty());
+    }
+
+    #[test]
+    fn test_commit_version_survives_reopen() {
+        let temp_dir = TempDir::new().unwrap();
+
+        {
+            let db = Database::open(Config::new(temp_dir.path())).unwrap();
+            for i in 0..3u8 {
+                let txn = db.begin_txn();
+                db.put_txn(txn, vec![i], vec![i]).unwrap();
+                db.commit_txn(txn).unwrap();
+            }
+            // Aborts and empty commits log nothing.
+            let txn = db.begin_txn();
+            db.put_txn(txn, b"aborted".to_vec(), b"x".to_vec()).unwrap();
+            db.abort_txn(txn).unwrap();
+        }
+
+        let db = Database::open(Config::new(temp_dir.path())).unwrap();
+        assert_eq!(db.txn_manager.current_version(), 3);
+        assert!(db.get(&b"aborted".to_vec()).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_batch_recovery_is_all_or_nothing() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = Config::new(temp_dir.path());
+        fs::create_dir_all(&config.wal_dir).unwrap();
+        let wal_path = config.wal_dir.join("wal.log");
+
+        let batch = |version: u64, keys: &[&[u8]]| WalBatch {
+            commit_version: version,
+            ops: keys.iter().map(|k| (k.to_vec(), Some(b"v".to_vec()))).collect(),
+        };
+
+        // A commit whose record was synced but never reached the memtable,
+        // followed by one torn halfway through its record.
+        {
+            let mut wal = WalWriter::create(&wal_path).unwrap();
+            wal.append(&WalEntry::put(0, b"plain".to_vec(), b"v".to_vec())).unwrap();
+            wal.append(&WalEntry::batch(1, &batch(1, &[b"a1", b"a2", b"a3"]))).unwrap();
+            wal.sync().unwrap();
+        }
+        let torn = WalEntry::batch(2, &batch(2, &[b"b1", b"b2", b"b3"])).encode();
+        let mut file = fs::OpenOptions::new().append(true).open(&wal_path).unwrap();
+        std::io::Write::write_all(&mut file, &torn[..torn.len() / 2]).unwrap();
+        drop(file);
+
+        let db = Database::open(config.clone()).unwrap();
+        for key in [&b"plain"[..], b"a1", b"a2", b"a3"] {
+            assert_eq!(db.get(&key.to_vec()).unwrap(), Some(b"v".to_vec()));
+        }
+        for key in [&b"b1"[..], b"b2", b"b3"] {
+            assert!(db.get(&key.to_vec()).unwrap().is_none());
+        }
+        assert_eq!(db.txn_manager.current_version(), 1);
+
+        // The torn tail was cut off, so later writes replay cleanly.
+        db.put(b"after".to_vec(), b"v".to_vec()).unwrap();
+        drop(db);
+        let db = Database::open(config).unwrap();
+        assert_eq!(db.get(&b"after".to_vec()).unwrap(), Some(b"v".to_vec()));
+    }
+
+    #[test]
+    fn test_unsynced_writes_survive_reopen() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut config = Config::new(temp_dir.path());
+        config.sync_writes = false;
+        {
+            let db = Database::open(config.clone()).unwrap();
+            db.put(b"a".to_vec(), b"1".to_vec()).unwrap();
+            db.delete(b"a".to_vec()).unwrap();
+            db.put(b"b".to_vec(), b"2".to_vec()).unwrap();
+        }
+
+        let db = Database::open(config).unwrap();
+        assert_eq!(db.get(&b"a".to_vec()).unwrap(), None);
+        assert_eq!(db.get(&b"b".to_vec()).unwrap(), Some(b"2".to_vec()));
+    }
+
+    #[test]
+    fn test_write_batch() {
+        let temp_dir = TempDir::new().unwrap();
+        {
+            let db = Database::open(Config::new(temp_dir.path())).unwrap();
+            db.put(b"gone".to_vec(), b"v".to_vec()).unwrap();
+            let mut batch = WriteBatch::new();
+            batch
+                .put(b"a".to_vec(), b"1".to_vec())
+                .put(b"b".to_vec(), b"2".to_vec())
+                .delete(b"gone".to_vec())
+                .put(b"a".to_vec(), b"3".to_vec());
+            db.write(batch).unwrap();
+            // One record for the whole batch; an empty one logs nothing.
+            db.write(WriteBatch::new()).unwrap();
+            assert_eq!(db.next_sequence(), 2);
+            assert_eq!(db.get(&b"a".to_vec()).unwrap(), Some(b"3".to_vec()));
+        }
+
+        let db = Database::open(Config::new(temp_dir.path())).unwrap();
+        assert_eq!(db.get(&b"a".to_vec()).unwrap(), Some(b"3".to_vec()));
+        assert_eq!(db.get(&b"b".to_vec()).unwrap(), Some(b"2".to_vec()));
+        assert!(db.get(&b"gone".to_vec()).unwrap().is_none());
+        assert_eq!(db.txn_manager.current_version(), 0);
+    }
+
+    #[test]
+    fn test_database_stats_report_retained_versions() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::open(Config::new(temp_dir.path())).unwrap();
+
+        let reader = db.begin_read_only_txn();
+        let txn = db.begin_txn();
+        db.put_txn(txn, b"key".to_vec(), b"value".to_vec()).unwrap();
+        db.commit_txn(txn).unwrap();
+
+        let stats = db.stats();
+        assert_eq!(stats.oldest_active_version, Some(0));
+        assert_eq!(stats.txn_retained_versions, 2);
+        assert_eq!(stats.txn_retained_bytes, 3 + 3 + 5);
+
+        db.commit_txn(reader).unwrap();
+        assert_eq!(db.stats().oldest_active_version, None);
+    }
+
+    #[test]
+    fn test_database_stats_report_txn_metrics() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::open(Config::new(temp_dir.path())).unwrap();
+
+        let t1 = db.begin_txn();
+        let t2 = db.begin_txn();
+        db.put_txn(t1, b"k".to_vec(), b"1".to_vec()).unwrap();
+        db.put_txn(t2, b"k".to_vec(), b"2".to_vec()).unwrap();
+        db.commit_txn(t1).unwrap();
+        assert!(db.commit_txn(t2).is_err());
+        let open = db.begin_txn();
+
+        let txn = db.stats().txn;
+        assert_eq!((txn.begun, txn.committed, txn.aborted_conflict), (3, 1, 1));
+        assert_eq!(txn.top_conflict_keys, vec![(b"k".to_vec(), 1)]);
+        assert_eq!(db.list_active_txns()[0].id, open);
+    }
 }
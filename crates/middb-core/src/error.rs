@@ -9,10 +9,17 @@ pub enum Error {
     Serialization(String),
     KeyNotFound,
     TransactionConflict,
+    TransactionTimedOut,
+    SnapshotTooOld,
+    /// A replica can't catch up from the WAL, and needs a copy of the
+    /// whole database first.
+    SnapshotRequired(String),
     StorageFull,
     Corruption(String),
     InvalidConfig(String),
     InvalidArgument(String),
+    /// Another process has the database open.
+    Locked(String),
     Internal(String),
 }
 
@@ -23,10 +30,14 @@ impl fmt::Display for Error {
             Error::Serialization(msg) => write!(f, "Serialization error: {}", msg),
             Error::KeyNotFound => write!(f, "Key not found"),
             Error::TransactionConflict => write!(f, "Transaction conflict"),
+            Error::TransactionTimedOut => write!(f, "Transaction timed out"),
+            Error::SnapshotTooOld => write!(f, "Snapshot too old"),
+            Error::SnapshotRequired(msg) => write!(f, "Snapshot required: {}", msg),
             Error::StorageFull => write!(f, "Storage full"),
             Error::Corruption(msg) => write!(f, "Data corruption: {}", msg),
             Error::InvalidConfig(msg) => write!(f, "Invalid configuration: {}", msg),
             Error::InvalidArgument(msg) => write!(f, "Invalid argument: {}", msg),
+            Error::Locked(msg) => write!(f, "Database locked: {}", msg),
             Error::Internal(msg) => write!(f, "Internal error: {}", msg),
         }
     }
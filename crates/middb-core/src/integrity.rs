@@ -0,0 +1,284 @@
+//! Checking a closed database's files for damage, and salvaging what can be
+//! read from them.
+
+use crate::db::Database;
+use crate::sstable::SSTableReader;
+use crate::wal::WalReader;
+use crate::{Config, Result};
+use std::fs;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Where `Database::repair` moves what it can't use.
+pub const LOST_DIR: &str = "lost";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileKind {
+    Wal,
+    SSTable,
+}
+
+/// One file `Database::verify_integrity` read, and what's wrong with it.
+#[derive(Debug, Clone)]
+pub struct FileCheck {
+    pub path: PathBuf,
+    pub kind: FileKind,
+    pub size: u64,
+    /// Records read whole: entries for an SSTable, records for the WAL.
+    pub entries: u64,
+    pub problems: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct IntegrityReport {
+    /// The WAL, if there is one, then the SSTables by name.
+    pub files: Vec<FileCheck>,
+}
+
+impl IntegrityReport {
+    pub fn problems(&self) -> usize {
+        self.files.iter().map(|f| f.problems.len()).sum()
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.problems() == 0
+    }
+}
+
+/// What `Database::repair` moved into `lost/`.
+#[derive(Debug, Clone, Default)]
+pub struct RepairReport {
+    /// SSTables that couldn't be read whole, and where they went.
+    pub quarantined: Vec<(PathBuf, PathBuf)>,
+    /// The WAL from its first unreadable record on: where it started, how
+    /// many bytes it ran to, and where they went.
+    pub wal_tail: Option<(u64, u64, PathBuf)>,
+}
+
+impl RepairReport {
+    pub fn is_empty(&self) -> bool {
+        self.quarantined.is_empty() && self.wal_tail.is_none()
+    }
+}
+
+/// How far the WAL at `path` reads: the records before the first that
+/// won't, where that one starts, and why it won't, if any doesn't.
+fn scan_wal(path: &Path) -> Result<(u64, u64, Option<String>)> {
+    let size = fs::metadata(path)?.len();
+    let mut reader = WalReader::open(path)?;
+    let mut records = 0;
+    loop {
+        match reader.next_entry() {
+            Ok(Some(_)) => records += 1,
+            Ok(None) if reader.offset() < size => {
+                let torn = size - reader.offset();
+                let problem = format!("torn record: {} bytes after the last whole one", torn);
+                return Ok((records, reader.offset(), Some(problem)));
+            }
+            Ok(None) => return Ok((records, size, None)),
+            Err(e) => return Ok((records, reader.offset(), Some(e.to_string()))),
+        }
+    }
+}
+
+/// The SSTables in `data_dir`, by name.
+fn sstable_paths(data_dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(data_dir)? {
+        let path = entry?.path();
+        if path.is_file() && path.extension().is_some_and(|e| e == "sst") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+fn check_sstable(path: &Path) -> Result<FileCheck> {
+    let size = fs::metadata(path)?.len();
+    let mut entries = 0;
+    let problems = match SSTableReader::open(path) {
+        Ok(reader) => {
+            let problems: Vec<String> = reader
+                .verify()?
+                .into_iter()
+                .map(|p| format!("offset {}: {}", p.offset, p.message))
+                .collect();
+            // Verification has read every block already; a failure here
+            // only repeats one of its problems.
+            if let Ok(mut iter) = reader.iter() {
+                while iter.valid() {
+                    entries += 1;
+                    if iter.next().is_err() {
+                        break;
+                    }
+                }
+            }
+            problems
+        }
+        Err(e) => vec![format!("unreadable: {}", e)],
+    };
+    Ok(FileCheck { path: path.to_path_buf(), kind: FileKind::SSTable, size, entries, problems })
+}
+
+/// `name` in `lost/`, numbered if something's there already.
+fn lost_path(lost: &Path, name: &str) -> PathBuf {
+    let mut path = lost.join(name);
+    let mut n = 1;
+    while path.exists() {
+        path = lost.join(format!("{}.{}", name, n));
+        n += 1;
+    }
+    path
+}
+
+impl Database {
+    /// Read every file of the database `config` names and check it: each
+    /// SSTable's footer, blocks, key order and bloom filter, and each WAL
+    /// record's CRC. Nothing is written, and the database needn't open.
+    pub fn verify_integrity(config: &Config) -> Result<IntegrityReport> {
+        let mut report = IntegrityReport::default();
+
+        let wal_path = config.wal_dir.join("wal.log");
+        if wal_path.exists() {
+            let size = fs::metadata(&wal_path)?.len();
+            let (entries, _, problem) = scan_wal(&wal_path)?;
+            let problems = problem.into_iter().collect();
+            let kind = FileKind::Wal;
+            report.files.push(FileCheck { path: wal_path, kind, size, entries, problems });
+        }
+        if config.data_dir.is_dir() {
+            for path in sstable_paths(&config.data_dir)? {
+                report.files.push(check_sstable(&path)?);
+            }
+        }
+        Ok(report)
+    }
+
+    /// Salvage a database that won't open, or opens missing data: cut the
+    /// WAL back to its last readable record and move SSTables that don't
+    /// verify aside, both into `lost/` under the data directory. Nothing
+    /// is deleted. There's no manifest to rebuild; opening the database
+    /// afterwards replays what's left of the WAL.
+    ///
+    /// Fails with `Error::Locked` while another process has it open.
+    pub fn repair(config: &Config) -> Result<RepairReport> {
+        let _lock = Self::lock_data_dir(config)?;
+        let lost = config.data_dir.join(LOST_DIR);
+        let mut report = RepairReport::default();
+
+        let wal_path = config.wal_dir.join("wal.log");
+        if wal_path.exists() {
+            let (_, valid, problem) = scan_wal(&wal_path)?;
+            if problem.is_some() {
+                let mut wal = fs::OpenOptions::new().read(true).write(true).open(&wal_path)?;
+                let size = wal.metadata()?.len();
+                let mut tail = Vec::new();
+                wal.seek(SeekFrom::Start(valid))?;
+                wal.read_to_end(&mut tail)?;
+
+                fs::create_dir_all(&lost)?;
+                let moved = lost_path(&lost, &format!("wal.log.{}", valid));
+                let mut out = fs::File::create(&moved)?;
+                out.write_all(&tail)?;
+                out.sync_all()?;
+                // Only once the tail is safely elsewhere.
+                wal.set_len(valid)?;
+                wal.sync_all()?;
+                report.wal_tail = Some((valid, size, moved));
+            }
+        }
+
+        for path in sstable_paths(&config.data_dir)? {
+            if check_sstable(&path)?.problems.is_empty() {
+                continue;
+            }
+            fs::create_dir_all(&lost)?;
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            let moved = lost_path(&lost, &name);
+            fs::rename(&path, &moved)?;
+            report.quarantined.push((path, moved));
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+    use tempfile::TempDir;
+
+    /// A closed database with some SSTables, and more in its WAL.
+    fn make_db(dir: &TempDir) -> Config {
+        let config = Config::new(dir.path());
+        let db = Database::open(config.clone()).unwrap();
+        for round in 0..2 {
+            for i in 0..20 {
+                let key = format!("key{:02}", i).into_bytes();
+                db.put(key, format!("v{}", round).into_bytes()).unwrap();
+            }
+            db.flush().unwrap();
+        }
+        db.put(b"last".to_vec(), b"1".to_vec()).unwrap();
+        db.close().unwrap();
+        config
+    }
+
+    #[test]
+    fn test_sound_database() {
+        let dir = TempDir::new().unwrap();
+        let config = make_db(&dir);
+        let report = Database::verify_integrity(&config).unwrap();
+        assert!(report.is_ok());
+        assert_eq!(report.files[0].kind, FileKind::Wal);
+        assert_eq!(report.files[0].entries, 41);
+        assert_eq!(report.files.len(), 4);
+        assert!(report.files[1..].iter().all(|f| f.kind == FileKind::SSTable && f.entries > 0));
+
+        assert!(Database::repair(&config).unwrap().is_empty());
+        assert!(!dir.path().join(LOST_DIR).exists());
+    }
+
+    #[test]
+    fn test_repair_moves_damage_aside() {
+        let dir = TempDir::new().unwrap();
+        let config = make_db(&dir);
+        let wal_path = config.wal_dir.join("wal.log");
+        let wal_size = fs::metadata(&wal_path).unwrap().len();
+        let mut wal = fs::OpenOptions::new().append(true).open(&wal_path).unwrap();
+        wal.write_all(&[7; 12]).unwrap();
+        let sst = sstable_paths(&config.data_dir).unwrap().remove(0);
+        let mut bytes = fs::read(&sst).unwrap();
+        bytes[0] ^= 0xff;
+        fs::write(&sst, &bytes).unwrap();
+
+        let report = Database::verify_integrity(&config).unwrap();
+        assert_eq!(report.files.iter().filter(|f| !f.problems.is_empty()).count(), 2);
+        assert!(report.files[0].problems[0].starts_with("torn record"));
+        // Checking changed nothing.
+        assert_eq!(fs::metadata(&wal_path).unwrap().len(), wal_size + 12);
+
+        let report = Database::repair(&config).unwrap();
+        let (offset, end, moved) = report.wal_tail.unwrap();
+        assert_eq!((offset, end), (wal_size, wal_size + 12));
+        assert_eq!(fs::read(moved).unwrap(), [7; 12]);
+        assert_eq!(report.quarantined.len(), 1);
+        assert_eq!(report.quarantined[0].0, sst);
+        assert_eq!(fs::read(&report.quarantined[0].1).unwrap(), bytes);
+        assert!(!sst.exists());
+
+        assert!(Database::verify_integrity(&config).unwrap().is_ok());
+        let db = Database::open(config).unwrap();
+        assert_eq!(db.get(&b"key03".to_vec()).unwrap(), Some(b"v1".to_vec()));
+        assert_eq!(db.get(&b"last".to_vec()).unwrap(), Some(b"1".to_vec()));
+    }
+
+    #[test]
+    fn test_repair_refuses_open_database() {
+        let dir = TempDir::new().unwrap();
+        let config = make_db(&dir);
+        let _db = Database::open(config.clone()).unwrap();
+        assert!(matches!(Database::repair(&config), Err(Error::Locked(_))));
+    }
+}
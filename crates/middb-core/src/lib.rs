@@ -16,12 +16,16 @@ pub mod storage;
 pub mod catalog;
 pub mod transaction;
 pub mod db;
+pub mod integrity;
+pub mod batch;
 pub use error::{Error, Result};
 pub use config::{Config, CompactionStyle};
 pub use types::{Key, Value, SequenceNumber, Timestamp, PageId, FileId, Level};
 pub use memtable::{MemTable, ValueEntry};
 pub use skiplist::SkipList;
 pub use bptree::BPTree;
-pub use db::{Database, DatabaseStats};
-pub use catalog::{Catalog, CatalogError, CatalogResult, Column, DataType, TableSchema, TableSchemaBuilder};
-pub use transaction::{Transaction, TransactionManager, TxnError, TxnId, TxnStatus, Version, WriteOp};
+pub use batch::WriteBatch;
+pub use db::{CasOutcome, CompactionPass, Database, DatabaseStats, LevelStats, ScanPage, WalListener, WriteEvent, WriteListener};
+pub use integrity::{FileCheck, FileKind, IntegrityReport, RepairReport};
+pub use catalog::{AlterOp, Catalog, CatalogError, CatalogResult, Column, ColumnStats, DataType, Datum, IdentifierCasing, IndexDef, TableSchema, TableSchemaBuilder, TableStats};
+pub use transaction::{AppendOperator, IsolationLevel, MergeOperator, Transaction, TransactionManager, TxnError, TxnId, TxnInfo, TxnMetrics, TxnOptions, TxnStatus, Version, Visible, WriteOp};
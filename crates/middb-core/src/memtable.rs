@@ -79,6 +79,12 @@ impl<K: Ord + Default, V: Default> MemTable<K, V> {
         }
     }
 
+    /// Like `get`, but distinguishes a deleted key (`Some(Tombstone)`) from one
+    /// this memtable has never seen (`None`).
+    pub fn get_entry(&self, key: &K) -> Option<&ValueEntry<V>> {
+        self.data.get(key)
+    }
+
     pub fn delete(&mut self, key: K) -> std::result::Result<(), String>
     where
         K: AsRef<[u8]>,
@@ -116,7 +122,7 @@ impl<K: Ord + Default, V: Default> MemTable<K, V> {
         K: AsRef<[u8]>,
         V: AsRef<[u8]>,
     {
-        let mut writer = SSTableWriter::create(path, block_size)?;
+        let mut writer = SSTableWriter::create_with_expected_entries(path, block_size, 10, self.len())?;
         
         for (key, entry) in self.iter() {
             let key_bytes: &[u8] = (*key).as_ref();
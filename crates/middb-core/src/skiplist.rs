@@ -196,6 +196,18 @@ impl<K: Ord + Default, V: Default> SkipList<K, V> {
     }
 }
 
+// Each node owns the rest of the list, so dropping the head would recurse
+// once per node and overflow the stack on a long list. Unlink them one by
+// one instead.
+impl<K, V> Drop for SkipList<K, V> {
+    fn drop(&mut self) {
+        let mut unlinked: Vec<_> = self.head.forward.iter_mut().filter_map(Option::take).collect();
+        while let Some(mut node) = unlinked.pop() {
+            unlinked.extend(node.forward.iter_mut().filter_map(Option::take));
+        }
+    }
+}
+
 impl<K: Ord + Default, V: Default> Default for SkipList<K, V> {
     fn default() -> Self {
         Self::new()
@@ -268,6 +280,17 @@ mod tests {
         assert_eq!(list.get(&4), None);
     }
 
+    #[test]
+    fn test_long_list_dropped() {
+        let mut list = SkipList::new();
+        // Smallest first each time, so each insert is quick.
+        for i in (0..500_000).rev() {
+            list.insert(i, i);
+        }
+        assert_eq!(list.len(), 500_000);
+        drop(list);
+    }
+
     #[test]
     fn test_update_existing() {
         let mut list = SkipList::new();
@@ -53,10 +53,10 @@ impl Block {
             return Err(Error::Corruption("Block has no restart points".to_string()));
         }
         
-        let restarts_offset = num_restarts_offset - num_restarts * 4;
-        if restarts_offset > data.len() {
-            return Err(Error::Corruption("Invalid restart points".to_string()));
-        }
+        let restarts_offset = num_restarts
+            .checked_mul(4)
+            .and_then(|size| num_restarts_offset.checked_sub(size))
+            .ok_or_else(|| Error::Corruption("Invalid restart points".to_string()))?;
         
         // Read restart points
         let mut restarts = Vec::with_capacity(num_restarts);
@@ -68,6 +68,9 @@ impl Block {
                 data[offset + 2],
                 data[offset + 3],
             ]);
+            if restart as usize > restarts_offset {
+                return Err(Error::Corruption("Restart point past block data".to_string()));
+            }
             restarts.push(restart);
         }
         
@@ -207,6 +210,18 @@ impl BlockIterator {
         !self.key.is_empty()
     }
     
+    /// Where the next entry starts in the block's data. Once the iterator
+    /// is no longer valid this is the end of the data, or the start of an
+    /// entry that can't be decoded.
+    pub fn offset(&self) -> usize {
+        self.current
+    }
+    
+    /// Whether every entry up to the end of the data has been read.
+    pub fn at_end(&self) -> bool {
+        self.current >= self.data.len()
+    }
+    
     pub fn next(&mut self) {
         if let Some((key, value)) = self.parse_next_entry() {
             self.key = key;
@@ -228,13 +243,15 @@ impl BlockIterator {
             return None;
         }
         
-        let shared = self.decode_varint()?;
-        let non_shared = self.decode_varint()?;
-        let value_len = self.decode_varint()?;
-        
-        if self.current + non_shared + value_len > self.data.len() {
-            return None;
-        }
+        let start = self.current;
+        let entry = self.decode_entry_header();
+        let (shared, non_shared, value_len) = match entry {
+            Some(header) => header,
+            None => {
+                self.current = start;
+                return None;
+            }
+        };
         
         let mut key = Vec::with_capacity(shared + non_shared);
         key.extend_from_slice(&self.key[..shared]);
@@ -247,6 +264,20 @@ impl BlockIterator {
         Some((key, value))
     }
     
+    /// The shared, non-shared and value lengths of the entry at `current`,
+    /// if they fit the data and the key before it.
+    fn decode_entry_header(&mut self) -> Option<(usize, usize, usize)> {
+        let shared = self.decode_varint()?;
+        let non_shared = self.decode_varint()?;
+        let value_len = self.decode_varint()?;
+        
+        let end = self.current.checked_add(non_shared)?.checked_add(value_len)?;
+        if shared > self.key.len() || end > self.data.len() {
+            return None;
+        }
+        Some((shared, non_shared, value_len))
+    }
+    
     fn decode_varint(&mut self) -> Option<usize> {
         let mut result = 0u64;
         let mut shift = 0;
@@ -7,5 +7,5 @@ mod iter;
 pub use block::{Block, BlockBuilder, BlockIterator};
 pub use footer::{BlockHandle, Footer, SSTableMetadata, FOOTER_SIZE};
 pub use writer::SSTableWriter;
-pub use reader::{SSTableReader, SSTableIterator};
+pub use reader::{BloomCounters, SSTableProblem, SSTableProperties, SSTableReader, SSTableIterator};
 pub use iter::MergeIterator;
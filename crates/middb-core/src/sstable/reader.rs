@@ -1,17 +1,58 @@
 use super::block::{Block, BlockIterator};
 use super::footer::{BlockHandle, Footer, FOOTER_SIZE};
-use crate::bloom::BloomFilter;
+use crate::bloom::{BloomFilter, BloomFilterStats};
 use crate::{Error, Result};
 use std::fs::File;
 use std::io::{Read, Seek, SeekFrom};
 use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 
+#[derive(Debug, Clone)]
+pub struct SSTableProperties {
+    pub file_size: u64,
+    pub bloom: Option<BloomFilterStats>,
+    pub bloom_probes: u64,
+    pub bloom_negatives: u64,
+}
+
+/// Something wrong with an SSTable file, at the byte offset it was found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SSTableProblem {
+    pub offset: u64,
+    pub message: String,
+}
+
+/// Bloom filter probes, and the negatives among them that saved reading
+/// the file.
+#[derive(Debug, Default)]
+pub struct BloomCounters {
+    probes: AtomicU64,
+    negatives: AtomicU64,
+}
+
+impl BloomCounters {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// `(probes, negatives)` so far.
+    pub fn load(&self) -> (u64, u64) {
+        (self.probes.load(Ordering::Relaxed), self.negatives.load(Ordering::Relaxed))
+    }
+    
+    fn add(&self, (probes, negatives): (u64, u64)) {
+        self.probes.fetch_add(probes, Ordering::Relaxed);
+        self.negatives.fetch_add(negatives, Ordering::Relaxed);
+    }
+}
+
 pub struct SSTableReader {
     file: Arc<File>,
     footer: Footer,
     file_size: u64,
     bloom_filter: Option<BloomFilter>,
+    bloom_counters: Arc<BloomCounters>,
 }
 
 impl SSTableReader {
@@ -29,6 +70,8 @@ impl SSTableReader {
         file.read_exact(&mut footer_bytes)?;
         
         let footer = Footer::decode(&footer_bytes)?;
+        check_handle(&footer.bloom_handle, file_size)?;
+        check_handle(&footer.index_handle, file_size)?;
         
         let bloom_filter = {
             file.seek(SeekFrom::Start(footer.bloom_handle.offset))?;
@@ -42,12 +85,15 @@ impl SSTableReader {
             footer,
             file_size,
             bloom_filter,
+            bloom_counters: Arc::new(BloomCounters::default()),
         })
     }
     
     pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
         if let Some(ref bloom) = self.bloom_filter {
+            self.bloom_counters.probes.fetch_add(1, Ordering::Relaxed);
             if !bloom.may_contain(key) {
+                self.bloom_counters.negatives.fetch_add(1, Ordering::Relaxed);
                 return Ok(None);
             }
         }
@@ -80,6 +126,7 @@ impl SSTableReader {
     }
     
     fn read_block(&self, handle: &BlockHandle) -> Result<Block> {
+        check_handle(handle, self.file_size)?;
         let mut file = self.file.as_ref();
         
         file.seek(SeekFrom::Start(handle.offset))?;
@@ -93,6 +140,146 @@ impl SSTableReader {
     pub fn footer(&self) -> &Footer {
         &self.footer
     }
+    
+    pub fn bloom_filter(&self) -> Option<&BloomFilter> {
+        self.bloom_filter.as_ref()
+    }
+    
+    /// Each index entry's separator key and the data block it points at.
+    pub fn index_entries(&self) -> Result<Vec<(Vec<u8>, BlockHandle)>> {
+        let mut index_iter = BlockIterator::new(self.read_block(&self.footer.index_handle)?);
+        index_iter.seek(&[]);
+        let mut entries = Vec::new();
+        while index_iter.valid() {
+            entries.push((index_iter.key().to_vec(), BlockHandle::decode(index_iter.value())?));
+            index_iter.next();
+        }
+        if !index_iter.at_end() {
+            return Err(Error::Corruption(format!(
+                "Undecodable index entry at offset {}",
+                self.footer.index_handle.offset + index_iter.offset() as u64
+            )));
+        }
+        Ok(entries)
+    }
+    
+    /// Read the whole file and check it hangs together: the blocks tile it
+    /// in order, every entry decodes, keys ascend within and across blocks
+    /// and stay at or under their block's separator, and the bloom filter
+    /// holds every key. Returns what's wrong, nothing for a sound file.
+    pub fn verify(&self) -> Result<Vec<SSTableProblem>> {
+        let mut problems = Vec::new();
+        let mut problem = |offset: u64, message: String| {
+            problems.push(SSTableProblem { offset, message });
+        };
+        let Footer { index_handle, bloom_handle, .. } = self.footer;
+        
+        let index_block = match self.read_block(&index_handle) {
+            Ok(block) => block,
+            Err(e) => {
+                problem(index_handle.offset, format!("index block unreadable: {}", e));
+                return Ok(problems);
+            }
+        };
+        let mut index_iter = BlockIterator::new(index_block);
+        index_iter.seek(&[]);
+        
+        let mut expected_offset = 0;
+        let mut last_key: Option<Vec<u8>> = None;
+        while index_iter.valid() {
+            let separator = index_iter.key().to_vec();
+            let handle = match BlockHandle::decode(index_iter.value()) {
+                Ok(handle) => handle,
+                Err(e) => {
+                    let offset = index_handle.offset + index_iter.offset() as u64;
+                    problem(offset, format!("index entry {}: {}", show(&separator), e));
+                    index_iter.next();
+                    continue;
+                }
+            };
+            index_iter.next();
+            
+            if handle.offset != expected_offset {
+                let message =
+                    format!("data block starts at {}, expected {}", handle.offset, expected_offset);
+                problem(handle.offset, message);
+            }
+            expected_offset = handle.offset.saturating_add(handle.size);
+            if expected_offset > bloom_handle.offset {
+                problem(handle.offset, "data block runs into the bloom filter".to_string());
+                continue;
+            }
+            let block = match self.read_block(&handle) {
+                Ok(block) => block,
+                Err(e) => {
+                    problem(handle.offset, format!("data block unreadable: {}", e));
+                    continue;
+                }
+            };
+            
+            let mut data_iter = BlockIterator::new(block);
+            data_iter.seek(&[]);
+            while data_iter.valid() {
+                let key = data_iter.key();
+                if last_key.as_deref().is_some_and(|last| key <= last) {
+                    problem(handle.offset, format!("key {} out of order", show(key)));
+                }
+                if key > separator.as_slice() {
+                    let message =
+                        format!("key {} sorts after separator {}", show(key), show(&separator));
+                    problem(handle.offset, message);
+                }
+                if self.bloom_filter.as_ref().is_some_and(|bloom| !bloom.may_contain(key)) {
+                    problem(bloom_handle.offset, format!("bloom filter misses key {}", show(key)));
+                }
+                last_key = Some(key.to_vec());
+                data_iter.next();
+            }
+            if !data_iter.at_end() {
+                let offset = handle.offset + data_iter.offset() as u64;
+                problem(offset, "data block entry can't be decoded".to_string());
+            }
+        }
+        if !index_iter.at_end() {
+            let offset = index_handle.offset + index_iter.offset() as u64;
+            problem(offset, "index entry can't be decoded".to_string());
+        }
+        
+        if expected_offset != bloom_handle.offset {
+            let message = format!(
+                "data blocks end at {}, bloom filter starts at {}",
+                expected_offset, bloom_handle.offset
+            );
+            problem(expected_offset.min(bloom_handle.offset), message);
+        }
+        if self.bloom_filter.is_none() && bloom_handle.size > 0 {
+            problem(bloom_handle.offset, "bloom filter can't be decoded".to_string());
+        }
+        if bloom_handle.offset + bloom_handle.size != index_handle.offset {
+            problem(index_handle.offset, "bloom filter doesn't end at the index block".to_string());
+        }
+        let footer_offset = self.file_size - FOOTER_SIZE as u64;
+        if index_handle.offset + index_handle.size != footer_offset {
+            problem(footer_offset, "index block doesn't end at the footer".to_string());
+        }
+        Ok(problems)
+    }
+    
+    /// Add this reader's probes to `totals`, as it leaves the set the
+    /// database sums them over, so they aren't lost with it.
+    pub fn retire_into(&self, totals: &BloomCounters) {
+        totals.add(self.bloom_counters.load());
+    }
+    
+    pub fn properties(&self) -> SSTableProperties {
+        let (bloom_probes, bloom_negatives) = self.bloom_counters.load();
+        SSTableProperties {
+            file_size: self.file_size,
+            bloom: self.bloom_filter.as_ref().map(|b| b.stats()),
+            bloom_probes,
+            bloom_negatives,
+        }
+    }
 }
 
 pub struct SSTableIterator {
@@ -189,13 +376,44 @@ impl SSTableIterator {
         let mut data_iter = BlockIterator::new(data_block);
         data_iter.seek(target);
         
+        // The index key is a separator that can sort after the block's last
+        // key, so the target may fall past the end of this block.
+        if !data_iter.valid() {
+            self.index_iter.next();
+            if !self.index_iter.valid() {
+                self.valid = false;
+                return Ok(());
+            }
+            let handle = BlockHandle::decode(self.index_iter.value())?;
+            data_iter = BlockIterator::new(self.reader.read_block(&handle)?);
+            data_iter.seek(&[]);
+        }
+        
+        self.valid = data_iter.valid();
         self.data_iter = Some(data_iter);
-        self.valid = self.data_iter.as_ref().map_or(false, |i| i.valid());
         
         Ok(())
     }
 }
 
+/// Fail unless `handle` lies within a file of `file_size` bytes, before
+/// its footer.
+fn check_handle(handle: &BlockHandle, file_size: u64) -> Result<()> {
+    let limit = file_size - FOOTER_SIZE as u64;
+    match handle.offset.checked_add(handle.size) {
+        Some(end) if end <= limit => Ok(()),
+        _ => Err(Error::Corruption(format!(
+            "Block at {} of {} bytes is outside the file",
+            handle.offset, handle.size
+        ))),
+    }
+}
+
+/// A key for a message, with bytes outside printable ASCII escaped.
+fn show(key: &[u8]) -> String {
+    format!("\"{}\"", key.escape_ascii())
+}
+
 impl Clone for SSTableReader {
     fn clone(&self) -> Self {
         SSTableReader {
@@ -203,6 +421,7 @@ impl Clone for SSTableReader {
             footer: self.footer.clone(),
             file_size: self.file_size,
             bloom_filter: self.bloom_filter.clone(),
+            bloom_counters: Arc::clone(&self.bloom_counters),
         }
     }
 }
@@ -234,6 +453,39 @@ mod tests {
         assert_eq!(reader.get(b"key4").unwrap(), None);
     }
     
+    #[test]
+    fn test_sstable_properties() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let path = temp_file.path();
+        
+        let mut writer = SSTableWriter::create(path, 4096).unwrap();
+        for i in 0..100 {
+            let key = format!("key{:03}", i);
+            writer.add(key.as_bytes(), b"value").unwrap();
+        }
+        let metadata = writer.finish(1, 0).unwrap();
+        
+        let reader = SSTableReader::open(path).unwrap();
+        let props = reader.properties();
+        assert_eq!(props.file_size, metadata.file_size);
+        
+        let bloom = props.bloom.unwrap();
+        assert_eq!(bloom.num_bits, 1000);
+        assert!(bloom.fill_ratio > 0.0 && bloom.fill_ratio < 1.0);
+        assert!(bloom.estimated_fp_rate < 0.05);
+        assert_eq!(props.bloom_probes, 0);
+        
+        reader.get(b"key050").unwrap();
+        for i in 0..50 {
+            let key = format!("missing{:03}", i);
+            reader.get(key.as_bytes()).unwrap();
+        }
+        
+        let props = reader.properties();
+        assert_eq!(props.bloom_probes, 51);
+        assert!(props.bloom_negatives >= 45);
+    }
+    
     #[test]
     fn test_sstable_iterator() {
         let temp_file = NamedTempFile::new().unwrap();
@@ -298,4 +550,46 @@ mod tests {
         assert!(iter.valid());
         assert_eq!(iter.key().unwrap(), b"key012");
     }
+    
+    #[test]
+    fn test_sstable_verify() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let path = temp_file.path();
+        
+        let mut writer = SSTableWriter::create(path, 256).unwrap();
+        for i in 0..100 {
+            let key = format!("key{:03}", i);
+            writer.add(key.as_bytes(), b"some value").unwrap();
+        }
+        writer.finish(1, 0).unwrap();
+        
+        let reader = SSTableReader::open(path).unwrap();
+        assert_eq!(reader.verify().unwrap(), []);
+        let index = reader.index_entries().unwrap();
+        assert!(index.len() > 2);
+        assert_eq!(index[0].1.offset, 0);
+        assert_eq!(index[1].1.offset, index[0].1.size);
+        
+        // An entry whose lengths never end.
+        let mut bytes = std::fs::read(path).unwrap();
+        let second = index[1].1;
+        bytes[second.offset as usize..][..10].fill(0xff);
+        let corrupt = NamedTempFile::new().unwrap();
+        std::fs::write(corrupt.path(), &bytes).unwrap();
+        let problems = SSTableReader::open(corrupt.path()).unwrap().verify().unwrap();
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].offset, second.offset);
+        assert_eq!(problems[0].message, "data block entry can't be decoded");
+        
+        // A restart count larger than the block.
+        let mut bytes = std::fs::read(path).unwrap();
+        let third = index[2].1;
+        let count = (third.offset + third.size - 4) as usize;
+        bytes[count..count + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        std::fs::write(corrupt.path(), &bytes).unwrap();
+        let problems = SSTableReader::open(corrupt.path()).unwrap().verify().unwrap();
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].offset, third.offset);
+        assert!(problems[0].message.starts_with("data block unreadable"));
+    }
 }
@@ -1,6 +1,6 @@
 use super::block::{Block, BlockBuilder};
 use super::footer::{BlockHandle, Footer, SSTableMetadata, FOOTER_SIZE};
-use crate::bloom::BloomFilterBuilder;
+use crate::bloom::{BloomFilter, BloomFilterBuilder};
 use crate::Result;
 use std::fs::{File, OpenOptions};
 use std::io::{BufWriter, Write};
@@ -10,7 +10,11 @@ pub struct SSTableWriter {
     file: BufWriter<File>,
     data_block_builder: BlockBuilder,
     index_block_builder: BlockBuilder,
-    bloom_builder: BloomFilterBuilder,
+    bloom_bits_per_key: usize,
+    expected_entries: usize,
+    /// Made on the first `add`, unless a prebuilt filter was given first.
+    bloom_builder: Option<BloomFilterBuilder>,
+    prebuilt_bloom: Option<BloomFilter>,
     block_size: usize,
     offset: u64,
     pending_index_entry: Option<(Vec<u8>, BlockHandle)>,
@@ -28,6 +32,17 @@ impl SSTableWriter {
         path: P,
         block_size: usize,
         bloom_bits_per_key: usize,
+    ) -> Result<Self> {
+        Self::create_with_expected_entries(path, block_size, bloom_bits_per_key, 0)
+    }
+    
+    /// Like `create_with_bloom_bits`, but reserves bloom builder space for
+    /// `expected_entries` keys up front when the caller knows the count.
+    pub fn create_with_expected_entries<P: AsRef<Path>>(
+        path: P,
+        block_size: usize,
+        bloom_bits_per_key: usize,
+        expected_entries: usize,
     ) -> Result<Self> {
         let file = OpenOptions::new()
             .create(true)
@@ -39,7 +54,10 @@ impl SSTableWriter {
             file: BufWriter::new(file),
             data_block_builder: BlockBuilder::new(16), // 16 restart points
             index_block_builder: BlockBuilder::new(1), // 1 restart point per index entry
-            bloom_builder: BloomFilterBuilder::new(bloom_bits_per_key),
+            bloom_bits_per_key,
+            expected_entries,
+            bloom_builder: None,
+            prebuilt_bloom: None,
             block_size,
             offset: 0,
             pending_index_entry: None,
@@ -49,6 +67,13 @@ impl SSTableWriter {
         })
     }
     
+    /// Writes `filter` as the table's bloom filter instead of building one
+    /// from the added keys. The caller must make sure it covers every key.
+    pub fn use_bloom_filter(&mut self, filter: BloomFilter) {
+        self.bloom_builder = None;
+        self.prebuilt_bloom = Some(filter);
+    }
+    
     pub fn add(&mut self, key: &[u8], value: &[u8]) -> Result<()> {
         assert!(!key.is_empty(), "Key cannot be empty");
         
@@ -65,7 +90,12 @@ impl SSTableWriter {
         
         self.data_block_builder.add(key, value);
         self.num_entries += 1;
-        self.bloom_builder.add_key(key);
+        if self.prebuilt_bloom.is_none() {
+            let (bits_per_key, expected) = (self.bloom_bits_per_key, self.expected_entries);
+            self.bloom_builder
+                .get_or_insert_with(|| BloomFilterBuilder::with_expected_keys(bits_per_key, expected))
+                .add_key(key);
+        }
         
         if self.data_block_builder.current_size_estimate() >= self.block_size {
             self.flush_data_block()?;
@@ -147,11 +177,13 @@ impl SSTableWriter {
     fn write_bloom_filter_block(&mut self) -> Result<BlockHandle> {
         let offset = self.offset;
         
-        let bloom_builder = std::mem::replace(
-            &mut self.bloom_builder,
-            BloomFilterBuilder::new(10),
-        );
-        let bloom_filter = bloom_builder.build();
+        let bloom_filter = match self.prebuilt_bloom.take() {
+            Some(filter) => filter,
+            None => self.bloom_builder
+                .take()
+                .unwrap_or_else(|| BloomFilterBuilder::new(self.bloom_bits_per_key))
+                .build(),
+        };
         let bloom_bytes = bloom_filter.to_bytes();
         
         self.file.write_all(&bloom_bytes)?;
@@ -210,6 +242,25 @@ mod tests {
         assert_eq!(metadata.largest_key, b"cherry");
     }
     
+    #[test]
+    fn test_prebuilt_bloom_skips_builder() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let mut writer = SSTableWriter::create_with_expected_entries(
+            temp_file.path(), 4096, 10, 1000,
+        ).unwrap();
+        
+        let mut filter = BloomFilter::new(2, 10);
+        filter.insert(b"apple");
+        filter.insert(b"banana");
+        writer.use_bloom_filter(filter);
+        writer.add(b"apple", b"red").unwrap();
+        writer.add(b"banana", b"yellow").unwrap();
+        assert!(writer.bloom_builder.is_none());
+        
+        let metadata = writer.finish(1, 0).unwrap();
+        assert_eq!(metadata.num_entries, 2);
+    }
+    
     #[test]
     fn test_find_shortest_separator() {
         // Normal case
@@ -0,0 +1,242 @@
+use super::page::{Page, PageType, PAGE_PAYLOAD_SIZE};
+use super::Storage;
+use crate::{PageId, Result};
+
+/// Location of an object too large for a single page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlobRef {
+    /// `n_pages` contiguous pages starting at `first`.
+    Extent { first: PageId, n_pages: u64, len: u64 },
+    /// Overflow pages linked through their `next_page` headers.
+    Chain { head: PageId, len: u64 },
+}
+
+impl BlobRef {
+    pub fn len(&self) -> u64 {
+        match *self {
+            BlobRef::Extent { len, .. } | BlobRef::Chain { len, .. } => len,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn n_pages(&self) -> u64 {
+        pages_for(self.len())
+    }
+}
+
+fn pages_for(len: u64) -> u64 {
+    len.div_ceil(PAGE_PAYLOAD_SIZE as u64).max(1)
+}
+
+fn overflow_pages(data: &[u8]) -> Vec<Page> {
+    let mut pages: Vec<Page> = data
+        .chunks(PAGE_PAYLOAD_SIZE)
+        .map(|chunk| {
+            let mut page = Page::init(PageType::Overflow, 0);
+            page.payload_mut()[..chunk.len()].copy_from_slice(chunk);
+            page
+        })
+        .collect();
+    if pages.is_empty() {
+        pages.push(Page::init(PageType::Overflow, 0));
+    }
+    pages
+}
+
+/// Store `data` across as many pages as it needs: one extent when the
+/// backend supports it, otherwise a chain of single overflow pages.
+pub fn write_blob<S: Storage + ?Sized>(storage: &S, data: &[u8]) -> Result<BlobRef> {
+    let mut pages = overflow_pages(data);
+    let len = data.len() as u64;
+
+    if storage.supports_extents() {
+        let n_pages = pages.len() as u64;
+        let first = storage.allocate_extent(n_pages)?;
+        storage.write_extent(first, &pages)?;
+        return Ok(BlobRef::Extent { first, n_pages, len });
+    }
+
+    let ids = (0..pages.len())
+        .map(|_| storage.allocate_page())
+        .collect::<Result<Vec<PageId>>>()?;
+    for (i, page) in pages.iter_mut().enumerate() {
+        page.set_next_page(ids.get(i + 1).copied().unwrap_or(0));
+        storage.write_page(ids[i], page)?;
+    }
+
+    Ok(BlobRef::Chain { head: ids[0], len })
+}
+
+pub fn read_blob<S: Storage + ?Sized>(storage: &S, blob: &BlobRef) -> Result<Vec<u8>> {
+    let pages = match *blob {
+        BlobRef::Extent { first, n_pages, .. } => storage.read_extent(first, n_pages)?,
+        BlobRef::Chain { head, .. } => read_chain(storage, head, blob.n_pages())?
+            .into_iter()
+            .map(|(_, page)| page)
+            .collect(),
+    };
+
+    let mut data = Vec::with_capacity(blob.len() as usize);
+    for page in &pages {
+        if page.page_type() != Some(PageType::Overflow) {
+            return Err(crate::Error::Corruption(format!(
+                "blob page {} is not an overflow page",
+                page.page_id()
+            )));
+        }
+        let take = (blob.len() as usize - data.len()).min(PAGE_PAYLOAD_SIZE);
+        data.extend_from_slice(&page.payload()[..take]);
+    }
+
+    Ok(data)
+}
+
+pub fn free_blob<S: Storage + ?Sized>(storage: &S, blob: &BlobRef) -> Result<()> {
+    match *blob {
+        BlobRef::Extent { first, n_pages, .. } => storage.free_extent(first, n_pages),
+        BlobRef::Chain { head, .. } => {
+            for (page_id, _) in read_chain(storage, head, blob.n_pages())? {
+                storage.free_page(page_id)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+fn read_chain<S: Storage + ?Sized>(
+    storage: &S,
+    head: PageId,
+    n_pages: u64,
+) -> Result<Vec<(PageId, Page)>> {
+    let mut pages = Vec::with_capacity(n_pages as usize);
+    let mut page_id = head;
+    for _ in 0..n_pages {
+        let page = storage.read_page(page_id)?;
+        let next = page.next_page();
+        pages.push((page_id, page));
+        page_id = next;
+    }
+    Ok(pages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{FileStorage, MemStorage};
+    use tempfile::NamedTempFile;
+
+    /// Page-at-a-time backend without extent support.
+    struct NoExtents(MemStorage);
+
+    impl Storage for NoExtents {
+        fn read_page(&self, page_id: PageId) -> Result<Page> {
+            self.0.read_page(page_id)
+        }
+
+        fn write_page(&self, page_id: PageId, page: &Page) -> Result<()> {
+            self.0.write_page(page_id, page)
+        }
+
+        fn allocate_page(&self) -> Result<PageId> {
+            self.0.allocate_page()
+        }
+
+        fn free_page(&self, page_id: PageId) -> Result<()> {
+            self.0.free_page(page_id)
+        }
+    }
+
+    fn blob_bytes(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 31 % 251) as u8).collect()
+    }
+
+    #[test]
+    fn test_blob_extent_round_trip_and_reuse() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let storage = FileStorage::create_or_open(temp_file.path()).unwrap();
+        let data = blob_bytes(1 << 20);
+
+        let blob = write_blob(&storage, &data).unwrap();
+        let (first, n_pages) = match blob {
+            BlobRef::Extent { first, n_pages, .. } => (first, n_pages),
+            other => panic!("expected extent, got {:?}", other),
+        };
+        assert_eq!(n_pages, blob.n_pages());
+        assert_eq!(read_blob(&storage, &blob).unwrap(), data);
+
+        free_blob(&storage, &blob).unwrap();
+        assert_eq!(storage.free_pages(), n_pages);
+
+        let high_water = storage.num_pages();
+        let again = write_blob(&storage, &data[..data.len() / 2]).unwrap();
+        match again {
+            BlobRef::Extent { first: reused, .. } => assert_eq!(reused, first),
+            other => panic!("expected extent, got {:?}", other),
+        }
+        assert_eq!(storage.num_pages(), high_water);
+        assert_eq!(read_blob(&storage, &again).unwrap(), &data[..data.len() / 2]);
+        assert_eq!(storage.free_pages(), n_pages - again.n_pages());
+    }
+
+    #[test]
+    fn test_blob_interleaved_allocations_do_not_overlap() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let storage = FileStorage::create_or_open(temp_file.path()).unwrap();
+
+        let mut owned: Vec<(PageId, u64)> = Vec::new();
+        for round in 0..20u64 {
+            owned.push((storage.allocate_page().unwrap(), 1));
+            let n = round % 5 + 2;
+            owned.push((storage.allocate_extent(n).unwrap(), n));
+            if round % 3 == 0 {
+                let (first, n) = owned.remove(owned.len() / 2);
+                storage.free_extent(first, n).unwrap();
+            }
+        }
+
+        let mut claimed: Vec<PageId> = owned
+            .iter()
+            .flat_map(|&(first, n)| first..first + n)
+            .collect();
+        let total = claimed.len();
+        claimed.sort_unstable();
+        claimed.dedup();
+        assert_eq!(claimed.len(), total);
+        assert!(!claimed.contains(&0));
+        assert_eq!(storage.allocated_pages(), total as u64);
+    }
+
+    #[test]
+    fn test_blob_overflow_chain_fallback() {
+        let storage = NoExtents(MemStorage::new());
+        assert!(storage.allocate_extent(4).is_err());
+
+        let data = blob_bytes(3 * PAGE_PAYLOAD_SIZE + 17);
+        let blob = write_blob(&storage, &data).unwrap();
+        assert!(matches!(blob, BlobRef::Chain { .. }));
+        assert_eq!(blob.n_pages(), 4);
+        assert_eq!(read_blob(&storage, &blob).unwrap(), data);
+
+        free_blob(&storage, &blob).unwrap();
+        assert_eq!(storage.0.num_pages(), 0);
+    }
+
+    #[test]
+    fn test_blob_mem_storage_extent() {
+        let storage = MemStorage::new();
+        let single = storage.allocate_page().unwrap();
+        let blob = write_blob(&storage, &blob_bytes(10_000)).unwrap();
+        match blob {
+            BlobRef::Extent { first, n_pages, .. } => {
+                assert!(first > single);
+                assert_eq!(n_pages, 3);
+            }
+            other => panic!("expected extent, got {:?}", other),
+        }
+        assert_eq!(read_blob(&storage, &blob).unwrap(), blob_bytes(10_000));
+        assert!(read_blob(&storage, &BlobRef::Extent { first: single, n_pages: 1, len: 1 }).is_err());
+    }
+}
@@ -0,0 +1,549 @@
+use super::page::{Page, PageType};
+use super::Storage;
+use crate::{Error, PageId, Result};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BufferPoolStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    pub writebacks: u64,
+}
+
+struct FrameData {
+    page: RwLock<Page>,
+    dirty: AtomicBool,
+}
+
+struct Frame {
+    data: Arc<FrameData>,
+    pin_count: usize,
+    last_used: u64,
+}
+
+struct PoolState {
+    frames: HashMap<PageId, Frame>,
+    /// Pages being read from storage. Fetches of them wait for the read.
+    loading: HashSet<PageId>,
+    /// Frames promised to loads and allocations still in progress.
+    reserved: usize,
+    tick: u64,
+    stats: BufferPoolStats,
+}
+
+/// Fixed-capacity page cache over a `Storage` backend.
+///
+/// Pages are pinned for as long as a `PageGuard` is alive. Unpinned pages are
+/// evicted least-recently-used first, and dirty pages are written back before
+/// their frame is reused. Dirty pages that are never evicted reach storage
+/// through `flush_page`, `flush_all`, or when the pool is dropped.
+///
+/// The pool's lock guards only its bookkeeping: page locks are never taken
+/// and storage is never touched while it is held, so a thread holding a
+/// page's write lock can still fetch other pages while a flush runs.
+pub struct BufferPool<S: Storage> {
+    state: Mutex<PoolState>,
+    loaded: Condvar,
+    storage: S,
+    capacity: usize,
+}
+
+impl<S: Storage> BufferPool<S> {
+    pub fn new(storage: S, capacity: usize) -> Self {
+        assert!(capacity > 0, "buffer pool capacity must be at least 1");
+        BufferPool {
+            state: Mutex::new(PoolState {
+                frames: HashMap::with_capacity(capacity),
+                loading: HashSet::new(),
+                reserved: 0,
+                tick: 0,
+                stats: BufferPoolStats::default(),
+            }),
+            loaded: Condvar::new(),
+            storage,
+            capacity,
+        }
+    }
+
+    pub fn fetch_page(&self, page_id: PageId) -> Result<PageGuard<'_, S>> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            state.tick += 1;
+            let tick = state.tick;
+
+            if let Some(frame) = state.frames.get_mut(&page_id) {
+                frame.pin_count += 1;
+                frame.last_used = tick;
+                let data = Arc::clone(&frame.data);
+                state.stats.hits += 1;
+                return Ok(PageGuard { pool: self, page_id, data });
+            }
+
+            if state.loading.contains(&page_id) {
+                state = self.loaded.wait(state).unwrap();
+                continue;
+            }
+
+            match self.make_room(&mut state)? {
+                None => break,
+                Some((victim, data)) => {
+                    // Another thread may load this page while the victim is
+                    // written, so look again afterwards.
+                    drop(state);
+                    self.write_back_pinned(victim, &data)?;
+                    state = self.state.lock().unwrap();
+                }
+            }
+        }
+
+        state.stats.misses += 1;
+        state.loading.insert(page_id);
+        state.reserved += 1;
+        drop(state);
+
+        let page = self.storage.read_page(page_id);
+
+        let mut state = self.state.lock().unwrap();
+        state.loading.remove(&page_id);
+        state.reserved -= 1;
+        self.loaded.notify_all();
+        Ok(self.install(&mut state, page_id, page?))
+    }
+
+    /// Allocate an unformatted page. Its header records only the page id.
+    pub fn new_page(&self) -> Result<PageGuard<'_, S>> {
+        self.allocate(|page_id| {
+            let mut page = Page::new();
+            page.set_page_id(page_id);
+            page
+        })
+    }
+
+    /// Allocate a page formatted with `Page::init`.
+    pub fn new_typed_page(&self, page_type: PageType) -> Result<PageGuard<'_, S>> {
+        let guard = self.allocate(|page_id| Page::init(page_type, page_id))?;
+        guard.data.dirty.store(true, Ordering::SeqCst);
+        Ok(guard)
+    }
+
+    pub fn flush_page(&self, page_id: PageId) -> Result<()> {
+        let data = {
+            let mut state = self.state.lock().unwrap();
+            match Self::pin_dirty(&mut state, page_id) {
+                Some(data) => data,
+                None => return Ok(()),
+            }
+        };
+        self.write_back_pinned(page_id, &data)
+    }
+
+    pub fn flush_all(&self) -> Result<()> {
+        let dirty: Vec<(PageId, Arc<FrameData>)> = {
+            let mut state = self.state.lock().unwrap();
+            let ids: Vec<PageId> = state.frames.keys().copied().collect();
+            ids.into_iter()
+                .filter_map(|id| Self::pin_dirty(&mut state, id).map(|data| (id, data)))
+                .collect()
+        };
+
+        let mut result = Ok(());
+        for (page_id, data) in dirty {
+            if result.is_ok() {
+                result = self.write_back_pinned(page_id, &data);
+            } else {
+                // Still release the pin taken above.
+                self.unpin(page_id);
+            }
+        }
+
+        result?;
+        self.storage.sync()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn resident_count(&self) -> usize {
+        self.state.lock().unwrap().frames.len()
+    }
+
+    pub fn pinned_count(&self) -> usize {
+        let state = self.state.lock().unwrap();
+        state.frames.values().filter(|f| f.pin_count > 0).count()
+    }
+
+    pub fn dirty_count(&self) -> usize {
+        let state = self.state.lock().unwrap();
+        state
+            .frames
+            .values()
+            .filter(|f| f.data.dirty.load(Ordering::SeqCst))
+            .count()
+    }
+
+    pub fn stats(&self) -> BufferPoolStats {
+        self.state.lock().unwrap().stats
+    }
+
+    fn allocate(&self, make_page: impl FnOnce(PageId) -> Page) -> Result<PageGuard<'_, S>> {
+        let mut state = self.state.lock().unwrap();
+        state.tick += 1;
+        while let Some((victim, data)) = self.make_room(&mut state)? {
+            drop(state);
+            self.write_back_pinned(victim, &data)?;
+            state = self.state.lock().unwrap();
+        }
+        state.reserved += 1;
+        drop(state);
+
+        let page_id = self.storage.allocate_page();
+
+        let mut state = self.state.lock().unwrap();
+        state.reserved -= 1;
+        let page_id = page_id?;
+        Ok(self.install(&mut state, page_id, make_page(page_id)))
+    }
+
+    fn install(
+        &self,
+        state: &mut PoolState,
+        page_id: PageId,
+        page: Page,
+    ) -> PageGuard<'_, S> {
+        let data = Arc::new(FrameData {
+            page: RwLock::new(page),
+            dirty: AtomicBool::new(false),
+        });
+        state.frames.insert(
+            page_id,
+            Frame {
+                data: Arc::clone(&data),
+                pin_count: 1,
+                last_used: state.tick,
+            },
+        );
+        PageGuard { pool: self, page_id, data }
+    }
+
+    /// Make room for one more page. A clean victim is dropped on the spot.
+    /// A dirty one is pinned and returned instead, for the caller to write
+    /// back once it has released the lock.
+    fn make_room(&self, state: &mut PoolState) -> Result<Option<(PageId, Arc<FrameData>)>> {
+        if state.frames.len() + state.reserved < self.capacity {
+            return Ok(None);
+        }
+
+        let victim = state
+            .frames
+            .iter()
+            .filter(|(_, f)| f.pin_count == 0)
+            .min_by_key(|(_, f)| f.last_used)
+            .map(|(id, _)| *id);
+
+        let page_id = victim.ok_or_else(|| {
+            Error::Internal(format!(
+                "buffer pool exhausted: all {} frames are pinned",
+                self.capacity
+            ))
+        })?;
+
+        match Self::pin_dirty(state, page_id) {
+            Some(data) => Ok(Some((page_id, data))),
+            None => {
+                state.frames.remove(&page_id);
+                state.stats.evictions += 1;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Pin `page_id` for a write-back if it is resident and dirty. The pin
+    /// keeps it from being evicted, and so read back stale, mid-write.
+    fn pin_dirty(state: &mut PoolState, page_id: PageId) -> Option<Arc<FrameData>> {
+        let frame = state.frames.get_mut(&page_id)?;
+        if !frame.data.dirty.load(Ordering::SeqCst) {
+            return None;
+        }
+        frame.pin_count += 1;
+        Some(Arc::clone(&frame.data))
+    }
+
+    /// Write back a frame pinned by `pin_dirty`, then unpin it. Takes the
+    /// page's read lock, so it must be called without the pool lock held.
+    fn write_back_pinned(&self, page_id: PageId, data: &FrameData) -> Result<()> {
+        let written = {
+            let page = data.page.read().unwrap();
+            if data.dirty.swap(false, Ordering::SeqCst) {
+                let written = self.storage.write_page(page_id, &page);
+                if written.is_err() {
+                    data.dirty.store(true, Ordering::SeqCst);
+                }
+                Some(written)
+            } else {
+                None
+            }
+        };
+
+        let mut state = self.state.lock().unwrap();
+        if let Some(Ok(())) = written {
+            state.stats.writebacks += 1;
+        }
+        if let Some(frame) = state.frames.get_mut(&page_id) {
+            frame.pin_count = frame.pin_count.saturating_sub(1);
+        }
+        written.unwrap_or(Ok(()))
+    }
+
+    fn unpin(&self, page_id: PageId) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(frame) = state.frames.get_mut(&page_id) {
+            frame.pin_count = frame.pin_count.saturating_sub(1);
+        }
+    }
+}
+
+impl<S: Storage> Drop for BufferPool<S> {
+    fn drop(&mut self) {
+        // Drop can't report an error; callers that need to know should call
+        // `flush_all` first.
+        let _ = self.flush_all();
+    }
+}
+
+/// A pinned page. The page stays resident until the guard is dropped.
+pub struct PageGuard<'a, S: Storage> {
+    pool: &'a BufferPool<S>,
+    page_id: PageId,
+    data: Arc<FrameData>,
+}
+
+impl<'a, S: Storage> PageGuard<'a, S> {
+    pub fn page_id(&self) -> PageId {
+        self.page_id
+    }
+
+    pub fn read(&self) -> RwLockReadGuard<'_, Page> {
+        self.data.page.read().unwrap()
+    }
+
+    pub fn write(&self) -> RwLockWriteGuard<'_, Page> {
+        let page = self.data.page.write().unwrap();
+        self.data.dirty.store(true, Ordering::SeqCst);
+        page
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.data.dirty.load(Ordering::SeqCst)
+    }
+}
+
+impl<'a, S: Storage> Drop for PageGuard<'a, S> {
+    fn drop(&mut self) {
+        self.pool.unpin(self.page_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{FileStorage, MemStorage};
+    use std::sync::Barrier;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_buffer_pool_new_and_fetch() {
+        let pool = BufferPool::new(MemStorage::new(), 4);
+
+        let page_id = {
+            let guard = pool.new_page().unwrap();
+            guard.write().write_at(0, b"hello").unwrap();
+            assert!(guard.is_dirty());
+            guard.page_id()
+        };
+        assert_eq!(pool.pinned_count(), 0);
+
+        let guard = pool.fetch_page(page_id).unwrap();
+        assert_eq!(guard.read().get_slice(0, 5).unwrap(), b"hello");
+        assert_eq!(pool.stats().hits, 1);
+    }
+
+    #[test]
+    fn test_buffer_pool_eviction_and_reread() {
+        let pool = BufferPool::new(MemStorage::new(), 3);
+
+        let mut ids = Vec::new();
+        for i in 0..10u8 {
+            let guard = pool.new_page().unwrap();
+            guard.write().write_at(0, &[i; 16]).unwrap();
+            ids.push(guard.page_id());
+        }
+        assert_eq!(pool.resident_count(), 3);
+        assert!(pool.stats().evictions >= 7);
+
+        for (i, id) in ids.iter().enumerate() {
+            let guard = pool.fetch_page(*id).unwrap();
+            assert_eq!(guard.read().get_slice(0, 16).unwrap(), &[i as u8; 16]);
+        }
+        assert!(pool.stats().writebacks >= 7);
+    }
+
+    #[test]
+    fn test_buffer_pool_lru_order() {
+        let pool = BufferPool::new(MemStorage::new(), 2);
+
+        let a = pool.new_page().unwrap().page_id();
+        let b = pool.new_page().unwrap().page_id();
+        drop(pool.fetch_page(a).unwrap());
+
+        let _c = pool.new_page().unwrap();
+
+        let misses = pool.stats().misses;
+        drop(pool.fetch_page(a).unwrap());
+        assert_eq!(pool.stats().misses, misses);
+        drop(pool.fetch_page(b).unwrap());
+        assert_eq!(pool.stats().misses, misses + 1);
+    }
+
+    #[test]
+    fn test_buffer_pool_pinned_pages_not_evicted() {
+        let pool = BufferPool::new(MemStorage::new(), 2);
+
+        let g1 = pool.new_page().unwrap();
+        let g2 = pool.new_page().unwrap();
+        assert_eq!(pool.pinned_count(), 2);
+
+        assert!(pool.new_page().is_err());
+
+        drop(g1);
+        assert_eq!(pool.pinned_count(), 1);
+        let g3 = pool.new_page().unwrap();
+        assert_ne!(g3.page_id(), g2.page_id());
+    }
+
+    #[test]
+    fn test_buffer_pool_shared_frame() {
+        let pool = BufferPool::new(MemStorage::new(), 4);
+        let page_id = pool.new_page().unwrap().page_id();
+        let barrier = Barrier::new(8);
+
+        std::thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    let guard = pool.fetch_page(page_id).unwrap();
+                    barrier.wait();
+                    assert_eq!(pool.pinned_count(), 1);
+                    assert_eq!(pool.resident_count(), 1);
+                    drop(guard);
+                });
+            }
+        });
+
+        assert_eq!(pool.pinned_count(), 0);
+        assert_eq!(pool.stats().misses, 0);
+        assert_eq!(pool.stats().hits, 8);
+    }
+
+    #[test]
+    fn test_buffer_pool_flush_all_survives_reopen() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let path = temp_file.path();
+
+        let ids: Vec<PageId> = {
+            let pool = BufferPool::new(FileStorage::create_or_open(path).unwrap(), 8);
+            let ids = (0..4u8)
+                .map(|i| {
+                    let guard = pool.new_page().unwrap();
+                    guard.write().write_at(100, &[i + 1; 8]).unwrap();
+                    guard.page_id()
+                })
+                .collect();
+            assert_eq!(pool.dirty_count(), 4);
+            pool.flush_all().unwrap();
+            assert_eq!(pool.dirty_count(), 0);
+            ids
+        };
+
+        let storage = FileStorage::create_or_open(path).unwrap();
+        for (i, id) in ids.iter().enumerate() {
+            let page = storage.read_page(*id).unwrap();
+            assert_eq!(page.get_slice(100, 8).unwrap(), &[i as u8 + 1; 8]);
+        }
+    }
+
+    #[test]
+    fn test_buffer_pool_flush_while_page_write_locked() {
+        let pool = BufferPool::new(MemStorage::new(), 2);
+        let a = pool.new_page().unwrap().page_id();
+        let b = pool.new_page().unwrap().page_id();
+        let barrier = Barrier::new(2);
+
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                let guard = pool.fetch_page(a).unwrap();
+                let mut page = guard.write();
+                barrier.wait();
+                // Give the flush time to block on this page's lock.
+                std::thread::sleep(std::time::Duration::from_millis(50));
+                let other = pool.fetch_page(b).unwrap();
+                other.write().write_at(0, b"b").unwrap();
+                page.write_at(0, b"a").unwrap();
+            });
+            s.spawn(|| {
+                barrier.wait();
+                pool.flush_all().unwrap();
+            });
+        });
+
+        pool.flush_all().unwrap();
+        assert_eq!(pool.dirty_count(), 0);
+        assert_eq!(pool.pinned_count(), 0);
+        assert_eq!(pool.fetch_page(a).unwrap().read().get_slice(0, 1).unwrap(), b"a");
+    }
+
+    #[test]
+    fn test_buffer_pool_drop_flushes() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let path = temp_file.path();
+
+        let page_id = {
+            let pool = BufferPool::new(FileStorage::create_or_open(path).unwrap(), 4);
+            let guard = pool.new_page().unwrap();
+            guard.write().write_at(100, b"kept").unwrap();
+            guard.page_id()
+        };
+
+        let storage = FileStorage::create_or_open(path).unwrap();
+        assert_eq!(storage.read_page(page_id).unwrap().get_slice(100, 4).unwrap(), b"kept");
+    }
+
+    #[test]
+    fn test_buffer_pool_typed_pages() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let pool = BufferPool::new(FileStorage::create_or_open(temp_file.path()).unwrap(), 2);
+
+        let leaf = {
+            let guard = pool.new_typed_page(PageType::BTreeLeaf).unwrap();
+            assert!(guard.is_dirty());
+            guard.write().set_lsn(17);
+            guard.page_id()
+        };
+        let interior = pool.new_typed_page(PageType::BTreeInterior).unwrap().page_id();
+        drop(pool.new_page().unwrap());
+        pool.flush_all().unwrap();
+
+        let guard = pool.fetch_page(leaf).unwrap();
+        let page = guard.read();
+        assert_eq!(page.page_type(), Some(PageType::BTreeLeaf));
+        assert_eq!(page.page_id(), leaf);
+        assert_eq!(page.lsn(), 17);
+        drop(page);
+
+        let guard = pool.fetch_page(interior).unwrap();
+        assert_eq!(guard.read().page_type(), Some(PageType::BTreeInterior));
+        assert_eq!(guard.read().page_id(), interior);
+    }
+}
@@ -1,41 +1,41 @@
 use super::page::Page;
 use crate::{PageId, Result};
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
 
 pub struct MemStorage {
-    pages: HashMap<PageId, Page>,
-    next_page_id: PageId,
+    pages: RwLock<HashMap<PageId, Page>>,
+    next_page_id: AtomicU64,
 }
 
 impl MemStorage {
     /// Create a new in-memory storage
     pub fn new() -> Self {
         MemStorage {
-            pages: HashMap::new(),
-            next_page_id: 0,
+            pages: RwLock::new(HashMap::new()),
+            next_page_id: AtomicU64::new(0),
         }
     }
     
     pub fn read_page(&self, page_id: PageId) -> Result<Page> {
         self.pages
+            .read()
+            .unwrap()
             .get(&page_id)
             .cloned()
             .ok_or_else(|| crate::Error::InvalidArgument(format!("Page {} not found", page_id)))
     }
     
-    pub fn write_page(&mut self, page_id: PageId, page: &Page) -> Result<()> {
-        self.pages.insert(page_id, page.clone());
-        
-        if page_id >= self.next_page_id {
-            self.next_page_id = page_id + 1;
-        }
+    pub fn write_page(&self, page_id: PageId, page: &Page) -> Result<()> {
+        self.pages.write().unwrap().insert(page_id, page.clone());
+        self.next_page_id.fetch_max(page_id + 1, Ordering::SeqCst);
         
         Ok(())
     }
     
-    pub fn allocate_page(&mut self) -> Result<PageId> {
-        let page_id = self.next_page_id;
-        self.next_page_id += 1;
+    pub fn allocate_page(&self) -> Result<PageId> {
+        let page_id = self.next_page_id.fetch_add(1, Ordering::SeqCst);
         
         let page = Page::new();
         self.write_page(page_id, &page)?;
@@ -43,18 +43,58 @@ impl MemStorage {
         Ok(page_id)
     }
     
-    pub fn free_page(&mut self, page_id: PageId) -> Result<()> {
-        self.pages.remove(&page_id);
+    /// Reserve `n_pages` consecutive ids and return the first.
+    pub fn allocate_extent(&self, n_pages: u64) -> Result<PageId> {
+        if n_pages == 0 {
+            return Err(crate::Error::InvalidArgument(
+                "extent must span at least one page".to_string(),
+            ));
+        }
+        
+        let first = self.next_page_id.fetch_add(n_pages, Ordering::SeqCst);
+        let mut pages = self.pages.write().unwrap();
+        for page_id in first..first + n_pages {
+            pages.insert(page_id, Page::new());
+        }
+        
+        Ok(first)
+    }
+    
+    pub fn read_extent(&self, first: PageId, n_pages: u64) -> Result<Vec<Page>> {
+        (first..first + n_pages).map(|page_id| self.read_page(page_id)).collect()
+    }
+    
+    pub fn write_extent(&self, first: PageId, pages: &[Page]) -> Result<()> {
+        let mut map = self.pages.write().unwrap();
+        for (page_id, page) in (first..).zip(pages) {
+            map.insert(page_id, page.clone());
+        }
+        self.next_page_id
+            .fetch_max(first + pages.len() as u64, Ordering::SeqCst);
+
+        Ok(())
+    }
+    
+    pub fn free_extent(&self, first: PageId, n_pages: u64) -> Result<()> {
+        let mut pages = self.pages.write().unwrap();
+        for page_id in first..first + n_pages {
+            pages.remove(&page_id);
+        }
+        Ok(())
+    }
+    
+    pub fn free_page(&self, page_id: PageId) -> Result<()> {
+        self.pages.write().unwrap().remove(&page_id);
         Ok(())
     }
     
     pub fn num_pages(&self) -> usize {
-        self.pages.len()
+        self.pages.read().unwrap().len()
     }
     
     pub fn clear(&mut self) {
-        self.pages.clear();
-        self.next_page_id = 0;
+        self.pages.get_mut().unwrap().clear();
+        *self.next_page_id.get_mut() = 0;
     }
 }
 
@@ -70,7 +110,7 @@ mod tests {
     
     #[test]
     fn test_mem_storage() {
-        let mut storage = MemStorage::new();
+        let storage = MemStorage::new();
         
         // Allocate pages
         let page0 = storage.allocate_page().unwrap();
@@ -90,7 +130,7 @@ mod tests {
     
     #[test]
     fn test_mem_storage_free() {
-        let mut storage = MemStorage::new();
+        let storage = MemStorage::new();
         
         let page_id = storage.allocate_page().unwrap();
         assert_eq!(storage.num_pages(), 1);
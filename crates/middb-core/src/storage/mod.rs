@@ -1,7 +1,132 @@
 pub mod page;
 pub mod file;
 pub mod mem;
+pub mod buffer_pool;
+pub mod blob;
 
-pub use page::{Page, PAGE_SIZE};
-pub use file::FileStorage;
+pub use page::{Page, PageType, PAGE_SIZE};
+pub use file::{FileStorage, StorageOptions};
 pub use mem::MemStorage;
+pub use buffer_pool::{BufferPool, BufferPoolStats, PageGuard};
+pub use blob::BlobRef;
+
+use crate::{Error, PageId, Result};
+
+pub trait Storage {
+    fn read_page(&self, page_id: PageId) -> Result<Page>;
+    fn write_page(&self, page_id: PageId, page: &Page) -> Result<()>;
+    fn allocate_page(&self) -> Result<PageId>;
+    fn free_page(&self, page_id: PageId) -> Result<()>;
+
+    fn sync(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Whether `allocate_extent` hands out physically contiguous pages.
+    /// Backends that return false store large objects as overflow chains.
+    fn supports_extents(&self) -> bool {
+        false
+    }
+
+    fn allocate_extent(&self, _n_pages: u64) -> Result<PageId> {
+        Err(Error::InvalidArgument("storage does not support extents".to_string()))
+    }
+
+    fn read_extent(&self, first: PageId, n_pages: u64) -> Result<Vec<Page>> {
+        (first..first + n_pages).map(|page_id| self.read_page(page_id)).collect()
+    }
+
+    fn write_extent(&self, first: PageId, pages: &[Page]) -> Result<()> {
+        for (page_id, page) in (first..).zip(pages) {
+            self.write_page(page_id, page)?;
+        }
+        Ok(())
+    }
+
+    fn free_extent(&self, first: PageId, n_pages: u64) -> Result<()> {
+        for page_id in first..first + n_pages {
+            self.free_page(page_id)?;
+        }
+        Ok(())
+    }
+}
+
+impl Storage for FileStorage {
+    fn read_page(&self, page_id: PageId) -> Result<Page> {
+        FileStorage::read_page(self, page_id)
+    }
+
+    fn write_page(&self, page_id: PageId, page: &Page) -> Result<()> {
+        FileStorage::write_page(self, page_id, page)
+    }
+
+    fn allocate_page(&self) -> Result<PageId> {
+        FileStorage::allocate_page(self)
+    }
+
+    fn free_page(&self, page_id: PageId) -> Result<()> {
+        FileStorage::free_page(self, page_id)
+    }
+
+    fn supports_extents(&self) -> bool {
+        true
+    }
+
+    fn allocate_extent(&self, n_pages: u64) -> Result<PageId> {
+        FileStorage::allocate_extent(self, n_pages)
+    }
+
+    fn read_extent(&self, first: PageId, n_pages: u64) -> Result<Vec<Page>> {
+        FileStorage::read_extent(self, first, n_pages)
+    }
+
+    fn write_extent(&self, first: PageId, pages: &[Page]) -> Result<()> {
+        FileStorage::write_extent(self, first, pages)
+    }
+
+    fn free_extent(&self, first: PageId, n_pages: u64) -> Result<()> {
+        FileStorage::free_extent(self, first, n_pages)
+    }
+
+    fn sync(&self) -> Result<()> {
+        FileStorage::sync(self)
+    }
+}
+
+impl Storage for MemStorage {
+    fn read_page(&self, page_id: PageId) -> Result<Page> {
+        MemStorage::read_page(self, page_id)
+    }
+
+    fn write_page(&self, page_id: PageId, page: &Page) -> Result<()> {
+        MemStorage::write_page(self, page_id, page)
+    }
+
+    fn allocate_page(&self) -> Result<PageId> {
+        MemStorage::allocate_page(self)
+    }
+
+    fn free_page(&self, page_id: PageId) -> Result<()> {
+        MemStorage::free_page(self, page_id)
+    }
+
+    fn supports_extents(&self) -> bool {
+        true
+    }
+
+    fn allocate_extent(&self, n_pages: u64) -> Result<PageId> {
+        MemStorage::allocate_extent(self, n_pages)
+    }
+
+    fn read_extent(&self, first: PageId, n_pages: u64) -> Result<Vec<Page>> {
+        MemStorage::read_extent(self, first, n_pages)
+    }
+
+    fn write_extent(&self, first: PageId, pages: &[Page]) -> Result<()> {
+        MemStorage::write_extent(self, first, pages)
+    }
+
+    fn free_extent(&self, first: PageId, n_pages: u64) -> Result<()> {
+        MemStorage::free_extent(self, first, n_pages)
+    }
+}
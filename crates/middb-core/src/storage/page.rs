@@ -1,19 +1,85 @@
-use crate::Result;
+use crate::wal::crc32;
+use crate::{PageId, Result};
 
 pub const PAGE_SIZE: usize = 4096;
 
+/// Alignment of page buffers in memory. Direct I/O requires buffers aligned
+/// to the logical block size of the device, which is at most 4KB in practice.
+pub const PAGE_ALIGN: usize = 4096;
+
+/// Every page starts with a fixed header:
+///
+/// | offset | size | field                                   |
+/// |--------|------|-----------------------------------------|
+/// | 0      | 4    | CRC32 over bytes `4..PAGE_SIZE`         |
+/// | 4      | 1    | page type (`PageType`, 0 = unformatted) |
+/// | 5      | 3    | reserved                                |
+/// | 8      | 8    | page id                                 |
+/// | 16     | 8    | LSN                                     |
+/// | 24     | 8    | next page (free list chain)             |
+///
+/// `get_slice` and `write_at` address the payload that follows the header.
+pub const PAGE_HEADER_SIZE: usize = 32;
+pub const PAGE_PAYLOAD_SIZE: usize = PAGE_SIZE - PAGE_HEADER_SIZE;
+
+const CHECKSUM_OFFSET: usize = 0;
+const PAGE_TYPE_OFFSET: usize = 4;
+const PAGE_ID_OFFSET: usize = 8;
+const LSN_OFFSET: usize = 16;
+const NEXT_PAGE_OFFSET: usize = 24;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum PageType {
+    Free = 1,
+    BTreeLeaf = 2,
+    BTreeInterior = 3,
+    Overflow = 4,
+    Meta = 5,
+}
+
+impl PageType {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            1 => Some(PageType::Free),
+            2 => Some(PageType::BTreeLeaf),
+            3 => Some(PageType::BTreeInterior),
+            4 => Some(PageType::Overflow),
+            5 => Some(PageType::Meta),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone)]
+#[repr(C, align(4096))]
+struct AlignedBlock([u8; PAGE_SIZE]);
+
+const _: () = assert!(std::mem::align_of::<AlignedBlock>() == PAGE_ALIGN);
+const _: () = assert!(std::mem::size_of::<AlignedBlock>() == PAGE_SIZE);
+
 #[derive(Clone)]
 pub struct Page {
-    data: Vec<u8>,
+    data: Box<AlignedBlock>,
 }
 
 impl Page {
     pub fn new() -> Self {
         Page {
-            data: vec![0u8; PAGE_SIZE],
+            data: Box::new(AlignedBlock([0u8; PAGE_SIZE])),
         }
     }
     
+    /// A zeroed page whose header already names its type and location.
+    pub fn init(page_type: PageType, page_id: PageId) -> Self {
+        let mut page = Page::new();
+        page.set_page_type(page_type);
+        page.set_page_id(page_id);
+        page
+    }
+    
+    /// Copy `data` into a new, aligned page buffer. The source does not need
+    /// to be aligned.
     pub fn from_bytes(data: Vec<u8>) -> Result<Self> {
         if data.len() != PAGE_SIZE {
             return Err(crate::Error::InvalidArgument(format!(
@@ -23,44 +89,127 @@ impl Page {
             )));
         }
         
-        Ok(Page { data })
+        let mut page = Page::new();
+        page.data.0.copy_from_slice(&data);
+        Ok(page)
     }
     
     pub fn data(&self) -> &[u8] {
-        &self.data
+        &self.data.0
     }
     
     pub fn data_mut(&mut self) -> &mut [u8] {
-        &mut self.data
+        &mut self.data.0
+    }
+    
+    pub fn payload(&self) -> &[u8] {
+        &self.data.0[PAGE_HEADER_SIZE..]
+    }
+    
+    pub fn payload_mut(&mut self) -> &mut [u8] {
+        &mut self.data.0[PAGE_HEADER_SIZE..]
     }
     
     pub fn get_slice(&self, offset: usize, len: usize) -> Result<&[u8]> {
-        if offset + len > PAGE_SIZE {
+        if offset + len > PAGE_PAYLOAD_SIZE {
             return Err(crate::Error::InvalidArgument(format!(
-                "Slice out of bounds: offset={}, len={}, page_size={}",
-                offset, len, PAGE_SIZE
+                "Slice out of bounds: offset={}, len={}, payload_size={}",
+                offset, len, PAGE_PAYLOAD_SIZE
             )));
         }
         
-        Ok(&self.data[offset..offset + len])
+        let start = PAGE_HEADER_SIZE + offset;
+        Ok(&self.data.0[start..start + len])
     }
     
     pub fn write_at(&mut self, offset: usize, data: &[u8]) -> Result<()> {
-        if offset + data.len() > PAGE_SIZE {
+        if offset + data.len() > PAGE_PAYLOAD_SIZE {
             return Err(crate::Error::InvalidArgument(format!(
-                "Write out of bounds: offset={}, len={}, page_size={}",
+                "Write out of bounds: offset={}, len={}, payload_size={}",
                 offset,
                 data.len(),
-                PAGE_SIZE
+                PAGE_PAYLOAD_SIZE
             )));
         }
         
-        self.data[offset..offset + data.len()].copy_from_slice(data);
+        let start = PAGE_HEADER_SIZE + offset;
+        self.data.0[start..start + data.len()].copy_from_slice(data);
         Ok(())
     }
     
     pub fn zero(&mut self) {
-        self.data.fill(0);
+        self.data.0.fill(0);
+    }
+    
+    pub fn checksum(&self) -> u32 {
+        self.read_u32(CHECKSUM_OFFSET)
+    }
+    
+    pub fn compute_checksum(&self) -> u32 {
+        crc32(&self.data.0[CHECKSUM_OFFSET + 4..])
+    }
+    
+    pub fn update_checksum(&mut self) {
+        let checksum = self.compute_checksum();
+        self.data.0[CHECKSUM_OFFSET..CHECKSUM_OFFSET + 4].copy_from_slice(&checksum.to_le_bytes());
+    }
+    
+    /// True if the stored checksum matches the contents. A page that was never
+    /// written (all zeroes, e.g. a hole left by extending the file) also passes.
+    pub fn verify_checksum(&self) -> bool {
+        self.checksum() == self.compute_checksum() || self.is_zeroed()
+    }
+    
+    /// True if every byte, header included, is zero.
+    pub fn is_zeroed(&self) -> bool {
+        self.data.0.iter().all(|&b| b == 0)
+    }
+    
+    /// The page type, or `None` if the page was never formatted or the byte
+    /// holds an unknown value.
+    pub fn page_type(&self) -> Option<PageType> {
+        PageType::from_u8(self.raw_page_type())
+    }
+    
+    pub fn set_page_type(&mut self, page_type: PageType) {
+        self.data.0[PAGE_TYPE_OFFSET] = page_type as u8;
+    }
+    
+    pub fn raw_page_type(&self) -> u8 {
+        self.data.0[PAGE_TYPE_OFFSET]
+    }
+    
+    pub fn page_id(&self) -> PageId {
+        self.read_u64(PAGE_ID_OFFSET)
+    }
+    
+    pub fn set_page_id(&mut self, page_id: PageId) {
+        self.data.0[PAGE_ID_OFFSET..PAGE_ID_OFFSET + 8].copy_from_slice(&page_id.to_le_bytes());
+    }
+    
+    /// LSN of the last logged change applied to this page.
+    pub fn lsn(&self) -> u64 {
+        self.read_u64(LSN_OFFSET)
+    }
+    
+    pub fn set_lsn(&mut self, lsn: u64) {
+        self.data.0[LSN_OFFSET..LSN_OFFSET + 8].copy_from_slice(&lsn.to_le_bytes());
+    }
+    
+    pub fn next_page(&self) -> PageId {
+        self.read_u64(NEXT_PAGE_OFFSET)
+    }
+    
+    pub fn set_next_page(&mut self, page_id: PageId) {
+        self.data.0[NEXT_PAGE_OFFSET..NEXT_PAGE_OFFSET + 8].copy_from_slice(&page_id.to_le_bytes());
+    }
+    
+    fn read_u32(&self, offset: usize) -> u32 {
+        u32::from_le_bytes(self.data.0[offset..offset + 4].try_into().unwrap())
+    }
+    
+    fn read_u64(&self, offset: usize) -> u64 {
+        u64::from_le_bytes(self.data.0[offset..offset + 8].try_into().unwrap())
     }
 }
 
@@ -70,6 +219,47 @@ impl Default for Page {
     }
 }
 
+/// A contiguous, page-aligned buffer spanning several pages, so that an
+/// extent can be moved with a single read or write.
+pub(crate) struct PageRun {
+    blocks: Vec<AlignedBlock>,
+}
+
+impl PageRun {
+    pub(crate) fn zeroed(n_pages: usize) -> Self {
+        PageRun {
+            blocks: vec![AlignedBlock([0u8; PAGE_SIZE]); n_pages],
+        }
+    }
+    
+    pub(crate) fn from_pages(pages: &[Page]) -> Self {
+        PageRun {
+            blocks: pages.iter().map(|page| (*page.data).clone()).collect(),
+        }
+    }
+    
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        // SAFETY: `AlignedBlock` is a `repr(C)` wrapper around `[u8; PAGE_SIZE]`
+        // whose size equals its alignment, so the blocks are laid out back to
+        // back with no padding.
+        unsafe { std::slice::from_raw_parts(self.blocks.as_ptr().cast::<u8>(), self.blocks.len() * PAGE_SIZE) }
+    }
+    
+    pub(crate) fn as_bytes_mut(&mut self) -> &mut [u8] {
+        // SAFETY: see `as_bytes`.
+        unsafe {
+            std::slice::from_raw_parts_mut(self.blocks.as_mut_ptr().cast::<u8>(), self.blocks.len() * PAGE_SIZE)
+        }
+    }
+    
+    pub(crate) fn into_pages(self) -> Vec<Page> {
+        self.blocks
+            .into_iter()
+            .map(|block| Page { data: Box::new(block) })
+            .collect()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -78,6 +268,22 @@ mod tests {
     fn test_page_creation() {
         let page = Page::new();
         assert_eq!(page.data().len(), PAGE_SIZE);
+        assert_eq!(page.payload().len(), PAGE_PAYLOAD_SIZE);
+        assert_eq!(page.data().as_ptr() as usize % PAGE_ALIGN, 0);
+    }
+    
+    #[test]
+    fn test_page_from_unaligned_bytes() {
+        let mut buf = vec![0u8; PAGE_SIZE + 1];
+        buf[1 + PAGE_HEADER_SIZE] = 42;
+        let unaligned = buf[1..].to_vec();
+        
+        let page = Page::from_bytes(unaligned).unwrap();
+        assert_eq!(page.data().as_ptr() as usize % PAGE_ALIGN, 0);
+        assert_eq!(page.get_slice(0, 1).unwrap(), &[42]);
+        assert_eq!(page.clone().data().as_ptr() as usize % PAGE_ALIGN, 0);
+        
+        assert!(Page::from_bytes(vec![0u8; PAGE_SIZE - 1]).is_err());
     }
     
     #[test]
@@ -99,5 +305,65 @@ mod tests {
         assert!(page.write_at(0, &large_data).is_err());
         
         assert!(page.get_slice(PAGE_SIZE - 10, 20).is_err());
+        assert!(page.write_at(PAGE_PAYLOAD_SIZE - 4, &[1u8; 8]).is_err());
+    }
+    
+    #[test]
+    fn test_page_write_does_not_touch_header() {
+        let mut page = Page::new();
+        page.set_page_id(9);
+        page.write_at(0, &[0xffu8; PAGE_PAYLOAD_SIZE]).unwrap();
+        
+        assert_eq!(page.page_id(), 9);
+        assert_eq!(page.raw_page_type(), 0);
+    }
+    
+    #[test]
+    fn test_page_checksum() {
+        let mut page = Page::new();
+        assert!(page.verify_checksum());
+        
+        page.write_at(0, b"payload").unwrap();
+        assert!(!page.verify_checksum());
+        
+        page.update_checksum();
+        assert!(page.verify_checksum());
+        
+        page.data_mut()[PAGE_SIZE / 2] ^= 0x01;
+        assert!(!page.verify_checksum());
+    }
+    
+    #[test]
+    fn test_page_header_round_trip() {
+        let mut page = Page::init(PageType::BTreeLeaf, 42);
+        assert_eq!(page.page_type(), Some(PageType::BTreeLeaf));
+        assert_eq!(page.page_id(), 42);
+        assert_eq!(page.lsn(), 0);
+        
+        page.set_lsn(u64::MAX - 1);
+        page.set_next_page(7);
+        page.set_page_type(PageType::Overflow);
+        page.write_at(0, b"body").unwrap();
+        page.update_checksum();
+        
+        let copy = Page::from_bytes(page.data().to_vec()).unwrap();
+        assert_eq!(copy.page_type(), Some(PageType::Overflow));
+        assert_eq!(copy.page_id(), 42);
+        assert_eq!(copy.lsn(), u64::MAX - 1);
+        assert_eq!(copy.next_page(), 7);
+        assert_eq!(copy.get_slice(0, 4).unwrap(), b"body");
+        assert!(copy.verify_checksum());
+        
+        for ty in [
+            PageType::Free,
+            PageType::BTreeLeaf,
+            PageType::BTreeInterior,
+            PageType::Overflow,
+            PageType::Meta,
+        ] {
+            assert_eq!(PageType::from_u8(ty as u8), Some(ty));
+        }
+        assert_eq!(Page::new().page_type(), None);
+        assert_eq!(PageType::from_u8(0xee), None);
     }
 }
@@ -1,11 +1,27 @@
+use super::merge::{AppendOperator, MergeOperator};
 use crate::{Key, Value};
-use std::collections::{HashMap, HashSet};
-use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::RwLock;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::ops::Bound;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock, RwLockWriteGuard};
+use std::time::{Duration, Instant};
 
 pub type TxnId = u64;
 pub type Version = u64;
 
+/// How many timed-out transaction ids are remembered so later calls on them
+/// report `TimedOut` rather than `TxnNotFound`.
+const TIMED_OUT_RETAINED: usize = 4096;
+
+/// Retained committed versions past which a commit triggers garbage
+/// collection, unless configured otherwise.
+pub const DEFAULT_GC_THRESHOLD: usize = 100_000;
+
+/// Bytes of keys and values retained versions may hold before older ones
+/// are evicted, unless configured otherwise.
+pub const DEFAULT_VERSION_CACHE_BYTES: u64 = 256 * 1024 * 1024;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TxnStatus {
     Active,
@@ -13,10 +29,54 @@ pub enum TxnStatus {
     Aborted,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IsolationLevel {
+    /// Commits unless another transaction committed a write to the same key
+    /// since this one began. Allows write skew.
+    SnapshotIsolation,
+    /// Snapshot isolation that also aborts any transaction that would
+    /// complete two consecutive read-write antidependencies between
+    /// concurrent transactions, which rules out write skew.
+    #[default]
+    Serializable,
+}
+
+/// A key as seen by a snapshot through the retained versions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Visible {
+    Value(Value),
+    Deleted,
+    /// No version that old is retained; the snapshot sees what the
+    /// underlying store holds.
+    NotRetained,
+}
+
 #[derive(Debug, Clone)]
 pub enum WriteOp {
     Put(Value),
     Delete,
+    /// A merge operand, applied to the key's value when it is read or
+    /// committed.
+    Merge(Value),
+    /// Deletes every key from the one it is buffered under up to this end
+    /// key, exclusive. An empty end has no upper bound.
+    DeleteRange(Key),
+}
+
+impl WriteOp {
+    /// The value `key` has after this op is applied to `existing`.
+    pub fn apply(
+        &self,
+        key: &[u8],
+        existing: Option<&[u8]>,
+        operator: &dyn MergeOperator,
+    ) -> Option<Value> {
+        match self {
+            WriteOp::Put(value) => Some(value.clone()),
+            WriteOp::Delete | WriteOp::DeleteRange(_) => None,
+            WriteOp::Merge(operand) => Some(operator.merge(key, existing, operand)),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -25,7 +85,25 @@ pub struct Transaction {
     pub start_version: Version,
     pub status: TxnStatus,
     pub read_set: HashSet<Key>,
+    /// Half-open `[start, end)` key ranges this transaction scanned. An empty
+    /// `end` has no upper bound.
+    pub read_ranges: Vec<(Key, Key)>,
     pub write_set: HashMap<Key, WriteOp>,
+    pub started_at: Instant,
+    pub deadline: Option<Instant>,
+    pub isolation: IsolationLevel,
+    /// A concurrent transaction read a key this one wrote.
+    pub in_conflict: bool,
+    /// This transaction read a key a concurrent transaction wrote.
+    pub out_conflict: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TxnOptions {
+    pub read_only: bool,
+    pub isolation: IsolationLevel,
+    /// Overrides the manager's default timeout for this transaction.
+    pub timeout: Option<Duration>,
 }
 
 impl Transaction {
@@ -35,24 +113,124 @@ impl Transaction {
             start_version,
             status: TxnStatus::Active,
             read_set: HashSet::new(),
+            read_ranges: Vec::new(),
             write_set: HashMap::new(),
+            started_at: Instant::now(),
+            deadline: None,
+            isolation: IsolationLevel::default(),
+            in_conflict: false,
+            out_conflict: false,
         }
     }
 
+    pub fn is_expired(&self, now: Instant) -> bool {
+        self.deadline.is_some_and(|deadline| now >= deadline)
+    }
+
     pub fn record_read(&mut self, key: Key) {
         self.read_set.insert(key);
     }
 
+    pub fn record_range_read(&mut self, start: Key, end: Key) {
+        self.read_ranges.push((start, end));
+    }
+
     pub fn record_put(&mut self, key: Key, value: Value) {
-        self.write_set.insert(key, WriteOp::Put(value));
+        self.record_point(key, WriteOp::Put(value));
     }
 
     pub fn record_delete(&mut self, key: Key) {
-        self.write_set.insert(key, WriteOp::Delete);
+        self.record_point(key, WriteOp::Delete);
+    }
+
+    /// Buffer a merge operand, folding it into whatever this transaction
+    /// already wrote to `key`.
+    pub fn record_merge(&mut self, key: Key, operand: Value, operator: &dyn MergeOperator) {
+        let op = match self.get_local(&key) {
+            Some(WriteOp::Merge(first)) => WriteOp::Merge(operator.combine(&key, &first, &operand)),
+            Some(op) => {
+                let existing = op.apply(&key, None, operator);
+                WriteOp::Put(operator.merge(&key, existing.as_deref(), &operand))
+            }
+            None => WriteOp::Merge(operand),
+        };
+        self.record_point(key, op);
     }
 
-    pub fn get_local(&self, key: &Key) -> Option<&WriteOp> {
-        self.write_set.get(key)
+    /// Buffer a delete of every key in `[start, end)`. Earlier writes in the
+    /// range are dropped, so any write found in a range at commit was made
+    /// after the range was deleted.
+    pub fn record_delete_range(&mut self, start: Key, end: Key) {
+        let covered: Vec<Key> = self
+            .write_set
+            .keys()
+            .filter(|key| range_contains(&start, &end, key))
+            .cloned()
+            .collect();
+        for key in covered {
+            if let Some(WriteOp::DeleteRange(inner_end)) = self.write_set.remove(&key) {
+                if !end.is_empty() && (inner_end.is_empty() || inner_end > end) {
+                    self.place_range(end.clone(), inner_end);
+                }
+            }
+        }
+        self.place_range(start, end);
+    }
+
+    fn record_point(&mut self, key: Key, op: WriteOp) {
+        if let Some(WriteOp::DeleteRange(end)) = self.write_set.insert(key.clone(), op) {
+            // The range still covers the keys after this one.
+            let mut next = key;
+            next.push(0);
+            self.place_range(next, end);
+        }
+    }
+
+    /// Buffer a range delete under `start`, moving it past any write
+    /// already buffered there, which was made after it.
+    fn place_range(&mut self, mut start: Key, end: Key) {
+        while end.is_empty() || start < end {
+            match self.write_set.get(&start) {
+                None => {
+                    self.write_set.insert(start, WriteOp::DeleteRange(end));
+                    return;
+                }
+                Some(WriteOp::DeleteRange(other)) => {
+                    let end = match other.is_empty() || end.is_empty() {
+                        true => Vec::new(),
+                        false => other.clone().max(end),
+                    };
+                    self.write_set.insert(start, WriteOp::DeleteRange(end));
+                    return;
+                }
+                Some(_) => start.push(0),
+            }
+        }
+    }
+
+    /// This transaction's own write to `key`, with a key under a buffered
+    /// range delete reported as `Delete`. A `Merge` still has to be applied
+    /// to the value the snapshot sees.
+    pub fn get_local(&self, key: &Key) -> Option<WriteOp> {
+        match self.write_set.get(key) {
+            Some(WriteOp::DeleteRange(_)) => Some(WriteOp::Delete),
+            Some(op) => Some(op.clone()),
+            None => self.range_deletes(key).then_some(WriteOp::Delete),
+        }
+    }
+
+    fn range_deletes(&self, key: &[u8]) -> bool {
+        self.write_set.iter().any(|(start, op)| {
+            matches!(op, WriteOp::DeleteRange(end) if range_contains(start, end, key))
+        })
+    }
+
+    /// Whether this transaction writes any key in `[start, end)`.
+    fn writes_within(&self, start: &[u8], end: &[u8]) -> bool {
+        self.write_set.iter().any(|(key, op)| match op {
+            WriteOp::DeleteRange(range_end) => ranges_overlap(key, range_end, start, end),
+            _ => range_contains(start, end, key),
+        })
     }
 
     pub fn is_active(&self) -> bool {
@@ -70,11 +248,177 @@ struct CommittedWrite {
     value: Option<Value>,
 }
 
+/// What a committed serializable transaction leaves behind for as long as
+/// some active transaction is concurrent with it.
+#[derive(Debug)]
+struct SerializableCommit {
+    read_set: HashSet<Key>,
+    read_ranges: Vec<(Key, Key)>,
+    in_conflict: bool,
+    out_conflict: bool,
+}
+
+/// Edges a committing serializable transaction would add, collected before
+/// they are applied so an abort leaves no trace.
+#[derive(Default)]
+struct RwEdges {
+    active_readers: Vec<TxnId>,
+    committed_readers: Vec<Version>,
+    committed_writers: Vec<Version>,
+}
+
+/// Start versions of open transactions as a sorted multiset, so the oldest
+/// snapshot still in use is cheap to find.
+#[derive(Default)]
+struct VersionPins(BTreeMap<Version, usize>);
+
+impl VersionPins {
+    fn pin(&mut self, version: Version) {
+        *self.0.entry(version).or_insert(0) += 1;
+    }
+
+    fn unpin(&mut self, version: Version) {
+        if let Some(count) = self.0.get_mut(&version) {
+            *count -= 1;
+            if *count == 0 {
+                self.0.remove(&version);
+            }
+        }
+    }
+
+    fn oldest(&self) -> Option<Version> {
+        self.0.keys().next().copied()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ReadOnlyTxn {
+    start_version: Version,
+    started_at: Instant,
+    deadline: Option<Instant>,
+}
+
+impl ReadOnlyTxn {
+    fn is_expired(&self, now: Instant) -> bool {
+        self.deadline.is_some_and(|deadline| now >= deadline)
+    }
+}
+
+/// Read-only transactions have no read or write set to track, so they live
+/// outside `active_txns`.
+#[derive(Default)]
+struct ReadOnlyTxns {
+    txns: HashMap<TxnId, ReadOnlyTxn>,
+    pinned: VersionPins,
+}
+
+impl ReadOnlyTxns {
+    fn insert(&mut self, txn_id: TxnId, txn: ReadOnlyTxn) {
+        self.pinned.pin(txn.start_version);
+        self.txns.insert(txn_id, txn);
+    }
+
+    fn remove(&mut self, txn_id: TxnId) -> Option<ReadOnlyTxn> {
+        let txn = self.txns.remove(&txn_id)?;
+        self.pinned.unpin(txn.start_version);
+        Some(txn)
+    }
+
+    fn remove_expired(&mut self, now: Instant) -> Vec<TxnId> {
+        let expired: Vec<TxnId> = self
+            .txns
+            .iter()
+            .filter(|(_, txn)| txn.is_expired(now))
+            .map(|(&txn_id, _)| txn_id)
+            .collect();
+        for &txn_id in &expired {
+            self.remove(txn_id);
+        }
+        expired
+    }
+
+    fn oldest(&self) -> Option<Version> {
+        self.pinned.oldest()
+    }
+}
+
+/// Distinct keys kept in the conflict histogram; the least conflicted are
+/// dropped past this.
+const CONFLICT_KEYS_RETAINED: usize = 1024;
+
+#[derive(Default)]
+struct Metrics {
+    begun: AtomicU64,
+    committed: AtomicU64,
+    committed_writes: AtomicU64,
+    aborted_explicit: AtomicU64,
+    aborted_conflict: AtomicU64,
+    aborted_timeout: AtomicU64,
+    conflict_keys: Mutex<HashMap<Key, u64>>,
+}
+
+impl Metrics {
+    /// Count a commit refused by validation, passing the error through.
+    fn conflict(&self, e: TxnError) -> TxnError {
+        self.aborted_conflict.fetch_add(1, Ordering::Relaxed);
+        if let TxnError::Conflict(key) = &e {
+            let mut keys = self.conflict_keys.lock().unwrap();
+            *keys.entry(key.clone()).or_insert(0) += 1;
+            if keys.len() > CONFLICT_KEYS_RETAINED {
+                if let Some(coldest) = keys.iter().min_by_key(|(_, &n)| n).map(|(k, _)| k.clone()) {
+                    keys.remove(&coldest);
+                }
+            }
+        }
+        e
+    }
+}
+
+/// Counters since the manager was created.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TxnMetrics {
+    pub begun: u64,
+    pub committed: u64,
+    pub aborted_explicit: u64,
+    pub aborted_conflict: u64,
+    pub aborted_timeout: u64,
+    pub active: usize,
+    /// Mean write-set size of committed transactions.
+    pub avg_write_set_size: f64,
+    /// Most conflicted keys, most frequent first.
+    pub top_conflict_keys: Vec<(Key, u64)>,
+}
+
+/// An open transaction, as listed by `TransactionManager::list_active`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TxnInfo {
+    pub id: TxnId,
+    pub start_version: Version,
+    pub age: Duration,
+    pub read_count: usize,
+    pub write_count: usize,
+    pub read_only: bool,
+}
+
 pub struct TransactionManager {
     next_txn_id: AtomicU64,
     current_version: AtomicU64,
     active_txns: RwLock<HashMap<TxnId, Transaction>>,
-    committed_versions: RwLock<HashMap<Key, Vec<CommittedWrite>>>,
+    active_pins: Mutex<VersionPins>,
+    read_only_txns: Mutex<ReadOnlyTxns>,
+    committed_versions: RwLock<BTreeMap<Key, Vec<CommittedWrite>>>,
+    serializable_commits: Mutex<BTreeMap<Version, SerializableCommit>>,
+    active_write_locks: AtomicU64,
+    default_timeout: Option<Duration>,
+    timed_out: Mutex<BTreeSet<TxnId>>,
+    metrics: Metrics,
+    gc_threshold: usize,
+    retained_versions: AtomicUsize,
+    retained_bytes: AtomicU64,
+    version_cache_bytes: u64,
+    /// Snapshots older than this lost versions to the byte cap.
+    snapshot_floor: AtomicU64,
+    merge_operator: Arc<dyn MergeOperator>,
 }
 
 impl TransactionManager {
@@ -83,25 +427,222 @@ impl TransactionManager {
             next_txn_id: AtomicU64::new(1),
             current_version: AtomicU64::new(0),
             active_txns: RwLock::new(HashMap::new()),
-            committed_versions: RwLock::new(HashMap::new()),
+            active_pins: Mutex::new(VersionPins::default()),
+            read_only_txns: Mutex::new(ReadOnlyTxns::default()),
+            committed_versions: RwLock::new(BTreeMap::new()),
+            serializable_commits: Mutex::new(BTreeMap::new()),
+            active_write_locks: AtomicU64::new(0),
+            default_timeout: None,
+            timed_out: Mutex::new(BTreeSet::new()),
+            metrics: Metrics::default(),
+            gc_threshold: DEFAULT_GC_THRESHOLD,
+            retained_versions: AtomicUsize::new(0),
+            retained_bytes: AtomicU64::new(0),
+            version_cache_bytes: DEFAULT_VERSION_CACHE_BYTES,
+            snapshot_floor: AtomicU64::new(0),
+            merge_operator: Arc::new(AppendOperator),
+        }
+    }
+
+    /// A manager that aborts transactions still open `timeout` after they
+    /// began, unless `TxnOptions::timeout` says otherwise.
+    pub fn with_timeout(timeout: Duration) -> Self {
+        TransactionManager {
+            default_timeout: Some(timeout),
+            ..Self::new()
         }
     }
 
+    /// Collect garbage whenever a commit leaves more than `threshold`
+    /// committed versions retained.
+    pub fn with_gc_threshold(mut self, threshold: usize) -> Self {
+        self.gc_threshold = threshold;
+        self
+    }
+
+    /// Cap the bytes retained versions may hold. Past it, versions no open
+    /// transaction needs go first; then the oldest versions are evicted
+    /// anyway, and snapshots that needed them get `SnapshotTooOld`.
+    pub fn with_version_cache_bytes(mut self, bytes: u64) -> Self {
+        self.version_cache_bytes = bytes;
+        self
+    }
+
+    pub fn with_merge_operator(mut self, operator: Arc<dyn MergeOperator>) -> Self {
+        self.merge_operator = operator;
+        self
+    }
+
+    pub fn merge_operator(&self) -> &dyn MergeOperator {
+        &*self.merge_operator
+    }
+
+    fn remove_active(
+        &self,
+        active: &mut HashMap<TxnId, Transaction>,
+        txn_id: TxnId,
+    ) -> Option<Transaction> {
+        let txn = active.remove(&txn_id)?;
+        self.active_pins.lock().unwrap().unpin(txn.start_version);
+        Some(txn)
+    }
+
+    fn active_mut(&self) -> RwLockWriteGuard<'_, HashMap<TxnId, Transaction>> {
+        self.active_write_locks.fetch_add(1, Ordering::Relaxed);
+        self.active_txns.write().unwrap()
+    }
+
+    /// `None` if `txn_id` isn't a read-only transaction, otherwise its start
+    /// version, or `TimedOut` if its deadline has passed.
+    fn read_only_start(&self, txn_id: TxnId) -> Option<Result<Version, TxnError>> {
+        let mut read_only = self.read_only_txns.lock().unwrap();
+        let txn = *read_only.txns.get(&txn_id)?;
+        if txn.is_expired(Instant::now()) {
+            read_only.remove(txn_id);
+            drop(read_only);
+            self.mark_timed_out(txn_id);
+            return Some(Err(TxnError::TimedOut(txn_id)));
+        }
+        Some(Ok(txn.start_version))
+    }
+
+    fn mark_timed_out(&self, txn_id: TxnId) {
+        self.metrics.aborted_timeout.fetch_add(1, Ordering::Relaxed);
+        let mut timed_out = self.timed_out.lock().unwrap();
+        timed_out.insert(txn_id);
+        while timed_out.len() > TIMED_OUT_RETAINED {
+            timed_out.pop_first();
+        }
+    }
+
+    fn not_found(&self, txn_id: TxnId) -> TxnError {
+        if self.timed_out.lock().unwrap().contains(&txn_id) {
+            TxnError::TimedOut(txn_id)
+        } else {
+            TxnError::TxnNotFound(txn_id)
+        }
+    }
+
+    /// Look up an active transaction for modification, aborting it instead
+    /// if its deadline has passed.
+    fn live_txn<'a>(
+        &self,
+        active: &'a mut HashMap<TxnId, Transaction>,
+        txn_id: TxnId,
+    ) -> Result<&'a mut Transaction, TxnError> {
+        let txn = active.get(&txn_id).ok_or_else(|| self.not_found(txn_id))?;
+        if txn.is_expired(Instant::now()) {
+            self.remove_active(active, txn_id);
+            self.mark_timed_out(txn_id);
+            return Err(TxnError::TimedOut(txn_id));
+        }
+        Ok(active.get_mut(&txn_id).unwrap())
+    }
+
     pub fn begin(&self) -> TxnId {
+        self.begin_with_options(TxnOptions::default())
+    }
+
+    /// Begin a transaction that only reads. It sees a snapshot like any other
+    /// transaction but records no read set, never conflicts, and rejects
+    /// writes with `TxnError::ReadOnly`.
+    pub fn begin_read_only(&self) -> TxnId {
+        self.begin_with_options(TxnOptions {
+            read_only: true,
+            ..Default::default()
+        })
+    }
+
+    pub fn begin_with_isolation(&self, isolation: IsolationLevel) -> TxnId {
+        self.begin_with_options(TxnOptions {
+            isolation,
+            ..Default::default()
+        })
+    }
+
+    pub fn begin_with_options(&self, options: TxnOptions) -> TxnId {
+        self.abort_expired();
+
         let txn_id = self.next_txn_id.fetch_add(1, Ordering::SeqCst);
-        let start_version = self.current_version.load(Ordering::SeqCst);
+        self.metrics.begun.fetch_add(1, Ordering::Relaxed);
+        let started_at = Instant::now();
+        let deadline = options
+            .timeout
+            .or(self.default_timeout)
+            .map(|timeout| started_at + timeout);
+
+        // The start version is read and pinned under one lock so a
+        // concurrent GC can't compute its watermark in between.
+        if options.read_only {
+            let mut read_only = self.read_only_txns.lock().unwrap();
+            let start_version = self.current_version.load(Ordering::SeqCst);
+            read_only.insert(
+                txn_id,
+                ReadOnlyTxn {
+                    start_version,
+                    started_at,
+                    deadline,
+                },
+            );
+            return txn_id;
+        }
 
-        let txn = Transaction::new(txn_id, start_version);
+        let start_version = {
+            let mut pins = self.active_pins.lock().unwrap();
+            let start_version = self.current_version.load(Ordering::SeqCst);
+            pins.pin(start_version);
+            start_version
+        };
+        let mut txn = Transaction::new(txn_id, start_version);
+        txn.isolation = options.isolation;
+        txn.started_at = started_at;
+        txn.deadline = deadline;
 
-        let mut active = self.active_txns.write().unwrap();
+        let mut active = self.active_mut();
         active.insert(txn_id, txn);
 
         txn_id
     }
 
+    /// Abort every transaction whose deadline has passed, returning how many
+    /// were aborted. Runs on every `begin` and `commit`.
+    pub fn abort_expired(&self) -> usize {
+        let now = Instant::now();
+        let mut expired = self.read_only_txns.lock().unwrap().remove_expired(now);
+
+        let active_expired: Vec<TxnId> = {
+            let active = self.active_txns.read().unwrap();
+            active
+                .values()
+                .filter(|txn| txn.is_expired(now))
+                .map(|txn| txn.id)
+                .collect()
+        };
+        if !active_expired.is_empty() {
+            let mut active = self.active_mut();
+            for &txn_id in &active_expired {
+                self.remove_active(&mut active, txn_id);
+            }
+        }
+        expired.extend(active_expired);
+
+        for &txn_id in &expired {
+            self.mark_timed_out(txn_id);
+        }
+        expired.len()
+    }
+
+    pub fn is_read_only(&self, txn_id: TxnId) -> bool {
+        self.read_only_txns.lock().unwrap().txns.contains_key(&txn_id)
+    }
+
     pub fn record_read(&self, txn_id: TxnId, key: Key) -> Result<(), TxnError> {
-        let mut active = self.active_txns.write().unwrap();
-        let txn = active.get_mut(&txn_id).ok_or(TxnError::TxnNotFound(txn_id))?;
+        if let Some(start) = self.read_only_start(txn_id) {
+            return start.map(|_| ());
+        }
+
+        let mut active = self.active_mut();
+        let txn = self.live_txn(&mut active, txn_id)?;
 
         if !txn.is_active() {
             return Err(TxnError::TxnNotActive(txn_id));
@@ -111,145 +652,720 @@ impl TransactionManager {
         Ok(())
     }
 
+    /// Record that the transaction read every key in `[start, end)`, so a
+    /// concurrent commit of any key in that range, including one that didn't
+    /// exist when it was scanned, conflicts with it. An empty `end` has no
+    /// upper bound.
+    pub fn record_range_read(&self, txn_id: TxnId, start: Key, end: Key) -> Result<(), TxnError> {
+        if let Some(start) = self.read_only_start(txn_id) {
+            return start.map(|_| ());
+        }
+
+        let mut active = self.active_mut();
+        let txn = self.live_txn(&mut active, txn_id)?;
+
+        if !txn.is_active() {
+            return Err(TxnError::TxnNotActive(txn_id));
+        }
+
+        txn.record_range_read(start, end);
+        Ok(())
+    }
+
     pub fn record_write(&self, txn_id: TxnId, key: Key, value: Option<Value>) -> Result<(), TxnError> {
-        let mut active = self.active_txns.write().unwrap();
-        let txn = active.get_mut(&txn_id).ok_or(TxnError::TxnNotFound(txn_id))?;
+        let op = match value {
+            Some(v) => WriteOp::Put(v),
+            None => WriteOp::Delete,
+        };
+        self.record_op(txn_id, key, op)
+    }
+
+    /// Buffer `op` on `key`. For `DeleteRange`, `key` is the start of the
+    /// range.
+    pub fn record_op(&self, txn_id: TxnId, key: Key, op: WriteOp) -> Result<(), TxnError> {
+        if let Some(start) = self.read_only_start(txn_id) {
+            start?;
+            return Err(TxnError::ReadOnly(txn_id));
+        }
+
+        let mut active = self.active_mut();
+        let txn = self.live_txn(&mut active, txn_id)?;
 
         if !txn.is_active() {
             return Err(TxnError::TxnNotActive(txn_id));
         }
 
-        match value {
-            Some(v) => txn.record_put(key, v),
-            None => txn.record_delete(key),
+        match op {
+            WriteOp::Put(v) => txn.record_put(key, v),
+            WriteOp::Delete => txn.record_delete(key),
+            WriteOp::Merge(operand) => txn.record_merge(key, operand, &*self.merge_operator),
+            WriteOp::DeleteRange(end) => txn.record_delete_range(key, end),
         }
         Ok(())
     }
 
-    pub fn get_local(&self, txn_id: TxnId, key: &Key) -> Result<Option<WriteOp>, TxnError> {
+    fn with_txn<T>(&self, txn_id: TxnId, f: impl FnOnce(&Transaction) -> T) -> Result<T, TxnError> {
         let active = self.active_txns.read().unwrap();
-        let txn = active.get(&txn_id).ok_or(TxnError::TxnNotFound(txn_id))?;
-        Ok(txn.get_local(key).cloned())
+        let txn = active.get(&txn_id).ok_or_else(|| self.not_found(txn_id))?;
+        if txn.is_expired(Instant::now()) {
+            return Err(TxnError::TimedOut(txn_id));
+        }
+        Ok(f(txn))
+    }
+
+    pub fn get_local(&self, txn_id: TxnId, key: &Key) -> Result<Option<WriteOp>, TxnError> {
+        if let Some(start) = self.read_only_start(txn_id) {
+            return start.map(|_| None);
+        }
+        self.with_txn(txn_id, |txn| txn.get_local(key))
+    }
+
+    pub fn write_keys(&self, txn_id: TxnId) -> Result<Vec<Key>, TxnError> {
+        if let Some(start) = self.read_only_start(txn_id) {
+            return start.map(|_| Vec::new());
+        }
+        self.with_txn(txn_id, |txn| txn.write_set.keys().cloned().collect())
+    }
+
+    /// The `[start, end)` ranges the transaction deletes.
+    pub fn delete_ranges(&self, txn_id: TxnId) -> Result<Vec<(Key, Key)>, TxnError> {
+        if let Some(start) = self.read_only_start(txn_id) {
+            return start.map(|_| Vec::new());
+        }
+        self.with_txn(txn_id, |txn| {
+            txn.write_set
+                .iter()
+                .filter_map(|(key, op)| match op {
+                    WriteOp::DeleteRange(end) => Some((key.clone(), end.clone())),
+                    _ => None,
+                })
+                .collect()
+        })
     }
 
     pub fn get_start_version(&self, txn_id: TxnId) -> Result<Version, TxnError> {
-        let active = self.active_txns.read().unwrap();
-        let txn = active.get(&txn_id).ok_or(TxnError::TxnNotFound(txn_id))?;
-        Ok(txn.start_version)
+        if let Some(start) = self.read_only_start(txn_id) {
+            return start;
+        }
+        self.with_txn(txn_id, |txn| txn.start_version)
     }
 
     pub fn commit(&self, txn_id: TxnId) -> Result<(Version, Vec<(Key, WriteOp)>), TxnError> {
-        let txn = {
-            let mut active = self.active_txns.write().unwrap();
-            active.remove(&txn_id).ok_or(TxnError::TxnNotFound(txn_id))?
-        };
+        self.commit_with_base(txn_id, |_| None, |_, _| Vec::new())
+    }
+
+    /// Commit, first recording for every key without retained versions the
+    /// value `base` reports it had before this commit. Snapshots older than
+    /// the commit keep reading that value once the write set reaches the
+    /// underlying store.
+    ///
+    /// `live_in` lists the keys the store holds in a `[start, end)` range
+    /// with their values, so range deletes can be expanded into deletes of
+    /// those keys. Merges are applied to the key's latest value. The writes
+    /// returned are only `Put`s and `Delete`s, in key order.
+    pub fn commit_with_base<F, R>(
+        &self,
+        txn_id: TxnId,
+        mut base: F,
+        mut live_in: R,
+    ) -> Result<(Version, Vec<(Key, WriteOp)>), TxnError>
+    where
+        F: FnMut(&Key) -> Option<Value>,
+        R: FnMut(&Key, &Key) -> Vec<(Key, Value)>,
+    {
+        // An expired transaction is swept here and reported as timed out below.
+        self.abort_expired();
+
+        if let Some(txn) = self.read_only_txns.lock().unwrap().remove(txn_id) {
+            self.metrics.committed.fetch_add(1, Ordering::Relaxed);
+            return Ok((txn.start_version, Vec::new()));
+        }
+
+        // Both held across validation and the insert so two commits can't
+        // validate against the same state, and so rw edges can be marked on
+        // the transactions still running.
+        let mut active = self.active_mut();
+        let txn = self
+            .remove_active(&mut active, txn_id)
+            .ok_or_else(|| self.not_found(txn_id))?;
 
         if !txn.is_active() {
             return Err(TxnError::TxnNotActive(txn_id));
         }
 
-        self.check_conflicts(&txn)?;
+        let mut committed = self.committed_versions.write().unwrap();
+        // Writes that would conflict may have been evicted.
+        self.check_snapshot(txn.start_version)?;
+        Self::check_write_conflicts(&committed, &txn).map_err(|e| self.metrics.conflict(e))?;
+
+        let commit_version = if txn.isolation == IsolationLevel::Serializable {
+            let mut recent = self.serializable_commits.lock().unwrap();
+            let (in_conflict, out_conflict) =
+                Self::check_serializable(&mut active, &committed, &mut recent, &txn)
+                    .map_err(|e| self.metrics.conflict(e))?;
+            let commit_version = self.current_version.fetch_add(1, Ordering::SeqCst) + 1;
+
+            // Nothing committed at or before the oldest active snapshot can
+            // take part in a future edge.
+            let oldest = active
+                .values()
+                .map(|t| t.start_version)
+                .min()
+                .unwrap_or(commit_version);
+            recent.retain(|&version, _| version > oldest);
+            if !active.is_empty() {
+                recent.insert(
+                    commit_version,
+                    SerializableCommit {
+                        read_set: txn.read_set.clone(),
+                        read_ranges: txn.read_ranges.clone(),
+                        in_conflict,
+                        out_conflict,
+                    },
+                );
+            }
+            commit_version
+        } else {
+            self.current_version.fetch_add(1, Ordering::SeqCst) + 1
+        };
+        drop(active);
 
-        let commit_version = self.current_version.fetch_add(1, Ordering::SeqCst) + 1;
+        self.metrics.committed.fetch_add(1, Ordering::Relaxed);
+        self.metrics.committed_writes.fetch_add(txn.write_set.len() as u64, Ordering::Relaxed);
 
-        let writes: Vec<(Key, WriteOp)> = txn.write_set.into_iter().collect();
+        let mut live = HashMap::new();
+        for (start, op) in &txn.write_set {
+            if let WriteOp::DeleteRange(end) = op {
+                live.extend(live_in(start, end));
+            }
+        }
+        // Keys a range delete expands to have their base in the store scan.
+        let mut base = |key: &Key| live.get(key).cloned().or_else(|| base(key));
+        let in_ranges: Vec<Key> = live.keys().cloned().collect();
+        let writes = self.resolve(&committed, txn.write_set, in_ranges, &mut base);
+        self.retain(&mut committed, commit_version, &writes, &mut base);
 
-        {
-            let mut committed = self.committed_versions.write().unwrap();
-            for (key, op) in &writes {
-                let value = match op {
-                    WriteOp::Put(v) => Some(v.clone()),
-                    WriteOp::Delete => None,
-                };
+        Ok((commit_version, writes))
+    }
 
-                let write = CommittedWrite {
-                    version: commit_version,
-                    value,
-                };
+    /// Keep writes made outside any transaction, with `Put`s and `Delete`s
+    /// only, as a new version over what `base` says each key held, so the
+    /// snapshots of transactions open now don't see them and their writes
+    /// to the same keys conflict. Called with commits held off, before the
+    /// writes reach the store.
+    pub fn record_untracked<F>(&self, writes: &[(Key, WriteOp)], mut base: F)
+    where
+        F: FnMut(&Key) -> Option<Value>,
+    {
+        let mut committed = self.committed_versions.write().unwrap();
+        let version = self.current_version.fetch_add(1, Ordering::SeqCst) + 1;
+        self.retain(&mut committed, version, writes, &mut base);
+    }
 
-                committed
-                    .entry(key.clone())
-                    .or_insert_with(Vec::new)
-                    .push(write);
+    /// Drop the versions kept for `keys`, about to be written outside any
+    /// transaction while none is open. Nothing reads them now, and a later
+    /// snapshot would take them over the newer values in the store.
+    pub fn forget<'a>(&self, keys: impl IntoIterator<Item = &'a Key>) {
+        let mut committed = self.committed_versions.write().unwrap();
+        if committed.is_empty() {
+            return;
+        }
+        let (mut freed_versions, mut freed_bytes) = (0, 0);
+        for key in keys {
+            for write in committed.remove(key).unwrap_or_default() {
+                freed_versions += 1;
+                freed_bytes += write_size(key, &write);
             }
         }
-
-        Ok((commit_version, writes))
+        self.retained_versions.fetch_sub(freed_versions, Ordering::Relaxed);
+        self.retained_bytes.fetch_sub(freed_bytes, Ordering::Relaxed);
     }
 
-    pub fn abort(&self, txn_id: TxnId) -> Result<(), TxnError> {
-        let mut active = self.active_txns.write().unwrap();
-        active.remove(&txn_id).ok_or(TxnError::TxnNotFound(txn_id))?;
-        Ok(())
+    /// Keep `writes`, made at `version`, for snapshot reads, each over what
+    /// `base` says its key held if none of its versions are kept yet. Then
+    /// collect, or evict, if that's put the versions over budget.
+    fn retain(
+        &self,
+        committed: &mut BTreeMap<Key, Vec<CommittedWrite>>,
+        version: Version,
+        writes: &[(Key, WriteOp)],
+        base: &mut impl FnMut(&Key) -> Option<Value>,
+    ) {
+        for (key, op) in writes {
+            let value = match op {
+                WriteOp::Put(v) => Some(v.clone()),
+                _ => None,
+            };
+
+            let write = CommittedWrite {
+                version,
+                value,
+            };
+            let mut added = vec![write];
+
+            if !committed.contains_key(key) {
+                added.insert(0, CommittedWrite {
+                    version: 0,
+                    value: base(key),
+                });
+            }
+            for write in &added {
+                self.retained_versions.fetch_add(1, Ordering::Relaxed);
+                self.retained_bytes.fetch_add(write_size(key, write), Ordering::Relaxed);
+            }
+            committed.entry(key.clone()).or_default().extend(added);
+        }
+
+        // Every earlier commit has reached the store by now; this one
+        // hasn't, so its versions must stay.
+        let newest_applied = version - 1;
+        let over_budget = self.retained_versions.load(Ordering::Relaxed) > self.gc_threshold
+            || self.retained_bytes.load(Ordering::Relaxed) > self.version_cache_bytes;
+        if over_budget {
+            let watermark = self
+                .oldest_active_version()
+                .map_or(newest_applied, |oldest| oldest.min(newest_applied));
+            self.collect(committed, watermark);
+        }
+        if self.retained_bytes.load(Ordering::Relaxed) > self.version_cache_bytes {
+            self.evict_to_cap(committed, newest_applied);
+        }
     }
 
-    fn check_conflicts(&self, txn: &Transaction) -> Result<(), TxnError> {
-        let committed = self.committed_versions.read().unwrap();
+    /// Turn a write set into the `Put`s and `Delete`s that carry it out.
+    /// `in_ranges` are the keys the store holds inside its range deletes.
+    /// Those are applied before the other writes, which were made after any
+    /// range holding them.
+    fn resolve(
+        &self,
+        committed: &BTreeMap<Key, Vec<CommittedWrite>>,
+        write_set: HashMap<Key, WriteOp>,
+        in_ranges: Vec<Key>,
+        base: &mut impl FnMut(&Key) -> Option<Value>,
+    ) -> Vec<(Key, WriteOp)> {
+        let mut latest = |key: &Key| match committed.get(key) {
+            Some(versions) => versions.iter().max_by_key(|w| w.version).and_then(|w| w.value.clone()),
+            None => base(key),
+        };
 
-        for key in &txn.read_set {
-            if let Some(versions) = committed.get(key) {
-                for write in versions {
-                    if write.version > txn.start_version {
-                        return Err(TxnError::Conflict(key.clone()));
-                    }
+        let mut resolved = BTreeMap::new();
+        for (start, op) in &write_set {
+            if let WriteOp::DeleteRange(end) = op {
+                let retained = committed.range::<[u8], _>(key_bounds(start, end)).map(|(k, _)| k);
+                let stored = in_ranges.iter().filter(|k| range_contains(start, end, k));
+                for key in retained.chain(stored) {
+                    resolved.insert(key.clone(), WriteOp::Delete);
                 }
             }
         }
+        // Deleting what's already gone would only add a version.
+        resolved.retain(|key, _| latest(key).is_some());
+
+        for (key, op) in write_set {
+            let op = match op {
+                WriteOp::DeleteRange(_) => continue,
+                WriteOp::Merge(_) => {
+                    let existing = latest(&key);
+                    match op.apply(&key, existing.as_deref(), &*self.merge_operator) {
+                        Some(value) => WriteOp::Put(value),
+                        None => WriteOp::Delete,
+                    }
+                }
+                op => op,
+            };
+            resolved.insert(key, op);
+        }
+        resolved.into_iter().collect()
+    }
 
-        for key in txn.write_set.keys() {
-            if let Some(versions) = committed.get(key) {
-                for write in versions {
-                    if write.version > txn.start_version {
+    pub fn abort(&self, txn_id: TxnId) -> Result<(), TxnError> {
+        if self.read_only_txns.lock().unwrap().remove(txn_id).is_none() {
+            let mut active = self.active_mut();
+            self.remove_active(&mut active, txn_id)
+                .ok_or_else(|| self.not_found(txn_id))?;
+        }
+        self.metrics.aborted_explicit.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// First committer wins: abort if a key this transaction wrote was
+    /// committed by someone else after it began.
+    fn check_write_conflicts(
+        committed: &BTreeMap<Key, Vec<CommittedWrite>>,
+        txn: &Transaction,
+    ) -> Result<(), TxnError> {
+        let newer = |versions: &Vec<CommittedWrite>| {
+            versions.iter().any(|w| w.version > txn.start_version)
+        };
+        for (key, op) in &txn.write_set {
+            // A range delete conflicts with any write committed inside it.
+            if let WriteOp::DeleteRange(end) = op {
+                for (key, versions) in committed.range::<[u8], _>(key_bounds(key, end)) {
+                    if newer(versions) {
                         return Err(TxnError::Conflict(key.clone()));
                     }
                 }
+            } else if committed.get(key).is_some_and(newer) {
+                return Err(TxnError::Conflict(key.clone()));
             }
         }
 
         Ok(())
     }
 
-    pub fn get_visible_value(&self, key: &Key, start_version: Version) -> Option<Value> {
-        let committed = self.committed_versions.read().unwrap();
-
-        if let Some(versions) = committed.get(key) {
-            let mut latest: Option<&CommittedWrite> = None;
+    /// Find the rw-antidependencies `txn` takes part in and refuse the commit
+    /// if it would leave any transaction with both an incoming and an
+    /// outgoing edge. This is conservative: some such structures are still
+    /// serializable. On success the edges are marked on the other side and
+    /// `txn`'s own `(in_conflict, out_conflict)` are returned.
+    fn check_serializable(
+        active: &mut HashMap<TxnId, Transaction>,
+        committed: &BTreeMap<Key, Vec<CommittedWrite>>,
+        recent: &mut BTreeMap<Version, SerializableCommit>,
+        txn: &Transaction,
+    ) -> Result<(bool, bool), TxnError> {
+        let failure = Err(TxnError::SerializationFailure(txn.id));
+        let mut edges = RwEdges::default();
+        let mut in_conflict = txn.in_conflict;
+        let mut out_conflict = txn.out_conflict;
+
+        // A concurrent commit into a scanned range is a phantom.
+        for (start, end) in &txn.read_ranges {
+            for (key, versions) in committed.range::<[u8], _>(key_bounds(start, end)) {
+                if versions.iter().any(|w| w.version > txn.start_version) {
+                    return Err(TxnError::Conflict(key.clone()));
+                }
+            }
+        }
 
-            for write in versions {
-                if write.version <= start_version {
-                    if latest.is_none() || write.version > latest.unwrap().version {
-                        latest = Some(write);
+        // txn -rw-> writer: a concurrent transaction committed over a key
+        // this one read.
+        for key in &txn.read_set {
+            for write in committed.get(key).into_iter().flatten() {
+                if write.version > txn.start_version {
+                    out_conflict = true;
+                    if let Some(writer) = recent.get(&write.version) {
+                        if writer.out_conflict {
+                            return failure;
+                        }
+                        edges.committed_writers.push(write.version);
                     }
                 }
             }
+        }
 
-            if let Some(w) = latest {
-                return w.value.clone();
+        // reader -rw-> txn: a concurrent transaction read a key this one is
+        // about to overwrite.
+        let overwrites = |read_set: &HashSet<Key>, read_ranges: &[(Key, Key)]| {
+            txn.write_set.keys().any(|k| read_set.contains(k))
+                || read_set.iter().any(|k| txn.range_deletes(k))
+                || read_ranges.iter().any(|(start, end)| txn.writes_within(start, end))
+        };
+        for (&id, reader) in active.iter() {
+            if reader.isolation == IsolationLevel::Serializable
+                && overwrites(&reader.read_set, &reader.read_ranges)
+            {
+                in_conflict = true;
+                edges.active_readers.push(id);
+            }
+        }
+        for (&version, reader) in recent.range(txn.start_version + 1..) {
+            if overwrites(&reader.read_set, &reader.read_ranges) {
+                in_conflict = true;
+                if reader.in_conflict {
+                    return failure;
+                }
+                edges.committed_readers.push(version);
             }
         }
 
-        None
+        if in_conflict && out_conflict {
+            return failure;
+        }
+
+        for id in edges.active_readers {
+            if let Some(reader) = active.get_mut(&id) {
+                reader.out_conflict = true;
+            }
+        }
+        for version in edges.committed_readers {
+            if let Some(reader) = recent.get_mut(&version) {
+                reader.out_conflict = true;
+            }
+        }
+        for version in edges.committed_writers {
+            if let Some(writer) = recent.get_mut(&version) {
+                writer.in_conflict = true;
+            }
+        }
+        Ok((in_conflict, out_conflict))
+    }
+
+    /// What a snapshot at `start_version` sees for `key` among the retained
+    /// versions, or `SnapshotTooOld` if versions it needs were evicted to
+    /// stay under the byte cap.
+    pub fn get_visible_value(&self, key: &Key, start_version: Version) -> Result<Visible, TxnError> {
+        let committed = self.committed_versions.read().unwrap();
+        self.check_snapshot(start_version)?;
+
+        let latest = committed.get(key).and_then(|versions| {
+            versions
+                .iter()
+                .filter(|w| w.version <= start_version)
+                .max_by_key(|w| w.version)
+        });
+        Ok(match latest {
+            Some(CommittedWrite { value: Some(v), .. }) => Visible::Value(v.clone()),
+            Some(CommittedWrite { value: None, .. }) => Visible::Deleted,
+            None => Visible::NotRetained,
+        })
+    }
+
+    fn check_snapshot(&self, start_version: Version) -> Result<(), TxnError> {
+        if start_version < self.snapshot_floor.load(Ordering::SeqCst) {
+            return Err(TxnError::SnapshotTooOld(start_version));
+        }
+        Ok(())
+    }
+
+    /// What a snapshot at `start_version` sees for `key`, given `current`,
+    /// the value in the underlying store. The store must be read before this
+    /// call: a commit records its versions here before applying them there,
+    /// so a `current` read earlier can't include a write this snapshot
+    /// shouldn't see.
+    pub fn visible_or(
+        &self,
+        key: &Key,
+        start_version: Version,
+        current: Option<Value>,
+    ) -> Result<Option<Value>, TxnError> {
+        Ok(match self.get_visible_value(key, start_version)? {
+            Visible::Value(value) => Some(value),
+            Visible::Deleted => None,
+            Visible::NotRetained => current,
+        })
+    }
+
+    /// `visible_or` for every key in `[start, end)` with retained versions:
+    /// each key paired with what a snapshot at `start_version` sees for it.
+    /// Keys without a version that old are left out.
+    pub fn visible_range(
+        &self,
+        start: &[u8],
+        end: &[u8],
+        start_version: Version,
+    ) -> Result<Vec<(Key, Option<Value>)>, TxnError> {
+        let committed = self.committed_versions.read().unwrap();
+        self.check_snapshot(start_version)?;
+        Ok(committed
+            .range::<[u8], _>(key_bounds(start, end))
+            .filter_map(|(key, versions)| {
+                versions
+                    .iter()
+                    .filter(|w| w.version <= start_version)
+                    .max_by_key(|w| w.version)
+                    .map(|w| (key.clone(), w.value.clone()))
+            })
+            .collect())
+    }
+
+    /// The transaction's own uncommitted writes to keys in `[start, end)`,
+    /// including range deletes that overlap it. Range deletes come first;
+    /// the other writes were made after any range holding them.
+    pub fn local_range(&self, txn_id: TxnId, start: &[u8], end: &[u8]) -> Result<Vec<(Key, WriteOp)>, TxnError> {
+        if let Some(start) = self.read_only_start(txn_id) {
+            return start.map(|_| Vec::new());
+        }
+        self.with_txn(txn_id, |txn| {
+            let mut writes: Vec<(Key, WriteOp)> = txn
+                .write_set
+                .iter()
+                .filter(|(key, op)| match op {
+                    WriteOp::DeleteRange(range_end) => ranges_overlap(key, range_end, start, end),
+                    _ => range_contains(start, end, key),
+                })
+                .map(|(key, op)| (key.clone(), op.clone()))
+                .collect();
+            writes.sort_by_key(|(_, op)| !matches!(op, WriteOp::DeleteRange(_)));
+            writes
+        })
     }
 
     pub fn active_count(&self) -> usize {
-        self.active_txns.read().unwrap().len()
+        let read_only = self.read_only_txns.lock().unwrap().txns.len();
+        self.active_txns.read().unwrap().len() + read_only
+    }
+
+    /// The start version of the oldest open transaction, read-only or not.
+    pub fn oldest_active_version(&self) -> Option<Version> {
+        let read_only = self.read_only_txns.lock().unwrap().oldest();
+        let active = self.active_pins.lock().unwrap().oldest();
+        read_only.into_iter().chain(active).min()
+    }
+
+    /// The oldest version any open transaction may still read at. `gc`
+    /// never collects past it.
+    pub fn gc_watermark(&self) -> Version {
+        let current = self.current_version.load(Ordering::SeqCst);
+        self.oldest_active_version().map_or(current, |oldest| oldest.min(current))
+    }
+
+    pub fn metrics(&self, top_n: usize) -> TxnMetrics {
+        let m = &self.metrics;
+        let committed = m.committed.load(Ordering::Relaxed);
+        let committed_writes = m.committed_writes.load(Ordering::Relaxed);
+
+        let mut top_conflict_keys: Vec<(Key, u64)> = m
+            .conflict_keys
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(k, &n)| (k.clone(), n))
+            .collect();
+        top_conflict_keys.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        top_conflict_keys.truncate(top_n);
+
+        TxnMetrics {
+            begun: m.begun.load(Ordering::Relaxed),
+            committed,
+            aborted_explicit: m.aborted_explicit.load(Ordering::Relaxed),
+            aborted_conflict: m.aborted_conflict.load(Ordering::Relaxed),
+            aborted_timeout: m.aborted_timeout.load(Ordering::Relaxed),
+            active: self.active_count(),
+            avg_write_set_size: match committed {
+                0 => 0.0,
+                n => committed_writes as f64 / n as f64,
+            },
+            top_conflict_keys,
+        }
+    }
+
+    /// Every open transaction, oldest first.
+    pub fn list_active(&self) -> Vec<TxnInfo> {
+        let now = Instant::now();
+        let mut infos: Vec<TxnInfo> = self
+            .active_txns
+            .read()
+            .unwrap()
+            .values()
+            .map(|txn| TxnInfo {
+                id: txn.id,
+                start_version: txn.start_version,
+                age: now.saturating_duration_since(txn.started_at),
+                read_count: txn.read_set.len() + txn.read_ranges.len(),
+                write_count: txn.write_set.len(),
+                read_only: false,
+            })
+            .collect();
+        infos.extend(self.read_only_txns.lock().unwrap().txns.iter().map(|(&id, txn)| TxnInfo {
+            id,
+            start_version: txn.start_version,
+            age: now.saturating_duration_since(txn.started_at),
+            read_count: 0,
+            write_count: 0,
+            read_only: true,
+        }));
+        infos.sort_by_key(|info| info.id);
+        infos
+    }
+
+    /// Committed versions kept for snapshot reads.
+    pub fn retained_versions(&self) -> usize {
+        self.retained_versions.load(Ordering::Relaxed)
+    }
+
+    /// Approximate key and value bytes held by retained versions.
+    pub fn retained_bytes(&self) -> u64 {
+        self.retained_bytes.load(Ordering::Relaxed)
     }
 
     pub fn current_version(&self) -> Version {
         self.current_version.load(Ordering::SeqCst)
     }
 
+    /// Resume numbering after `version`, the last commit found in the log.
+    pub fn recover_version(&self, version: Version) {
+        self.current_version.fetch_max(version, Ordering::SeqCst);
+    }
+
+    /// Drop versions no snapshot at or after `min_version` can read: for
+    /// each key, everything older than its newest version at or below
+    /// `min_version`, and the key entirely once that version is its latest,
+    /// since the store then holds the same value. `min_version` is capped at
+    /// `gc_watermark`, so open transactions keep what they can see.
     pub fn gc(&self, min_version: Version) {
+        let watermark = min_version.min(self.gc_watermark());
         let mut committed = self.committed_versions.write().unwrap();
+        self.collect(&mut committed, watermark);
+    }
 
-        for versions in committed.values_mut() {
-            versions.retain(|w| w.version >= min_version);
+    /// Collect past the GC watermark, one commit version at a time, until the
+    /// byte cap is met, raising the snapshot floor as it goes.
+    fn evict_to_cap(&self, committed: &mut BTreeMap<Key, Vec<CommittedWrite>>, newest_applied: Version) {
+        let mut versions: Vec<Version> = committed
+            .values()
+            .flatten()
+            .map(|w| w.version)
+            .filter(|&v| v <= newest_applied)
+            .collect();
+        versions.sort_unstable();
+        versions.dedup();
+
+        for version in versions {
+            self.snapshot_floor.fetch_max(version, Ordering::SeqCst);
+            self.collect(committed, version);
+            if self.retained_bytes.load(Ordering::Relaxed) <= self.version_cache_bytes {
+                break;
+            }
         }
+    }
+
+    fn collect(&self, committed: &mut BTreeMap<Key, Vec<CommittedWrite>>, watermark: Version) {
+        let mut freed_versions = 0;
+        let mut freed_bytes = 0;
+
+        committed.retain(|key, versions| {
+            // Versions are pushed in commit order, so they're ascending.
+            let Some(newest_visible) = versions.iter().rposition(|w| w.version <= watermark) else {
+                return true;
+            };
+            let cut = match newest_visible + 1 == versions.len() {
+                true => versions.len(),
+                false => newest_visible,
+            };
+            for write in versions.drain(..cut) {
+                freed_versions += 1;
+                freed_bytes += write_size(key, &write);
+            }
+            !versions.is_empty()
+        });
 
-        committed.retain(|_, versions| !versions.is_empty());
+        self.retained_versions.fetch_sub(freed_versions, Ordering::Relaxed);
+        self.retained_bytes.fetch_sub(freed_bytes, Ordering::Relaxed);
     }
 }
 
+fn write_size(key: &Key, write: &CommittedWrite) -> u64 {
+    (key.len() + write.value.as_ref().map_or(0, Vec::len)) as u64
+}
+
+fn range_contains(start: &[u8], end: &[u8], key: &[u8]) -> bool {
+    key >= start && (end.is_empty() || key < end)
+}
+
+fn ranges_overlap(a_start: &[u8], a_end: &[u8], b_start: &[u8], b_end: &[u8]) -> bool {
+    (a_end.is_empty() || b_start < a_end) && (b_end.is_empty() || a_start < b_end)
+}
+
+/// `[start, end)` as bounds for `BTreeMap::range`.
+fn key_bounds<'a>(start: &'a [u8], end: &'a [u8]) -> (Bound<&'a [u8]>, Bound<&'a [u8]>) {
+    let upper = match end.is_empty() {
+        true => Bound::Unbounded,
+        false => Bound::Excluded(end),
+    };
+    (Bound::Included(start), upper)
+}
+
 impl Default for TransactionManager {
     fn default() -> Self {
         Self::new()
@@ -261,6 +1377,11 @@ pub enum TxnError {
     TxnNotFound(TxnId),
     TxnNotActive(TxnId),
     Conflict(Key),
+    ReadOnly(TxnId),
+    TimedOut(TxnId),
+    SerializationFailure(TxnId),
+    /// Versions a snapshot taken at this version needs were evicted.
+    SnapshotTooOld(Version),
 }
 
 impl std::fmt::Display for TxnError {
@@ -269,6 +1390,14 @@ impl std::fmt::Display for TxnError {
             TxnError::TxnNotFound(id) => write!(f, "transaction {} not found", id),
             TxnError::TxnNotActive(id) => write!(f, "transaction {} not active", id),
             TxnError::Conflict(key) => write!(f, "conflict on key {:?}", key),
+            TxnError::ReadOnly(id) => write!(f, "transaction {} is read-only", id),
+            TxnError::TimedOut(id) => write!(f, "transaction {} timed out", id),
+            TxnError::SnapshotTooOld(version) => {
+                write!(f, "snapshot at version {} is too old", version)
+            }
+            TxnError::SerializationFailure(id) => {
+                write!(f, "transaction {} could not be serialized", id)
+            }
         }
     }
 }
@@ -312,7 +1441,7 @@ mod tests {
         tm.abort(txn).unwrap();
 
         assert_eq!(tm.active_count(), 0);
-        assert!(tm.get_visible_value(&b"key".to_vec(), 100).is_none());
+        assert_eq!(tm.get_visible_value(&b"key".to_vec(), 100), Ok(Visible::NotRetained));
     }
 
     #[test]
@@ -331,7 +1460,7 @@ mod tests {
         tm.commit(t3).unwrap();
 
         let visible = tm.get_visible_value(&b"key".to_vec(), start_version);
-        assert_eq!(visible, Some(b"v1".to_vec()));
+        assert_eq!(visible, Ok(Visible::Value(b"v1".to_vec())));
 
         tm.commit(t2).unwrap();
     }
@@ -343,7 +1472,7 @@ mod tests {
         let t1 = tm.begin();
         let t2 = tm.begin();
 
-        tm.record_read(t1, b"key".to_vec()).unwrap();
+        tm.record_write(t1, b"key".to_vec(), Some(b"v1".to_vec())).unwrap();
         tm.record_write(t2, b"key".to_vec(), Some(b"v2".to_vec())).unwrap();
         tm.commit(t2).unwrap();
 
@@ -351,6 +1480,151 @@ mod tests {
         assert!(matches!(result, Err(TxnError::Conflict(_))));
     }
 
+    #[test]
+    fn test_read_own_merge() {
+        let operator = AppendOperator;
+        let mut txn = Transaction::new(1, 0);
+
+        txn.record_merge(b"log".to_vec(), b"a".to_vec(), &operator);
+        txn.record_merge(b"log".to_vec(), b"b".to_vec(), &operator);
+        let op = txn.get_local(&b"log".to_vec()).unwrap();
+        assert!(matches!(&op, WriteOp::Merge(v) if v == b"ab"));
+        assert_eq!(op.apply(b"log", Some(b"x"), &operator), Some(b"xab".to_vec()));
+
+        // Over a local write the merge resolves at once.
+        txn.record_put(b"k".to_vec(), b"v".to_vec());
+        txn.record_merge(b"k".to_vec(), b"1".to_vec(), &operator);
+        assert!(matches!(txn.get_local(&b"k".to_vec()), Some(WriteOp::Put(v)) if v == b"v1"));
+    }
+
+    #[test]
+    fn test_merge_stacks_commit_in_order() {
+        let tm = TransactionManager::new();
+
+        let t1 = tm.begin();
+        tm.record_op(t1, b"log".to_vec(), WriteOp::Merge(b"a".to_vec())).unwrap();
+        tm.record_op(t1, b"log".to_vec(), WriteOp::Merge(b"b".to_vec())).unwrap();
+        let (_, writes) = tm.commit_with_base(t1, |_| Some(b"x".to_vec()), |_, _| Vec::new()).unwrap();
+        assert!(matches!(&writes[..], [(_, WriteOp::Put(v))] if v == b"xab"));
+
+        let t2 = tm.begin();
+        tm.record_op(t2, b"log".to_vec(), WriteOp::Merge(b"c".to_vec())).unwrap();
+        let (version, writes) = tm.commit(t2).unwrap();
+        assert!(matches!(&writes[..], [(_, WriteOp::Put(v))] if v == b"xabc"));
+        assert_eq!(
+            tm.get_visible_value(&b"log".to_vec(), version - 1),
+            Ok(Visible::Value(b"xab".to_vec()))
+        );
+    }
+
+    #[test]
+    fn test_range_delete_conflicts_with_point_write_inside() {
+        let tm = TransactionManager::new();
+
+        let t1 = tm.begin();
+        let t2 = tm.begin();
+        tm.record_op(t1, b"b".to_vec(), WriteOp::DeleteRange(b"d".to_vec())).unwrap();
+        tm.record_write(t2, b"c".to_vec(), Some(b"v".to_vec())).unwrap();
+        tm.commit(t2).unwrap();
+        assert!(matches!(tm.commit(t1), Err(TxnError::Conflict(k)) if k == b"c"));
+
+        // A write just past the end doesn't.
+        let t3 = tm.begin();
+        let t4 = tm.begin();
+        tm.record_op(t3, b"b".to_vec(), WriteOp::DeleteRange(b"d".to_vec())).unwrap();
+        tm.record_write(t4, b"d".to_vec(), Some(b"v".to_vec())).unwrap();
+        tm.commit(t4).unwrap();
+        assert!(tm.commit(t3).is_ok());
+    }
+
+    #[test]
+    fn test_range_delete_keeps_later_writes() {
+        let mut txn = Transaction::new(1, 0);
+        txn.record_put(b"b".to_vec(), b"old".to_vec());
+        txn.record_delete_range(b"a".to_vec(), b"z".to_vec());
+        txn.record_put(b"a".to_vec(), b"new".to_vec());
+
+        assert!(matches!(txn.get_local(&b"a".to_vec()), Some(WriteOp::Put(v)) if v == b"new"));
+        assert!(matches!(txn.get_local(&b"b".to_vec()), Some(WriteOp::Delete)));
+        assert!(txn.get_local(&b"z".to_vec()).is_none());
+
+        let tm = TransactionManager::new();
+        let t = tm.begin();
+        tm.record_op(t, b"a".to_vec(), WriteOp::DeleteRange(b"z".to_vec())).unwrap();
+        tm.record_write(t, b"m".to_vec(), Some(b"new".to_vec())).unwrap();
+        let store = vec![(b"c".to_vec(), b"1".to_vec()), (b"m".to_vec(), b"2".to_vec())];
+        let (_, writes) = tm.commit_with_base(t, |_| None, |_, _| store.clone()).unwrap();
+        assert!(matches!(&writes[..], [
+            (c, WriteOp::Delete),
+            (m, WriteOp::Put(v)),
+        ] if c == b"c" && m == b"m" && v == b"new"));
+    }
+
+    /// Two on-call doctors, each checking that the other is still on call
+    /// before going off call themselves.
+    fn write_skew(tm: &TransactionManager, isolation: IsolationLevel) -> Result<(), TxnError> {
+        let (x, y) = (b"x".to_vec(), b"y".to_vec());
+        let t1 = tm.begin_with_isolation(isolation);
+        let t2 = tm.begin_with_isolation(isolation);
+
+        for t in [t1, t2] {
+            tm.record_read(t, x.clone())?;
+            tm.record_read(t, y.clone())?;
+        }
+        tm.record_write(t1, x, Some(b"off".to_vec()))?;
+        tm.record_write(t2, y, Some(b"off".to_vec()))?;
+
+        tm.commit(t1)?;
+        tm.commit(t2).map(|_| ())
+    }
+
+    #[test]
+    fn test_write_skew_allowed_under_snapshot_isolation() {
+        let tm = TransactionManager::new();
+        assert!(write_skew(&tm, IsolationLevel::SnapshotIsolation).is_ok());
+    }
+
+    #[test]
+    fn test_write_skew_aborts_under_serializable() {
+        let tm = TransactionManager::new();
+        assert!(matches!(
+            write_skew(&tm, IsolationLevel::Serializable),
+            Err(TxnError::SerializationFailure(_))
+        ));
+    }
+
+    #[test]
+    fn test_single_rw_edge_is_serializable() {
+        let tm = TransactionManager::new();
+
+        // t1 read a key t2 then overwrote; t1 simply serializes first.
+        let t1 = tm.begin();
+        let t2 = tm.begin();
+        tm.record_read(t1, b"key".to_vec()).unwrap();
+        tm.record_write(t1, b"other".to_vec(), Some(b"v".to_vec())).unwrap();
+        tm.record_write(t2, b"key".to_vec(), Some(b"v2".to_vec())).unwrap();
+        tm.commit(t2).unwrap();
+        tm.commit(t1).unwrap();
+    }
+
+    #[test]
+    fn test_serializable_detects_edge_through_committed_reader() {
+        let tm = TransactionManager::new();
+        let (x, y) = (b"x".to_vec(), b"y".to_vec());
+
+        // t1 reads y and writes x; t2 reads x and writes y. t1 commits
+        // first, so the edge t1 -rw-> t2 is found from t1's saved read set.
+        let t1 = tm.begin();
+        let t2 = tm.begin();
+        tm.record_read(t1, y.clone()).unwrap();
+        tm.record_write(t1, x.clone(), Some(b"1".to_vec())).unwrap();
+        tm.record_read(t2, x).unwrap();
+        tm.record_write(t2, y, Some(b"2".to_vec())).unwrap();
+
+        tm.commit(t1).unwrap();
+        assert!(matches!(tm.commit(t2), Err(TxnError::SerializationFailure(_))));
+    }
+
     #[test]
     fn test_gc() {
         let tm = TransactionManager::new();
@@ -364,10 +1638,10 @@ mod tests {
         tm.gc(3);
 
         let visible = tm.get_visible_value(&b"key".to_vec(), 2);
-        assert!(visible.is_none());
+        assert_eq!(visible, Ok(Visible::NotRetained));
 
         let visible = tm.get_visible_value(&b"key".to_vec(), 5);
-        assert_eq!(visible, Some(b"v4".to_vec()));
+        assert_eq!(visible, Ok(Visible::Value(b"v4".to_vec())));
     }
 
     #[test]
@@ -382,7 +1656,326 @@ mod tests {
         tm.record_write(t2, b"key".to_vec(), None).unwrap();
         tm.commit(t2).unwrap();
 
-        assert!(tm.get_visible_value(&b"key".to_vec(), 2).is_none());
-        assert_eq!(tm.get_visible_value(&b"key".to_vec(), 1), Some(b"value".to_vec()));
+        assert_eq!(tm.get_visible_value(&b"key".to_vec(), 2), Ok(Visible::Deleted));
+        assert_eq!(
+            tm.get_visible_value(&b"key".to_vec(), 1),
+            Ok(Visible::Value(b"value".to_vec()))
+        );
+    }
+
+    #[test]
+    fn test_read_only_transaction() {
+        let tm = TransactionManager::new();
+        let key = b"key".to_vec();
+
+        let t1 = tm.begin();
+        tm.record_write(t1, key.clone(), Some(b"v1".to_vec())).unwrap();
+        tm.commit(t1).unwrap();
+
+        let reader = tm.begin_read_only();
+        assert!(tm.is_read_only(reader));
+        assert_eq!(tm.active_count(), 1);
+        assert_eq!(
+            tm.record_write(reader, key.clone(), None),
+            Err(TxnError::ReadOnly(reader))
+        );
+        tm.record_read(reader, key.clone()).unwrap();
+
+        let t2 = tm.begin();
+        tm.record_write(t2, key.clone(), Some(b"v2".to_vec())).unwrap();
+        tm.commit(t2).unwrap();
+
+        let start_version = tm.get_start_version(reader).unwrap();
+        assert_eq!(
+            tm.get_visible_value(&key, start_version),
+            Ok(Visible::Value(b"v1".to_vec()))
+        );
+
+        // A concurrent write to a key it read doesn't make it conflict.
+        assert!(tm.commit(reader).unwrap().1.is_empty());
+        assert_eq!(tm.active_count(), 0);
+        assert!(matches!(tm.commit(reader), Err(TxnError::TxnNotFound(_))));
+    }
+
+    #[test]
+    fn test_read_only_transactions_skip_active_write_lock() {
+        let tm = std::sync::Arc::new(TransactionManager::new());
+        let before = tm.active_write_locks.load(Ordering::Relaxed);
+
+        let readers: Vec<_> = (0..8)
+            .map(|_| {
+                let tm = std::sync::Arc::clone(&tm);
+                std::thread::spawn(move || {
+                    for i in 0..500u32 {
+                        let txn = tm.begin_read_only();
+                        tm.record_read(txn, i.to_le_bytes().to_vec()).unwrap();
+                        tm.commit(txn).unwrap();
+                    }
+                })
+            })
+            .collect();
+
+        let writer = tm.begin();
+        tm.record_write(writer, b"key".to_vec(), Some(b"v".to_vec())).unwrap();
+        tm.commit(writer).unwrap();
+
+        for handle in readers {
+            handle.join().unwrap();
+        }
+
+        // begin, record_write and commit of the one writer.
+        assert_eq!(tm.active_write_locks.load(Ordering::Relaxed) - before, 3);
+        assert_eq!(tm.active_count(), 0);
+    }
+
+    #[test]
+    fn test_gc_watermark_respects_read_only_transaction() {
+        let tm = TransactionManager::new();
+        let key = b"key".to_vec();
+
+        let t1 = tm.begin();
+        tm.record_write(t1, key.clone(), Some(b"v1".to_vec())).unwrap();
+        tm.commit(t1).unwrap();
+
+        let reader = tm.begin_read_only();
+        for i in 2..5 {
+            let t = tm.begin();
+            tm.record_write(t, key.clone(), Some(format!("v{}", i).into_bytes())).unwrap();
+            tm.commit(t).unwrap();
+        }
+
+        assert_eq!(tm.gc_watermark(), 1);
+        tm.gc(tm.gc_watermark());
+        let start_version = tm.get_start_version(reader).unwrap();
+        assert_eq!(
+            tm.get_visible_value(&key, start_version),
+            Ok(Visible::Value(b"v1".to_vec()))
+        );
+
+        tm.abort(reader).unwrap();
+        assert_eq!(tm.gc_watermark(), tm.current_version());
+    }
+
+    #[test]
+    fn test_transaction_timeout() {
+        let tm = TransactionManager::with_timeout(Duration::from_millis(20));
+
+        let stale = tm.begin();
+        tm.record_write(stale, b"key".to_vec(), Some(b"v".to_vec())).unwrap();
+        let stale_reader = tm.begin_read_only();
+        std::thread::sleep(Duration::from_millis(40));
+
+        assert!(matches!(tm.commit(stale), Err(TxnError::TimedOut(_))));
+        assert_eq!(tm.active_count(), 0);
+        assert_eq!(tm.get_start_version(stale_reader), Err(TxnError::TimedOut(stale_reader)));
+        assert_eq!(tm.abort(stale), Err(TxnError::TimedOut(stale)));
+    }
+
+    #[test]
+    fn test_transaction_under_timeout_unaffected() {
+        let tm = TransactionManager::with_timeout(Duration::from_millis(20));
+
+        let long = tm.begin_with_options(TxnOptions {
+            timeout: Some(Duration::from_secs(60)),
+            ..Default::default()
+        });
+        tm.record_write(long, b"a".to_vec(), Some(b"1".to_vec())).unwrap();
+        let short = tm.begin();
+        std::thread::sleep(Duration::from_millis(40));
+
+        assert_eq!(tm.abort_expired(), 1);
+        assert_eq!(tm.active_count(), 1);
+        assert_eq!(
+            tm.record_write(short, b"b".to_vec(), None),
+            Err(TxnError::TimedOut(short))
+        );
+        assert_eq!(tm.commit(long).unwrap().1.len(), 1);
+    }
+
+    #[test]
+    fn test_range_read_conflicts_with_phantom_insert() {
+        let tm = TransactionManager::new();
+
+        let t1 = tm.begin();
+        tm.record_range_read(t1, b"user:100".to_vec(), b"user:200".to_vec()).unwrap();
+        tm.record_write(t1, b"summary".to_vec(), Some(b"0 users".to_vec())).unwrap();
+
+        let t2 = tm.begin();
+        tm.record_write(t2, b"user:150".to_vec(), Some(b"new".to_vec())).unwrap();
+        tm.commit(t2).unwrap();
+
+        assert_eq!(
+            tm.commit(t1).unwrap_err(),
+            TxnError::Conflict(b"user:150".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_range_read_ignores_writes_outside_range() {
+        let tm = TransactionManager::new();
+
+        let t1 = tm.begin();
+        tm.record_range_read(t1, b"user:100".to_vec(), b"user:200".to_vec()).unwrap();
+        tm.record_write(t1, b"summary".to_vec(), Some(b"0 users".to_vec())).unwrap();
+
+        let t2 = tm.begin();
+        tm.record_write(t2, b"user:200".to_vec(), Some(b"edge".to_vec())).unwrap();
+        tm.record_write(t2, b"user:099".to_vec(), Some(b"below".to_vec())).unwrap();
+        tm.commit(t2).unwrap();
+
+        tm.commit(t1).unwrap();
+    }
+
+    #[test]
+    fn test_gc_keeps_versions_pinned_by_long_transaction() {
+        let tm = TransactionManager::new();
+        let key = b"key".to_vec();
+
+        let t = tm.begin();
+        tm.record_write(t, key.clone(), Some(b"v1".to_vec())).unwrap();
+        tm.commit(t).unwrap();
+
+        let long = tm.begin();
+        let start_version = tm.get_start_version(long).unwrap();
+        for i in 2..6 {
+            let t = tm.begin();
+            tm.record_write(t, key.clone(), Some(format!("v{}", i).into_bytes())).unwrap();
+            tm.commit(t).unwrap();
+        }
+        assert_eq!(tm.oldest_active_version(), Some(start_version));
+
+        tm.gc(tm.current_version());
+        assert_eq!(
+            tm.get_visible_value(&key, start_version),
+            Ok(Visible::Value(b"v1".to_vec()))
+        );
+        assert_eq!(tm.retained_versions(), 5);
+
+        tm.abort(long).unwrap();
+        assert_eq!(tm.oldest_active_version(), None);
+        tm.gc(tm.current_version());
+        assert_eq!(tm.retained_versions(), 0);
+        assert_eq!(tm.retained_bytes(), 0);
+    }
+
+    #[test]
+    fn test_automatic_gc_bounds_retained_versions() {
+        let tm = TransactionManager::new().with_gc_threshold(64);
+
+        for i in 0..1000u32 {
+            let t = tm.begin();
+            tm.record_write(t, (i % 200).to_le_bytes().to_vec(), Some(vec![0; 16])).unwrap();
+            tm.commit(t).unwrap();
+            assert!(tm.retained_versions() <= 64 + 2);
+        }
+    }
+
+    #[test]
+    fn test_metrics_follow_scripted_workload() {
+        let tm = TransactionManager::with_timeout(Duration::from_secs(60));
+        let hot = b"hot".to_vec();
+
+        // Two clean commits writing 1 and 3 keys.
+        let t = tm.begin();
+        tm.record_write(t, hot.clone(), Some(b"0".to_vec())).unwrap();
+        tm.commit(t).unwrap();
+        let t = tm.begin();
+        for key in [&b"a"[..], b"b", b"c"] {
+            tm.record_write(t, key.to_vec(), None).unwrap();
+        }
+        tm.commit(t).unwrap();
+
+        // Two write-write conflicts on the hot key.
+        for _ in 0..2 {
+            let loser = tm.begin();
+            let winner = tm.begin();
+            tm.record_write(loser, hot.clone(), Some(b"x".to_vec())).unwrap();
+            tm.record_write(winner, hot.clone(), Some(b"y".to_vec())).unwrap();
+            tm.commit(winner).unwrap();
+            assert!(tm.commit(loser).is_err());
+        }
+
+        // One explicit abort, one timeout.
+        let t = tm.begin();
+        tm.abort(t).unwrap();
+        let t = tm.begin_with_options(TxnOptions {
+            timeout: Some(Duration::from_millis(1)),
+            ..Default::default()
+        });
+        std::thread::sleep(Duration::from_millis(5));
+        assert!(tm.commit(t).is_err());
+
+        let open = tm.begin();
+        tm.record_read(open, b"r".to_vec()).unwrap();
+        tm.record_write(open, b"w".to_vec(), None).unwrap();
+
+        let m = tm.metrics(5);
+        assert_eq!(m.begun, 9);
+        assert_eq!(m.committed, 4);
+        assert_eq!(m.aborted_explicit, 1);
+        assert_eq!(m.aborted_conflict, 2);
+        assert_eq!(m.aborted_timeout, 1);
+        assert_eq!(m.active, 1);
+        assert_eq!(m.avg_write_set_size, 6.0 / 4.0);
+        assert_eq!(m.top_conflict_keys, vec![(hot, 2)]);
+
+        let active = tm.list_active();
+        assert_eq!(active.len(), 1);
+        assert_eq!(active[0].id, open);
+        assert_eq!((active[0].read_count, active[0].write_count), (1, 1));
+        assert!(!active[0].read_only);
+    }
+
+    #[test]
+    fn test_list_active_includes_read_only() {
+        let tm = TransactionManager::new();
+        let writer = tm.begin();
+        let reader = tm.begin_read_only();
+
+        let ids: Vec<(TxnId, bool)> = tm.list_active().iter().map(|i| (i.id, i.read_only)).collect();
+        assert_eq!(ids, vec![(writer, false), (reader, true)]);
+
+        tm.commit(reader).unwrap();
+        tm.abort(writer).unwrap();
+        assert!(tm.list_active().is_empty());
+    }
+
+    #[test]
+    fn test_version_cache_cap_evicts_oldest_first() {
+        let tm = TransactionManager::new().with_version_cache_bytes(4 * 1024);
+        let key = b"key".to_vec();
+        let value = |i: u8| vec![i; 1024];
+
+        let t = tm.begin();
+        tm.record_write(t, key.clone(), Some(value(1))).unwrap();
+        tm.commit(t).unwrap();
+
+        let ancient = tm.begin_read_only();
+        let writer = tm.begin();
+        tm.record_write(writer, b"other".to_vec(), None).unwrap();
+        for i in 2..=3 {
+            let t = tm.begin();
+            tm.record_write(t, key.clone(), Some(value(i))).unwrap();
+            tm.commit(t).unwrap();
+        }
+        let recent = tm.begin_read_only();
+        assert_eq!(tm.retained_versions(), 4);
+
+        // The fourth large value pushes past the cap. The base version goes
+        // first, then v1, though `ancient` still needs it.
+        let t = tm.begin();
+        tm.record_write(t, key.clone(), Some(value(4))).unwrap();
+        tm.commit(t).unwrap();
+        assert!(tm.retained_bytes() <= 4 * 1024);
+        assert_eq!(tm.retained_versions(), 3);
+
+        let ancient_start = tm.get_start_version(ancient).unwrap();
+        let recent_start = tm.get_start_version(recent).unwrap();
+        assert_eq!(
+            tm.get_visible_value(&key, ancient_start),
+            Err(TxnError::SnapshotTooOld(ancient_start))
+        );
+        assert_eq!(tm.get_visible_value(&key, recent_start), Ok(Visible::Value(value(3))));
+        assert_eq!(tm.commit(writer).unwrap_err(), TxnError::SnapshotTooOld(1));
     }
 }
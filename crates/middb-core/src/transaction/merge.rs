@@ -0,0 +1,31 @@
+use crate::Value;
+
+/// Folds merge operands into a key's value.
+///
+/// A transaction combines the operands it buffers on one key before the
+/// value they apply to is known, so an operator must satisfy
+/// `merge(merge(v, a), b) == merge(v, combine(a, b))`.
+pub trait MergeOperator: Send + Sync {
+    /// The value after applying `operand` to `existing`, which is `None` if
+    /// the key has no value.
+    fn merge(&self, key: &[u8], existing: Option<&[u8]>, operand: &[u8]) -> Value;
+
+    /// One operand with the effect of applying `first` and then `second`.
+    fn combine(&self, key: &[u8], first: &[u8], second: &[u8]) -> Value;
+}
+
+/// Appends each operand to the existing value.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AppendOperator;
+
+impl MergeOperator for AppendOperator {
+    fn merge(&self, _key: &[u8], existing: Option<&[u8]>, operand: &[u8]) -> Value {
+        let mut value = existing.unwrap_or_default().to_vec();
+        value.extend_from_slice(operand);
+        value
+    }
+
+    fn combine(&self, _key: &[u8], first: &[u8], second: &[u8]) -> Value {
+        [first, second].concat()
+    }
+}
@@ -1,5 +1,8 @@
 pub mod manager;
+pub mod merge;
 
 pub use manager::{
-    Transaction, TransactionManager, TxnError, TxnId, TxnStatus, Version, WriteOp,
+    IsolationLevel, Transaction, TransactionManager, TxnError, TxnId, TxnInfo, TxnMetrics,
+    TxnOptions, TxnStatus, Version, Visible, WriteOp,
 };
+pub use merge::{AppendOperator, MergeOperator};
@@ -2,6 +2,6 @@ mod entry;
 mod writer;
 mod reader;
 
-pub use entry::{WalEntry, EntryType};
+pub use entry::{crc32, WalBatch, WalEntry, EntryType};
 pub use writer::WalWriter;
 pub use reader::WalReader;
@@ -33,7 +33,14 @@ impl WalReader {
         let data_len = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize;
         
         let mut data = vec![0u8; data_len];
-        self.reader.read_exact(&mut data)?;
+        match self.reader.read_exact(&mut data) {
+            Ok(_) => {}
+            // A torn final record from a crash mid-append.
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                return Ok(None);
+            }
+            Err(e) => return Err(e.into()),
+        }
         
         let mut full_entry = Vec::with_capacity(8 + data_len);
         full_entry.extend_from_slice(&header);
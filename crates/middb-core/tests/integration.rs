@@ -155,7 +155,7 @@ fn test_bloom_filter_integration() {
 fn test_storage_page_operations() {
     use middb_core::storage::{Page, MemStorage};
     
-    let mut storage = MemStorage::new();
+    let storage = MemStorage::new();
     
     let page_id = storage.allocate_page().unwrap();
     let mut page = Page::new();
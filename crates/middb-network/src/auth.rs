@@ -0,0 +1,56 @@
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// `password` hashed with argon2 under a fresh random salt, as a PHC
+/// string of the kind `ServerConfig::users` holds.
+pub fn hash_password(password: &str) -> String {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .expect("argon2's default parameters are valid")
+        .to_string()
+}
+
+/// Whether `password` hashes to `hash`. A hash that doesn't parse matches
+/// nothing.
+pub fn verify_password(password: &str, hash: &str) -> bool {
+    match PasswordHash::new(hash) {
+        Ok(hash) => Argon2::default().verify_password(password.as_bytes(), &hash).is_ok(),
+        Err(_) => false,
+    }
+}
+
+/// Whether `user` exists in `users` with `password`. An unknown user costs
+/// a hash check all the same, so timing doesn't tell which users exist.
+pub(crate) fn check_credentials(users: &HashMap<String, String>, user: &str, password: &str) -> bool {
+    static UNKNOWN_USER: OnceLock<String> = OnceLock::new();
+    match users.get(user) {
+        Some(hash) => verify_password(password, hash),
+        None => {
+            verify_password(password, UNKNOWN_USER.get_or_init(|| hash_password("")));
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_hash_and_verify() {
+        let hash = hash_password("secret");
+        assert!(verify_password("secret", &hash));
+        assert!(!verify_password("Secret", &hash));
+        assert!(!verify_password("secret", "not a hash"));
+        // Each hash has its own salt.
+        assert_ne!(hash, hash_password("secret"));
+        
+        let users = HashMap::from([("ann".to_string(), hash)]);
+        assert!(check_credentials(&users, "ann", "secret"));
+        assert!(!check_credentials(&users, "bob", "secret"));
+    }
+}
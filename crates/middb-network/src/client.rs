@@ -1,81 +1,1320 @@
-use crate::protocol::{Request, Response};
+use crate::error::{ClientError, Result};
+use crate::protocol::{
+    self, Request, Response, ServerStats, WatchEvent, FEATURES, PROTOCOL_VERSION,
+};
+use middb_core::wal::WalEntry;
+use middb_core::CasOutcome;
+use std::collections::hash_map::RandomState;
+use std::collections::VecDeque;
+use std::fmt;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
 use std::io;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use std::ops::{Deref, DerefMut};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
 use tokio::net::TcpStream;
 
+/// Largest response frame the client will read.
+const MAX_RESPONSE_BYTES: usize = 10 * 1024 * 1024;
+
+/// Where the client waits out its backoff between retries. Tests put in
+/// one that records the waits instead of sleeping through them.
+pub trait Clock: Send + Sync {
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()> + Send + '_>>;
+}
+
+/// The clock of the Tokio runtime.
+pub struct TokioClock;
+
+impl Clock for TokioClock {
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()> + Send + '_>> {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+/// When a request whose connection failed or timed out is tried again, on
+/// a new connection.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Tries after the first; 0 never retries.
+    pub max_retries: u32,
+    /// The wait before the first retry, doubled for each one after.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Retry puts, deletes and batches holding them too. A write whose
+    /// answer was lost may have been applied already, so it's off unless
+    /// the caller knows applying it twice is harmless.
+    pub retry_writes: bool,
+}
+
+impl RetryPolicy {
+    /// Never retry.
+    pub fn none() -> Self {
+        RetryPolicy {
+            max_retries: 0,
+            ..RetryPolicy::default()
+        }
+    }
+    
+    /// The wait before retry number `retry`, counting from 0.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let factor = 1u32.checked_shl(retry).unwrap_or(u32::MAX);
+        self.initial_backoff.saturating_mul(factor).min(self.max_backoff)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(50),
+            max_backoff: Duration::from_secs(2),
+            retry_writes: false,
+        }
+    }
+}
+
+/// How a client dials again once its connection is lost, with
+/// `ClientOptions::reconnect`.
+#[derive(Debug, Clone)]
+pub struct ReconnectPolicy {
+    /// Dials to make before giving up; the first is made at once.
+    pub max_attempts: u32,
+    /// The wait before the second dial, doubled for each one after.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Wait a random half to all of each backoff, so that clients cut off
+    /// together don't all dial back at the same moments.
+    pub jitter: bool,
+}
+
+impl ReconnectPolicy {
+    /// The wait after failed dial number `attempt`, counting from 0.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 1u32.checked_shl(attempt).unwrap_or(u32::MAX);
+        let backoff = self.initial_backoff.saturating_mul(factor).min(self.max_backoff);
+        match self.jitter {
+            // Any hasher's output is as good as random here.
+            true => {
+                let random = RandomState::new().build_hasher().finish();
+                backoff.mul_f64(0.5 + 0.5 * (random as f64 / u64::MAX as f64))
+            }
+            false => backoff,
+        }
+    }
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        ReconnectPolicy {
+            max_attempts: 10,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+            jitter: true,
+        }
+    }
+}
+
+/// Told why a client's connection was lost.
+pub type DisconnectCallback = Arc<dyn Fn(&ClientError) + Send + Sync>;
+
+/// Told when a client that lost its connection has a new one.
+pub type ReconnectCallback = Arc<dyn Fn() + Send + Sync>;
+
+#[derive(Clone)]
+pub struct ClientOptions {
+    /// How long a request, or connecting, may take before it fails with
+    /// `ClientError::Timeout`.
+    pub request_timeout: Duration,
+    pub retry: RetryPolicy,
+    /// User and password to log in with, for servers that require it.
+    pub credentials: Option<(String, String)>,
+    pub clock: Arc<dyn Clock>,
+    /// Dial again as soon as the connection is lost, rather than on the
+    /// next request, keeping at it as the policy says. The request that
+    /// found it lost is then retried if the retry policy allows, and
+    /// watches subscribe again. With `None`, a lost connection is dialed
+    /// again once, on the next request.
+    pub reconnect: Option<ReconnectPolicy>,
+    pub on_disconnect: Option<DisconnectCallback>,
+    pub on_reconnect: Option<ReconnectCallback>,
+}
+
+impl Default for ClientOptions {
+    fn default() -> Self {
+        ClientOptions {
+            request_timeout: Duration::from_secs(30),
+            retry: RetryPolicy::default(),
+            credentials: None,
+            clock: Arc::new(TokioClock),
+            reconnect: None,
+            on_disconnect: None,
+            on_reconnect: None,
+        }
+    }
+}
+
+impl fmt::Debug for ClientOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClientOptions")
+            .field("request_timeout", &self.request_timeout)
+            .field("retry", &self.retry)
+            .field("credentials", &self.credentials.as_ref().map(|(user, _)| user))
+            .field("reconnect", &self.reconnect)
+            .finish_non_exhaustive()
+    }
+}
+
 pub struct Client {
-    stream: TcpStream,
+    addr: String,
+    options: ClientOptions,
+    /// `None` once the connection has failed, or been left in doubt by a
+    /// timeout. The next request connects again.
+    stream: Option<TcpStream>,
+    server_version: u32,
+    features: Vec<String>,
+    /// Whether a connection has been made before, so that the next is a
+    /// reconnection.
+    connected_before: bool,
+    /// Whether a streamed scan was cancelled, and the rest of it is still
+    /// to be read past before the next response.
+    stream_cancelled: bool,
 }
 
 impl Client {
-    pub async fn connect(addr: &str) -> io::Result<Self> {
-        let stream = TcpStream::connect(addr).await?;
-        Ok(Client { stream })
+    /// Connect and make the protocol handshake.
+    pub async fn connect(addr: &str) -> Result<Self> {
+        Self::connect_with_options(addr, ClientOptions::default()).await
     }
     
-    pub async fn get(&mut self, key: &[u8]) -> io::Result<Option<Vec<u8>>> {
-        let request = Request::Get { key: key.to_vec() };
-        let response = self.send_request(request).await?;
+    /// Connect and log in as `user`.
+    pub async fn connect_with_auth(addr: &str, user: &str, password: &str) -> Result<Self> {
+        let options = ClientOptions {
+            credentials: Some((user.to_string(), password.to_string())),
+            ..ClientOptions::default()
+        };
+        Self::connect_with_options(addr, options).await
+    }
+    
+    /// Connect, make the handshake, and log in if `options` has
+    /// credentials.
+    pub async fn connect_with_options(addr: &str, options: ClientOptions) -> Result<Self> {
+        let mut client = Client {
+            addr: addr.to_string(),
+            options,
+            stream: None,
+            server_version: 0,
+            features: Vec::new(),
+            connected_before: false,
+            stream_cancelled: false,
+        };
+        client.dial().await?;
+        Ok(client)
+    }
+    
+    /// The protocol version the server answered the handshake with.
+    pub fn server_version(&self) -> u32 {
+        self.server_version
+    }
+    
+    /// The optional features this connection may use.
+    pub fn features(&self) -> &[String] {
+        &self.features
+    }
+    
+    pub fn options(&self) -> &ClientOptions {
+        &self.options
+    }
+    
+    /// The client with a different request timeout, for the requests made
+    /// through the returned guard.
+    pub fn with_timeout(&mut self, timeout: Duration) -> Overridden<'_> {
+        let saved = self.options.clone();
+        self.options.request_timeout = timeout;
+        Overridden { client: self, saved }
+    }
+    
+    /// The client with a different retry policy, for the requests made
+    /// through the returned guard.
+    pub fn with_retry(&mut self, retry: RetryPolicy) -> Overridden<'_> {
+        let saved = self.options.clone();
+        self.options.retry = retry;
+        Overridden { client: self, saved }
+    }
+    
+    /// Open a new connection, make the handshake and log in.
+    async fn dial(&mut self) -> Result<()> {
+        let connect = TcpStream::connect(&self.addr);
+        let stream = tokio::time::timeout(self.options.request_timeout, connect)
+            .await
+            .map_err(|_| ClientError::Timeout)??;
+        self.stream = Some(stream);
+        self.stream_cancelled = false;
+        if let Err(e) = self.log_in().await {
+            self.stream = None;
+            return Err(e);
+        }
+        if self.connected_before {
+            if let Some(on_reconnect) = &self.options.on_reconnect {
+                on_reconnect();
+            }
+        }
+        self.connected_before = true;
+        Ok(())
+    }
+    
+    /// Dial until connected, as `policy` says, or give up with the last
+    /// dial's error.
+    async fn reconnect(&mut self, policy: &ReconnectPolicy) -> Result<()> {
+        let mut attempt = 0;
+        loop {
+            match self.dial().await {
+                Err(e) if e.is_transient() && attempt + 1 < policy.max_attempts => {
+                    self.options.clock.sleep(policy.backoff(attempt)).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+    
+    /// Drop the connection, which failed with `error` or can't be trusted
+    /// after it.
+    fn lost(&mut self, error: &ClientError) {
+        self.stream = None;
+        if let Some(on_disconnect) = &self.options.on_disconnect {
+            on_disconnect(error);
+        }
+    }
+    
+    async fn log_in(&mut self) -> Result<()> {
+        self.hello().await?;
+        if let Some((user, password)) = self.options.credentials.clone() {
+            let request = Request::Auth { user, password };
+            match self.exchange(&request).await? {
+                Response::Ok => {}
+                Response::Error(code, e) => return Err(ClientError::Server { code, message: e }),
+                _ => return Err(ClientError::unexpected_response()),
+            }
+        }
+        Ok(())
+    }
+    
+    async fn hello(&mut self) -> Result<()> {
+        let request = Request::Hello {
+            protocol_version: PROTOCOL_VERSION,
+            features: FEATURES.iter().map(|f| f.to_string()).collect(),
+        };
+        let response = match self.exchange(&request).await {
+            Ok(response) => response,
+            // A server from before the handshake can't decode it, and
+            // hangs up.
+            Err(ClientError::ConnectionLost(e)) if matches!(
+                e.kind(),
+                io::ErrorKind::UnexpectedEof
+                    | io::ErrorKind::ConnectionReset
+                    | io::ErrorKind::BrokenPipe
+            ) =>
+            {
+                let message = "server too old: it doesn't understand the protocol handshake";
+                return Err(ClientError::ProtocolError(message.to_string()));
+            }
+            Err(e) => return Err(e),
+        };
         
         match response {
+            Response::Hello { protocol_version, features } => {
+                self.server_version = protocol_version;
+                self.features = features;
+                Ok(())
+            }
+            Response::Error(code, e) => Err(ClientError::Server { code, message: e }),
+            _ => Err(ClientError::unexpected_response()),
+        }
+    }
+    
+    pub async fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let request = Request::Get { key: key.to_vec() };
+        
+        match self.call(request).await? {
             Response::Value(value) => Ok(value),
-            Response::Error(e) => Err(io::Error::new(io::ErrorKind::Other, e)),
-            _ => Err(io::Error::new(io::ErrorKind::InvalidData, "Unexpected response")),
+            _ => Err(ClientError::unexpected_response()),
         }
     }
     
-    pub async fn put(&mut self, key: &[u8], value: &[u8]) -> io::Result<()> {
+    pub async fn put(&mut self, key: &[u8], value: &[u8]) -> Result<()> {
         let request = Request::Put {
             key: key.to_vec(),
             value: value.to_vec(),
         };
-        let response = self.send_request(request).await?;
         
-        match response {
+        match self.call(request).await? {
             Response::Ok => Ok(()),
-            Response::Error(e) => Err(io::Error::new(io::ErrorKind::Other, e)),
-            _ => Err(io::Error::new(io::ErrorKind::InvalidData, "Unexpected response")),
+            _ => Err(ClientError::unexpected_response()),
         }
     }
     
-    pub async fn delete(&mut self, key: &[u8]) -> io::Result<()> {
+    pub async fn delete(&mut self, key: &[u8]) -> Result<()> {
         let request = Request::Delete { key: key.to_vec() };
-        let response = self.send_request(request).await?;
         
-        match response {
+        match self.call(request).await? {
             Response::Ok => Ok(()),
-            Response::Error(e) => Err(io::Error::new(io::ErrorKind::Other, e)),
-            _ => Err(io::Error::new(io::ErrorKind::InvalidData, "Unexpected response")),
+            _ => Err(ClientError::unexpected_response()),
         }
     }
     
-    pub async fn ping(&mut self) -> io::Result<()> {
-        let request = Request::Ping;
-        let response = self.send_request(request).await?;
+    /// Set `key` to `new`, or delete it for `None`, if its value is
+    /// `expected`; `None` expects it absent. The outcome has the key's
+    /// value now, so a failed swap can be tried again without a get.
+    pub async fn compare_and_swap(
+        &mut self,
+        key: &[u8],
+        expected: Option<&[u8]>,
+        new: Option<&[u8]>,
+    ) -> Result<CasOutcome> {
+        let request = Request::CompareAndSwap {
+            key: key.to_vec(),
+            expected: expected.map(<[u8]>::to_vec),
+            new: new.map(<[u8]>::to_vec),
+        };
         
-        match response {
+        match self.call(request).await? {
+            Response::CasResult { succeeded, current } => Ok(CasOutcome { succeeded, current }),
+            _ => Err(ClientError::unexpected_response()),
+        }
+    }
+    
+    pub async fn ping(&mut self) -> Result<()> {
+        match self.call(Request::Ping).await? {
             Response::Pong => Ok(()),
-            _ => Err(io::Error::new(io::ErrorKind::InvalidData, "Expected pong")),
+            _ => Err(ClientError::ProtocolError("expected pong".to_string())),
+        }
+    }
+    
+    /// The server's stats and its database's.
+    pub async fn stats(&mut self) -> Result<ServerStats> {
+        match self.call(Request::Stats).await? {
+            Response::Stats(stats) => Ok(*stats),
+            _ => Err(ClientError::unexpected_response()),
+        }
+    }
+    
+    /// The server's stats in the Prometheus text format, as its metrics
+    /// endpoint serves them.
+    pub async fn metrics(&mut self) -> Result<String> {
+        match self.call(Request::Metrics).await? {
+            Response::Metrics(text) => Ok(text),
+            _ => Err(ClientError::unexpected_response()),
+        }
+    }
+    
+    /// A database property by name, or `None` for a name the server
+    /// doesn't know.
+    pub async fn property(&mut self, name: &str) -> Result<Option<String>> {
+        match self.call(Request::Property(name.to_string())).await? {
+            Response::Property(value) => Ok(value),
+            _ => Err(ClientError::unexpected_response()),
+        }
+    }
+    
+    /// The values of `keys`, in the same order, read in one request from
+    /// one snapshot.
+    pub async fn multi_get(&mut self, keys: &[&[u8]]) -> Result<Vec<Option<Vec<u8>>>> {
+        let request = Request::MultiGet { keys: keys.iter().map(|k| k.to_vec()).collect() };
+        
+        match self.call(request).await? {
+            Response::MultiGetResult { values } if values.len() == keys.len() => Ok(values),
+            _ => Err(ClientError::unexpected_response()),
+        }
+    }
+    
+    /// The entries with keys in `[start, end)`, in key order, fetched a
+    /// page at a time as they're read.
+    pub fn scan(&mut self, start: Option<&[u8]>, end: Option<&[u8]>) -> Scan<'_> {
+        Scan {
+            client: self,
+            start: start.map(<[u8]>::to_vec),
+            end: end.map(<[u8]>::to_vec),
+            page_size: 0,
+            reverse: false,
+            page: VecDeque::new(),
+            done: false,
+        }
+    }
+    
+    /// The entries with keys in `[start, end)`, in key order, streamed by
+    /// the server as it reads them. Unlike `scan`, the whole range is one
+    /// request, and it holds the connection until it's read or dropped.
+    pub fn scan_stream(&mut self, start: Option<&[u8]>, end: Option<&[u8]>) -> ScanStream<'_> {
+        ScanStream {
+            client: self,
+            start: start.map(<[u8]>::to_vec),
+            end: end.map(<[u8]>::to_vec),
+            reverse: false,
+            started: false,
+            open: false,
+            chunk: VecDeque::new(),
+        }
+    }
+    
+    /// Watch for writes to keys starting with `prefix`. The watch has a
+    /// connection of its own, which closes when it's dropped; writes made
+    /// after this returns are all seen.
+    pub async fn watch(&self, prefix: &[u8]) -> Result<Watch> {
+        let mut client = Client::connect_with_options(&self.addr, self.options.clone()).await?;
+        let subscribe = Request::Watch { prefix: prefix.to_vec() };
+        client.subscribe(&subscribe).await?;
+        Ok(Watch { client, subscribe })
+    }
+    
+    /// Follow the server's WAL from sequence `from` on, on a connection of
+    /// its own, as a replica does. Fails with
+    /// `ErrorCode::SnapshotRequired` if the server's WAL can't serve it.
+    pub async fn replicate(&self, from: u64) -> Result<WalStream> {
+        let mut client = Client::connect_with_options(&self.addr, self.options.clone()).await?;
+        client.subscribe(&Request::ReplSubscribe { from_sequence: from }).await?;
+        Ok(WalStream { client, next_sequence: from })
+    }
+    
+    /// Begin a transaction. It lives on this connection: if the connection
+    /// is lost, so is the transaction.
+    pub async fn begin(&mut self) -> Result<RemoteTransaction<'_>> {
+        match self.call(Request::TxnBegin).await? {
+            Response::TxnBegun(txn_id) => Ok(RemoteTransaction { client: self, txn_id }),
+            _ => Err(ClientError::unexpected_response()),
+        }
+    }
+    
+    /// Queue requests to send together in one frame.
+    pub fn pipeline(&mut self) -> Pipeline<'_> {
+        Pipeline {
+            client: self,
+            requests: Vec::new(),
         }
     }
     
-    async fn send_request(&mut self, request: Request) -> io::Result<Response> {
+    /// Give the connection over to pushes with `request`.
+    async fn subscribe(&mut self, request: &Request) -> Result<()> {
+        match self.call(request.clone()).await? {
+            Response::Ok => Ok(()),
+            _ => Err(ClientError::unexpected_response()),
+        }
+    }
+    
+    /// The next response pushed on a connection given over with
+    /// `subscribe`. Fails once the connection is lost, unless the client
+    /// reconnects by itself; then it subscribes again and carries on.
+    async fn next_pushed(&mut self, subscribe: &Request) -> Result<Response> {
+        loop {
+            if self.stream.is_none() {
+                let Some(policy) = self.options.reconnect.clone() else {
+                    return Err(ClientError::ConnectionLost(io::ErrorKind::NotConnected.into()));
+                };
+                self.reconnect(&policy).await?;
+                self.subscribe(subscribe).await?;
+            }
+            let stream = self.stream.as_mut().expect("connected above");
+            match read_response(stream).await {
+                Ok(response) => return Ok(response),
+                Err(e) => {
+                    self.lost(&e);
+                    if self.options.reconnect.is_none() {
+                        return Err(e);
+                    }
+                }
+            }
+        }
+    }
+    
+    /// Send `request`, retrying as the retry policy allows, and turn an
+    /// error response into an error.
+    async fn call(&mut self, request: Request) -> Result<Response> {
+        let policy = self.options.retry.clone();
+        let retryable = policy.retry_writes || request.is_idempotent();
+        let mut retries = 0;
+        loop {
+            match self.send_request(request.clone()).await {
+                Ok(Response::Error(code, e)) => return Err(ClientError::Server { code, message: e }),
+                Err(e) if e.is_transient() && retryable && retries < policy.max_retries => {
+                    // Reconnecting backs off by itself.
+                    match self.options.reconnect.clone() {
+                        Some(reconnect) => self.reconnect(&reconnect).await?,
+                        None => self.options.clock.sleep(policy.backoff(retries)).await,
+                    }
+                    retries += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+    
+    /// Send `request` once, connecting first if the last connection was
+    /// lost, and return whatever the server answers.
+    pub(crate) async fn send_request(&mut self, request: Request) -> Result<Response> {
+        if self.stream.is_none() {
+            self.dial().await?;
+        }
+        self.exchange(&request).await
+    }
+    
+    /// Send `request` on the open connection and read the answer. A
+    /// connection that fails or times out is dropped, as what's left on
+    /// it can't be trusted.
+    async fn exchange(&mut self, request: &Request) -> Result<Response> {
         let request_data = request.encode()
-            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            .map_err(|e| ClientError::ProtocolError(e.to_string()))?;
+        self.round_trip(Some(&protocol::encode_frame(&request_data))).await
+    }
+    
+    /// Read the next response on the open connection, sent unasked, as a
+    /// stream's are.
+    async fn receive(&mut self) -> Result<Response> {
+        self.round_trip(None).await
+    }
+    
+    /// Write `frame`, if there is one, then read a response, past what's
+    /// left of a cancelled stream.
+    async fn round_trip(&mut self, frame: Option<&[u8]>) -> Result<Response> {
+        let skip_stream = std::mem::take(&mut self.stream_cancelled);
+        let Some(stream) = self.stream.as_mut() else {
+            return Err(ClientError::ConnectionLost(io::ErrorKind::NotConnected.into()));
+        };
+        
+        let round_trip = async {
+            if skip_stream {
+                while let Response::Chunk { last: false, .. } = read_response(stream).await? {}
+            }
+            if let Some(frame) = frame {
+                stream.write_all(frame).await?;
+            }
+            read_response(stream).await
+        };
+        let response = match tokio::time::timeout(self.options.request_timeout, round_trip).await {
+            Ok(Ok(response)) => response,
+            Ok(Err(e)) => {
+                self.lost(&e);
+                return Err(e);
+            }
+            Err(_) => {
+                self.lost(&ClientError::Timeout);
+                return Err(ClientError::Timeout);
+            }
+        };
+        
+        Ok(response)
+    }
+    
+    /// Ask the server to stop the streamed scan in progress, without
+    /// waiting. The next response read skips past the rest of it.
+    fn cancel_stream(&mut self) {
+        let Some(stream) = &self.stream else {
+            return;
+        };
+        let cancel = Request::Cancel.encode().expect("a cancel always encodes");
+        let frame = protocol::encode_frame(&cancel);
+        match stream.try_write(&frame) {
+            Ok(n) if n == frame.len() => self.stream_cancelled = true,
+            // Part of a frame, or none of it, leaves the connection out of
+            // step; a new one is simpler.
+            _ => self.stream = None,
+        }
+    }
+}
+
+/// Read the next response on `stream`, answering any pings the server
+/// sends first.
+async fn read_response(stream: &mut TcpStream) -> Result<Response> {
+    loop {
+        let buf = protocol::read_frame(stream, MAX_RESPONSE_BYTES).await?;
+        match Response::decode(&buf).map_err(|e| ClientError::ProtocolError(e.to_string()))? {
+            Response::Ping => {
+                let pong = Request::Pong.encode()
+                    .map_err(|e| ClientError::ProtocolError(e.to_string()))?;
+                stream.write_all(&protocol::encode_frame(&pong)).await?;
+            }
+            response => return Ok(response),
+        }
+    }
+}
+
+/// A client with some options changed, from `Client::with_timeout` or
+/// `Client::with_retry`. The options go back when it's dropped.
+pub struct Overridden<'a> {
+    client: &'a mut Client,
+    saved: ClientOptions,
+}
+
+impl Deref for Overridden<'_> {
+    type Target = Client;
+    
+    fn deref(&self) -> &Client {
+        self.client
+    }
+}
+
+impl DerefMut for Overridden<'_> {
+    fn deref_mut(&mut self) -> &mut Client {
+        self.client
+    }
+}
+
+impl Drop for Overridden<'_> {
+    fn drop(&mut self) {
+        self.client.options = self.saved.clone();
+    }
+}
+
+/// Gets, puts and deletes queued to go to the server as one batch.
+pub struct Pipeline<'a> {
+    client: &'a mut Client,
+    requests: Vec<Request>,
+}
+
+impl Pipeline<'_> {
+    pub fn get(mut self, key: &[u8]) -> Self {
+        self.requests.push(Request::Get { key: key.to_vec() });
+        self
+    }
+    
+    pub fn put(mut self, key: &[u8], value: &[u8]) -> Self {
+        self.requests.push(Request::Put {
+            key: key.to_vec(),
+            value: value.to_vec(),
+        });
+        self
+    }
+    
+    pub fn delete(mut self, key: &[u8]) -> Self {
+        self.requests.push(Request::Delete { key: key.to_vec() });
+        self
+    }
+    
+    pub fn len(&self) -> usize {
+        self.requests.len()
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.requests.is_empty()
+    }
+    
+    /// Send the queued requests and return one response for each, in the
+    /// order they were queued. A request that failed on its own has a
+    /// `Response::Error`; a batch the server refused whole is an error.
+    pub async fn execute(self) -> Result<Vec<Response>> {
+        let count = self.requests.len();
         
-        self.stream.write_u32(request_data.len() as u32).await?;
-        self.stream.write_all(&request_data).await?;
+        match self.client.call(Request::Batch(self.requests)).await? {
+            Response::Batch(responses) if responses.len() == count => Ok(responses),
+            _ => Err(ClientError::unexpected_response()),
+        }
+    }
+}
+
+/// A transaction open on the server, from `Client::begin`. One dropped
+/// without a commit or abort stays open until the connection closes or the
+/// server times it out.
+pub struct RemoteTransaction<'a> {
+    client: &'a mut Client,
+    txn_id: u64,
+}
+
+impl RemoteTransaction<'_> {
+    pub fn id(&self) -> u64 {
+        self.txn_id
+    }
+    
+    pub async fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let request = Request::TxnGet { txn_id: self.txn_id, key: key.to_vec() };
         
-        let len = self.stream.read_u32().await? as usize;
+        match self.client.call(request).await? {
+            Response::Value(value) => Ok(value),
+            _ => Err(ClientError::unexpected_response()),
+        }
+    }
+    
+    pub async fn put(&mut self, key: &[u8], value: &[u8]) -> Result<()> {
+        let request = Request::TxnPut {
+            txn_id: self.txn_id,
+            key: key.to_vec(),
+            value: value.to_vec(),
+        };
+        self.expect_ok(request).await
+    }
+    
+    pub async fn delete(&mut self, key: &[u8]) -> Result<()> {
+        let request = Request::TxnDelete { txn_id: self.txn_id, key: key.to_vec() };
+        self.expect_ok(request).await
+    }
+    
+    /// Commit the transaction's writes together. A conflict with another
+    /// transaction is a `ClientError::Server` with `ErrorCode::TxnConflict`.
+    pub async fn commit(mut self) -> Result<()> {
+        self.expect_ok(Request::TxnCommit { txn_id: self.txn_id }).await
+    }
+    
+    pub async fn abort(mut self) -> Result<()> {
+        self.expect_ok(Request::TxnAbort { txn_id: self.txn_id }).await
+    }
+    
+    async fn expect_ok(&mut self, request: Request) -> Result<()> {
+        match self.client.call(request).await? {
+            Response::Ok => Ok(()),
+            _ => Err(ClientError::unexpected_response()),
+        }
+    }
+}
+
+/// A scan being read from the server. Each page resumes after the last
+/// key of the one before, so keys written meanwhile are seen if they fall
+/// in the part of the range still to come.
+pub struct Scan<'a> {
+    client: &'a mut Client,
+    start: Option<Vec<u8>>,
+    end: Option<Vec<u8>>,
+    page_size: u32,
+    reverse: bool,
+    page: VecDeque<(Vec<u8>, Vec<u8>)>,
+    done: bool,
+}
+
+impl Scan<'_> {
+    /// Read from the end of the range back to its start.
+    pub fn reverse(mut self) -> Self {
+        self.reverse = true;
+        self
+    }
+    
+    /// Ask for at most `size` entries a page. The server may send fewer.
+    pub fn page_size(mut self, size: u32) -> Self {
+        self.page_size = size;
+        self
+    }
+    
+    /// The next entry, fetching another page when this one runs out.
+    pub async fn next(&mut self) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        // A page can come back empty with more to follow, where all the
+        // keys it covered had been deleted.
+        while self.page.is_empty() && !self.done {
+            self.fetch().await?;
+        }
+        Ok(self.page.pop_front())
+    }
+    
+    /// Every remaining entry.
+    pub async fn collect(mut self) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let mut entries = Vec::new();
+        while let Some(entry) = self.next().await? {
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+    
+    async fn fetch(&mut self) -> Result<()> {
+        let request = Request::Scan {
+            start: self.start.clone(),
+            end: self.end.clone(),
+            limit: self.page_size,
+            reverse: self.reverse,
+        };
         
-        if len > 10 * 1024 * 1024 {
-            return Err(io::Error::new(io::ErrorKind::InvalidData, "Response too large"));
+        match self.client.call(request).await? {
+            Response::ScanResult { entries, has_more, next_cursor } => {
+                self.page.extend(entries);
+                match next_cursor {
+                    Some(cursor) if has_more => match self.reverse {
+                        false => self.start = Some(cursor),
+                        true => self.end = Some(cursor),
+                    },
+                    _ => self.done = true,
+                }
+                Ok(())
+            }
+            _ => Err(ClientError::unexpected_response()),
         }
+    }
+}
+
+/// A scan the server streams a chunk at a time, from `Client::scan_stream`.
+/// Entries can be read as soon as the first chunk arrives; the server
+/// reads ahead only as far as the connection lets it. Dropping the stream
+/// before the end cancels the rest.
+pub struct ScanStream<'a> {
+    client: &'a mut Client,
+    start: Option<Vec<u8>>,
+    end: Option<Vec<u8>>,
+    reverse: bool,
+    /// Whether the request was sent.
+    started: bool,
+    /// Whether chunks are still to come.
+    open: bool,
+    chunk: VecDeque<(Vec<u8>, Vec<u8>)>,
+}
+
+impl ScanStream<'_> {
+    /// Read from the end of the range back to its start.
+    pub fn reverse(mut self) -> Self {
+        self.reverse = true;
+        self
+    }
+    
+    /// The next entry, waiting for the next chunk when this one runs out.
+    /// An error the server meets partway ends the stream with it.
+    pub async fn next(&mut self) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        loop {
+            if let Some(entry) = self.chunk.pop_front() {
+                return Ok(Some(entry));
+            }
+            let response = match (self.started, self.open) {
+                (false, _) => {
+                    self.started = true;
+                    let request = Request::StreamScan {
+                        start: self.start.clone(),
+                        end: self.end.clone(),
+                        reverse: self.reverse,
+                    };
+                    self.client.call(request).await?
+                }
+                (true, true) => {
+                    self.open = false;
+                    self.client.receive().await?
+                }
+                (true, false) => return Ok(None),
+            };
+            match response {
+                Response::Chunk { entries, last } => {
+                    self.chunk.extend(entries);
+                    self.open = !last;
+                }
+                Response::Error(code, message) => {
+                    return Err(ClientError::Server { code, message })
+                }
+                _ => {
+                    // There's no telling where the stream ends now.
+                    let e = ClientError::unexpected_response();
+                    self.client.lost(&e);
+                    return Err(e);
+                }
+            }
+        }
+    }
+    
+    /// Every remaining entry.
+    pub async fn collect(mut self) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let mut entries = Vec::new();
+        while let Some(entry) = self.next().await? {
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+}
+
+impl Drop for ScanStream<'_> {
+    fn drop(&mut self) {
+        if self.open {
+            self.client.cancel_stream();
+        }
+    }
+}
+
+/// Writes under a prefix, as the server sends them. A watcher that falls
+/// far enough behind misses some.
+pub struct Watch {
+    client: Client,
+    subscribe: Request,
+}
+
+impl Watch {
+    /// The next write, waiting for one as long as it takes. Fails once the
+    /// connection is lost, unless the client reconnects by itself; then
+    /// the watch carries on, missing the writes made while it was away.
+    ///
+    /// The server's keepalive pings are answered while waiting here, so a
+    /// watch left unread for longer than the server's idle timeout may be
+    /// closed.
+    pub async fn next(&mut self) -> Result<WatchEvent> {
+        match self.client.next_pushed(&self.subscribe).await? {
+            Response::Event(event) => Ok(event),
+            _ => Err(ClientError::unexpected_response()),
+        }
+    }
+}
+
+/// A server's WAL records, as `Client::replicate` streams them.
+pub struct WalStream {
+    client: Client,
+    next_sequence: u64,
+}
+
+impl WalStream {
+    /// The next WAL record, waiting for one as long as it takes. If the
+    /// client reconnects by itself, the stream picks up after the last
+    /// record it returned.
+    pub async fn next(&mut self) -> Result<WalEntry> {
+        let subscribe = Request::ReplSubscribe { from_sequence: self.next_sequence };
+        let Response::WalRecord(bytes) = self.client.next_pushed(&subscribe).await? else {
+            return Err(ClientError::unexpected_response());
+        };
+        let (entry, _) =
+            WalEntry::decode(&bytes).map_err(|e| ClientError::ProtocolError(e.to_string()))?;
+        self.next_sequence = entry.sequence_number + 1;
+        Ok(entry)
+    }
+    
+    /// The sequence number of the record `next` returns next.
+    pub fn next_sequence(&self) -> u64 {
+        self.next_sequence
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::ErrorCode;
+    use crate::server::{Server, ServerConfig, ServerHandle};
+    use middb_core::{Config, Database};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+    use tempfile::TempDir;
+    use tokio::net::TcpListener;
+    
+    /// What the fake server does with a request.
+    enum Action {
+        Respond(Response),
+        Delay(Duration, Response),
+        /// Several responses, as a stream.
+        Stream(Vec<Response>),
+        HangUp,
+    }
+    
+    /// A server that makes the handshake, then answers the `n`th request
+    /// after it, counting across connections, with `behave(n, request)`.
+    /// Returns its address and the count of requests it has had.
+    async fn fake_server(
+        behave: impl Fn(usize, &Request) -> Action + Send + Sync + 'static,
+    ) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let seen = Arc::new(AtomicUsize::new(0));
+        let behave = Arc::new(behave);
+        let counter = Arc::clone(&seen);
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let (behave, seen) = (Arc::clone(&behave), Arc::clone(&counter));
+                tokio::spawn(async move {
+                    while let Ok(buf) = protocol::read_frame(&mut socket, usize::MAX).await {
+                        let request = Request::decode(&buf).unwrap();
+                        let action = match request {
+                            Request::Hello { features, .. } => Action::Respond(Response::Hello {
+                                protocol_version: PROTOCOL_VERSION,
+                                features,
+                            }),
+                            _ => behave(seen.fetch_add(1, Ordering::SeqCst), &request),
+                        };
+                        let responses = match action {
+                            Action::Respond(response) => vec![response],
+                            Action::Delay(delay, response) => {
+                                tokio::time::sleep(delay).await;
+                                vec![response]
+                            }
+                            Action::Stream(responses) => responses,
+                            Action::HangUp => return,
+                        };
+                        for response in responses {
+                            let frame = protocol::encode_frame(&response.encode().unwrap());
+                            if socket.write_all(&frame).await.is_err() {
+                                return;
+                            }
+                        }
+                    }
+                });
+            }
+        });
+        (addr, seen)
+    }
+    
+    /// A clock that notes each wait and returns at once.
+    #[derive(Default)]
+    struct RecordingClock {
+        sleeps: Mutex<Vec<Duration>>,
+    }
+    
+    impl Clock for RecordingClock {
+        fn sleep(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()> + Send + '_>> {
+            self.sleeps.lock().unwrap().push(duration);
+            Box::pin(std::future::ready(()))
+        }
+    }
+    
+    fn options_with_clock(clock: &Arc<RecordingClock>) -> ClientOptions {
+        ClientOptions {
+            clock: Arc::clone(clock) as Arc<dyn Clock>,
+            ..ClientOptions::default()
+        }
+    }
+    
+    #[tokio::test]
+    async fn test_slow_server_times_out() {
+        let (addr, seen) = fake_server(|_, request| match request {
+            Request::Ping => Action::Respond(Response::Pong),
+            _ => Action::Delay(Duration::from_secs(10), Response::Ok),
+        })
+        .await;
+        let options = ClientOptions {
+            request_timeout: Duration::from_millis(100),
+            ..ClientOptions::default()
+        };
+        let mut client = Client::connect_with_options(&addr, options).await.unwrap();
+        
+        let err = client.put(b"k", b"v").await.unwrap_err();
+        assert!(matches!(err, ClientError::Timeout));
+        assert_eq!(seen.load(Ordering::SeqCst), 1);
+        // The connection in doubt was dropped; the next request gets a new one.
+        client.ping().await.unwrap();
         
-        let mut buf = vec![0u8; len];
-        self.stream.read_exact(&mut buf).await?;
+        // A shorter timeout for one call, then back to the default.
+        let mut client = Client::connect(&addr).await.unwrap();
+        let err = client.with_timeout(Duration::from_millis(50)).put(b"k", b"v").await.unwrap_err();
+        assert!(matches!(err, ClientError::Timeout));
+        assert_eq!(client.options().request_timeout, ClientOptions::default().request_timeout);
+    }
+    
+    #[tokio::test]
+    async fn test_gets_retried_after_connection_lost() {
+        // The first request of all has its connection dropped.
+        let (addr, seen) = fake_server(|n, _| match n {
+            0 => Action::HangUp,
+            _ => Action::Respond(Response::Value(Some(b"v".to_vec()))),
+        })
+        .await;
+        let clock = Arc::new(RecordingClock::default());
+        let mut client = Client::connect_with_options(&addr, options_with_clock(&clock)).await.unwrap();
+        
+        assert_eq!(client.get(b"k").await.unwrap(), Some(b"v".to_vec()));
+        assert_eq!(seen.load(Ordering::SeqCst), 2);
+        assert_eq!(*clock.sleeps.lock().unwrap(), [RetryPolicy::default().initial_backoff]);
+    }
+    
+    #[tokio::test]
+    async fn test_puts_not_retried_after_connection_lost() {
+        let (addr, seen) = fake_server(|n, _| match n {
+            0 => Action::HangUp,
+            _ => Action::Respond(Response::Ok),
+        })
+        .await;
+        let clock = Arc::new(RecordingClock::default());
+        let mut client = Client::connect_with_options(&addr, options_with_clock(&clock)).await.unwrap();
+        
+        let err = client.put(b"k", b"v").await.unwrap_err();
+        assert!(matches!(err, ClientError::ConnectionLost(_)));
+        assert!(err.is_transient());
+        assert_eq!(seen.load(Ordering::SeqCst), 1);
+        assert!(clock.sleeps.lock().unwrap().is_empty());
+        
+        // Unless the caller says a second put does no harm.
+        let (addr, seen) = fake_server(|n, _| match n {
+            0 => Action::HangUp,
+            _ => Action::Respond(Response::Ok),
+        })
+        .await;
+        let mut options = options_with_clock(&clock);
+        options.retry.retry_writes = true;
+        let mut client = Client::connect_with_options(&addr, options).await.unwrap();
+        client.put(b"k", b"v").await.unwrap();
+        assert_eq!(seen.load(Ordering::SeqCst), 2);
+    }
+    
+    #[tokio::test]
+    async fn test_backoff_doubles_up_to_max() {
+        let (addr, seen) = fake_server(|_, _| Action::HangUp).await;
+        let clock = Arc::new(RecordingClock::default());
+        let mut options = options_with_clock(&clock);
+        options.retry = RetryPolicy {
+            max_retries: 5,
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_millis(50),
+            retry_writes: false,
+        };
+        let mut client = Client::connect_with_options(&addr, options).await.unwrap();
+        
+        let err = client.ping().await.unwrap_err();
+        assert!(matches!(err, ClientError::ConnectionLost(_)));
+        assert_eq!(seen.load(Ordering::SeqCst), 6);
+        let millis: Vec<_> = clock.sleeps.lock().unwrap().iter().map(|d| d.as_millis()).collect();
+        assert_eq!(millis, [10, 20, 40, 50, 50]);
+    }
+    
+    #[tokio::test]
+    async fn test_stream_error_ends_stream() {
+        let (addr, _) = fake_server(|_, request| match request {
+            Request::StreamScan { .. } => Action::Stream(vec![
+                Response::Chunk { entries: vec![(b"a".to_vec(), b"1".to_vec())], last: false },
+                Response::Chunk { entries: vec![(b"b".to_vec(), b"2".to_vec())], last: false },
+                Response::Error(ErrorCode::Corruption, "bad block".to_string()),
+            ]),
+            _ => Action::Respond(Response::Pong),
+        })
+        .await;
+        let mut client = Client::connect(&addr).await.unwrap();
+        
+        let mut stream = client.scan_stream(None, None);
+        assert_eq!(stream.next().await.unwrap(), Some((b"a".to_vec(), b"1".to_vec())));
+        assert_eq!(stream.next().await.unwrap(), Some((b"b".to_vec(), b"2".to_vec())));
+        let err = stream.next().await.unwrap_err();
+        assert!(matches!(err, ClientError::Server { code: ErrorCode::Corruption, .. }));
+        assert_eq!(stream.next().await.unwrap(), None);
+        drop(stream);
+        
+        // The error ended the stream, so nothing is left to skip.
+        client.ping().await.unwrap();
+    }
+    
+    #[tokio::test]
+    async fn test_server_errors_classified() {
+        let (addr, seen) = fake_server(|_, request| match request {
+            Request::Get { .. } => {
+                Action::Respond(Response::Error(ErrorCode::Internal, "disk on fire".to_string()))
+            }
+            _ => Action::Respond(Response::Pong),
+        })
+        .await;
+        let clock = Arc::new(RecordingClock::default());
+        let mut client = Client::connect_with_options(&addr, options_with_clock(&clock)).await.unwrap();
+        
+        // An error the server sent is final, and not retried.
+        let err = client.get(b"k").await.unwrap_err();
+        assert!(matches!(&err, ClientError::Server { code: ErrorCode::Internal, message: e } if e == "disk on fire"));
+        assert!(!err.is_transient());
+        assert_eq!(seen.load(Ordering::SeqCst), 1);
+        
+        // An answer that doesn't fit the request.
+        let err = client.delete(b"k").await.unwrap_err();
+        assert!(matches!(err, ClientError::ProtocolError(_)));
+        assert!(clock.sleeps.lock().unwrap().is_empty());
+    }
+    
+    /// A real server on `addr`, over the database in `dir`; port 0 picks
+    /// a free one. Shut it down and serve again on its address to restart
+    /// it.
+    async fn serve(dir: &TempDir, addr: &str) -> ServerHandle {
+        let db = Database::open(Config::new(dir.path())).unwrap();
+        let server = Server::with_config(db, addr.to_string(), ServerConfig::default());
+        server.spawn().await.unwrap()
+    }
+    
+    /// Options that reconnect quickly, counting disconnections and
+    /// reconnections.
+    fn reconnecting() -> (ClientOptions, Arc<AtomicUsize>, Arc<AtomicUsize>) {
+        let (disconnects, reconnects) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+        let (lost, found) = (Arc::clone(&disconnects), Arc::clone(&reconnects));
+        let options = ClientOptions {
+            reconnect: Some(ReconnectPolicy {
+                max_attempts: 100,
+                initial_backoff: Duration::from_millis(5),
+                max_backoff: Duration::from_millis(20),
+                jitter: true,
+            }),
+            on_disconnect: Some(Arc::new(move |_| {
+                lost.fetch_add(1, Ordering::SeqCst);
+            })),
+            on_reconnect: Some(Arc::new(move || {
+                found.fetch_add(1, Ordering::SeqCst);
+            })),
+            ..ClientOptions::default()
+        };
+        (options, disconnects, reconnects)
+    }
+    
+    #[test]
+    fn test_reconnect_backoff_jittered() {
+        let policy = ReconnectPolicy {
+            max_attempts: 10,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(400),
+            jitter: true,
+        };
+        for (attempt, full) in [(0, 100), (1, 200), (2, 400), (5, 400)] {
+            let backoff = policy.backoff(attempt);
+            let full = Duration::from_millis(full);
+            assert!(backoff >= full / 2 && backoff <= full, "{:?} for {}", backoff, attempt);
+        }
+        let steady = ReconnectPolicy { jitter: false, ..policy };
+        assert_eq!(steady.backoff(1), Duration::from_millis(200));
+    }
+    
+    #[tokio::test]
+    async fn test_gets_survive_server_restart() {
+        let dir = TempDir::new().unwrap();
+        let server = serve(&dir, "127.0.0.1:0").await;
+        let addr = server.local_addr().to_string();
+        let (options, disconnects, reconnects) = reconnecting();
+        let mut client = Client::connect_with_options(&addr, options).await.unwrap();
+        client.put(b"k", b"v").await.unwrap();
+        
+        server.shutdown().await.unwrap();
+        let restart = {
+            let (dir, addr) = (dir.path().to_path_buf(), addr.clone());
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                let db = Database::open(Config::new(dir)).unwrap();
+                let server = Server::with_config(db, addr, ServerConfig::default());
+                server.spawn().await.unwrap()
+            })
+        };
+        
+        assert_eq!(client.get(b"k").await.unwrap().as_deref(), Some(&b"v"[..]));
+        assert_eq!(disconnects.load(Ordering::SeqCst), 1);
+        assert_eq!(reconnects.load(Ordering::SeqCst), 1);
+        restart.await.unwrap().shutdown().await.unwrap();
+    }
+    
+    #[tokio::test]
+    async fn test_put_in_flight_not_retried_across_restart() {
+        let dir = TempDir::new().unwrap();
+        let server = serve(&dir, "127.0.0.1:0").await;
+        let addr = server.local_addr().to_string();
+        let (options, disconnects, _) = reconnecting();
+        let mut client = Client::connect_with_options(&addr, options).await.unwrap();
+        client.ping().await.unwrap();
+        
+        server.shutdown().await.unwrap();
+        let err = client.put(b"k", b"v").await.unwrap_err();
+        assert!(matches!(err, ClientError::ConnectionLost(_)));
+        assert_eq!(disconnects.load(Ordering::SeqCst), 1);
+        
+        let server = serve(&dir, &addr).await;
+        assert_eq!(client.get(b"k").await.unwrap(), None);
+        server.shutdown().await.unwrap();
+    }
+    
+    #[tokio::test]
+    async fn test_watch_resumes_after_server_restart() {
+        let dir = TempDir::new().unwrap();
+        let server = serve(&dir, "127.0.0.1:0").await;
+        let addr = server.local_addr().to_string();
+        let (options, _, reconnects) = reconnecting();
+        let client = Client::connect_with_options(&addr, options).await.unwrap();
+        let mut watch = client.watch(b"").await.unwrap();
+        let watching = tokio::spawn(async move { watch.next().await });
+        
+        server.shutdown().await.unwrap();
+        let server = serve(&dir, &addr).await;
+        for _ in 0..100 {
+            if server.load().watchers == 1 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(server.load().watchers, 1);
         
-        Response::decode(&buf)
-            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        let mut writer = Client::connect(&addr).await.unwrap();
+        writer.put(b"k", b"v").await.unwrap();
+        let event = watching.await.unwrap().unwrap();
+        assert_eq!(event.key, b"k");
+        assert_eq!(reconnects.load(Ordering::SeqCst), 1);
+        server.shutdown().await.unwrap();
     }
 }
@@ -0,0 +1,64 @@
+use crate::protocol::ErrorCode;
+use std::fmt;
+use std::io;
+
+pub type Result<T> = std::result::Result<T, ClientError>;
+
+/// Why a client request failed.
+#[derive(Debug)]
+pub enum ClientError {
+    /// No response came within the request timeout. The request may or
+    /// may not have been carried out.
+    Timeout,
+    /// The connection failed or closed before the response came. The
+    /// request may or may not have been carried out.
+    ConnectionLost(io::Error),
+    /// The server answered with an error; the request was not carried out.
+    /// `code` says what went wrong; `message` is for people.
+    Server { code: ErrorCode, message: String },
+    /// The server's answer made no sense to the client, or the two don't
+    /// speak the same protocol.
+    ProtocolError(String),
+}
+
+impl ClientError {
+    /// Whether trying again, on a new connection, might succeed.
+    pub fn is_transient(&self) -> bool {
+        matches!(self, ClientError::Timeout | ClientError::ConnectionLost(_))
+    }
+
+    pub(crate) fn unexpected_response() -> Self {
+        ClientError::ProtocolError("unexpected response".to_string())
+    }
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Timeout => write!(f, "request timed out"),
+            ClientError::ConnectionLost(e) => write!(f, "connection lost: {}", e),
+            ClientError::Server { message, .. } => write!(f, "{}", message),
+            ClientError::ProtocolError(message) => write!(f, "protocol error: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ClientError::ConnectionLost(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// A failure reading or writing the connection. Bad frames are the
+/// server's doing; anything else is the connection's.
+impl From<io::Error> for ClientError {
+    fn from(error: io::Error) -> Self {
+        match error.kind() {
+            io::ErrorKind::InvalidData => ClientError::ProtocolError(error.to_string()),
+            _ => ClientError::ConnectionLost(error),
+        }
+    }
+}
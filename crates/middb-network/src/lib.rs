@@ -1,7 +1,20 @@
+pub mod auth;
+pub mod error;
 pub mod protocol;
 pub mod server;
 pub mod client;
+pub mod pool;
+pub mod replica;
+pub mod metrics;
 
-pub use protocol::{Request, Response};
-pub use server::Server;
-pub use client::Client;
+pub use error::ClientError;
+pub use protocol::{
+    ErrorCode, EventKind, Request, Response, ServerStats, WatchEvent, PROTOCOL_VERSION,
+};
+pub use server::{Server, ServerConfig, ServerHandle, ServerLoad};
+pub use client::{
+    Client, ClientOptions, Clock, DisconnectCallback, Overridden, Pipeline, ReconnectCallback,
+    ReconnectPolicy, RemoteTransaction, RetryPolicy, Scan, ScanStream, TokioClock, WalStream, Watch,
+};
+pub use pool::{ClientPool, PoolOptions, PooledClient};
+pub use replica::{Replica, ReplicaError};
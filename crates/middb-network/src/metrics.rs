@@ -0,0 +1,270 @@
+//! A server's numbers in the Prometheus text format, as `Request::Metrics`
+//! and the server's metrics endpoint give them.
+
+use std::fmt::{Display, Write};
+use std::time::Duration;
+
+/// Upper bounds of the request latency buckets, in seconds.
+pub const LATENCY_BUCKETS: [f64; 14] = [
+    0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 5.0,
+];
+
+/// Request latencies counted into `LATENCY_BUCKETS`.
+#[derive(Debug, Clone, Default)]
+pub struct Histogram {
+    /// Observations in each bucket alone, not counting the ones below it.
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    count: u64,
+    sum: Duration,
+}
+
+impl Histogram {
+    pub fn observe(&mut self, latency: Duration) {
+        let seconds = latency.as_secs_f64();
+        if let Some(bucket) = LATENCY_BUCKETS.iter().position(|&bound| seconds <= bound) {
+            self.buckets[bucket] += 1;
+        }
+        self.count += 1;
+        self.sum += latency;
+    }
+    
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+}
+
+/// Text in the Prometheus exposition format, a metric family at a time.
+pub(crate) struct Exposition {
+    text: String,
+}
+
+impl Exposition {
+    pub(crate) fn new() -> Self {
+        Exposition { text: String::new() }
+    }
+    
+    /// Start a family of samples: `kind` is `counter`, `gauge` or
+    /// `histogram`.
+    pub(crate) fn family(&mut self, name: &str, kind: &str, help: &str) {
+        let _ = writeln!(self.text, "# HELP {} {}", name, help);
+        let _ = writeln!(self.text, "# TYPE {} {}", name, kind);
+    }
+    
+    pub(crate) fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: impl Display) {
+        self.text.push_str(name);
+        if !labels.is_empty() {
+            self.text.push('{');
+            for (i, (label, value)) in labels.iter().enumerate() {
+                if i > 0 {
+                    self.text.push(',');
+                }
+                let _ = write!(self.text, "{}=\"{}\"", label, escape(value));
+            }
+            self.text.push('}');
+        }
+        let _ = writeln!(self.text, " {}", value);
+    }
+    
+    /// A counter family of one sample.
+    pub(crate) fn counter(&mut self, name: &str, help: &str, value: impl Display) {
+        self.family(name, "counter", help);
+        self.sample(name, &[], value);
+    }
+    
+    /// A gauge family of one sample.
+    pub(crate) fn gauge(&mut self, name: &str, help: &str, value: impl Display) {
+        self.family(name, "gauge", help);
+        self.sample(name, &[], value);
+    }
+    
+    /// The samples of one histogram in a family started as `histogram`.
+    pub(crate) fn histogram(&mut self, name: &str, labels: &[(&str, &str)], histogram: &Histogram) {
+        let bucket = format!("{}_bucket", name);
+        let mut cumulative = 0;
+        for (bound, count) in LATENCY_BUCKETS.iter().zip(histogram.buckets) {
+            cumulative += count;
+            let le = bound.to_string();
+            self.sample(&bucket, &[labels, &[("le", &le)]].concat(), cumulative);
+        }
+        self.sample(&bucket, &[labels, &[("le", "+Inf")]].concat(), histogram.count);
+        self.sample(&format!("{}_sum", name), labels, histogram.sum.as_secs_f64());
+        self.sample(&format!("{}_count", name), labels, histogram.count);
+    }
+    
+    pub(crate) fn finish(self) -> String {
+        self.text
+    }
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Check `text` is well-formed exposition text: every sample belongs to a
+/// family declared once before it, with a valid name, labels and value,
+/// and each histogram's buckets add up to its count.
+#[cfg(test)]
+pub(crate) fn validate(text: &str) -> Result<(), String> {
+    use std::collections::{BTreeMap, HashMap};
+    
+    fn valid_name(name: &str) -> bool {
+        let mut chars = name.chars();
+        matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_' || c == ':')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':')
+    }
+    
+    fn parse_labels(labels: &str) -> Result<Vec<(String, String)>, String> {
+        let mut parsed = Vec::new();
+        let mut rest = labels;
+        while !rest.is_empty() {
+            let (name, after) = rest.split_once("=\"").ok_or("a label without a value")?;
+            if !valid_name(name) {
+                return Err(format!("bad label name {:?}", name));
+            }
+            let mut value = String::new();
+            let mut chars = after.char_indices();
+            let end = loop {
+                match chars.next().ok_or("an unterminated label value")? {
+                    (_, '\\') => match chars.next().ok_or("a dangling escape")?.1 {
+                        'n' => value.push('\n'),
+                        c @ ('\\' | '"') => value.push(c),
+                        c => return Err(format!("bad escape \\{}", c)),
+                    },
+                    (i, '"') => break i,
+                    (_, c) => value.push(c),
+                }
+            };
+            parsed.push((name.to_string(), value));
+            rest = &after[end + 1..];
+            if let Some(next) = rest.strip_prefix(',') {
+                rest = next;
+            } else if !rest.is_empty() {
+                return Err(format!("junk after a label: {:?}", rest));
+            }
+        }
+        Ok(parsed)
+    }
+    
+    type Series = (String, Vec<(String, String)>);
+    let mut kinds: HashMap<String, String> = HashMap::new();
+    // Each histogram's last bucket count and its `+Inf` count, by labels.
+    let mut buckets: BTreeMap<Series, (f64, Option<f64>)> = BTreeMap::new();
+    let mut counts: BTreeMap<Series, f64> = BTreeMap::new();
+    for line in text.lines() {
+        if let Some(comment) = line.strip_prefix("# ") {
+            let mut words = comment.splitn(3, ' ');
+            match (words.next(), words.next(), words.next()) {
+                (Some("HELP"), Some(name), Some(_)) if valid_name(name) => {}
+                (Some("TYPE"), Some(name), Some(kind)) if valid_name(name) => {
+                    if !["counter", "gauge", "histogram"].contains(&kind) {
+                        return Err(format!("{} has unknown type {}", name, kind));
+                    }
+                    if kinds.insert(name.to_string(), kind.to_string()).is_some() {
+                        return Err(format!("{} declared twice", name));
+                    }
+                }
+                _ => return Err(format!("bad comment: {:?}", line)),
+            }
+            continue;
+        }
+        
+        let (series, value) = line.rsplit_once(' ').ok_or(format!("no value: {:?}", line))?;
+        let value: f64 = match value {
+            "+Inf" => f64::INFINITY,
+            "-Inf" => f64::NEG_INFINITY,
+            value => value.parse().map_err(|_| format!("bad value: {:?}", line))?,
+        };
+        let (name, labels) = match series.split_once('{') {
+            Some((name, labels)) => {
+                let labels = labels.strip_suffix('}').ok_or(format!("bad labels: {:?}", line))?;
+                (name, parse_labels(labels)?)
+            }
+            None => (series, Vec::new()),
+        };
+        if !valid_name(name) {
+            return Err(format!("bad metric name {:?}", name));
+        }
+        
+        let family = ["_bucket", "_sum", "_count"]
+            .iter()
+            .filter_map(|suffix| name.strip_suffix(suffix))
+            .find(|family| kinds.get(*family).map(String::as_str) == Some("histogram"));
+        match family {
+            Some(family) if name.ends_with("_bucket") => {
+                let mut labels = labels;
+                let le = labels.iter().position(|(label, _)| label == "le");
+                let le = labels.remove(le.ok_or("a bucket without le")?).1;
+                let entry = buckets.entry((family.to_string(), labels)).or_insert((0.0, None));
+                if value < entry.0 {
+                    return Err(format!("{} buckets go down", family));
+                }
+                entry.0 = value;
+                if le == "+Inf" {
+                    entry.1 = Some(value);
+                }
+            }
+            Some(family) if name.ends_with("_count") => {
+                counts.insert((family.to_string(), labels), value);
+            }
+            Some(_) => {}
+            None if kinds.contains_key(name) => {}
+            None => return Err(format!("{} has no TYPE before it", name)),
+        }
+    }
+    
+    for (series, (_, inf)) in &buckets {
+        if inf.is_none() || counts.get(series) != inf.as_ref() {
+            return Err(format!("{} has a +Inf bucket unlike its count", series.0));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_histogram_buckets() {
+        let mut histogram = Histogram::default();
+        histogram.observe(Duration::from_micros(50));
+        histogram.observe(Duration::from_millis(3));
+        histogram.observe(Duration::from_millis(3));
+        histogram.observe(Duration::from_secs(60));
+        assert_eq!(histogram.count(), 4);
+        
+        let mut exposition = Exposition::new();
+        exposition.family("t_seconds", "histogram", "Test latencies.");
+        exposition.histogram("t_seconds", &[("type", "get")], &histogram);
+        let text = exposition.finish();
+        validate(&text).unwrap();
+        assert!(text.contains("t_seconds_bucket{type=\"get\",le=\"0.0001\"} 1\n"));
+        assert!(text.contains("t_seconds_bucket{type=\"get\",le=\"0.0025\"} 1\n"));
+        assert!(text.contains("t_seconds_bucket{type=\"get\",le=\"0.005\"} 3\n"));
+        assert!(text.contains("t_seconds_bucket{type=\"get\",le=\"5\"} 3\n"));
+        assert!(text.contains("t_seconds_bucket{type=\"get\",le=\"+Inf\"} 4\n"));
+        assert!(text.contains("t_seconds_count{type=\"get\"} 4\n"));
+    }
+    
+    #[test]
+    fn test_label_values_escaped() {
+        let mut exposition = Exposition::new();
+        exposition.family("t_total", "counter", "Test counts.");
+        exposition.sample("t_total", &[("key", "a\"b\\c\nd")], 1);
+        let text = exposition.finish();
+        assert!(text.ends_with("t_total{key=\"a\\\"b\\\\c\\nd\"} 1\n"));
+        validate(&text).unwrap();
+    }
+    
+    #[test]
+    fn test_validate_rejects_malformed_text() {
+        assert!(validate("t_total 1\n").is_err());
+        assert!(validate("# TYPE t_total counter\nt_total one\n").is_err());
+        assert!(validate("# TYPE t_total counter\nt_total{a=\"1} 1\n").is_err());
+        assert!(validate("# TYPE t counter\n# TYPE t gauge\n").is_err());
+        let histogram = "# TYPE h histogram\nh_bucket{le=\"1\"} 2\nh_bucket{le=\"+Inf\"} 1\n";
+        assert!(validate(histogram).is_err());
+        let histogram = "# TYPE h histogram\nh_bucket{le=\"+Inf\"} 2\nh_count 3\n";
+        assert!(validate(histogram).is_err());
+    }
+}
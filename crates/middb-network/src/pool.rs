@@ -0,0 +1,281 @@
+use crate::client::{Client, ClientOptions, RetryPolicy};
+use crate::error::{ClientError, Result};
+use std::collections::VecDeque;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+#[derive(Debug, Clone)]
+pub struct PoolOptions {
+    /// Most connections open at once, in use or idle.
+    pub max_connections: usize,
+    /// Idle connections kept open however long they go unused.
+    pub min_idle: usize,
+    pub connect_timeout: Duration,
+    /// How long a connection may sit idle before it's closed.
+    pub idle_timeout: Duration,
+    /// User and password to log in with, for servers that require it.
+    pub credentials: Option<(String, String)>,
+}
+
+impl Default for PoolOptions {
+    fn default() -> Self {
+        PoolOptions {
+            max_connections: 10,
+            min_idle: 0,
+            connect_timeout: Duration::from_secs(5),
+            idle_timeout: Duration::from_secs(300),
+            credentials: None,
+        }
+    }
+}
+
+/// Connections to one server, shared by however many tasks need them. A
+/// task takes a connection with `get` and it returns to the pool when
+/// dropped. Tasks waiting for a connection get one in the order they
+/// asked.
+#[derive(Clone)]
+pub struct ClientPool {
+    inner: Arc<PoolInner>,
+}
+
+struct PoolInner {
+    addr: String,
+    options: PoolOptions,
+    /// One permit per connection that may be open.
+    permits: Arc<Semaphore>,
+    /// Oldest first.
+    idle: Mutex<VecDeque<IdleClient>>,
+}
+
+struct IdleClient {
+    client: Client,
+    since: Instant,
+}
+
+impl ClientPool {
+    /// A pool that connects to `addr` as connections are needed. Must be
+    /// called within a Tokio runtime, where idle connections are reaped.
+    pub fn new(addr: impl Into<String>, options: PoolOptions) -> Self {
+        let inner = Arc::new(PoolInner {
+            addr: addr.into(),
+            permits: Arc::new(Semaphore::new(options.max_connections)),
+            idle: Mutex::new(VecDeque::new()),
+            options,
+        });
+        let every = (inner.options.idle_timeout / 2).max(Duration::from_millis(10));
+        tokio::spawn(reap_idle(Arc::downgrade(&inner), every));
+        ClientPool { inner }
+    }
+    
+    /// A connection, waiting for one to free up if all are in use. Idle
+    /// connections are checked with a ping first, and one that doesn't
+    /// answer is replaced by a new connection.
+    pub async fn get(&self) -> Result<PooledClient> {
+        let permit = Arc::clone(&self.inner.permits)
+            .acquire_owned()
+            .await
+            .expect("the pool never closes its semaphore");
+
+        loop {
+            let idle = self.inner.idle.lock().unwrap().pop_back();
+            let Some(IdleClient { mut client, .. }) = idle else {
+                break;
+            };
+            // A retry would connect anew, which isn't what's being checked.
+            if client.with_retry(RetryPolicy::none()).ping().await.is_ok() {
+                return Ok(self.pooled(client, permit));
+            }
+        }
+        
+        let client = tokio::time::timeout(self.inner.options.connect_timeout, self.connect())
+            .await
+            .map_err(|_| ClientError::Timeout)??;
+        Ok(self.pooled(client, permit))
+    }
+    
+    /// Connections open and waiting to be used.
+    pub fn idle_count(&self) -> usize {
+        self.inner.idle.lock().unwrap().len()
+    }
+    
+    /// Connections handed out and not yet returned.
+    pub fn active_count(&self) -> usize {
+        let idle_or_closed = self.inner.permits.available_permits();
+        self.inner.options.max_connections - idle_or_closed
+    }
+    
+    async fn connect(&self) -> Result<Client> {
+        let options = ClientOptions {
+            credentials: self.inner.options.credentials.clone(),
+            ..ClientOptions::default()
+        };
+        Client::connect_with_options(&self.inner.addr, options).await
+    }
+    
+    fn pooled(&self, client: Client, permit: OwnedSemaphorePermit) -> PooledClient {
+        PooledClient {
+            client: Some(client),
+            pool: Arc::downgrade(&self.inner),
+            _permit: permit,
+        }
+    }
+}
+
+impl PoolInner {
+    /// Close connections idle past the timeout, oldest first, down to
+    /// `min_idle`.
+    fn reap(&self) {
+        let mut idle = self.idle.lock().unwrap();
+        let excess = idle.len().saturating_sub(self.options.min_idle);
+        let expired = idle
+            .iter()
+            .take(excess)
+            .take_while(|c| c.since.elapsed() >= self.options.idle_timeout)
+            .count();
+        idle.drain(..expired);
+    }
+}
+
+async fn reap_idle(pool: Weak<PoolInner>, every: Duration) {
+    let mut ticks = tokio::time::interval(every);
+    loop {
+        ticks.tick().await;
+        match pool.upgrade() {
+            Some(pool) => pool.reap(),
+            None => return,
+        }
+    }
+}
+
+/// A connection on loan from a `ClientPool`, used as a `Client`.
+pub struct PooledClient {
+    client: Option<Client>,
+    pool: Weak<PoolInner>,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl PooledClient {
+    /// Close the connection rather than return it, as after an error that
+    /// leaves it in doubt.
+    pub fn discard(mut self) {
+        self.client = None;
+    }
+}
+
+impl Deref for PooledClient {
+    type Target = Client;
+    
+    fn deref(&self) -> &Client {
+        self.client.as_ref().expect("a pooled client holds its connection until dropped")
+    }
+}
+
+impl DerefMut for PooledClient {
+    fn deref_mut(&mut self) -> &mut Client {
+        self.client.as_mut().expect("a pooled client holds its connection until dropped")
+    }
+}
+
+impl Drop for PooledClient {
+    fn drop(&mut self) {
+        // The idle connection goes back before the permit is released, so
+        // the next task to get the permit finds it.
+        if let (Some(client), Some(pool)) = (self.client.take(), self.pool.upgrade()) {
+            pool.idle.lock().unwrap().push_back(IdleClient {
+                client,
+                since: Instant::now(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::{Server, ServerHandle};
+    use middb_core::{Config, Database};
+    use tempfile::TempDir;
+    
+    async fn start(dir: &TempDir, addr: &str) -> ServerHandle {
+        let db = Database::open(Config::new(dir.path())).unwrap();
+        Server::new(db, addr.to_string()).spawn().await.unwrap()
+    }
+    
+    #[tokio::test]
+    async fn test_tasks_share_connections() {
+        let dir = TempDir::new().unwrap();
+        let server = start(&dir, "127.0.0.1:0").await;
+        let options = PoolOptions {
+            max_connections: 3,
+            ..PoolOptions::default()
+        };
+        let pool = ClientPool::new(server.local_addr().to_string(), options);
+        
+        let tasks: Vec<_> = (0..12u8)
+            .map(|i| {
+                let pool = pool.clone();
+                tokio::spawn(async move {
+                    let mut client = pool.get().await.unwrap();
+                    assert!(pool.active_count() <= 3);
+                    client.put(&[i], b"v").await.unwrap();
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+        
+        assert_eq!(pool.active_count(), 0);
+        assert!(pool.idle_count() <= 3);
+        let mut client = pool.get().await.unwrap();
+        for i in 0..12u8 {
+            assert_eq!(client.get(&[i]).await.unwrap(), Some(b"v".to_vec()));
+        }
+    }
+    
+    #[tokio::test]
+    async fn test_broken_connections_replaced() {
+        let dir = TempDir::new().unwrap();
+        let server = start(&dir, "127.0.0.1:0").await;
+        let addr = server.local_addr().to_string();
+        let pool = ClientPool::new(addr.clone(), PoolOptions::default());
+        let (a, b) = (pool.get().await.unwrap(), pool.get().await.unwrap());
+        drop((a, b));
+        assert_eq!(pool.idle_count(), 2);
+        
+        server.shutdown().await.unwrap();
+        let _server = start(&dir, &addr).await;
+        
+        // Both idle connections fail their ping, and a new one stands in.
+        let mut client = pool.get().await.unwrap();
+        client.put(b"k", b"v").await.unwrap();
+        assert_eq!(pool.idle_count(), 0);
+        drop(client);
+        assert_eq!(pool.idle_count(), 1);
+    }
+    
+    #[tokio::test]
+    async fn test_idle_connections_reaped() {
+        let dir = TempDir::new().unwrap();
+        let server = start(&dir, "127.0.0.1:0").await;
+        let options = PoolOptions {
+            min_idle: 1,
+            idle_timeout: Duration::from_millis(50),
+            ..PoolOptions::default()
+        };
+        let pool = ClientPool::new(server.local_addr().to_string(), options);
+        
+        let mut clients = Vec::new();
+        for _ in 0..3 {
+            clients.push(pool.get().await.unwrap());
+        }
+        drop(clients);
+        assert_eq!(pool.idle_count(), 3);
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(pool.idle_count(), 1);
+        pool.get().await.unwrap().ping().await.unwrap();
+    }
+}
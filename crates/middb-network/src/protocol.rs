@@ -1,4 +1,19 @@
+use middb_core::wal::crc32;
+use middb_core::DatabaseStats;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// The version of the protocol this build speaks. Versions before
+/// `MIN_PROTOCOL_VERSION` are refused at the handshake.
+pub const PROTOCOL_VERSION: u32 = 4;
+pub const MIN_PROTOCOL_VERSION: u32 = 4;
+
+/// Optional parts of the protocol this build supports. A connection may
+/// only use those both ends named in the handshake.
+pub const FEATURES: &[&str] = &["batch", "scan", "stats", "watch", "txn", "replication"];
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Request {
@@ -6,17 +21,337 @@ pub enum Request {
     Put { key: Vec<u8>, value: Vec<u8> },
     Delete { key: Vec<u8> },
     Ping,
+    /// Log in as `user`. Where the server requires it, this must come
+    /// before any other request on the connection.
+    Auth { user: String, password: String },
+    /// Up to `limit` entries with keys in `[start, end)`, in key order or,
+    /// with `reverse`, from the end back. A missing bound is open; a
+    /// `limit` of 0 asks for as many as the server sends at once.
+    Scan {
+        start: Option<Vec<u8>>,
+        end: Option<Vec<u8>>,
+        limit: u32,
+        reverse: bool,
+    },
+    /// Several requests in one frame, answered by a `Response::Batch` in
+    /// the same order. Batches don't nest.
+    Batch(Vec<Request>),
+    /// The handshake, sent once when a connection opens, naming the
+    /// protocol version and the features the client would like to use.
+    /// Answered by `Response::Hello`.
+    Hello { protocol_version: u32, features: Vec<String> },
+    /// The database's stats and the server's own, as `Response::Stats`.
+    Stats,
+    /// One database property, as `Database::property` gives it.
+    Property(String),
+    /// Turn the connection into a stream of `Response::Event`s, one for
+    /// each write to a key starting with `prefix` from now on. Answered by
+    /// `Response::Ok` first; the connection takes no more requests.
+    Watch { prefix: Vec<u8> },
+    /// Set `key` to `new`, or delete it for `None`, only if its value is
+    /// `expected`, where `None` expects it absent. Answered by a
+    /// `Response::CasResult`.
+    CompareAndSwap {
+        key: Vec<u8>,
+        expected: Option<Vec<u8>>,
+        new: Option<Vec<u8>>,
+    },
+    /// The values of several keys, read from one snapshot, answered by a
+    /// `Response::MultiGetResult`.
+    MultiGet { keys: Vec<Vec<u8>> },
+    /// Begin a transaction, answered by `Response::TxnBegun` with its id.
+    /// The transaction belongs to the connection: only it may use the id,
+    /// and it's aborted if the connection closes first.
+    TxnBegin,
+    TxnGet { txn_id: u64, key: Vec<u8> },
+    TxnPut { txn_id: u64, key: Vec<u8>, value: Vec<u8> },
+    TxnDelete { txn_id: u64, key: Vec<u8> },
+    TxnCommit { txn_id: u64 },
+    TxnAbort { txn_id: u64 },
+    /// Turn the connection into a stream of `Response::WalRecord`s, the
+    /// server's WAL from `from_sequence` on and then each record as it's
+    /// logged. Answered by `Response::Ok` first, or an error with
+    /// `ErrorCode::SnapshotRequired` if the WAL can't serve it.
+    ReplSubscribe { from_sequence: u64 },
+    /// The entries with keys in `[start, end)`, as `Scan` reads them but
+    /// sent in `Response::Chunk`s as the server reads the range, the last
+    /// one marked. While it lasts, the client may send only `Cancel`.
+    StreamScan {
+        start: Option<Vec<u8>>,
+        end: Option<Vec<u8>>,
+        reverse: bool,
+    },
+    /// The database's stats and the server's own in the Prometheus text
+    /// format, as `Response::Metrics`, with request latencies besides.
+    Metrics,
+    /// Stop the streamed scan in progress. It still ends with a last
+    /// chunk, perhaps empty. Gets no answer of its own, and is ignored
+    /// once the stream is over.
+    Cancel,
+    /// The answer to a `Response::Ping` from the server. It gets no answer
+    /// of its own.
+    Pong,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Response {
     Ok,
     Value(Option<Vec<u8>>),
-    Error(String),
+    Error(ErrorCode, String),
     Pong,
+    Batch(Vec<Response>),
+    /// One page of a scan. While `has_more`, the next page is the same
+    /// scan with `next_cursor` as its `start`, or as its `end` in reverse.
+    ScanResult {
+        entries: Vec<(Vec<u8>, Vec<u8>)>,
+        has_more: bool,
+        next_cursor: Option<Vec<u8>>,
+    },
+    /// The server's protocol version and the features both ends support,
+    /// which are the ones the connection may use.
+    Hello { protocol_version: u32, features: Vec<String> },
+    Stats(Box<ServerStats>),
+    Property(Option<String>),
+    /// A write to a watched key, sent unasked on a watch connection.
+    Event(WatchEvent),
+    /// Whether a compare-and-swap found the expected value and made its
+    /// write, and the key's value now.
+    CasResult { succeeded: bool, current: Option<Vec<u8>> },
+    /// The id of a transaction just begun.
+    TxnBegun(u64),
+    /// Each key's value, or `None`, in the order the keys were asked for.
+    MultiGetResult { values: Vec<Option<Vec<u8>>> },
+    /// Part of a streamed scan's entries, in order. `last` marks the end
+    /// of the stream.
+    Chunk {
+        entries: Vec<(Vec<u8>, Vec<u8>)>,
+        last: bool,
+    },
+    /// One WAL record, as `WalEntry::encode` writes it, sent unasked on a
+    /// replication connection.
+    WalRecord(Vec<u8>),
+    /// Prometheus exposition text, for `Request::Metrics`.
+    Metrics(String),
+    /// Sent unasked to check the client is still there, and to keep quiet
+    /// connections open through NATs. The client answers `Request::Pong`.
+    Ping,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EventKind {
+    Put,
+    Delete,
+}
+
+/// A write seen by a watch: the key, and its new value for a put.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WatchEvent {
+    pub key: Vec<u8>,
+    pub kind: EventKind,
+    pub value: Option<Vec<u8>>,
+}
+
+/// What kind of failure a `Response::Error` reports. Each goes on the
+/// wire as a fixed number, so a code added later reaches an older client
+/// as `Unknown` rather than failing to decode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "u16", into = "u16")]
+pub enum ErrorCode {
+    /// The request was malformed, or isn't allowed where it was sent.
+    InvalidArgument,
+    /// The connection must authenticate first.
+    AuthRequired,
+    /// The credentials were wrong.
+    AuthFailed,
+    /// The server has as many connections as it takes.
+    TooManyConnections,
+    /// The request, or the connection, is past one of the server's limits.
+    LimitExceeded,
+    /// The request needs a feature the handshake didn't settle on.
+    UnsupportedFeature,
+    /// What the request names isn't there, such as a transaction.
+    NotFound,
+    /// The transaction conflicted with another, or outlived the versions
+    /// its snapshot needs. Trying it again may succeed.
+    TxnConflict,
+    /// The transaction ran past its time and was aborted.
+    Timeout,
+    /// The database found its data damaged.
+    Corruption,
+    /// The database has no room left to write.
+    StorageFull,
+    /// The replica asked for WAL records the server no longer has, or
+    /// never had; it needs a copy of the whole database.
+    SnapshotRequired,
+    /// The server failed the operation for reasons of its own.
+    Internal,
+    /// A code this build doesn't know, from a newer server.
+    Unknown(u16),
+}
+
+impl From<ErrorCode> for u16 {
+    fn from(code: ErrorCode) -> Self {
+        match code {
+            ErrorCode::InvalidArgument => 1,
+            ErrorCode::AuthRequired => 2,
+            ErrorCode::AuthFailed => 3,
+            ErrorCode::TooManyConnections => 4,
+            ErrorCode::LimitExceeded => 5,
+            ErrorCode::UnsupportedFeature => 6,
+            ErrorCode::NotFound => 7,
+            ErrorCode::TxnConflict => 8,
+            ErrorCode::Timeout => 9,
+            ErrorCode::Corruption => 10,
+            ErrorCode::StorageFull => 11,
+            ErrorCode::SnapshotRequired => 12,
+            ErrorCode::Internal => 13,
+            ErrorCode::Unknown(code) => code,
+        }
+    }
+}
+
+impl From<u16> for ErrorCode {
+    fn from(code: u16) -> Self {
+        match code {
+            1 => ErrorCode::InvalidArgument,
+            2 => ErrorCode::AuthRequired,
+            3 => ErrorCode::AuthFailed,
+            4 => ErrorCode::TooManyConnections,
+            5 => ErrorCode::LimitExceeded,
+            6 => ErrorCode::UnsupportedFeature,
+            7 => ErrorCode::NotFound,
+            8 => ErrorCode::TxnConflict,
+            9 => ErrorCode::Timeout,
+            10 => ErrorCode::Corruption,
+            11 => ErrorCode::StorageFull,
+            12 => ErrorCode::SnapshotRequired,
+            13 => ErrorCode::Internal,
+            code => ErrorCode::Unknown(code),
+        }
+    }
+}
+
+impl From<&middb_core::Error> for ErrorCode {
+    fn from(error: &middb_core::Error) -> Self {
+        use middb_core::Error;
+        match error {
+            Error::KeyNotFound => ErrorCode::NotFound,
+            Error::TransactionConflict | Error::SnapshotTooOld => ErrorCode::TxnConflict,
+            Error::TransactionTimedOut => ErrorCode::Timeout,
+            Error::InvalidArgument(_) => ErrorCode::InvalidArgument,
+            Error::Corruption(_) => ErrorCode::Corruption,
+            Error::StorageFull => ErrorCode::StorageFull,
+            Error::SnapshotRequired(_) => ErrorCode::SnapshotRequired,
+            Error::Io(_)
+            | Error::Serialization(_)
+            | Error::InvalidConfig(_)
+            | Error::Locked(_)
+            | Error::Internal(_) => ErrorCode::Internal,
+        }
+    }
+}
+
+/// A server's numbers at a moment: the database's stats, and counts kept
+/// since the server started.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ServerStats {
+    pub db: DatabaseStats,
+    pub uptime: Duration,
+    pub active_connections: usize,
+    pub requests_in_flight: usize,
+    /// Requests received, by `Request::kind`. A batch counts once, as a
+    /// batch.
+    pub requests: BTreeMap<String, u64>,
+    /// Bytes of frames read and written.
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    /// Entries sent in streamed scans' chunks.
+    pub streamed_entries: u64,
 }
 
 impl Request {
+    /// The optional feature the request needs, if any.
+    pub fn feature(&self) -> Option<&'static str> {
+        match self {
+            Request::Batch(_) | Request::MultiGet { .. } => Some("batch"),
+            Request::Scan { .. } | Request::StreamScan { .. } => Some("scan"),
+            Request::Stats | Request::Property(_) | Request::Metrics => Some("stats"),
+            Request::Watch { .. } => Some("watch"),
+            Request::ReplSubscribe { .. } => Some("replication"),
+            _ if self.is_txn() => Some("txn"),
+            _ => None,
+        }
+    }
+    
+    /// Whether sending the request twice does no more than sending it
+    /// once, so that it's safe to retry when the first answer was lost.
+    pub fn is_idempotent(&self) -> bool {
+        match self {
+            Request::Put { .. } | Request::Delete { .. } | Request::CompareAndSwap { .. } => false,
+            // A transaction doesn't outlive its connection, so there's no
+            // going on with it on another.
+            _ if self.is_txn() => false,
+            Request::Batch(requests) => requests.iter().all(Request::is_idempotent),
+            _ => true,
+        }
+    }
+    
+    /// A short name for the kind of request, as stats count them.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Request::Get { .. } => "get",
+            Request::Put { .. } => "put",
+            Request::Delete { .. } => "delete",
+            Request::Ping => "ping",
+            Request::Auth { .. } => "auth",
+            Request::Scan { .. } => "scan",
+            Request::Batch(_) => "batch",
+            Request::Hello { .. } => "hello",
+            Request::Stats => "stats",
+            Request::Metrics => "metrics",
+            Request::Property(_) => "property",
+            Request::Watch { .. } => "watch",
+            Request::CompareAndSwap { .. } => "cas",
+            Request::TxnBegin => "txn_begin",
+            Request::TxnGet { .. } => "txn_get",
+            Request::TxnPut { .. } => "txn_put",
+            Request::TxnDelete { .. } => "txn_delete",
+            Request::TxnCommit { .. } => "txn_commit",
+            Request::TxnAbort { .. } => "txn_abort",
+            Request::MultiGet { .. } => "multi_get",
+            Request::ReplSubscribe { .. } => "repl_subscribe",
+            Request::StreamScan { .. } => "stream_scan",
+            Request::Cancel => "cancel",
+            Request::Pong => "pong",
+        }
+    }
+    
+    /// Whether the request writes, or may.
+    pub fn is_write(&self) -> bool {
+        match self {
+            Request::Put { .. }
+            | Request::Delete { .. }
+            | Request::CompareAndSwap { .. }
+            | Request::TxnPut { .. }
+            | Request::TxnDelete { .. } => true,
+            Request::Batch(requests) => requests.iter().any(Request::is_write),
+            _ => false,
+        }
+    }
+    
+    /// Whether the request is part of a transaction's life.
+    pub fn is_txn(&self) -> bool {
+        matches!(
+            self,
+            Request::TxnBegin
+                | Request::TxnGet { .. }
+                | Request::TxnPut { .. }
+                | Request::TxnDelete { .. }
+                | Request::TxnCommit { .. }
+                | Request::TxnAbort { .. }
+        )
+    }
+    
     pub fn encode(&self) -> Result<Vec<u8>, bincode::Error> {
         bincode::serialize(self)
     }
@@ -36,6 +371,53 @@ impl Response {
     }
 }
 
+/// Bytes a frame carries ahead of its payload.
+pub const FRAME_HEADER_LEN: usize = 8;
+
+/// A message on the wire: the payload's length and its CRC32, both four
+/// bytes little-endian, then the payload.
+pub fn encode_frame(payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(FRAME_HEADER_LEN + payload.len());
+    frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    frame.extend_from_slice(&crc32(payload).to_le_bytes());
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// Read one frame's payload, refusing one longer than `max_len` bytes.
+pub async fn read_frame(
+    reader: &mut (impl AsyncRead + Unpin),
+    max_len: usize,
+) -> io::Result<Vec<u8>> {
+    let len = reader.read_u32_le().await?;
+    read_frame_after_len(reader, len, max_len).await
+}
+
+/// Read the rest of a frame whose length has been read already. A bad
+/// length or checksum is an `InvalidData` error, and a length is checked
+/// before anything is set aside for the payload.
+pub async fn read_frame_after_len(
+    reader: &mut (impl AsyncRead + Unpin),
+    len: u32,
+    max_len: usize,
+) -> io::Result<Vec<u8>> {
+    let len = len as usize;
+    if len == 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "empty frame"));
+    }
+    if len > max_len {
+        let message = format!("frame of {} bytes exceeds the limit of {}", len, max_len);
+        return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+    }
+    let checksum = reader.read_u32_le().await?;
+    let mut payload = vec![0u8; len];
+    reader.read_exact(&mut payload).await?;
+    if crc32(&payload) != checksum {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "frame checksum mismatch"));
+    }
+    Ok(payload)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -70,4 +452,121 @@ mod tests {
             _ => panic!("Wrong variant"),
         }
     }
+    
+    #[test]
+    fn test_error_codes() {
+        // Each code keeps its number, and numbers not yet given out decode
+        // as unknown codes.
+        for number in 0..=20u16 {
+            let code = ErrorCode::from(number);
+            assert_eq!(u16::from(code), number);
+            let response = Response::Error(code, String::new());
+            match Response::decode(&response.encode().unwrap()).unwrap() {
+                Response::Error(decoded, _) => assert_eq!(decoded, code),
+                _ => panic!("Wrong variant"),
+            }
+        }
+        assert_eq!(ErrorCode::from(13), ErrorCode::Internal);
+        assert_eq!(ErrorCode::from(14), ErrorCode::Unknown(14));
+        
+        let codes = [
+            (middb_core::Error::KeyNotFound, ErrorCode::NotFound),
+            (middb_core::Error::TransactionConflict, ErrorCode::TxnConflict),
+            (middb_core::Error::SnapshotTooOld, ErrorCode::TxnConflict),
+            (middb_core::Error::TransactionTimedOut, ErrorCode::Timeout),
+            (middb_core::Error::InvalidArgument("k".into()), ErrorCode::InvalidArgument),
+            (middb_core::Error::Corruption("bad crc".into()), ErrorCode::Corruption),
+            (middb_core::Error::StorageFull, ErrorCode::StorageFull),
+            (middb_core::Error::SnapshotRequired("gap".into()), ErrorCode::SnapshotRequired),
+            (middb_core::Error::Internal("oops".into()), ErrorCode::Internal),
+            (io::Error::other("disk").into(), ErrorCode::Internal),
+        ];
+        for (error, code) in codes {
+            assert_eq!(ErrorCode::from(&error), code, "{}", error);
+        }
+    }
+    
+    #[tokio::test]
+    async fn test_frames_round_trip() {
+        let requests = vec![
+            Request::Get { key: b"k".to_vec() },
+            Request::Put { key: b"k".to_vec(), value: b"v".to_vec() },
+            Request::Delete { key: b"k".to_vec() },
+            Request::Ping,
+            Request::Auth { user: "ann".to_string(), password: "secret".to_string() },
+            Request::Scan { start: Some(b"a".to_vec()), end: None, limit: 10, reverse: true },
+            Request::Batch(vec![Request::Ping, Request::Get { key: b"k".to_vec() }]),
+            Request::Hello { protocol_version: PROTOCOL_VERSION, features: vec!["scan".into()] },
+            Request::Stats,
+            Request::Metrics,
+            Request::Property("middb.num-sstables".to_string()),
+            Request::Watch { prefix: b"user:".to_vec() },
+            Request::StreamScan { start: None, end: Some(b"z".to_vec()), reverse: false },
+            Request::Cancel,
+        ];
+        let responses = vec![
+            Response::Ok,
+            Response::Value(None),
+            Response::Error(ErrorCode::Internal, "oops".to_string()),
+            Response::Pong,
+            Response::Batch(vec![Response::Ok, Response::Value(Some(b"v".to_vec()))]),
+            Response::ScanResult {
+                entries: vec![(b"k".to_vec(), b"v".to_vec())],
+                has_more: true,
+                next_cursor: Some(b"k\0".to_vec()),
+            },
+            Response::Hello { protocol_version: PROTOCOL_VERSION, features: Vec::new() },
+            Response::Error(ErrorCode::Unknown(999), "from the future".to_string()),
+            Response::Stats(Box::default()),
+            Response::Metrics("middb_uptime_seconds 1\n".to_string()),
+            Response::Property(Some("3".to_string())),
+            Response::Event(WatchEvent {
+                key: b"user:1".to_vec(),
+                kind: EventKind::Put,
+                value: Some(b"ann".to_vec()),
+            }),
+            Response::Chunk { entries: vec![(b"k".to_vec(), b"v".to_vec())], last: true },
+        ];
+        
+        // All the frames back to back, as on a connection.
+        let mut wire = Vec::new();
+        for request in &requests {
+            wire.extend(encode_frame(&request.encode().unwrap()));
+        }
+        for response in &responses {
+            wire.extend(encode_frame(&response.encode().unwrap()));
+        }
+        let mut reader = wire.as_slice();
+        for request in &requests {
+            let decoded = Request::decode(&read_frame(&mut reader, 1024).await.unwrap()).unwrap();
+            assert_eq!(format!("{:?}", decoded), format!("{:?}", request));
+        }
+        for response in &responses {
+            let decoded = Response::decode(&read_frame(&mut reader, 1024).await.unwrap()).unwrap();
+            assert_eq!(format!("{:?}", decoded), format!("{:?}", response));
+        }
+        assert!(reader.is_empty());
+    }
+    
+    #[tokio::test]
+    async fn test_bad_frames_rejected() {
+        let frame = encode_frame(&Request::Ping.encode().unwrap());
+        let mut flipped = frame.clone();
+        *flipped.last_mut().unwrap() ^= 0x01;
+        let err = read_frame(&mut flipped.as_slice(), 1024).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "frame checksum mismatch");
+        
+        // An oversized frame is refused from its length alone, with nothing
+        // after it read.
+        let length = u32::MAX.to_le_bytes();
+        let err = read_frame(&mut length.as_slice(), 1024).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let expected = format!("frame of {} bytes exceeds the limit of 1024", u32::MAX);
+        assert_eq!(err.to_string(), expected);
+        
+        let truncated = &frame[..frame.len() - 1];
+        let err = read_frame(&mut &truncated[..], 1024).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
 }
@@ -0,0 +1,227 @@
+use crate::client::{Client, ClientOptions};
+use crate::error::ClientError;
+use middb_core::Database;
+use std::fmt;
+use std::sync::Arc;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+/// Why a replica stopped following its leader.
+#[derive(Debug)]
+pub enum ReplicaError {
+    /// The leader couldn't be reached, or couldn't serve the WAL from
+    /// where the replica got to. `ErrorCode::SnapshotRequired` means the
+    /// replica needs a copy of the leader's whole database.
+    Leader(ClientError),
+    /// A record from the leader couldn't be applied here.
+    Apply(middb_core::Error),
+}
+
+impl fmt::Display for ReplicaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplicaError::Leader(e) => write!(f, "leader: {}", e),
+            ReplicaError::Apply(e) => write!(f, "applying the leader's WAL: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ReplicaError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ReplicaError::Leader(e) => Some(e),
+            ReplicaError::Apply(e) => Some(e),
+        }
+    }
+}
+
+impl From<ClientError> for ReplicaError {
+    fn from(error: ClientError) -> Self {
+        ReplicaError::Leader(error)
+    }
+}
+
+impl From<middb_core::Error> for ReplicaError {
+    fn from(error: middb_core::Error) -> Self {
+        ReplicaError::Apply(error)
+    }
+}
+
+/// A database kept level with a leader's by applying the leader's WAL as
+/// it's written. It follows from its own next sequence number, so one
+/// restarted over the same data carries on where it left off.
+///
+/// Nothing stops the database being written to other than through the
+/// replica; serve it with `ServerConfig::read_only` to keep clients off.
+pub struct Replica {
+    stop: oneshot::Sender<()>,
+    task: JoinHandle<Result<(), ReplicaError>>,
+}
+
+impl Replica {
+    /// Start following `leader`. With a reconnect policy in `options`, a
+    /// lost connection to the leader is made again and following picks up
+    /// where it stopped; without one, losing it stops the replica.
+    pub fn spawn(db: Arc<Database>, leader: impl Into<String>, options: ClientOptions) -> Self {
+        let (stop, stopped) = oneshot::channel();
+        let task = tokio::spawn(follow(db, leader.into(), options, stopped));
+        Replica { stop, task }
+    }
+    
+    /// Whether the replica has stopped by itself, on an error.
+    pub fn is_finished(&self) -> bool {
+        self.task.is_finished()
+    }
+    
+    /// Stop following, and return the error that stopped it already, if
+    /// one did.
+    pub async fn stop(self) -> Result<(), ReplicaError> {
+        let _ = self.stop.send(());
+        self.task.await.expect("the replica task panicked")
+    }
+}
+
+async fn follow(
+    db: Arc<Database>,
+    leader: String,
+    options: ClientOptions,
+    mut stopped: oneshot::Receiver<()>,
+) -> Result<(), ReplicaError> {
+    let following = async {
+        let client = Client::connect_with_options(&leader, options).await?;
+        let mut wal = client.replicate(db.next_sequence()).await?;
+        loop {
+            let entry = wal.next().await?;
+            db.apply_replicated(entry)?;
+        }
+    };
+    let result = tokio::select! {
+        result = following => result,
+        _ = &mut stopped => return Ok(()),
+    };
+    if let Err(e) = &result {
+        eprintln!("Stopped following {}: {}", leader, e);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::ErrorCode;
+    use crate::server::{Server, ServerConfig, ServerHandle};
+    use middb_core::wal::{WalEntry, WalWriter};
+    use middb_core::Config;
+    use std::time::Duration;
+    use tempfile::TempDir;
+    
+    async fn leader(dir: &TempDir) -> ServerHandle {
+        let db = Database::open(Config::new(dir.path())).unwrap();
+        Server::new(db, "127.0.0.1:0".to_string()).spawn().await.unwrap()
+    }
+    
+    /// Wait until `db` has `key`, failing after a few seconds.
+    async fn replicated(db: &Database, key: &[u8]) -> Vec<u8> {
+        for _ in 0..500 {
+            if let Some(value) = db.get(&key.to_vec()).unwrap() {
+                return value;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("{:?} never reached the replica", key);
+    }
+    
+    #[tokio::test]
+    async fn test_replica_follows_leader() {
+        let (leader_dir, replica_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        let leader = leader(&leader_dir).await;
+        let addr = leader.local_addr().to_string();
+        let mut client = Client::connect(&addr).await.unwrap();
+        client.put(b"before", b"1").await.unwrap();
+        
+        let db = Arc::new(Database::open(Config::new(replica_dir.path())).unwrap());
+        let replica = Replica::spawn(Arc::clone(&db), addr, ClientOptions::default());
+        assert_eq!(replicated(&db, b"before").await, b"1");
+        
+        client.delete(b"before").await.unwrap();
+        client.put(b"after", b"2").await.unwrap();
+        assert_eq!(replicated(&db, b"after").await, b"2");
+        assert_eq!(db.get(&b"before".to_vec()).unwrap(), None);
+        
+        // Served read-only, the replica's copy takes reads but no writes.
+        let config = ServerConfig { read_only: true, ..ServerConfig::default() };
+        let follower = Server::with_shared_db(Arc::clone(&db), "127.0.0.1:0".to_string(), config)
+            .spawn()
+            .await
+            .unwrap();
+        let mut reader = Client::connect(&follower.local_addr().to_string()).await.unwrap();
+        assert_eq!(reader.get(b"after").await.unwrap(), Some(b"2".to_vec()));
+        assert!(matches!(
+            reader.put(b"x", b"y").await,
+            Err(ClientError::Server { code: ErrorCode::InvalidArgument, .. })
+        ));
+        
+        replica.stop().await.unwrap();
+        follower.shutdown().await.unwrap();
+        leader.shutdown().await.unwrap();
+    }
+    
+    #[tokio::test]
+    async fn test_restarted_replica_resumes() {
+        let (leader_dir, replica_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        let leader = leader(&leader_dir).await;
+        let addr = leader.local_addr().to_string();
+        let mut client = Client::connect(&addr).await.unwrap();
+        client.put(b"a", b"1").await.unwrap();
+        
+        {
+            let db = Arc::new(Database::open(Config::new(replica_dir.path())).unwrap());
+            let replica = Replica::spawn(Arc::clone(&db), addr.clone(), ClientOptions::default());
+            replicated(&db, b"a").await;
+            replica.stop().await.unwrap();
+        }
+        client.put(b"b", b"2").await.unwrap();
+        
+        let db = Arc::new(Database::open(Config::new(replica_dir.path())).unwrap());
+        assert_eq!(db.next_sequence(), 1);
+        let replica = Replica::spawn(Arc::clone(&db), addr, ClientOptions::default());
+        assert_eq!(replicated(&db, b"b").await, b"2");
+        // Only what it missed was shipped again.
+        assert_eq!(db.next_sequence(), 2);
+        assert_eq!(db.wal_since(0).unwrap().len(), 2);
+        
+        replica.stop().await.unwrap();
+        leader.shutdown().await.unwrap();
+    }
+    
+    #[tokio::test]
+    async fn test_replica_behind_leader_wal_needs_snapshot() {
+        let (leader_dir, replica_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        let config = Config::new(leader_dir.path());
+        std::fs::create_dir_all(&config.wal_dir).unwrap();
+        {
+            // A leader whose records before 10 are gone.
+            let mut wal = WalWriter::create(config.wal_dir.join("wal.log")).unwrap();
+            wal.append(&WalEntry::put(10, b"a".to_vec(), b"1".to_vec())).unwrap();
+            wal.sync().unwrap();
+        }
+        let leader = Server::new(Database::open(config).unwrap(), "127.0.0.1:0".to_string())
+            .spawn()
+            .await
+            .unwrap();
+        
+        let db = Arc::new(Database::open(Config::new(replica_dir.path())).unwrap());
+        let addr = leader.local_addr().to_string();
+        let replica = Replica::spawn(Arc::clone(&db), addr, ClientOptions::default());
+        while !replica.is_finished() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(matches!(
+            replica.stop().await,
+            Err(ReplicaError::Leader(ClientError::Server { code: ErrorCode::SnapshotRequired, .. }))
+        ));
+        assert_eq!(db.get(&b"a".to_vec()).unwrap(), None);
+        
+        leader.shutdown().await.unwrap();
+    }
+}
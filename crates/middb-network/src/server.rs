@@ -1,88 +1,2482 @@
-use crate::protocol::{Request, Response};
-use middb_core::Database;
+use crate::auth::{self, check_credentials};
+use crate::metrics::{Exposition, Histogram};
+use crate::protocol::{
+    self, ErrorCode, EventKind, Request, Response, ServerStats, WatchEvent, FEATURES,
+    MIN_PROTOCOL_VERSION, PROTOCOL_VERSION,
+};
+use middb_core::wal::WalEntry;
+use middb_core::{Database, TxnId, TxnOptions, WriteEvent};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::future::Future;
 use std::io;
-use std::sync::Arc;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::{oneshot, watch, Semaphore};
+use tokio::task::{JoinHandle, JoinSet};
+
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    /// Most requests one batch may hold; larger batches are refused whole.
+    pub max_batch_size: usize,
+    /// Most entries one scan response carries, whatever the client asks,
+    /// and the keys a streamed scan reads at a time.
+    pub max_scan_entries: usize,
+    /// Bytes of keys and values a streamed scan gathers into a chunk
+    /// before sending it.
+    pub scan_chunk_bytes: usize,
+    /// Most keys one multi-get may ask for; larger ones are refused.
+    pub max_multi_get_keys: usize,
+    /// Password hashes by user name, as `auth::hash_password` makes them.
+    /// With any users, each connection must authenticate before anything
+    /// else; with none, no one needs to.
+    pub users: HashMap<String, String>,
+    /// Failed authentications a connection may make before it's closed.
+    pub max_auth_failures: u32,
+    /// How long a shutdown waits for requests in flight before closing
+    /// their connections anyway.
+    pub drain_timeout: Duration,
+    /// Most connections open at once. Past it, new connections are told
+    /// so and closed.
+    pub max_connections: usize,
+    /// Most requests processed at once, across all connections; the rest
+    /// wait their turn.
+    pub max_concurrent_requests: usize,
+    /// Largest request frame accepted. A larger one is refused from its
+    /// length alone, and the connection closed.
+    pub max_request_bytes: usize,
+    /// Writes a watching connection may fall behind by before it starts
+    /// missing some, and WAL records a replica may fall behind by before
+    /// it's cut off to catch up again.
+    pub watch_buffer: usize,
+    /// Refuse writes, as a replica does: its writes come from its leader.
+    pub read_only: bool,
+    /// How long a connection may go without a request before it's closed.
+    /// A watch connection counts its answers to keepalive pings.
+    pub idle_timeout: Duration,
+    /// How long a transaction begun over the network may stay open before
+    /// it's aborted.
+    pub txn_timeout: Duration,
+    /// How often a watch connection is pinged, or `None` not to ping
+    /// them; then they're closed once idle like any other.
+    pub keepalive_interval: Option<Duration>,
+    /// Where to serve `Request::Metrics`' text over HTTP, at `/metrics`,
+    /// for Prometheus to scrape; `None` not to.
+    pub metrics_addr: Option<String>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            max_batch_size: 10_000,
+            max_scan_entries: 1_000,
+            scan_chunk_bytes: 64 * 1024,
+            max_multi_get_keys: 10_000,
+            users: HashMap::new(),
+            max_auth_failures: 3,
+            drain_timeout: Duration::from_secs(30),
+            max_connections: 1024,
+            max_concurrent_requests: 64,
+            max_request_bytes: 10 * 1024 * 1024,
+            watch_buffer: 1024,
+            read_only: false,
+            idle_timeout: Duration::from_secs(300),
+            txn_timeout: Duration::from_secs(60),
+            keepalive_interval: Some(Duration::from_secs(60)),
+            metrics_addr: None,
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Add a user who may connect with `password`.
+    pub fn with_user(mut self, user: &str, password: &str) -> Self {
+        self.users.insert(user.to_string(), auth::hash_password(password));
+        self
+    }
+    
+    pub fn auth_enabled(&self) -> bool {
+        !self.users.is_empty()
+    }
+}
+
+/// How long a failed authentication waits before its answer, per failure
+/// so far on the connection, to slow down guessing.
+const AUTH_FAILURE_DELAY: Duration = Duration::from_millis(100);
+
+/// How long a refused connection stays open for the client to read why.
+const REFUSAL_LINGER: Duration = Duration::from_secs(1);
+
+/// How long the server waits to accept again after accepting failed, so
+/// running out of file descriptors doesn't spin the accept loop.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// How long a metrics scrape may take to send its HTTP request, and the
+/// most bytes its head may run to.
+const SCRAPE_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_SCRAPE_HEAD: usize = 8 * 1024;
+
+/// What a server is busy with at a moment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ServerLoad {
+    /// Connections open, not counting refused ones.
+    pub connections: usize,
+    /// Requests being processed, not counting those waiting their turn.
+    pub requests: usize,
+    /// Connections watching for writes.
+    pub watchers: usize,
+    /// Replicas following the WAL.
+    pub replicas: usize,
+}
+
+/// What the database does as it happens, for the connections pushing it
+/// on: writes for watchers, and WAL records for replicas.
+#[derive(Clone)]
+struct Feeds {
+    writes: broadcast::Sender<Arc<[WriteEvent]>>,
+    wal: broadcast::Sender<Arc<WalEntry>>,
+}
+
+impl Feeds {
+    /// Feeds from `db`, which only pay for what's sent while someone is
+    /// listening.
+    fn new(db: &Database, capacity: usize) -> Self {
+        let (writes, _) = broadcast::channel(capacity);
+        let (wal, _) = broadcast::channel(capacity);
+        let sender = writes.clone();
+        db.on_write(Box::new(move |written| {
+            if sender.receiver_count() > 0 {
+                let _ = sender.send(Arc::from(written));
+            }
+        }));
+        let sender = wal.clone();
+        db.on_wal_append(Box::new(move |entry| {
+            if sender.receiver_count() > 0 {
+                let _ = sender.send(Arc::new(entry.clone()));
+            }
+        }));
+        Feeds { writes, wal }
+    }
+}
+
+/// What every connection's task shares with the server.
+struct Shared {
+    permits: Permits,
+    counters: Arc<Counters>,
+    feeds: Feeds,
+}
+
+/// One permit per connection and per request the server may take on.
+#[derive(Clone)]
+struct Permits {
+    connections: Arc<Semaphore>,
+    requests: Arc<Semaphore>,
+    max_connections: usize,
+    max_requests: usize,
+}
+
+impl Permits {
+    fn new(config: &ServerConfig) -> Self {
+        Permits {
+            connections: Arc::new(Semaphore::new(config.max_connections)),
+            requests: Arc::new(Semaphore::new(config.max_concurrent_requests)),
+            max_connections: config.max_connections,
+            max_requests: config.max_concurrent_requests,
+        }
+    }
+    
+    fn load(&self, feeds: &Feeds) -> ServerLoad {
+        ServerLoad {
+            connections: self.max_connections - self.connections.available_permits(),
+            requests: self.max_requests - self.requests.available_permits(),
+            watchers: feeds.writes.receiver_count(),
+            replicas: feeds.wal.receiver_count(),
+        }
+    }
+}
+
+/// Counts kept since the server started, for `Request::Stats` and
+/// `Request::Metrics`.
+struct Counters {
+    started: Instant,
+    requests: Mutex<BTreeMap<&'static str, u64>>,
+    /// How long requests took, from being read to their answer written,
+    /// by `Request::kind`. Watches and replication aren't timed.
+    latencies: Mutex<BTreeMap<&'static str, Histogram>>,
+    connections_accepted: AtomicU64,
+    connections_refused: AtomicU64,
+    bytes_in: AtomicU64,
+    bytes_out: AtomicU64,
+    streamed_entries: AtomicU64,
+}
+
+impl Counters {
+    fn new() -> Self {
+        Counters {
+            started: Instant::now(),
+            requests: Mutex::new(BTreeMap::new()),
+            latencies: Mutex::new(BTreeMap::new()),
+            connections_accepted: AtomicU64::new(0),
+            connections_refused: AtomicU64::new(0),
+            bytes_in: AtomicU64::new(0),
+            bytes_out: AtomicU64::new(0),
+            streamed_entries: AtomicU64::new(0),
+        }
+    }
+    
+    fn count_request(&self, request: &Request) {
+        *self.requests.lock().unwrap().entry(request.kind()).or_default() += 1;
+    }
+    
+    fn time_request(&self, kind: &'static str, started: Instant) {
+        self.latencies.lock().unwrap().entry(kind).or_default().observe(started.elapsed());
+    }
+    
+    /// The database's stats with the server's own. The database takes its
+    /// locks one at a time, so writers are held up no longer than by a read.
+    fn snapshot(&self, db: &Database, load: ServerLoad) -> ServerStats {
+        let requests = self.requests.lock().unwrap()
+            .iter()
+            .map(|(kind, count)| (kind.to_string(), *count))
+            .collect();
+        ServerStats {
+            db: db.stats(),
+            uptime: self.started.elapsed(),
+            active_connections: load.connections,
+            requests_in_flight: load.requests,
+            requests,
+            bytes_in: self.bytes_in.load(Ordering::Relaxed),
+            bytes_out: self.bytes_out.load(Ordering::Relaxed),
+            streamed_entries: self.streamed_entries.load(Ordering::Relaxed),
+        }
+    }
+    
+    /// What `snapshot` gives, with connection counts and request
+    /// latencies, as Prometheus exposition text.
+    fn metrics(&self, db: &Database, load: ServerLoad) -> String {
+        let stats = self.snapshot(db, load);
+        let mut out = Exposition::new();
+        let uptime = stats.uptime.as_secs_f64();
+        out.gauge("middb_uptime_seconds", "Time since the server started.", uptime);
+        
+        out.gauge("middb_connections", "Connections open.", load.connections);
+        out.family("middb_connections_total", "counter", "Connections accepted, or refused as too many.");
+        let accepted = self.connections_accepted.load(Ordering::Relaxed);
+        let refused = self.connections_refused.load(Ordering::Relaxed);
+        out.sample("middb_connections_total", &[("result", "accepted")], accepted);
+        out.sample("middb_connections_total", &[("result", "refused")], refused);
+        out.gauge("middb_watchers", "Connections watching for writes.", load.watchers);
+        out.gauge("middb_replicas", "Replicas following the WAL.", load.replicas);
+        
+        out.gauge("middb_requests_in_flight", "Requests being processed.", load.requests);
+        out.family("middb_requests_total", "counter", "Requests received, by type.");
+        for (kind, count) in &stats.requests {
+            out.sample("middb_requests_total", &[("type", kind)], count);
+        }
+        let latency = "middb_request_duration_seconds";
+        out.family(latency, "histogram", "Time from reading a request to answering it, by type.");
+        for (kind, latencies) in self.latencies.lock().unwrap().iter() {
+            out.histogram(latency, &[("type", kind)], latencies);
+        }
+        out.counter("middb_received_bytes_total", "Bytes of frames read.", stats.bytes_in);
+        out.counter("middb_sent_bytes_total", "Bytes of frames written.", stats.bytes_out);
+        let streamed = stats.streamed_entries;
+        out.counter("middb_streamed_entries_total", "Entries sent in streamed scans.", streamed);
+        
+        let db = &stats.db;
+        out.gauge("middb_memtable_bytes", "Approximate size of the memtable.", db.memtable_size);
+        out.gauge("middb_memtable_entries", "Entries in the memtable.", db.memtable_entries);
+        out.gauge("middb_sstables", "SSTables across all levels.", db.num_sstables);
+        out.gauge("middb_l0_files", "SSTables in level 0, to be compacted.", db.l0_file_count);
+        out.gauge("middb_sequence_number", "The last sequence number written.", db.sequence_number);
+        out.counter("middb_bloom_probes_total", "SSTable bloom filter probes.", db.bloom_probes);
+        let negatives = db.bloom_negatives;
+        out.counter("middb_bloom_negatives_total", "Bloom probes that ruled a key out.", negatives);
+        
+        let txn = &db.txn;
+        out.counter("middb_txns_begun_total", "Transactions begun.", txn.begun);
+        out.counter("middb_txns_committed_total", "Transactions committed.", txn.committed);
+        out.family("middb_txns_aborted_total", "counter", "Transactions aborted, by why.");
+        out.sample("middb_txns_aborted_total", &[("reason", "explicit")], txn.aborted_explicit);
+        out.sample("middb_txns_aborted_total", &[("reason", "conflict")], txn.aborted_conflict);
+        out.sample("middb_txns_aborted_total", &[("reason", "timeout")], txn.aborted_timeout);
+        out.gauge("middb_txns_active", "Transactions open.", txn.active);
+        let retained = db.txn_retained_versions;
+        out.gauge("middb_txn_retained_versions", "Versions kept for snapshot reads.", retained);
+        let retained = db.txn_retained_bytes;
+        out.gauge("middb_txn_retained_bytes", "Bytes of versions kept for snapshot reads.", retained);
+        out.finish()
+    }
+}
 
 pub struct Server {
     db: Arc<Database>,
     addr: String,
+    config: Arc<ServerConfig>,
+    permits: Permits,
+    counters: Arc<Counters>,
+    feeds: Feeds,
 }
 
 impl Server {
     pub fn new(db: Database, addr: String) -> Self {
+        Self::with_config(db, addr, ServerConfig::default())
+    }
+    
+    pub fn with_config(db: Database, addr: String, config: ServerConfig) -> Self {
+        Self::with_shared_db(Arc::new(db), addr, config)
+    }
+    
+    /// Serve a database something else uses too, as a replica's follower
+    /// does. It's closed at shutdown only if nothing else holds it then.
+    pub fn with_shared_db(db: Arc<Database>, addr: String, config: ServerConfig) -> Self {
         Server {
-            db: Arc::new(db),
+            feeds: Feeds::new(&db, config.watch_buffer.max(1)),
+            db,
             addr,
+            permits: Permits::new(&config),
+            config: Arc::new(config),
+            counters: Arc::new(Counters::new()),
         }
     }
     
+    pub fn load(&self) -> ServerLoad {
+        self.permits.load(&self.feeds)
+    }
+    
     pub async fn run(&self) -> io::Result<()> {
         let listener = TcpListener::bind(&self.addr).await?;
         println!("Server listening on {}", self.addr);
-        
+        self.serve(listener).await
+    }
+    
+    /// Accept connections on an already bound listener.
+    pub async fn serve(&self, listener: TcpListener) -> io::Result<()> {
+        let metrics = self.bind_metrics().await?;
+        self.accept(listener, metrics, std::future::pending()).await
+    }
+    
+    /// Run until `signal` completes, then shut down: stop accepting
+    /// connections, let requests in flight finish for up to the drain
+    /// timeout, close every connection and close the database.
+    pub async fn run_with_shutdown(self, signal: impl Future<Output = ()>) -> io::Result<()> {
+        let listener = TcpListener::bind(&self.addr).await?;
+        println!("Server listening on {}", self.addr);
+        self.serve_with_shutdown(listener, signal).await
+    }
+    
+    pub async fn serve_with_shutdown(
+        self,
+        listener: TcpListener,
+        signal: impl Future<Output = ()>,
+    ) -> io::Result<()> {
+        let metrics = self.bind_metrics().await?;
+        self.serve_until(listener, metrics, signal).await
+    }
+    
+    async fn serve_until(
+        self,
+        listener: TcpListener,
+        metrics: Option<TcpListener>,
+        signal: impl Future<Output = ()>,
+    ) -> io::Result<()> {
+        self.accept(listener, metrics, signal).await?;
+        // Aborted connections have let go of the database by now; anything
+        // else holding it keeps it open, to close when it's dropped.
+        match Arc::try_unwrap(self.db) {
+            Ok(db) => db.close().map_err(io::Error::other),
+            Err(_) => Ok(()),
+        }
+    }
+    
+    /// Run in the background, stopped through the returned handle.
+    pub async fn spawn(self) -> io::Result<ServerHandle> {
+        let listener = TcpListener::bind(&self.addr).await?;
+        let addr = listener.local_addr()?;
+        let metrics = self.bind_metrics().await?;
+        let metrics_addr = metrics.as_ref().map(TcpListener::local_addr).transpose()?;
+        let (stop, stopped) = oneshot::channel();
+        let permits = self.permits.clone();
+        let feeds = self.feeds.clone();
+        let signal = async {
+            // A dropped handle leaves the server running.
+            if stopped.await.is_err() {
+                std::future::pending::<()>().await;
+            }
+        };
+        let task = tokio::spawn(self.serve_until(listener, metrics, signal));
+        Ok(ServerHandle { addr, metrics_addr, stop, task, permits, feeds })
+    }
+    
+    async fn bind_metrics(&self) -> io::Result<Option<TcpListener>> {
+        let Some(addr) = &self.config.metrics_addr else {
+            return Ok(None);
+        };
+        let listener = TcpListener::bind(addr).await?;
+        println!("Serving metrics on http://{}/metrics", listener.local_addr()?);
+        Ok(Some(listener))
+    }
+    
+    fn shared(&self) -> Shared {
+        Shared {
+            permits: self.permits.clone(),
+            counters: Arc::clone(&self.counters),
+            feeds: self.feeds.clone(),
+        }
+    }
+    
+    /// Serve connections until `signal` completes, then drain them. The
+    /// metrics endpoint, if there is one, is served until they're gone.
+    async fn accept(
+        &self,
+        listener: TcpListener,
+        metrics: Option<TcpListener>,
+        signal: impl Future<Output = ()>,
+    ) -> io::Result<()> {
+        let metrics = metrics.map(|listener| {
+            tokio::spawn(serve_metrics(listener, Arc::clone(&self.db), self.shared()))
+        });
+        let (stop, stopping) = watch::channel(false);
+        let mut connections = JoinSet::new();
+        tokio::pin!(signal);
         loop {
-            let (socket, addr) = listener.accept().await?;
-            println!("New connection from {}", addr);
-            
-            let db = Arc::clone(&self.db);
-            tokio::spawn(async move {
-                if let Err(e) = handle_connection(socket, db).await {
-                    eprintln!("Connection error: {}", e);
+            tokio::select! {
+                _ = &mut signal => break,
+                accepted = listener.accept() => {
+                    // Accepting fails with EMFILE or ENFILE when the process
+                    // or system runs out of file descriptors, and with
+                    // ECONNABORTED when a client gives up first. None of
+                    // them means the listener is broken, so the server
+                    // keeps serving the connections it has and tries again.
+                    let (socket, addr) = match accepted {
+                        Ok(accepted) => accepted,
+                        Err(e) => {
+                            eprintln!("Accepting a connection failed: {}", e);
+                            tokio::time::sleep(ACCEPT_BACKOFF).await;
+                            continue;
+                        }
+                    };
+                    let Ok(permit) = Arc::clone(&self.permits.connections).try_acquire_owned()
+                    else {
+                        eprintln!("Refusing connection from {}: too many connections", addr);
+                        self.counters.connections_refused.fetch_add(1, Ordering::Relaxed);
+                        let message = "too many connections".to_string();
+                        connections.spawn(refuse(socket, ErrorCode::TooManyConnections, message));
+                        continue;
+                    };
+                    println!("New connection from {}", addr);
+                    self.counters.connections_accepted.fetch_add(1, Ordering::Relaxed);
+                    
+                    let db = Arc::clone(&self.db);
+                    let config = Arc::clone(&self.config);
+                    let shared = self.shared();
+                    let stopping = stopping.clone();
+                    connections.spawn(async move {
+                        let result = handle_connection(socket, addr, db, config, shared, stopping);
+                        if let Err(e) = result.await {
+                            eprintln!("Connection error: {}", e);
+                        }
+                        drop(permit);
+                    });
                 }
-            });
+                // Reap finished connections as they go.
+                Some(_) = connections.join_next() => {}
+            }
+        }
+        
+        drop(listener);
+        let _ = stop.send(true);
+        let drain = async { while connections.join_next().await.is_some() {} };
+        if tokio::time::timeout(self.config.drain_timeout, drain).await.is_err() {
+            let busy = connections.len();
+            eprintln!("Closing {} connections still busy after the drain timeout", busy);
+            connections.shutdown().await;
         }
+        if let Some(metrics) = metrics {
+            metrics.abort();
+            let _ = metrics.await;
+        }
+        Ok(())
     }
 }
 
-async fn handle_connection(mut socket: TcpStream, db: Arc<Database>) -> io::Result<()> {
+/// A server running in the background, from `Server::spawn`.
+pub struct ServerHandle {
+    addr: SocketAddr,
+    metrics_addr: Option<SocketAddr>,
+    stop: oneshot::Sender<()>,
+    task: JoinHandle<io::Result<()>>,
+    permits: Permits,
+    feeds: Feeds,
+}
+
+impl ServerHandle {
+    /// The address the server is listening on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+    
+    /// The address the metrics endpoint is listening on, if it is.
+    pub fn metrics_addr(&self) -> Option<SocketAddr> {
+        self.metrics_addr
+    }
+    
+    pub fn load(&self) -> ServerLoad {
+        self.permits.load(&self.feeds)
+    }
+    
+    /// Shut the server down as `Server::run_with_shutdown` does, and wait
+    /// until it has.
+    pub async fn shutdown(self) -> io::Result<()> {
+        let _ = self.stop.send(());
+        self.task.await.map_err(io::Error::other)?
+    }
+}
+
+async fn handle_connection(
+    mut socket: TcpStream,
+    peer: SocketAddr,
+    db: Arc<Database>,
+    config: Arc<ServerConfig>,
+    shared: Shared,
+    mut stopping: watch::Receiver<bool>,
+) -> io::Result<()> {
+    let Shared { permits, counters, feeds } = shared;
+    let mut authenticated = !config.auth_enabled();
+    let mut auth_failures = 0;
+    let mut txns = ConnectionTxns::new(Arc::clone(&db), config.txn_timeout);
+    // The features settled on in the handshake, once it's made.
+    let mut negotiated: Option<Vec<String>> = None;
     loop {
-        let len = match socket.read_u32().await {
-            Ok(len) => len as usize,
-            Err(_) => return Ok(()),
+        // A shutdown closes the connection between requests, never during
+        // one.
+        let len = tokio::select! {
+            len = tokio::time::timeout(config.idle_timeout, socket.read_u32_le()) => match len {
+                Ok(Ok(len)) => len,
+                Ok(Err(_)) => return Ok(()),
+                Err(_) => {
+                    println!("Closing idle connection from {}", peer);
+                    return Ok(());
+                }
+            },
+            _ = stopping.wait_for(|stop| *stop) => return Ok(()),
         };
         
-        if len == 0 || len > 10 * 1024 * 1024 {
-            return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid length"));
-        }
-        
-        let mut buf = vec![0u8; len];
-        socket.read_exact(&mut buf).await?;
+        // Past a bad frame, there's no telling where the next one starts.
+        let frame = protocol::read_frame_after_len(&mut socket, len, config.max_request_bytes);
+        let buf = match frame.await {
+            Ok(buf) => buf,
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                let code = match len as usize > config.max_request_bytes {
+                    true => ErrorCode::LimitExceeded,
+                    false => ErrorCode::InvalidArgument,
+                };
+                refuse(socket, code, e.to_string()).await;
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        };
         
+        counters.bytes_in.fetch_add(protocol::FRAME_HEADER_LEN as u64 + len as u64, Ordering::Relaxed);
         let request = Request::decode(&buf)
             .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        counters.count_request(&request);
+        // A cancel here came too late, after its stream was over.
+        if let Request::Pong | Request::Cancel = request {
+            continue;
+        }
+        let (kind, started) = (request.kind(), Instant::now());
+
+        let permit = permits.requests.acquire().await.expect("the server never closes its semaphore");
+        let response = match request {
+            Request::Hello { .. } if negotiated.is_some() => {
+                bad_request("the handshake was already made")
+            }
+            Request::Hello { .. } => {
+                let response = handle_request(&db, &config, request);
+                if let Response::Hello { features, .. } = &response {
+                    negotiated = Some(features.clone());
+                }
+                response
+            }
+            Request::Auth { user, password } if config.auth_enabled() => {
+                let users = Arc::clone(&config);
+                let valid = tokio::task::spawn_blocking(move || {
+                    check_credentials(&users.users, &user, &password)
+                })
+                .await
+                .map_err(io::Error::other)?;
+                if valid {
+                    authenticated = true;
+                    Response::Ok
+                } else {
+                    auth_failures += 1;
+                    tokio::time::sleep(AUTH_FAILURE_DELAY * auth_failures).await;
+                    if auth_failures >= config.max_auth_failures {
+                        let message = "too many failed authentications".to_string();
+                        let response = Response::Error(ErrorCode::AuthFailed, message);
+                        write_response(&mut socket, &response).await?;
+                        return Ok(());
+                    }
+                    Response::Error(ErrorCode::AuthFailed, "authentication failed".to_string())
+                }
+            }
+            _ if !authenticated => {
+                Response::Error(ErrorCode::AuthRequired, "authentication required".to_string())
+            }
+            request if config.read_only && request.is_write() => {
+                bad_request("the server is read-only")
+            }
+            request => match request.feature() {
+                Some(feature) if !negotiated.iter().flatten().any(|f| f == feature) => {
+                    let message = format!("the connection doesn't support {}", feature);
+                    Response::Error(ErrorCode::UnsupportedFeature, message)
+                }
+                _ => match request {
+                    Request::Stats => {
+                        Response::Stats(Box::new(counters.snapshot(&db, permits.load(&feeds))))
+                    }
+                    Request::Metrics => {
+                        Response::Metrics(counters.metrics(&db, permits.load(&feeds)))
+                    }
+                    Request::Watch { prefix } => {
+                        // Subscribed before the answer, so no write after
+                        // it is missed.
+                        let source = WatchSource { prefix, writes: feeds.writes.subscribe(), peer };
+                        drop(permit);
+                        let written = write_response(&mut socket, &Response::Ok).await?;
+                        counters.bytes_out.fetch_add(written as u64, Ordering::Relaxed);
+                        return push(socket, peer, source, &config, &counters, stopping).await;
+                    }
+                    Request::ReplSubscribe { from_sequence } => {
+                        // Subscribed before the WAL is read, so no record
+                        // falls between the two.
+                        let live = feeds.wal.subscribe();
+                        match db.wal_since(from_sequence) {
+                            Ok(catch_up) => {
+                                drop(permit);
+                                println!("Replica at {} following from {}", peer, from_sequence);
+                                let written = write_response(&mut socket, &Response::Ok).await?;
+                                counters.bytes_out.fetch_add(written as u64, Ordering::Relaxed);
+                                let source = ReplicationSource {
+                                    catch_up: Some(catch_up),
+                                    live,
+                                    next_sequence: from_sequence,
+                                };
+                                return push(socket, peer, source, &config, &counters, stopping).await;
+                            }
+                            Err(e) => Response::Error((&e).into(), e.to_string()),
+                        }
+                    }
+                    Request::StreamScan { start, end, reverse } => {
+                        drop(permit);
+                        let pages = ScanPages {
+                            start: start.unwrap_or_default(),
+                            end: end.unwrap_or_default(),
+                            reverse,
+                            page_size: config.max_scan_entries.max(1),
+                            done: false,
+                        };
+                        let stream = StreamedScan {
+                            socket: &mut socket,
+                            config: &config,
+                            counters: &counters,
+                        };
+                        stream.send(&db, pages, &permits.requests).await?;
+                        counters.time_request(kind, started);
+                        continue;
+                    }
+                    request if request.is_txn() => txns.handle(request),
+                    request => handle_request(&db, &config, request),
+                },
+            },
+        };
+        drop(permit);
         
-        let response = handle_request(&db, request);
-        
-        let response_data = response.encode()
-            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-        
-        socket.write_u32(response_data.len() as u32).await?;
-        socket.write_all(&response_data).await?;
+        let written = write_response(&mut socket, &response).await?;
+        counters.bytes_out.fetch_add(written as u64, Ordering::Relaxed);
+        counters.time_request(kind, started);
+    }
+}
+
+/// Serve the server's metrics over HTTP, a scrape per connection.
+async fn serve_metrics(listener: TcpListener, db: Arc<Database>, shared: Shared) {
+    let source = Arc::new((db, shared));
+    let mut scrapes = JoinSet::new();
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let socket = match accepted {
+                    Ok((socket, _)) => socket,
+                    Err(e) => {
+                        eprintln!("Metrics connection error: {}", e);
+                        continue;
+                    }
+                };
+                let source = Arc::clone(&source);
+                scrapes.spawn(async move {
+                    let (db, shared) = &*source;
+                    let metrics = || shared.counters.metrics(db, shared.permits.load(&shared.feeds));
+                    if let Err(e) = scrape(socket, metrics).await {
+                        eprintln!("Metrics scrape error: {}", e);
+                    }
+                });
+            }
+            Some(_) = scrapes.join_next() => {}
+        }
+    }
+}
+
+/// Answer one HTTP request: `metrics` for a `GET /metrics`, an error for
+/// anything else. The connection is closed after.
+async fn scrape(mut socket: TcpStream, metrics: impl FnOnce() -> String) -> io::Result<()> {
+    let mut head = Vec::new();
+    let read_head = async {
+        let mut buf = [0; 1024];
+        while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+            if head.len() > MAX_SCRAPE_HEAD {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "request head too long"));
+            }
+            let n = socket.read(&mut buf).await?;
+            if n == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            head.extend_from_slice(&buf[..n]);
+        }
+        Ok(())
+    };
+    tokio::time::timeout(SCRAPE_TIMEOUT, read_head)
+        .await
+        .map_err(|_| io::Error::from(io::ErrorKind::TimedOut))??;
+    
+    let head = String::from_utf8_lossy(&head);
+    let mut request_line = head.lines().next().unwrap_or_default().split(' ');
+    let (status, body) = match (request_line.next(), request_line.next()) {
+        (Some("GET"), Some(target)) if target.split('?').next() == Some("/metrics") => {
+            ("200 OK", metrics())
+        }
+        (Some("GET"), _) => ("404 Not Found", "not found\n".to_string()),
+        _ => ("405 Method Not Allowed", "only GET is served\n".to_string()),
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4; charset=utf-8\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    socket.write_all(response.as_bytes()).await?;
+    socket.shutdown().await
+}
+
+/// The transactions a connection has open. Only it may use them, and
+/// those still open when it closes are aborted.
+struct ConnectionTxns {
+    db: Arc<Database>,
+    timeout: Duration,
+    open: HashSet<TxnId>,
+}
+
+impl ConnectionTxns {
+    fn new(db: Arc<Database>, timeout: Duration) -> Self {
+        ConnectionTxns { db, timeout, open: HashSet::new() }
+    }
+    
+    fn handle(&mut self, request: Request) -> Response {
+        match request {
+            Request::TxnBegin => {
+                let options = TxnOptions {
+                    timeout: Some(self.timeout),
+                    ..TxnOptions::default()
+                };
+                let txn_id = self.db.begin_txn_with_options(options);
+                self.open.insert(txn_id);
+                Response::TxnBegun(txn_id)
+            }
+            Request::TxnGet { txn_id, key } => {
+                self.with(txn_id, |db| db.get_txn(txn_id, &key).map(Response::Value))
+            }
+            Request::TxnPut { txn_id, key, value } => {
+                self.with(txn_id, |db| db.put_txn(txn_id, key, value).map(|()| Response::Ok))
+            }
+            Request::TxnDelete { txn_id, key } => {
+                self.with(txn_id, |db| db.delete_txn(txn_id, key).map(|()| Response::Ok))
+            }
+            // Whether it commits or not, the transaction is over.
+            Request::TxnCommit { txn_id } => {
+                let response = self.with(txn_id, |db| db.commit_txn(txn_id).map(|()| Response::Ok));
+                self.open.remove(&txn_id);
+                response
+            }
+            Request::TxnAbort { txn_id } => {
+                let response = self.with(txn_id, |db| db.abort_txn(txn_id).map(|()| Response::Ok));
+                self.open.remove(&txn_id);
+                response
+            }
+            _ => bad_request("not a transaction request"),
+        }
+    }
+    
+    /// Run `op` on the transaction if it's one of this connection's.
+    fn with(
+        &self,
+        txn_id: TxnId,
+        op: impl FnOnce(&Database) -> middb_core::Result<Response>,
+    ) -> Response {
+        match self.open.contains(&txn_id) {
+            true => respond(op(&self.db)),
+            false => Response::Error(
+                ErrorCode::NotFound,
+                "no such transaction on this connection".to_string(),
+            ),
+        }
+    }
+}
+
+impl Drop for ConnectionTxns {
+    fn drop(&mut self) {
+        for txn_id in self.open.drain() {
+            // One that timed out is gone already.
+            let _ = self.db.abort_txn(txn_id);
+        }
+    }
+}
+
+/// What a connection given over to pushing sends: the responses for the
+/// next thing to happen, or `None` once nothing more will.
+trait PushSource {
+    fn next(&mut self) -> impl Future<Output = io::Result<Option<Vec<Response>>>> + Send;
+}
+
+/// An event for each write under a prefix. A watcher that falls too far
+/// behind misses writes rather than holding up the writers.
+struct WatchSource {
+    prefix: Vec<u8>,
+    writes: broadcast::Receiver<Arc<[WriteEvent]>>,
+    peer: SocketAddr,
+}
+
+impl PushSource for WatchSource {
+    async fn next(&mut self) -> io::Result<Option<Vec<Response>>> {
+        let written = match self.writes.recv().await {
+            Ok(written) => written,
+            Err(RecvError::Lagged(missed)) => {
+                eprintln!("Watcher at {} fell behind and missed {} writes", self.peer, missed);
+                return Ok(Some(Vec::new()));
+            }
+            Err(RecvError::Closed) => return Ok(None),
+        };
+        let events = written
+            .iter()
+            .filter(|write| write.key.starts_with(&self.prefix))
+            .map(|write| {
+                Response::Event(WatchEvent {
+                    key: write.key.clone(),
+                    kind: match write.value {
+                        Some(_) => EventKind::Put,
+                        None => EventKind::Delete,
+                    },
+                    value: write.value.clone(),
+                })
+            })
+            .collect();
+        Ok(Some(events))
+    }
+}
+
+/// The WAL from a sequence number on: what's logged already, then each
+/// record as it's logged. A replica that falls too far behind is cut off,
+/// to follow again from where it got to.
+struct ReplicationSource {
+    catch_up: Option<Vec<WalEntry>>,
+    live: broadcast::Receiver<Arc<WalEntry>>,
+    next_sequence: u64,
+}
+
+impl PushSource for ReplicationSource {
+    async fn next(&mut self) -> io::Result<Option<Vec<Response>>> {
+        if let Some(entries) = self.catch_up.take() {
+            if let Some(last) = entries.last() {
+                self.next_sequence = last.sequence_number + 1;
+            }
+            return Ok(Some(entries.iter().map(|e| Response::WalRecord(e.encode())).collect()));
+        }
+        loop {
+            match self.live.recv().await {
+                // Logged while the WAL was being read, and sent with it.
+                Ok(entry) if entry.sequence_number < self.next_sequence => continue,
+                Ok(entry) => {
+                    self.next_sequence = entry.sequence_number + 1;
+                    return Ok(Some(vec![Response::WalRecord(entry.encode())]));
+                }
+                Err(RecvError::Lagged(_)) => {
+                    return Err(io::Error::other("the replica fell too far behind"))
+                }
+                Err(RecvError::Closed) => return Ok(None),
+            }
+        }
+    }
+}
+
+/// Send what `source` yields, pinging the client while it's quiet, until
+/// the source runs dry, the client hangs up or goes idle, or the server
+/// shuts down.
+async fn push(
+    mut socket: TcpStream,
+    peer: SocketAddr,
+    mut source: impl PushSource,
+    config: &ServerConfig,
+    counters: &Counters,
+    mut stopping: watch::Receiver<bool>,
+) -> io::Result<()> {
+    let mut keepalive = config.keepalive_interval.map(|period| {
+        tokio::time::interval_at(tokio::time::Instant::now() + period, period)
+    });
+    let idle = tokio::time::sleep(config.idle_timeout);
+    // Waited on apart from the rest, so none of its borrow of the flag is
+    // held while writing.
+    let stop = async move {
+        let _ = stopping.wait_for(|stop| *stop).await;
+    };
+    tokio::pin!(idle, stop);
+    // What the client sends can only be pongs; a whole one shows it's
+    // still there. Reads are gathered here, as one may stop mid-frame.
+    let mut received = Vec::new();
+    let mut buf = [0u8; 1024];
+    loop {
+        let pushed = tokio::select! {
+            pushed = source.next() => pushed?,
+            _ = tick(&mut keepalive) => {
+                let written = write_response(&mut socket, &Response::Ping).await?;
+                counters.bytes_out.fetch_add(written as u64, Ordering::Relaxed);
+                continue;
+            }
+            read = socket.read(&mut buf) => match read {
+                Ok(n @ 1..) => {
+                    received.extend_from_slice(&buf[..n]);
+                    while let Some(len) = frame_len(&received) {
+                        if len > protocol::FRAME_HEADER_LEN + config.max_request_bytes {
+                            return Ok(());
+                        }
+                        if received.len() < len {
+                            break;
+                        }
+                        received.drain(..len);
+                        counters.bytes_in.fetch_add(len as u64, Ordering::Relaxed);
+                        idle.as_mut().reset(tokio::time::Instant::now() + config.idle_timeout);
+                    }
+                    continue;
+                }
+                _ => return Ok(()),
+            },
+            _ = &mut idle => {
+                println!("Closing idle connection from {}", peer);
+                return Ok(());
+            }
+            _ = &mut stop => return Ok(()),
+        };
+        let Some(responses) = pushed else {
+            return Ok(());
+        };
+        for response in &responses {
+            let written = write_response(&mut socket, response).await?;
+            counters.bytes_out.fetch_add(written as u64, Ordering::Relaxed);
+        }
     }
 }
 
-fn handle_request(db: &Database, request: Request) -> Response {
+/// Entries of a scan, in the order they're sent.
+type Entries = Vec<(Vec<u8>, Vec<u8>)>;
+
+/// A streamed scan's range, read a page of keys at a time. Read from the
+/// end back, it's read whole, as `scan_reverse` reads it.
+struct ScanPages {
+    start: Vec<u8>,
+    end: Vec<u8>,
+    reverse: bool,
+    page_size: usize,
+    done: bool,
+}
+
+impl ScanPages {
+    /// The next page's entries, or `None` past the end. A page can be
+    /// empty with more to follow, where all its keys were deleted.
+    fn next(&mut self, db: &Database) -> middb_core::Result<Option<Entries>> {
+        if self.done {
+            return Ok(None);
+        }
+        if self.reverse {
+            self.done = true;
+            let mut entries = db.scan_range(&self.start, &self.end)?;
+            entries.reverse();
+            return Ok(Some(entries));
+        }
+        let (entries, resume) = db.scan_range_page(&self.start, &self.end, self.page_size)?;
+        match resume {
+            Some(next) => self.start = next,
+            None => self.done = true,
+        }
+        Ok(Some(entries))
+    }
+}
+
+/// A connection sending a streamed scan.
+struct StreamedScan<'a> {
+    socket: &'a mut TcpStream,
+    config: &'a ServerConfig,
+    counters: &'a Counters,
+}
+
+impl StreamedScan<'_> {
+    /// Send what `pages` reads in chunks, as it's read, until it's all
+    /// sent or the client cancels. Each page is read under a request
+    /// permit; writing waits for the client to keep up. A failed read ends
+    /// the stream with an error in place of its last chunk.
+    async fn send(
+        mut self,
+        db: &Database,
+        mut pages: ScanPages,
+        requests: &Semaphore,
+    ) -> io::Result<()> {
+        let mut received = Vec::new();
+        let mut chunk = Vec::new();
+        let mut chunk_bytes = 0;
+        'pages: loop {
+            if self.cancelled(&mut received).await? {
+                chunk.clear();
+                break;
+            }
+            let page = {
+                let _permit = requests.acquire().await
+                    .expect("the server never closes its semaphore");
+                pages.next(db)
+            };
+            let entries = match page {
+                Ok(Some(entries)) => entries,
+                Ok(None) => break,
+                Err(e) => return self.write(&Response::Error((&e).into(), e.to_string())).await,
+            };
+            for (key, value) in entries {
+                chunk_bytes += key.len() + value.len();
+                chunk.push((key, value));
+                if chunk_bytes >= self.config.scan_chunk_bytes {
+                    self.write_chunk(std::mem::take(&mut chunk), false).await?;
+                    chunk_bytes = 0;
+                    if self.cancelled(&mut received).await? {
+                        break 'pages;
+                    }
+                }
+            }
+        }
+        self.write_chunk(chunk, true).await
+    }
+    
+    async fn write_chunk(&mut self, entries: Entries, last: bool) -> io::Result<()> {
+        self.counters.streamed_entries.fetch_add(entries.len() as u64, Ordering::Relaxed);
+        self.write(&Response::Chunk { entries, last }).await
+    }
+    
+    async fn write(&mut self, response: &Response) -> io::Result<()> {
+        let written = write_response(self.socket, response).await?;
+        self.counters.bytes_out.fetch_add(written as u64, Ordering::Relaxed);
+        Ok(())
+    }
+    
+    /// Whether the client has cancelled, judged from what it has sent so
+    /// far without waiting for more. Reads are gathered in `received`, as
+    /// one may stop mid-frame. Anything but a cancel or a pong breaks the
+    /// protocol, and ends the connection.
+    async fn cancelled(&mut self, received: &mut Vec<u8>) -> io::Result<bool> {
+        let mut buf = [0u8; 1024];
+        loop {
+            match self.socket.try_read(&mut buf) {
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(n) => received.extend_from_slice(&buf[..n]),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            }
+        }
+        while let Some(len) = frame_len(received) {
+            if len > protocol::FRAME_HEADER_LEN + self.config.max_request_bytes {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "frame too large"));
+            }
+            if received.len() < len {
+                break;
+            }
+            let frame: Vec<u8> = received.drain(..len).collect();
+            self.counters.bytes_in.fetch_add(len as u64, Ordering::Relaxed);
+            let payload = protocol::read_frame(&mut frame.as_slice(), len).await?;
+            let request = Request::decode(&payload)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            self.counters.count_request(&request);
+            match request {
+                Request::Cancel => return Ok(true),
+                Request::Pong => {}
+                _ => {
+                    let message = "only a cancel may come during a streamed scan";
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+                }
+            }
+        }
+        Ok(false)
+    }
+}
+
+/// The next tick of `interval`, or never without one.
+async fn tick(interval: &mut Option<tokio::time::Interval>) {
+    match interval {
+        Some(interval) => {
+            interval.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// The length of the frame starting `received`, header and all, once its
+/// length has arrived.
+fn frame_len(received: &[u8]) -> Option<usize> {
+    let len: [u8; 4] = received.get(..4)?.try_into().ok()?;
+    Some(protocol::FRAME_HEADER_LEN + u32::from_le_bytes(len) as usize)
+}
+
+/// Tell the client why it's being turned away, then close the connection
+/// once it has hung up or had time to read the answer. Closing at once
+/// could reset the connection before the answer is read.
+async fn refuse(mut socket: TcpStream, code: ErrorCode, message: String) {
+    if write_response(&mut socket, &Response::Error(code, message)).await.is_err() {
+        return;
+    }
+    let _ = socket.shutdown().await;
+    let mut discard = [0u8; 1024];
+    let hangup = async { while let Ok(1..) = socket.read(&mut discard).await {} };
+    let _ = tokio::time::timeout(REFUSAL_LINGER, hangup).await;
+}
+
+/// Write `response` in a frame, returning the frame's length.
+async fn write_response(socket: &mut TcpStream, response: &Response) -> io::Result<usize> {
+    let response_data = response.encode()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    let frame = protocol::encode_frame(&response_data);
+    socket.write_all(&frame).await?;
+    Ok(frame.len())
+}
+
+fn handle_request(db: &Database, config: &ServerConfig, request: Request) -> Response {
     match request {
         Request::Get { key } => {
             match db.get(&key) {
                 Ok(value) => Response::Value(value),
-                Err(e) => Response::Error(e.to_string()),
+                Err(e) => Response::Error((&e).into(), e.to_string()),
             }
         }
         Request::Put { key, value } => {
             match db.put(key, value) {
                 Ok(()) => Response::Ok,
-                Err(e) => Response::Error(e.to_string()),
+                Err(e) => Response::Error((&e).into(), e.to_string()),
             }
         }
         Request::Delete { key } => {
             match db.delete(key) {
                 Ok(()) => Response::Ok,
-                Err(e) => Response::Error(e.to_string()),
+                Err(e) => Response::Error((&e).into(), e.to_string()),
             }
         }
         Request::Ping => Response::Pong,
+        // The connection checks credentials; past it, there's nothing to do.
+        Request::Auth { .. } => Response::Ok,
+        Request::Scan { start, end, limit, reverse } => {
+            let limit = match limit {
+                0 => config.max_scan_entries,
+                n => (n as usize).min(config.max_scan_entries),
+            };
+            let start = start.unwrap_or_default();
+            let end = end.unwrap_or_default();
+            respond(match reverse {
+                false => scan_forward(db, &start, &end, limit),
+                true => scan_reverse(db, &start, &end, limit),
+            })
+        }
+        Request::Hello { protocol_version, features } => handshake(protocol_version, features),
+        // The connection answers stats; it has the server's counts.
+        Request::Stats | Request::Metrics => bad_request("stats are answered by the connection"),
+        Request::Watch { .. } => bad_request("watches are answered by the connection"),
+        Request::CompareAndSwap { key, expected, new } => {
+            respond(db.compare_and_swap(key, expected.as_deref(), new).map(|outcome| {
+                Response::CasResult {
+                    succeeded: outcome.succeeded,
+                    current: outcome.current,
+                }
+            }))
+        }
+        Request::MultiGet { keys } => {
+            if keys.len() > config.max_multi_get_keys {
+                return Response::Error(ErrorCode::LimitExceeded, format!(
+                    "multi-get of {} keys exceeds the limit of {}",
+                    keys.len(),
+                    config.max_multi_get_keys
+                ));
+            }
+            respond(multi_get(db, keys).map(|values| Response::MultiGetResult { values }))
+        }
+        Request::Pong => bad_request("pongs are taken by the connection"),
+        Request::StreamScan { .. } | Request::Cancel => {
+            bad_request("streamed scans are answered by the connection")
+        }
+        Request::ReplSubscribe { .. } => bad_request("replication is answered by the connection"),
+        Request::TxnBegin
+        | Request::TxnGet { .. }
+        | Request::TxnPut { .. }
+        | Request::TxnDelete { .. }
+        | Request::TxnCommit { .. }
+        | Request::TxnAbort { .. } => bad_request("transactions are answered by the connection"),
+        Request::Property(name) => Response::Property(db.property(&name)),
+        Request::Batch(requests) => {
+            if requests.len() > config.max_batch_size {
+                return Response::Error(ErrorCode::LimitExceeded, format!(
+                    "batch of {} requests exceeds the limit of {}",
+                    requests.len(),
+                    config.max_batch_size
+                ));
+            }
+            handle_batch(db, requests)
+        }
+    }
+}
+
+/// Answer a client's hello with this server's version and the features
+/// both support. A client newer than the server gets the server's version
+/// back, and is expected to speak it.
+fn handshake(protocol_version: u32, features: Vec<String>) -> Response {
+    if protocol_version == 0 || features.iter().any(String::is_empty) {
+        return bad_request("malformed hello");
+    }
+    if protocol_version < MIN_PROTOCOL_VERSION {
+        return Response::Error(ErrorCode::InvalidArgument, format!(
+            "protocol version {} is too old; the server needs at least {}",
+            protocol_version, MIN_PROTOCOL_VERSION
+        ));
+    }
+    let features = FEATURES
+        .iter()
+        .filter(|supported| features.iter().any(|f| f == *supported))
+        .map(|f| f.to_string())
+        .collect();
+    Response::Hello {
+        protocol_version: PROTOCOL_VERSION,
+        features,
+    }
+}
+
+/// Each key's value, all read in one read-only transaction so that they
+/// come from the same moment.
+fn multi_get(db: &Database, keys: Vec<Vec<u8>>) -> middb_core::Result<Vec<Option<Vec<u8>>>> {
+    let txn = db.begin_read_only_txn();
+    let values = keys.iter().map(|key| db.get_txn(txn, key)).collect();
+    db.commit_txn(txn)?;
+    values
+}
+
+/// Run a batch in one transaction, so its writes apply together and its
+/// gets see the writes before them. Each request gets its own response;
+/// if the commit fails, every write's response is that failure.
+fn handle_batch(db: &Database, requests: Vec<Request>) -> Response {
+    let txn = db.begin_txn();
+    let mut writes = Vec::new();
+    let mut responses: Vec<Response> = requests
+        .into_iter()
+        .enumerate()
+        .map(|(i, request)| {
+            let result = match request {
+                Request::Get { key } => return respond(db.get_txn(txn, &key).map(Response::Value)),
+                Request::Put { key, value } => db.put_txn(txn, key, value),
+                Request::Delete { key } => db.delete_txn(txn, key),
+                Request::Ping => return Response::Pong,
+                Request::Scan { .. } => {
+                    return bad_request("scans cannot be batched")
+                }
+                Request::Auth { .. } => {
+                    return bad_request("authentication cannot be batched")
+                }
+                Request::Batch(_) => {
+                    return bad_request("batches cannot be nested")
+                }
+                Request::Hello { .. } => {
+                    return bad_request("the handshake cannot be batched")
+                }
+                Request::Stats | Request::Property(_) | Request::Metrics => {
+                    return bad_request("stats cannot be batched")
+                }
+                Request::Watch { .. } => return bad_request("watches cannot be batched"),
+                Request::MultiGet { .. } => {
+                    return bad_request("multi-gets cannot be batched")
+                }
+                Request::CompareAndSwap { .. } => {
+                    return bad_request("compare-and-swaps cannot be batched")
+                }
+                Request::Pong => return bad_request("pongs cannot be batched"),
+                Request::StreamScan { .. } | Request::Cancel => {
+                    return bad_request("streamed scans cannot be batched")
+                }
+                Request::ReplSubscribe { .. } => {
+                    return bad_request("replication cannot be batched")
+                }
+                Request::TxnBegin
+                | Request::TxnGet { .. }
+                | Request::TxnPut { .. }
+                | Request::TxnDelete { .. }
+                | Request::TxnCommit { .. }
+                | Request::TxnAbort { .. } => {
+                    return bad_request("transactions cannot be batched")
+                }
+            };
+            writes.push(i);
+            respond(result.map(|()| Response::Ok))
+        })
+        .collect();
+
+    if let Err(e) = db.commit_txn(txn) {
+        for i in writes {
+            responses[i] = Response::Error((&e).into(), e.to_string());
+        }
+    }
+    Response::Batch(responses)
+}
+
+/// A page of `[start, end)` from `start` on. An empty `end` has no bound.
+fn scan_forward(
+    db: &Database,
+    start: &[u8],
+    end: &[u8],
+    limit: usize,
+) -> middb_core::Result<Response> {
+    let (entries, resume) = db.scan_range_page(start, end, limit)?;
+    Ok(Response::ScanResult {
+        entries,
+        has_more: resume.is_some(),
+        next_cursor: resume,
+    })
+}
+
+/// A page of `[start, end)` from `end` back. The range is read whole and
+/// its tail kept, so each page costs a read of everything before it.
+fn scan_reverse(
+    db: &Database,
+    start: &[u8],
+    end: &[u8],
+    limit: usize,
+) -> middb_core::Result<Response> {
+    let mut entries = db.scan_range(start, end)?;
+    let has_more = entries.len() > limit;
+    let entries: Vec<_> = entries.drain(entries.len().saturating_sub(limit)..).rev().collect();
+    // The cursor is the last key sent, which as an exclusive end resumes
+    // just below it.
+    let next_cursor = match has_more {
+        true => entries.last().map(|(key, _)| key.clone()),
+        false => None,
+    };
+    Ok(Response::ScanResult {
+        entries,
+        has_more,
+        next_cursor,
+    })
+}
+
+fn respond(result: middb_core::Result<Response>) -> Response {
+    result.unwrap_or_else(|e| Response::Error((&e).into(), e.to_string()))
+}
+
+fn bad_request(message: &str) -> Response {
+    Response::Error(ErrorCode::InvalidArgument, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::{Client, RetryPolicy};
+    use crate::metrics;
+    use crate::error::ClientError;
+    use middb_core::Config;
+    use tempfile::TempDir;
+    
+    /// Whether `response` refuses a request for needing `feature`.
+    fn unsupported(response: &Response, feature: &str) -> bool {
+        matches!(
+            response,
+            Response::Error(ErrorCode::UnsupportedFeature, message) if message.ends_with(feature)
+        )
+    }
+    
+    fn open_db(dir: &TempDir) -> Database {
+        Database::open(Config::new(dir.path())).unwrap()
+    }
+    
+    /// A server on a free local port, and its address.
+    async fn start(dir: &TempDir, config: ServerConfig) -> String {
+        start_with_db(open_db(dir), config).await
+    }
+    
+    async fn start_with_db(db: Database, config: ServerConfig) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = Server::with_config(db, addr.clone(), config);
+        tokio::spawn(async move { server.serve(listener).await });
+        addr
+    }
+    
+    /// Send `request` in a frame of its own and read the response.
+    async fn exchange(socket: &mut TcpStream, request: Request) -> Response {
+        let frame = protocol::encode_frame(&request.encode().unwrap());
+        socket.write_all(&frame).await.unwrap();
+        read_response(socket).await
+    }
+    
+    async fn read_response(socket: &mut TcpStream) -> Response {
+        let buf = protocol::read_frame(socket, usize::MAX).await.unwrap();
+        Response::decode(&buf).unwrap()
+    }
+    
+    fn hello(protocol_version: u32, features: &[&str]) -> Request {
+        let features = features.iter().map(|f| f.to_string()).collect();
+        Request::Hello { protocol_version, features }
+    }
+    
+    #[tokio::test]
+    async fn test_handshake() {
+        let dir = TempDir::new().unwrap();
+        let addr = start(&dir, ServerConfig::default()).await;
+        let client = Client::connect(&addr).await.unwrap();
+        assert_eq!(client.server_version(), PROTOCOL_VERSION);
+        assert_eq!(client.features(), FEATURES);
+        
+        // A connection that skips the handshake has only the basics.
+        let mut socket = TcpStream::connect(&addr).await.unwrap();
+        let put = Request::Put { key: b"k".to_vec(), value: b"v".to_vec() };
+        assert!(matches!(exchange(&mut socket, put).await, Response::Ok));
+        let scan = Request::Scan { start: None, end: None, limit: 0, reverse: false };
+        let response = exchange(&mut socket, scan).await;
+        assert!(unsupported(&response, "scan"));
+    }
+    
+    #[tokio::test]
+    async fn test_handshake_with_newer_client() {
+        let dir = TempDir::new().unwrap();
+        let addr = start(&dir, ServerConfig::default()).await;
+        let mut socket = TcpStream::connect(&addr).await.unwrap();
+        
+        let newer = hello(PROTOCOL_VERSION + 1, &["scan", "compression"]);
+        match exchange(&mut socket, newer).await {
+            Response::Hello { protocol_version, features } => {
+                assert_eq!(protocol_version, PROTOCOL_VERSION);
+                assert_eq!(features, ["scan"]);
+            }
+            other => panic!("expected a hello, got {:?}", other),
+        }
+        let scan = Request::Scan { start: None, end: None, limit: 0, reverse: false };
+        assert!(matches!(exchange(&mut socket, scan).await, Response::ScanResult { .. }));
+        // The client left batches out, so they're off.
+        let batch = Request::Batch(vec![Request::Ping]);
+        let response = exchange(&mut socket, batch).await;
+        assert!(unsupported(&response, "batch"));
+    }
+    
+    #[tokio::test]
+    async fn test_malformed_hello_rejected() {
+        let dir = TempDir::new().unwrap();
+        let addr = start(&dir, ServerConfig::default()).await;
+        let mut socket = TcpStream::connect(&addr).await.unwrap();
+        
+        for malformed in [hello(0, &["scan"]), hello(PROTOCOL_VERSION, &["scan", ""])] {
+            let response = exchange(&mut socket, malformed).await;
+            assert!(matches!(response, Response::Error(_, e) if e == "malformed hello"));
+        }
+        let response = exchange(&mut socket, hello(PROTOCOL_VERSION, &["batch"])).await;
+        assert!(matches!(response, Response::Hello { .. }));
+        let response = exchange(&mut socket, hello(PROTOCOL_VERSION, &["batch"])).await;
+        assert!(matches!(response, Response::Error(_, e) if e == "the handshake was already made"));
+    }
+    
+    #[tokio::test]
+    async fn test_server_too_old_for_handshake() {
+        // A server from before the handshake fails to make sense of the
+        // hello and closes the connection.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let read = socket.read(&mut [0u8; 1024]).await.unwrap();
+            assert!(read > 0);
+        });
+        
+        let err = Client::connect(&addr).await.err().unwrap();
+        assert!(matches!(&err, ClientError::ProtocolError(e) if e.starts_with("server too old")));
+    }
+    
+    #[tokio::test]
+    async fn test_pipeline_round_trip() {
+        let dir = TempDir::new().unwrap();
+        let addr = start(&dir, ServerConfig::default()).await;
+        let mut client = Client::connect(&addr).await.unwrap();
+        
+        // 400 puts, 200 deletes of some of them, then 400 gets of all.
+        let key = |i: usize| format!("key{:04}", i).into_bytes();
+        let mut pipeline = client.pipeline();
+        for i in 0..400 {
+            pipeline = pipeline.put(&key(i), format!("value{}", i).as_bytes());
+        }
+        for i in (0..400).step_by(2) {
+            pipeline = pipeline.delete(&key(i));
+        }
+        for i in 0..400 {
+            pipeline = pipeline.get(&key(i));
+        }
+        assert_eq!(pipeline.len(), 1000);
+        let responses = pipeline.execute().await.unwrap();
+        
+        assert_eq!(responses.len(), 1000);
+        assert!(responses[..600].iter().all(|r| matches!(r, Response::Ok)));
+        for (i, response) in responses[600..].iter().enumerate() {
+            match response {
+                Response::Value(None) => assert_eq!(i % 2, 0),
+                Response::Value(Some(value)) => {
+                    assert_eq!(i % 2, 1);
+                    assert_eq!(value, format!("value{}", i).as_bytes());
+                }
+                other => panic!("unexpected response {:?}", other),
+            }
+        }
+        assert_eq!(client.get(&key(1)).await.unwrap(), Some(b"value1".to_vec()));
+        assert_eq!(client.get(&key(2)).await.unwrap(), None);
+    }
+    
+    #[test]
+    fn test_batch_reports_each_failure() {
+        let dir = TempDir::new().unwrap();
+        let db = open_db(&dir);
+        let batch = Request::Batch(vec![
+            Request::Put { key: b"a".to_vec(), value: b"1".to_vec() },
+            Request::Batch(vec![Request::Ping]),
+            Request::Get { key: b"a".to_vec() },
+        ]);
+        
+        let responses = match handle_request(&db, &ServerConfig::default(), batch) {
+            Response::Batch(responses) => responses,
+            other => panic!("expected a batch, got {:?}", other),
+        };
+        assert!(matches!(responses[0], Response::Ok));
+        assert!(matches!(&responses[1], Response::Error(_, e) if e == "batches cannot be nested"));
+        assert!(matches!(&responses[2], Response::Value(Some(v)) if v == b"1"));
+        assert_eq!(db.get(&b"a".to_vec()).unwrap(), Some(b"1".to_vec()));
+    }
+    
+    #[tokio::test]
+    async fn test_oversized_batch_rejected() {
+        let dir = TempDir::new().unwrap();
+        let config = ServerConfig {
+            max_batch_size: 10,
+            ..ServerConfig::default()
+        };
+        let addr = start(&dir, config).await;
+        let mut client = Client::connect(&addr).await.unwrap();
+        
+        let mut pipeline = client.pipeline();
+        for i in 0..11u8 {
+            pipeline = pipeline.put(&[i], b"v");
+        }
+        let err = pipeline.execute().await.unwrap_err();
+        assert_eq!(err.to_string(), "batch of 11 requests exceeds the limit of 10");
+        // Nothing in the refused batch was applied.
+        assert_eq!(client.get(&[0]).await.unwrap(), None);
+    }
+    
+    fn key(i: usize) -> Vec<u8> {
+        format!("k{:02}", i).into_bytes()
+    }
+    
+    fn keys(entries: &[(Vec<u8>, Vec<u8>)]) -> Vec<Vec<u8>> {
+        entries.iter().map(|(k, _)| k.clone()).collect()
+    }
+    
+    #[tokio::test]
+    async fn test_scan_pages() {
+        let dir = TempDir::new().unwrap();
+        let config = ServerConfig {
+            max_scan_entries: 4,
+            ..ServerConfig::default()
+        };
+        let addr = start(&dir, config).await;
+        let mut client = Client::connect(&addr).await.unwrap();
+        let mut pipeline = client.pipeline();
+        for i in 0..25 {
+            pipeline = pipeline.put(&key(i), b"v");
+        }
+        pipeline.execute().await.unwrap();
+        
+        let all: Vec<_> = (0..25).map(key).collect();
+        let entries = client.scan(None, None).page_size(10).collect().await.unwrap();
+        assert_eq!(keys(&entries), all);
+        let entries = client.scan(Some(&key(3)), Some(&key(9))).collect().await.unwrap();
+        assert_eq!(keys(&entries), all[3..9]);
+        
+        let reversed: Vec<_> = all.iter().rev().cloned().collect();
+        let entries = client.scan(None, None).reverse().page_size(3).collect().await.unwrap();
+        assert_eq!(keys(&entries), reversed);
+        let entries = client.scan(Some(&key(3)), Some(&key(9))).reverse().collect().await.unwrap();
+        assert_eq!(keys(&entries), reversed[16..22]);
+        
+        for (start, end) in [(key(9), key(3)), (key(5), key(5)), (b"z".to_vec(), Vec::new())] {
+            let forward = client.scan(Some(&start), Some(&end)).collect().await.unwrap();
+            assert!(forward.is_empty());
+        }
+        let empty = client.scan(Some(b"z"), None).reverse().collect().await.unwrap();
+        assert!(empty.is_empty());
+    }
+    
+    #[test]
+    fn test_scan_limit_capped_by_server() {
+        let dir = TempDir::new().unwrap();
+        let db = open_db(&dir);
+        for i in 0..10 {
+            db.put(key(i), b"v".to_vec()).unwrap();
+        }
+        let config = ServerConfig {
+            max_scan_entries: 3,
+            ..ServerConfig::default()
+        };
+        let scan = Request::Scan { start: None, end: None, limit: 100, reverse: false };
+        match handle_request(&db, &config, scan) {
+            Response::ScanResult { entries, has_more, next_cursor } => {
+                assert_eq!(keys(&entries), [key(0), key(1), key(2)]);
+                assert!(has_more);
+                assert!(next_cursor.is_some_and(|c| c > key(2) && c < key(3)));
+            }
+            other => panic!("expected a scan result, got {:?}", other),
+        }
+    }
+    
+    #[tokio::test]
+    async fn test_scan_continues_past_inserts() {
+        let dir = TempDir::new().unwrap();
+        let addr = start(&dir, ServerConfig::default()).await;
+        let mut client = Client::connect(&addr).await.unwrap();
+        let mut writer = Client::connect(&addr).await.unwrap();
+        let mut pipeline = client.pipeline();
+        for i in (0..20).step_by(2) {
+            pipeline = pipeline.put(&key(i), b"v");
+        }
+        pipeline.execute().await.unwrap();
+        
+        let mut scan = client.scan(None, None).page_size(5);
+        let mut seen = Vec::new();
+        for _ in 0..5 {
+            seen.push(scan.next().await.unwrap().unwrap().0);
+        }
+        // One key lands behind the scan and one ahead of it.
+        writer.put(&key(3), b"v").await.unwrap();
+        writer.put(&key(15), b"v").await.unwrap();
+        while let Some((k, _)) = scan.next().await.unwrap() {
+            seen.push(k);
+        }
+        
+        let expected = [0, 2, 4, 6, 8, 10, 12, 14, 15, 16, 18].map(key);
+        assert_eq!(seen, expected);
+    }
+    
+    /// The `i`th of `fill`'s keys, which sort in numeric order.
+    fn long_key(i: usize) -> Vec<u8> {
+        format!("key:{:06}", i).into_bytes()
+    }
+    
+    /// A database at `dir` holding `n` keys, `long_key(0)` on, each with
+    /// a 100-byte value.
+    fn fill(dir: &TempDir, n: usize) -> Database {
+        // Written smallest first and flushed often, as the memtable is
+        // slow to insert far into.
+        let config = Config {
+            memtable_size: 1024 * 1024,
+            ..Config::new(dir.path())
+        };
+        let db = Database::open(config).unwrap();
+        let keys: Vec<_> = (0..n).rev().collect();
+        for batch in keys.chunks(1_000) {
+            let txn = db.begin_txn();
+            for &i in batch {
+                db.put_txn(txn, long_key(i), vec![b'v'; 100]).unwrap();
+            }
+            db.commit_txn(txn).unwrap();
+        }
+        db
+    }
+    
+    #[tokio::test]
+    async fn test_stream_scan() {
+        let dir = TempDir::new().unwrap();
+        let config = ServerConfig {
+            max_scan_entries: 100,
+            scan_chunk_bytes: 1000,
+            ..ServerConfig::default()
+        };
+        let addr = start_with_db(fill(&dir, 1000), config).await;
+        let mut client = Client::connect(&addr).await.unwrap();
+        
+        let all: Vec<_> = (0..1000).map(long_key).collect();
+        let entries = client.scan_stream(None, None).collect().await.unwrap();
+        assert_eq!(keys(&entries), all);
+        let reversed: Vec<_> = all.iter().rev().cloned().collect();
+        let entries = client.scan_stream(None, None).reverse().collect().await.unwrap();
+        assert_eq!(keys(&entries), reversed);
+        let (start, end) = (long_key(10), long_key(20));
+        let entries = client.scan_stream(Some(&start), Some(&end)).collect().await.unwrap();
+        assert_eq!(keys(&entries), &all[10..20]);
+        
+        // The entries come a chunk at a time, the last one marked.
+        let mut socket = TcpStream::connect(&addr).await.unwrap();
+        exchange(&mut socket, hello(PROTOCOL_VERSION, &["scan"])).await;
+        let scan = Request::StreamScan { start: None, end: None, reverse: false };
+        let mut response = exchange(&mut socket, scan).await;
+        let mut chunks = 1;
+        while let Response::Chunk { last: false, .. } = response {
+            response = read_response(&mut socket).await;
+            chunks += 1;
+        }
+        assert!(matches!(response, Response::Chunk { last: true, .. }));
+        assert!(chunks >= 100, "{} chunks", chunks);
+        assert_eq!(client.stats().await.unwrap().streamed_entries, 3010);
+    }
+    
+    #[tokio::test]
+    async fn test_stream_scan_cancelled() {
+        let dir = TempDir::new().unwrap();
+        // Far more than the connection can hold unread.
+        let addr = start_with_db(fill(&dir, 100_000), ServerConfig::default()).await;
+        let mut client = Client::connect(&addr).await.unwrap();
+        let mut other = Client::connect(&addr).await.unwrap();
+        
+        let mut stream = client.scan_stream(None, None);
+        let (first, _) = stream.next().await.unwrap().unwrap();
+        assert_eq!(first, long_key(0));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let streamed = other.stats().await.unwrap().streamed_entries;
+        assert!(streamed > 0 && streamed < 100_000, "{} streamed", streamed);
+        
+        // Dropped, the stream is cancelled, and the connection carries on.
+        drop(stream);
+        assert_eq!(client.get(&long_key(1)).await.unwrap(), Some(vec![b'v'; 100]));
+        let streamed = other.stats().await.unwrap().streamed_entries;
+        assert!(streamed < 100_000, "{} streamed", streamed);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(other.stats().await.unwrap().streamed_entries, streamed);
+        assert_eq!(other.stats().await.unwrap().requests.get("cancel"), Some(&1));
+    }
+    
+    #[tokio::test]
+    async fn test_auth() {
+        let dir = TempDir::new().unwrap();
+        let config = ServerConfig::default().with_user("ann", "secret");
+        let addr = start(&dir, config).await;
+        
+        let mut client = Client::connect(&addr).await.unwrap();
+        let err = client.get(b"k").await.unwrap_err();
+        assert!(matches!(err, ClientError::Server { code: ErrorCode::AuthRequired, .. }));
+        assert_eq!(err.to_string(), "authentication required");
+        
+        let err = Client::connect_with_auth(&addr, "ann", "wrong").await.err().unwrap();
+        assert!(matches!(err, ClientError::Server { code: ErrorCode::AuthFailed, .. }));
+        assert_eq!(err.to_string(), "authentication failed");
+        assert!(Client::connect_with_auth(&addr, "bob", "secret").await.is_err());
+        
+        let mut client = Client::connect_with_auth(&addr, "ann", "secret").await.unwrap();
+        client.put(b"k", b"v").await.unwrap();
+        assert_eq!(client.get(b"k").await.unwrap(), Some(b"v".to_vec()));
+    }
+    
+    #[tokio::test]
+    async fn test_repeated_auth_failures_close_connection() {
+        let dir = TempDir::new().unwrap();
+        let config = ServerConfig {
+            max_auth_failures: 2,
+            ..ServerConfig::default().with_user("ann", "secret")
+        };
+        let addr = start(&dir, config).await;
+        let mut client = Client::connect(&addr).await.unwrap();
+        let auth = |password: &str| Request::Auth {
+            user: "ann".to_string(),
+            password: password.to_string(),
+        };
+        
+        let response = client.send_request(auth("wrong")).await.unwrap();
+        assert!(matches!(response, Response::Error(_, e) if e == "authentication failed"));
+        let response = client.send_request(auth("wrong")).await.unwrap();
+        assert!(matches!(response, Response::Error(_, e) if e == "too many failed authentications"));
+        // The server hung up, so even the right password gets no answer.
+        assert!(client.send_request(auth("secret")).await.is_err());
+    }
+    
+    #[tokio::test]
+    async fn test_auth_disabled() {
+        let dir = TempDir::new().unwrap();
+        let addr = start(&dir, ServerConfig::default()).await;
+        
+        let mut client = Client::connect(&addr).await.unwrap();
+        client.put(b"k", b"v").await.unwrap();
+        let mut client = Client::connect_with_auth(&addr, "anyone", "anything").await.unwrap();
+        assert_eq!(client.get(b"k").await.unwrap(), Some(b"v".to_vec()));
+    }
+    
+    #[tokio::test]
+    async fn test_shutdown_finishes_requests_in_flight() {
+        let dir = TempDir::new().unwrap();
+        let server = Server::new(open_db(&dir), "127.0.0.1:0".to_string());
+        let handle = server.spawn().await.unwrap();
+        let addr = handle.local_addr();
+        
+        // A put whose frame arrives slowly, half before the shutdown and
+        // half after.
+        let put = Request::Put { key: b"k".to_vec(), value: b"v".to_vec() };
+        let frame = protocol::encode_frame(&put.encode().unwrap());
+        let mut socket = TcpStream::connect(addr).await.unwrap();
+        socket.write_all(&frame[..12]).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        
+        let shutdown = tokio::spawn(handle.shutdown());
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(TcpStream::connect(addr).await.is_err());
+        
+        socket.write_all(&frame[12..]).await.unwrap();
+        assert!(matches!(read_response(&mut socket).await, Response::Ok));
+        shutdown.await.unwrap().unwrap();
+        
+        let flushed = std::fs::read_dir(dir.path())
+            .unwrap()
+            .any(|entry| entry.unwrap().file_name().to_string_lossy().ends_with(".sst"));
+        assert!(flushed);
+        assert_eq!(open_db(&dir).get(&b"k".to_vec()).unwrap(), Some(b"v".to_vec()));
+    }
+    
+    #[tokio::test]
+    async fn test_shutdown_closes_stalled_connections() {
+        let dir = TempDir::new().unwrap();
+        let config = ServerConfig {
+            drain_timeout: Duration::from_millis(100),
+            ..ServerConfig::default()
+        };
+        let server = Server::with_config(open_db(&dir), "127.0.0.1:0".to_string(), config);
+        let handle = server.spawn().await.unwrap();
+        let mut idle = Client::connect(&handle.local_addr().to_string()).await.unwrap();
+        idle.ping().await.unwrap();
+        
+        // A frame that never finishes arriving holds the drain up until
+        // the timeout.
+        let mut stalled = TcpStream::connect(handle.local_addr()).await.unwrap();
+        stalled.write_u32_le(100).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        handle.shutdown().await.unwrap();
+        
+        assert!(idle.ping().await.is_err());
+        assert!(stalled.read_u32().await.is_err());
+    }
+    
+    #[tokio::test]
+    async fn test_connections_over_limit_refused() {
+        let dir = TempDir::new().unwrap();
+        let config = ServerConfig {
+            max_connections: 2,
+            ..ServerConfig::default()
+        };
+        let handle = Server::with_config(open_db(&dir), "127.0.0.1:0".to_string(), config)
+            .spawn()
+            .await
+            .unwrap();
+        let addr = handle.local_addr().to_string();
+        let mut first = Client::connect(&addr).await.unwrap();
+        let mut second = Client::connect(&addr).await.unwrap();
+        first.ping().await.unwrap();
+        second.ping().await.unwrap();
+        assert_eq!(handle.load().connections, 2);
+        
+        let err = Client::connect(&addr).await.err().unwrap();
+        assert!(matches!(err, ClientError::Server { code: ErrorCode::TooManyConnections, .. }));
+        assert_eq!(err.to_string(), "too many connections");
+        assert_eq!(handle.load().connections, 2);
+        
+        // A closed connection makes room for another.
+        drop(first);
+        while handle.load().connections > 1 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        Client::connect(&addr).await.unwrap().ping().await.unwrap();
+        second.ping().await.unwrap();
+    }
+    
+    #[tokio::test]
+    async fn test_oversized_request_refused() {
+        let dir = TempDir::new().unwrap();
+        let config = ServerConfig {
+            max_request_bytes: 1024,
+            ..ServerConfig::default()
+        };
+        let addr = start(&dir, config).await;
+        
+        // Only the length is sent; the server answers from it alone,
+        // without waiting for or making room for the rest.
+        let mut socket = TcpStream::connect(&addr).await.unwrap();
+        socket.write_u32_le(u32::MAX).await.unwrap();
+        let expected = format!("frame of {} bytes exceeds the limit of 1024", u32::MAX);
+        assert!(matches!(read_response(&mut socket).await, Response::Error(_, e) if e == expected));
+        assert!(socket.read_u32().await.is_err());
+        
+        let mut client = Client::connect(&addr).await.unwrap();
+        client.put(b"k", &[0u8; 900]).await.unwrap();
+        assert!(client.put(b"k", &[0u8; 1100]).await.is_err());
+    }
+    
+    #[tokio::test]
+    async fn test_corrupt_frame_closes_connection() {
+        let dir = TempDir::new().unwrap();
+        let addr = start(&dir, ServerConfig::default()).await;
+        let mut socket = TcpStream::connect(&addr).await.unwrap();
+        
+        let put = Request::Put { key: b"k".to_vec(), value: b"v".to_vec() };
+        let mut frame = protocol::encode_frame(&put.encode().unwrap());
+        *frame.last_mut().unwrap() ^= 0x01;
+        socket.write_all(&frame).await.unwrap();
+        let response = read_response(&mut socket).await;
+        assert!(matches!(response, Response::Error(_, e) if e == "frame checksum mismatch"));
+        assert!(socket.read_u32().await.is_err());
+        
+        let mut client = Client::connect(&addr).await.unwrap();
+        assert_eq!(client.get(b"k").await.unwrap(), None);
+    }
+    
+    #[tokio::test]
+    async fn test_many_frames_on_one_connection() {
+        let dir = TempDir::new().unwrap();
+        let addr = start(&dir, ServerConfig::default()).await;
+        let mut socket = TcpStream::connect(&addr).await.unwrap();
+        
+        // Every request sent before any response is read, so frames run
+        // together on the wire both ways.
+        let mut wire = Vec::new();
+        for i in 0..200usize {
+            let request = match i % 3 {
+                0 => Request::Put { key: key(i), value: vec![i as u8; i * 7] },
+                1 => Request::Get { key: key(i - 1) },
+                _ => Request::Ping,
+            };
+            wire.extend(protocol::encode_frame(&request.encode().unwrap()));
+        }
+        let writer = tokio::spawn(async move {
+            socket.write_all(&wire).await.unwrap();
+            socket
+        });
+        let mut socket = writer.await.unwrap();
+        for i in 0..200usize {
+            match (i % 3, read_response(&mut socket).await) {
+                (0, Response::Ok) | (2, Response::Pong) => {}
+                (1, Response::Value(Some(value))) => {
+                    assert_eq!(value, vec![(i - 1) as u8; (i - 1) * 7]);
+                }
+                (_, other) => panic!("unexpected response {:?} to request {}", other, i),
+            }
+        }
+    }
+    
+    #[tokio::test]
+    async fn test_concurrent_requests_capped() {
+        let dir = TempDir::new().unwrap();
+        let config = ServerConfig {
+            max_concurrent_requests: 1,
+            ..ServerConfig::default()
+        };
+        let server = Server::with_config(open_db(&dir), "127.0.0.1:0".to_string(), config);
+        let requests = Arc::clone(&server.permits.requests);
+        let handle = server.spawn().await.unwrap();
+        let addr = handle.local_addr().to_string();
+        let mut client = Client::connect(&addr).await.unwrap();
+        let mut other = Client::connect(&addr).await.unwrap();
+        client.ping().await.unwrap();
+        
+        // With the one permit taken, requests wait for it to come back.
+        let held = Arc::clone(&requests).acquire_owned().await.unwrap();
+        assert_eq!(handle.load().requests, 1);
+        let ping = tokio::time::timeout(Duration::from_millis(100), client.ping()).await;
+        assert!(ping.is_err());
+        
+        let ping = tokio::spawn(async move { other.ping().await });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!ping.is_finished());
+        drop(held);
+        ping.await.unwrap().unwrap();
+        assert_eq!(handle.load().requests, 0);
+    }
+    
+    #[tokio::test]
+    async fn test_stats() {
+        let dir = TempDir::new().unwrap();
+        let addr = start(&dir, ServerConfig::default()).await;
+        let mut client = Client::connect(&addr).await.unwrap();
+        let _idle = Client::connect(&addr).await.unwrap();
+        
+        client.put(b"a", b"1").await.unwrap();
+        client.put(b"b", b"2").await.unwrap();
+        client.get(b"a").await.unwrap();
+        client.delete(b"b").await.unwrap();
+        client.ping().await.unwrap();
+        client.pipeline().get(b"a").put(b"c", b"3").execute().await.unwrap();
+        
+        let stats = client.stats().await.unwrap();
+        let requests: Vec<_> = stats.requests.iter().map(|(k, v)| (k.as_str(), *v)).collect();
+        assert_eq!(
+            requests,
+            [("batch", 1), ("delete", 1), ("get", 1), ("hello", 2), ("ping", 1), ("put", 2), ("stats", 1)]
+        );
+        assert_eq!(stats.active_connections, 2);
+        assert_eq!(stats.requests_in_flight, 1);
+        assert_eq!(stats.db.memtable_entries, 3);
+        assert!(stats.uptime > Duration::ZERO);
+        assert!(stats.bytes_in > 0 && stats.bytes_out > 0);
+        
+        // The counts go on from there.
+        client.ping().await.unwrap();
+        let again = client.stats().await.unwrap();
+        assert_eq!(again.requests["ping"], 2);
+        assert_eq!(again.requests["stats"], 2);
+        assert!(again.bytes_in > stats.bytes_in);
+        assert!(again.bytes_out > stats.bytes_out);
+        assert!(again.db.sequence_number >= stats.db.sequence_number);
+    }
+    
+    /// The value of the sample written `series` in exposition `text`.
+    fn sample(text: &str, series: &str) -> f64 {
+        let line = text.lines().find(|line| line.rsplit_once(' ').unwrap().0 == series);
+        line.unwrap_or_else(|| panic!("no {} in the metrics", series))
+            .rsplit_once(' ')
+            .unwrap()
+            .1
+            .parse()
+            .unwrap()
+    }
+    
+    #[tokio::test]
+    async fn test_metrics() {
+        let dir = TempDir::new().unwrap();
+        let config = ServerConfig {
+            max_connections: 2,
+            metrics_addr: Some("127.0.0.1:0".to_string()),
+            ..ServerConfig::default()
+        };
+        let server = Server::with_config(open_db(&dir), "127.0.0.1:0".to_string(), config);
+        let handle = server.spawn().await.unwrap();
+        let addr = handle.local_addr().to_string();
+        let mut client = Client::connect(&addr).await.unwrap();
+        let _idle = Client::connect(&addr).await.unwrap();
+        let mut refused = TcpStream::connect(&addr).await.unwrap();
+        assert!(matches!(read_response(&mut refused).await, Response::Error(..)));
+        
+        client.put(b"a", b"1").await.unwrap();
+        client.put(b"b", b"2").await.unwrap();
+        client.put(b"c", b"3").await.unwrap();
+        client.get(b"a").await.unwrap();
+        client.get(b"z").await.unwrap();
+        client.delete(b"b").await.unwrap();
+        let txn = client.begin().await.unwrap();
+        txn.abort().await.unwrap();
+        
+        let text = client.metrics().await.unwrap();
+        metrics::validate(&text).unwrap();
+        assert_eq!(sample(&text, "middb_requests_total{type=\"put\"}"), 3.0);
+        assert_eq!(sample(&text, "middb_requests_total{type=\"get\"}"), 2.0);
+        assert_eq!(sample(&text, "middb_requests_total{type=\"delete\"}"), 1.0);
+        assert_eq!(sample(&text, "middb_requests_total{type=\"metrics\"}"), 1.0);
+        assert_eq!(sample(&text, "middb_request_duration_seconds_count{type=\"put\"}"), 3.0);
+        let within_5s = "middb_request_duration_seconds_bucket{type=\"get\",le=\"5\"}";
+        assert_eq!(sample(&text, within_5s), 2.0);
+        // Not timed until it's answered.
+        assert!(!text.contains("middb_request_duration_seconds_count{type=\"metrics\"}"));
+        assert_eq!(sample(&text, "middb_connections"), 2.0);
+        assert_eq!(sample(&text, "middb_connections_total{result=\"accepted\"}"), 2.0);
+        assert_eq!(sample(&text, "middb_connections_total{result=\"refused\"}"), 1.0);
+        assert_eq!(sample(&text, "middb_memtable_entries"), 3.0);
+        assert_eq!(sample(&text, "middb_txns_begun_total"), 1.0);
+        assert_eq!(sample(&text, "middb_txns_aborted_total{reason=\"explicit\"}"), 1.0);
+        
+        // The endpoint serves the same, over HTTP.
+        let mut http = TcpStream::connect(handle.metrics_addr().unwrap()).await.unwrap();
+        http.write_all(b"GET /metrics HTTP/1.1\r\nHost: middb\r\n\r\n").await.unwrap();
+        let mut reply = String::new();
+        http.read_to_string(&mut reply).await.unwrap();
+        let (head, body) = reply.split_once("\r\n\r\n").unwrap();
+        assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(head.contains(&format!("Content-Length: {}", body.len())));
+        metrics::validate(body).unwrap();
+        assert_eq!(sample(body, "middb_requests_total{type=\"put\"}"), 3.0);
+        assert_eq!(sample(body, "middb_request_duration_seconds_count{type=\"metrics\"}"), 1.0);
+        
+        let mut http = TcpStream::connect(handle.metrics_addr().unwrap()).await.unwrap();
+        http.write_all(b"GET / HTTP/1.1\r\n\r\n").await.unwrap();
+        let mut reply = String::new();
+        http.read_to_string(&mut reply).await.unwrap();
+        assert!(reply.starts_with("HTTP/1.1 404 Not Found\r\n"));
+        
+        drop((client, _idle));
+        handle.shutdown().await.unwrap();
+    }
+    
+    #[tokio::test]
+    async fn test_property() {
+        let dir = TempDir::new().unwrap();
+        let addr = start(&dir, ServerConfig::default()).await;
+        let mut client = Client::connect(&addr).await.unwrap();
+        
+        client.put(b"a", b"1").await.unwrap();
+        let entries = client.property("middb.memtable-entries").await.unwrap();
+        assert_eq!(entries.as_deref(), Some("1"));
+        assert_eq!(client.property("middb.num-sstables").await.unwrap().as_deref(), Some("0"));
+        assert_eq!(client.property("middb.no-such-thing").await.unwrap(), None);
+        
+        // Stats are an optional feature, off without the handshake.
+        let mut socket = TcpStream::connect(&addr).await.unwrap();
+        let response = exchange(&mut socket, Request::Stats).await;
+        assert!(unsupported(&response, "stats"));
+        let response = exchange(&mut socket, Request::Property("middb.num-sstables".into())).await;
+        assert!(unsupported(&response, "stats"));
+    }
+    
+    #[tokio::test]
+    async fn test_watch() {
+        let dir = TempDir::new().unwrap();
+        let addr = start(&dir, ServerConfig::default()).await;
+        let mut client = Client::connect(&addr).await.unwrap();
+        let mut watch = client.watch(b"user:").await.unwrap();
+        
+        client.put(b"user:1", b"ann").await.unwrap();
+        client.put(b"order:1", b"socks").await.unwrap();
+        client.delete(b"user:1").await.unwrap();
+        client.pipeline().put(b"user:2", b"bob").put(b"order:2", b"hat").execute().await.unwrap();
+        
+        let event = watch.next().await.unwrap();
+        assert_eq!(event.key, b"user:1");
+        assert_eq!(event.kind, EventKind::Put);
+        assert_eq!(event.value.as_deref(), Some(&b"ann"[..]));
+        let event = watch.next().await.unwrap();
+        assert_eq!((event.key, event.kind, event.value), (b"user:1".to_vec(), EventKind::Delete, None));
+        let event = watch.next().await.unwrap();
+        assert_eq!((event.key, event.value), (b"user:2".to_vec(), Some(b"bob".to_vec())));
+        
+        // Nothing else was under the prefix.
+        let next = tokio::time::timeout(Duration::from_millis(100), watch.next()).await;
+        assert!(next.is_err());
+        
+        // Watching is an optional feature, off without the handshake.
+        let mut socket = TcpStream::connect(&addr).await.unwrap();
+        let response = exchange(&mut socket, Request::Watch { prefix: Vec::new() }).await;
+        assert!(unsupported(&response, "watch"));
+    }
+    
+    #[tokio::test]
+    async fn test_multiple_watchers() {
+        let dir = TempDir::new().unwrap();
+        let addr = start(&dir, ServerConfig::default()).await;
+        let mut client = Client::connect(&addr).await.unwrap();
+        let mut everything = client.watch(b"").await.unwrap();
+        let mut a = client.watch(b"a").await.unwrap();
+        let mut b = client.watch(b"b").await.unwrap();
+        
+        client.put(b"a1", b"1").await.unwrap();
+        client.put(b"b1", b"2").await.unwrap();
+        client.put(b"a2", b"3").await.unwrap();
+        
+        for expected in [b"a1", b"b1", b"a2"] {
+            assert_eq!(everything.next().await.unwrap().key, expected);
+        }
+        assert_eq!(a.next().await.unwrap().key, b"a1");
+        assert_eq!(a.next().await.unwrap().key, b"a2");
+        assert_eq!(b.next().await.unwrap().key, b"b1");
+    }
+    
+    #[tokio::test]
+    async fn test_watcher_cleaned_up_when_connection_dies() {
+        let dir = TempDir::new().unwrap();
+        let server = Server::with_config(open_db(&dir), "127.0.0.1:0".to_string(), ServerConfig::default());
+        let handle = server.spawn().await.unwrap();
+        let addr = handle.local_addr().to_string();
+        let mut client = Client::connect(&addr).await.unwrap();
+        
+        let watch = client.watch(b"").await.unwrap();
+        let mut socket = TcpStream::connect(&addr).await.unwrap();
+        exchange(&mut socket, hello(PROTOCOL_VERSION, &["watch"])).await;
+        let response = exchange(&mut socket, Request::Watch { prefix: Vec::new() }).await;
+        assert!(matches!(response, Response::Ok));
+        assert_eq!(handle.load().watchers, 2);
+        
+        drop(watch);
+        drop(socket);
+        // The server notices the connections are gone the next time it
+        // reads from them.
+        for _ in 0..100 {
+            if handle.load().watchers == 0 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(handle.load().watchers, 0);
+        client.put(b"a", b"1").await.unwrap();
+        assert_eq!(client.get(b"a").await.unwrap().as_deref(), Some(&b"1"[..]));
+    }
+    
+    #[tokio::test]
+    async fn test_idle_connection_closed() {
+        let dir = TempDir::new().unwrap();
+        let config = ServerConfig {
+            idle_timeout: Duration::from_millis(200),
+            ..ServerConfig::default()
+        };
+        let addr = start(&dir, config).await;
+        let mut idle = TcpStream::connect(&addr).await.unwrap();
+        let mut client = Client::connect(&addr).await.unwrap();
+        let mut client = client.with_retry(RetryPolicy::none());
+        
+        for _ in 0..10 {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            client.ping().await.unwrap();
+        }
+        
+        // The idle one was closed partway through.
+        let mut buf = [0u8; 1];
+        let read = tokio::time::timeout(Duration::from_millis(100), idle.read(&mut buf)).await;
+        assert!(matches!(read, Ok(Ok(0))));
+        client.ping().await.unwrap();
+    }
+    
+    #[tokio::test]
+    async fn test_watch_kept_alive() {
+        let dir = TempDir::new().unwrap();
+        let config = ServerConfig {
+            idle_timeout: Duration::from_millis(200),
+            keepalive_interval: Some(Duration::from_millis(50)),
+            ..ServerConfig::default()
+        };
+        let addr = start(&dir, config).await;
+        let client = Client::connect(&addr).await.unwrap();
+        let mut watch = client.watch(b"").await.unwrap();
+        
+        // The write comes well past the idle timeout; the watch answers
+        // pings while it waits.
+        let writer = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            let mut client = Client::connect(&addr).await.unwrap();
+            client.put(b"a", b"1").await.unwrap();
+        });
+        let event = watch.next().await.unwrap();
+        assert_eq!(event.key, b"a");
+        writer.await.unwrap();
+    }
+    
+    #[tokio::test]
+    async fn test_watch_without_keepalives_goes_idle() {
+        let dir = TempDir::new().unwrap();
+        let config = ServerConfig {
+            idle_timeout: Duration::from_millis(200),
+            keepalive_interval: None,
+            ..ServerConfig::default()
+        };
+        let addr = start(&dir, config).await;
+        let client = Client::connect(&addr).await.unwrap();
+        let mut watch = client.watch(b"").await.unwrap();
+        
+        let next = tokio::time::timeout(Duration::from_secs(1), watch.next()).await;
+        assert!(matches!(next, Ok(Err(ClientError::ConnectionLost(_)))));
+    }
+    
+    #[tokio::test]
+    async fn test_multi_get() {
+        let dir = TempDir::new().unwrap();
+        let addr = start(&dir, ServerConfig::default()).await;
+        let mut client = Client::connect(&addr).await.unwrap();
+        client.put(b"a", b"1").await.unwrap();
+        client.put(b"c", b"3").await.unwrap();
+        
+        let keys: [&[u8]; 4] = [b"c", b"b", b"a", b"c"];
+        let values = client.multi_get(&keys).await.unwrap();
+        assert_eq!(values, [Some(b"3".to_vec()), None, Some(b"1".to_vec()), Some(b"3".to_vec())]);
+        for (key, value) in keys.iter().zip(&values) {
+            assert_eq!(&client.get(key).await.unwrap(), value);
+        }
+        
+        assert!(client.multi_get(&[]).await.unwrap().is_empty());
+    }
+    
+    #[tokio::test]
+    async fn test_multi_get_over_limit_refused() {
+        let dir = TempDir::new().unwrap();
+        let config = ServerConfig {
+            max_multi_get_keys: 2,
+            ..ServerConfig::default()
+        };
+        let addr = start(&dir, config).await;
+        let mut client = Client::connect(&addr).await.unwrap();
+        
+        let values = client.multi_get(&[b"a", b"b"]).await.unwrap();
+        assert_eq!(values, [None, None]);
+        let err = client.multi_get(&[b"a", b"b", b"c"]).await.unwrap_err();
+        assert!(matches!(err, ClientError::Server { code: ErrorCode::LimitExceeded, .. }));
+    }
+    
+    #[tokio::test]
+    async fn test_compare_and_swap() {
+        let dir = TempDir::new().unwrap();
+        let addr = start(&dir, ServerConfig::default()).await;
+        let mut client = Client::connect(&addr).await.unwrap();
+        
+        // Expecting nothing inserts only if absent.
+        let outcome = client.compare_and_swap(b"k", None, Some(b"1")).await.unwrap();
+        assert!(outcome.succeeded);
+        let outcome = client.compare_and_swap(b"k", None, Some(b"2")).await.unwrap();
+        assert!(!outcome.succeeded);
+        assert_eq!(outcome.current.as_deref(), Some(&b"1"[..]));
+        
+        // No new value deletes only if it matches.
+        let outcome = client.compare_and_swap(b"k", Some(b"2"), None).await.unwrap();
+        assert!(!outcome.succeeded);
+        assert_eq!(client.get(b"k").await.unwrap().as_deref(), Some(&b"1"[..]));
+        let outcome = client.compare_and_swap(b"k", Some(b"1"), None).await.unwrap();
+        assert!(outcome.succeeded);
+        assert_eq!(outcome.current, None);
+        assert_eq!(client.get(b"k").await.unwrap(), None);
+    }
+    
+    #[tokio::test]
+    async fn test_compare_and_swap_concurrent_increments() {
+        let dir = TempDir::new().unwrap();
+        let addr = start(&dir, ServerConfig::default()).await;
+        let mut client = Client::connect(&addr).await.unwrap();
+        client.put(b"n", b"0").await.unwrap();
+        
+        let mut workers = JoinSet::new();
+        for _ in 0..4 {
+            let addr = addr.clone();
+            workers.spawn(async move {
+                let mut client = Client::connect(&addr).await.unwrap();
+                for _ in 0..25 {
+                    let mut current = client.get(b"n").await.unwrap();
+                    loop {
+                        let n: u64 = std::str::from_utf8(current.as_deref().unwrap())
+                            .unwrap()
+                            .parse()
+                            .unwrap();
+                        let next = (n + 1).to_string();
+                        let outcome = client
+                            .compare_and_swap(b"n", current.as_deref(), Some(next.as_bytes()))
+                            .await
+                            .unwrap();
+                        if outcome.succeeded {
+                            break;
+                        }
+                        current = outcome.current;
+                    }
+                }
+            });
+        }
+        while let Some(result) = workers.join_next().await {
+            result.unwrap();
+        }
+        assert_eq!(client.get(b"n").await.unwrap().as_deref(), Some(&b"100"[..]));
+    }
+    
+    #[tokio::test]
+    async fn test_remote_txn_commit_visible() {
+        let dir = TempDir::new().unwrap();
+        let addr = start(&dir, ServerConfig::default()).await;
+        let mut client = Client::connect(&addr).await.unwrap();
+        let mut other = Client::connect(&addr).await.unwrap();
+        other.put(b"b", b"old").await.unwrap();
+        
+        let mut txn = client.begin().await.unwrap();
+        txn.put(b"a", b"1").await.unwrap();
+        txn.delete(b"b").await.unwrap();
+        assert_eq!(txn.get(b"a").await.unwrap().as_deref(), Some(&b"1"[..]));
+        assert_eq!(other.get(b"a").await.unwrap(), None);
+        assert_eq!(other.get(b"b").await.unwrap().as_deref(), Some(&b"old"[..]));
+        txn.commit().await.unwrap();
+        
+        assert_eq!(other.get(b"a").await.unwrap().as_deref(), Some(&b"1"[..]));
+        assert_eq!(other.get(b"b").await.unwrap(), None);
+        
+        let mut txn = client.begin().await.unwrap();
+        txn.put(b"c", b"3").await.unwrap();
+        txn.abort().await.unwrap();
+        assert_eq!(other.get(b"c").await.unwrap(), None);
+    }
+    
+    #[tokio::test]
+    async fn test_remote_txn_conflict() {
+        let dir = TempDir::new().unwrap();
+        let addr = start(&dir, ServerConfig::default()).await;
+        let mut client = Client::connect(&addr).await.unwrap();
+        let mut other = Client::connect(&addr).await.unwrap();
+        
+        let mut first = client.begin().await.unwrap();
+        let mut second = other.begin().await.unwrap();
+        first.put(b"k", b"1").await.unwrap();
+        second.put(b"k", b"2").await.unwrap();
+        first.commit().await.unwrap();
+        let err = second.commit().await.unwrap_err();
+        assert!(matches!(err, ClientError::Server { code: ErrorCode::TxnConflict, .. }));
+        assert_eq!(other.get(b"k").await.unwrap().as_deref(), Some(&b"1"[..]));
+    }
+    
+    #[tokio::test]
+    async fn test_remote_txn_aborted_on_disconnect() {
+        let dir = TempDir::new().unwrap();
+        let addr = start(&dir, ServerConfig::default()).await;
+        let mut client = Client::connect(&addr).await.unwrap();
+        let mut other = Client::connect(&addr).await.unwrap();
+        
+        let mut txn = client.begin().await.unwrap();
+        txn.put(b"k", b"1").await.unwrap();
+        assert_eq!(other.stats().await.unwrap().db.txn.active, 1);
+        drop(client);
+        
+        for _ in 0..100 {
+            if other.stats().await.unwrap().db.txn.active == 0 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(other.stats().await.unwrap().db.txn.active, 0);
+        assert_eq!(other.get(b"k").await.unwrap(), None);
+    }
+    
+    #[tokio::test]
+    async fn test_remote_txn_bound_to_its_connection() {
+        let dir = TempDir::new().unwrap();
+        let config = ServerConfig {
+            txn_timeout: Duration::from_millis(50),
+            ..ServerConfig::default()
+        };
+        let addr = start(&dir, config).await;
+        let mut client = Client::connect(&addr).await.unwrap();
+        let mut other = Client::connect(&addr).await.unwrap();
+        
+        let txn = client.begin().await.unwrap();
+        let txn_id = txn.id();
+        let response = other.send_request(Request::TxnGet { txn_id, key: b"k".to_vec() }).await;
+        assert!(matches!(response, Ok(Response::Error(ErrorCode::NotFound, _))));
+        let response = other.send_request(Request::TxnCommit { txn_id }).await;
+        assert!(matches!(response, Ok(Response::Error(ErrorCode::NotFound, _))));
+        
+        // Left open, the server aborts it in time.
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let err = txn.commit().await.unwrap_err();
+        assert!(matches!(err, ClientError::Server { code: ErrorCode::Timeout, .. }));
+        
+        // Transactions are an optional feature, off without the handshake.
+        let mut socket = TcpStream::connect(&addr).await.unwrap();
+        let response = exchange(&mut socket, Request::TxnBegin).await;
+        assert!(unsupported(&response, "txn"));
     }
 }
@@ -0,0 +1,111 @@
+use crate::expr::Value;
+use crate::plan::{AggExpr, AggFunc};
+use std::cmp::Ordering;
+
+/// Running state of one aggregate over a group. NULL inputs are skipped;
+/// `COUNT(*)` is fed a non-NULL marker per row.
+pub(crate) enum Accumulator {
+    Count(i64),
+    Sum(Option<Value>),
+    Avg { sum: f64, count: u64 },
+    Min(Option<Value>),
+    Max(Option<Value>),
+}
+
+impl Accumulator {
+    pub(crate) fn new(agg: &AggExpr) -> Self {
+        match agg.func {
+            AggFunc::Count => Accumulator::Count(0),
+            AggFunc::Sum => Accumulator::Sum(None),
+            AggFunc::Avg => Accumulator::Avg { sum: 0.0, count: 0 },
+            AggFunc::Min => Accumulator::Min(None),
+            AggFunc::Max => Accumulator::Max(None),
+        }
+    }
+    
+    pub(crate) fn update(&mut self, value: Value) -> Result<(), String> {
+        if value == Value::Null {
+            return Ok(());
+        }
+        match self {
+            Accumulator::Count(count) => *count += 1,
+            Accumulator::Sum(sum) => {
+                *sum = Some(match sum.take() {
+                    Some(acc) => add(&acc, &value)?,
+                    None => {
+                        to_f64(&value)?;
+                        value
+                    }
+                });
+            }
+            Accumulator::Avg { sum, count } => {
+                *sum += to_f64(&value)?;
+                *count += 1;
+            }
+            Accumulator::Min(min) => {
+                if min.as_ref().is_none_or(|m| value.compare(m) == Some(Ordering::Less)) {
+                    *min = Some(value);
+                }
+            }
+            Accumulator::Max(max) => {
+                if max.as_ref().is_none_or(|m| value.compare(m) == Some(Ordering::Greater)) {
+                    *max = Some(value);
+                }
+            }
+        }
+        Ok(())
+    }
+    
+    pub(crate) fn finish(self) -> Value {
+        match self {
+            Accumulator::Count(count) => Value::Int(count),
+            Accumulator::Sum(sum) | Accumulator::Min(sum) | Accumulator::Max(sum) => {
+                sum.unwrap_or(Value::Null)
+            }
+            Accumulator::Avg { count: 0, .. } => Value::Null,
+            Accumulator::Avg { sum, count } => Value::Float(sum / count as f64),
+        }
+    }
+}
+
+/// Sum of two numbers: exact for integers and decimals, floating point once
+/// either side is a float.
+fn add(a: &Value, b: &Value) -> Result<Value, String> {
+    let overflow = || "numeric overflow in SUM".to_string();
+    match (a, b) {
+        (Value::Int(x), Value::Int(y)) => x.checked_add(*y).map(Value::Int).ok_or_else(overflow),
+        (Value::Float(_), _) | (_, Value::Float(_)) => Ok(Value::Float(to_f64(a)? + to_f64(b)?)),
+        _ => {
+            let (x, x_scale) = as_decimal(a)?;
+            let (y, y_scale) = as_decimal(b)?;
+            let scale = x_scale.max(y_scale);
+            let lift = |v: i128, from: u8| {
+                10i128
+                    .checked_pow((scale - from) as u32)
+                    .and_then(|f| v.checked_mul(f))
+            };
+            lift(x, x_scale)
+                .zip(lift(y, y_scale))
+                .and_then(|(x, y)| x.checked_add(y))
+                .map(|value| Value::Decimal { value, scale })
+                .ok_or_else(overflow)
+        }
+    }
+}
+
+fn as_decimal(value: &Value) -> Result<(i128, u8), String> {
+    match value {
+        Value::Int(i) => Ok((*i as i128, 0)),
+        Value::Decimal { value, scale } => Ok((*value, *scale)),
+        other => Err(format!("cannot aggregate non-numeric value {:?}", other)),
+    }
+}
+
+fn to_f64(value: &Value) -> Result<f64, String> {
+    match value {
+        Value::Float(f) => Ok(*f),
+        Value::Int(i) => Ok(*i as f64),
+        Value::Decimal { value, scale } => Ok(*value as f64 / 10f64.powi(*scale as i32)),
+        other => Err(format!("cannot aggregate non-numeric value {:?}", other)),
+    }
+}
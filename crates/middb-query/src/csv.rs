@@ -0,0 +1,325 @@
+use crate::executor::{Row, Table};
+use crate::expr::Value;
+use middb_core::catalog::{Column, DataType, IdentifierCasing, TableSchema};
+use std::fmt;
+use std::io::Read;
+
+/// How a CSV file is laid out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CsvOptions {
+    /// Whether the first record names the columns. Without one, fields
+    /// follow the schema's column order.
+    pub has_header: bool,
+    pub delimiter: char,
+    /// An unquoted field reading exactly this is NULL. Quoted, it's text.
+    pub null_token: String,
+    /// Keep going past bad records and report all of them, rather than
+    /// stopping at the first.
+    pub lenient: bool,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        CsvOptions {
+            has_header: true,
+            delimiter: ',',
+            null_token: String::new(),
+            lenient: false,
+        }
+    }
+}
+
+/// A record that couldn't be imported, at its 1-based line and, when one
+/// field is to blame, that field's 1-based column.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordError {
+    pub line: usize,
+    pub column: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for RecordError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.column {
+            Some(column) => write!(f, "line {}, column {}: {}", self.line, column, self.message),
+            None => write!(f, "line {}: {}", self.line, self.message),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ImportError {
+    Io(std::io::Error),
+    /// The first bad record, or in lenient mode every one.
+    Records(Vec<RecordError>),
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportError::Io(e) => write!(f, "cannot read CSV: {}", e),
+            ImportError::Records(errors) => {
+                let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+                write!(f, "{}", errors.join("; "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+impl From<std::io::Error> for ImportError {
+    fn from(e: std::io::Error) -> Self {
+        ImportError::Io(e)
+    }
+}
+
+impl Table {
+    /// Read a table of `schema`'s shape from CSV, parsing each field as
+    /// its column's type. Header names match columns exactly. Columns the
+    /// file leaves out are left out of the rows, for defaults to fill.
+    pub fn from_csv(
+        mut reader: impl Read,
+        schema: &TableSchema,
+        opts: CsvOptions,
+    ) -> Result<Table, ImportError> {
+        let mut text = String::new();
+        reader.read_to_string(&mut text)?;
+        let mut records = Records::new(&text, opts.delimiter);
+        let mut errors = Vec::new();
+        
+        let columns: Vec<&Column> = match opts.has_header {
+            true => match records.next() {
+                Some(Ok((line, names))) => {
+                    let mut columns = Vec::with_capacity(names.len());
+                    for (i, field) in names.iter().enumerate() {
+                        match schema.find_column(&field.text, IdentifierCasing::Sensitive) {
+                            Some(column) => columns.push(column),
+                            None => {
+                                return Err(ImportError::Records(vec![RecordError {
+                                    line,
+                                    column: Some(i + 1),
+                                    message: format!(
+                                        "column '{}' not found in table '{}'",
+                                        field.text, schema.name
+                                    ),
+                                }]))
+                            }
+                        }
+                    }
+                    columns
+                }
+                Some(Err(e)) => return Err(ImportError::Records(vec![e])),
+                None => Vec::new(),
+            },
+            false => schema.columns.iter().collect(),
+        };
+        
+        let mut table = Table::new(schema.name.clone());
+        for record in records {
+            let result = record.and_then(|(line, fields)| {
+                if fields.len() != columns.len() {
+                    return Err(RecordError {
+                        line,
+                        column: None,
+                        message: format!("expected {} fields, got {}", columns.len(), fields.len()),
+                    });
+                }
+                let mut values = Vec::with_capacity(fields.len());
+                for (i, (field, column)) in fields.iter().zip(&columns).enumerate() {
+                    let value = parse_field(field, column, &opts.null_token).map_err(|message| {
+                        RecordError {
+                            line,
+                            column: Some(i + 1),
+                            message,
+                        }
+                    })?;
+                    values.push((column.name.clone(), value));
+                }
+                Ok(Row::new_with_values(values))
+            });
+            match result {
+                Ok(row) => table.add_row(row),
+                Err(e) => {
+                    errors.push(e);
+                    if !opts.lenient {
+                        break;
+                    }
+                }
+            }
+        }
+        match errors.is_empty() {
+            true => Ok(table),
+            false => Err(ImportError::Records(errors)),
+        }
+    }
+}
+
+struct Field {
+    text: String,
+    quoted: bool,
+}
+
+/// The records of a CSV text, each with the line it starts on. Quoted
+/// fields may hold delimiters, newlines and `""` for a quote. Blank lines
+/// are skipped.
+struct Records<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    delimiter: char,
+    line: usize,
+}
+
+impl<'a> Records<'a> {
+    fn new(text: &'a str, delimiter: char) -> Self {
+        Records {
+            chars: text.chars().peekable(),
+            delimiter,
+            line: 1,
+        }
+    }
+}
+
+impl Iterator for Records<'_> {
+    type Item = Result<(usize, Vec<Field>), RecordError>;
+    
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(c) = self.chars.next_if(|&c| c == '\n' || c == '\r') {
+            if c == '\n' {
+                self.line += 1;
+            }
+        }
+        self.chars.peek()?;
+        
+        let start = self.line;
+        let mut fields = Vec::new();
+        let mut field = Field {
+            text: String::new(),
+            quoted: false,
+        };
+        loop {
+            match self.chars.next() {
+                None | Some('\n') => {
+                    self.line += 1;
+                    fields.push(field);
+                    return Some(Ok((start, fields)));
+                }
+                Some('\r') if self.chars.peek() == Some(&'\n') => {}
+                Some(c) if c == self.delimiter => {
+                    let done = std::mem::replace(&mut field, Field {
+                        text: String::new(),
+                        quoted: false,
+                    });
+                    fields.push(done);
+                }
+                Some('"') if field.text.is_empty() && !field.quoted => {
+                    field.quoted = true;
+                    loop {
+                        match self.chars.next() {
+                            Some('"') if self.chars.next_if_eq(&'"').is_some() => {
+                                field.text.push('"')
+                            }
+                            Some('"') => break,
+                            Some(c) => {
+                                if c == '\n' {
+                                    self.line += 1;
+                                }
+                                field.text.push(c);
+                            }
+                            None => {
+                                return Some(Err(RecordError {
+                                    line: start,
+                                    column: Some(fields.len() + 1),
+                                    message: "unterminated quoted field".to_string(),
+                                }))
+                            }
+                        }
+                    }
+                    let next = self.chars.peek();
+                    if !matches!(next, None | Some('\n' | '\r')) && next != Some(&self.delimiter) {
+                        let column = fields.len() + 1;
+                        // Skip to the next record so lenient imports go on.
+                        while self.chars.next().is_some_and(|c| c != '\n') {}
+                        self.line += 1;
+                        return Some(Err(RecordError {
+                            line: start,
+                            column: Some(column),
+                            message: "unexpected text after a quoted field".to_string(),
+                        }));
+                    }
+                }
+                Some(c) => field.text.push(c),
+            }
+        }
+    }
+}
+
+fn parse_field(field: &Field, column: &Column, null_token: &str) -> Result<Value, String> {
+    let text = field.text.as_str();
+    if !field.quoted && text == null_token {
+        return match column.nullable {
+            true => Ok(Value::Null),
+            false => Err(format!("column '{}' is NOT NULL", column.name)),
+        };
+    }
+    let invalid = || {
+        format!("invalid {} '{}' for column '{}'", column.data_type, text, column.name)
+    };
+    let trimmed = text.trim();
+    match column.data_type {
+        DataType::Int64 => trimmed.parse().map(Value::Int).map_err(|_| invalid()),
+        DataType::Float64 => trimmed.parse().map(Value::Float).map_err(|_| invalid()),
+        DataType::Bool => match trimmed.to_ascii_lowercase().as_str() {
+            "true" => Ok(Value::Bool(true)),
+            "false" => Ok(Value::Bool(false)),
+            _ => Err(invalid()),
+        },
+        DataType::Timestamp => trimmed.parse().map(Value::Timestamp).map_err(|_| invalid()),
+        DataType::String => Ok(Value::String(text.to_string())),
+        DataType::Bytes => parse_hex(trimmed).map(Value::Bytes).ok_or_else(invalid),
+        DataType::Decimal { precision, scale } => {
+            parse_decimal(trimmed, precision, scale).ok_or_else(invalid)
+        }
+    }
+}
+
+/// Bytes written as hex digits, two per byte.
+fn parse_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) || !text.is_ascii() {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// A decimal written as `[-]digits[.digits]`, at the column's scale. More
+/// fraction digits than the scale, or more digits than the precision, don't
+/// fit.
+fn parse_decimal(text: &str, precision: u8, scale: u8) -> Option<Value> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text),
+    };
+    let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+    let all_digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+    if whole.is_empty() || !all_digits(whole) || !all_digits(fraction) {
+        return None;
+    }
+    if fraction.len() > scale as usize {
+        return None;
+    }
+    let whole = whole.trim_start_matches('0');
+    if whole.len() + scale as usize > precision as usize {
+        return None;
+    }
+    let padded = format!("{}{:0<width$}", whole, fraction, width = scale as usize);
+    let value: i128 = match padded.is_empty() {
+        true => 0,
+        false => padded.parse().ok()?,
+    };
+    Some(Value::Decimal {
+        value: if negative { -value } else { value },
+        scale,
+    })
+}
@@ -1,47 +1,571 @@
-use crate::expr::{BinaryOperator, Expr, Value};
-use crate::plan::PhysicalPlan;
-use middb_core::catalog::{Catalog, DataType, TableSchema};
+use crate::aggregate::Accumulator;
+use crate::csv::CsvOptions;
+use crate::expr::{arithmetic, decimal_to_f64, like_match, BinaryOperator, Expr, Value};
+use crate::function::{builtins, ReturnType, ScalarFunction};
+use crate::index::ColumnIndex;
+use crate::optimizer::referenced_columns;
+use crate::plan::{
+    projected_name, AggExpr, AggFunc, JoinType, LogicalPlan, NullOrder, PhysicalPlan, SortOrder,
+};
+use crate::planner::Planner;
+use crate::storage::{table_id, StoredTable, TableProvider};
+use crate::stream::{self, BoxedStream, FlatMap, Materialize, RowStream, Rows};
+use middb_core::catalog::{
+    split_qualified, Catalog, CatalogError, Column, ColumnStats, DataType, Datum,
+    IdentifierCasing, IndexDef, TableSchema, TableStats, DEFAULT_NAMESPACE,
+};
+use middb_core::Database;
+use std::borrow::Cow;
 use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::ops::Bound;
+use std::path::Path;
 use std::sync::{Arc, RwLock};
 
+/// Column names in order, each with its type if known.
+type TypedColumns = Vec<(String, Option<DataType>)>;
+
+/// For each column of a union input: its name there, the union column it
+/// becomes, and that column's type.
+type UnionShape = Vec<(String, String, Option<DataType>)>;
+
+/// The results of a predicate's subqueries, run before its first row, by
+/// the address of each subquery's plan.
+type Subqueries = HashMap<usize, SubqueryResult>;
+
 pub struct Executor {
     tables: HashMap<String, Table>,
+    /// Tables whose rows live elsewhere, under the same keys as `tables`.
+    providers: HashMap<String, Box<dyn TableProvider>>,
+    /// Built indexes, under the same keys as `tables`.
+    indexes: HashMap<String, Vec<ColumnIndex>>,
+    functions: HashMap<String, ScalarFunction>,
     catalog: Option<Arc<RwLock<Catalog>>>,
+    /// Where tables created by CREATE TABLE are stored, if not in memory.
+    db: Option<Arc<Database>>,
+    /// Copied from the catalog, whose policy never changes once created.
+    casing: IdentifierCasing,
 }
 
 impl Executor {
     pub fn new() -> Self {
         Executor {
             tables: HashMap::new(),
+            providers: HashMap::new(),
+            indexes: HashMap::new(),
+            functions: builtins(),
             catalog: None,
+            db: None,
+            casing: IdentifierCasing::Sensitive,
         }
     }
-
+    
     pub fn with_catalog(catalog: Arc<RwLock<Catalog>>) -> Self {
+        let casing = catalog.read().unwrap().identifier_casing();
         Executor {
             tables: HashMap::new(),
+            providers: HashMap::new(),
+            indexes: HashMap::new(),
+            functions: builtins(),
             catalog: Some(catalog),
+            db: None,
+            casing,
         }
     }
-
+    
+    /// An executor over `db`: its catalog, and every table in the default
+    /// namespace read and written through the database, including those
+    /// created later.
+    pub fn with_database(db: Arc<Database>) -> Result<Self, String> {
+        let mut executor = Executor::with_catalog(db.catalog());
+        for name in db.list_tables() {
+            let table = StoredTable::open(db.clone(), &name)?;
+            executor.register_provider(&name, Box::new(table));
+        }
+        executor.db = Some(db);
+        Ok(executor)
+    }
+    
     pub fn set_catalog(&mut self, catalog: Arc<RwLock<Catalog>>) {
+        self.casing = catalog.read().unwrap().identifier_casing();
         self.catalog = Some(catalog);
     }
-
+    
+    /// Register an in-memory table. `name` may be qualified as
+    /// `namespace.table`; unqualified names land in the default namespace.
     pub fn register_table(&mut self, name: String, table: Table) {
-        self.tables.insert(name, table);
+        let key = self.table_key(&name);
+        self.tables.insert(key.clone(), table);
+        self.rebuild_indexes(&key);
     }
-
+    
+    /// Read and write the table `name` through `provider`, in place of any
+    /// rows held for it in memory.
+    pub fn register_provider(&mut self, name: &str, provider: Box<dyn TableProvider>) {
+        let key = self.table_key(name);
+        self.tables.remove(&key);
+        self.indexes.remove(&key);
+        self.providers.insert(key, provider);
+    }
+    
+    /// Make `function` callable from expressions as `name`, in any case,
+    /// replacing any function already called that.
+    pub fn register_function(&mut self, name: &str, function: ScalarFunction) {
+        self.functions.insert(name.to_ascii_uppercase(), function);
+    }
+    
+    fn function(&self, name: &str) -> Option<&ScalarFunction> {
+        self.functions.get(&name.to_ascii_uppercase())
+    }
+    
+    /// Build an index over a table's rows and keep it up to date as rows
+    /// change. With a catalog, the index is also recorded there, so the
+    /// planner can choose it; an index the catalog already has is just
+    /// built.
+    pub fn create_index(&mut self, def: IndexDef) -> Result<(), String> {
+        let def = match &self.catalog {
+            Some(catalog) => {
+                let mut catalog = catalog.write().unwrap();
+                let existing = catalog
+                    .indexes_for_table(&def.table)
+                    .iter()
+                    .find(|i| self.casing.matches(&i.name, &def.name))
+                    .cloned();
+                match existing {
+                    Some(existing) => existing,
+                    None => {
+                        catalog.create_index(def.clone()).map_err(|e| e.to_string())?;
+                        catalog
+                            .indexes_for_table(&def.table)
+                            .iter()
+                            .find(|i| self.casing.matches(&i.name, &def.name))
+                            .cloned()
+                            .expect("index was just created")
+                    }
+                }
+            }
+            None => def,
+        };
+        if def.columns.is_empty() {
+            return Err(format!("index '{}' has no columns", def.name));
+        }
+        let key = self.table_key(&def.table);
+        // Stored tables are indexed afresh for each lookup.
+        if self.providers.contains_key(&key) {
+            return Ok(());
+        }
+        let rows = self.tables.get(&key).map(|t| t.rows.as_slice()).unwrap_or(&[]);
+        let index = ColumnIndex::build(def, rows, self.casing);
+        let indexes = self.indexes.entry(key).or_default();
+        indexes.retain(|i| !self.casing.matches(&i.def.name, &index.def.name));
+        indexes.push(index);
+        Ok(())
+    }
+    
+    /// Rebuild the indexes of a table whose rows were replaced wholesale.
+    fn rebuild_indexes(&mut self, key: &str) {
+        let indexes = match self.indexes.get_mut(key) {
+            Some(indexes) => indexes,
+            None => return,
+        };
+        let rows = self.tables.get(key).map(|t| t.rows.as_slice()).unwrap_or(&[]);
+        for index in indexes.iter_mut() {
+            *index = ColumnIndex::build(index.def.clone(), rows, self.casing);
+        }
+    }
+    
+    /// Registry key: the name qualified with its namespace, then folded.
+    fn table_key(&self, name: &str) -> String {
+        table_id(name, self.casing)
+    }
+    
+    fn table(&self, name: &str) -> Option<&Table> {
+        self.tables.get(&self.table_key(name))
+    }
+    
+    /// Append a row to a table, enforcing the primary key and unique
+    /// constraints recorded in the catalog. Omitted columns take their
+    /// default, or NULL if nullable. A table known only to the catalog
+    /// starts out empty.
+    pub fn insert(&mut self, table_name: &str, row: Row) -> Result<(), String> {
+        self.insert_rows(table_name, vec![row])
+    }
+    
+    /// Append rows as `insert` does: all of them, or none if any fails.
+    fn insert_rows(&mut self, table_name: &str, mut rows: Vec<Row>) -> Result<(), String> {
+        let schema = self
+            .catalog
+            .as_ref()
+            .and_then(|c| c.read().unwrap().get_table(table_name).cloned());
+        if let Some(schema) = &schema {
+            for row in rows.iter_mut() {
+                self.fill_defaults(schema, row)?;
+            }
+        }
+        let key = self.table_key(table_name);
+        let casing = self.casing;
+        
+        if let Some(provider) = self.providers.get(&key) {
+            let mut existing = provider.scan()?;
+            let start = existing.len();
+            for row in rows {
+                append_checked(&mut existing, row, table_name, schema.as_ref(), casing)?;
+            }
+            return provider.write(&[], &existing[start..]);
+        }
+        
+        if let Some(schema) = &schema {
+            self.tables
+                .entry(key.clone())
+                .or_insert_with(|| Table::new(schema.name.clone()));
+        }
+        let table = self.tables.get_mut(&key)
+            .ok_or_else(|| format!("Table not found: {}", table_name))?;
+        let start = table.rows.len();
+        for row in rows {
+            if let Err(e) = append_checked(&mut table.rows, row, table_name, schema.as_ref(), casing) {
+                table.rows.truncate(start);
+                return Err(e);
+            }
+        }
+        for index in self.indexes.get_mut(&key).into_iter().flatten() {
+            for (position, row) in table.rows.iter().enumerate().skip(start) {
+                index.insert(row, position, casing);
+            }
+        }
+        Ok(())
+    }
+    
+    /// Insert the rows of the CSV file at `path`, which has a header
+    /// naming the columns, into a table the catalog knows. Either every
+    /// row goes in or none does. Returns how many rows there were.
+    pub fn import_csv(&mut self, table_name: &str, path: impl AsRef<Path>) -> Result<usize, String> {
+        let schema = self
+            .catalog
+            .as_ref()
+            .and_then(|c| c.read().unwrap().get_table(table_name).cloned())
+            .ok_or_else(|| format!("table not found: {}", table_name))?;
+        let file = File::open(path).map_err(|e| format!("cannot read CSV: {}", e))?;
+        let table = Table::from_csv(file, &schema, CsvOptions::default()).map_err(|e| e.to_string())?;
+        let count = table.rows.len();
+        self.insert_rows(table_name, table.rows)?;
+        Ok(count)
+    }
+    
+    /// Run a plan of any kind. Queries return their rows; INSERT, UPDATE
+    /// and DELETE return how many rows they touched, and change nothing if
+    /// they fail.
+    pub fn run(&mut self, plan: PhysicalPlan) -> Result<ExecutionResult, String> {
+        self.validate_plan(&plan)?;
+        
+        match plan {
+            PhysicalPlan::Insert { table, rows } => {
+                let count = rows.len();
+                self.insert_rows(&table, rows)?;
+                Ok(ExecutionResult::Count(count))
+            }
+            PhysicalPlan::Update { table, assignments, filter } => {
+                let subqueries = self.run_subqueries(filter.as_ref())?;
+                let mut rows = self.scan_rows(&table)?;
+                let (mut removed, mut changed) = (Vec::new(), Vec::new());
+                for (i, row) in rows.iter_mut().enumerate() {
+                    if filter.as_ref().is_some_and(|f| !self.eval_predicate(f, row, &subqueries)) {
+                        continue;
+                    }
+                    removed.push(row.clone());
+                    let mut values = Vec::with_capacity(assignments.len());
+                    for (column, expr) in &assignments {
+                        let value = self.eval_expr(expr, row).ok_or_else(|| {
+                            format!("cannot evaluate {} for column '{}'", expr, column)
+                        })?;
+                        values.push((column, value));
+                    }
+                    for (column, value) in values {
+                        let name = row
+                            .columns
+                            .keys()
+                            .find(|c| self.casing.matches(c, column))
+                            .cloned()
+                            .unwrap_or_else(|| column.clone());
+                        row.columns.insert(name, value);
+                    }
+                    changed.push(i);
+                }
+                self.check_table(&table, &rows)?;
+                let written: Vec<Row> = changed.iter().map(|&i| rows[i].clone()).collect();
+                self.write_rows(&table, rows, &removed, &written)?;
+                Ok(ExecutionResult::Count(changed.len()))
+            }
+            PhysicalPlan::Delete { table, filter } => {
+                let subqueries = self.run_subqueries(filter.as_ref())?;
+                let (removed, kept): (Vec<Row>, Vec<Row>) =
+                    self.scan_rows(&table)?.into_iter().partition(|row| {
+                        filter.as_ref().is_none_or(|f| self.eval_predicate(f, row, &subqueries))
+                    });
+                self.write_rows(&table, kept, &removed, &[])?;
+                Ok(ExecutionResult::Count(removed.len()))
+            }
+            PhysicalPlan::CreateTable { schema, if_not_exists } => {
+                self.create_table(schema, if_not_exists)?;
+                Ok(ExecutionResult::Done)
+            }
+            PhysicalPlan::DropTable { name, if_exists } => {
+                self.drop_table(&name, if_exists)?;
+                Ok(ExecutionResult::Done)
+            }
+            query => self.execute(query).map(ExecutionResult::Rows),
+        }
+    }
+    
+    fn ddl_catalog(&self, statement: &str) -> Result<Arc<RwLock<Catalog>>, String> {
+        self.catalog
+            .clone()
+            .ok_or_else(|| format!("{} needs an executor with a catalog", statement))
+    }
+    
+    /// Record `schema` in the catalog and start the table empty: in the
+    /// database if the executor has one, else in memory.
+    fn create_table(&mut self, schema: TableSchema, if_not_exists: bool) -> Result<(), String> {
+        let catalog = self.ddl_catalog("CREATE TABLE")?;
+        let name = schema.name.clone();
+        if catalog.read().unwrap().table_exists(&name) {
+            return match if_not_exists {
+                true => Ok(()),
+                false => Err(CatalogError::TableAlreadyExists(name).to_string()),
+            };
+        }
+        match self.db.clone() {
+            Some(db) => {
+                db.create_table(schema).map_err(|e| e.to_string())?;
+                let table = StoredTable::open(db, &name)?;
+                self.register_provider(&name, Box::new(table));
+            }
+            None => {
+                let (namespace, table) = split_qualified(&name);
+                let mut schema = schema;
+                schema.name = table.to_string();
+                catalog
+                    .write()
+                    .unwrap()
+                    .register_table_in(namespace.unwrap_or(DEFAULT_NAMESPACE), schema)
+                    .map_err(|e| e.to_string())?;
+                self.register_table(name.clone(), Table::new(name));
+            }
+        }
+        Ok(())
+    }
+    
+    /// Remove a table's rows, indexes and schema.
+    fn drop_table(&mut self, name: &str, if_exists: bool) -> Result<(), String> {
+        let catalog = self.ddl_catalog("DROP TABLE")?;
+        if !catalog.read().unwrap().table_exists(name) {
+            return match if_exists {
+                true => Ok(()),
+                false => Err(CatalogError::TableNotFound(name.to_string()).to_string()),
+            };
+        }
+        let key = self.table_key(name);
+        if let Some(provider) = self.providers.get(&key) {
+            provider.truncate()?;
+        }
+        match &self.db {
+            Some(db) => db.drop_table(name).map(drop),
+            None => catalog.write().unwrap().drop_table(name).map(drop),
+        }
+        .map_err(|e| e.to_string())?;
+        self.providers.remove(&key);
+        self.tables.remove(&key);
+        self.indexes.remove(&key);
+        Ok(())
+    }
+    
+    /// Check every row of a rewritten table against its schema and its
+    /// primary key and unique constraints.
+    fn check_table(&self, table_name: &str, rows: &[Row]) -> Result<(), String> {
+        let catalog = match &self.catalog {
+            Some(c) => c.read().unwrap(),
+            None => return Ok(()),
+        };
+        let schema = match catalog.get_table(table_name) {
+            Some(schema) => schema,
+            None => return Ok(()),
+        };
+        for row in rows {
+            check_row(schema, row, self.casing)?;
+        }
+        let unique = schema.unique_constraints().iter().map(Vec::as_slice);
+        let keys = schema.primary_key().into_iter().chain(unique);
+        for cols in keys {
+            let mut seen = HashSet::new();
+            for row in rows {
+                let key: Vec<Value> = cols
+                    .iter()
+                    .map(|c| row.find_column(c, self.casing).unwrap_or(Value::Null))
+                    .collect();
+                if key.contains(&Value::Null) {
+                    continue;
+                }
+                if !seen.insert(key.into_iter().map(Datum::from).collect::<Vec<_>>()) {
+                    return Err(format!(
+                        "constraint violation: duplicate key ({}) in '{}'",
+                        cols.join(", "),
+                        table_name
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+    
+    /// Store a changed table: `rows` as it now stands, or for a table with
+    /// a provider, the rows `removed` and `written` to get there.
+    fn write_rows(
+        &mut self,
+        table_name: &str,
+        rows: Vec<Row>,
+        removed: &[Row],
+        written: &[Row],
+    ) -> Result<(), String> {
+        match self.providers.get(&self.table_key(table_name)) {
+            Some(provider) => provider.write(removed, written),
+            None => {
+                self.replace_rows(table_name, rows);
+                Ok(())
+            }
+        }
+    }
+    
+    fn replace_rows(&mut self, table_name: &str, rows: Vec<Row>) {
+        let key = self.table_key(table_name);
+        self.tables
+            .entry(key.clone())
+            .or_insert_with(|| Table::new(table_name.to_string()))
+            .rows = rows;
+        self.rebuild_indexes(&key);
+    }
+    
+    /// Scan a table and compute its statistics. If the catalog knows the
+    /// table, the result is also recorded there for the planner.
+    pub fn analyze(&self, table_name: &str) -> Result<TableStats, String> {
+        let table = Table {
+            name: table_name.to_string(),
+            rows: match self.providers.get(&self.table_key(table_name)) {
+                Some(provider) => provider.scan()?,
+                None => match self.table(table_name) {
+                    Some(table) => table.rows.clone(),
+                    None => return Err(format!("Table not found: {}", table_name)),
+                },
+            },
+        };
+        
+        let mut columns: Vec<String> = self
+            .catalog
+            .as_ref()
+            .and_then(|c| {
+                c.read().unwrap().get_table(table_name).map(|s| {
+                    s.column_names().into_iter().map(String::from).collect()
+                })
+            })
+            .unwrap_or_default();
+        for row in &table.rows {
+            for name in row.columns.keys() {
+                if !columns.contains(name) {
+                    columns.push(name.clone());
+                }
+            }
+        }
+        
+        let mut stats = TableStats {
+            row_count: table.rows.len() as u64,
+            total_bytes: table.rows.iter().map(Self::row_size).sum(),
+            column_stats: HashMap::new(),
+        };
+        for name in columns {
+            let mut col = ColumnStats::default();
+            let mut distinct = HashSet::new();
+            let mut min: Option<&Value> = None;
+            let mut max: Option<&Value> = None;
+            for row in &table.rows {
+                let value = match row.columns.get(&name) {
+                    None | Some(Value::Null) => {
+                        col.null_count += 1;
+                        continue;
+                    }
+                    Some(v) => v,
+                };
+                distinct.insert(Datum::from(value.clone()));
+                if min.is_none_or(|m| value.compare(m) == Some(Ordering::Less)) {
+                    min = Some(value);
+                }
+                if max.is_none_or(|m| value.compare(m) == Some(Ordering::Greater)) {
+                    max = Some(value);
+                }
+            }
+            col.distinct_count = distinct.len() as u64;
+            col.min = min.cloned().map(Datum::from);
+            col.max = max.cloned().map(Datum::from);
+            stats.column_stats.insert(name, col);
+        }
+        
+        if let Some(catalog) = &self.catalog {
+            let mut catalog = catalog.write().unwrap();
+            if catalog.table_exists(table_name) {
+                catalog
+                    .update_stats(table_name, stats.clone())
+                    .map_err(|e| e.to_string())?;
+            }
+        }
+        Ok(stats)
+    }
+    
+    /// Approximate in-memory payload size of a row, used for `total_bytes`.
+    fn row_size(row: &Row) -> u64 {
+        row.columns
+            .iter()
+            .map(|(name, value)| {
+                let payload = match value {
+                    Value::Int(_) | Value::Float(_) | Value::Timestamp(_) => 8,
+                    Value::Decimal { .. } => 16,
+                    Value::Bool(_) => 1,
+                    Value::String(s) => s.len(),
+                    Value::Bytes(b) => b.len(),
+                    Value::Null => 0,
+                };
+                (name.len() + payload) as u64
+            })
+            .sum()
+    }
+    
+    fn fill_defaults(&self, schema: &TableSchema, row: &mut Row) -> Result<(), String> {
+        for col in &schema.columns {
+            if row.find_column(&col.name, self.casing).is_some() {
+                continue;
+            }
+            let value = match &col.default {
+                Some(default) => Value::from(default.clone()),
+                None if col.nullable => Value::Null,
+                None => {
+                    return Err(format!(
+                        "column '{}' of '{}' is non-nullable and has no default",
+                        col.name, schema.name
+                    ))
+                }
+            };
+            row.columns.insert(col.name.clone(), value);
+        }
+        Ok(())
+    }
+    
     pub fn validate_plan(&self, plan: &PhysicalPlan) -> Result<(), String> {
         let catalog = match &self.catalog {
             Some(c) => c.read().unwrap(),
             None => return Ok(()),
         };
-
+        
         match plan {
             PhysicalPlan::SeqScan { table, filter } => {
-                if !catalog.table_exists(table) && !self.tables.contains_key(table) {
+                if !catalog.table_exists(table) && self.table(table).is_none() {
                     return Err(format!("table not found: {}", table));
                 }
                 if let Some(expr) = filter {
@@ -51,47 +575,383 @@ impl Executor {
                 }
                 Ok(())
             }
+            PhysicalPlan::IndexScan { table, index, .. } => {
+                if !catalog.table_exists(table) && self.table(table).is_none() {
+                    return Err(format!("table not found: {}", table));
+                }
+                if self.index_def(table, index, &catalog).is_none() {
+                    return Err(format!("index not found: {} on {}", index, table));
+                }
+                Ok(())
+            }
             PhysicalPlan::Filter { input, predicate } => {
                 self.validate_plan(input)?;
-                if let Some(table_name) = self.get_table_name(input) {
-                    if let Some(schema) = catalog.get_table(&table_name) {
-                        self.validate_expr(predicate, schema)?;
+                self.validate_input_expr(predicate, input, &catalog)
+            }
+            PhysicalPlan::Project { input, columns } => {
+                self.validate_plan(input)?;
+                let mut names: Vec<String> = Vec::with_capacity(columns.len());
+                for (expr, alias) in columns {
+                    self.validate_input_expr(expr, input, &catalog)?;
+                    let name = projected_name(expr, alias.as_deref());
+                    if names.iter().any(|n| self.casing.matches(n, &name)) {
+                        return Err(format!("duplicate output column '{}'", name));
                     }
+                    names.push(name);
                 }
                 Ok(())
             }
-            PhysicalPlan::Project { input, columns } => {
+            PhysicalPlan::Sort { input, keys, .. } => {
                 self.validate_plan(input)?;
-                if let Some(table_name) = self.get_table_name(input) {
-                    if let Some(schema) = catalog.get_table(&table_name) {
-                        for col in columns {
-                            if schema.get_column(col).is_none() {
-                                return Err(format!(
-                                    "column '{}' not found in table '{}'",
-                                    col, table_name
-                                ));
-                            }
+                for (key, _) in keys {
+                    self.validate_input_expr(&Expr::Column(key.clone()), input, &catalog)?;
+                }
+                Ok(())
+            }
+            PhysicalPlan::HashAggregate { input, group_by, aggregates } => {
+                self.validate_plan(input)?;
+                for col in group_by {
+                    self.validate_input_expr(&Expr::Column(col.clone()), input, &catalog)?;
+                }
+                let schema = match self.output_columns(input, &catalog) {
+                    Some(_) => None,
+                    None => self
+                        .get_table_name(input)
+                        .and_then(|table_name| catalog.get_table(&table_name)),
+                };
+                for agg in aggregates {
+                    let col = match &agg.column {
+                        Some(col) => col,
+                        None => continue,
+                    };
+                    self.validate_input_expr(&Expr::Column(col.clone()), input, &catalog)?;
+                    let data_type = schema
+                        .and_then(|s| s.find_column(col, self.casing))
+                        .map(|c| c.data_type);
+                    if let (Some(schema), Some(data_type)) = (schema, data_type) {
+                        if matches!(agg.func, AggFunc::Sum | AggFunc::Avg) && !data_type.is_numeric() {
+                            return Err(format!(
+                                "{} requires a numeric column, but '{}' of '{}' is {}",
+                                agg.func, col, schema.name, data_type
+                            ));
                         }
                     }
                 }
                 Ok(())
             }
+            PhysicalPlan::HashDistinct { input } | PhysicalPlan::Limit { input, .. } => {
+                self.validate_plan(input)
+            }
+            PhysicalPlan::Union { inputs } => {
+                for input in inputs {
+                    self.validate_plan(input)?;
+                }
+                self.union_columns(inputs, &catalog).map(|_| ())
+            }
+            PhysicalPlan::NestedLoopJoin { left, right, on, .. } => {
+                self.validate_plan(left)?;
+                self.validate_plan(right)?;
+                match self.output_columns(plan, &catalog) {
+                    Some(columns) => self.validate_output_columns(on, &columns),
+                    None => Ok(()),
+                }
+            }
+            PhysicalPlan::HashJoin { build, probe, build_key, probe_key, .. } => {
+                self.validate_plan(build)?;
+                self.validate_plan(probe)?;
+                if let Some(columns) = self.output_columns(plan, &catalog) {
+                    for key in [build_key, probe_key] {
+                        self.validate_output_columns(&Expr::Column(key.clone()), &columns)?;
+                    }
+                }
+                Ok(())
+            }
+            PhysicalPlan::Insert { table, .. } => {
+                if !catalog.table_exists(table) && self.table(table).is_none() {
+                    return Err(format!("table not found: {}", table));
+                }
+                Ok(())
+            }
+            PhysicalPlan::Update { table, assignments, filter } => {
+                if !catalog.table_exists(table) && self.table(table).is_none() {
+                    return Err(format!("table not found: {}", table));
+                }
+                if let Some(schema) = catalog.get_table(table) {
+                    for (column, expr) in assignments {
+                        self.validate_expr(&Expr::Column(column.clone()), schema)?;
+                        self.validate_expr(expr, schema)?;
+                    }
+                    if let Some(expr) = filter {
+                        self.validate_expr(expr, schema)?;
+                    }
+                }
+                Ok(())
+            }
+            PhysicalPlan::Delete { table, filter } => self.validate_plan(&PhysicalPlan::SeqScan {
+                table: table.clone(),
+                filter: filter.clone(),
+            }),
+            // Whether the table exists is checked when these run, since
+            // IF [NOT] EXISTS may make it not matter.
+            PhysicalPlan::CreateTable { .. } | PhysicalPlan::DropTable { .. } => Ok(()),
         }
     }
-
+    
     fn get_table_name(&self, plan: &PhysicalPlan) -> Option<String> {
         match plan {
-            PhysicalPlan::SeqScan { table, .. } => Some(table.clone()),
+            PhysicalPlan::SeqScan { table, .. } | PhysicalPlan::IndexScan { table, .. } => {
+                Some(table.clone())
+            }
             PhysicalPlan::Filter { input, .. } => self.get_table_name(input),
             PhysicalPlan::Project { input, .. } => self.get_table_name(input),
+            PhysicalPlan::Sort { input, .. } => self.get_table_name(input),
+            PhysicalPlan::HashAggregate { input, .. } => self.get_table_name(input),
+            PhysicalPlan::HashDistinct { input } => self.get_table_name(input),
+            PhysicalPlan::Limit { input, .. } => self.get_table_name(input),
+            PhysicalPlan::Union { inputs } => self.get_table_name(inputs.first()?),
+            PhysicalPlan::NestedLoopJoin { .. } | PhysicalPlan::HashJoin { .. } => None,
+            PhysicalPlan::Insert { table, .. }
+            | PhysicalPlan::Update { table, .. }
+            | PhysicalPlan::Delete { table, .. } => Some(table.clone()),
+            PhysicalPlan::CreateTable { schema, .. } => Some(schema.name.clone()),
+            PhysicalPlan::DropTable { name, .. } => Some(name.clone()),
         }
     }
-
+    
+    /// The columns `plan` produces, when they aren't its table's. `None`
+    /// for a join with a side whose columns aren't known.
+    fn output_columns(&self, plan: &PhysicalPlan, catalog: &Catalog) -> Option<Vec<String>> {
+        match plan {
+            PhysicalPlan::SeqScan { .. }
+            | PhysicalPlan::IndexScan { .. }
+            | PhysicalPlan::Insert { .. }
+            | PhysicalPlan::Update { .. }
+            | PhysicalPlan::Delete { .. }
+            | PhysicalPlan::CreateTable { .. }
+            | PhysicalPlan::DropTable { .. } => None,
+            PhysicalPlan::Filter { input, .. }
+            | PhysicalPlan::Sort { input, .. }
+            | PhysicalPlan::HashDistinct { input }
+            | PhysicalPlan::Limit { input, .. } => self.output_columns(input, catalog),
+            PhysicalPlan::Union { inputs } => self.output_columns(inputs.first()?, catalog),
+            PhysicalPlan::Project { columns, .. } => Some(
+                columns
+                    .iter()
+                    .map(|(expr, alias)| projected_name(expr, alias.as_deref()))
+                    .collect(),
+            ),
+            PhysicalPlan::HashAggregate { group_by, aggregates, .. } => Some(
+                group_by
+                    .iter()
+                    .cloned()
+                    .chain(aggregates.iter().map(AggExpr::output_name))
+                    .collect(),
+            ),
+            PhysicalPlan::NestedLoopJoin { left, right, left_name, right_name, .. }
+            | PhysicalPlan::HashJoin {
+                build: left,
+                probe: right,
+                build_name: left_name,
+                probe_name: right_name,
+                ..
+            } => {
+                let mut columns = Vec::new();
+                for (side, name) in [(left, left_name), (right, right_name)] {
+                    let side_columns = self.output_columns(side, catalog).or_else(|| {
+                        let table_name = self.get_table_name(side)?;
+                        let schema = catalog.get_table(&table_name)?;
+                        Some(schema.column_names().into_iter().map(String::from).collect())
+                    })?;
+                    columns.extend(side_columns.iter().map(|c| qualify(name, c)));
+                }
+                Some(columns)
+            }
+        }
+    }
+    
+    /// The columns `plan` produces in order, with their types where they
+    /// can be told. `None` if the order can't, as for a table the catalog
+    /// doesn't know.
+    fn typed_columns(&self, plan: &PhysicalPlan, catalog: &Catalog) -> Option<TypedColumns> {
+        match plan {
+            PhysicalPlan::SeqScan { table, .. } | PhysicalPlan::IndexScan { table, .. } => {
+                let schema = catalog.get_table(table)?;
+                Some(
+                    schema
+                        .columns
+                        .iter()
+                        .map(|c| (c.name.clone(), Some(c.data_type)))
+                        .collect(),
+                )
+            }
+            PhysicalPlan::Filter { input, .. }
+            | PhysicalPlan::Sort { input, .. }
+            | PhysicalPlan::HashDistinct { input }
+            | PhysicalPlan::Limit { input, .. } => self.typed_columns(input, catalog),
+            PhysicalPlan::Project { input, columns } => {
+                // Typing the expressions needs the input's columns as a schema.
+                let schema = self.typed_columns(input, catalog).map(|input| {
+                    let columns = input
+                        .into_iter()
+                        .filter_map(|(name, data_type)| Some(Column::new(name, data_type?)))
+                        .collect();
+                    TableSchema::new(String::new(), columns)
+                });
+                Some(
+                    columns
+                        .iter()
+                        .map(|(expr, alias)| {
+                            let data_type = schema.as_ref().and_then(|s| self.infer_type(expr, s));
+                            (projected_name(expr, alias.as_deref()), data_type)
+                        })
+                        .collect(),
+                )
+            }
+            PhysicalPlan::HashAggregate { input, group_by, aggregates } => {
+                let input = self.typed_columns(input, catalog);
+                let type_of = |name: &str| {
+                    let input = input.as_ref()?;
+                    input.iter().find(|(c, _)| self.casing.matches(c, name))?.1
+                };
+                let aggregates = aggregates.iter().map(|agg| {
+                    let data_type = match (agg.func, &agg.column) {
+                        (AggFunc::Count, _) => Some(DataType::Int64),
+                        (AggFunc::Avg, _) => Some(DataType::Float64),
+                        (_, Some(column)) => type_of(column),
+                        (_, None) => None,
+                    };
+                    (agg.output_name(), data_type)
+                });
+                Some(
+                    group_by
+                        .iter()
+                        .map(|g| (g.clone(), type_of(g)))
+                        .chain(aggregates)
+                        .collect(),
+                )
+            }
+            PhysicalPlan::NestedLoopJoin { left, right, left_name, right_name, .. }
+            | PhysicalPlan::HashJoin {
+                build: left,
+                probe: right,
+                build_name: left_name,
+                probe_name: right_name,
+                ..
+            } => {
+                let mut columns = self.typed_columns(left, catalog)?;
+                for column in &mut columns {
+                    column.0 = qualify(left_name, &column.0);
+                }
+                let right = self.typed_columns(right, catalog)?;
+                columns.extend(right.into_iter().map(|(c, t)| (qualify(right_name, &c), t)));
+                Some(columns)
+            }
+            PhysicalPlan::Union { inputs } => self.union_columns(inputs, catalog).ok()?,
+            PhysicalPlan::Insert { .. }
+            | PhysicalPlan::Update { .. }
+            | PhysicalPlan::Delete { .. }
+            | PhysicalPlan::CreateTable { .. }
+            | PhysicalPlan::DropTable { .. } => None,
+        }
+    }
+    
+    /// The columns a union produces: the first input's names, each typed
+    /// as the widest of the inputs' types at its position. An error if the
+    /// inputs differ in width or have a column of types that don't mix.
+    /// `None` if some input's columns can't be told.
+    fn union_columns(
+        &self,
+        inputs: &[PhysicalPlan],
+        catalog: &Catalog,
+    ) -> Result<Option<TypedColumns>, String> {
+        let mut union: Option<TypedColumns> = None;
+        for input in inputs {
+            let columns = match self.typed_columns(input, catalog) {
+                Some(columns) => columns,
+                None => return Ok(None),
+            };
+            let union = match &mut union {
+                Some(union) => union,
+                None => {
+                    union = Some(columns);
+                    continue;
+                }
+            };
+            if columns.len() != union.len() {
+                return Err(format!(
+                    "UNION inputs have different numbers of columns: {} and {}",
+                    union.len(),
+                    columns.len()
+                ));
+            }
+            for ((name, union_type), (_, data_type)) in union.iter_mut().zip(columns) {
+                *union_type = match (*union_type, data_type) {
+                    (Some(a), Some(b)) => Some(widest(a, b).ok_or_else(|| {
+                        format!("UNION column '{}' mixes types {} and {}", name, a, b)
+                    })?),
+                    _ => None,
+                };
+            }
+        }
+        Ok(union)
+    }
+    
+    /// Check `expr` against the columns `input` produces: its table's, or
+    /// those of a projection or aggregate within it.
+    fn validate_input_expr(
+        &self,
+        expr: &Expr,
+        input: &PhysicalPlan,
+        catalog: &Catalog,
+    ) -> Result<(), String> {
+        if let Some(columns) = self.output_columns(input, catalog) {
+            return self.validate_output_columns(expr, &columns);
+        }
+        let schema = self
+            .get_table_name(input)
+            .and_then(|table_name| catalog.get_table(&table_name));
+        match schema {
+            Some(schema) => self.validate_expr(expr, schema),
+            None => Ok(()),
+        }
+    }
+    
+    fn validate_output_columns(&self, expr: &Expr, columns: &[String]) -> Result<(), String> {
+        match expr {
+            Expr::Literal(_) => Ok(()),
+            Expr::Column(name) => {
+                if columns.iter().any(|c| self.casing.matches(c, name)) {
+                    return Ok(());
+                }
+                let unqualified = columns
+                    .iter()
+                    .filter(|c| unqualified_matches(c, name, self.casing))
+                    .count();
+                match unqualified {
+                    0 => Err(format!("column '{}' is not produced by the input", name)),
+                    1 => Ok(()),
+                    _ => Err(format!("column '{}' is ambiguous", name)),
+                }
+            }
+            Expr::InSubquery { expr, subplan, .. } => {
+                self.validate_output_columns(expr, columns)?;
+                self.validate_subquery(subplan, None, true).map(|_| ())
+            }
+            Expr::Exists { subplan, .. } => {
+                self.validate_subquery(subplan, None, false).map(|_| ())
+            }
+            other => other
+                .children()
+                .into_iter()
+                .try_for_each(|child| self.validate_output_columns(child, columns)),
+        }
+    }
+    
     fn validate_expr(&self, expr: &Expr, schema: &TableSchema) -> Result<(), String> {
         match expr {
             Expr::Literal(_) => Ok(()),
             Expr::Column(name) => {
-                if schema.get_column(name).is_none() {
+                if schema.find_column(name, self.casing).is_none() {
                     Err(format!(
                         "column '{}' not found in table '{}'",
                         name, schema.name
@@ -105,9 +965,129 @@ impl Executor {
                 self.validate_expr(right, schema)?;
                 self.validate_binary_op_types(left, right, *op, schema)
             }
+            Expr::Not(inner) => {
+                self.validate_expr(inner, schema)?;
+                match self.infer_type(inner, schema) {
+                    Some(t) if t != DataType::Bool => {
+                        Err(format!("NOT requires a boolean, got {}", t))
+                    }
+                    _ => Ok(()),
+                }
+            }
+            Expr::IsNull { expr, .. } => self.validate_expr(expr, schema),
+            Expr::Like { expr, pattern, .. } => {
+                for operand in [expr, pattern] {
+                    self.validate_expr(operand, schema)?;
+                    match self.infer_type(operand, schema) {
+                        Some(t) if t != DataType::String => {
+                            return Err(format!("LIKE requires a string, got {}", t));
+                        }
+                        _ => {}
+                    }
+                }
+                Ok(())
+            }
+            Expr::InList { expr, list, .. } => {
+                self.validate_expr(expr, schema)?;
+                for item in list {
+                    self.validate_expr(item, schema)?;
+                    self.validate_operand_types("IN", expr, item, schema)?;
+                }
+                Ok(())
+            }
+            Expr::Between { expr, low, high, .. } => {
+                self.validate_expr(expr, schema)?;
+                for bound in [low, high] {
+                    self.validate_expr(bound, schema)?;
+                    self.validate_operand_types("BETWEEN", expr, bound, schema)?;
+                }
+                Ok(())
+            }
+            Expr::FunctionCall { name, args } => {
+                let function = self
+                    .function(name)
+                    .ok_or_else(|| format!("unknown function: {}", name))?;
+                for arg in args {
+                    self.validate_expr(arg, schema)?;
+                }
+                let types: Vec<_> = args.iter().map(|a| self.infer_type(a, schema)).collect();
+                function.check_call(&name.to_ascii_uppercase(), &types)
+            }
+            Expr::InSubquery { expr, subplan, .. } => {
+                self.validate_expr(expr, schema)?;
+                let columns = self.validate_subquery(subplan, Some(schema), true)?;
+                let column_type = columns.and_then(|c| c.into_iter().next()?.1);
+                match (self.infer_type(expr, schema), column_type) {
+                    (Some(lt), Some(rt)) if !lt.is_compatible(&rt) => {
+                        Err(format!("incompatible types for IN: {} and {}", lt, rt))
+                    }
+                    _ => Ok(()),
+                }
+            }
+            Expr::Exists { subplan, .. } => {
+                self.validate_subquery(subplan, Some(schema), false).map(|_| ())
+            }
         }
     }
-
+    
+    /// Check a subquery as a query of its own and return its columns where
+    /// they're known. A column it can't find but the outer query's table
+    /// has is a correlated reference, which isn't supported. An IN
+    /// subquery must produce exactly one column.
+    fn validate_subquery(
+        &self,
+        subplan: &LogicalPlan,
+        outer: Option<&TableSchema>,
+        single_column: bool,
+    ) -> Result<Option<TypedColumns>, String> {
+        let plan = self.plan_subquery(subplan);
+        let checked = self.validate_plan(&plan);
+        let catalog = match &self.catalog {
+            Some(c) => c.read().unwrap(),
+            None => return Ok(None),
+        };
+        if let Err(e) = checked {
+            let inner = self.get_table_name(&plan).and_then(|t| catalog.get_table(&t));
+            let mut columns = Vec::new();
+            plan_columns(&plan, &mut columns);
+            let outer_column = columns.into_iter().find(|c| {
+                inner.is_none_or(|s| s.find_column(c, self.casing).is_none())
+                    && outer.is_some_and(|s| s.find_column(c, self.casing).is_some())
+            });
+            return Err(match outer_column {
+                Some(c) => format!(
+                    "subquery refers to outer column '{}'; only uncorrelated subqueries are supported",
+                    c
+                ),
+                None => e,
+            });
+        }
+        let columns = self.typed_columns(&plan, &catalog);
+        match &columns {
+            Some(columns) if single_column && columns.len() != 1 => Err(format!(
+                "IN subquery must produce one column, got {}",
+                columns.len()
+            )),
+            _ => Ok(columns),
+        }
+    }
+    
+    fn validate_operand_types(
+        &self,
+        what: &str,
+        left: &Expr,
+        right: &Expr,
+        schema: &TableSchema,
+    ) -> Result<(), String> {
+        match (self.infer_type(left, schema), self.infer_type(right, schema)) {
+            (Some(lt), Some(rt)) if !lt.is_compatible(&rt) => Err(format!(
+                "incompatible types for {}: {} and {}",
+                what, lt, rt
+            )),
+            _ => Ok(()),
+        }
+    }
+    
     fn validate_binary_op_types(
         &self,
         left: &Expr,
@@ -117,7 +1097,7 @@ impl Executor {
     ) -> Result<(), String> {
         let left_type = self.infer_type(left, schema);
         let right_type = self.infer_type(right, schema);
-
+        
         match (left_type, right_type) {
             (Some(lt), Some(rt)) => {
                 if !Self::types_compatible(&lt, &rt, op) {
@@ -131,18 +1111,20 @@ impl Executor {
         }
         Ok(())
     }
-
+    
     fn infer_type(&self, expr: &Expr, schema: &TableSchema) -> Option<DataType> {
         match expr {
-            Expr::Literal(v) => match v {
-                Value::Int(_) => Some(DataType::Int64),
-                Value::String(_) => Some(DataType::String),
-                Value::Bool(_) => Some(DataType::Bool),
-                Value::Bytes(_) => Some(DataType::Bytes),
-                Value::Null => None,
-            },
-            Expr::Column(name) => schema.get_column(name).map(|c| c.data_type),
-            Expr::BinaryOp { op, .. } => match op {
+            Expr::Literal(v) => value_type(v),
+            Expr::Column(name) => schema.find_column(name, self.casing).map(|c| c.data_type),
+            Expr::BinaryOp { op, left, right } => match op {
+                BinaryOperator::Plus
+                | BinaryOperator::Minus
+                | BinaryOperator::Multiply
+                | BinaryOperator::Divide => arithmetic_type(
+                    *op,
+                    &self.infer_type(left, schema)?,
+                    &self.infer_type(right, schema)?,
+                ),
                 BinaryOperator::Eq
                 | BinaryOperator::Ne
                 | BinaryOperator::Lt
@@ -152,100 +1134,864 @@ impl Executor {
                 | BinaryOperator::And
                 | BinaryOperator::Or => Some(DataType::Bool),
             },
+            Expr::Not(_)
+            | Expr::IsNull { .. }
+            | Expr::Like { .. }
+            | Expr::InList { .. }
+            | Expr::Between { .. }
+            | Expr::InSubquery { .. }
+            | Expr::Exists { .. } => Some(DataType::Bool),
+            Expr::FunctionCall { name, args } => match self.function(name)?.returns() {
+                ReturnType::Fixed(data_type) => Some(data_type),
+                ReturnType::FirstArgument => {
+                    args.iter().find_map(|arg| self.infer_type(arg, schema))
+                }
+            },
         }
     }
-
-    fn types_compatible(left: &DataType, right: &DataType, _op: BinaryOperator) -> bool {
-        left == right
+    
+    /// Which operand types an operator takes. AND and OR take booleans,
+    /// and arithmetic takes numbers. Comparisons take two values of one
+    /// type, or two numbers, so an INT64 column compares with a FLOAT64
+    /// literal; strings never compare with numbers, nor booleans with
+    /// anything but booleans.
+    fn types_compatible(left: &DataType, right: &DataType, op: BinaryOperator) -> bool {
+        match op {
+            BinaryOperator::And | BinaryOperator::Or => {
+                *left == DataType::Bool && *right == DataType::Bool
+            }
+            op if op.is_arithmetic() => left.is_numeric() && right.is_numeric(),
+            _ => left.is_compatible(right),
+        }
     }
-
+    
+    /// The names of the columns `plan` produces, in order. `None` if the
+    /// order can't be told, as without a catalog.
+    pub fn columns(&self, plan: &PhysicalPlan) -> Option<Vec<String>> {
+        let catalog = self.catalog.as_ref()?.read().unwrap();
+        let columns = self.typed_columns(plan, &catalog)?;
+        Some(columns.into_iter().map(|(name, _)| name).collect())
+    }
+    
+    /// Run a query to completion.
     pub fn execute(&self, plan: PhysicalPlan) -> Result<Vec<Row>, String> {
+        self.stream(plan)?.collect()
+    }
+    
+    /// Validate a query and build its operators, which produce rows as
+    /// they are pulled. Sorts and aggregates read all their input on the
+    /// first pull; joins read their inner side then.
+    pub fn stream(&self, plan: PhysicalPlan) -> Result<Box<dyn RowStream + '_>, String> {
         self.validate_plan(&plan)?;
-
-        match plan {
-            PhysicalPlan::SeqScan { table, filter } => self.execute_scan(&table, filter),
+        self.build_stream(plan)
+    }
+    
+    fn build_stream(&self, plan: PhysicalPlan) -> Result<BoxedStream<'_>, String> {
+        Ok(match plan {
+            PhysicalPlan::SeqScan { table, filter } => {
+                let rows = self.scan_stream(&table)?;
+                match filter {
+                    Some(predicate) => self.filter_stream(rows, predicate)?,
+                    None => rows,
+                }
+            }
+            PhysicalPlan::IndexScan { table, index, range } => {
+                Box::new(Rows(self.execute_index_scan(&table, &index, &range)?.into_iter()))
+            }
             PhysicalPlan::Filter { input, predicate } => {
-                let rows = self.execute(*input)?;
-                Ok(rows
-                    .into_iter()
-                    .filter(|row| {
-                        self.eval_expr(&predicate, row)
-                            .map(|v| v.as_bool().unwrap_or(false))
-                            .unwrap_or(false)
-                    })
-                    .collect())
+                self.filter_stream(self.build_stream(*input)?, predicate)?
             }
             PhysicalPlan::Project { input, columns } => {
-                let rows = self.execute(*input)?;
-                Ok(rows
+                let columns: Vec<(String, Expr)> = columns
                     .into_iter()
-                    .map(|row| self.project_row(row, &columns))
-                    .collect())
+                    .map(|(expr, alias)| (projected_name(&expr, alias.as_deref()), expr))
+                    .collect();
+                Box::new(stream::Map {
+                    input: self.build_stream(*input)?,
+                    f: move |row| self.project_row(row, &columns),
+                })
+            }
+            PhysicalPlan::Sort { input, keys, nulls } => {
+                Box::new(Materialize::new(self.build_stream(*input)?, move |mut rows: Vec<Row>| {
+                    rows.sort_by(|a, b| self.compare_rows(a, b, &keys, nulls));
+                    Ok(rows)
+                }))
+            }
+            PhysicalPlan::HashAggregate { input, group_by, aggregates } => {
+                Box::new(Materialize::new(self.build_stream(*input)?, move |rows| {
+                    self.aggregate(rows, &group_by, &aggregates)
+                }))
+            }
+            PhysicalPlan::HashDistinct { input } => {
+                let mut seen = HashSet::new();
+                Box::new(stream::Filter {
+                    input: self.build_stream(*input)?,
+                    keep: move |row: &Row| seen.insert(distinct_key(row)),
+                })
+            }
+            PhysicalPlan::Union { inputs } => {
+                let shapes = self.union_shapes(&inputs);
+                let mut streams = Vec::with_capacity(inputs.len());
+                for (input, shape) in inputs.into_iter().zip(shapes) {
+                    let rows = self.build_stream(input)?;
+                    streams.push(match shape {
+                        Some(shape) => Box::new(stream::Map {
+                            input: rows,
+                            f: move |row| Ok(self.conform_row(row, &shape)),
+                        }),
+                        None => rows,
+                    });
+                }
+                Box::new(stream::Chain::new(streams))
+            }
+            PhysicalPlan::Limit { input, count } => Box::new(stream::Limit {
+                input: self.build_stream(*input)?,
+                remaining: count,
+            }),
+            PhysicalPlan::NestedLoopJoin { left, right, left_name, right_name, on, join_type } => {
+                let mut right = self.build_stream(*right)?;
+                let mut right_rows: Option<Vec<Row>> = None;
+                Box::new(FlatMap::new(self.build_stream(*left)?, move |l: Row| {
+                    if right_rows.is_none() {
+                        let rows = right.collect()?;
+                        let rows = rows.iter().map(|r| qualify_row(r, &right_name));
+                        right_rows = Some(rows.collect());
+                    }
+                    let l = qualify_row(&l, &left_name);
+                    let mut rows = Vec::new();
+                    match join_type {
+                        JoinType::Inner => {
+                            for r in right_rows.iter().flatten() {
+                                let row = join_rows(&l, r);
+                                if self.eval_predicate(&on, &row, &Subqueries::new()) {
+                                    rows.push(row);
+                                }
+                            }
+                        }
+                    }
+                    Ok(rows)
+                }))
+            }
+            PhysicalPlan::HashJoin { build, probe, build_name, probe_name, build_key, probe_key } => {
+                let mut build = self.build_stream(*build)?;
+                let mut table: Option<HashMap<Datum, Vec<Row>>> = None;
+                Box::new(FlatMap::new(self.build_stream(*probe)?, move |row: Row| {
+                    if table.is_none() {
+                        let mut built: HashMap<Datum, Vec<Row>> = HashMap::new();
+                        for row in build.collect()? {
+                            let row = qualify_row(&row, &build_name);
+                            let key = row.find_column(&build_key, self.casing);
+                            if let Some(key) = key.and_then(|v| v.hash_key()) {
+                                built.entry(key).or_default().push(row);
+                            }
+                        }
+                        table = Some(built);
+                    }
+                    let row = qualify_row(&row, &probe_name);
+                    let key = row.find_column(&probe_key, self.casing).and_then(|v| v.hash_key());
+                    Ok(match key.and_then(|key| table.as_ref()?.get(&key)) {
+                        Some(matches) => matches.iter().map(|m| join_rows(m, &row)).collect(),
+                        None => Vec::new(),
+                    })
+                }))
+            }
+            PhysicalPlan::Insert { .. } | PhysicalPlan::Update { .. } | PhysicalPlan::Delete { .. } => {
+                let message = "INSERT, UPDATE and DELETE change tables; use Executor::run";
+                return Err(message.to_string());
+            }
+            PhysicalPlan::CreateTable { .. } | PhysicalPlan::DropTable { .. } => {
+                let message = "CREATE TABLE and DROP TABLE change the catalog; use Executor::run";
+                return Err(message.to_string());
+            }
+        })
+    }
+    
+    fn filter_stream<'a>(
+        &'a self,
+        input: BoxedStream<'a>,
+        predicate: Expr,
+    ) -> Result<BoxedStream<'a>, String> {
+        let subqueries = self.run_subqueries(Some(&predicate))?;
+        Ok(Box::new(stream::Filter {
+            input,
+            keep: move |row: &Row| self.eval_predicate(&predicate, row, &subqueries),
+        }))
+    }
+    
+    /// A subquery's physical plan, planned as a query of its own would be.
+    fn plan_subquery(&self, subplan: &LogicalPlan) -> PhysicalPlan {
+        let planner = match &self.catalog {
+            Some(catalog) => Planner::with_catalog(catalog.clone()),
+            None => Planner::new(),
+        };
+        planner.to_physical(subplan.clone())
+    }
+    
+    /// Run each subquery in `predicate` once, ahead of the rows it filters.
+    fn run_subqueries(&self, predicate: Option<&Expr>) -> Result<Subqueries, String> {
+        fn visit(executor: &Executor, expr: &Expr, out: &mut Subqueries) -> Result<(), String> {
+            match expr {
+                Expr::InSubquery { subplan, .. } => {
+                    out.insert(subquery_key(subplan), executor.run_subquery(subplan, true)?);
+                }
+                Expr::Exists { subplan, .. } => {
+                    out.insert(subquery_key(subplan), executor.run_subquery(subplan, false)?);
+                }
+                _ => {}
             }
+            expr.children().into_iter().try_for_each(|child| visit(executor, child, out))
         }
+        let mut subqueries = Subqueries::new();
+        if let Some(predicate) = predicate {
+            visit(self, predicate, &mut subqueries)?;
+        }
+        Ok(subqueries)
     }
     
-    fn execute_scan(&self, table_name: &str, filter: Option<Expr>) -> Result<Vec<Row>, String> {
-        let table = self.tables.get(table_name)
-            .ok_or_else(|| format!("Table not found: {}", table_name))?;
-        
-        let mut rows = table.rows.clone();
+    /// Run a subquery, keeping its one column's values for IN, or for
+    /// EXISTS only whether it produced a row.
+    fn run_subquery(
+        &self,
+        subplan: &LogicalPlan,
+        in_list: bool,
+    ) -> Result<SubqueryResult, String> {
+        let plan = self.plan_subquery(subplan);
+        let mut result = SubqueryResult::default();
+        if !in_list {
+            result.rows = self.stream(plan)?.next().transpose()?.map_or(0, |_| 1);
+            return Ok(result);
+        }
+        let column = self.catalog.as_ref().and_then(|catalog| {
+            let columns = self.typed_columns(&plan, &catalog.read().unwrap())?;
+            columns.into_iter().next().map(|(name, _)| name)
+        });
+        for row in self.execute(plan)? {
+            let value = match &column {
+                Some(name) => row.find_column(name, self.casing).unwrap_or(Value::Null),
+                None if row.columns.len() == 1 => row.columns.into_values().next().unwrap(),
+                None => return Err("IN subquery must produce one column".to_string()),
+            };
+            result.rows += 1;
+            match value.hash_key() {
+                Some(key) => {
+                    result.keys.insert(key);
+                }
+                None => result.has_null = true,
+            }
+        }
+        Ok(result)
+    }
+    
+    /// Hash rows into groups by their `group_by` values and fold each group
+    /// through the aggregates. Groups come out in order of first appearance.
+    fn aggregate(
+        &self,
+        rows: Vec<Row>,
+        group_by: &[String],
+        aggregates: &[AggExpr],
+    ) -> Result<Vec<Row>, String> {
+        let new_group = || aggregates.iter().map(Accumulator::new).collect::<Vec<_>>();
+        let mut groups: Vec<(Vec<Value>, Vec<Accumulator>)> = Vec::new();
+        let mut index: HashMap<Vec<Datum>, usize> = HashMap::new();
+        if group_by.is_empty() {
+            groups.push((Vec::new(), new_group()));
+            index.insert(Vec::new(), 0);
+        }
         
-        if let Some(predicate) = filter {
-            rows.retain(|row| {
-                self.eval_expr(&predicate, row)
-                    .map(|v| v.as_bool().unwrap_or(false))
-                    .unwrap_or(false)
+        for row in rows {
+            let key: Vec<Value> = group_by
+                .iter()
+                .map(|col| row.find_column(col, self.casing).unwrap_or(Value::Null))
+                .collect();
+            let hashed = key.iter().cloned().map(Datum::from).collect();
+            let i = *index.entry(hashed).or_insert_with(|| {
+                groups.push((key, new_group()));
+                groups.len() - 1
             });
+            for (acc, agg) in groups[i].1.iter_mut().zip(aggregates) {
+                let value = match &agg.column {
+                    Some(col) => row.find_column(col, self.casing).unwrap_or(Value::Null),
+                    None => Value::Bool(true),
+                };
+                acc.update(value)?;
+            }
         }
         
-        Ok(rows)
+        Ok(groups
+            .into_iter()
+            .map(|(key, accs)| {
+                let keys = group_by.iter().cloned().zip(key);
+                let values = aggregates
+                    .iter()
+                    .map(AggExpr::output_name)
+                    .zip(accs.into_iter().map(Accumulator::finish));
+                Row::new_with_values(keys.chain(values).collect())
+            })
+            .collect())
+    }
+    
+    /// Row order for a sort. A missing column sorts as NULL; values that
+    /// can't be compared tie.
+    fn compare_rows(
+        &self,
+        a: &Row,
+        b: &Row,
+        keys: &[(String, SortOrder)],
+        nulls: NullOrder,
+    ) -> Ordering {
+        let null_vs_value = match nulls {
+            NullOrder::First => Ordering::Less,
+            NullOrder::Last => Ordering::Greater,
+        };
+        for (column, order) in keys {
+            let left = a.find_column(column, self.casing).unwrap_or(Value::Null);
+            let right = b.find_column(column, self.casing).unwrap_or(Value::Null);
+            let ord = match (&left, &right) {
+                (Value::Null, Value::Null) => Ordering::Equal,
+                (Value::Null, _) => null_vs_value,
+                (_, Value::Null) => null_vs_value.reverse(),
+                _ => {
+                    let ord = left.compare(&right).unwrap_or(Ordering::Equal);
+                    match order {
+                        SortOrder::Asc => ord,
+                        SortOrder::Desc => ord.reverse(),
+                    }
+                }
+            };
+            if ord != Ordering::Equal {
+                return ord;
+            }
+        }
+        Ordering::Equal
+    }
+    
+    /// Rows whose indexed value falls in `range`, in index order. An index
+    /// only the catalog knows is built for the one lookup.
+    fn execute_index_scan(
+        &self,
+        table_name: &str,
+        index_name: &str,
+        range: &(Bound<Value>, Bound<Value>),
+    ) -> Result<Vec<Row>, String> {
+        let stored;
+        let rows = match self.providers.get(&self.table_key(table_name)) {
+            Some(provider) => {
+                stored = provider.scan()?;
+                stored.as_slice()
+            }
+            None => self.table(table_name).map(|t| t.rows.as_slice()).unwrap_or(&[]),
+        };
+        let built = self
+            .indexes
+            .get(&self.table_key(table_name))
+            .into_iter()
+            .flatten()
+            .find(|i| self.casing.matches(&i.def.name, index_name));
+        let positions = match built {
+            Some(index) => index.lookup(range),
+            None => {
+                let catalog = self.catalog.as_ref().map(|c| c.read().unwrap());
+                let def = catalog
+                    .as_ref()
+                    .and_then(|c| self.index_def(table_name, index_name, c))
+                    .ok_or_else(|| format!("index not found: {} on {}", index_name, table_name))?;
+                ColumnIndex::build(def, rows, self.casing).lookup(range)
+            }
+        };
+        Ok(positions.into_iter().map(|p| rows[p].clone()).collect())
+    }
+    
+    /// The definition of a built index, or else of one in the catalog.
+    fn index_def(&self, table_name: &str, index_name: &str, catalog: &Catalog) -> Option<IndexDef> {
+        self.indexes
+            .get(&self.table_key(table_name))
+            .into_iter()
+            .flatten()
+            .map(|i| &i.def)
+            .chain(catalog.indexes_for_table(table_name))
+            .find(|def| self.casing.matches(&def.name, index_name))
+            .cloned()
+    }
+    
+    fn scan_rows(&self, table_name: &str) -> Result<Vec<Row>, String> {
+        self.scan_stream(table_name)?.collect()
+    }
+    
+    /// A table's rows, pulled from its provider or cloned one at a time.
+    fn scan_stream(&self, table_name: &str) -> Result<BoxedStream<'_>, String> {
+        if let Some(provider) = self.providers.get(&self.table_key(table_name)) {
+            return provider.stream();
+        }
+        // A table known only to the catalog has no rows yet.
+        match self.table(table_name) {
+            Some(table) => Ok(Box::new(Rows(table.rows.iter().cloned()))),
+            None if self
+                .catalog
+                .as_ref()
+                .is_some_and(|c| c.read().unwrap().get_table(table_name).is_some()) =>
+            {
+                Ok(Box::new(Rows(std::iter::empty())))
+            }
+            None => Err(format!("Table not found: {}", table_name)),
+        }
+    }
+    
+    /// Whether `predicate` is TRUE for `row`; NULL and errors reject it.
+    fn eval_predicate(&self, predicate: &Expr, row: &Row, subqueries: &Subqueries) -> bool {
+        self.eval_with(predicate, row, subqueries)
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false)
     }
     
     fn eval_expr(&self, expr: &Expr, row: &Row) -> Option<Value> {
+        self.eval_with(expr, row, &Subqueries::new())
+    }
+    
+    /// `expr` for `row`, taking subquery results from `subqueries`. A
+    /// subquery missing there is run on the spot.
+    fn eval_with(&self, expr: &Expr, row: &Row, subqueries: &Subqueries) -> Option<Value> {
+        let eval = |expr: &Expr| self.eval_with(expr, row, subqueries);
+        let subquery = |subplan: &LogicalPlan, in_list: bool| {
+            match subqueries.get(&subquery_key(subplan)) {
+                Some(result) => Some(Cow::Borrowed(result)),
+                None => self.run_subquery(subplan, in_list).ok().map(Cow::Owned),
+            }
+        };
         match expr {
             Expr::Literal(value) => Some(value.clone()),
-            Expr::Column(name) => row.get_column(name),
+            Expr::Column(name) => row.find_column(name, self.casing),
             Expr::BinaryOp { op, left, right } => {
-                let left_val = self.eval_expr(left, row)?;
-                let right_val = self.eval_expr(right, row)?;
+                let left_val = eval(left)?;
+                let right_val = eval(right)?;
                 self.eval_binary_op(*op, left_val, right_val)
             }
+            Expr::Not(inner) => match eval(inner)? {
+                Value::Null => Some(Value::Null),
+                value => value.as_bool().map(|b| Value::Bool(!b)),
+            },
+            Expr::IsNull { expr, negated } => {
+                let value = eval(expr)?;
+                Some(Value::Bool((value == Value::Null) != *negated))
+            }
+            Expr::Like { expr, pattern, negated } => {
+                match (eval(expr)?, eval(pattern)?) {
+                    (Value::Null, _) | (_, Value::Null) => Some(Value::Null),
+                    (Value::String(text), Value::String(pattern)) => {
+                        Some(Value::Bool(like_match(&text, &pattern) != *negated))
+                    }
+                    _ => None,
+                }
+            }
+            Expr::InList { expr, list, negated } => {
+                let value = eval(expr)?;
+                if value == Value::Null {
+                    return Some(Value::Null);
+                }
+                // No match against a list holding NULL is unknown, not false.
+                let mut saw_null = false;
+                for item in list {
+                    let item = eval(item)?;
+                    if item == Value::Null {
+                        saw_null = true;
+                    } else if value == item {
+                        return Some(Value::Bool(!*negated));
+                    }
+                }
+                Some(if saw_null { Value::Null } else { Value::Bool(*negated) })
+            }
+            Expr::Between { expr, low, high, negated } => {
+                let value = eval(expr)?;
+                let low = eval(low)?;
+                let high = eval(high)?;
+                if [&value, &low, &high].contains(&&Value::Null) {
+                    return Some(Value::Null);
+                }
+                let within = value.compare(&low)? != Ordering::Less
+                    && value.compare(&high)? != Ordering::Greater;
+                Some(Value::Bool(within != *negated))
+            }
+            Expr::FunctionCall { name, args } => {
+                let args = args
+                    .iter()
+                    .map(eval)
+                    .collect::<Option<Vec<_>>>()?;
+                self.function(name)?.call(&args)
+            }
+            Expr::InSubquery { expr, subplan, negated } => {
+                let value = eval(expr)?;
+                Some(match subquery(subplan, true)?.contains(&value) {
+                    Some(found) => Value::Bool(found != *negated),
+                    None => Value::Null,
+                })
+            }
+            Expr::Exists { subplan, negated } => {
+                Some(Value::Bool((subquery(subplan, false)?.rows > 0) != *negated))
+            }
         }
     }
     
+    /// Equality that agrees with `Value::compare`, so 1.50 equals 1.5.
     fn eval_binary_op(&self, op: BinaryOperator, left: Value, right: Value) -> Option<Value> {
+        let comparison = !matches!(op, BinaryOperator::And | BinaryOperator::Or);
+        // NULL compares as unknown, so it never equals anything, itself
+        // included, and arithmetic on it is NULL too.
+        if comparison && (left == Value::Null || right == Value::Null) {
+            return Some(Value::Null);
+        }
         match op {
+            BinaryOperator::Plus
+            | BinaryOperator::Minus
+            | BinaryOperator::Multiply
+            | BinaryOperator::Divide => arithmetic(op, &left, &right),
             BinaryOperator::Eq => Some(Value::Bool(left == right)),
             BinaryOperator::Ne => Some(Value::Bool(left != right)),
             BinaryOperator::Lt => left.compare(&right).map(|ord| Value::Bool(ord == Ordering::Less)),
             BinaryOperator::Le => left.compare(&right).map(|ord| Value::Bool(ord != Ordering::Greater)),
             BinaryOperator::Gt => left.compare(&right).map(|ord| Value::Bool(ord == Ordering::Greater)),
             BinaryOperator::Ge => left.compare(&right).map(|ord| Value::Bool(ord != Ordering::Less)),
-            BinaryOperator::And => {
-                match (left.as_bool(), right.as_bool()) {
-                    (Some(a), Some(b)) => Some(Value::Bool(a && b)),
-                    _ => None,
-                }
+            // Kleene logic: NULL is unknown, so it decides nothing unless
+            // the other side can't settle the result either.
+            BinaryOperator::And => match (truth(&left)?, truth(&right)?) {
+                (Some(false), _) | (_, Some(false)) => Some(Value::Bool(false)),
+                (Some(true), Some(true)) => Some(Value::Bool(true)),
+                _ => Some(Value::Null),
+            },
+            BinaryOperator::Or => match (truth(&left)?, truth(&right)?) {
+                (Some(true), _) | (_, Some(true)) => Some(Value::Bool(true)),
+                (Some(false), Some(false)) => Some(Value::Bool(false)),
+                _ => Some(Value::Null),
+            },
+        }
+    }
+    
+    /// How each union input's rows become union rows. `None` for an input
+    /// whose rows pass through as they are, since the union's columns or
+    /// its own can't be told.
+    fn union_shapes(&self, inputs: &[PhysicalPlan]) -> Vec<Option<UnionShape>> {
+        let catalog = match &self.catalog {
+            Some(catalog) => catalog.read().unwrap(),
+            None => return vec![None; inputs.len()],
+        };
+        let union = self.union_columns(inputs, &catalog).ok().flatten();
+        inputs
+            .iter()
+            .map(|input| {
+                let union = union.as_ref()?;
+                let columns = self.typed_columns(input, &catalog)?;
+                let shape = columns
+                    .into_iter()
+                    .zip(union)
+                    .map(|((from, _), (to, data_type))| (from, to.clone(), *data_type))
+                    .collect();
+                Some(shape)
+            })
+            .collect()
+    }
+    
+    /// `row` with its columns renamed and widened per `shape`.
+    fn conform_row(&self, row: Row, shape: &UnionShape) -> Row {
+        let mut fields = Vec::with_capacity(shape.len());
+        for (from, to, data_type) in shape {
+            if let Some(value) = row.find_column(from, self.casing) {
+                let value = match (value, data_type) {
+                    (Value::Int(i), Some(DataType::Float64)) => Value::Float(i as f64),
+                    (value @ Value::Decimal { .. }, Some(DataType::Float64)) => {
+                        Value::Float(decimal_to_f64(&value))
+                    }
+                    (value, _) => value,
+                };
+                fields.push((to.clone(), value));
             }
-            BinaryOperator::Or => {
-                match (left.as_bool(), right.as_bool()) {
-                    (Some(a), Some(b)) => Some(Value::Bool(a || b)),
-                    _ => None,
-                }
+        }
+        Row::new_with_values(fields)
+    }
+    
+    /// Each `(name, expr)` of `columns` evaluated against `row`, NULL where
+    /// it reads a column the row lacks, so every projected row has every
+    /// projected column. Any other failure to evaluate is an error.
+    fn project_row(&self, row: Row, columns: &[(String, Expr)]) -> Result<Row, String> {
+        let fields = columns
+            .iter()
+            .map(|(name, expr)| {
+                let value = match self.eval_expr(expr, &row) {
+                    Some(value) => value,
+                    None if self.reads_missing_column(expr, &row) => Value::Null,
+                    None => return Err(format!("cannot evaluate {} for column '{}'", expr, name)),
+                };
+                Ok((name.clone(), value))
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Row::new_with_values(fields))
+    }
+    
+    fn reads_missing_column(&self, expr: &Expr, row: &Row) -> bool {
+        match expr {
+            Expr::Column(name) => row.find_column(name, self.casing).is_none(),
+            expr => expr.children().into_iter().any(|child| self.reads_missing_column(child, row)),
+        }
+    }
+}
+
+/// What a subquery produced, as far as IN and EXISTS need to know.
+#[derive(Clone, Default)]
+struct SubqueryResult {
+    rows: usize,
+    /// The hash keys of the column's non-NULL values.
+    keys: HashSet<Datum>,
+    has_null: bool,
+}
+
+impl SubqueryResult {
+    /// Whether `value` is among the values, `None` for unknown: a NULL
+    /// `value`, or no match where the values hold a NULL. Nothing is in an
+    /// empty result, not even NULL.
+    fn contains(&self, value: &Value) -> Option<bool> {
+        if self.rows == 0 {
+            return Some(false);
+        }
+        if self.keys.contains(&value.hash_key()?) {
+            return Some(true);
+        }
+        match self.has_null {
+            true => None,
+            false => Some(false),
+        }
+    }
+}
+
+/// A subquery's identity within the expression that holds it.
+fn subquery_key(subplan: &LogicalPlan) -> usize {
+    subplan as *const LogicalPlan as usize
+}
+
+/// The columns the expressions in `plan` read, down to its table.
+fn plan_columns<'a>(plan: &'a PhysicalPlan, out: &mut Vec<&'a str>) {
+    match plan {
+        PhysicalPlan::SeqScan { filter: Some(predicate), .. } => {
+            referenced_columns(predicate, out)
+        }
+        PhysicalPlan::Filter { input, predicate } => {
+            referenced_columns(predicate, out);
+            plan_columns(input, out);
+        }
+        PhysicalPlan::Project { input, columns } => {
+            for (expr, _) in columns {
+                referenced_columns(expr, out);
             }
+            plan_columns(input, out);
+        }
+        PhysicalPlan::Sort { input, .. }
+        | PhysicalPlan::HashAggregate { input, .. }
+        | PhysicalPlan::HashDistinct { input }
+        | PhysicalPlan::Limit { input, .. } => plan_columns(input, out),
+        _ => {}
+    }
+}
+
+/// A value as a truth value: `Some(None)` for NULL, `None` if it isn't a
+/// boolean at all.
+fn truth(value: &Value) -> Option<Option<bool>> {
+    match value {
+        Value::Null => Some(None),
+        Value::Bool(b) => Some(Some(*b)),
+        _ => None,
+    }
+}
+
+/// The type `left op right` gives for an arithmetic `op`, as `arithmetic`
+/// computes it.
+fn arithmetic_type(op: BinaryOperator, left: &DataType, right: &DataType) -> Option<DataType> {
+    let scale = |data_type: &DataType| match data_type {
+        DataType::Int64 => Some(0),
+        DataType::Decimal { scale, .. } => Some(*scale),
+        _ => None,
+    };
+    Some(match (left, right) {
+        (DataType::Int64, DataType::Int64) => DataType::Int64,
+        (DataType::Float64, _) | (_, DataType::Float64) => DataType::Float64,
+        _ if op == BinaryOperator::Divide => DataType::Float64,
+        _ => DataType::Decimal {
+            precision: DataType::MAX_DECIMAL_PRECISION,
+            scale: match op {
+                BinaryOperator::Multiply => scale(left)? + scale(right)?,
+                _ => scale(left)?.max(scale(right)?),
+            },
+        },
+    })
+}
+
+fn value_type(value: &Value) -> Option<DataType> {
+    match value {
+        Value::Int(_) => Some(DataType::Int64),
+        Value::String(_) => Some(DataType::String),
+        Value::Bool(_) => Some(DataType::Bool),
+        Value::Bytes(_) => Some(DataType::Bytes),
+        Value::Float(_) => Some(DataType::Float64),
+        Value::Timestamp(_) => Some(DataType::Timestamp),
+        Value::Decimal { scale, .. } => Some(DataType::Decimal {
+            precision: DataType::MAX_DECIMAL_PRECISION,
+            scale: *scale,
+        }),
+        Value::Null => None,
+    }
+}
+
+/// Append `row` to `rows` if it keeps `schema`'s primary key and unique
+/// constraints and fits its columns.
+fn append_checked(
+    rows: &mut Vec<Row>,
+    row: Row,
+    table_name: &str,
+    schema: Option<&TableSchema>,
+    casing: IdentifierCasing,
+) -> Result<(), String> {
+    let schema = match schema {
+        Some(schema) => schema,
+        None => {
+            rows.push(row);
+            return Ok(());
+        }
+    };
+    let key_of = |row: &Row, cols: &[String]| -> Vec<Value> {
+        cols.iter()
+            .map(|c| row.find_column(c, casing).unwrap_or(Value::Null))
+            .collect()
+    };
+    
+    if let Some(pk) = schema.primary_key() {
+        if key_of(&row, pk).contains(&Value::Null) {
+            return Err(format!(
+                "constraint violation: primary key ({}) of '{}' cannot be NULL",
+                pk.join(", "),
+                table_name
+            ));
         }
     }
     
-    fn project_row(&self, row: Row, columns: &[String]) -> Row {
-        let mut fields = Vec::new();
-        for col in columns {
-            if let Some(value) = row.get_column(col) {
-                fields.push(value);
+    let unique = schema.unique_constraints().iter().map(Vec::as_slice);
+    for cols in schema.primary_key().into_iter().chain(unique) {
+        let key = key_of(&row, cols);
+        // NULLs never collide under a unique constraint.
+        if key.contains(&Value::Null) {
+            continue;
+        }
+        if rows.iter().any(|existing| key_of(existing, cols) == key) {
+            return Err(format!(
+                "constraint violation: duplicate key ({}) in '{}'",
+                cols.join(", "),
+                table_name
+            ));
+        }
+    }
+    check_row(schema, &row, casing)?;
+    rows.push(row);
+    Ok(())
+}
+
+/// A row's columns and values in name order, for finding duplicates.
+/// NULLs share a key, as DISTINCT takes them to be equal.
+fn distinct_key(row: &Row) -> Vec<(String, Datum)> {
+    let mut key: Vec<(String, Datum)> = row
+        .columns
+        .iter()
+        .map(|(name, value)| (name.clone(), Datum::from(value.clone())))
+        .collect();
+    key.sort_by(|a, b| a.0.cmp(&b.0));
+    key
+}
+
+/// The type both of two column types fit in, if they mix: a float when
+/// either is one, else the decimal with room for both, else their shared
+/// type.
+fn widest(a: DataType, b: DataType) -> Option<DataType> {
+    match (a, b) {
+        _ if a == b => Some(a),
+        _ if !a.is_compatible(&b) => None,
+        (DataType::Float64, _) | (_, DataType::Float64) => Some(DataType::Float64),
+        (
+            DataType::Decimal { precision: p, scale: s },
+            DataType::Decimal { precision: q, scale: t },
+        ) => {
+            let scale = s.max(t);
+            let whole = p.saturating_sub(s).max(q.saturating_sub(t));
+            Some(DataType::Decimal {
+                precision: (whole + scale).min(DataType::MAX_DECIMAL_PRECISION),
+                scale,
+            })
+        }
+        (decimal @ DataType::Decimal { .. }, _) | (_, decimal @ DataType::Decimal { .. }) => {
+            Some(decimal)
+        }
+        _ => None,
+    }
+}
+
+/// Whether a column of `data_type` can hold `value`. Integers widen to any
+/// numeric type and decimals to floats; other values need their own type.
+fn value_fits(value: &Value, data_type: &DataType) -> bool {
+    match (value, data_type) {
+        (Value::Int(_), t) => t.is_numeric(),
+        (Value::Decimal { .. }, DataType::Decimal { .. } | DataType::Float64) => true,
+        (v, t) => value_type(v).as_ref() == Some(t),
+    }
+}
+
+/// Check `row`'s columns exist in `schema` and hold values their types and
+/// nullability allow.
+fn check_row(schema: &TableSchema, row: &Row, casing: IdentifierCasing) -> Result<(), String> {
+    for (name, value) in &row.columns {
+        let column = schema.find_column(name, casing).ok_or_else(|| {
+            format!("column '{}' not found in table '{}'", name, schema.name)
+        })?;
+        if *value == Value::Null {
+            if !column.nullable {
+                return Err(format!(
+                    "column '{}' of '{}' cannot be NULL",
+                    column.name, schema.name
+                ));
             }
+        } else if !value_fits(value, &column.data_type) {
+            return Err(format!(
+                "type mismatch: column '{}' of '{}' is {}, got {:?}",
+                column.name, schema.name, column.data_type, value
+            ));
         }
-        Row::new(fields)
     }
+    Ok(())
+}
+
+/// `column` as seen from outside a join side called `name`.
+fn qualify(name: &str, column: &str) -> String {
+    if column.contains('.') {
+        column.to_string()
+    } else {
+        format!("{}.{}", name, column)
+    }
+}
+
+/// Whether the qualified `column` is `name` once its qualifier is dropped.
+fn unqualified_matches(column: &str, name: &str, casing: IdentifierCasing) -> bool {
+    !name.contains('.')
+        && column
+            .rsplit_once('.')
+            .is_some_and(|(_, bare)| casing.matches(bare, name))
+}
+
+fn qualify_row(row: &Row, name: &str) -> Row {
+    Row::new_with_values(
+        row.columns
+            .iter()
+            .map(|(col, value)| (qualify(name, col), value.clone()))
+            .collect(),
+    )
+}
+
+/// The columns of two qualified rows together.
+fn join_rows(left: &Row, right: &Row) -> Row {
+    let mut row = left.clone();
+    row.columns
+        .extend(right.columns.iter().map(|(col, value)| (col.clone(), value.clone())));
+    row
 }
 
 impl Default for Executor {
@@ -254,9 +2000,19 @@ impl Default for Executor {
     }
 }
 
-#[derive(Debug, Clone)]
+/// What running a plan produced.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExecutionResult {
+    Rows(Vec<Row>),
+    /// Rows inserted, updated or deleted.
+    Count(usize),
+    /// A table was created or dropped, or already was.
+    Done,
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Row {
-    columns: HashMap<String, Value>,
+    pub(crate) columns: HashMap<String, Value>,
 }
 
 impl Row {
@@ -278,9 +2034,39 @@ impl Row {
         self.columns.get(name).cloned()
     }
     
+    /// Column lookup under a casing policy; an exact match always wins.
+    /// In a joined row a bare name also finds a qualified column, as long
+    /// as only one side has it.
+    pub fn find_column(&self, name: &str, casing: IdentifierCasing) -> Option<Value> {
+        self.get_column(name)
+            .or_else(|| {
+                self.columns
+                    .iter()
+                    .find(|(col, _)| casing.matches(col, name))
+                    .map(|(_, value)| value.clone())
+            })
+            .or_else(|| {
+                let mut found = self
+                    .columns
+                    .iter()
+                    .filter(|(col, _)| unqualified_matches(col, name, casing));
+                match (found.next(), found.next()) {
+                    (Some((_, value)), None) => Some(value.clone()),
+                    _ => None,
+                }
+            })
+    }
+    
     pub fn fields(&self) -> Vec<Value> {
         self.columns.values().cloned().collect()
     }
+    
+    /// The row's column names, sorted.
+    pub fn column_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.columns.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        names
+    }
 }
 
 #[derive(Debug, Clone)]
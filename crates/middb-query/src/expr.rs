@@ -1,5 +1,8 @@
 use std::fmt;
 use std::cmp::Ordering;
+use middb_core::catalog::{DataType, Datum};
+use middb_core::Timestamp;
+use crate::plan::LogicalPlan;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Expr {
@@ -10,21 +13,135 @@ pub enum Expr {
         left: Box<Expr>,
         right: Box<Expr>,
     },
+    Not(Box<Expr>),
+    IsNull {
+        expr: Box<Expr>,
+        negated: bool,
+    },
+    /// `%` matches any run of characters and `_` any one; a backslash
+    /// makes the next character literal.
+    Like {
+        expr: Box<Expr>,
+        pattern: Box<Expr>,
+        negated: bool,
+    },
+    InList {
+        expr: Box<Expr>,
+        list: Vec<Expr>,
+        negated: bool,
+    },
+    /// Inclusive at both ends.
+    Between {
+        expr: Box<Expr>,
+        low: Box<Expr>,
+        high: Box<Expr>,
+        negated: bool,
+    },
+    /// A call of a function registered with the executor, by name in any
+    /// case.
+    FunctionCall {
+        name: String,
+        args: Vec<Expr>,
+    },
+    /// Whether `expr` is among the values of the subquery's one column.
+    /// The subquery can't refer to the outer query's columns, so it runs
+    /// once however many rows ask.
+    InSubquery {
+        expr: Box<Expr>,
+        subplan: Box<LogicalPlan>,
+        negated: bool,
+    },
+    /// Whether the subquery produces any row.
+    Exists {
+        subplan: Box<LogicalPlan>,
+        negated: bool,
+    },
 }
 
-#[derive(Debug, Clone, PartialEq)]
+impl Expr {
+    /// The expressions directly inside this one.
+    pub fn children(&self) -> Vec<&Expr> {
+        match self {
+            Expr::Literal(_) | Expr::Column(_) => Vec::new(),
+            Expr::BinaryOp { left, right, .. } => vec![left, right],
+            Expr::Not(expr) | Expr::IsNull { expr, .. } => vec![expr],
+            Expr::Like { expr, pattern, .. } => vec![expr, pattern],
+            Expr::InList { expr, list, .. } => std::iter::once(&**expr).chain(list).collect(),
+            Expr::Between { expr, low, high, .. } => vec![expr, low, high],
+            Expr::FunctionCall { args, .. } => args.iter().collect(),
+            Expr::InSubquery { expr, .. } => vec![expr],
+            Expr::Exists { .. } => Vec::new(),
+        }
+    }
+}
+
+const TWO_POW_63: f64 = 9_223_372_036_854_775_808.0;
+
+/// A value in a row or expression. Floats are totally ordered as
+/// `f64::total_cmp` orders them, -NaN, -inf, ..., 0.0, ..., inf, NaN, but
+/// with -0.0 the same as 0.0. So NaN equals itself and sorts above every
+/// number. Equality is `compare`'s: numbers of any kind are equal when
+/// their values are, so `Int(3)`, `Float(3.0)` and 3.00 all equal.
+#[derive(Debug, Clone)]
 pub enum Value {
     Int(i64),
     String(String),
     Bool(bool),
     Bytes(Vec<u8>),
+    Float(f64),
+    Timestamp(Timestamp),
+    /// Fixed-point number: `value / 10^scale`.
+    Decimal { value: i128, scale: u8 },
     Null,
 }
 
+impl PartialEq for Value {
+    fn eq(&self, other: &Value) -> bool {
+        self.compare(other) == Some(Ordering::Equal)
+    }
+}
+
+/// Floats print in their shortest form that parses back to the same value,
+/// always with a point or exponent so they don't read as integers.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Null => write!(f, "NULL"),
+            Value::Int(i) => write!(f, "{}", i),
+            Value::Float(x) => write!(f, "{:?}", x),
+            Value::String(s) => write!(f, "{}", s),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Bytes(bytes) => {
+                write!(f, "x'")?;
+                for b in bytes {
+                    write!(f, "{:02x}", b)?;
+                }
+                write!(f, "'")
+            }
+            Value::Timestamp(t) => write!(f, "{}", t),
+            Value::Decimal { value, scale } => write!(f, "{}", decimal_text(*value, *scale)),
+        }
+    }
+}
+
 impl Value {
+    /// The integer, or a float with no fraction that an `i64` holds exactly.
     pub fn as_int(&self) -> Option<i64> {
         match self {
             Value::Int(i) => Some(*i),
+            Value::Float(f) if f.fract() == 0.0 && *f >= -TWO_POW_63 && *f < TWO_POW_63 => {
+                Some(*f as i64)
+            }
+            _ => None,
+        }
+    }
+    
+    /// The float, or an integer small enough (|i| <= 2^53) for an `f64` to
+    /// hold exactly.
+    pub fn as_float(&self) -> Option<f64> {
+        match self {
+            Value::Float(f) => Some(*f),
+            Value::Int(i) if i.unsigned_abs() <= 1 << 53 => Some(*i as f64),
             _ => None,
         }
     }
@@ -56,12 +173,159 @@ impl Value {
             (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
             (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
             (Value::Bytes(a), Value::Bytes(b)) => Some(a.cmp(b)),
+            (Value::Float(a), Value::Float(b)) => Some(float_cmp(*a, *b)),
+            (Value::Timestamp(a), Value::Timestamp(b)) => Some(a.cmp(b)),
             (Value::Null, Value::Null) => Some(Ordering::Equal),
+            (Value::Int(a), Value::Float(b)) => Some(compare_int_float(*a, *b)),
+            (Value::Float(a), Value::Int(b)) => Some(compare_int_float(*b, *a).reverse()),
+            (a, b) => match (a.as_decimal(), b.as_decimal()) {
+                (Some((av, ascale)), Some((bv, bscale))) => {
+                    Some(compare_decimal(av, ascale, bv, bscale))
+                }
+                _ => match (a, b) {
+                    (Value::Decimal { .. }, Value::Float(f)) => {
+                        Some(float_cmp(decimal_to_f64(a), *f))
+                    }
+                    (Value::Float(f), Value::Decimal { .. }) => {
+                        Some(float_cmp(*f, decimal_to_f64(b)))
+                    }
+                    _ => None,
+                },
+            },
+        }
+    }
+    
+    /// This value as a hash table key: values that compare equal get the
+    /// same key, so `Int(1)`, `Float(1.0)` and 1.00 collide. NULL has no key
+    /// since it equals nothing.
+    pub(crate) fn hash_key(&self) -> Option<Datum> {
+        let float_key = |f: f64| {
+            if f.fract() == 0.0 && f >= i64::MIN as f64 && f < i64::MAX as f64 {
+                Datum::Int(f as i64)
+            } else {
+                Datum::Float(f)
+            }
+        };
+        Some(match self {
+            Value::Null => return None,
+            Value::Float(f) => float_key(*f),
+            Value::Decimal { value, scale } => {
+                let whole = rescale(1, *scale)
+                    .filter(|unit| value % unit == 0)
+                    .and_then(|unit| i64::try_from(value / unit).ok());
+                match whole {
+                    Some(i) => Datum::Int(i),
+                    None => float_key(decimal_to_f64(self)),
+                }
+            }
+            other => Datum::from(other.clone()),
+        })
+    }
+    
+    /// Integer view of a decimal or integer value as `(unscaled, scale)`.
+    fn as_decimal(&self) -> Option<(i128, u8)> {
+        match self {
+            Value::Int(i) => Some((*i as i128, 0)),
+            Value::Decimal { value, scale } => Some((*value, *scale)),
             _ => None,
         }
     }
 }
 
+impl From<Datum> for Value {
+    fn from(datum: Datum) -> Self {
+        match datum {
+            Datum::Null => Value::Null,
+            Datum::Int(i) => Value::Int(i),
+            Datum::Float(f) => Value::Float(f),
+            Datum::String(s) => Value::String(s),
+            Datum::Bytes(b) => Value::Bytes(b),
+            Datum::Bool(b) => Value::Bool(b),
+            Datum::Timestamp(t) => Value::Timestamp(t),
+            Datum::Decimal { value, scale } => Value::Decimal { value, scale },
+        }
+    }
+}
+
+impl From<Value> for Datum {
+    fn from(value: Value) -> Self {
+        match value {
+            Value::Null => Datum::Null,
+            Value::Int(i) => Datum::Int(i),
+            Value::Float(f) => Datum::Float(f),
+            Value::String(s) => Datum::String(s),
+            Value::Bytes(b) => Datum::Bytes(b),
+            Value::Bool(b) => Datum::Bool(b),
+            Value::Timestamp(t) => Datum::Timestamp(t),
+            Value::Decimal { value, scale } => Datum::Decimal { value, scale },
+        }
+    }
+}
+
+/// A decimal written out in full, with `scale` digits after the point.
+pub(crate) fn decimal_text(value: i128, scale: u8) -> String {
+    let scale = scale as usize;
+    let digits = format!("{:0>width$}", value.unsigned_abs(), width = scale + 1);
+    let (whole, fraction) = digits.split_at(digits.len() - scale);
+    let sign = if value < 0 { "-" } else { "" };
+    match scale {
+        0 => format!("{}{}", sign, whole),
+        _ => format!("{}{}.{}", sign, whole, fraction),
+    }
+}
+
+pub(crate) fn decimal_to_f64(v: &Value) -> f64 {
+    match v {
+        Value::Decimal { value, scale } => *value as f64 / 10f64.powi(*scale as i32),
+        _ => f64::NAN,
+    }
+}
+
+/// `f64::total_cmp`, but with -0.0 equal to 0.0 as it is in arithmetic.
+fn float_cmp(a: f64, b: f64) -> Ordering {
+    let unsigned_zero = |f: f64| if f == 0.0 { 0.0 } else { f };
+    unsigned_zero(a).total_cmp(&unsigned_zero(b))
+}
+
+/// Compares an integer with a float by their exact values. Converting the
+/// integer to f64 would round integers beyond 2^53, so 2^53 + 1 would equal
+/// the float 2^53; instead the float's whole part is compared as an
+/// integer and its fraction breaks ties. NaN orders as `total_cmp` has it:
+/// above every integer, or below if its sign bit is set.
+fn compare_int_float(i: i64, f: f64) -> Ordering {
+    if f.is_nan() {
+        return (i as f64).total_cmp(&f);
+    }
+    if f >= TWO_POW_63 {
+        return Ordering::Less;
+    }
+    if f < -TWO_POW_63 {
+        return Ordering::Greater;
+    }
+    let whole = f.trunc();
+    match i.cmp(&(whole as i64)) {
+        Ordering::Equal => 0f64.total_cmp(&(f - whole)),
+        ord => ord,
+    }
+}
+
+/// Compares two scaled integers exactly by lifting the smaller scale.
+fn compare_decimal(a: i128, a_scale: u8, b: i128, b_scale: u8) -> Ordering {
+    if a_scale >= b_scale {
+        match rescale(b, a_scale - b_scale) {
+            Some(b) => a.cmp(&b),
+            // b no longer fits, so its magnitude dominates a's.
+            None => 0.cmp(&b),
+        }
+    } else {
+        compare_decimal(b, b_scale, a, a_scale).reverse()
+    }
+}
+
+fn rescale(value: i128, by: u8) -> Option<i128> {
+    10i128.checked_pow(by as u32).and_then(|f| value.checked_mul(f))
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BinaryOperator {
     Eq,
@@ -72,16 +336,199 @@ pub enum BinaryOperator {
     Ge,
     And,
     Or,
+    Plus,
+    Minus,
+    Multiply,
+    /// Integers divide to an integer, rounding toward zero.
+    Divide,
+}
+
+impl BinaryOperator {
+    pub fn is_arithmetic(&self) -> bool {
+        matches!(
+            self,
+            BinaryOperator::Plus
+                | BinaryOperator::Minus
+                | BinaryOperator::Multiply
+                | BinaryOperator::Divide
+        )
+    }
+}
+
+impl fmt::Display for BinaryOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            BinaryOperator::Eq => "=",
+            BinaryOperator::Ne => "<>",
+            BinaryOperator::Lt => "<",
+            BinaryOperator::Le => "<=",
+            BinaryOperator::Gt => ">",
+            BinaryOperator::Ge => ">=",
+            BinaryOperator::And => "AND",
+            BinaryOperator::Or => "OR",
+            BinaryOperator::Plus => "+",
+            BinaryOperator::Minus => "-",
+            BinaryOperator::Multiply => "*",
+            BinaryOperator::Divide => "/",
+        })
+    }
+}
+
+/// `left op right` for an arithmetic `op`. Integers and decimals stay
+/// exact, and a float on either side makes the result a float. `None` on
+/// overflow, division by zero, or an operand that isn't a number.
+pub(crate) fn arithmetic(op: BinaryOperator, left: &Value, right: &Value) -> Option<Value> {
+    if matches!(left, Value::Float(_)) || matches!(right, Value::Float(_)) {
+        let (a, b) = (number_to_f64(left)?, number_to_f64(right)?);
+        return Some(Value::Float(match op {
+            BinaryOperator::Plus => a + b,
+            BinaryOperator::Minus => a - b,
+            BinaryOperator::Multiply => a * b,
+            BinaryOperator::Divide => a / b,
+            _ => return None,
+        }));
+    }
+    if let (Value::Int(a), Value::Int(b)) = (left, right) {
+        return match op {
+            BinaryOperator::Plus => a.checked_add(*b),
+            BinaryOperator::Minus => a.checked_sub(*b),
+            BinaryOperator::Multiply => a.checked_mul(*b),
+            BinaryOperator::Divide => a.checked_div(*b),
+            _ => None,
+        }
+        .map(Value::Int);
+    }
+    
+    let ((a, a_scale), (b, b_scale)) = (left.as_decimal()?, right.as_decimal()?);
+    let (value, scale) = match op {
+        BinaryOperator::Plus | BinaryOperator::Minus => {
+            let scale = a_scale.max(b_scale);
+            let (a, b) = (rescale(a, scale - a_scale)?, rescale(b, scale - b_scale)?);
+            match op {
+                BinaryOperator::Plus => (a.checked_add(b)?, scale),
+                _ => (a.checked_sub(b)?, scale),
+            }
+        }
+        BinaryOperator::Multiply => (a.checked_mul(b)?, a_scale.checked_add(b_scale)?),
+        BinaryOperator::Divide if b == 0 => return None,
+        BinaryOperator::Divide => {
+            return Some(Value::Float(number_to_f64(left)? / number_to_f64(right)?));
+        }
+        _ => return None,
+    };
+    if scale > DataType::MAX_DECIMAL_PRECISION {
+        return None;
+    }
+    Some(Value::Decimal { value, scale })
+}
+
+fn number_to_f64(value: &Value) -> Option<f64> {
+    match value {
+        Value::Float(f) => Some(*f),
+        Value::Int(i) => Some(*i as f64),
+        Value::Decimal { .. } => Some(decimal_to_f64(value)),
+        _ => None,
+    }
+}
+
+/// `value` as SQL would spell it, so strings come quoted.
+fn sql_literal(value: &Value) -> String {
+    match value {
+        Value::String(s) => format!("'{}'", s.replace('\'', "''")),
+        Value::Bool(true) => "TRUE".to_string(),
+        Value::Bool(false) => "FALSE".to_string(),
+        Value::Timestamp(t) => format!("'{}'", t),
+        other => other.to_string(),
+    }
 }
 
 impl fmt::Display for Expr {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let not = |negated: &bool| if *negated { "NOT " } else { "" };
         match self {
-            Expr::Literal(v) => write!(f, "{:?}", v),
+            Expr::Literal(v) => write!(f, "{}", sql_literal(v)),
             Expr::Column(name) => write!(f, "{}", name),
             Expr::BinaryOp { op, left, right } => {
-                write!(f, "({} {:?} {})", left, op, right)
+                write!(f, "({} {} {})", left, op, right)
+            }
+            Expr::Not(expr) => write!(f, "(NOT {})", expr),
+            Expr::IsNull { expr, negated } => write!(f, "({} IS {}NULL)", expr, not(negated)),
+            Expr::Like { expr, pattern, negated } => {
+                write!(f, "({} {}LIKE {})", expr, not(negated), pattern)
+            }
+            Expr::InList { expr, list, negated } => {
+                let list: Vec<String> = list.iter().map(|e| e.to_string()).collect();
+                write!(f, "({} {}IN ({}))", expr, not(negated), list.join(", "))
+            }
+            Expr::Between { expr, low, high, negated } => {
+                write!(f, "({} {}BETWEEN {} AND {})", expr, not(negated), low, high)
+            }
+            Expr::FunctionCall { name, args } => {
+                write!(f, "{}(", name)?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", arg)?;
+                }
+                write!(f, ")")
+            }
+            Expr::InSubquery { expr, negated, .. } => {
+                write!(f, "({} {}IN (subquery))", expr, not(negated))
+            }
+            Expr::Exists { negated, .. } => write!(f, "({}EXISTS (subquery))", not(negated)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum LikeToken {
+    Any,
+    One,
+    Char(char),
+}
+
+/// Whether `text` matches the LIKE `pattern`.
+pub(crate) fn like_match(text: &str, pattern: &str) -> bool {
+    let mut tokens = Vec::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        tokens.push(match c {
+            '%' => LikeToken::Any,
+            '_' => LikeToken::One,
+            '\\' => LikeToken::Char(chars.next().unwrap_or('\\')),
+            c => LikeToken::Char(c),
+        });
+    }
+    let text: Vec<char> = text.chars().collect();
+    
+    // Match greedily, and on a mismatch let the last `%` take one more
+    // character.
+    let (mut t, mut p) = (0, 0);
+    let mut last_any: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match tokens.get(p) {
+            Some(LikeToken::One) => {
+                t += 1;
+                p += 1;
+            }
+            Some(LikeToken::Char(c)) if *c == text[t] => {
+                t += 1;
+                p += 1;
+            }
+            Some(LikeToken::Any) => {
+                last_any = Some((p, t));
+                p += 1;
             }
+            _ => match last_any {
+                Some((any, from)) => {
+                    last_any = Some((any, from + 1));
+                    p = any + 1;
+                    t = from + 1;
+                }
+                None => return false,
+            },
         }
     }
+    tokens[p..].iter().all(|token| *token == LikeToken::Any)
 }
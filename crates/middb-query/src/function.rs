@@ -0,0 +1,241 @@
+use crate::expr::{decimal_text, Value};
+use middb_core::catalog::DataType;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// What one argument of a function must be.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParamType {
+    Any,
+    Numeric,
+    Exactly(DataType),
+}
+
+impl ParamType {
+    fn accepts(&self, data_type: &DataType) -> bool {
+        match self {
+            ParamType::Any => true,
+            ParamType::Numeric => data_type.is_numeric(),
+            ParamType::Exactly(expected) => expected.is_compatible(data_type),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReturnType {
+    Fixed(DataType),
+    /// The type of the first argument whose type is known.
+    FirstArgument,
+}
+
+type Eval = dyn Fn(&[Value]) -> Option<Value> + Send + Sync;
+
+/// A function callable from expressions: its signature, used to check
+/// calls before running them, and how to evaluate it.
+///
+/// Unless built with `null_aware`, a call with a NULL argument is NULL
+/// without `eval` seeing it. `eval` returns `None` for arguments it can't
+/// compute a value from.
+#[derive(Clone)]
+pub struct ScalarFunction {
+    params: Vec<ParamType>,
+    optional: usize,
+    variadic: bool,
+    returns: ReturnType,
+    null_aware: bool,
+    eval: Arc<Eval>,
+}
+
+impl ScalarFunction {
+    pub fn new(
+        params: Vec<ParamType>,
+        returns: ReturnType,
+        eval: impl Fn(&[Value]) -> Option<Value> + Send + Sync + 'static,
+    ) -> Self {
+        ScalarFunction {
+            params,
+            optional: 0,
+            variadic: false,
+            returns,
+            null_aware: false,
+            eval: Arc::new(eval),
+        }
+    }
+    
+    /// Let callers leave out the last `count` parameters.
+    pub fn with_optional(mut self, count: usize) -> Self {
+        self.optional = count;
+        self
+    }
+    
+    /// Accept any number of further arguments of the last parameter's type.
+    pub fn variadic(mut self) -> Self {
+        self.variadic = true;
+        self
+    }
+    
+    /// Pass NULL arguments through to `eval` instead of returning NULL.
+    pub fn null_aware(mut self) -> Self {
+        self.null_aware = true;
+        self
+    }
+    
+    pub fn returns(&self) -> ReturnType {
+        self.returns
+    }
+    
+    /// The type expected of argument `i`, if the function takes that many.
+    pub fn param(&self, i: usize) -> Option<ParamType> {
+        match self.params.get(i) {
+            Some(param) => Some(*param),
+            None if self.variadic => self.params.last().copied(),
+            None => None,
+        }
+    }
+    
+    /// Check a call of `name` with arguments of the given types, `None`
+    /// where a type isn't known until the call runs.
+    pub fn check_call(&self, name: &str, args: &[Option<DataType>]) -> Result<(), String> {
+        let min = self.params.len() - self.optional;
+        let max = self.params.len();
+        let arity_ok = args.len() >= min && (self.variadic || args.len() <= max);
+        if !arity_ok {
+            let expected = match (self.variadic, min == max) {
+                (true, _) => format!("at least {}", min),
+                (false, true) => min.to_string(),
+                (false, false) => format!("{} to {}", min, max),
+            };
+            return Err(format!(
+                "{} takes {} argument{}, got {}",
+                name,
+                expected,
+                if min == 1 && max == 1 { "" } else { "s" },
+                args.len()
+            ));
+        }
+        for (i, arg) in args.iter().enumerate() {
+            let (Some(param), Some(arg)) = (self.param(i), arg) else {
+                continue;
+            };
+            if !param.accepts(arg) {
+                let expected = match param {
+                    ParamType::Exactly(t) => t.to_string(),
+                    _ => "numeric".to_string(),
+                };
+                return Err(format!(
+                    "argument {} of {} must be {}, got {}",
+                    i + 1,
+                    name,
+                    expected,
+                    arg
+                ));
+            }
+        }
+        Ok(())
+    }
+    
+    pub fn call(&self, args: &[Value]) -> Option<Value> {
+        if !self.null_aware && args.contains(&Value::Null) {
+            return Some(Value::Null);
+        }
+        (self.eval)(args)
+    }
+}
+
+/// The functions every executor starts with, by upper-case name.
+pub(crate) fn builtins() -> HashMap<String, ScalarFunction> {
+    use DataType::{Int64, String as Text};
+    let text = ParamType::Exactly(Text);
+    let int = ParamType::Exactly(Int64);
+    let string_fn = |f: fn(&str) -> String| {
+        ScalarFunction::new(vec![text], ReturnType::Fixed(Text), move |args| {
+            Some(Value::String(f(args[0].as_string()?)))
+        })
+    };
+    
+    let functions = [
+        ("UPPER", string_fn(str::to_uppercase)),
+        ("LOWER", string_fn(str::to_lowercase)),
+        (
+            "LENGTH",
+            ScalarFunction::new(vec![text], ReturnType::Fixed(Int64), |args| {
+                Some(Value::Int(args[0].as_string()?.chars().count() as i64))
+            }),
+        ),
+        (
+            "ABS",
+            ScalarFunction::new(vec![ParamType::Numeric], ReturnType::FirstArgument, |args| {
+                match &args[0] {
+                    Value::Int(i) => i.checked_abs().map(Value::Int),
+                    Value::Float(f) => Some(Value::Float(f.abs())),
+                    Value::Decimal { value, scale } => value
+                        .checked_abs()
+                        .map(|value| Value::Decimal { value, scale: *scale }),
+                    _ => None,
+                }
+            }),
+        ),
+        (
+            "COALESCE",
+            ScalarFunction::new(vec![ParamType::Any], ReturnType::FirstArgument, |args| {
+                Some(args.iter().find(|v| **v != Value::Null).cloned().unwrap_or(Value::Null))
+            })
+            .variadic()
+            .null_aware(),
+        ),
+        (
+            "SUBSTR",
+            ScalarFunction::new(vec![text, int, int], ReturnType::Fixed(Text), substr)
+                .with_optional(1),
+        ),
+        (
+            "CONCAT",
+            ScalarFunction::new(vec![ParamType::Any], ReturnType::Fixed(Text), |args| {
+                let mut out = String::new();
+                for arg in args {
+                    out.push_str(&text_of(arg)?);
+                }
+                Some(Value::String(out))
+            })
+            .variadic(),
+        ),
+    ];
+    functions
+        .into_iter()
+        .map(|(name, function)| (name.to_string(), function))
+        .collect()
+}
+
+/// `SUBSTR(text, start [, length])`: characters counted from 1. Positions
+/// before the first character count toward `length` but yield nothing.
+fn substr(args: &[Value]) -> Option<Value> {
+    let text = args[0].as_string()?;
+    let start = args[1].as_int()?;
+    let end = match args.get(2) {
+        Some(length) => {
+            let length = length.as_int()?;
+            if length < 0 {
+                return None;
+            }
+            start.saturating_add(length)
+        }
+        None => i64::MAX,
+    };
+    let skip = start.max(1) - 1;
+    let take = end.saturating_sub(start.max(1)).max(0);
+    Some(Value::String(
+        text.chars().skip(skip as usize).take(take as usize).collect(),
+    ))
+}
+
+/// A value as CONCAT writes it. Bytes and timestamps have no text form.
+fn text_of(value: &Value) -> Option<String> {
+    Some(match value {
+        Value::String(s) => s.clone(),
+        Value::Int(i) => i.to_string(),
+        Value::Float(f) => f.to_string(),
+        Value::Bool(b) => b.to_string(),
+        Value::Decimal { value, scale } => decimal_text(*value, *scale),
+        Value::Bytes(_) | Value::Timestamp(_) | Value::Null => return None,
+    })
+}
@@ -0,0 +1,112 @@
+use crate::executor::Row;
+use crate::expr::Value;
+use middb_core::catalog::{IdentifierCasing, IndexDef};
+use middb_core::BPTree;
+use std::cmp::Ordering;
+use std::ops::Bound;
+
+const FANOUT: usize = 32;
+
+/// A column value ordered for an index: by `Value::compare`, and by type
+/// where two values don't compare.
+#[derive(Debug, Clone)]
+pub(crate) struct IndexKey(pub(crate) Value);
+
+impl IndexKey {
+    fn type_rank(&self) -> u8 {
+        match self.0 {
+            Value::Null => 0,
+            Value::Bool(_) => 1,
+            Value::Int(_) | Value::Float(_) | Value::Decimal { .. } => 2,
+            Value::Timestamp(_) => 3,
+            Value::String(_) => 4,
+            Value::Bytes(_) => 5,
+        }
+    }
+}
+
+impl Ord for IndexKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0
+            .compare(&other.0)
+            .unwrap_or_else(|| self.type_rank().cmp(&other.type_rank()))
+    }
+}
+
+impl PartialOrd for IndexKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for IndexKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for IndexKey {}
+
+/// A B+ tree from the values of an index's leading column to the positions
+/// of the rows holding them. NULLs aren't indexed, since no range matches
+/// them.
+pub(crate) struct ColumnIndex {
+    pub(crate) def: IndexDef,
+    tree: BPTree<FANOUT, IndexKey, Vec<usize>>,
+}
+
+impl ColumnIndex {
+    pub(crate) fn build(def: IndexDef, rows: &[Row], casing: IdentifierCasing) -> Self {
+        let mut index = ColumnIndex {
+            def,
+            tree: BPTree::new(),
+        };
+        for (position, row) in rows.iter().enumerate() {
+            index.insert(row, position, casing);
+        }
+        index
+    }
+    
+    pub(crate) fn insert(&mut self, row: &Row, position: usize, casing: IdentifierCasing) {
+        let value = match row.find_column(&self.def.columns[0], casing) {
+            None | Some(Value::Null) => return,
+            Some(value) => value,
+        };
+        let key = IndexKey(value);
+        let mut positions = self.tree.get(&key).unwrap_or_default();
+        positions.push(position);
+        self.tree.insert(key, positions);
+    }
+    
+    /// Positions of the rows whose value falls in `range`, in key order.
+    pub(crate) fn lookup(&self, range: &(Bound<Value>, Bound<Value>)) -> Vec<usize> {
+        let entries: Box<dyn Iterator<Item = (IndexKey, Vec<usize>)>> = match &range.0 {
+            Bound::Included(low) | Bound::Excluded(low) => {
+                Box::new(self.tree.range_from(&IndexKey(low.clone())))
+            }
+            Bound::Unbounded => Box::new(self.tree.iter()),
+        };
+        let above_low = |key: &IndexKey| match &range.0 {
+            Bound::Excluded(low) => key.0.compare(low) == Some(Ordering::Greater),
+            Bound::Included(low) => key.0.compare(low).is_some_and(|o| o != Ordering::Less),
+            Bound::Unbounded => true,
+        };
+        let below_high = |key: &IndexKey| match &range.1 {
+            Bound::Excluded(high) => key.0.compare(high) == Some(Ordering::Less),
+            Bound::Included(high) => key.0.compare(high).is_some_and(|o| o != Ordering::Greater),
+            Bound::Unbounded => true,
+        };
+        // Keys of another type than the bounds sort around the range and
+        // never fall in it.
+        let past_high = |key: &IndexKey| match &range.1 {
+            Bound::Excluded(high) => key.0.compare(high).is_some_and(|o| o != Ordering::Less),
+            Bound::Included(high) => key.0.compare(high) == Some(Ordering::Greater),
+            Bound::Unbounded => false,
+        };
+        entries
+            .take_while(|(key, _)| !past_high(key))
+            .filter(|(key, _)| above_low(key) && below_high(key))
+            .flat_map(|(_, positions)| positions)
+            .collect()
+    }
+}
@@ -0,0 +1,200 @@
+use crate::executor::Row;
+use crate::expr::{decimal_text, Value};
+use serde_json::{json, Map, Number, Value as Json};
+use std::io::{self, Write};
+
+/// Rows with the columns to show them under, in order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResultSet {
+    pub columns: Vec<String>,
+    pub rows: Vec<Row>,
+}
+
+impl ResultSet {
+    pub fn new(columns: Vec<String>, rows: Vec<Row>) -> Self {
+        ResultSet { columns, rows }
+    }
+    
+    /// `{"columns": [...], "rows": [[...], ...]}`, each row's values in
+    /// column order, and NULL for a column the row lacks.
+    pub fn to_json(&self) -> Json {
+        let rows: Vec<Json> = self
+            .rows
+            .iter()
+            .map(|row| {
+                let values = self.columns.iter().map(|c| value_to_json(&column_of(row, c)));
+                Json::Array(values.collect())
+            })
+            .collect();
+        json!({ "columns": self.columns, "rows": rows })
+    }
+    
+    pub fn from_json(json: &Json) -> Result<ResultSet, String> {
+        let columns: Vec<String> = json
+            .get("columns")
+            .and_then(Json::as_array)
+            .ok_or("result set has no \"columns\" array")?
+            .iter()
+            .map(|c| c.as_str().map(str::to_string).ok_or("column names must be strings"))
+            .collect::<Result<_, _>>()?;
+        let rows = json
+            .get("rows")
+            .and_then(Json::as_array)
+            .ok_or("result set has no \"rows\" array")?
+            .iter()
+            .enumerate()
+            .map(|(i, row)| {
+                let values = row
+                    .as_array()
+                    .filter(|values| values.len() == columns.len())
+                    .ok_or_else(|| {
+                        format!("row {} must be an array of {} values", i, columns.len())
+                    })?;
+                let values = columns
+                    .iter()
+                    .zip(values)
+                    .map(|(c, v)| Ok((c.clone(), value_from_json(v)?)))
+                    .collect::<Result<Vec<_>, String>>()?;
+                Ok(Row::new_with_values(values))
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(ResultSet { columns, rows })
+    }
+}
+
+impl Row {
+    /// The row as a JSON object, keyed by column name in sorted order.
+    pub fn to_json(&self) -> Json {
+        let columns = self
+            .columns
+            .iter()
+            .map(|(name, value)| (name.clone(), value_to_json(value)))
+            .collect::<Map<_, _>>();
+        Json::Object(columns)
+    }
+}
+
+/// Write each row as a JSON object on its own line, keys in the order of
+/// `columns`. Rows are written as they come, so a stream of rows needn't
+/// be held in memory.
+pub fn rows_to_ndjson(
+    mut writer: impl Write,
+    columns: &[String],
+    rows: impl IntoIterator<Item = Row>,
+) -> io::Result<()> {
+    for row in rows {
+        writer.write_all(b"{")?;
+        for (i, column) in columns.iter().enumerate() {
+            if i > 0 {
+                writer.write_all(b",")?;
+            }
+            serde_json::to_writer(&mut writer, column)?;
+            writer.write_all(b":")?;
+            serde_json::to_writer(&mut writer, &value_to_json(&column_of(&row, column)))?;
+        }
+        writer.write_all(b"}\n")?;
+    }
+    writer.flush()
+}
+
+fn column_of(row: &Row, column: &str) -> Value {
+    row.get_column(column).unwrap_or(Value::Null)
+}
+
+/// Integers, finite floats, booleans and strings map to their JSON
+/// counterparts and NULL to null. Other values are an object with one
+/// tagged key: `$bytes` (base64), `$decimal` (text, keeping the scale),
+/// `$timestamp`, or `$float` for NaN and the infinities.
+pub fn value_to_json(value: &Value) -> Json {
+    match value {
+        Value::Null => Json::Null,
+        Value::Int(i) => Json::from(*i),
+        Value::Bool(b) => Json::Bool(*b),
+        Value::String(s) => Json::String(s.clone()),
+        Value::Float(f) => match Number::from_f64(*f) {
+            Some(n) => Json::Number(n),
+            None => json!({ "$float": f.to_string() }),
+        },
+        Value::Bytes(b) => json!({ "$bytes": base64_encode(b) }),
+        Value::Decimal { value, scale } => json!({ "$decimal": decimal_text(*value, *scale) }),
+        Value::Timestamp(t) => json!({ "$timestamp": t }),
+    }
+}
+
+pub fn value_from_json(json: &Json) -> Result<Value, String> {
+    let invalid = || format!("not a value: {}", json);
+    Ok(match json {
+        Json::Null => Value::Null,
+        Json::Bool(b) => Value::Bool(*b),
+        Json::String(s) => Value::String(s.clone()),
+        Json::Number(n) => match n.as_i64() {
+            Some(i) if !n.is_f64() => Value::Int(i),
+            _ => Value::Float(n.as_f64().ok_or_else(invalid)?),
+        },
+        Json::Object(tagged) if tagged.len() == 1 => {
+            let (tag, inner) = tagged.iter().next().unwrap();
+            let value = match (tag.as_str(), inner) {
+                ("$bytes", Json::String(s)) => base64_decode(s).map(Value::Bytes),
+                ("$decimal", Json::String(s)) => parse_decimal(s),
+                ("$timestamp", Json::Number(n)) => n.as_u64().map(Value::Timestamp),
+                ("$float", Json::String(s)) => s.parse().ok().map(Value::Float),
+                _ => None,
+            };
+            value.ok_or_else(invalid)?
+        }
+        _ => return Err(invalid()),
+    })
+}
+
+/// `[-]digits[.digits]`, with as many digits of scale as follow the point.
+fn parse_decimal(text: &str) -> Option<Value> {
+    let (whole, fraction) = text.split_once('.').unwrap_or((text, ""));
+    let digits = whole.trim_start_matches('-');
+    let valid = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+    if digits.is_empty() || !valid(digits) || !valid(fraction) {
+        return None;
+    }
+    Some(Value::Decimal {
+        value: format!("{}{}", whole, fraction).parse().ok()?,
+        scale: u8::try_from(fraction.len()).ok()?,
+    })
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Standard base64, padded with `=`.
+fn base64_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            match i <= chunk.len() {
+                true => out.push(BASE64[(n >> (18 - 6 * i) & 0x3F) as usize] as char),
+                false => out.push('='),
+            }
+        }
+    }
+    out
+}
+
+fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let text = text.as_bytes();
+    if !text.len().is_multiple_of(4) {
+        return None;
+    }
+    let mut out = Vec::with_capacity(text.len() / 4 * 3);
+    for (c, chunk) in text.chunks(4).enumerate() {
+        let padding = chunk.iter().rev().take_while(|&&b| b == b'=').count();
+        let last = c == text.len() / 4 - 1;
+        if padding > 2 || (padding > 0 && !last) {
+            return None;
+        }
+        let mut n = 0u32;
+        for (i, &b) in chunk[..4 - padding].iter().enumerate() {
+            let digit = BASE64.iter().position(|&d| d == b)? as u32;
+            n |= digit << (18 - 6 * i);
+        }
+        out.extend_from_slice(&n.to_be_bytes()[1..4 - padding]);
+    }
+    Some(out)
+}
@@ -2,11 +2,30 @@ pub mod expr;
 pub mod plan;
 pub mod planner;
 pub mod executor;
+pub mod migrate;
+pub mod storage;
+pub mod stream;
+pub mod optimizer;
+pub mod function;
+pub mod csv;
+pub mod json;
+mod aggregate;
+mod index;
+pub mod sql;
 
 #[cfg(test)]
 mod tests;
 
 pub use expr::{Expr, Value, BinaryOperator};
-pub use plan::{LogicalPlan, PhysicalPlan};
-pub use planner::Planner;
-pub use executor::{Executor, Row, Table};
+pub use plan::{
+    AggExpr, AggFunc, JoinType, LogicalPlan, NullOrder, PhysicalPlan, SortOrder, TableRef,
+};
+pub use planner::{CostModel, Planner};
+pub use function::{ParamType, ReturnType, ScalarFunction};
+pub use executor::{ExecutionResult, Executor, Row, Table};
+pub use migrate::{decode_row, encode_row, RowMigrator};
+pub use storage::{StoredTable, TableProvider};
+pub use stream::RowStream;
+pub use csv::{CsvOptions, ImportError, RecordError};
+pub use json::{rows_to_ndjson, ResultSet};
+pub use sql::{ParseError, Statement};
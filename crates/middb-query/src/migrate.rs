@@ -0,0 +1,198 @@
+use crate::executor::Row;
+use crate::expr::Value;
+use middb_core::catalog::{AlterOp, TableSchema};
+use middb_core::Database;
+use std::collections::HashMap;
+
+/// Encode a row under the schema's current version. Columns the row omits are
+/// written as NULL; columns the schema doesn't know are dropped.
+///
+/// Layout: `version: u64`, `count: u32`, then per column its name and a tagged
+/// value. Names are stored so a row can be decoded without the schema it was
+/// written under.
+pub fn encode_row(schema: &TableSchema, row: &Row) -> Vec<u8> {
+    let mut buf = Vec::new();
+    buf.extend_from_slice(&schema.schema_version.to_le_bytes());
+    buf.extend_from_slice(&(schema.columns.len() as u32).to_le_bytes());
+    for col in &schema.columns {
+        put_bytes(&mut buf, col.name.as_bytes());
+        encode_value(&mut buf, row.columns.get(&col.name).unwrap_or(&Value::Null));
+    }
+    buf
+}
+
+/// Decode a row as stored, returning the schema version it was written under.
+pub fn decode_row(data: &[u8]) -> Result<(u64, Row), String> {
+    let mut reader = RowReader { data, pos: 0 };
+    let version = reader.u64()?;
+    let count = reader.u32()? as usize;
+    let mut columns = HashMap::with_capacity(count.min(1024));
+    for _ in 0..count {
+        let name = String::from_utf8(reader.bytes()?.to_vec()).map_err(|e| e.to_string())?;
+        columns.insert(name, reader.value()?);
+    }
+    if reader.pos != data.len() {
+        return Err("trailing bytes after row".to_string());
+    }
+    Ok((version, Row { columns }))
+}
+
+/// Brings rows written under an older schema version up to the current one
+/// by replaying the ALTERs recorded in the schema's history: added columns
+/// take their default (or NULL), dropped columns disappear, and renamed
+/// columns move.
+pub struct RowMigrator {
+    schema: TableSchema,
+}
+
+impl RowMigrator {
+    pub fn new(schema: TableSchema) -> Self {
+        RowMigrator { schema }
+    }
+    
+    pub fn schema(&self) -> &TableSchema {
+        &self.schema
+    }
+    
+    pub fn migrate(&self, from_version: u64, row: &mut Row) {
+        for op in self.schema.changes_since(from_version) {
+            match op {
+                AlterOp::AddColumn(col) => {
+                    let value = col.default.clone().map(Value::from).unwrap_or(Value::Null);
+                    row.columns.entry(col.name.clone()).or_insert(value);
+                }
+                AlterOp::DropColumn(name) => {
+                    row.columns.remove(name);
+                }
+                AlterOp::RenameColumn { from, to } => {
+                    if let Some(value) = row.columns.remove(from) {
+                        row.columns.insert(to.clone(), value);
+                    }
+                }
+            }
+        }
+    }
+    
+    /// Decode a stored row and migrate it lazily to the current version.
+    pub fn decode(&self, data: &[u8]) -> Result<Row, String> {
+        let (version, mut row) = decode_row(data)?;
+        if version > self.schema.schema_version {
+            return Err(format!(
+                "row of '{}' has schema version {} but the catalog is at {}",
+                self.schema.name, version, self.schema.schema_version
+            ));
+        }
+        self.migrate(version, &mut row);
+        Ok(row)
+    }
+    
+    /// Eagerly rewrite every stored row under `prefix` that is behind the
+    /// current schema version. Returns the number of rows rewritten.
+    pub fn migrate_all(&self, db: &Database, prefix: &[u8]) -> Result<usize, String> {
+        let mut rewritten = 0;
+        for (key, value) in db.scan_prefix(prefix).map_err(|e| e.to_string())? {
+            let (version, _) = decode_row(&value)?;
+            if version == self.schema.schema_version {
+                continue;
+            }
+            let row = self.decode(&value)?;
+            db.put(key, encode_row(&self.schema, &row))
+                .map_err(|e| e.to_string())?;
+            rewritten += 1;
+        }
+        Ok(rewritten)
+    }
+}
+
+fn put_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    buf.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+    buf.extend_from_slice(bytes);
+}
+
+fn encode_value(buf: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::Null => buf.push(0),
+        Value::Int(v) => {
+            buf.push(1);
+            buf.extend_from_slice(&v.to_le_bytes());
+        }
+        Value::Float(v) => {
+            buf.push(2);
+            buf.extend_from_slice(&v.to_bits().to_le_bytes());
+        }
+        Value::String(v) => {
+            buf.push(3);
+            put_bytes(buf, v.as_bytes());
+        }
+        Value::Bytes(v) => {
+            buf.push(4);
+            put_bytes(buf, v);
+        }
+        Value::Bool(v) => {
+            buf.push(5);
+            buf.push(*v as u8);
+        }
+        Value::Timestamp(v) => {
+            buf.push(6);
+            buf.extend_from_slice(&v.to_le_bytes());
+        }
+        Value::Decimal { value, scale } => {
+            buf.push(7);
+            buf.extend_from_slice(&value.to_le_bytes());
+            buf.push(*scale);
+        }
+    }
+}
+
+struct RowReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> RowReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.data.len() - self.pos < len {
+            return Err("row encoding truncated".to_string());
+        }
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+    
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+    
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+    
+    fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+    
+    fn bytes(&mut self) -> Result<&'a [u8], String> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+    
+    fn value(&mut self) -> Result<Value, String> {
+        let tag = self.u8()?;
+        Ok(match tag {
+            0 => Value::Null,
+            1 => Value::Int(self.u64()? as i64),
+            2 => Value::Float(f64::from_bits(self.u64()?)),
+            3 => Value::String(
+                String::from_utf8(self.bytes()?.to_vec()).map_err(|e| e.to_string())?,
+            ),
+            4 => Value::Bytes(self.bytes()?.to_vec()),
+            5 => Value::Bool(self.u8()? != 0),
+            6 => Value::Timestamp(self.u64()?),
+            7 => Value::Decimal {
+                value: i128::from_le_bytes(self.take(16)?.try_into().unwrap()),
+                scale: self.u8()?,
+            },
+            _ => return Err(format!("unknown value tag {}", tag)),
+        })
+    }
+}
@@ -0,0 +1,244 @@
+use crate::expr::{BinaryOperator, Expr, Value};
+use crate::plan::{projected_name, LogicalPlan};
+
+/// Move each conjunct of every filter as close to the scans as the columns
+/// it references allow: into a scan's own filter, below projections that
+/// produce those columns (with the projected expressions in their place), below sorts and DISTINCT, below aggregates when it
+/// only reads group keys, and into one side of a join when it names only that side.
+/// Single-sided conjuncts of a join's ON condition move the same way.
+/// Whatever can't move stays where it was.
+pub fn push_down_filters(plan: LogicalPlan) -> LogicalPlan {
+    push(plan, Vec::new())
+}
+
+/// Rewrite `plan` with `pending` conjuncts from above to be applied to its
+/// output.
+fn push(plan: LogicalPlan, mut pending: Vec<Expr>) -> LogicalPlan {
+    match plan {
+        LogicalPlan::Filter { input, predicate } => {
+            split_conjuncts(predicate, &mut pending);
+            push(*input, pending)
+        }
+        LogicalPlan::Scan { table, filter } => {
+            let mut conjuncts = Vec::new();
+            if let Some(filter) = filter {
+                split_conjuncts(filter, &mut conjuncts);
+            }
+            conjuncts.extend(pending);
+            LogicalPlan::Scan {
+                table,
+                filter: conjoin(conjuncts),
+            }
+        }
+        LogicalPlan::Project { input, columns } => {
+            let (mut down, mut keep) = (Vec::new(), Vec::new());
+            for conjunct in pending {
+                match inline_projection(&conjunct, &columns) {
+                    Some(inlined) => down.push(inlined),
+                    None => keep.push(conjunct),
+                }
+            }
+            let project = LogicalPlan::Project {
+                input: Box::new(push(*input, down)),
+                columns,
+            };
+            filter(project, keep)
+        }
+        LogicalPlan::Sort { input, keys, nulls } => LogicalPlan::Sort {
+            input: Box::new(push(*input, pending)),
+            keys,
+            nulls,
+        },
+        LogicalPlan::Distinct { input } => LogicalPlan::Distinct {
+            input: Box::new(push(*input, pending)),
+        },
+        // A filter below a limit would change which rows it keeps.
+        LogicalPlan::Limit { input, count } => {
+            let limit = LogicalPlan::Limit {
+                input: Box::new(push(*input, Vec::new())),
+                count,
+            };
+            filter(limit, pending)
+        }
+        // Inputs after the first may name their columns differently, so
+        // filters on the union stay above it.
+        LogicalPlan::Union { inputs, all } => {
+            let union = LogicalPlan::Union {
+                inputs: inputs.into_iter().map(|input| push(input, Vec::new())).collect(),
+                all,
+            };
+            filter(union, pending)
+        }
+        LogicalPlan::Aggregate { input, group_by, aggregates } => {
+            let (down, keep) = partition(pending, |name| group_by.iter().any(|g| g == name));
+            let aggregate = LogicalPlan::Aggregate {
+                input: Box::new(push(*input, down)),
+                group_by,
+                aggregates,
+            };
+            filter(aggregate, keep)
+        }
+        LogicalPlan::Join { left, right, left_name, right_name, on, join_type } => {
+            let mut on_conjuncts = Vec::new();
+            split_conjuncts(on, &mut on_conjuncts);
+            let (mut to_left, mut to_right) = (Vec::new(), Vec::new());
+            // A side that is itself a join keeps its inner tables' names, so
+            // its columns aren't found under the side's name.
+            let left_open = !matches!(*left, LogicalPlan::Join { .. });
+            let right_open = !matches!(*right, LogicalPlan::Join { .. });
+            let mut sort = |conjuncts: Vec<Expr>| {
+                let mut keep = Vec::new();
+                for conjunct in conjuncts {
+                    match (unqualify(&conjunct, &left_name), unqualify(&conjunct, &right_name)) {
+                        (Some(local), _) if left_open => to_left.push(local),
+                        (_, Some(local)) if right_open => to_right.push(local),
+                        _ => keep.push(conjunct),
+                    }
+                }
+                keep
+            };
+            let on = sort(on_conjuncts);
+            let keep = sort(pending);
+            let join = LogicalPlan::Join {
+                left: Box::new(push(*left, to_left)),
+                right: Box::new(push(*right, to_right)),
+                left_name,
+                right_name,
+                on: conjoin(on).unwrap_or(Expr::Literal(Value::Bool(true))),
+                join_type,
+            };
+            filter(join, keep)
+        }
+        statement @ (LogicalPlan::Insert { .. }
+        | LogicalPlan::Update { .. }
+        | LogicalPlan::Delete { .. }
+        | LogicalPlan::CreateTable { .. }
+        | LogicalPlan::DropTable { .. }) => filter(statement, pending),
+    }
+}
+
+fn filter(input: LogicalPlan, conjuncts: Vec<Expr>) -> LogicalPlan {
+    match conjoin(conjuncts) {
+        Some(predicate) => LogicalPlan::Filter {
+            input: Box::new(input),
+            predicate,
+        },
+        None => input,
+    }
+}
+
+/// Split conjuncts into those whose every column passes `visible`, and the
+/// rest.
+fn partition(conjuncts: Vec<Expr>, visible: impl Fn(&str) -> bool) -> (Vec<Expr>, Vec<Expr>) {
+    conjuncts.into_iter().partition(|conjunct| {
+        let mut columns = Vec::new();
+        referenced_columns(conjunct, &mut columns);
+        columns.into_iter().all(&visible)
+    })
+}
+
+fn split_conjuncts(expr: Expr, out: &mut Vec<Expr>) {
+    match expr {
+        Expr::BinaryOp { op: BinaryOperator::And, left, right } => {
+            split_conjuncts(*left, out);
+            split_conjuncts(*right, out);
+        }
+        other => out.push(other),
+    }
+}
+
+pub(crate) fn conjoin(conjuncts: Vec<Expr>) -> Option<Expr> {
+    conjuncts.into_iter().reduce(|acc, conjunct| Expr::BinaryOp {
+        op: BinaryOperator::And,
+        left: Box::new(acc),
+        right: Box::new(conjunct),
+    })
+}
+
+pub(crate) fn referenced_columns<'a>(expr: &'a Expr, out: &mut Vec<&'a str>) {
+    match expr {
+        Expr::Column(name) => out.push(name),
+        other => {
+            for child in other.children() {
+                referenced_columns(child, out);
+            }
+        }
+    }
+}
+
+/// `expr` as seen by the join side `side`, if all its columns are
+/// qualified with `side`'s name. Conjuncts without columns stay put.
+fn unqualify(expr: &Expr, side: &str) -> Option<Expr> {
+    let mut columns = Vec::new();
+    referenced_columns(expr, &mut columns);
+    let prefix = format!("{}.", side);
+    if columns.is_empty() || !columns.iter().all(|c| c.starts_with(&prefix)) {
+        return None;
+    }
+    Some(map_columns(expr, &|name| Expr::Column(name[prefix.len()..].to_string())))
+}
+
+/// `expr` as seen below a projection: each column it references replaced
+/// by the expression projected under that name. `None` if it references a
+/// column the projection doesn't produce.
+fn inline_projection(expr: &Expr, columns: &[(Expr, Option<String>)]) -> Option<Expr> {
+    let mut referenced = Vec::new();
+    referenced_columns(expr, &mut referenced);
+    let projected = |name: &str| {
+        columns
+            .iter()
+            .find(|(column, alias)| projected_name(column, alias.as_deref()) == name)
+            .map(|(column, _)| column)
+    };
+    if !referenced.into_iter().all(|name| projected(name).is_some()) {
+        return None;
+    }
+    Some(map_columns(expr, &|name| {
+        projected(name).cloned().unwrap_or_else(|| Expr::Column(name.to_string()))
+    }))
+}
+
+fn map_columns(expr: &Expr, f: &impl Fn(&str) -> Expr) -> Expr {
+    let map = |e: &Expr| Box::new(map_columns(e, f));
+    match expr {
+        Expr::Literal(value) => Expr::Literal(value.clone()),
+        Expr::Column(name) => f(name),
+        Expr::BinaryOp { op, left, right } => Expr::BinaryOp {
+            op: *op,
+            left: map(left),
+            right: map(right),
+        },
+        Expr::Not(inner) => Expr::Not(map(inner)),
+        Expr::IsNull { expr, negated } => Expr::IsNull {
+            expr: map(expr),
+            negated: *negated,
+        },
+        Expr::Like { expr, pattern, negated } => Expr::Like {
+            expr: map(expr),
+            pattern: map(pattern),
+            negated: *negated,
+        },
+        Expr::InList { expr, list, negated } => Expr::InList {
+            expr: map(expr),
+            list: list.iter().map(|e| map_columns(e, f)).collect(),
+            negated: *negated,
+        },
+        Expr::Between { expr, low, high, negated } => Expr::Between {
+            expr: map(expr),
+            low: map(low),
+            high: map(high),
+            negated: *negated,
+        },
+        Expr::FunctionCall { name, args } => Expr::FunctionCall {
+            name: name.clone(),
+            args: args.iter().map(|e| map_columns(e, f)).collect(),
+        },
+        // The subquery's columns are its own, so only the outer side maps.
+        Expr::InSubquery { expr, subplan, negated } => Expr::InSubquery {
+            expr: map(expr),
+            subplan: subplan.clone(),
+            negated: *negated,
+        },
+        Expr::Exists { .. } => expr.clone(),
+    }
+}
@@ -1,6 +1,141 @@
-use crate::expr::Expr;
+use crate::executor::Row;
+use crate::expr::{Expr, Value};
+use middb_core::catalog::TableSchema;
+use std::fmt;
+use std::ops::Bound;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+/// Where a sort puts NULLs (and missing columns), whatever the direction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NullOrder {
+    First,
+    #[default]
+    Last,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AggFunc {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+impl fmt::Display for AggFunc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name().to_uppercase())
+    }
+}
+
+impl AggFunc {
+    fn name(&self) -> &'static str {
+        match self {
+            AggFunc::Count => "count",
+            AggFunc::Sum => "sum",
+            AggFunc::Avg => "avg",
+            AggFunc::Min => "min",
+            AggFunc::Max => "max",
+        }
+    }
+}
+
+/// One aggregate output column. `column` is `None` only for `COUNT(*)`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AggExpr {
+    pub func: AggFunc,
+    pub column: Option<String>,
+    pub alias: Option<String>,
+}
+
+impl AggExpr {
+    pub fn count_star() -> Self {
+        AggExpr {
+            func: AggFunc::Count,
+            column: None,
+            alias: None,
+        }
+    }
+    
+    pub fn new(func: AggFunc, column: impl Into<String>) -> Self {
+        AggExpr {
+            func,
+            column: Some(column.into()),
+            alias: None,
+        }
+    }
+    
+    pub fn with_alias(mut self, alias: impl Into<String>) -> Self {
+        self.alias = Some(alias.into());
+        self
+    }
+    
+    /// The alias, or the function and column, e.g. `sum_price`, or `count`
+    /// for `COUNT(*)`.
+    pub fn output_name(&self) -> String {
+        match (&self.alias, &self.column) {
+            (Some(alias), _) => alias.clone(),
+            (None, Some(column)) => format!("{}_{}", self.func.name(), column),
+            (None, None) => self.func.name().to_string(),
+        }
+    }
+}
+
+/// The name a projected expression's column gets: the alias, else the
+/// column it reads as written, else the expression's SQL text, e.g.
+/// `SUBSTR(name, 0, 3)` or `price * 2`.
+pub fn projected_name(expr: &Expr, alias: Option<&str>) -> String {
+    match (alias, expr) {
+        (Some(alias), _) => alias.to_string(),
+        (None, Expr::Column(name)) => name.clone(),
+        (None, Expr::Literal(_) | Expr::FunctionCall { .. }) => expr.to_string(),
+        // The rest print inside one pair of parentheses, which the name
+        // doesn't need.
+        (None, expr) => {
+            let text = expr.to_string();
+            text[1..text.len() - 1].to_string()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum JoinType {
+    #[default]
+    Inner,
+}
+
+/// A table named in a join, optionally under an alias.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableRef {
+    pub table: String,
+    pub alias: Option<String>,
+}
+
+impl TableRef {
+    pub fn new(table: impl Into<String>) -> Self {
+        TableRef {
+            table: table.into(),
+            alias: None,
+        }
+    }
+    
+    pub fn with_alias(mut self, alias: impl Into<String>) -> Self {
+        self.alias = Some(alias.into());
+        self
+    }
+    
+    /// The name its columns are qualified with: the alias, else the table.
+    pub fn name(&self) -> &str {
+        self.alias.as_deref().unwrap_or(&self.table)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum LogicalPlan {
     Scan {
         table: String,
@@ -10,9 +145,81 @@ pub enum LogicalPlan {
         input: Box<LogicalPlan>,
         predicate: Expr,
     },
+    /// One output column per expression, named by its alias, else by the
+    /// column it reads or by the expression's text (see `projected_name`).
     Project {
         input: Box<LogicalPlan>,
-        columns: Vec<String>,
+        columns: Vec<(Expr, Option<String>)>,
+    },
+    /// Orders by each key in turn; rows equal on every key keep their input
+    /// order.
+    Sort {
+        input: Box<LogicalPlan>,
+        keys: Vec<(String, SortOrder)>,
+        nulls: NullOrder,
+    },
+    /// One row per distinct `group_by` key: the key columns, then one
+    /// column per aggregate. Without `group_by`, exactly one row.
+    Aggregate {
+        input: Box<LogicalPlan>,
+        group_by: Vec<String>,
+        aggregates: Vec<AggExpr>,
+    },
+    /// The input without duplicate rows, comparing every column and taking
+    /// NULLs as equal. The first of each set of duplicates is kept, so
+    /// input order survives.
+    Distinct {
+        input: Box<LogicalPlan>,
+    },
+    /// The first `count` rows of the input.
+    Limit {
+        input: Box<LogicalPlan>,
+        count: u64,
+    },
+    /// Pairs of rows from both inputs that satisfy `on`. Each column is
+    /// named `<side name>.<column>`; columns already qualified by an inner
+    /// join keep their names.
+    Join {
+        left: Box<LogicalPlan>,
+        right: Box<LogicalPlan>,
+        left_name: String,
+        right_name: String,
+        on: Expr,
+        join_type: JoinType,
+    },
+    /// The rows of every input in turn, or with `all` unset, without
+    /// duplicates. Inputs line up by column position, and the columns are
+    /// named as the first input names them.
+    Union {
+        inputs: Vec<LogicalPlan>,
+        all: bool,
+    },
+    Insert {
+        table: String,
+        rows: Vec<Row>,
+    },
+    /// Sets each assigned column to its expression, evaluated against the
+    /// row before the update.
+    Update {
+        table: String,
+        assignments: Vec<(String, Expr)>,
+        filter: Option<Expr>,
+    },
+    Delete {
+        table: String,
+        filter: Option<Expr>,
+    },
+    /// With `if_not_exists`, a table already called `schema.name` is left
+    /// as it is instead of being an error.
+    CreateTable {
+        schema: TableSchema,
+        if_not_exists: bool,
+    },
+    /// Removes the table and its rows. With `if_exists`, a missing table
+    /// isn't an error.
+    DropTable {
+        name: String,
+        if_exists: bool,
     },
 }
 
@@ -22,12 +229,82 @@ pub enum PhysicalPlan {
         table: String,
         filter: Option<Expr>,
     },
+    /// The rows whose value of `index`'s leading column lies in `range`.
+    IndexScan {
+        table: String,
+        index: String,
+        range: (Bound<Value>, Bound<Value>),
+    },
     Filter {
         input: Box<PhysicalPlan>,
         predicate: Expr,
     },
     Project {
         input: Box<PhysicalPlan>,
-        columns: Vec<String>,
+        columns: Vec<(Expr, Option<String>)>,
+    },
+    Sort {
+        input: Box<PhysicalPlan>,
+        keys: Vec<(String, SortOrder)>,
+        nulls: NullOrder,
+    },
+    HashAggregate {
+        input: Box<PhysicalPlan>,
+        group_by: Vec<String>,
+        aggregates: Vec<AggExpr>,
+    },
+    /// Remembers a key for each distinct row seen, and drops the rest.
+    HashDistinct {
+        input: Box<PhysicalPlan>,
+    },
+    /// Stops pulling from the input after `count` rows.
+    Limit {
+        input: Box<PhysicalPlan>,
+        count: u64,
+    },
+    NestedLoopJoin {
+        left: Box<PhysicalPlan>,
+        right: Box<PhysicalPlan>,
+        left_name: String,
+        right_name: String,
+        on: Expr,
+        join_type: JoinType,
+    },
+    /// Inner equi-join: hashes `build` on `build_key`, then looks up each
+    /// `probe` row's `probe_key`. Rows are named as for a `Join`.
+    HashJoin {
+        build: Box<PhysicalPlan>,
+        probe: Box<PhysicalPlan>,
+        build_name: String,
+        probe_name: String,
+        build_key: String,
+        probe_key: String,
+    },
+    /// Pulls each input's rows in turn, renamed by position to the first
+    /// input's columns and widened to the types the inputs share. A plain
+    /// UNION is this under a `HashDistinct`.
+    Union {
+        inputs: Vec<PhysicalPlan>,
+    },
+    Insert {
+        table: String,
+        rows: Vec<Row>,
+    },
+    Update {
+        table: String,
+        assignments: Vec<(String, Expr)>,
+        filter: Option<Expr>,
+    },
+    Delete {
+        table: String,
+        filter: Option<Expr>,
+    },
+    CreateTable {
+        schema: TableSchema,
+        if_not_exists: bool,
+    },
+    DropTable {
+        name: String,
+        if_exists: bool,
     },
 }
@@ -1,11 +1,66 @@
-use crate::expr::Expr;
-use crate::plan::{LogicalPlan, PhysicalPlan};
+use crate::executor::Row;
+use crate::expr::{decimal_to_f64, BinaryOperator, Expr, Value};
+use crate::optimizer::{conjoin, push_down_filters};
+use crate::plan::{
+    projected_name, AggExpr, JoinType, LogicalPlan, NullOrder, PhysicalPlan, SortOrder, TableRef,
+};
+use middb_core::catalog::{Catalog, ColumnStats, IdentifierCasing, TableSchema, TableStats};
+use std::cmp::Ordering;
+use std::ops::Bound;
+use std::sync::{Arc, RwLock};
 
-pub struct Planner;
+/// What a scan costs per row it touches, in arbitrary units. A sequential
+/// scan pays `row` for every row of the table; an index scan pays
+/// `index_probe + fetch` for each row in its range.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CostModel {
+    pub row: f64,
+    pub index_probe: f64,
+    pub fetch: f64,
+}
+
+impl Default for CostModel {
+    fn default() -> Self {
+        CostModel {
+            row: 1.0,
+            index_probe: 1.0,
+            fetch: 3.0,
+        }
+    }
+}
+
+/// Share of rows an equality keeps when its column has no statistics.
+const EQ_SELECTIVITY: f64 = 0.1;
+/// Share of rows a range keeps when it can't be placed between the
+/// column's minimum and maximum.
+const RANGE_SELECTIVITY: f64 = 1.0 / 3.0;
+/// Share of rows any other predicate keeps.
+const OTHER_SELECTIVITY: f64 = 0.5;
+
+pub struct Planner {
+    /// Schemas and statistics for physical planning, if available.
+    catalog: Option<Arc<RwLock<Catalog>>>,
+    costs: CostModel,
+}
 
 impl Planner {
     pub fn new() -> Self {
-        Planner
+        Planner {
+            catalog: None,
+            costs: CostModel::default(),
+        }
+    }
+    
+    pub fn with_catalog(catalog: Arc<RwLock<Catalog>>) -> Self {
+        Planner {
+            catalog: Some(catalog),
+            costs: CostModel::default(),
+        }
+    }
+    
+    pub fn with_cost_model(mut self, costs: CostModel) -> Self {
+        self.costs = costs;
+        self
     }
     
     pub fn plan(&self, scan_table: String, filter: Option<Expr>) -> LogicalPlan {
@@ -15,26 +70,676 @@ impl Planner {
         }
     }
     
+    /// A scan of `table`, narrowed to `projection` if given. Column names
+    /// are kept as written in `projection`.
+    pub fn plan_select(
+        &self,
+        table: String,
+        projection: Option<Vec<String>>,
+        filter: Option<Expr>,
+    ) -> LogicalPlan {
+        let scan = self.plan(table, filter);
+        match projection {
+            Some(columns) => {
+                let columns = columns.into_iter().map(|c| (Expr::Column(c), None)).collect();
+                self.plan_project(scan, columns)
+            }
+            None => scan,
+        }
+    }
+    
+    /// Evaluate each expression of `columns` per row of `input`, naming
+    /// the results by their aliases.
+    pub fn plan_project(
+        &self,
+        input: LogicalPlan,
+        columns: Vec<(Expr, Option<String>)>,
+    ) -> LogicalPlan {
+        LogicalPlan::Project {
+            input: Box::new(input),
+            columns,
+        }
+    }
+    
+    /// Sort `input` by `keys`, NULLs last.
+    pub fn plan_sort(&self, input: LogicalPlan, keys: Vec<(String, SortOrder)>) -> LogicalPlan {
+        LogicalPlan::Sort {
+            input: Box::new(input),
+            keys,
+            nulls: NullOrder::default(),
+        }
+    }
+    
+    pub fn plan_aggregate(
+        &self,
+        input: LogicalPlan,
+        group_by: Vec<String>,
+        aggregates: Vec<AggExpr>,
+    ) -> LogicalPlan {
+        LogicalPlan::Aggregate {
+            input: Box::new(input),
+            group_by,
+            aggregates,
+        }
+    }
+    
+    pub fn plan_distinct(&self, input: LogicalPlan) -> LogicalPlan {
+        LogicalPlan::Distinct {
+            input: Box::new(input),
+        }
+    }
+    
+    pub fn plan_limit(&self, input: LogicalPlan, count: u64) -> LogicalPlan {
+        LogicalPlan::Limit {
+            input: Box::new(input),
+            count,
+        }
+    }
+    
+    /// The rows of all `inputs`; without `all`, each distinct row once.
+    pub fn plan_union(&self, inputs: Vec<LogicalPlan>, all: bool) -> LogicalPlan {
+        LogicalPlan::Union { inputs, all }
+    }
+    
+    /// An inner join of two tables on `on`, which may name columns as
+    /// `<table or alias>.<column>`, or bare where only one side has them.
+    pub fn plan_join(&self, left: TableRef, right: TableRef, on: Expr) -> LogicalPlan {
+        LogicalPlan::Join {
+            left_name: left.name().to_string(),
+            right_name: right.name().to_string(),
+            left: Box::new(self.plan(left.table, None)),
+            right: Box::new(self.plan(right.table, None)),
+            on,
+            join_type: JoinType::Inner,
+        }
+    }
+    
+    pub fn plan_insert(&self, table: String, rows: Vec<Row>) -> LogicalPlan {
+        LogicalPlan::Insert { table, rows }
+    }
+    
+    pub fn plan_update(
+        &self,
+        table: String,
+        assignments: Vec<(String, Expr)>,
+        filter: Option<Expr>,
+    ) -> LogicalPlan {
+        LogicalPlan::Update {
+            table,
+            assignments,
+            filter,
+        }
+    }
+    
+    pub fn plan_delete(&self, table: String, filter: Option<Expr>) -> LogicalPlan {
+        LogicalPlan::Delete { table, filter }
+    }
+    
+    pub fn plan_create_table(&self, schema: TableSchema, if_not_exists: bool) -> LogicalPlan {
+        LogicalPlan::CreateTable { schema, if_not_exists }
+    }
+    
+    pub fn plan_drop_table(&self, name: String, if_exists: bool) -> LogicalPlan {
+        LogicalPlan::DropTable { name, if_exists }
+    }
+    
+    /// Optimize `logical`, then choose an operator for each node.
     pub fn to_physical(&self, logical: LogicalPlan) -> PhysicalPlan {
+        self.to_physical_unoptimized(push_down_filters(logical))
+    }
+    
+    /// Choose an operator for each node of `logical` as it stands.
+    pub fn to_physical_unoptimized(&self, logical: LogicalPlan) -> PhysicalPlan {
         match logical {
-            LogicalPlan::Scan { table, filter } => {
-                PhysicalPlan::SeqScan { table, filter }
-            }
+            LogicalPlan::Scan { table, filter } => self.plan_scan(table, filter),
             LogicalPlan::Filter { input, predicate } => {
-                let child = self.to_physical(*input);
+                let child = self.to_physical_unoptimized(*input);
                 PhysicalPlan::Filter {
                     input: Box::new(child),
                     predicate,
                 }
             }
             LogicalPlan::Project { input, columns } => {
-                let child = self.to_physical(*input);
+                let child = self.to_physical_unoptimized(*input);
                 PhysicalPlan::Project {
                     input: Box::new(child),
                     columns,
                 }
             }
+            LogicalPlan::Sort { input, keys, nulls } => {
+                let child = self.to_physical_unoptimized(*input);
+                PhysicalPlan::Sort {
+                    input: Box::new(child),
+                    keys,
+                    nulls,
+                }
+            }
+            LogicalPlan::Aggregate { input, group_by, aggregates } => {
+                let child = self.to_physical_unoptimized(*input);
+                PhysicalPlan::HashAggregate {
+                    input: Box::new(child),
+                    group_by,
+                    aggregates,
+                }
+            }
+            LogicalPlan::Distinct { input } => PhysicalPlan::HashDistinct {
+                input: Box::new(self.to_physical_unoptimized(*input)),
+            },
+            LogicalPlan::Union { inputs, all } => {
+                let inputs = inputs
+                    .into_iter()
+                    .map(|input| self.to_physical_unoptimized(input))
+                    .collect();
+                let union = PhysicalPlan::Union { inputs };
+                match all {
+                    true => union,
+                    false => PhysicalPlan::HashDistinct {
+                        input: Box::new(union),
+                    },
+                }
+            }
+            LogicalPlan::Limit { input, count } => PhysicalPlan::Limit {
+                input: Box::new(self.to_physical_unoptimized(*input)),
+                count,
+            },
+            LogicalPlan::Join { left, right, left_name, right_name, on, join_type } => {
+                let keys = self.equi_join_keys(&left, &left_name, &right, &right_name, &on);
+                let (left_key, right_key, residual) = match (join_type, keys) {
+                    (JoinType::Inner, Some(keys)) => keys,
+                    _ => {
+                        return PhysicalPlan::NestedLoopJoin {
+                            left: Box::new(self.to_physical_unoptimized(*left)),
+                            right: Box::new(self.to_physical_unoptimized(*right)),
+                            left_name,
+                            right_name,
+                            on,
+                            join_type,
+                        }
+                    }
+                };
+                // Build on the side expected to be smaller, or the right one
+                // if unsure.
+                let build_left = self
+                    .estimated_rows(&left)
+                    .zip(self.estimated_rows(&right))
+                    .is_some_and(|(l, r)| l < r);
+                let left = (self.to_physical_unoptimized(*left), left_name, left_key);
+                let right = (self.to_physical_unoptimized(*right), right_name, right_key);
+                let (build, probe) = if build_left { (left, right) } else { (right, left) };
+                let join = PhysicalPlan::HashJoin {
+                    build: Box::new(build.0),
+                    probe: Box::new(probe.0),
+                    build_name: build.1,
+                    probe_name: probe.1,
+                    build_key: build.2,
+                    probe_key: probe.2,
+                };
+                match residual {
+                    Some(predicate) => PhysicalPlan::Filter {
+                        input: Box::new(join),
+                        predicate,
+                    },
+                    None => join,
+                }
+            }
+            LogicalPlan::Insert { table, rows } => PhysicalPlan::Insert { table, rows },
+            LogicalPlan::Update { table, assignments, filter } => PhysicalPlan::Update {
+                table,
+                assignments,
+                filter,
+            },
+            LogicalPlan::Delete { table, filter } => PhysicalPlan::Delete { table, filter },
+            LogicalPlan::CreateTable { schema, if_not_exists } => {
+                PhysicalPlan::CreateTable { schema, if_not_exists }
+            }
+            LogicalPlan::DropTable { name, if_exists } => PhysicalPlan::DropTable { name, if_exists },
+        }
+    }
+    
+    /// Scan through an index when `filter` compares an indexed column with
+    /// constants and the table's statistics say that reads fewer rows than
+    /// is worth it, filtering on whatever else `filter` asks afterwards.
+    /// Without statistics, a sequential scan.
+    fn plan_scan(&self, table: String, filter: Option<Expr>) -> PhysicalPlan {
+        let (catalog, predicate) = match (&self.catalog, &filter) {
+            (Some(catalog), Some(predicate)) => (catalog.read().unwrap(), predicate),
+            _ => return PhysicalPlan::SeqScan { table, filter },
+        };
+        let casing = catalog.identifier_casing();
+        let mut conjuncts = Vec::new();
+        split_conjuncts(predicate, &mut conjuncts);
+        
+        let mut best: Option<(f64, PhysicalPlan)> = None;
+        for index in catalog.indexes_for_table(&table) {
+            let mut range = (Bound::Unbounded, Bound::Unbounded);
+            let mut residual = Vec::new();
+            for conjunct in &conjuncts {
+                let bound = column_bound(conjunct, &index.columns[0], casing);
+                if !bound.is_some_and(|(op, value)| narrow(&mut range, op, value)) {
+                    residual.push((*conjunct).clone());
+                }
+            }
+            if residual.len() == conjuncts.len() {
+                continue;
+            }
+            let scan = PhysicalPlan::IndexScan {
+                table: table.clone(),
+                index: index.name.clone(),
+                range,
+            };
+            let cost = match self.scan_estimate(&scan, &catalog) {
+                Some(estimate) => estimate.cost,
+                None => continue,
+            };
+            if best.as_ref().is_some_and(|(best, _)| *best <= cost) {
+                continue;
+            }
+            let scan = match conjoin(residual) {
+                Some(predicate) => PhysicalPlan::Filter {
+                    input: Box::new(scan),
+                    predicate,
+                },
+                None => scan,
+            };
+            best = Some((cost, scan));
+        }
+        let seq = PhysicalPlan::SeqScan { table, filter };
+        let seq_cost = self.scan_estimate(&seq, &catalog).map(|estimate| estimate.cost);
+        match (best, seq_cost) {
+            (Some((cost, scan)), Some(seq_cost)) if cost < seq_cost => scan,
+            _ => seq,
+        }
+    }
+    
+    /// The rows a scan is expected to produce and what reading them costs,
+    /// per its table's statistics. `None` for other plans, or without
+    /// statistics.
+    fn scan_estimate(&self, scan: &PhysicalPlan, catalog: &Catalog) -> Option<ScanEstimate> {
+        let casing = catalog.identifier_casing();
+        match scan {
+            PhysicalPlan::SeqScan { table, filter } => {
+                let stats = catalog.get_stats(table)?;
+                let total = stats.row_count as f64;
+                Some(ScanEstimate {
+                    rows: total * filter_selectivity(filter.as_ref(), stats, casing),
+                    cost: total * self.costs.row,
+                })
+            }
+            PhysicalPlan::IndexScan { table, index, range } => {
+                let stats = catalog.get_stats(table)?;
+                let index = catalog
+                    .indexes_for_table(table)
+                    .iter()
+                    .find(|def| casing.matches(&def.name, index))?;
+                let column = column_stats(stats, &index.columns[0], casing);
+                let rows = stats.row_count as f64 * range_selectivity(range, column);
+                Some(ScanEstimate {
+                    rows,
+                    cost: rows * (self.costs.index_probe + self.costs.fetch),
+                })
+            }
+            _ => None,
+        }
+    }
+    
+    /// Find a `left.col = right.col` conjunct in `on`, returning the left
+    /// and right key columns and the other conjuncts, if any.
+    fn equi_join_keys(
+        &self,
+        left: &LogicalPlan,
+        left_name: &str,
+        right: &LogicalPlan,
+        right_name: &str,
+        on: &Expr,
+    ) -> Option<(String, String, Option<Expr>)> {
+        let mut conjuncts = Vec::new();
+        split_conjuncts(on, &mut conjuncts);
+        let side = |column: &str| {
+            let in_left = self.side_has(left, left_name, column);
+            let in_right = self.side_has(right, right_name, column);
+            match (in_left, in_right) {
+                (true, false) => Some(true),
+                (false, true) => Some(false),
+                _ => None,
+            }
+        };
+        let found = conjuncts.iter().enumerate().find_map(|(i, conjunct)| {
+            match conjunct {
+                Expr::BinaryOp { op: BinaryOperator::Eq, left, right } => match (&**left, &**right) {
+                    (Expr::Column(a), Expr::Column(b)) => match (side(a)?, side(b)?) {
+                        (true, false) => Some((i, a.clone(), b.clone())),
+                        (false, true) => Some((i, b.clone(), a.clone())),
+                        _ => None,
+                    },
+                    _ => None,
+                },
+                _ => None,
+            }
+        });
+        let (i, left_key, right_key) = found?;
+        conjuncts.remove(i);
+        let residual = conjoin(conjuncts.into_iter().cloned().collect());
+        Some((left_key, right_key, residual))
+    }
+    
+    /// Whether `column` refers to the join side `plan`, called `name`: it's
+    /// qualified with `name`, or bare and in the side's table schema.
+    fn side_has(&self, plan: &LogicalPlan, name: &str, column: &str) -> bool {
+        let catalog = self.catalog.as_ref().map(|c| c.read().unwrap());
+        let casing = catalog
+            .as_ref()
+            .map_or(IdentifierCasing::Sensitive, |c| c.identifier_casing());
+        if let Some((qualifier, _)) = column.rsplit_once('.') {
+            return casing.matches(qualifier, name);
+        }
+        match (plan, &catalog) {
+            (LogicalPlan::Scan { table, .. }, Some(catalog)) => catalog
+                .get_table(table)
+                .is_some_and(|schema| schema.find_column(column, casing).is_some()),
+            _ => false,
+        }
+    }
+    
+    /// Rows `plan` is expected to produce when it scans a table, per the
+    /// table's last analyze.
+    fn estimated_rows(&self, plan: &LogicalPlan) -> Option<f64> {
+        let catalog = self.catalog.as_ref()?.read().unwrap();
+        match plan {
+            LogicalPlan::Scan { table, filter } => {
+                let stats = catalog.get_stats(table)?;
+                let casing = catalog.identifier_casing();
+                Some(stats.row_count as f64 * filter_selectivity(filter.as_ref(), stats, casing))
+            }
+            _ => None,
+        }
+    }
+    
+    /// The physical plan for `plan`, one operator per line with its inputs
+    /// indented below it. Scans show the rows they're expected to produce
+    /// and their cost, where the table has statistics.
+    pub fn explain(&self, plan: LogicalPlan) -> String {
+        let mut out = String::new();
+        let physical = self.to_physical(plan);
+        let catalog = self.catalog.as_ref().map(|c| c.read().unwrap());
+        self.explain_node(&physical, catalog.as_deref(), 0, &mut out);
+        out
+    }
+    
+    fn explain_node(
+        &self,
+        plan: &PhysicalPlan,
+        catalog: Option<&Catalog>,
+        depth: usize,
+        out: &mut String,
+    ) {
+        let names = |columns: &[String]| columns.join(", ");
+        let (line, inputs): (String, Vec<&PhysicalPlan>) = match plan {
+            PhysicalPlan::SeqScan { table, filter } => {
+                let filter = filter.as_ref().map(|f| format!(" filter {}", f)).unwrap_or_default();
+                (format!("SeqScan {}{}", table, filter), Vec::new())
+            }
+            PhysicalPlan::IndexScan { table, index, range } => (
+                format!("IndexScan {} using {} {}", table, index, format_range(range)),
+                Vec::new(),
+            ),
+            PhysicalPlan::Filter { input, predicate } => {
+                (format!("Filter {}", predicate), vec![input])
+            }
+            PhysicalPlan::Project { input, columns } => {
+                let columns: Vec<String> = columns
+                    .iter()
+                    .map(|(expr, alias)| projected_name(expr, alias.as_deref()))
+                    .collect();
+                (format!("Project {}", names(&columns)), vec![input])
+            }
+            PhysicalPlan::Sort { input, keys, .. } => {
+                let keys: Vec<String> = keys
+                    .iter()
+                    .map(|(key, order)| match order {
+                        SortOrder::Asc => key.clone(),
+                        SortOrder::Desc => format!("{} DESC", key),
+                    })
+                    .collect();
+                (format!("Sort {}", names(&keys)), vec![input])
+            }
+            PhysicalPlan::HashAggregate { input, group_by, aggregates } => {
+                let outputs: Vec<String> = aggregates.iter().map(AggExpr::output_name).collect();
+                let line = match group_by.is_empty() {
+                    true => format!("HashAggregate {}", names(&outputs)),
+                    false => format!("HashAggregate {} by {}", names(&outputs), names(group_by)),
+                };
+                (line, vec![input])
+            }
+            PhysicalPlan::HashDistinct { input } => ("HashDistinct".to_string(), vec![input]),
+            PhysicalPlan::Limit { input, count } => (format!("Limit {}", count), vec![input]),
+            PhysicalPlan::NestedLoopJoin { left, right, left_name, right_name, on, .. } => (
+                format!("NestedLoopJoin {} with {} on {}", left_name, right_name, on),
+                vec![left, right],
+            ),
+            PhysicalPlan::HashJoin {
+                build,
+                probe,
+                build_name,
+                probe_name,
+                build_key,
+                probe_key,
+            } => {
+                let line = format!(
+                    "HashJoin build {} on {}, probe {} on {}",
+                    build_name, build_key, probe_name, probe_key
+                );
+                (line, vec![build, probe])
+            }
+            PhysicalPlan::Union { inputs } => ("Union".to_string(), inputs.iter().collect()),
+            PhysicalPlan::Insert { table, rows } => {
+                (format!("Insert {} rows into {}", rows.len(), table), Vec::new())
+            }
+            PhysicalPlan::Update { table, .. } => (format!("Update {}", table), Vec::new()),
+            PhysicalPlan::Delete { table, .. } => (format!("Delete from {}", table), Vec::new()),
+            PhysicalPlan::CreateTable { schema, .. } => {
+                (format!("CreateTable {}", schema.name), Vec::new())
+            }
+            PhysicalPlan::DropTable { name, .. } => (format!("DropTable {}", name), Vec::new()),
+        };
+        out.push_str(&"  ".repeat(depth));
+        out.push_str(&line);
+        if let Some(estimate) = catalog.and_then(|catalog| self.scan_estimate(plan, catalog)) {
+            out.push_str(&format!(" (rows={:.0} cost={:.1})", estimate.rows, estimate.cost));
+        }
+        out.push('\n');
+        for input in inputs {
+            self.explain_node(input, catalog, depth + 1, out);
+        }
+    }
+}
+
+/// What a scan is expected to produce and cost.
+#[derive(Debug, Clone, Copy)]
+struct ScanEstimate {
+    rows: f64,
+    cost: f64,
+}
+
+fn column_stats<'a>(
+    stats: &'a TableStats,
+    column: &str,
+    casing: IdentifierCasing,
+) -> Option<&'a ColumnStats> {
+    stats
+        .column_stats
+        .iter()
+        .find(|(name, _)| casing.matches(name, column))
+        .map(|(_, column)| column)
+}
+
+/// Share of a table's rows `filter` keeps: the product of its conjuncts'
+/// shares, taking them as independent.
+fn filter_selectivity(filter: Option<&Expr>, stats: &TableStats, casing: IdentifierCasing) -> f64 {
+    let mut conjuncts = Vec::new();
+    if let Some(filter) = filter {
+        split_conjuncts(filter, &mut conjuncts);
+    }
+    conjuncts
+        .into_iter()
+        .map(|conjunct| match column_comparison(conjunct) {
+            Some((column, op, value)) => {
+                let mut range = (Bound::Unbounded, Bound::Unbounded);
+                match narrow(&mut range, op, value.clone()) {
+                    true => range_selectivity(&range, column_stats(stats, column, casing)),
+                    false => OTHER_SELECTIVITY,
+                }
+            }
+            None => OTHER_SELECTIVITY,
+        })
+        .product()
+}
+
+/// Share of rows whose value of a column lies in `range`. One value keeps
+/// one in `distinct_count`; a range of numbers keeps the share of the
+/// column's span from minimum to maximum it covers.
+fn range_selectivity(range: &(Bound<Value>, Bound<Value>), stats: Option<&ColumnStats>) -> f64 {
+    let point = match range {
+        (Bound::Included(low), Bound::Included(high)) => low.compare(high) == Some(Ordering::Equal),
+        (Bound::Unbounded, Bound::Unbounded) => return 1.0,
+        _ => false,
+    };
+    if point {
+        return match stats {
+            Some(stats) if stats.distinct_count > 0 => 1.0 / stats.distinct_count as f64,
+            _ => EQ_SELECTIVITY,
+        };
+    }
+    let span = stats.and_then(|stats| {
+        let min = number(&Value::from(stats.min.clone()?))?;
+        let max = number(&Value::from(stats.max.clone()?))?;
+        (max > min).then_some((min, max))
+    });
+    let position = |bound: &Bound<Value>, unbounded: f64| match (bound, span) {
+        (Bound::Unbounded, _) => Some(unbounded),
+        (Bound::Included(v) | Bound::Excluded(v), Some((min, max))) => {
+            Some(((number(v)? - min) / (max - min)).clamp(0.0, 1.0))
+        }
+        _ => None,
+    };
+    match (position(&range.0, 0.0), position(&range.1, 1.0)) {
+        (Some(low), Some(high)) => (high - low).max(0.0),
+        _ => RANGE_SELECTIVITY,
+    }
+}
+
+fn number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Int(i) => Some(*i as f64),
+        Value::Float(f) => Some(*f),
+        value @ Value::Decimal { .. } => Some(decimal_to_f64(value)),
+        _ => None,
+    }
+}
+
+fn format_range(range: &(Bound<Value>, Bound<Value>)) -> String {
+    let low = match &range.0 {
+        Bound::Included(v) => format!("[{}", v),
+        Bound::Excluded(v) => format!("({}", v),
+        Bound::Unbounded => "(-inf".to_string(),
+    };
+    let high = match &range.1 {
+        Bound::Included(v) => format!("{}]", v),
+        Bound::Excluded(v) => format!("{})", v),
+        Bound::Unbounded => "inf)".to_string(),
+    };
+    format!("{}, {}", low, high)
+}
+
+/// `conjunct` as `column <op> constant`, if it compares `column` with a
+/// non-NULL constant.
+fn column_bound(
+    conjunct: &Expr,
+    column: &str,
+    casing: IdentifierCasing,
+) -> Option<(BinaryOperator, Value)> {
+    let (name, op, value) = column_comparison(conjunct)?;
+    casing.matches(name, column).then(|| (op, value.clone()))
+}
+
+/// `conjunct` as `column <op> constant` for a comparison of some column
+/// with a non-NULL constant, flipped if the constant comes first.
+fn column_comparison(conjunct: &Expr) -> Option<(&str, BinaryOperator, &Value)> {
+    let (op, left, right) = match conjunct {
+        Expr::BinaryOp { op, left, right } => (*op, &**left, &**right),
+        _ => return None,
+    };
+    let (op, name, value) = match (left, right) {
+        (Expr::Column(name), Expr::Literal(value)) => (op, name, value),
+        (Expr::Literal(value), Expr::Column(name)) => {
+            let flipped = match op {
+                BinaryOperator::Lt => BinaryOperator::Gt,
+                BinaryOperator::Le => BinaryOperator::Ge,
+                BinaryOperator::Gt => BinaryOperator::Lt,
+                BinaryOperator::Ge => BinaryOperator::Le,
+                other => other,
+            };
+            (flipped, name, value)
+        }
+        _ => return None,
+    };
+    let comparison = matches!(
+        op,
+        BinaryOperator::Eq
+            | BinaryOperator::Lt
+            | BinaryOperator::Le
+            | BinaryOperator::Gt
+            | BinaryOperator::Ge
+    );
+    (comparison && *value != Value::Null).then_some((name.as_str(), op, value))
+}
+
+/// Tighten `range` by `column <op> value`. Returns false, leaving `range`
+/// alone, if `value` doesn't compare with a bound already there.
+fn narrow(range: &mut (Bound<Value>, Bound<Value>), op: BinaryOperator, value: Value) -> bool {
+    // Whether `new` leaves out more than `old`, which sits on the side
+    // `toward` points away from.
+    fn tighter(new: &Bound<Value>, old: &Bound<Value>, toward: Ordering) -> Option<bool> {
+        match (new, old) {
+            (_, Bound::Unbounded) => Some(true),
+            (Bound::Included(n) | Bound::Excluded(n), Bound::Included(o) | Bound::Excluded(o)) => {
+                Some(match n.compare(o)? {
+                    Ordering::Equal => matches!(new, Bound::Excluded(_)),
+                    ord => ord == toward,
+                })
+            }
+            (Bound::Unbounded, _) => Some(false),
+        }
+    }
+    let (low, high) = match op {
+        BinaryOperator::Eq => (Bound::Included(value.clone()), Bound::Included(value)),
+        BinaryOperator::Gt => (Bound::Excluded(value), Bound::Unbounded),
+        BinaryOperator::Ge => (Bound::Included(value), Bound::Unbounded),
+        BinaryOperator::Lt => (Bound::Unbounded, Bound::Excluded(value)),
+        BinaryOperator::Le => (Bound::Unbounded, Bound::Included(value)),
+        _ => return false,
+    };
+    let (Some(raise), Some(lower)) = (
+        tighter(&low, &range.0, Ordering::Greater),
+        tighter(&high, &range.1, Ordering::Less),
+    ) else {
+        return false;
+    };
+    if raise {
+        range.0 = low;
+    }
+    if lower {
+        range.1 = high;
+    }
+    true
+}
+
+fn split_conjuncts<'a>(expr: &'a Expr, out: &mut Vec<&'a Expr>) {
+    match expr {
+        Expr::BinaryOp { op: BinaryOperator::And, left, right } => {
+            split_conjuncts(left, out);
+            split_conjuncts(right, out);
         }
+        other => out.push(other),
     }
 }
 
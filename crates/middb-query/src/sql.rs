@@ -0,0 +1,861 @@
+use crate::expr::{BinaryOperator, Expr, Value};
+use crate::plan::{projected_name, LogicalPlan, SortOrder};
+use crate::planner::Planner;
+use middb_core::catalog::{DataType, Datum, TableSchema, TableSchemaBuilder};
+use std::fmt;
+
+/// A parsed SQL statement.
+#[derive(Debug, Clone)]
+pub enum Statement {
+    /// `limit` applies to the rows `plan` produces.
+    Select {
+        plan: LogicalPlan,
+        limit: Option<u64>,
+    },
+    CreateTable {
+        schema: TableSchema,
+        if_not_exists: bool,
+    },
+    DropTable {
+        name: String,
+        if_exists: bool,
+    },
+    /// `columns` is `None` when the statement doesn't name them, in which
+    /// case values follow the table's column order.
+    Insert {
+        table: String,
+        columns: Option<Vec<String>>,
+        rows: Vec<Vec<Value>>,
+    },
+    Delete {
+        table: String,
+        filter: Option<Expr>,
+    },
+}
+
+/// A syntax error and the 1-based line and column it was found at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub message: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at {}:{}", self.message, self.line, self.column)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Parse one statement, optionally terminated by `;`.
+///
+/// Supported: `SELECT <expr [AS alias], ...|*> FROM t [WHERE expr] [ORDER
+/// BY col [ASC|DESC], ...] [LIMIT n]`, `CREATE TABLE [IF NOT EXISTS]`, `DROP
+/// TABLE [IF EXISTS] t`, `INSERT INTO t [(cols)] VALUES (...), ...` and
+/// `DELETE FROM t [WHERE expr]`. ORDER BY may name a select-list alias.
+/// SELECTs combine with `UNION [ALL]`, ORDER BY and LIMIT then applying to
+/// the union. Keywords are case-insensitive.
+pub fn parse(sql: &str) -> Result<Statement, ParseError> {
+    let mut parser = Parser {
+        tokens: tokenize(sql)?,
+        pos: 0,
+    };
+    let statement = parser.statement()?;
+    parser.eat_symbol(";");
+    parser.expect_end()?;
+    Ok(statement)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    Word(String),
+    QuotedIdent(String),
+    Int(i64),
+    Float(f64),
+    Str(String),
+    Symbol(&'static str),
+    End,
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    /// The token as written, for error messages.
+    text: String,
+    line: usize,
+    column: usize,
+}
+
+const SYMBOLS: [&str; 16] = [
+    "<=", ">=", "<>", "!=", "(", ")", ",", ";", "*", "=", "<", ">", ".", "+", "-", "/",
+];
+
+fn tokenize(sql: &str) -> Result<Vec<Token>, ParseError> {
+    let chars: Vec<char> = sql.chars().collect();
+    let mut tokens = Vec::new();
+    let (mut i, mut line, mut column) = (0, 1, 1);
+    
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '\n' {
+            i += 1;
+            line += 1;
+            column = 1;
+            continue;
+        }
+        if c.is_whitespace() {
+            i += 1;
+            column += 1;
+            continue;
+        }
+        if c == '-' && chars.get(i + 1) == Some(&'-') {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+            continue;
+        }
+        
+        let start = i;
+        let error = |message: String| ParseError { message, line, column };
+        let kind = if c.is_ascii_alphabetic() || c == '_' {
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            TokenKind::Word(chars[start..i].iter().collect())
+        } else if c.is_ascii_digit() || (c == '-' && chars.get(i + 1).is_some_and(char::is_ascii_digit)) {
+            i += 1;
+            while i < chars.len() && chars[i].is_ascii_digit() {
+                i += 1;
+            }
+            let is_float = chars.get(i) == Some(&'.')
+                && chars.get(i + 1).is_some_and(char::is_ascii_digit);
+            if is_float {
+                i += 1;
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+            }
+            let text: String = chars[start..i].iter().collect();
+            if is_float {
+                TokenKind::Float(text.parse().map_err(|_| error(format!("invalid number '{}'", text)))?)
+            } else {
+                TokenKind::Int(text.parse().map_err(|_| error(format!("integer '{}' out of range", text)))?)
+            }
+        } else if c == '\'' || c == '"' {
+            let (value, end) = read_quoted(&chars, i)
+                .ok_or_else(|| error("unterminated quoted literal".to_string()))?;
+            i = end;
+            match c {
+                '\'' => TokenKind::Str(value),
+                _ => TokenKind::QuotedIdent(value),
+            }
+        } else {
+            let rest: String = chars[i..chars.len().min(i + 2)].iter().collect();
+            let symbol = SYMBOLS
+                .iter()
+                .find(|s| rest.starts_with(**s))
+                .ok_or_else(|| error(format!("unexpected character '{}'", c)))?;
+            i += symbol.len();
+            TokenKind::Symbol(symbol)
+        };
+        
+        let text: String = chars[start..i].iter().collect();
+        tokens.push(Token { kind, text, line, column });
+        // Quoted literals may span lines.
+        for &ch in &chars[start..i] {
+            if ch == '\n' {
+                line += 1;
+                column = 1;
+            } else {
+                column += 1;
+            }
+        }
+    }
+    
+    tokens.push(Token {
+        kind: TokenKind::End,
+        text: String::new(),
+        line,
+        column,
+    });
+    Ok(tokens)
+}
+
+/// Read a literal quoted by `chars[start]`, returning its contents and the
+/// index just past the closing quote. A doubled quote stands for itself;
+/// backslash escapes `\n`, `\t`, `\r`, `\0`, `\\` and the quote.
+fn read_quoted(chars: &[char], start: usize) -> Option<(String, usize)> {
+    let quote = chars[start];
+    let mut value = String::new();
+    let mut i = start + 1;
+    loop {
+        match *chars.get(i)? {
+            c if c == quote => {
+                if chars.get(i + 1) == Some(&quote) {
+                    value.push(quote);
+                    i += 2;
+                } else {
+                    return Some((value, i + 1));
+                }
+            }
+            '\\' => {
+                value.push(match *chars.get(i + 1)? {
+                    'n' => '\n',
+                    't' => '\t',
+                    'r' => '\r',
+                    '0' => '\0',
+                    other => other,
+                });
+                i += 2;
+            }
+            c => {
+                value.push(c);
+                i += 1;
+            }
+        }
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos]
+    }
+    
+    fn next(&mut self) -> Token {
+        let token = self.tokens[self.pos].clone();
+        if token.kind != TokenKind::End {
+            self.pos += 1;
+        }
+        token
+    }
+    
+    fn unexpected(&self, token: &Token) -> ParseError {
+        let message = match token.kind {
+            TokenKind::End => "unexpected end of input".to_string(),
+            _ => format!("unexpected token '{}'", token.text),
+        };
+        ParseError {
+            message,
+            line: token.line,
+            column: token.column,
+        }
+    }
+    
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(&self.peek().kind, TokenKind::Word(w) if w.eq_ignore_ascii_case(keyword))
+    }
+    
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let found = self.is_keyword(keyword);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+    
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), ParseError> {
+        match self.eat_keyword(keyword) {
+            true => Ok(()),
+            false => Err(self.unexpected(self.peek())),
+        }
+    }
+    
+    fn eat_symbol(&mut self, symbol: &'static str) -> bool {
+        let found = self.peek().kind == TokenKind::Symbol(symbol);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+    
+    fn expect_symbol(&mut self, symbol: &'static str) -> Result<(), ParseError> {
+        match self.eat_symbol(symbol) {
+            true => Ok(()),
+            false => Err(self.unexpected(self.peek())),
+        }
+    }
+    
+    fn expect_end(&self) -> Result<(), ParseError> {
+        match self.peek().kind {
+            TokenKind::End => Ok(()),
+            _ => Err(self.unexpected(self.peek())),
+        }
+    }
+    
+    /// A plain or double-quoted identifier. Keywords that start a clause
+    /// can't be used unquoted.
+    fn identifier(&mut self) -> Result<String, ParseError> {
+        let token = self.next();
+        match token.kind {
+            TokenKind::Word(w) if !is_reserved(&w) => Ok(w),
+            TokenKind::QuotedIdent(w) => Ok(w),
+            _ => Err(self.unexpected(&token)),
+        }
+    }
+    
+    /// An identifier, optionally qualified as `a.b`.
+    fn name(&mut self) -> Result<String, ParseError> {
+        let mut name = self.identifier()?;
+        while self.eat_symbol(".") {
+            name.push('.');
+            name.push_str(&self.identifier()?);
+        }
+        Ok(name)
+    }
+    
+    fn statement(&mut self) -> Result<Statement, ParseError> {
+        if self.eat_keyword("SELECT") {
+            self.select()
+        } else if self.eat_keyword("CREATE") {
+            self.create_table()
+        } else if self.eat_keyword("DROP") {
+            self.drop_table()
+        } else if self.eat_keyword("INSERT") {
+            self.insert()
+        } else if self.eat_keyword("DELETE") {
+            self.delete()
+        } else {
+            Err(self.unexpected(self.peek()))
+        }
+    }
+    
+    fn select(&mut self) -> Result<Statement, ParseError> {
+        let (plan, limit) = self.query()?;
+        Ok(Statement::Select { plan, limit })
+    }
+    
+    /// A SELECT and its unions, as a plan and the LIMIT on its rows.
+    fn query(&mut self) -> Result<(LogicalPlan, Option<u64>), ParseError> {
+        let first = self.select_branch()?;
+        let mut unions = Vec::new();
+        while self.eat_keyword("UNION") {
+            let all = self.eat_keyword("ALL");
+            self.expect_keyword("SELECT")?;
+            unions.push((all, self.select_branch()?));
+        }
+        
+        let mut order_by = Vec::new();
+        if self.eat_keyword("ORDER") {
+            self.expect_keyword("BY")?;
+            order_by = self.comma_separated(|p| {
+                let column = p.name()?;
+                let order = match p.eat_keyword("DESC") {
+                    true => SortOrder::Desc,
+                    false => {
+                        p.eat_keyword("ASC");
+                        SortOrder::Asc
+                    }
+                };
+                Ok((column, order))
+            })?;
+        }
+        
+        let mut limit = None;
+        if self.eat_keyword("LIMIT") {
+            let token = self.next();
+            match token.kind {
+                TokenKind::Int(n) if n >= 0 => limit = Some(n as u64),
+                _ => return Err(self.unexpected(&token)),
+            }
+        }
+        
+        if unions.is_empty() {
+            return Ok((first.plan(order_by), limit));
+        }
+        // Runs of one kind of UNION share a node; a change of kind nests
+        // what came before, since UNION and UNION ALL associate left. ORDER
+        // BY and LIMIT apply to the whole union.
+        let planner = Planner::new();
+        let mut plan = first.plan(Vec::new());
+        for (all, branch) in unions {
+            let next = branch.plan(Vec::new());
+            plan = match plan {
+                LogicalPlan::Union { mut inputs, all: same } if same == all => {
+                    inputs.push(next);
+                    planner.plan_union(inputs, all)
+                }
+                plan => planner.plan_union(vec![plan, next], all),
+            };
+        }
+        if !order_by.is_empty() {
+            plan = planner.plan_sort(plan, order_by);
+        }
+        Ok((plan, limit))
+    }
+    
+    /// A SELECT up to its ORDER BY, which may belong to a whole union.
+    fn select_branch(&mut self) -> Result<SelectBranch, ParseError> {
+        let distinct = self.eat_keyword("DISTINCT");
+        let columns = match self.eat_symbol("*") {
+            true => None,
+            false => Some(self.comma_separated(|p| {
+                let expr = p.expr()?;
+                let alias = match p.eat_keyword("AS") {
+                    true => Some(p.identifier()?),
+                    false => None,
+                };
+                Ok((expr, alias))
+            })?),
+        };
+        self.expect_keyword("FROM")?;
+        let table = self.name()?;
+        let filter = self.where_clause()?;
+        Ok(SelectBranch {
+            distinct,
+            columns,
+            table,
+            filter,
+        })
+    }
+    
+    fn create_table(&mut self) -> Result<Statement, ParseError> {
+        self.expect_keyword("TABLE")?;
+        let if_not_exists = self.eat_keyword("IF");
+        if if_not_exists {
+            self.expect_keyword("NOT")?;
+            self.expect_keyword("EXISTS")?;
+        }
+        let name_token = self.peek().clone();
+        let name = self.name()?;
+        self.expect_symbol("(")?;
+        
+        let mut columns: Vec<(String, DataType, bool, Option<Datum>)> = Vec::new();
+        let mut primary_key = None;
+        let mut unique = Vec::new();
+        loop {
+            if self.eat_keyword("PRIMARY") {
+                self.expect_keyword("KEY")?;
+                primary_key = Some(self.column_list()?);
+            } else if self.eat_keyword("UNIQUE") {
+                unique.push(self.column_list()?);
+            } else {
+                let column = self.identifier()?;
+                let data_type = self.data_type()?;
+                let (mut nullable, mut default) = (true, None);
+                loop {
+                    if self.eat_keyword("NOT") {
+                        self.expect_keyword("NULL")?;
+                        nullable = false;
+                    } else if self.eat_keyword("NULL") {
+                        nullable = true;
+                    } else if self.eat_keyword("DEFAULT") {
+                        default = Some(Datum::from(self.literal()?));
+                    } else if self.eat_keyword("PRIMARY") {
+                        self.expect_keyword("KEY")?;
+                        primary_key = Some(vec![column.clone()]);
+                    } else if self.eat_keyword("UNIQUE") {
+                        unique.push(vec![column.clone()]);
+                    } else {
+                        break;
+                    }
+                }
+                columns.push((column, data_type, nullable, default));
+            }
+            if !self.eat_symbol(",") {
+                break;
+            }
+        }
+        self.expect_symbol(")")?;
+        
+        let mut builder = TableSchemaBuilder::new(name);
+        for (column, data_type, nullable, default) in columns {
+            // Primary key columns are implicitly NOT NULL.
+            let nullable = nullable
+                && !primary_key.as_ref().is_some_and(|pk: &Vec<String>| pk.contains(&column));
+            builder = match default {
+                Some(default) => builder.column_with_default(column, data_type, nullable, default),
+                None => builder.column(column, data_type, nullable),
+            };
+        }
+        if let Some(pk) = primary_key {
+            builder = builder.primary_key(pk);
+        }
+        for cols in unique {
+            builder = builder.unique(cols);
+        }
+        let schema = builder.try_build().map_err(|e| ParseError {
+            message: e.to_string(),
+            line: name_token.line,
+            column: name_token.column,
+        })?;
+        Ok(Statement::CreateTable { schema, if_not_exists })
+    }
+    
+    fn drop_table(&mut self) -> Result<Statement, ParseError> {
+        self.expect_keyword("TABLE")?;
+        let if_exists = self.eat_keyword("IF");
+        if if_exists {
+            self.expect_keyword("EXISTS")?;
+        }
+        let name = self.name()?;
+        Ok(Statement::DropTable { name, if_exists })
+    }
+    
+    fn data_type(&mut self) -> Result<DataType, ParseError> {
+        let token = self.next();
+        let word = match &token.kind {
+            TokenKind::Word(w) => w.to_ascii_uppercase(),
+            _ => return Err(self.unexpected(&token)),
+        };
+        let data_type = match word.as_str() {
+            "INT" | "INTEGER" | "BIGINT" | "INT64" => DataType::Int64,
+            "TEXT" | "STRING" => DataType::String,
+            "VARCHAR" => {
+                // The length is accepted but not enforced.
+                if self.eat_symbol("(") {
+                    self.integer()?;
+                    self.expect_symbol(")")?;
+                }
+                DataType::String
+            }
+            "BYTES" | "BLOB" => DataType::Bytes,
+            "BOOL" | "BOOLEAN" => DataType::Bool,
+            "FLOAT" | "DOUBLE" | "REAL" | "FLOAT64" => DataType::Float64,
+            "TIMESTAMP" => DataType::Timestamp,
+            "DECIMAL" | "NUMERIC" => {
+                self.expect_symbol("(")?;
+                let precision = self.integer()?;
+                let scale = match self.eat_symbol(",") {
+                    true => self.integer()?,
+                    false => 0,
+                };
+                self.expect_symbol(")")?;
+                let valid = (1..=DataType::MAX_DECIMAL_PRECISION as i64).contains(&precision)
+                    && (0..=precision).contains(&scale);
+                if !valid {
+                    return Err(ParseError {
+                        message: format!("invalid DECIMAL({}, {})", precision, scale),
+                        line: token.line,
+                        column: token.column,
+                    });
+                }
+                DataType::Decimal {
+                    precision: precision as u8,
+                    scale: scale as u8,
+                }
+            }
+            _ => return Err(self.unexpected(&token)),
+        };
+        Ok(data_type)
+    }
+    
+    fn integer(&mut self) -> Result<i64, ParseError> {
+        let token = self.next();
+        match token.kind {
+            TokenKind::Int(n) => Ok(n),
+            _ => Err(self.unexpected(&token)),
+        }
+    }
+    
+    fn column_list(&mut self) -> Result<Vec<String>, ParseError> {
+        self.expect_symbol("(")?;
+        let columns = self.comma_separated(Self::identifier)?;
+        self.expect_symbol(")")?;
+        Ok(columns)
+    }
+    
+    fn insert(&mut self) -> Result<Statement, ParseError> {
+        self.expect_keyword("INTO")?;
+        let table = self.name()?;
+        let columns = match self.peek().kind == TokenKind::Symbol("(") {
+            true => Some(self.column_list()?),
+            false => None,
+        };
+        self.expect_keyword("VALUES")?;
+        let rows = self.comma_separated(|p| {
+            p.expect_symbol("(")?;
+            let values = p.comma_separated(Self::literal)?;
+            p.expect_symbol(")")?;
+            Ok(values)
+        })?;
+        Ok(Statement::Insert {
+            table,
+            columns,
+            rows,
+        })
+    }
+    
+    fn delete(&mut self) -> Result<Statement, ParseError> {
+        self.expect_keyword("FROM")?;
+        let table = self.name()?;
+        let filter = self.where_clause()?;
+        Ok(Statement::Delete { table, filter })
+    }
+    
+    fn where_clause(&mut self) -> Result<Option<Expr>, ParseError> {
+        match self.eat_keyword("WHERE") {
+            true => self.expr().map(Some),
+            false => Ok(None),
+        }
+    }
+    
+    fn comma_separated<T>(
+        &mut self,
+        mut item: impl FnMut(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<Vec<T>, ParseError> {
+        let mut items = vec![item(self)?];
+        while self.eat_symbol(",") {
+            items.push(item(self)?);
+        }
+        Ok(items)
+    }
+    
+    fn expr(&mut self) -> Result<Expr, ParseError> {
+        self.binary(0)
+    }
+    
+    /// Operators bind loosest to tightest: OR, AND, NOT, comparisons and
+    /// the other predicates, which don't chain, then `+` and `-`, then `*`
+    /// and `/`.
+    fn binary(&mut self, level: usize) -> Result<Expr, ParseError> {
+        if level == 2 {
+            return self.negation();
+        }
+        
+        let (keyword, op) = match level {
+            0 => ("OR", BinaryOperator::Or),
+            _ => ("AND", BinaryOperator::And),
+        };
+        let mut left = self.binary(level + 1)?;
+        while self.eat_keyword(keyword) {
+            let right = self.binary(level + 1)?;
+            left = binary_op(op, left, right);
+        }
+        Ok(left)
+    }
+    
+    fn negation(&mut self) -> Result<Expr, ParseError> {
+        match self.eat_keyword("NOT") {
+            true => Ok(match self.negation()? {
+                Expr::Exists { subplan, negated } => Expr::Exists { subplan, negated: !negated },
+                expr => Expr::Not(Box::new(expr)),
+            }),
+            false => self.predicate(),
+        }
+    }
+    
+    fn predicate(&mut self) -> Result<Expr, ParseError> {
+        if self.eat_keyword("EXISTS") {
+            self.expect_symbol("(")?;
+            self.expect_keyword("SELECT")?;
+            let subplan = Box::new(self.subquery()?);
+            return Ok(Expr::Exists { subplan, negated: false });
+        }
+        let left = self.additive()?;
+        if let Some(op) = self.comparison() {
+            let right = self.additive()?;
+            return Ok(binary_op(op, left, right));
+        }
+        let expr = Box::new(left);
+        if self.eat_keyword("IS") {
+            let negated = self.eat_keyword("NOT");
+            self.expect_keyword("NULL")?;
+            return Ok(Expr::IsNull { expr, negated });
+        }
+        
+        let negated = self.eat_keyword("NOT");
+        if self.eat_keyword("LIKE") {
+            let pattern = Box::new(self.additive()?);
+            Ok(Expr::Like { expr, pattern, negated })
+        } else if self.eat_keyword("IN") {
+            self.expect_symbol("(")?;
+            if self.eat_keyword("SELECT") {
+                let subplan = Box::new(self.subquery()?);
+                return Ok(Expr::InSubquery { expr, subplan, negated });
+            }
+            let list = match self.eat_symbol(")") {
+                true => Vec::new(),
+                false => {
+                    let list = self.comma_separated(Self::expr)?;
+                    self.expect_symbol(")")?;
+                    list
+                }
+            };
+            Ok(Expr::InList { expr, list, negated })
+        } else if self.eat_keyword("BETWEEN") {
+            let low = Box::new(self.additive()?);
+            self.expect_keyword("AND")?;
+            let high = Box::new(self.additive()?);
+            Ok(Expr::Between { expr, low, high, negated })
+        } else if negated {
+            Err(self.unexpected(self.peek()))
+        } else {
+            Ok(*expr)
+        }
+    }
+    
+    /// The rest of a parenthesized SELECT, through its closing `)`.
+    fn subquery(&mut self) -> Result<LogicalPlan, ParseError> {
+        let (plan, limit) = self.query()?;
+        self.expect_symbol(")")?;
+        Ok(match limit {
+            Some(count) => Planner::new().plan_limit(plan, count),
+            None => plan,
+        })
+    }
+    
+    fn comparison(&mut self) -> Option<BinaryOperator> {
+        let op = match self.peek().kind {
+            TokenKind::Symbol("=") => BinaryOperator::Eq,
+            TokenKind::Symbol("!=") | TokenKind::Symbol("<>") => BinaryOperator::Ne,
+            TokenKind::Symbol("<") => BinaryOperator::Lt,
+            TokenKind::Symbol("<=") => BinaryOperator::Le,
+            TokenKind::Symbol(">") => BinaryOperator::Gt,
+            TokenKind::Symbol(">=") => BinaryOperator::Ge,
+            _ => return None,
+        };
+        self.pos += 1;
+        Some(op)
+    }
+    
+    fn additive(&mut self) -> Result<Expr, ParseError> {
+        let mut left = self.multiplicative()?;
+        loop {
+            // `a -1` lexes as `a` and the literal -1, which only makes sense
+            // as a subtraction.
+            let negative = self.peek().text.starts_with('-');
+            let (op, right) = match self.peek().kind {
+                TokenKind::Symbol("+") => (BinaryOperator::Plus, None),
+                TokenKind::Symbol("-") => (BinaryOperator::Minus, None),
+                TokenKind::Int(i64::MIN) => (BinaryOperator::Plus, Some(Value::Int(i64::MIN))),
+                TokenKind::Int(n) if negative => (BinaryOperator::Minus, Some(Value::Int(-n))),
+                TokenKind::Float(f) if negative => (BinaryOperator::Minus, Some(Value::Float(-f))),
+                _ => return Ok(left),
+            };
+            self.pos += 1;
+            let right = match right {
+                Some(value) => self.multiplicative_from(Expr::Literal(value))?,
+                None => self.multiplicative()?,
+            };
+            left = binary_op(op, left, right);
+        }
+    }
+    
+    fn multiplicative(&mut self) -> Result<Expr, ParseError> {
+        let left = self.primary()?;
+        self.multiplicative_from(left)
+    }
+    
+    fn multiplicative_from(&mut self, mut left: Expr) -> Result<Expr, ParseError> {
+        loop {
+            let op = match self.peek().kind {
+                TokenKind::Symbol("*") => BinaryOperator::Multiply,
+                TokenKind::Symbol("/") => BinaryOperator::Divide,
+                _ => return Ok(left),
+            };
+            self.pos += 1;
+            let right = self.primary()?;
+            left = binary_op(op, left, right);
+        }
+    }
+    
+    fn primary(&mut self) -> Result<Expr, ParseError> {
+        if self.eat_symbol("(") {
+            let expr = self.expr()?;
+            self.expect_symbol(")")?;
+            return Ok(expr);
+        }
+        match &self.peek().kind {
+            TokenKind::Word(w) if is_literal_keyword(w) => self.literal().map(Expr::Literal),
+            TokenKind::Word(_) | TokenKind::QuotedIdent(_) => {
+                let name = self.name()?;
+                if !self.eat_symbol("(") {
+                    return Ok(Expr::Column(name));
+                }
+                let args = match self.eat_symbol(")") {
+                    true => Vec::new(),
+                    false => {
+                        let args = self.comma_separated(Self::expr)?;
+                        self.expect_symbol(")")?;
+                        args
+                    }
+                };
+                Ok(Expr::FunctionCall { name, args })
+            }
+            _ => self.literal().map(Expr::Literal),
+        }
+    }
+    
+    fn literal(&mut self) -> Result<Value, ParseError> {
+        let token = self.next();
+        match token.kind {
+            TokenKind::Int(n) => Ok(Value::Int(n)),
+            TokenKind::Float(f) => Ok(Value::Float(f)),
+            TokenKind::Str(s) => Ok(Value::String(s)),
+            TokenKind::Word(w) if w.eq_ignore_ascii_case("TRUE") => Ok(Value::Bool(true)),
+            TokenKind::Word(w) if w.eq_ignore_ascii_case("FALSE") => Ok(Value::Bool(false)),
+            TokenKind::Word(w) if w.eq_ignore_ascii_case("NULL") => Ok(Value::Null),
+            _ => Err(self.unexpected(&token)),
+        }
+    }
+}
+
+/// One SELECT of a statement, before ordering.
+struct SelectBranch {
+    distinct: bool,
+    columns: Option<Vec<(Expr, Option<String>)>>,
+    table: String,
+    filter: Option<Expr>,
+}
+
+impl SelectBranch {
+    fn plan(self, mut order_by: Vec<(String, SortOrder)>) -> LogicalPlan {
+        // Sort below the projection, so rows can be ordered by columns the
+        // projection drops, unless every key is a projected name, which may
+        // be an alias only the projection's output has. DISTINCT goes on
+        // top and keeps the first of each set of duplicates, which leaves
+        // the rows in order.
+        let planner = Planner::new();
+        let mut plan = planner.plan(self.table, self.filter);
+        let sort_projected = self.columns.as_ref().is_some_and(|columns| {
+            let names: Vec<String> = columns
+                .iter()
+                .map(|(expr, alias)| projected_name(expr, alias.as_deref()))
+                .collect();
+            order_by.iter().all(|(key, _)| names.contains(key))
+        });
+        if !order_by.is_empty() && !sort_projected {
+            plan = planner.plan_sort(plan, std::mem::take(&mut order_by));
+        }
+        if let Some(columns) = self.columns {
+            plan = planner.plan_project(plan, columns);
+        }
+        if !order_by.is_empty() {
+            plan = planner.plan_sort(plan, order_by);
+        }
+        if self.distinct {
+            plan = planner.plan_distinct(plan);
+        }
+        plan
+    }
+}
+
+fn binary_op(op: BinaryOperator, left: Expr, right: Expr) -> Expr {
+    Expr::BinaryOp {
+        op,
+        left: Box::new(left),
+        right: Box::new(right),
+    }
+}
+
+fn is_literal_keyword(word: &str) -> bool {
+    ["TRUE", "FALSE", "NULL"].iter().any(|k| word.eq_ignore_ascii_case(k))
+}
+
+/// Words that can't name a table or column without quotes.
+fn is_reserved(word: &str) -> bool {
+    const RESERVED: [&str; 31] = [
+        "SELECT", "FROM", "WHERE", "ORDER", "BY", "LIMIT", "AND", "OR", "NOT", "NULL", "TRUE",
+        "FALSE", "CREATE", "TABLE", "INSERT", "INTO", "VALUES", "DELETE", "PRIMARY", "UNIQUE",
+        "DEFAULT", "IS", "LIKE", "IN", "BETWEEN", "DISTINCT", "DROP", "AS", "UNION", "ALL",
+        "EXISTS",
+    ];
+    RESERVED.iter().any(|k| word.eq_ignore_ascii_case(k))
+}
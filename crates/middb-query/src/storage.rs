@@ -0,0 +1,375 @@
+use crate::executor::Row;
+use crate::expr::Value;
+use crate::migrate::{encode_row, RowMigrator};
+use crate::stream::{RowStream, Rows};
+use middb_core::catalog::{
+    split_qualified, DataType, IdentifierCasing, TableSchema, DEFAULT_NAMESPACE,
+};
+use middb_core::{Database, TxnId};
+use std::collections::{HashMap, HashSet};
+use std::ops::Bound;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// A source of rows for a table the executor doesn't hold itself.
+pub trait TableProvider {
+    /// Every row of the table.
+    fn scan(&self) -> Result<Vec<Row>, String>;
+    
+    /// Remove `removed` and add `written` as one change: all of it happens
+    /// or none does. A row in both, by key, is rewritten in place.
+    fn write(&self, removed: &[Row], written: &[Row]) -> Result<(), String>;
+    
+    /// Remove every row, as when the table is dropped.
+    fn truncate(&self) -> Result<(), String> {
+        self.write(&self.scan()?, &[])
+    }
+    
+    /// The table's rows one at a time. By default, everything `scan`
+    /// returns; sources that can read lazily should.
+    fn stream(&self) -> Result<Box<dyn RowStream + '_>, String> {
+        Ok(Box::new(Rows(self.scan()?.into_iter())))
+    }
+}
+
+/// Rows read from the database this many keys at a time.
+const PAGE_SIZE: usize = 128;
+
+/// Where a table's rows live and how it's keyed in a registry: the name
+/// qualified with its namespace, then folded.
+pub(crate) fn table_id(name: &str, casing: IdentifierCasing) -> String {
+    let (namespace, table) = split_qualified(name);
+    format!(
+        "{}.{}",
+        casing.fold(namespace.unwrap_or(DEFAULT_NAMESPACE)),
+        casing.fold(table)
+    )
+}
+
+/// A table whose rows are stored in a `Database`, one key per row under
+/// `t/<table id>/`, followed by the primary key encoded so keys sort as the
+/// key values do. Tables without a primary key number their rows instead.
+/// Values are encoded by `encode_row` and migrated on read, so rows written
+/// before an ALTER come back in the current shape.
+///
+/// The schema is read from the database's catalog on every call.
+pub struct StoredTable {
+    db: Arc<Database>,
+    name: String,
+    prefix: Vec<u8>,
+    casing: IdentifierCasing,
+    /// Next number for a row of a table without a primary key.
+    next_row_id: AtomicU64,
+}
+
+impl StoredTable {
+    pub fn open(db: Arc<Database>, name: &str) -> Result<Self, String> {
+        let casing = db.catalog().read().unwrap().identifier_casing();
+        if db.get_schema(name).is_none() {
+            return Err(format!("Table not found: {}", name));
+        }
+        let mut prefix = b"t/".to_vec();
+        prefix.extend_from_slice(table_id(name, casing).as_bytes());
+        prefix.push(b'/');
+        
+        let last = db.scan_prefix(&prefix).map_err(|e| e.to_string())?.pop();
+        let next_row_id = match last {
+            Some((key, _)) if key.len() == prefix.len() + 8 => {
+                u64::from_be_bytes(key[prefix.len()..].try_into().unwrap()) + 1
+            }
+            _ => 0,
+        };
+        Ok(StoredTable {
+            db,
+            name: name.to_string(),
+            prefix,
+            casing,
+            next_row_id: AtomicU64::new(next_row_id),
+        })
+    }
+    
+    fn schema(&self) -> Result<TableSchema, String> {
+        self.db
+            .get_schema(&self.name)
+            .ok_or_else(|| format!("Table not found: {}", self.name))
+    }
+    
+    /// The database key `row` is stored under, or `None` if the table has
+    /// no primary key.
+    pub fn key(&self, row: &Row) -> Result<Option<Vec<u8>>, String> {
+        self.key_of(&self.schema()?, row)
+    }
+    
+    fn key_of(&self, schema: &TableSchema, row: &Row) -> Result<Option<Vec<u8>>, String> {
+        let primary_key = match schema.primary_key() {
+            Some(pk) => pk,
+            None => return Ok(None),
+        };
+        let values: Vec<Value> = primary_key
+            .iter()
+            .map(|c| row.find_column(c, self.casing).unwrap_or(Value::Null))
+            .collect();
+        self.key_for(schema, &values).map(Some)
+    }
+    
+    /// The key for a row whose leading primary key columns hold `values`.
+    /// Every row whose key starts with those values has a key starting
+    /// with this one.
+    fn key_for(&self, schema: &TableSchema, values: &[Value]) -> Result<Vec<u8>, String> {
+        let primary_key = schema.primary_key().unwrap_or(&[]);
+        let mut key = self.prefix.clone();
+        for (column, value) in primary_key.iter().zip(values) {
+            let data_type = schema
+                .find_column(column, self.casing)
+                .map(|c| c.data_type)
+                .ok_or_else(|| format!("column '{}' not found in table '{}'", column, schema.name))?;
+            encode_key_value(&mut key, value, &data_type).ok_or_else(|| {
+                format!(
+                    "cannot use {:?} as a key of column '{}' of '{}', which is {}",
+                    value, column, schema.name, data_type
+                )
+            })?;
+        }
+        Ok(key)
+    }
+    
+    /// Rows whose primary key lies between `low` and `high`, each giving
+    /// values for leading key columns, read with one range scan.
+    pub fn scan_range(&self, low: Bound<&[Value]>, high: Bound<&[Value]>) -> Result<Vec<Row>, String> {
+        let schema = self.schema()?;
+        if schema.primary_key().is_none() {
+            return Err(format!("table '{}' has no primary key", schema.name));
+        }
+        let start = match low {
+            Bound::Included(values) => self.key_for(&schema, values)?,
+            Bound::Excluded(values) => prefix_end(&self.key_for(&schema, values)?),
+            Bound::Unbounded => self.prefix.clone(),
+        };
+        let end = match high {
+            Bound::Included(values) => prefix_end(&self.key_for(&schema, values)?),
+            Bound::Excluded(values) => self.key_for(&schema, values)?,
+            Bound::Unbounded => prefix_end(&self.prefix),
+        };
+        if start >= end {
+            return Ok(Vec::new());
+        }
+        let migrator = RowMigrator::new(schema);
+        self.db
+            .scan_range(&start, &end)
+            .map_err(|e| e.to_string())?
+            .iter()
+            .map(|(_, value)| migrator.decode(value))
+            .collect()
+    }
+    
+    /// `row` with its columns named as the schema names them.
+    fn canonical(&self, schema: &TableSchema, row: &Row) -> Row {
+        let columns = row
+            .columns
+            .iter()
+            .map(|(name, value)| {
+                let name = schema
+                    .find_column(name, self.casing)
+                    .map_or_else(|| name.clone(), |c| c.name.clone());
+                (name, value.clone())
+            })
+            .collect();
+        Row { columns }
+    }
+    
+    /// Keys of stored rows equal to `rows`, for a table without a primary
+    /// key: any one of several equal rows will do.
+    fn find_keys(&self, schema: &TableSchema, rows: &[Row]) -> Result<Vec<Vec<u8>>, String> {
+        let migrator = RowMigrator::new(schema.clone());
+        let mut stored = Vec::new();
+        for (key, value) in self.db.scan_prefix(&self.prefix).map_err(|e| e.to_string())? {
+            stored.push((key, migrator.decode(&value)?));
+        }
+        let mut taken = HashSet::new();
+        let mut keys = Vec::with_capacity(rows.len());
+        for row in rows {
+            let row = self.canonical(schema, row);
+            let found = stored
+                .iter()
+                .enumerate()
+                .find(|(i, (_, stored))| !taken.contains(i) && *stored == row);
+            match found {
+                Some((i, (key, _))) => {
+                    taken.insert(i);
+                    keys.push(key.clone());
+                }
+                None => return Err(format!("row not found in '{}'", schema.name)),
+            }
+        }
+        Ok(keys)
+    }
+    
+    /// Run `f` in a transaction, committed if it succeeds.
+    fn in_txn(&self, f: impl FnOnce(TxnId) -> middb_core::Result<()>) -> Result<(), String> {
+        let txn = self.db.begin_txn();
+        match f(txn) {
+            Ok(()) => self.db.commit_txn(txn).map_err(|e| e.to_string()),
+            Err(e) => {
+                let _ = self.db.abort_txn(txn);
+                Err(e.to_string())
+            }
+        }
+    }
+}
+
+impl TableProvider for StoredTable {
+    fn scan(&self) -> Result<Vec<Row>, String> {
+        let migrator = RowMigrator::new(self.schema()?);
+        self.db
+            .scan_prefix(&self.prefix)
+            .map_err(|e| e.to_string())?
+            .iter()
+            .map(|(_, value)| migrator.decode(value))
+            .collect()
+    }
+    
+    fn stream(&self) -> Result<Box<dyn RowStream + '_>, String> {
+        Ok(Box::new(StoredRows {
+            db: &self.db,
+            migrator: RowMigrator::new(self.schema()?),
+            next: Some(self.prefix.clone()),
+            end: prefix_end(&self.prefix),
+            page: Vec::new().into_iter(),
+        }))
+    }
+    
+    fn write(&self, removed: &[Row], written: &[Row]) -> Result<(), String> {
+        let schema = self.schema()?;
+        let mut puts = HashMap::new();
+        for row in written {
+            let key = match self.key_of(&schema, row)? {
+                Some(key) => key,
+                None => {
+                    let mut key = self.prefix.clone();
+                    let id = self.next_row_id.fetch_add(1, Ordering::SeqCst);
+                    key.extend_from_slice(&id.to_be_bytes());
+                    key
+                }
+            };
+            puts.insert(key, encode_row(&schema, &self.canonical(&schema, row)));
+        }
+        let deletes = match schema.primary_key() {
+            Some(_) => removed
+                .iter()
+                .map(|row| self.key_of(&schema, row).map(Option::unwrap))
+                .collect::<Result<Vec<_>, _>>()?,
+            None => self.find_keys(&schema, removed)?,
+        };
+        
+        self.in_txn(|txn| {
+            deletes
+                .into_iter()
+                .filter(|key| !puts.contains_key(key))
+                .try_for_each(|key| self.db.delete_txn(txn, key))?;
+            puts.into_iter()
+                .try_for_each(|(key, value)| self.db.put_txn(txn, key, value))
+        })
+    }
+    
+    fn truncate(&self) -> Result<(), String> {
+        let keys = self.db.scan_prefix(&self.prefix).map_err(|e| e.to_string())?;
+        self.in_txn(|txn| {
+            keys.into_iter()
+                .try_for_each(|(key, _)| self.db.delete_txn(txn, key))
+        })
+    }
+}
+
+/// A stored table's rows, read a page of keys at a time as they're pulled.
+struct StoredRows<'a> {
+    db: &'a Database,
+    migrator: RowMigrator,
+    /// Where the next page starts, or `None` once the range is read.
+    next: Option<Vec<u8>>,
+    end: Vec<u8>,
+    page: std::vec::IntoIter<(Vec<u8>, Vec<u8>)>,
+}
+
+impl RowStream for StoredRows<'_> {
+    fn next(&mut self) -> Option<Result<Row, String>> {
+        loop {
+            if let Some((_, value)) = self.page.next() {
+                return Some(self.migrator.decode(&value));
+            }
+            let start = self.next.take()?;
+            match self.db.scan_range_page(&start, &self.end, PAGE_SIZE) {
+                Ok((page, next)) => {
+                    self.page = page.into_iter();
+                    self.next = next;
+                }
+                Err(e) => return Some(Err(e.to_string())),
+            }
+        }
+    }
+}
+
+/// Append `value` to a key so that keys compare as the values do within
+/// a column of `data_type`: fixed-width big-endian numbers with the sign
+/// bit flipped, and strings and bytes with zero bytes escaped and a
+/// terminator that sorts below any content. `None` if the value can't be
+/// held by the column.
+fn encode_key_value(key: &mut Vec<u8>, value: &Value, data_type: &DataType) -> Option<()> {
+    match (data_type, value) {
+        (DataType::Int64, Value::Int(i)) => {
+            key.extend_from_slice(&((*i as u64) ^ (1 << 63)).to_be_bytes());
+        }
+        (DataType::Float64, Value::Int(_) | Value::Float(_) | Value::Decimal { .. }) => {
+            let f = match value {
+                Value::Int(i) => *i as f64,
+                Value::Float(f) => *f,
+                Value::Decimal { value, scale } => *value as f64 / 10f64.powi(*scale as i32),
+                _ => unreachable!(),
+            };
+            let bits = f.to_bits();
+            let ordered = if bits >> 63 == 1 { !bits } else { bits | (1 << 63) };
+            key.extend_from_slice(&ordered.to_be_bytes());
+        }
+        (DataType::Decimal { scale: column_scale, .. }, Value::Int(_) | Value::Decimal { .. }) => {
+            let (unscaled, scale) = match value {
+                Value::Int(i) => (*i as i128, 0),
+                Value::Decimal { value, scale } => (*value, *scale),
+                _ => unreachable!(),
+            };
+            let unscaled = if scale <= *column_scale {
+                unscaled.checked_mul(10i128.checked_pow((column_scale - scale) as u32)?)?
+            } else {
+                let factor = 10i128.checked_pow((scale - column_scale) as u32)?;
+                (unscaled % factor == 0).then(|| unscaled / factor)?
+            };
+            key.extend_from_slice(&((unscaled as u128) ^ (1 << 127)).to_be_bytes());
+        }
+        (DataType::Bool, Value::Bool(b)) => key.push(*b as u8),
+        (DataType::Timestamp, Value::Timestamp(t)) => key.extend_from_slice(&t.to_be_bytes()),
+        (DataType::String, Value::String(s)) => encode_key_bytes(key, s.as_bytes()),
+        (DataType::Bytes, Value::Bytes(b)) => encode_key_bytes(key, b),
+        _ => return None,
+    }
+    Some(())
+}
+
+fn encode_key_bytes(key: &mut Vec<u8>, bytes: &[u8]) {
+    for &byte in bytes {
+        key.push(byte);
+        if byte == 0 {
+            key.push(0xFF);
+        }
+    }
+    key.extend_from_slice(&[0, 1]);
+}
+
+/// The smallest key greater than every key starting with `prefix`.
+fn prefix_end(prefix: &[u8]) -> Vec<u8> {
+    let mut end = prefix.to_vec();
+    while let Some(last) = end.pop() {
+        if last < u8::MAX {
+            end.push(last + 1);
+            return end;
+        }
+    }
+    end
+}
@@ -0,0 +1,161 @@
+use crate::executor::Row;
+
+/// A pull-based source of rows. Operators ask their input for a row only
+/// when asked for one themselves, so a plan reads no further than its
+/// consumer does.
+pub trait RowStream {
+    /// The next row, or `None` once the stream is exhausted.
+    fn next(&mut self) -> Option<Result<Row, String>>;
+    
+    /// Drain the stream, stopping at the first error.
+    fn collect(&mut self) -> Result<Vec<Row>, String> {
+        let mut rows = Vec::new();
+        while let Some(row) = self.next() {
+            rows.push(row?);
+        }
+        Ok(rows)
+    }
+}
+
+pub(crate) type BoxedStream<'a> = Box<dyn RowStream + 'a>;
+
+/// Rows already at hand, or computed one at a time by an iterator.
+pub(crate) struct Rows<I>(pub(crate) I);
+
+impl<I: Iterator<Item = Row>> RowStream for Rows<I> {
+    fn next(&mut self) -> Option<Result<Row, String>> {
+        self.0.next().map(Ok)
+    }
+}
+
+pub(crate) struct Filter<'a, F> {
+    pub(crate) input: BoxedStream<'a>,
+    pub(crate) keep: F,
+}
+
+impl<F: FnMut(&Row) -> bool> RowStream for Filter<'_, F> {
+    fn next(&mut self) -> Option<Result<Row, String>> {
+        loop {
+            match self.input.next()? {
+                Ok(row) if !(self.keep)(&row) => continue,
+                result => return Some(result),
+            }
+        }
+    }
+}
+
+pub(crate) struct Map<'a, F> {
+    pub(crate) input: BoxedStream<'a>,
+    pub(crate) f: F,
+}
+
+impl<F: FnMut(Row) -> Result<Row, String>> RowStream for Map<'_, F> {
+    fn next(&mut self) -> Option<Result<Row, String>> {
+        Some(self.input.next()?.and_then(&mut self.f))
+    }
+}
+
+/// Each input row turned into any number of output rows, as a join turns
+/// a row of one side into its matches.
+pub(crate) struct FlatMap<'a, F> {
+    pub(crate) input: BoxedStream<'a>,
+    pub(crate) f: F,
+    pub(crate) pending: std::vec::IntoIter<Row>,
+}
+
+impl<'a, F> FlatMap<'a, F> {
+    pub(crate) fn new(input: BoxedStream<'a>, f: F) -> Self {
+        FlatMap {
+            input,
+            f,
+            pending: Vec::new().into_iter(),
+        }
+    }
+}
+
+impl<F: FnMut(Row) -> Result<Vec<Row>, String>> RowStream for FlatMap<'_, F> {
+    fn next(&mut self) -> Option<Result<Row, String>> {
+        loop {
+            if let Some(row) = self.pending.next() {
+                return Some(Ok(row));
+            }
+            match self.input.next()?.and_then(&mut self.f) {
+                Ok(rows) => self.pending = rows.into_iter(),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+/// Each input's rows in turn, pulling from an input only once the ones
+/// before it are exhausted.
+pub(crate) struct Chain<'a> {
+    pub(crate) inputs: std::collections::VecDeque<BoxedStream<'a>>,
+}
+
+impl<'a> Chain<'a> {
+    pub(crate) fn new(inputs: Vec<BoxedStream<'a>>) -> Self {
+        Chain {
+            inputs: inputs.into(),
+        }
+    }
+}
+
+impl RowStream for Chain<'_> {
+    fn next(&mut self) -> Option<Result<Row, String>> {
+        loop {
+            match self.inputs.front_mut()?.next() {
+                Some(row) => return Some(row),
+                None => {
+                    self.inputs.pop_front();
+                }
+            }
+        }
+    }
+}
+
+/// Stops pulling from its input once it has passed on `remaining` rows.
+pub(crate) struct Limit<'a> {
+    pub(crate) input: BoxedStream<'a>,
+    pub(crate) remaining: u64,
+}
+
+impl RowStream for Limit<'_> {
+    fn next(&mut self) -> Option<Result<Row, String>> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        self.input.next()
+    }
+}
+
+/// An operator that needs all of its input before it can produce a row,
+/// like a sort. The input is drained on the first pull.
+pub(crate) struct Materialize<'a, F> {
+    pub(crate) input: Option<BoxedStream<'a>>,
+    pub(crate) f: Option<F>,
+    pub(crate) output: std::vec::IntoIter<Row>,
+}
+
+impl<'a, F> Materialize<'a, F> {
+    pub(crate) fn new(input: BoxedStream<'a>, f: F) -> Self {
+        Materialize {
+            input: Some(input),
+            f: Some(f),
+            output: Vec::new().into_iter(),
+        }
+    }
+}
+
+impl<F: FnOnce(Vec<Row>) -> Result<Vec<Row>, String>> RowStream for Materialize<'_, F> {
+    fn next(&mut self) -> Option<Result<Row, String>> {
+        if let (Some(mut input), Some(f)) = (self.input.take(), self.f.take()) {
+            match input.collect().and_then(f) {
+                Ok(rows) => self.output = rows.into_iter(),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        self.output.next().map(Ok)
+    }
+}
@@ -1,7 +1,16 @@
 use crate::expr::{BinaryOperator, Expr, Value};
-use crate::plan::LogicalPlan;
-use crate::planner::Planner;
-use crate::{Executor, Row, Table};
+use crate::plan::{
+    AggExpr, AggFunc, JoinType, LogicalPlan, NullOrder, PhysicalPlan, SortOrder, TableRef,
+};
+use crate::function::{ParamType, ReturnType, ScalarFunction};
+use crate::planner::{CostModel, Planner};
+use crate::sql::{self, Statement};
+use crate::{ExecutionResult, Executor, Row, Table};
+use middb_core::catalog::{
+    AlterOp, Catalog, Column, DataType, Datum, IndexDef, TableSchemaBuilder, TableStats,
+};
+use std::ops::Bound;
+use std::sync::{Arc, RwLock};
 
 #[test]
 fn test_simple_scan_plan() {
@@ -143,3 +152,3319 @@ fn test_expression_evaluation() {
     let rows = executor.execute(physical).unwrap();
     assert_eq!(rows.len(), 1);
 }
+
+fn people_executor() -> Executor {
+    let mut catalog = Catalog::new();
+    catalog
+        .register_table(
+            TableSchemaBuilder::new("people")
+                .column("id", DataType::Int64, false)
+                .column("name", DataType::String, false)
+                .column("age", DataType::Int64, true)
+                .build(),
+        )
+        .unwrap();
+    let mut executor = Executor::with_catalog(Arc::new(RwLock::new(catalog)));
+    for (id, name, age) in [(1, "ann", 31), (2, "bob", 27)] {
+        executor
+            .insert(
+                "people",
+                Row::new_with_values(vec![
+                    ("id".to_string(), Value::Int(id)),
+                    ("name".to_string(), Value::String(name.to_string())),
+                    ("age".to_string(), Value::Int(age)),
+                ]),
+            )
+            .unwrap();
+    }
+    executor
+}
+
+#[test]
+fn test_executor_projection_keeps_column_names() {
+    let executor = people_executor();
+    let planner = Planner::new();
+    let columns = vec!["name".to_string(), "id".to_string()];
+    let logical = planner.plan_select("people".to_string(), Some(columns), None);
+    assert!(matches!(logical, LogicalPlan::Project { .. }));
+    
+    let rows = executor.execute(planner.to_physical(logical)).unwrap();
+    assert_eq!(rows.len(), 2);
+    for row in &rows {
+        let mut names: Vec<&String> = row.columns.keys().collect();
+        names.sort();
+        assert_eq!(names, ["id", "name"]);
+    }
+    assert_eq!(rows[0].get_column("name"), Some(Value::String("ann".to_string())));
+    assert_eq!(rows[1].get_column("id"), Some(Value::Int(2)));
+}
+
+#[test]
+fn test_executor_projection_validates_columns() {
+    let executor = people_executor();
+    let planner = Planner::new();
+    
+    let plan = planner.plan_select("people".to_string(), Some(vec!["salary".to_string()]), None);
+    let err = executor.execute(planner.to_physical(plan)).unwrap_err();
+    assert_eq!(err, "column 'salary' not found in table 'people'");
+    
+    // Under a filter, and filtering on a column the projection dropped.
+    let adults = Expr::BinaryOp {
+        op: BinaryOperator::Ge,
+        left: Box::new(Expr::Column("age".to_string())),
+        right: Box::new(Expr::Literal(Value::Int(18))),
+    };
+    for columns in [vec!["salary".to_string()], vec!["name".to_string()]] {
+        let project = planner.plan_select("people".to_string(), Some(columns), None);
+        let filtered = LogicalPlan::Filter {
+            input: Box::new(project),
+            predicate: adults.clone(),
+        };
+        assert!(executor.validate_plan(&planner.to_physical(filtered)).is_err());
+    }
+}
+
+#[test]
+fn test_projection_fills_missing_columns_with_null() {
+    let mut table = Table::new("loose".to_string());
+    table.add_row(Row::new_with_values(vec![
+        ("id".to_string(), Value::Int(1)),
+        ("name".to_string(), Value::String("ann".to_string())),
+    ]));
+    table.add_row(Row::new_with_values(vec![("id".to_string(), Value::Int(2))]));
+    let mut executor = Executor::new();
+    executor.register_table("loose".to_string(), table);
+    
+    let planner = Planner::new();
+    let columns = vec!["id".to_string(), "typo".to_string(), "name".to_string()];
+    let plan = planner.plan_select("loose".to_string(), Some(columns), None);
+    let rows = executor.execute(planner.to_physical(plan)).unwrap();
+    assert_eq!(rows.len(), 2);
+    for row in &rows {
+        let mut names: Vec<&String> = row.columns.keys().collect();
+        names.sort();
+        assert_eq!(names, ["id", "name", "typo"]);
+        assert_eq!(row.get_column("typo"), Some(Value::Null));
+    }
+    assert_eq!(rows[0].get_column("name"), Some(Value::String("ann".to_string())));
+    assert_eq!(rows[1].get_column("name"), Some(Value::Null));
+}
+
+#[test]
+fn test_projection_expression_names() {
+    let executor = people_executor();
+    let columns = |sql: &str| {
+        let (plan, _) = select_plan(sql);
+        executor.columns(&Planner::new().to_physical(plan)).unwrap()
+    };
+    
+    assert_eq!(
+        columns("SELECT SUBSTR(name, 0, 100), UPPER('x'), age > 30 FROM people"),
+        ["SUBSTR(name, 0, 100)", "UPPER('x')", "age > 30"]
+    );
+    assert_eq!(
+        columns("SELECT age * 2 + 1, name = 'it''s', age IS NOT NULL, 1.5 FROM people"),
+        ["(age * 2) + 1", "name = 'it''s'", "age IS NOT NULL", "1.5"]
+    );
+    
+    let rows = run_select(&executor, "SELECT age * 2 + 1, age - 1 FROM people").unwrap();
+    assert_eq!(rows[0].get_column("(age * 2) + 1"), Some(Value::Int(63)));
+    assert_eq!(rows[0].get_column("age - 1"), Some(Value::Int(30)));
+}
+
+#[test]
+fn test_arithmetic() {
+    let executor = people_executor();
+    let values = |sql: &str| -> Result<Vec<Value>, String> {
+        let rows = run_select(&executor, sql)?;
+        Ok(rows.iter().map(|row| row.get_column("v").unwrap()).collect())
+    };
+    
+    assert_eq!(
+        values("SELECT age * 2 AS v FROM people").unwrap(),
+        [Value::Int(62), Value::Int(54)]
+    );
+    assert_eq!(
+        values("SELECT age / 2 AS v FROM people").unwrap(),
+        [Value::Int(15), Value::Int(13)]
+    );
+    assert_eq!(
+        values("SELECT age + 0.5 AS v FROM people").unwrap(),
+        [Value::Float(31.5), Value::Float(27.5)]
+    );
+    // `-` binds as tightly as `+`, left to right, and `a -1` subtracts.
+    assert_eq!(
+        values("SELECT 10 - age -1 + id * 3 AS v FROM people").unwrap(),
+        [Value::Int(-19), Value::Int(-12)]
+    );
+    assert_eq!(
+        values("SELECT (age - 1) * (id + 1) AS v FROM people").unwrap(),
+        [Value::Int(60), Value::Int(78)]
+    );
+    assert_eq!(values("SELECT id AS v FROM people WHERE age - 30 > 0").unwrap(), [Value::Int(1)]);
+    assert_eq!(
+        values("SELECT id AS v FROM people WHERE age BETWEEN 20 + 5 AND 30").unwrap(),
+        [Value::Int(2)]
+    );
+    
+    // Arithmetic takes numbers.
+    let err = values("SELECT name * 2 AS v FROM people").unwrap_err();
+    assert!(err.contains("incompatible types"), "{}", err);
+    
+    use crate::expr::arithmetic;
+    let dec = |value, scale| Value::Decimal { value, scale };
+    assert_eq!(arithmetic(BinaryOperator::Plus, &dec(150, 2), &Value::Int(1)), Some(dec(250, 2)));
+    assert_eq!(arithmetic(BinaryOperator::Minus, &dec(15, 1), &dec(125, 2)), Some(dec(25, 2)));
+    assert_eq!(arithmetic(BinaryOperator::Multiply, &dec(15, 1), &dec(15, 1)), Some(dec(225, 2)));
+    assert_eq!(
+        arithmetic(BinaryOperator::Divide, &dec(15, 1), &Value::Int(2)),
+        Some(Value::Float(0.75))
+    );
+    assert_eq!(arithmetic(BinaryOperator::Divide, &Value::Int(1), &Value::Int(0)), None);
+    assert_eq!(arithmetic(BinaryOperator::Plus, &Value::Int(i64::MAX), &Value::Int(1)), None);
+    assert_eq!(
+        arithmetic(BinaryOperator::Divide, &Value::Float(1.0), &Value::Int(0)),
+        Some(Value::Float(f64::INFINITY))
+    );
+}
+
+#[test]
+fn test_projection_fails_on_evaluation_error() {
+    let mut table = Table::new("loose".to_string());
+    table.add_row(Row::new_with_values(vec![
+        ("id".to_string(), Value::Int(1)),
+        ("name".to_string(), Value::String("ann".to_string())),
+    ]));
+    let mut executor = Executor::new();
+    executor.register_table("loose".to_string(), table);
+    
+    // Without a catalog nothing is checked before the rows are read.
+    let err = run_select(&executor, "SELECT id, name * 2 FROM loose").unwrap_err();
+    assert_eq!(err, "cannot evaluate (name * 2) for column 'name * 2'");
+    let rows = run_select(&executor, "SELECT typo * 2 AS doubled FROM loose").unwrap();
+    assert_eq!(rows[0].get_column("doubled"), Some(Value::Null));
+    
+    let err = run_select(&people_executor(), "SELECT id / 0 AS v FROM people").unwrap_err();
+    assert_eq!(err, "cannot evaluate (id / 0) for column 'v'");
+}
+
+#[test]
+fn test_projection_aliases() {
+    let executor = people_executor();
+    let names = |sql: &str| -> Vec<Value> {
+        run_select(&executor, sql)
+            .unwrap()
+            .iter()
+            .map(|row| row.get_column("shout").unwrap())
+            .collect()
+    };
+    let text = |s: &str| Value::String(s.to_string());
+    
+    let rows = run_select(&executor, "SELECT id AS key, UPPER(name) FROM people").unwrap();
+    assert_eq!(rows[0].get_column("key"), Some(Value::Int(1)));
+    assert_eq!(rows[0].get_column("UPPER(name)"), Some(text("ANN")));
+    assert_eq!(rows[0].columns.len(), 2);
+    
+    assert_eq!(
+        names("SELECT UPPER(name) AS shout FROM people ORDER BY shout DESC"),
+        [text("BOB"), text("ANN")]
+    );
+    // Keys the projection drops still sort below it.
+    assert_eq!(
+        names("SELECT UPPER(name) AS shout FROM people ORDER BY age"),
+        [text("BOB"), text("ANN")]
+    );
+    
+    // A filter on an alias above the projection, pushed down or not.
+    let planner = Planner::new();
+    let project = planner.plan_project(
+        planner.plan("people".to_string(), None),
+        vec![(
+            Expr::FunctionCall {
+                name: "UPPER".to_string(),
+                args: vec![Expr::Column("name".to_string())],
+            },
+            Some("shout".to_string()),
+        )],
+    );
+    let plan = LogicalPlan::Filter {
+        input: Box::new(project),
+        predicate: Expr::BinaryOp {
+            op: BinaryOperator::Eq,
+            left: Box::new(Expr::Column("shout".to_string())),
+            right: Box::new(Expr::Literal(text("BOB"))),
+        },
+    };
+    for physical in [planner.to_physical(plan.clone()), planner.to_physical_unoptimized(plan)] {
+        let rows = executor.execute(physical).unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].get_column("shout"), Some(text("BOB")));
+    }
+    
+    let err = run_select(&executor, "SELECT id, name AS id FROM people").unwrap_err();
+    assert_eq!(err, "duplicate output column 'id'");
+}
+
+#[test]
+fn test_executor_validation_sees_altered_schema() {
+    let mut catalog = Catalog::new();
+    catalog
+        .register_table(
+            TableSchemaBuilder::new("people")
+                .column("id", DataType::Int64, false)
+                .column("name", DataType::String, false)
+                .build(),
+        )
+        .unwrap();
+    let catalog = Arc::new(RwLock::new(catalog));
+    let executor = Executor::with_catalog(Arc::clone(&catalog));
+    let planner = Planner::new();
+    
+    let age_filter = Expr::BinaryOp {
+        op: BinaryOperator::Gt,
+        left: Box::new(Expr::Column("age".to_string())),
+        right: Box::new(Expr::Literal(Value::Int(18))),
+    };
+    let plan = planner.to_physical(planner.plan("people".to_string(), Some(age_filter)));
+    assert!(executor.validate_plan(&plan).is_err());
+    
+    catalog
+        .write()
+        .unwrap()
+        .alter_table("people", AlterOp::AddColumn(Column::new("age", DataType::Int64)))
+        .unwrap();
+    assert!(executor.validate_plan(&plan).is_ok());
+    
+    catalog
+        .write()
+        .unwrap()
+        .alter_table(
+            "people",
+            AlterOp::RenameColumn {
+                from: "age".to_string(),
+                to: "years".to_string(),
+            },
+        )
+        .unwrap();
+    let err = executor.validate_plan(&plan).unwrap_err();
+    assert!(err.contains("age"), "{}", err);
+}
+
+#[test]
+fn test_executor_insert_enforces_keys() {
+    let mut catalog = Catalog::new();
+    catalog
+        .register_table(
+            TableSchemaBuilder::new("users")
+                .column("id", DataType::Int64, false)
+                .column("email", DataType::String, true)
+                .primary_key(vec!["id"])
+                .unique(vec!["email"])
+                .build(),
+        )
+        .unwrap();
+    let mut executor = Executor::with_catalog(Arc::new(RwLock::new(catalog)));
+    
+    let user = |id: i64, email: Value| {
+        Row::new_with_values(vec![
+            ("id".to_string(), Value::Int(id)),
+            ("email".to_string(), email),
+        ])
+    };
+    
+    executor.insert("users", user(1, Value::String("a@x".to_string()))).unwrap();
+    executor.insert("users", user(2, Value::Null)).unwrap();
+    executor.insert("users", user(3, Value::Null)).unwrap();
+    
+    let dup_pk = executor.insert("users", user(1, Value::String("b@x".to_string())));
+    assert!(dup_pk.unwrap_err().contains("constraint violation"));
+    
+    let dup_email = executor.insert("users", user(4, Value::String("a@x".to_string())));
+    assert!(dup_email.unwrap_err().contains("email"));
+    
+    let null_pk = executor.insert(
+        "users",
+        Row::new_with_values(vec![("email".to_string(), Value::String("c@x".to_string()))]),
+    );
+    assert!(null_pk.is_err());
+    
+    let planner = Planner::new();
+    let rows = executor
+        .execute(planner.to_physical(planner.plan("users".to_string(), None)))
+        .unwrap();
+    assert_eq!(rows.len(), 3);
+}
+
+#[test]
+fn test_float_total_order() {
+    use std::cmp::Ordering;
+    
+    let nan = Value::Float(f64::NAN);
+    let inf = Value::Float(f64::INFINITY);
+    assert_eq!(nan.compare(&inf), Some(Ordering::Greater));
+    assert_eq!(nan.compare(&Value::Float(f64::NAN)), Some(Ordering::Equal));
+    assert_eq!(
+        Value::Float(-0.0).compare(&Value::Float(0.0)),
+        Some(Ordering::Equal)
+    );
+    assert_eq!(Value::Int(3).compare(&Value::Float(2.5)), Some(Ordering::Greater));
+    assert_eq!(
+        Value::Timestamp(1_000).compare(&Value::Timestamp(999)),
+        Some(Ordering::Greater)
+    );
+    assert_eq!(Value::Timestamp(1).compare(&Value::Int(1)), None);
+}
+
+#[test]
+fn test_float_values() {
+    for x in [0.1, -0.0, 1e300, 2.5e-8, f64::INFINITY, f64::NEG_INFINITY, f64::NAN] {
+        let text = Value::Float(x).to_string();
+        assert_eq!(Value::Float(text.parse().unwrap()), Value::Float(x), "{}", text);
+    }
+    assert_eq!(Value::Float(3.0).to_string(), "3.0");
+    assert_eq!(Value::Float(-0.0).to_string(), "-0.0");
+    assert_eq!(Value::Float(0.0), Value::Float(-0.0));
+    assert_eq!(Value::Int(3), Value::Float(3.0));
+    assert_eq!(Value::Int(3), Value::Decimal { value: 300, scale: 2 });
+    
+    assert_eq!(Value::Float(3.0).as_int(), Some(3));
+    assert_eq!(Value::Float(3.5).as_int(), None);
+    assert_eq!(Value::Float(9.3e18).as_int(), None);
+    assert_eq!(Value::Int(1 << 53).as_float(), Some(9_007_199_254_740_992.0));
+    assert_eq!(Value::Int((1 << 53) + 1).as_float(), None);
+    
+    let mut table = Table::new("m".to_string());
+    for (id, x) in [(1, 2.5), (2, f64::NAN), (3, -1.0), (4, 0.25), (5, f64::INFINITY)] {
+        table.add_row(Row::new_with_values(vec![
+            ("id".to_string(), Value::Int(id)),
+            ("x".to_string(), Value::Float(x)),
+        ]));
+    }
+    let mut executor = Executor::new();
+    executor.register_table("m".to_string(), table);
+    let planner = Planner::new();
+    let positive = Expr::BinaryOp {
+        op: BinaryOperator::Gt,
+        left: Box::new(Expr::Column("x".to_string())),
+        right: Box::new(Expr::Literal(Value::Int(0))),
+    };
+    let plan = LogicalPlan::Sort {
+        input: Box::new(planner.plan("m".to_string(), Some(positive))),
+        keys: vec![("x".to_string(), SortOrder::Asc)],
+        nulls: NullOrder::Last,
+    };
+    let ids: Vec<i64> = executor
+        .execute(planner.to_physical(plan))
+        .unwrap()
+        .iter()
+        .map(|row| row.get_column("id").and_then(|v| v.as_int()).unwrap())
+        .collect();
+    assert_eq!(ids, [4, 1, 5, 2]);
+    
+    let finite = Expr::BinaryOp {
+        op: BinaryOperator::Lt,
+        left: Box::new(Expr::Column("x".to_string())),
+        right: Box::new(Expr::Literal(Value::Int(10))),
+    };
+    let plan = planner.plan_aggregate(
+        planner.plan("m".to_string(), Some(finite)),
+        Vec::new(),
+        vec![AggExpr::new(AggFunc::Sum, "x"), AggExpr::new(AggFunc::Avg, "x")],
+    );
+    let rows = executor.execute(planner.to_physical(plan)).unwrap();
+    assert_eq!(rows[0].get_column("sum_x"), Some(Value::Float(1.75)));
+    assert_eq!(rows[0].get_column("avg_x"), Some(Value::Float(1.75 / 3.0)));
+}
+
+#[test]
+fn test_decimal_comparison_across_scales() {
+    use std::cmp::Ordering;
+    
+    let d = |value: i128, scale: u8| Value::Decimal { value, scale };
+    assert_eq!(d(150, 2).compare(&d(15, 1)), Some(Ordering::Equal));
+    assert_eq!(d(1005, 3).compare(&d(101, 2)), Some(Ordering::Less));
+    assert_eq!(d(250, 2).compare(&Value::Int(2)), Some(Ordering::Greater));
+    assert_eq!(Value::Int(-1).compare(&d(-5, 1)), Some(Ordering::Less));
+    assert_eq!(d(125, 2).compare(&Value::Float(1.25)), Some(Ordering::Equal));
+    // Rescaling i128::MAX would overflow; the comparison must still be exact in sign.
+    assert_eq!(d(1, 30).compare(&d(i128::MAX, 0)), Some(Ordering::Less));
+    assert_eq!(d(1, 30).compare(&d(i128::MIN, 0)), Some(Ordering::Greater));
+    
+    let mut executor = Executor::new();
+    let mut table = Table::new("prices".to_string());
+    for (sku, cents) in [("a", 150), ("b", 275)] {
+        table.add_row(Row::new_with_values(vec![
+            ("sku".to_string(), Value::String(sku.to_string())),
+            ("price".to_string(), d(cents, 2)),
+        ]));
+    }
+    executor.register_table("prices".to_string(), table);
+    
+    let filter = Expr::BinaryOp {
+        op: BinaryOperator::Eq,
+        left: Box::new(Expr::Column("price".to_string())),
+        right: Box::new(Expr::Literal(d(15, 1))),
+    };
+    let planner = Planner::new();
+    let rows = executor
+        .execute(planner.to_physical(planner.plan("prices".to_string(), Some(filter))))
+        .unwrap();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].get_column("sku"), Some(Value::String("a".to_string())));
+}
+
+#[test]
+fn test_numeric_literal_against_decimal_column() {
+    let catalog = Arc::new(RwLock::new(Catalog::new()));
+    catalog
+        .write()
+        .unwrap()
+        .register_table(
+            TableSchemaBuilder::new("accounts")
+                .column("balance", DataType::Decimal { precision: 10, scale: 2 }, false)
+                .column("opened", DataType::Timestamp, false)
+                .build(),
+        )
+        .unwrap();
+    let executor = Executor::with_catalog(catalog);
+    let planner = Planner::new();
+    
+    let cmp = |lit: Value| Expr::BinaryOp {
+        op: BinaryOperator::Gt,
+        left: Box::new(Expr::Column("balance".to_string())),
+        right: Box::new(Expr::Literal(lit)),
+    };
+    for lit in [Value::Int(0), Value::Float(0.5), Value::Decimal { value: 1, scale: 0 }] {
+        let plan = planner.to_physical(planner.plan("accounts".to_string(), Some(cmp(lit))));
+        assert!(executor.validate_plan(&plan).is_ok());
+    }
+    
+    let bad = Expr::BinaryOp {
+        op: BinaryOperator::Gt,
+        left: Box::new(Expr::Column("opened".to_string())),
+        right: Box::new(Expr::Literal(Value::Float(1.0))),
+    };
+    let plan = planner.to_physical(planner.plan("accounts".to_string(), Some(bad)));
+    assert!(executor.validate_plan(&plan).is_err());
+}
+
+#[test]
+fn test_int_float_coercion() {
+    use std::cmp::Ordering;
+    
+    let catalog = Arc::new(RwLock::new(Catalog::new()));
+    catalog
+        .write()
+        .unwrap()
+        .register_table(
+            TableSchemaBuilder::new("stock")
+                .column("sku", DataType::String, false)
+                .column("qty", DataType::Int64, false)
+                .column("price", DataType::Float64, false)
+                .column("active", DataType::Bool, false)
+                .build(),
+        )
+        .unwrap();
+    let mut executor = Executor::with_catalog(catalog);
+    for (sku, qty, price) in [("a", 3, 99.5), ("b", 4, 100.0), ("c", 100, 150.25)] {
+        executor
+            .insert(
+                "stock",
+                Row::new_with_values(vec![
+                    ("sku".to_string(), Value::String(sku.to_string())),
+                    ("qty".to_string(), Value::Int(qty)),
+                    ("price".to_string(), Value::Float(price)),
+                    ("active".to_string(), Value::Bool(true)),
+                ]),
+            )
+            .unwrap();
+    }
+    let skus = |cond: &str| -> Result<Vec<String>, String> {
+        let rows = run_select(&executor, &format!("SELECT sku FROM stock WHERE {}", cond))?;
+        Ok(rows
+            .iter()
+            .map(|r| r.get_column("sku").unwrap().as_string().unwrap().to_string())
+            .collect())
+    };
+    
+    // Integers and floats compare by value, on either side.
+    assert_eq!(skus("price > 100").unwrap(), vec!["c"]);
+    assert_eq!(skus("price >= 100").unwrap(), vec!["b", "c"]);
+    assert_eq!(skus("qty = 3.0").unwrap(), vec!["a"]);
+    assert_eq!(skus("qty < 3.5").unwrap(), vec!["a"]);
+    assert_eq!(skus("qty = price").unwrap(), Vec::<String>::new());
+    assert_eq!(skus("100 = qty").unwrap(), vec!["c"]);
+    assert_eq!(skus("qty IN (4.0, 7)").unwrap(), vec!["b"]);
+    
+    // Beyond 2^53 floats can't hold every integer; integers still compare
+    // by exact value rather than rounding to the nearest float.
+    let two_53 = 1i64 << 53;
+    let float = Value::Float(two_53 as f64);
+    assert_eq!(Value::Int(two_53).compare(&float), Some(Ordering::Equal));
+    assert_eq!(Value::Int(two_53 + 1).compare(&float), Some(Ordering::Greater));
+    assert_eq!(float.compare(&Value::Int(two_53 + 1)), Some(Ordering::Less));
+    assert_eq!(Value::Int(-two_53 - 1).compare(&Value::Float(-(two_53 as f64))), Some(Ordering::Less));
+    assert_eq!(Value::Int(i64::MAX).compare(&Value::Float(i64::MAX as f64)), Some(Ordering::Less));
+    assert_eq!(Value::Int(i64::MIN).compare(&Value::Float(i64::MIN as f64)), Some(Ordering::Equal));
+    assert_eq!(Value::Int(-3).compare(&Value::Float(-2.5)), Some(Ordering::Less));
+    assert_eq!(Value::Int(0).compare(&Value::Float(-0.0)), Some(Ordering::Equal));
+    assert_eq!(Value::Int(i64::MAX).compare(&Value::Float(f64::NAN)), Some(Ordering::Less));
+    // Equal values hash alike, so joins and DISTINCT agree with `=`.
+    assert_ne!(Value::Int(two_53 + 1).hash_key(), float.hash_key());
+    assert_eq!(Value::Int(3).hash_key(), Value::Float(3.0).hash_key());
+    
+    // Strings don't compare with numbers, booleans only with booleans, and
+    // AND and OR need booleans.
+    assert_eq!(skus("sku > 1").unwrap_err(), "incompatible types for Gt: STRING and INT64");
+    assert_eq!(skus("price = '1.5'").unwrap_err(), "incompatible types for Eq: FLOAT64 and STRING");
+    assert_eq!(skus("active = 1").unwrap_err(), "incompatible types for Eq: BOOL and INT64");
+    assert_eq!(skus("active AND qty").unwrap_err(), "incompatible types for And: BOOL and INT64");
+    assert_eq!(skus("active = TRUE").unwrap().len(), 3);
+}
+
+#[test]
+fn test_executor_insert_applies_defaults() {
+    let mut catalog = Catalog::new();
+    catalog
+        .register_table(
+            TableSchemaBuilder::new("tasks")
+                .column("id", DataType::Int64, false)
+                .column_with_default("done", DataType::Bool, false, Datum::Bool(false))
+                .column("note", DataType::String, true)
+                .build(),
+        )
+        .unwrap();
+    let mut executor = Executor::with_catalog(Arc::new(RwLock::new(catalog)));
+    
+    executor
+        .insert("tasks", Row::new_with_values(vec![("id".to_string(), Value::Int(1))]))
+        .unwrap();
+    executor
+        .insert(
+            "tasks",
+            Row::new_with_values(vec![
+                ("id".to_string(), Value::Int(2)),
+                ("done".to_string(), Value::Bool(true)),
+            ]),
+        )
+        .unwrap();
+
+    let missing_id = executor.insert(
+        "tasks",
+        Row::new_with_values(vec![("note".to_string(), Value::String("x".to_string()))]),
+    );
+    assert!(missing_id.unwrap_err().contains("no default"));
+    
+    let planner = Planner::new();
+    let mut rows = executor
+        .execute(planner.to_physical(planner.plan("tasks".to_string(), None)))
+        .unwrap();
+    rows.sort_by_key(|r| r.get_column("id").and_then(|v| v.as_int()));
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[0].get_column("done"), Some(Value::Bool(false)));
+    assert_eq!(rows[0].get_column("note"), Some(Value::Null));
+    assert_eq!(rows[1].get_column("done"), Some(Value::Bool(true)));
+}
+
+#[test]
+fn test_analyze_computes_and_persists_stats() {
+    use middb_core::{Config, Database};
+    
+    let dir = tempfile::TempDir::new().unwrap();
+    let db = Database::open(Config::new(dir.path())).unwrap();
+    db.create_table(
+        TableSchemaBuilder::new("visits")
+            .column("user", DataType::Int64, false)
+            .column("page", DataType::String, true)
+            .column("referrer", DataType::String, true)
+            .build(),
+    )
+    .unwrap();
+    
+    let mut executor = Executor::with_catalog(db.catalog());
+    let visits = [
+        (3, Some("/home")),
+        (1, Some("/about")),
+        (3, Some("/home")),
+        (2, None),
+        (1, Some("/home")),
+    ];
+    for (user, page) in visits {
+        let page = page.map_or(Value::Null, |p| Value::String(p.to_string()));
+        executor
+            .insert(
+                "visits",
+                Row::new_with_values(vec![
+                    ("user".to_string(), Value::Int(user)),
+                    ("page".to_string(), page),
+                ]),
+            )
+            .unwrap();
+    }
+    
+    let stats = executor.analyze("visits").unwrap();
+    assert_eq!(stats.row_count, 5);
+    assert!(stats.total_bytes > 0);
+    
+    let user = stats.column("user").unwrap();
+    assert_eq!(user.distinct_count, 3);
+    assert_eq!(user.null_count, 0);
+    assert_eq!(user.min, Some(Datum::Int(1)));
+    assert_eq!(user.max, Some(Datum::Int(3)));
+    
+    let page = stats.column("page").unwrap();
+    assert_eq!(page.distinct_count, 2);
+    assert_eq!(page.null_count, 1);
+    assert_eq!(page.min, Some(Datum::String("/about".to_string())));
+    assert_eq!(page.max, Some(Datum::String("/home".to_string())));
+    
+    let referrer = stats.column("referrer").unwrap();
+    assert_eq!(referrer.null_count, 5);
+    assert_eq!(referrer.distinct_count, 0);
+    assert_eq!(referrer.min, None);
+    
+    assert_eq!(db.catalog().read().unwrap().get_stats("visits"), Some(&stats));
+    db.update_stats("visits", stats.clone()).unwrap();
+    db.close().unwrap();
+    
+    let db = Database::open(Config::new(dir.path())).unwrap();
+    assert_eq!(db.get_stats("visits"), Some(stats));
+}
+
+#[test]
+fn test_row_migration_across_alters() {
+    use crate::{encode_row, RowMigrator};
+    use middb_core::{Config, Database};
+    
+    let dir = tempfile::TempDir::new().unwrap();
+    let db = Database::open(Config::new(dir.path())).unwrap();
+    db.create_table(
+        TableSchemaBuilder::new("items")
+            .column("id", DataType::Int64, false)
+            .column("legacy", DataType::String, true)
+            .build(),
+    )
+    .unwrap();
+    
+    let v1 = db.get_schema("items").unwrap();
+    for id in 1..=3 {
+        let row = Row::new_with_values(vec![
+            ("id".to_string(), Value::Int(id)),
+            ("legacy".to_string(), Value::String(format!("old-{}", id))),
+        ]);
+        db.put(format!("rows/items/{}", id).into_bytes(), encode_row(&v1, &row)).unwrap();
+    }
+    
+    db.alter_table(
+        "items",
+        AlterOp::AddColumn(Column::new("qty", DataType::Int64).with_default(Datum::Int(7))),
+    )
+    .unwrap();
+    let migrator = RowMigrator::new(db.get_schema("items").unwrap());
+    let stored = db.get(&b"rows/items/1".to_vec()).unwrap().unwrap();
+    let row = migrator.decode(&stored).unwrap();
+    assert_eq!(row.get_column("qty"), Some(Value::Int(7)));
+    assert_eq!(row.get_column("legacy"), Some(Value::String("old-1".to_string())));
+    
+    db.alter_table("items", AlterOp::DropColumn("legacy".to_string())).unwrap();
+    let migrator = RowMigrator::new(db.get_schema("items").unwrap());
+    let row = migrator.decode(&stored).unwrap();
+    assert_eq!(row.get_column("legacy"), None);
+    assert_eq!(row.get_column("id"), Some(Value::Int(1)));
+    assert_eq!(row.get_column("qty"), Some(Value::Int(7)));
+    
+    assert_eq!(migrator.migrate_all(&db, b"rows/items/").unwrap(), 3);
+    let rewritten = db.get(&b"rows/items/1".to_vec()).unwrap().unwrap();
+    assert_ne!(rewritten, stored);
+    let (version, _) = crate::decode_row(&rewritten).unwrap();
+    assert_eq!(version, migrator.schema().schema_version);
+    assert_eq!(migrator.migrate_all(&db, b"rows/items/").unwrap(), 0);
+    drop(db);
+    
+    // The change history is part of the persisted schema, so lazy migration
+    // keeps working after a restart.
+    let db = Database::open(Config::new(dir.path())).unwrap();
+    let schema = db.get_schema("items").unwrap();
+    assert_eq!(schema.changes_since(1).count(), 2);
+    let row = RowMigrator::new(schema).decode(&stored).unwrap();
+    assert_eq!(row.get_column("qty"), Some(Value::Int(7)));
+}
+
+#[test]
+fn test_executor_folds_identifiers() {
+    use middb_core::catalog::IdentifierCasing;
+    
+    let schema = || {
+        TableSchemaBuilder::new("Users")
+            .column("Id", DataType::Int64, false)
+            .column("Name", DataType::String, true)
+            .build()
+    };
+    let users = |executor: &mut Executor| {
+        for (id, name) in [(1, "ann"), (2, "bo")] {
+            executor
+                .insert(
+                    "Users",
+                    Row::new_with_values(vec![
+                        ("Id".to_string(), Value::Int(id)),
+                        ("Name".to_string(), Value::String(name.to_string())),
+                    ]),
+                )
+                .unwrap();
+        }
+    };
+    let filter = Expr::BinaryOp {
+        op: BinaryOperator::Eq,
+        left: Box::new(Expr::Column("ID".to_string())),
+        right: Box::new(Expr::Literal(Value::Int(2))),
+    };
+    let planner = Planner::new();
+    let plan = || planner.to_physical(planner.plan("users".to_string(), Some(filter.clone())));
+    
+    let mut catalog = Catalog::with_identifier_casing(IdentifierCasing::LowercaseFold);
+    catalog.register_table(schema()).unwrap();
+    let mut folding = Executor::with_catalog(Arc::new(RwLock::new(catalog)));
+    users(&mut folding);
+    let rows = folding.execute(plan()).unwrap();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].get_column("Name"), Some(Value::String("bo".to_string())));
+    assert_eq!(
+        rows[0].find_column("name", IdentifierCasing::LowercaseFold),
+        Some(Value::String("bo".to_string()))
+    );
+    
+    let mut catalog = Catalog::new();
+    catalog.register_table(schema()).unwrap();
+    let mut exact = Executor::with_catalog(Arc::new(RwLock::new(catalog)));
+    users(&mut exact);
+    assert!(exact.execute(plan()).unwrap_err().contains("not found"));
+}
+
+#[test]
+fn test_executor_resolves_qualified_tables() {
+    let mut catalog = Catalog::new();
+    catalog.create_namespace("analytics").unwrap();
+    let events = || {
+        TableSchemaBuilder::new("events")
+            .column("id", DataType::Int64, false)
+            .build()
+    };
+    catalog.register_table(events()).unwrap();
+    catalog.register_table_in("analytics", events()).unwrap();
+    let mut executor = Executor::with_catalog(Arc::new(RwLock::new(catalog)));
+    
+    executor
+        .insert("events", Row::new_with_values(vec![("id".to_string(), Value::Int(1))]))
+        .unwrap();
+    for id in 10..13 {
+        executor
+            .insert(
+                "analytics.events",
+                Row::new_with_values(vec![("id".to_string(), Value::Int(id))]),
+            )
+            .unwrap();
+    }
+    
+    let planner = Planner::new();
+    let count = |table: &str| {
+        executor
+            .execute(planner.to_physical(planner.plan(table.to_string(), None)))
+            .map(|rows| rows.len())
+    };
+    assert_eq!(count("events"), Ok(1));
+    assert_eq!(count("public.events"), Ok(1));
+    assert_eq!(count("analytics.events"), Ok(3));
+    assert!(count("other.events").is_err());
+}
+
+fn sorted_ids(
+    rows: &[(i64, Option<&str>, Option<i64>)],
+    keys: Vec<(&str, SortOrder)>,
+    nulls: NullOrder,
+) -> Vec<i64> {
+    let mut table = Table::new("t".to_string());
+    for (id, name, score) in rows {
+        let mut columns = vec![("id".to_string(), Value::Int(*id))];
+        if let Some(name) = name {
+            columns.push(("name".to_string(), Value::String(name.to_string())));
+        }
+        // A missing score is absent from the row, not NULL.
+        if let Some(score) = score {
+            columns.push(("score".to_string(), Value::Int(*score)));
+        }
+        table.add_row(Row::new_with_values(columns));
+    }
+    let mut executor = Executor::new();
+    executor.register_table("t".to_string(), table);
+    
+    let planner = Planner::new();
+    let keys = keys.into_iter().map(|(c, o)| (c.to_string(), o)).collect();
+    let plan = LogicalPlan::Sort {
+        input: Box::new(planner.plan("t".to_string(), None)),
+        keys,
+        nulls,
+    };
+    executor
+        .execute(planner.to_physical(plan))
+        .unwrap()
+        .iter()
+        .map(|row| row.get_column("id").and_then(|v| v.as_int()).unwrap())
+        .collect()
+}
+
+#[test]
+fn test_sort_multiple_keys() {
+    let rows = [
+        (1, Some("bob"), Some(10)),
+        (2, Some("ann"), Some(20)),
+        (3, Some("bob"), Some(30)),
+        (4, Some("ann"), Some(5)),
+    ];
+    let asc = SortOrder::Asc;
+    let desc = SortOrder::Desc;
+    
+    assert_eq!(sorted_ids(&rows, vec![("score", asc)], NullOrder::Last), [4, 1, 2, 3]);
+    assert_eq!(
+        sorted_ids(&rows, vec![("name", asc), ("score", desc)], NullOrder::Last),
+        [2, 4, 3, 1]
+    );
+    assert_eq!(
+        sorted_ids(&rows, vec![("name", desc), ("score", asc)], NullOrder::Last),
+        [1, 3, 4, 2]
+    );
+}
+
+#[test]
+fn test_sort_ties_keep_input_order() {
+    let rows = [
+        (1, Some("b"), Some(1)),
+        (2, Some("a"), Some(1)),
+        (3, Some("b"), Some(1)),
+        (4, Some("a"), Some(1)),
+        (5, Some("b"), Some(0)),
+    ];
+    assert_eq!(
+        sorted_ids(&rows, vec![("name", SortOrder::Asc)], NullOrder::Last),
+        [2, 4, 1, 3, 5]
+    );
+    assert_eq!(
+        sorted_ids(&rows, vec![("score", SortOrder::Desc), ("name", SortOrder::Desc)], NullOrder::Last),
+        [1, 3, 2, 4, 5]
+    );
+}
+
+#[test]
+fn test_sort_null_placement() {
+    // Row 2's name is NULL only by absence; row 4 lacks the score column.
+    let rows = [
+        (1, Some("c"), Some(3)),
+        (2, None, Some(1)),
+        (3, Some("a"), Some(2)),
+        (4, Some("b"), None),
+    ];
+    for order in [SortOrder::Asc, SortOrder::Desc] {
+        let ids = sorted_ids(&rows, vec![("name", order)], NullOrder::Last);
+        assert_eq!(ids.last(), Some(&2));
+        let ids = sorted_ids(&rows, vec![("score", order)], NullOrder::First);
+        assert_eq!(ids.first(), Some(&4));
+    }
+    assert_eq!(sorted_ids(&rows, vec![("score", SortOrder::Desc)], NullOrder::Last), [1, 3, 2, 4]);
+    assert_eq!(sorted_ids(&rows, vec![("name", SortOrder::Asc)], NullOrder::First), [2, 3, 4, 1]);
+}
+
+#[test]
+fn test_sql_order_by_executes() {
+    let executor = people_executor();
+    let planner = Planner::new();
+    let (plan, _) = select_plan("SELECT name FROM people ORDER BY age");
+    let rows = executor.execute(planner.to_physical(plan)).unwrap();
+    let names: Vec<Value> = rows.iter().filter_map(|r| r.get_column("name")).collect();
+    assert_eq!(names, [Value::String("bob".to_string()), Value::String("ann".to_string())]);
+    
+    let (plan, _) = select_plan("SELECT * FROM people ORDER BY salary");
+    let err = executor.execute(planner.to_physical(plan)).unwrap_err();
+    assert!(err.contains("salary"), "{}", err);
+}
+
+fn orders_executor(rows: &[(&str, Option<i64>)]) -> Executor {
+    let mut catalog = Catalog::new();
+    catalog
+        .register_table(
+            TableSchemaBuilder::new("orders")
+                .column("customer", DataType::String, false)
+                .column("price", DataType::Int64, true)
+                .build(),
+        )
+        .unwrap();
+    let mut executor = Executor::with_catalog(Arc::new(RwLock::new(catalog)));
+    for (customer, price) in rows {
+        let price = price.map(Value::Int).unwrap_or(Value::Null);
+        executor
+            .insert(
+                "orders",
+                Row::new_with_values(vec![
+                    ("customer".to_string(), Value::String(customer.to_string())),
+                    ("price".to_string(), price),
+                ]),
+            )
+            .unwrap();
+    }
+    executor
+}
+
+fn aggregate(
+    executor: &Executor,
+    group_by: &[&str],
+    aggregates: Vec<AggExpr>,
+) -> Result<Vec<Row>, String> {
+    let planner = Planner::new();
+    let plan = planner.plan_aggregate(
+        planner.plan("orders".to_string(), None),
+        group_by.iter().map(|c| c.to_string()).collect(),
+        aggregates,
+    );
+    executor.execute(planner.to_physical(plan))
+}
+
+#[test]
+fn test_grouped_aggregation() {
+    let executor = orders_executor(&[
+        ("ann", Some(10)),
+        ("bob", Some(5)),
+        ("ann", Some(30)),
+        ("ann", None),
+        ("bob", None),
+    ]);
+    let rows = aggregate(
+        &executor,
+        &["customer"],
+        vec![
+            AggExpr::count_star(),
+            AggExpr::new(AggFunc::Count, "price"),
+            AggExpr::new(AggFunc::Sum, "price"),
+            AggExpr::new(AggFunc::Avg, "price").with_alias("mean"),
+            AggExpr::new(AggFunc::Min, "price"),
+            AggExpr::new(AggFunc::Max, "price"),
+        ],
+    )
+    .unwrap();
+    
+    assert_eq!(rows.len(), 2);
+    let ann = &rows[0];
+    assert_eq!(ann.get_column("customer"), Some(Value::String("ann".to_string())));
+    assert_eq!(ann.get_column("count"), Some(Value::Int(3)));
+    assert_eq!(ann.get_column("count_price"), Some(Value::Int(2)));
+    assert_eq!(ann.get_column("sum_price"), Some(Value::Int(40)));
+    assert_eq!(ann.get_column("mean"), Some(Value::Float(20.0)));
+    assert_eq!(ann.get_column("min_price"), Some(Value::Int(10)));
+    assert_eq!(ann.get_column("max_price"), Some(Value::Int(30)));
+    let bob = &rows[1];
+    assert_eq!(bob.get_column("count"), Some(Value::Int(2)));
+    assert_eq!(bob.get_column("sum_price"), Some(Value::Int(5)));
+}
+
+#[test]
+fn test_global_aggregation_and_null_groups() {
+    let executor = orders_executor(&[("ann", None), ("bob", None)]);
+    let rows = aggregate(
+        &executor,
+        &[],
+        vec![AggExpr::count_star(), AggExpr::new(AggFunc::Sum, "price"), AggExpr::new(AggFunc::Avg, "price")],
+    )
+    .unwrap();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].get_column("count"), Some(Value::Int(2)));
+    assert_eq!(rows[0].get_column("sum_price"), Some(Value::Null));
+    assert_eq!(rows[0].get_column("avg_price"), Some(Value::Null));
+    
+    // NULL keys form one group.
+    let rows = aggregate(&executor, &["price"], vec![AggExpr::count_star()]).unwrap();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].get_column("count"), Some(Value::Int(2)));
+}
+
+#[test]
+fn test_aggregation_over_empty_input() {
+    let executor = orders_executor(&[]);
+    let aggregates = || vec![AggExpr::count_star(), AggExpr::new(AggFunc::Sum, "price")];
+    
+    let rows = aggregate(&executor, &[], aggregates()).unwrap();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].get_column("count"), Some(Value::Int(0)));
+    assert_eq!(rows[0].get_column("sum_price"), Some(Value::Null));
+    
+    assert!(aggregate(&executor, &["customer"], aggregates()).unwrap().is_empty());
+}
+
+#[test]
+fn test_aggregation_type_validation() {
+    let executor = orders_executor(&[("ann", Some(1))]);
+    
+    let err = aggregate(&executor, &[], vec![AggExpr::new(AggFunc::Sum, "customer")]).unwrap_err();
+    assert_eq!(err, "SUM requires a numeric column, but 'customer' of 'orders' is STRING");
+    let err = aggregate(&executor, &[], vec![AggExpr::new(AggFunc::Avg, "customer")]).unwrap_err();
+    assert!(err.starts_with("AVG requires a numeric column"), "{}", err);
+    
+    // MIN, MAX and COUNT take any type; unknown columns are still caught.
+    assert!(aggregate(&executor, &[], vec![AggExpr::new(AggFunc::Max, "customer")]).is_ok());
+    assert!(aggregate(&executor, &["region"], vec![AggExpr::count_star()]).is_err());
+    assert!(aggregate(&executor, &[], vec![AggExpr::new(AggFunc::Count, "region")]).is_err());
+}
+
+/// `(customer, price)` of each row `sql` selects from orders, in order.
+fn customer_prices(executor: &Executor, sql: &str) -> Vec<(Value, Value)> {
+    let (plan, _) = select_plan(sql);
+    let rows = executor.execute(Planner::new().to_physical(plan)).unwrap();
+    rows.iter()
+        .map(|row| {
+            let get = |c| row.get_column(c).unwrap_or(Value::Null);
+            (get("customer"), get("price"))
+        })
+        .collect()
+}
+
+#[test]
+fn test_distinct_rows() {
+    let executor = orders_executor(&[
+        ("ann", Some(5)),
+        ("bob", None),
+        ("ann", Some(5)),
+        ("bob", None),
+        ("ann", Some(7)),
+        ("cat", Some(5)),
+    ]);
+    let s = |v: &str| Value::String(v.to_string());
+    
+    // Rows repeated across every column collapse, NULLs included, and the
+    // first of each keeps its place.
+    assert_eq!(
+        customer_prices(&executor, "SELECT DISTINCT * FROM orders"),
+        vec![
+            (s("ann"), Value::Int(5)),
+            (s("bob"), Value::Null),
+            (s("ann"), Value::Int(7)),
+            (s("cat"), Value::Int(5)),
+        ]
+    );
+    assert_eq!(customer_prices(&executor, "SELECT * FROM orders").len(), 6);
+    
+    // Only the projected columns decide what's a duplicate.
+    assert_eq!(
+        customer_prices(&executor, "SELECT DISTINCT customer FROM orders ORDER BY customer"),
+        vec![(s("ann"), Value::Null), (s("bob"), Value::Null), (s("cat"), Value::Null)]
+    );
+    assert_eq!(
+        customer_prices(&executor, "SELECT DISTINCT price FROM orders ORDER BY price DESC"),
+        vec![(Value::Null, Value::Int(7)), (Value::Null, Value::Int(5)), (Value::Null, Value::Null)]
+    );
+    
+    // A filter above DISTINCT is pushed below it without changing the rows.
+    let planner = Planner::new();
+    let plan = LogicalPlan::Filter {
+        input: Box::new(planner.plan_distinct(planner.plan("orders".to_string(), None))),
+        predicate: Expr::BinaryOp {
+            op: BinaryOperator::Eq,
+            left: col("customer"),
+            right: lit(s("ann")),
+        },
+    };
+    assert!(matches!(
+        planner.to_physical(plan.clone()),
+        PhysicalPlan::HashDistinct { .. }
+    ));
+    let rows = executor.execute(planner.to_physical(plan)).unwrap();
+    assert_eq!(rows.len(), 2);
+}
+
+fn col(name: &str) -> Box<Expr> {
+    Box::new(Expr::Column(name.to_string()))
+}
+
+fn lit(value: Value) -> Box<Expr> {
+    Box::new(Expr::Literal(value))
+}
+
+fn select_plan(sql: &str) -> (LogicalPlan, Option<u64>) {
+    match sql::parse(sql) {
+        Ok(Statement::Select { plan, limit }) => (plan, limit),
+        other => panic!("expected SELECT from {:?}, got {:?}", sql, other),
+    }
+}
+
+#[test]
+fn test_sql_select_corpus() {
+    let scan = |filter: Option<Expr>| LogicalPlan::Scan {
+        table: "users".to_string(),
+        filter,
+    };
+    let age_over_30 = Expr::BinaryOp {
+        op: BinaryOperator::Gt,
+        left: col("age"),
+        right: lit(Value::Int(30)),
+    };
+    
+    let cases = vec![
+        ("SELECT * FROM users", scan(None), None),
+        ("select * from users;", scan(None), None),
+        (
+            "SELECT id, name FROM users",
+            LogicalPlan::Project {
+                input: Box::new(scan(None)),
+                columns: vec![(*col("id"), None), (*col("name"), None)],
+            },
+            None,
+        ),
+        (
+            "SELECT DISTINCT name FROM users",
+            LogicalPlan::Distinct {
+                input: Box::new(LogicalPlan::Project {
+                    input: Box::new(scan(None)),
+                    columns: vec![(*col("name"), None)],
+                }),
+            },
+            None,
+        ),
+        ("SELECT * FROM users WHERE age > 30", scan(Some(age_over_30.clone())), None),
+        (
+            "SELECT * FROM users WHERE age > 30 AND name = 'ann' OR active = TRUE",
+            scan(Some(Expr::BinaryOp {
+                op: BinaryOperator::Or,
+                left: Box::new(Expr::BinaryOp {
+                    op: BinaryOperator::And,
+                    left: Box::new(age_over_30.clone()),
+                    right: Box::new(Expr::BinaryOp {
+                        op: BinaryOperator::Eq,
+                        left: col("name"),
+                        right: lit(Value::String("ann".to_string())),
+                    }),
+                }),
+                right: Box::new(Expr::BinaryOp {
+                    op: BinaryOperator::Eq,
+                    left: col("active"),
+                    right: lit(Value::Bool(true)),
+                }),
+            })),
+            None,
+        ),
+        (
+            "SELECT * FROM users WHERE age > 30 AND (id <> -1 OR note = NULL)",
+            scan(Some(Expr::BinaryOp {
+                op: BinaryOperator::And,
+                left: Box::new(age_over_30),
+                right: Box::new(Expr::BinaryOp {
+                    op: BinaryOperator::Or,
+                    left: Box::new(Expr::BinaryOp {
+                        op: BinaryOperator::Ne,
+                        left: col("id"),
+                        right: lit(Value::Int(-1)),
+                    }),
+                    right: Box::new(Expr::BinaryOp {
+                        op: BinaryOperator::Eq,
+                        left: col("note"),
+                        right: lit(Value::Null),
+                    }),
+                }),
+            })),
+            None,
+        ),
+        (
+            "SELECT name FROM users ORDER BY age DESC, name LIMIT 10",
+            LogicalPlan::Project {
+                input: Box::new(LogicalPlan::Sort {
+                    input: Box::new(scan(None)),
+                    keys: vec![
+                        ("age".to_string(), SortOrder::Desc),
+                        ("name".to_string(), SortOrder::Asc),
+                    ],
+                    nulls: NullOrder::Last,
+                }),
+                columns: vec![(*col("name"), None)],
+            },
+            Some(10),
+        ),
+        (
+            "SELECT * FROM analytics.users",
+            LogicalPlan::Scan {
+                table: "analytics.users".to_string(),
+                filter: None,
+            },
+            None,
+        ),
+    ];
+    
+    for (text, plan, limit) in cases {
+        assert_eq!(select_plan(text), (plan, limit), "{}", text);
+    }
+}
+
+#[test]
+fn test_sql_string_literals() {
+    let literal = |text: &str| match select_plan(&format!("SELECT * FROM t WHERE s = {}", text)).0 {
+        LogicalPlan::Scan { filter: Some(Expr::BinaryOp { right, .. }), .. } => *right,
+        plan => panic!("unexpected plan {:?}", plan),
+    };
+    let string = |s: &str| Expr::Literal(Value::String(s.to_string()));
+    
+    assert_eq!(literal("'it''s'"), string("it's"));
+    assert_eq!(literal(r"'a\nb\t\\ \'q\''"), string("a\nb\t\\ 'q'"));
+    assert_eq!(literal("''"), string(""));
+    assert_eq!(literal("false"), Expr::Literal(Value::Bool(false)));
+    assert_eq!(literal("2.5"), Expr::Literal(Value::Float(2.5)));
+}
+
+#[test]
+fn test_sql_create_table() {
+    let sql = "CREATE TABLE users (
+        id INT PRIMARY KEY,
+        name VARCHAR(64) NOT NULL,
+        email TEXT UNIQUE,
+        balance DECIMAL(10, 2) DEFAULT 0,
+        active BOOLEAN DEFAULT TRUE
+    )";
+    let schema = match sql::parse(sql).unwrap() {
+        Statement::CreateTable { schema, if_not_exists: false } => schema,
+        other => panic!("expected CREATE TABLE, got {:?}", other),
+    };
+    
+    assert_eq!(schema.name, "users");
+    assert_eq!(schema.primary_key(), Some(&["id".to_string()][..]));
+    assert_eq!(schema.unique_constraints(), &[vec!["email".to_string()]]);
+    let expected = vec![
+        Column::non_null("id", DataType::Int64),
+        Column::non_null("name", DataType::String),
+        Column::new("email", DataType::String),
+        Column::new("balance", DataType::Decimal { precision: 10, scale: 2 })
+            .with_default(Datum::Int(0)),
+        Column::new("active", DataType::Bool).with_default(Datum::Bool(true)),
+    ];
+    let actual: Vec<Column> = schema
+        .columns
+        .iter()
+        .map(|c| Column { position: 0, ..c.clone() })
+        .collect();
+    assert_eq!(actual, expected);
+    
+    let composite = sql::parse("CREATE TABLE m (a INT, b INT, PRIMARY KEY (a, b))").unwrap();
+    match composite {
+        Statement::CreateTable { schema, .. } => {
+            assert_eq!(schema.primary_key(), Some(&["a".to_string(), "b".to_string()][..]));
+            assert!(schema.columns.iter().all(|c| !c.nullable));
+        }
+        other => panic!("expected CREATE TABLE, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_sql_insert_and_delete() {
+    match sql::parse("INSERT INTO users (id, name) VALUES (1, 'ann'), (2, NULL)").unwrap() {
+        Statement::Insert { table, columns, rows } => {
+            assert_eq!(table, "users");
+            assert_eq!(columns, Some(vec!["id".to_string(), "name".to_string()]));
+            assert_eq!(
+                rows,
+                vec![
+                    vec![Value::Int(1), Value::String("ann".to_string())],
+                    vec![Value::Int(2), Value::Null],
+                ]
+            );
+        }
+        other => panic!("expected INSERT, got {:?}", other),
+    }
+    match sql::parse("insert into users values (3, 'cy', true)").unwrap() {
+        Statement::Insert { columns, rows, .. } => {
+            assert_eq!(columns, None);
+            assert_eq!(rows[0].len(), 3);
+        }
+        other => panic!("expected INSERT, got {:?}", other),
+    }
+    
+    match sql::parse("DELETE FROM users WHERE id = 2").unwrap() {
+        Statement::Delete { table, filter } => {
+            assert_eq!(table, "users");
+            assert_eq!(
+                filter,
+                Some(Expr::BinaryOp {
+                    op: BinaryOperator::Eq,
+                    left: col("id"),
+                    right: lit(Value::Int(2)),
+                })
+            );
+        }
+        other => panic!("expected DELETE, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_sql_error_positions() {
+    let cases = [
+        ("SELECT * FORM users", "unexpected token 'FORM'", 1, 10),
+        ("SELECT id, FROM users", "unexpected token 'FROM'", 1, 12),
+        ("SELECT * FROM users WHERE", "unexpected end of input", 1, 26),
+        ("SELECT *\nFROM users\nWHERE age >> 3", "unexpected token '>'", 3, 12),
+        ("SELECT * FROM users LIMIT -1", "unexpected token '-1'", 1, 27),
+        ("SELECT * FROM t WHERE s = 'open", "unterminated quoted literal", 1, 27),
+        ("UPDATE users SET a = 1", "unexpected token 'UPDATE'", 1, 1),
+        ("INSERT INTO t VALUES (1, 2", "unexpected end of input", 1, 27),
+        ("CREATE TABLE t (id FLOATY)", "unexpected token 'FLOATY'", 1, 20),
+        ("SELECT * FROM users extra", "unexpected token 'extra'", 1, 21),
+        ("SELECT * FROM t WHERE a = 1 ? 2", "unexpected character '?'", 1, 29),
+    ];
+    
+    for (text, message, line, column) in cases {
+        let err = sql::parse(text).unwrap_err();
+        assert_eq!(
+            (err.message.as_str(), err.line, err.column),
+            (message, line, column),
+            "{}",
+            text
+        );
+    }
+    assert_eq!(
+        sql::parse("SELECT * FORM users").unwrap_err().to_string(),
+        "unexpected token 'FORM' at 1:10"
+    );
+    
+    // Constraint errors point at the table.
+    let err = sql::parse("CREATE TABLE t (a INT, PRIMARY KEY (b))").unwrap_err();
+    assert_eq!((err.line, err.column), (1, 14));
+}
+
+fn shop_executor(users: &[(i64, &str)], orders: &[(i64, i64, i64)]) -> Executor {
+    let mut catalog = Catalog::new();
+    catalog
+        .register_table(
+            TableSchemaBuilder::new("users")
+                .column("id", DataType::Int64, false)
+                .column("name", DataType::String, false)
+                .build(),
+        )
+        .unwrap();
+    catalog
+        .register_table(
+            TableSchemaBuilder::new("orders")
+                .column("id", DataType::Int64, false)
+                .column("user_id", DataType::Int64, false)
+                .column("amount", DataType::Int64, false)
+                .build(),
+        )
+        .unwrap();
+    let mut executor = Executor::with_catalog(Arc::new(RwLock::new(catalog)));
+    for (id, name) in users {
+        executor
+            .insert(
+                "users",
+                Row::new_with_values(vec![
+                    ("id".to_string(), Value::Int(*id)),
+                    ("name".to_string(), Value::String(name.to_string())),
+                ]),
+            )
+            .unwrap();
+    }
+    for (id, user_id, amount) in orders {
+        executor
+            .insert(
+                "orders",
+                Row::new_with_values(vec![
+                    ("id".to_string(), Value::Int(*id)),
+                    ("user_id".to_string(), Value::Int(*user_id)),
+                    ("amount".to_string(), Value::Int(*amount)),
+                ]),
+            )
+            .unwrap();
+    }
+    executor
+}
+
+fn eq(left: Box<Expr>, right: Box<Expr>) -> Expr {
+    Expr::BinaryOp {
+        op: BinaryOperator::Eq,
+        left,
+        right,
+    }
+}
+
+fn join(executor: &Executor, left: TableRef, right: TableRef, on: Expr) -> Result<Vec<Row>, String> {
+    let planner = Planner::new();
+    executor.execute(planner.to_physical(planner.plan_join(left, right, on)))
+}
+
+/// `(order id, user name)` pairs from joined rows, in order id order.
+fn order_owners(rows: &[Row]) -> Vec<(i64, String)> {
+    let mut pairs: Vec<_> = rows
+        .iter()
+        .map(|row| {
+            (
+                row.get_column("orders.id").unwrap().as_int().unwrap(),
+                row.get_column("users.name").unwrap().as_string().unwrap().to_string(),
+            )
+        })
+        .collect();
+    pairs.sort();
+    pairs
+}
+
+#[test]
+fn test_nested_loop_join() {
+    let executor = shop_executor(
+        &[(1, "ann"), (2, "bob"), (3, "cat")],
+        &[(10, 1, 5), (11, 2, 7), (12, 1, 9), (13, 4, 1)],
+    );
+    let rows = join(
+        &executor,
+        TableRef::new("users"),
+        TableRef::new("orders"),
+        eq(col("users.id"), col("user_id")),
+    )
+    .unwrap();
+    assert_eq!(
+        order_owners(&rows),
+        vec![(10, "ann".to_string()), (11, "bob".to_string()), (12, "ann".to_string())]
+    );
+    
+    // Both sides contribute to the predicate, alongside a constant.
+    let on = Expr::BinaryOp {
+        op: BinaryOperator::And,
+        left: Box::new(eq(col("users.id"), col("orders.user_id"))),
+        right: Box::new(Expr::BinaryOp {
+            op: BinaryOperator::Gt,
+            left: col("amount"),
+            right: lit(Value::Int(6)),
+        }),
+    };
+    let rows = join(&executor, TableRef::new("users"), TableRef::new("orders"), on).unwrap();
+    assert_eq!(
+        order_owners(&rows),
+        vec![(11, "bob".to_string()), (12, "ann".to_string())]
+    );
+}
+
+#[test]
+fn test_join_qualifies_colliding_columns() {
+    let executor = shop_executor(&[(1, "ann")], &[(1, 1, 5)]);
+    let rows = join(
+        &executor,
+        TableRef::new("users").with_alias("u"),
+        TableRef::new("orders").with_alias("o"),
+        eq(col("u.id"), col("o.user_id")),
+    )
+    .unwrap();
+    assert_eq!(rows.len(), 1);
+    let row = &rows[0];
+    assert_eq!(row.columns.len(), 5);
+    assert_eq!(row.get_column("u.id"), Some(Value::Int(1)));
+    assert_eq!(row.get_column("o.id"), Some(Value::Int(1)));
+    assert_eq!(row.get_column("o.amount"), Some(Value::Int(5)));
+    assert_eq!(row.get_column("id"), None);
+    
+    // A bare name is fine only when one side has it.
+    let err = join(
+        &executor,
+        TableRef::new("users"),
+        TableRef::new("orders"),
+        eq(col("id"), col("user_id")),
+    )
+    .unwrap_err();
+    assert_eq!(err, "column 'id' is ambiguous");
+    let err = join(
+        &executor,
+        TableRef::new("users").with_alias("u"),
+        TableRef::new("orders"),
+        eq(col("users.id"), col("user_id")),
+    )
+    .unwrap_err();
+    assert_eq!(err, "column 'users.id' is not produced by the input");
+}
+
+#[test]
+fn test_join_with_empty_side() {
+    let executor = shop_executor(&[(1, "ann"), (2, "bob")], &[]);
+    let on = || eq(col("users.id"), col("orders.user_id"));
+    let rows = join(&executor, TableRef::new("users"), TableRef::new("orders"), on()).unwrap();
+    assert!(rows.is_empty());
+    let rows = join(&executor, TableRef::new("orders"), TableRef::new("users"), on()).unwrap();
+    assert!(rows.is_empty());
+}
+
+/// An in-memory table with a join key `k` and a unique `id`.
+fn keyed_table(name: &str, keys: &[Value]) -> Table {
+    let mut table = Table::new(name.to_string());
+    for (id, key) in keys.iter().enumerate() {
+        table.add_row(Row::new_with_values(vec![
+            ("id".to_string(), Value::Int(id as i64)),
+            ("k".to_string(), key.clone()),
+        ]));
+    }
+    table
+}
+
+fn keyed_executor(a: &[Value], b: &[Value]) -> Executor {
+    let mut executor = Executor::new();
+    executor.register_table("a".to_string(), keyed_table("a", a));
+    executor.register_table("b".to_string(), keyed_table("b", b));
+    executor
+}
+
+/// Joined rows in a form that ignores row and column order.
+fn canonical(rows: Vec<Row>) -> Vec<Vec<(String, String)>> {
+    let mut rows: Vec<_> = rows
+        .into_iter()
+        .map(|row| {
+            let mut columns: Vec<_> = row
+                .columns
+                .into_iter()
+                .map(|(name, value)| (name, format!("{:?}", value)))
+                .collect();
+            columns.sort();
+            columns
+        })
+        .collect();
+    rows.sort();
+    rows
+}
+
+/// Run the planned join over `a` and `b`, and the same join forced through
+/// a nested loop.
+fn hash_and_nested(executor: &Executor, on: Expr) -> (PhysicalPlan, Vec<Row>, Vec<Row>) {
+    let planner = Planner::new();
+    let physical = planner.to_physical(planner.plan_join(TableRef::new("a"), TableRef::new("b"), on.clone()));
+    let hashed = executor.execute(physical.clone()).unwrap();
+    let nested = executor
+        .execute(PhysicalPlan::NestedLoopJoin {
+            left: Box::new(PhysicalPlan::SeqScan { table: "a".to_string(), filter: None }),
+            right: Box::new(PhysicalPlan::SeqScan { table: "b".to_string(), filter: None }),
+            left_name: "a".to_string(),
+            right_name: "b".to_string(),
+            on,
+            join_type: JoinType::Inner,
+        })
+        .unwrap();
+    (physical, hashed, nested)
+}
+
+#[test]
+fn test_hash_join_matches_nested_loop() {
+    let key = |i: i64| match i % 10 {
+        0 => Value::Null,
+        3 => Value::Float((i % 7) as f64),
+        5 => Value::Decimal { value: (i % 7) as i128 * 100, scale: 2 },
+        _ => Value::Int(i % 7),
+    };
+    let a: Vec<Value> = (0..200).map(key).collect();
+    let b: Vec<Value> = (0..150).map(|i| key(i * 3)).collect();
+    let executor = keyed_executor(&a, &b);
+    
+    let (physical, hashed, nested) = hash_and_nested(&executor, eq(col("a.k"), col("b.k")));
+    assert!(matches!(physical, PhysicalPlan::HashJoin { .. }));
+    assert!(!nested.is_empty());
+    assert_eq!(hashed.len(), nested.len());
+    assert_eq!(canonical(hashed), canonical(nested));
+    
+    // Extra conjuncts are applied after the hash join.
+    let on = Expr::BinaryOp {
+        op: BinaryOperator::And,
+        left: Box::new(Expr::BinaryOp {
+            op: BinaryOperator::Lt,
+            left: col("a.id"),
+            right: col("b.id"),
+        }),
+        right: Box::new(eq(col("b.k"), col("a.k"))),
+    };
+    let (physical, hashed, nested) = hash_and_nested(&executor, on);
+    match physical {
+        PhysicalPlan::Filter { input, .. } => {
+            assert!(matches!(*input, PhysicalPlan::HashJoin { .. }))
+        }
+        other => panic!("expected a filtered hash join, got {:?}", other),
+    }
+    assert_eq!(canonical(hashed), canonical(nested));
+    
+    // Without an equality between the sides there's nothing to hash on.
+    let on = Expr::BinaryOp {
+        op: BinaryOperator::Lt,
+        left: col("a.k"),
+        right: col("b.k"),
+    };
+    let (physical, _, _) = hash_and_nested(&executor, on);
+    assert!(matches!(physical, PhysicalPlan::NestedLoopJoin { .. }));
+}
+
+#[test]
+fn test_signed_zero_equality() {
+    let zeros = [Value::Float(-0.0), Value::Float(0.0), Value::Int(0)];
+    let executor = keyed_executor(&zeros, &zeros);
+    
+    // Every zero equals every other, hashed or compared.
+    let (physical, hashed, nested) = hash_and_nested(&executor, eq(col("a.k"), col("b.k")));
+    assert!(matches!(physical, PhysicalPlan::HashJoin { .. }));
+    assert_eq!(hashed.len(), 9);
+    assert_eq!(canonical(hashed), canonical(nested));
+    
+    let filter = eq(col("k"), lit(Value::Float(-0.0)));
+    let rows = executor
+        .execute(PhysicalPlan::SeqScan { table: "a".to_string(), filter: Some(filter) })
+        .unwrap();
+    assert_eq!(rows.len(), 3);
+}
+
+#[test]
+fn test_hash_join_duplicate_and_null_keys() {
+    let executor = keyed_executor(
+        &[Value::Int(1), Value::Int(1), Value::Null, Value::Int(2)],
+        &[Value::Int(1), Value::Float(1.0), Value::Int(1), Value::Null],
+    );
+    let (_, hashed, nested) = hash_and_nested(&executor, eq(col("a.k"), col("b.k")));
+    assert_eq!(hashed.len(), 6);
+    assert!(hashed
+        .iter()
+        .all(|row| row.get_column("a.k") != Some(Value::Null)));
+    assert_eq!(canonical(hashed), canonical(nested));
+}
+
+#[test]
+fn test_hash_join_builds_smaller_side() {
+    let mut catalog = Catalog::new();
+    for (table, rows) in [("users", 3), ("orders", 40)] {
+        catalog
+            .register_table(
+                TableSchemaBuilder::new(table)
+                    .column("id", DataType::Int64, false)
+                    .column(format!("{}_ref", table), DataType::Int64, false)
+                    .build(),
+            )
+            .unwrap();
+        catalog
+            .update_stats(table, TableStats { row_count: rows, ..Default::default() })
+            .unwrap();
+    }
+    let planner = Planner::with_catalog(Arc::new(RwLock::new(catalog)));
+    let build_of = |left: &str, right: &str, on: Expr| {
+        let logical = planner.plan_join(TableRef::new(left), TableRef::new(right), on);
+        match planner.to_physical(logical) {
+            PhysicalPlan::HashJoin { build_name, build_key, .. } => (build_name, build_key),
+            other => panic!("expected a hash join, got {:?}", other),
+        }
+    };
+    
+    // Bare columns are placed by the schemas.
+    let on = || eq(col("users_ref"), col("orders_ref"));
+    assert_eq!(build_of("orders", "users", on()), ("users".to_string(), "users_ref".to_string()));
+    assert_eq!(build_of("users", "orders", on()), ("users".to_string(), "users_ref".to_string()));
+    
+    // Without stats the right side is built.
+    let planner = Planner::new();
+    let logical = planner.plan_join(
+        TableRef::new("users"),
+        TableRef::new("orders"),
+        eq(col("users.id"), col("orders.id")),
+    );
+    assert!(matches!(
+        planner.to_physical(logical),
+        PhysicalPlan::HashJoin { build_name, .. } if build_name == "orders"
+    ));
+}
+
+fn person(id: i64, name: &str, age: Value) -> Row {
+    Row::new_with_values(vec![
+        ("id".to_string(), Value::Int(id)),
+        ("name".to_string(), Value::String(name.to_string())),
+        ("age".to_string(), age),
+    ])
+}
+
+fn people_by_id(executor: &mut Executor) -> Vec<(i64, String, Value)> {
+    let planner = Planner::new();
+    let scan = planner.plan_sort(
+        planner.plan("people".to_string(), None),
+        vec![("id".to_string(), SortOrder::Asc)],
+    );
+    match executor.run(planner.to_physical(scan)).unwrap() {
+        ExecutionResult::Rows(rows) => rows
+            .iter()
+            .map(|row| {
+                (
+                    row.get_column("id").unwrap().as_int().unwrap(),
+                    row.get_column("name").unwrap().as_string().unwrap().to_string(),
+                    row.get_column("age").unwrap(),
+                )
+            })
+            .collect(),
+        other => panic!("expected rows, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_insert_then_select() {
+    let mut executor = people_executor();
+    let planner = Planner::new();
+    let insert = planner.plan_insert(
+        "people".to_string(),
+        vec![person(3, "cy", Value::Int(40)), person(4, "di", Value::Null)],
+    );
+    assert_eq!(
+        executor.run(planner.to_physical(insert)).unwrap(),
+        ExecutionResult::Count(2)
+    );
+    let people = people_by_id(&mut executor);
+    assert_eq!(people.len(), 4);
+    assert_eq!(people[2], (3, "cy".to_string(), Value::Int(40)));
+    assert_eq!(people[3], (4, "di".to_string(), Value::Null));
+    
+    // DML produces no rows, so only `run` takes it.
+    let delete = planner.to_physical(planner.plan_delete("people".to_string(), None));
+    assert!(executor.execute(delete).is_err());
+}
+
+#[test]
+fn test_update_with_filter() {
+    let mut executor = people_executor();
+    let planner = Planner::new();
+    let update = planner.plan_update(
+        "people".to_string(),
+        vec![
+            ("age".to_string(), Expr::Column("id".to_string())),
+            ("name".to_string(), Expr::Literal(Value::String("old".to_string()))),
+        ],
+        Some(Expr::BinaryOp {
+            op: BinaryOperator::Gt,
+            left: col("age"),
+            right: lit(Value::Int(30)),
+        }),
+    );
+    assert_eq!(
+        executor.run(planner.to_physical(update)).unwrap(),
+        ExecutionResult::Count(1)
+    );
+    assert_eq!(
+        people_by_id(&mut executor),
+        vec![
+            (1, "old".to_string(), Value::Int(1)),
+            (2, "bob".to_string(), Value::Int(27)),
+        ]
+    );
+    
+    // A failed update leaves every row as it was.
+    let update = planner.plan_update(
+        "people".to_string(),
+        vec![("name".to_string(), Expr::Literal(Value::Int(7)))],
+        None,
+    );
+    assert!(executor.run(planner.to_physical(update)).is_err());
+    let update = planner.plan_update(
+        "people".to_string(),
+        vec![("salary".to_string(), Expr::Literal(Value::Int(7)))],
+        None,
+    );
+    assert_eq!(
+        executor.run(planner.to_physical(update)).unwrap_err(),
+        "column 'salary' not found in table 'people'"
+    );
+    assert_eq!(people_by_id(&mut executor)[0].1, "old");
+}
+
+#[test]
+fn test_delete_all_and_none() {
+    let mut executor = people_executor();
+    let planner = Planner::new();
+    let none = planner.plan_delete(
+        "people".to_string(),
+        Some(Expr::BinaryOp {
+            op: BinaryOperator::Eq,
+            left: col("name"),
+            right: lit(Value::String("zed".to_string())),
+        }),
+    );
+    assert_eq!(
+        executor.run(planner.to_physical(none)).unwrap(),
+        ExecutionResult::Count(0)
+    );
+    assert_eq!(people_by_id(&mut executor).len(), 2);
+    
+    let all = planner.plan_delete("people".to_string(), None);
+    assert_eq!(
+        executor.run(planner.to_physical(all)).unwrap(),
+        ExecutionResult::Count(2)
+    );
+    assert!(people_by_id(&mut executor).is_empty());
+}
+
+#[test]
+fn test_insert_rejects_type_mismatch() {
+    let mut executor = people_executor();
+    let planner = Planner::new();
+    let insert = |rows| planner.to_physical(planner.plan_insert("people".to_string(), rows));
+    
+    let err = executor
+        .run(insert(vec![
+            person(3, "cy", Value::Int(40)),
+            person(4, "di", Value::String("forty".to_string())),
+        ]))
+        .unwrap_err();
+    assert_eq!(
+        err,
+        "type mismatch: column 'age' of 'people' is INT64, got String(\"forty\")"
+    );
+    // The whole insert is rolled back.
+    assert_eq!(people_by_id(&mut executor).len(), 2);
+    
+    let mut nameless = person(5, "", Value::Null);
+    nameless.columns.insert("name".to_string(), Value::Null);
+    let err = executor.run(insert(vec![nameless])).unwrap_err();
+    assert_eq!(err, "column 'name' of 'people' cannot be NULL");
+}
+
+fn items_executor() -> Executor {
+    items_with_catalog().0
+}
+
+/// `items_executor`, along with its catalog for a planner to share.
+fn items_with_catalog() -> (Executor, Arc<RwLock<Catalog>>) {
+    let mut catalog = Catalog::new();
+    catalog
+        .register_table(
+            TableSchemaBuilder::new("items")
+                .column("id", DataType::Int64, false)
+                .column("name", DataType::String, true)
+                .column("qty", DataType::Int64, true)
+                .build(),
+        )
+        .unwrap();
+    let catalog = Arc::new(RwLock::new(catalog));
+    let mut executor = Executor::with_catalog(catalog.clone());
+    let items = [
+        (1, Some("apple"), Some(3)),
+        (2, Some("apricot"), None),
+        (3, None, Some(7)),
+        (4, Some("50% off"), Some(10)),
+        (5, Some("a_b"), Some(0)),
+    ];
+    for (id, name, qty) in items {
+        executor
+            .insert(
+                "items",
+                Row::new_with_values(vec![
+                    ("id".to_string(), Value::Int(id)),
+                    ("name".to_string(), name.map_or(Value::Null, |n| Value::String(n.to_string()))),
+                    ("qty".to_string(), qty.map_or(Value::Null, Value::Int)),
+                ]),
+            )
+            .unwrap();
+    }
+    (executor, catalog)
+}
+
+/// Ids of the items matching the SQL condition `cond`, in order.
+fn ids_where(executor: &Executor, cond: &str) -> Result<Vec<i64>, String> {
+    ids_planned_where(executor, &Planner::new(), cond)
+}
+
+fn ids_planned_where(executor: &Executor, planner: &Planner, cond: &str) -> Result<Vec<i64>, String> {
+    let (plan, _) = select_plan(&format!("SELECT id FROM items WHERE {} ORDER BY id", cond));
+    let rows = executor.execute(planner.to_physical(plan))?;
+    Ok(rows
+        .iter()
+        .map(|row| row.get_column("id").unwrap().as_int().unwrap())
+        .collect())
+}
+
+#[test]
+fn test_not_and_is_null() {
+    let executor = items_executor();
+    assert_eq!(ids_where(&executor, "NOT qty > 5").unwrap(), vec![1, 5]);
+    assert_eq!(ids_where(&executor, "NOT NOT qty > 5").unwrap(), vec![3, 4]);
+    assert_eq!(ids_where(&executor, "name IS NULL").unwrap(), vec![3]);
+    assert_eq!(ids_where(&executor, "name IS NOT NULL").unwrap(), vec![1, 2, 4, 5]);
+    assert_eq!(ids_where(&executor, "NOT name IS NULL AND qty IS NULL").unwrap(), vec![2]);
+    
+    let err = ids_where(&executor, "NOT qty").unwrap_err();
+    assert_eq!(err, "NOT requires a boolean, got INT64");
+}
+
+#[test]
+fn test_like() {
+    let executor = items_executor();
+    assert_eq!(ids_where(&executor, "name LIKE 'ap%'").unwrap(), vec![1, 2]);
+    assert_eq!(ids_where(&executor, "name NOT LIKE 'ap%'").unwrap(), vec![4, 5]);
+    assert_eq!(ids_where(&executor, "name LIKE '_pple'").unwrap(), vec![1]);
+    assert_eq!(ids_where(&executor, "name LIKE 'apple_'").unwrap(), Vec::<i64>::new());
+    assert_eq!(ids_where(&executor, "name LIKE '%'").unwrap(), vec![1, 2, 4, 5]);
+    assert_eq!(ids_where(&executor, "name LIKE 'a_b'").unwrap(), vec![5]);
+    // SQL strings take `\\` for a backslash, which then escapes the wildcard.
+    assert_eq!(ids_where(&executor, r"name LIKE '%\\%%'").unwrap(), vec![4]);
+    assert_eq!(ids_where(&executor, r"name LIKE 'a\\_%'").unwrap(), vec![5]);
+    
+    let err = ids_where(&executor, "qty LIKE '1%'").unwrap_err();
+    assert_eq!(err, "LIKE requires a string, got INT64");
+}
+
+#[test]
+fn test_like_patterns() {
+    use crate::expr::like_match;
+    
+    assert!(like_match("", "%"));
+    assert!(like_match("", ""));
+    assert!(!like_match("", "_"));
+    assert!(!like_match("a", ""));
+    assert!(like_match("abc", "a%c%"));
+    assert!(like_match("mississippi", "m%iss%ppi"));
+    assert!(!like_match("mississippi", "m%iss%ppix"));
+    assert!(like_match("aXbXc", "%b%"));
+    assert!(like_match("naïve", "na_ve"));
+    assert!(like_match("100%", r"100\%"));
+    assert!(!like_match("1000", r"100\%"));
+    assert!(like_match(r"a\b", r"a\\b"));
+    assert!(like_match(r"ends\", r"ends\"));
+}
+
+#[test]
+fn test_in_list() {
+    let executor = items_executor();
+    assert_eq!(ids_where(&executor, "qty IN (0, 10, 99)").unwrap(), vec![4, 5]);
+    assert_eq!(ids_where(&executor, "qty NOT IN (0, 10)").unwrap(), vec![1, 3]);
+    assert_eq!(ids_where(&executor, "qty IN (3.0)").unwrap(), vec![1]);
+    assert_eq!(ids_where(&executor, "qty IN ()").unwrap(), Vec::<i64>::new());
+    assert_eq!(ids_where(&executor, "id NOT IN ()").unwrap(), vec![1, 2, 3, 4, 5]);
+    // Missing from a list holding NULL is unknown, so NOT IN drops it.
+    assert_eq!(ids_where(&executor, "qty IN (3, NULL)").unwrap(), vec![1]);
+    assert_eq!(ids_where(&executor, "qty NOT IN (3, NULL)").unwrap(), Vec::<i64>::new());
+    
+    let err = ids_where(&executor, "qty IN (1, 'two')").unwrap_err();
+    assert_eq!(err, "incompatible types for IN: INT64 and STRING");
+}
+
+#[test]
+fn test_between() {
+    let executor = items_executor();
+    assert_eq!(ids_where(&executor, "qty BETWEEN 3 AND 7").unwrap(), vec![1, 3]);
+    assert_eq!(ids_where(&executor, "qty NOT BETWEEN 3 AND 7").unwrap(), vec![4, 5]);
+    assert_eq!(ids_where(&executor, "qty BETWEEN 7 AND 3").unwrap(), Vec::<i64>::new());
+    assert_eq!(
+        ids_where(&executor, "qty BETWEEN 1 AND 10 AND name LIKE 'a%'").unwrap(),
+        vec![1]
+    );
+    assert_eq!(ids_where(&executor, "qty BETWEEN NULL AND 10").unwrap(), Vec::<i64>::new());
+    assert_eq!(ids_where(&executor, "name BETWEEN 'b' AND 'z'").unwrap(), Vec::<i64>::new());
+    
+    let err = ids_where(&executor, "qty BETWEEN 'a' AND 5").unwrap_err();
+    assert_eq!(err, "incompatible types for BETWEEN: INT64 and STRING");
+}
+
+/// The truth value of a constant SQL condition: `None` for NULL.
+fn truth_of(executor: &Executor, cond: &str) -> Option<bool> {
+    if !ids_where(executor, cond).unwrap().is_empty() {
+        Some(true)
+    } else if !ids_where(executor, &format!("({}) IS NULL", cond)).unwrap().is_empty() {
+        None
+    } else {
+        Some(false)
+    }
+}
+
+#[test]
+fn test_three_valued_truth_tables() {
+    let executor = items_executor();
+    let sql = |v: Option<bool>| match v {
+        Some(true) => "TRUE",
+        Some(false) => "FALSE",
+        None => "NULL",
+    };
+    let values = [Some(true), Some(false), None];
+    for a in values {
+        assert_eq!(truth_of(&executor, &format!("NOT {}", sql(a))), a.map(|a| !a));
+        for b in values {
+            let and = match (a, b) {
+                (Some(false), _) | (_, Some(false)) => Some(false),
+                (Some(true), Some(true)) => Some(true),
+                _ => None,
+            };
+            let or = match (a, b) {
+                (Some(true), _) | (_, Some(true)) => Some(true),
+                (Some(false), Some(false)) => Some(false),
+                _ => None,
+            };
+            assert_eq!(truth_of(&executor, &format!("{} AND {}", sql(a), sql(b))), and);
+            assert_eq!(truth_of(&executor, &format!("{} OR {}", sql(a), sql(b))), or);
+        }
+    }
+    
+    for cond in ["NULL = NULL", "NULL <> NULL", "1 < NULL", "NULL >= 'a'"] {
+        assert_eq!(truth_of(&executor, cond), None, "{}", cond);
+    }
+    assert_eq!(truth_of(&executor, "NULL IS NULL"), Some(true));
+    assert_eq!(truth_of(&executor, "(NULL = NULL) IS NULL"), Some(true));
+    assert_eq!(truth_of(&executor, "(1 = 1) IS NOT NULL"), Some(true));
+}
+
+#[test]
+fn test_filter_keeps_only_true_rows() {
+    let executor = items_executor();
+    // Item 2 has no qty and item 3 no name.
+    assert_eq!(
+        ids_where(&executor, "qty > 5 OR name LIKE 'ap%'").unwrap(),
+        vec![1, 2, 3, 4]
+    );
+    assert_eq!(ids_where(&executor, "qty > 5 AND name LIKE '%'").unwrap(), vec![4]);
+    assert_eq!(
+        ids_where(&executor, "NOT (qty > 5 AND name LIKE 'a%')").unwrap(),
+        vec![1, 4, 5]
+    );
+    assert_eq!(ids_where(&executor, "qty = qty").unwrap(), vec![1, 3, 4, 5]);
+    assert_eq!(ids_where(&executor, "(qty > 5) IS NULL").unwrap(), vec![2]);
+}
+
+fn gt(column: &str, value: i64) -> Expr {
+    Expr::BinaryOp {
+        op: BinaryOperator::Gt,
+        left: col(column),
+        right: lit(Value::Int(value)),
+    }
+}
+
+fn and(left: Expr, right: Expr) -> Expr {
+    Expr::BinaryOp {
+        op: BinaryOperator::And,
+        left: Box::new(left),
+        right: Box::new(right),
+    }
+}
+
+fn filtered(input: LogicalPlan, predicate: Expr) -> LogicalPlan {
+    LogicalPlan::Filter {
+        input: Box::new(input),
+        predicate,
+    }
+}
+
+fn scan(table: &str, filter: Option<Expr>) -> LogicalPlan {
+    LogicalPlan::Scan {
+        table: table.to_string(),
+        filter,
+    }
+}
+
+#[test]
+fn test_push_down_through_project_and_sort() {
+    use crate::optimizer::push_down_filters;
+    
+    let planner = Planner::new();
+    let project = planner.plan_select(
+        "people".to_string(),
+        Some(vec!["id".to_string(), "age".to_string()]),
+        Some(gt("id", 0)),
+    );
+    let sorted = planner.plan_sort(project.clone(), vec![("age".to_string(), SortOrder::Asc)]);
+    let plan = filtered(sorted, gt("age", 28));
+    let expected = LogicalPlan::Sort {
+        input: Box::new(LogicalPlan::Project {
+            input: Box::new(scan("people", Some(and(gt("id", 0), gt("age", 28))))),
+            columns: vec![(*col("id"), None), (*col("age"), None)],
+        }),
+        keys: vec![("age".to_string(), SortOrder::Asc)],
+        nulls: NullOrder::Last,
+    };
+    assert_eq!(push_down_filters(plan), expected);
+    
+    // A filter on a column the projection drops stays above it.
+    let plan = filtered(project.clone(), gt("name", 1));
+    assert_eq!(push_down_filters(plan.clone()), plan);
+    
+    // Only conjuncts on group keys go below an aggregate.
+    let aggregate = planner.plan_aggregate(
+        scan("orders", None),
+        vec!["customer".to_string()],
+        vec![AggExpr::count_star()],
+    );
+    let customer_is_ann = Expr::BinaryOp {
+        op: BinaryOperator::Eq,
+        left: col("customer"),
+        right: lit(Value::String("ann".to_string())),
+    };
+    let plan = filtered(aggregate, and(gt("count", 1), customer_is_ann.clone()));
+    let expected = filtered(
+        planner.plan_aggregate(
+            scan("orders", Some(customer_is_ann)),
+            vec!["customer".to_string()],
+            vec![AggExpr::count_star()],
+        ),
+        gt("count", 1),
+    );
+    assert_eq!(push_down_filters(plan), expected);
+}
+
+#[test]
+fn test_push_down_into_join_sides() {
+    use crate::optimizer::push_down_filters;
+    
+    let planner = Planner::new();
+    let join = planner.plan_join(
+        TableRef::new("users").with_alias("u"),
+        TableRef::new("orders"),
+        and(eq(col("u.id"), col("orders.user_id")), gt("orders.amount", 5)),
+    );
+    let plan = filtered(
+        join,
+        and(
+            and(gt("u.id", 1), gt("amount", 0)),
+            Expr::BinaryOp {
+                op: BinaryOperator::Lt,
+                left: col("u.id"),
+                right: col("orders.id"),
+            },
+        ),
+    );
+    let expected = filtered(
+        LogicalPlan::Join {
+            left: Box::new(scan("users", Some(gt("id", 1)))),
+            right: Box::new(scan("orders", Some(gt("amount", 5)))),
+            left_name: "u".to_string(),
+            right_name: "orders".to_string(),
+            on: eq(col("u.id"), col("orders.user_id")),
+            join_type: JoinType::Inner,
+        },
+        // Bare names can't be placed without the schemas.
+        and(
+            gt("amount", 0),
+            Expr::BinaryOp {
+                op: BinaryOperator::Lt,
+                left: col("u.id"),
+                right: col("orders.id"),
+            },
+        ),
+    );
+    assert_eq!(push_down_filters(plan), expected);
+}
+
+#[test]
+fn test_push_down_keeps_results() {
+    let executor = shop_executor(
+        &[(1, "ann"), (2, "bob"), (3, "cat")],
+        &[(10, 1, 5), (11, 2, 7), (12, 1, 9), (13, 3, 1), (14, 3, 8)],
+    );
+    let planner = Planner::new();
+    let join = |on| {
+        planner.plan_join(TableRef::new("users").with_alias("u"), TableRef::new("orders"), on)
+    };
+    let plans = vec![
+        filtered(
+            join(eq(col("u.id"), col("orders.user_id"))),
+            and(gt("orders.amount", 6), gt("u.id", 1)),
+        ),
+        filtered(
+            join(and(eq(col("u.id"), col("orders.user_id")), gt("u.id", 2))),
+            gt("amount", 1),
+        ),
+        filtered(join(gt("orders.amount", 8)), gt("u.id", 2)),
+        filtered(
+            planner.plan_select(
+                "orders".to_string(),
+                Some(vec!["id".to_string(), "amount".to_string()]),
+                Some(gt("user_id", 1)),
+            ),
+            gt("amount", 2),
+        ),
+        filtered(
+            planner.plan_aggregate(
+                scan("orders", None),
+                vec!["user_id".to_string()],
+                vec![AggExpr::new(AggFunc::Sum, "amount")],
+            ),
+            and(gt("user_id", 1), gt("sum_amount", 8)),
+        ),
+    ];
+    for plan in plans {
+        let optimized = executor.execute(planner.to_physical(plan.clone())).unwrap();
+        let unoptimized = executor.execute(planner.to_physical_unoptimized(plan.clone())).unwrap();
+        assert!(!optimized.is_empty(), "{:?}", plan);
+        assert_eq!(canonical(optimized), canonical(unoptimized), "{:?}", plan);
+    }
+}
+
+fn item(id: i64, name: &str, qty: i64) -> Row {
+    Row::new_with_values(vec![
+        ("id".to_string(), Value::Int(id)),
+        ("name".to_string(), Value::String(name.to_string())),
+        ("qty".to_string(), Value::Int(qty)),
+    ])
+}
+
+/// The plan for the items matching `cond`, down to its scan and any filter
+/// over it.
+fn items_scan(planner: &Planner, cond: &str) -> PhysicalPlan {
+    let (plan, _) = select_plan(&format!("SELECT id FROM items WHERE {}", cond));
+    match planner.to_physical(plan) {
+        PhysicalPlan::Project { input, .. } => *input,
+        other => panic!("expected a projection, got {:?}", other),
+    }
+}
+
+/// A planner that takes any index it can use: `items` is analyzed, and
+/// index scans cost nothing.
+fn index_planner(executor: &Executor, catalog: Arc<RwLock<Catalog>>) -> Planner {
+    executor.analyze("items").unwrap();
+    let free_index = CostModel {
+        index_probe: 0.0,
+        fetch: 0.0,
+        ..CostModel::default()
+    };
+    Planner::with_catalog(catalog).with_cost_model(free_index)
+}
+
+fn index_range(plan: &PhysicalPlan) -> Option<&(Bound<Value>, Bound<Value>)> {
+    match plan {
+        PhysicalPlan::IndexScan { index, range, .. } if index == "items_qty" => Some(range),
+        PhysicalPlan::Filter { input, .. } => index_range(input),
+        _ => None,
+    }
+}
+
+#[test]
+fn test_index_scan_matches_seq_scan() {
+    let (mut executor, catalog) = items_with_catalog();
+    executor
+        .create_index(IndexDef::new("items_qty", "items", vec!["qty"], false))
+        .unwrap();
+    let planner = index_planner(&executor, catalog);
+    
+    let conditions = [
+        "qty = 3",
+        "qty = 4",
+        "qty >= 3 AND qty < 10",
+        "qty > 0",
+        "qty <= 7",
+        "10 > qty",
+        "qty > 3 AND qty >= 7 AND qty <= 10",
+    ];
+    let check = |executor: &Executor| {
+        for cond in conditions {
+            assert!(index_range(&items_scan(&planner, cond)).is_some(), "{}", cond);
+            assert_eq!(
+                ids_planned_where(executor, &planner, cond).unwrap(),
+                ids_where(executor, cond).unwrap(),
+                "{}",
+                cond
+            );
+        }
+    };
+    check(&executor);
+    assert_eq!(ids_planned_where(&executor, &planner, "qty >= 3 AND qty < 10").unwrap(), vec![1, 3]);
+    assert_eq!(
+        index_range(&items_scan(&planner, "qty >= 3 AND qty < 10")),
+        Some(&(Bound::Included(Value::Int(3)), Bound::Excluded(Value::Int(10))))
+    );
+    assert_eq!(
+        index_range(&items_scan(&planner, "qty > 3 AND qty >= 7 AND qty <= 10")),
+        Some(&(Bound::Included(Value::Int(7)), Bound::Included(Value::Int(10))))
+    );
+    
+    // The index follows every change to the table.
+    let changes = [
+        planner.plan_insert("items".to_string(), vec![item(6, "fig", 3)]),
+        planner.plan_update(
+            "items".to_string(),
+            vec![("qty".to_string(), Expr::Literal(Value::Int(4)))],
+            Some(eq(col("id"), lit(Value::Int(1)))),
+        ),
+        planner.plan_delete("items".to_string(), Some(eq(col("qty"), lit(Value::Int(7))))),
+    ];
+    for change in changes {
+        executor.run(planner.to_physical(change)).unwrap();
+        check(&executor);
+    }
+    assert_eq!(ids_planned_where(&executor, &planner, "qty = 4").unwrap(), vec![1]);
+    assert_eq!(ids_planned_where(&executor, &planner, "qty = 3").unwrap(), vec![6]);
+}
+
+#[test]
+fn test_index_scan_keeps_residual_filters() {
+    let (mut executor, catalog) = items_with_catalog();
+    executor
+        .create_index(IndexDef::new("items_qty", "items", vec!["qty"], false))
+        .unwrap();
+    let planner = index_planner(&executor, catalog);
+    
+    let cond = "qty >= 3 AND name LIKE 'a%'";
+    match items_scan(&planner, cond) {
+        PhysicalPlan::Filter { input, predicate } => {
+            assert!(matches!(*input, PhysicalPlan::IndexScan { .. }));
+            assert!(matches!(predicate, Expr::Like { .. }), "{}", predicate);
+        }
+        other => panic!("expected a filter over the index scan, got {:?}", other),
+    }
+    assert_eq!(ids_planned_where(&executor, &planner, cond).unwrap(), vec![1]);
+    assert_eq!(ids_where(&executor, cond).unwrap(), vec![1]);
+    
+    // A NULL comparison matches nothing, so it stays a filter.
+    assert_eq!(ids_planned_where(&executor, &planner, "qty >= 3 AND qty < NULL").unwrap(), Vec::<i64>::new());
+}
+
+#[test]
+fn test_non_indexed_filters_use_seq_scan() {
+    let (mut executor, catalog) = items_with_catalog();
+    executor
+        .create_index(IndexDef::new("items_qty", "items", vec!["qty"], false))
+        .unwrap();
+    let planner = index_planner(&executor, catalog);
+    
+    for cond in ["name = 'apple'", "qty = id", "qty > 3 OR id = 1", "qty IS NULL"] {
+        assert!(
+            matches!(items_scan(&planner, cond), PhysicalPlan::SeqScan { .. }),
+            "{}",
+            cond
+        );
+    }
+    assert_eq!(ids_planned_where(&executor, &planner, "qty > 3 OR id = 1").unwrap(), vec![1, 3, 4]);
+    
+    // The planner can pick an index the executor never built.
+    let mut catalog = Catalog::new();
+    catalog
+        .register_table(
+            TableSchemaBuilder::new("items")
+                .column("id", DataType::Int64, false)
+                .column("qty", DataType::Int64, true)
+                .build(),
+        )
+        .unwrap();
+    catalog
+        .create_index(IndexDef::new("items_qty", "items", vec!["qty"], false))
+        .unwrap();
+    let catalog = Arc::new(RwLock::new(catalog));
+    let mut executor = Executor::with_catalog(catalog.clone());
+    for (id, qty) in [(1, 5), (2, 2), (3, 5)] {
+        executor
+            .insert(
+                "items",
+                Row::new_with_values(vec![
+                    ("id".to_string(), Value::Int(id)),
+                    ("qty".to_string(), Value::Int(qty)),
+                ]),
+            )
+            .unwrap();
+    }
+    let planner = index_planner(&executor, catalog);
+    assert!(index_range(&items_scan(&planner, "qty = 5")).is_some());
+    assert_eq!(ids_planned_where(&executor, &planner, "qty = 5").unwrap(), vec![1, 3]);
+}
+
+#[test]
+fn test_string_functions() {
+    let executor = items_executor();
+    assert_eq!(ids_where(&executor, "UPPER(name) = 'APPLE'").unwrap(), vec![1]);
+    assert_eq!(ids_where(&executor, "upper(name) IS NULL").unwrap(), vec![3]);
+    assert_eq!(ids_where(&executor, "LOWER(UPPER(name)) = name").unwrap(), vec![1, 2, 4, 5]);
+    assert_eq!(truth_of(&executor, "LOWER('ApPle') = 'apple'"), Some(true));
+    
+    assert_eq!(ids_where(&executor, "LENGTH(name) > 5").unwrap(), vec![2, 4]);
+    assert_eq!(truth_of(&executor, "LENGTH('héllo') = 5"), Some(true));
+    assert_eq!(truth_of(&executor, "LENGTH(NULL) = 0"), None);
+    
+    assert_eq!(ids_where(&executor, "SUBSTR(name, 2, 3) = 'pri'").unwrap(), vec![2]);
+    assert_eq!(ids_where(&executor, "SUBSTR(name, 5) = 'off'").unwrap(), vec![4]);
+    assert_eq!(truth_of(&executor, "SUBSTR('hello', 0, 3) = 'he'"), Some(true));
+    assert_eq!(truth_of(&executor, "SUBSTR('hello', 9) = ''"), Some(true));
+    assert_eq!(truth_of(&executor, "SUBSTR('hello', NULL) = 'h'"), None);
+    
+    assert_eq!(ids_where(&executor, "CONCAT(name, '-', qty) = 'apple-3'").unwrap(), vec![1]);
+    assert_eq!(truth_of(&executor, "CONCAT('a', 1.50, TRUE) = 'a1.5true'"), Some(true));
+    assert_eq!(ids_where(&executor, "CONCAT(name, qty) IS NULL").unwrap(), vec![2, 3]);
+}
+
+#[test]
+fn test_numeric_and_null_functions() {
+    let executor = items_executor();
+    assert_eq!(ids_where(&executor, "ABS(qty) = 3").unwrap(), vec![1]);
+    assert_eq!(truth_of(&executor, "ABS(-7) = 7"), Some(true));
+    assert_eq!(truth_of(&executor, "ABS(-2.5) = 2.5"), Some(true));
+    assert_eq!(truth_of(&executor, "ABS(NULL) = 0"), None);
+    
+    // COALESCE alone looks past NULLs.
+    assert_eq!(ids_where(&executor, "COALESCE(qty, 0) = 0").unwrap(), vec![2, 5]);
+    assert_eq!(ids_where(&executor, "COALESCE(name, 'none') = 'none'").unwrap(), vec![3]);
+    assert_eq!(truth_of(&executor, "COALESCE(NULL, NULL, 4) = 4"), Some(true));
+    assert_eq!(truth_of(&executor, "COALESCE(NULL, NULL) = 4"), None);
+}
+
+#[test]
+fn test_function_validation() {
+    let executor = items_executor();
+    let err = |cond| ids_where(&executor, cond).unwrap_err();
+    assert_eq!(err("NOPE(name) = 1"), "unknown function: NOPE");
+    assert_eq!(err("UPPER(name, name) = 'A'"), "UPPER takes 1 argument, got 2");
+    assert_eq!(err("LENGTH() = 0"), "LENGTH takes 1 argument, got 0");
+    assert_eq!(err("SUBSTR(name) = 'a'"), "SUBSTR takes 2 to 3 arguments, got 1");
+    assert_eq!(err("COALESCE() = 1"), "COALESCE takes at least 1 argument, got 0");
+    assert_eq!(err("UPPER(qty) = 'A'"), "argument 1 of UPPER must be STRING, got INT64");
+    assert_eq!(err("SUBSTR(name, 'x') = 'a'"), "argument 2 of SUBSTR must be INT64, got STRING");
+    assert_eq!(err("ABS(name) = 1"), "argument 1 of ABS must be numeric, got STRING");
+    // Results are typed too.
+    assert_eq!(err("LENGTH(name) = 'five'"), "incompatible types for Eq: INT64 and STRING");
+    assert_eq!(err("COALESCE(qty, 0) = 'x'"), "incompatible types for Eq: INT64 and STRING");
+}
+
+#[test]
+fn test_register_custom_function() {
+    let mut executor = items_executor();
+    executor.register_function(
+        "Double",
+        ScalarFunction::new(vec![ParamType::Numeric], ReturnType::FirstArgument, |args| {
+            args[0].as_int().and_then(|i| i.checked_mul(2)).map(Value::Int)
+        }),
+    );
+    assert_eq!(ids_where(&executor, "DOUBLE(qty) = 6").unwrap(), vec![1]);
+    assert_eq!(ids_where(&executor, "double(qty) > qty").unwrap(), vec![1, 3, 4]);
+    assert_eq!(
+        ids_where(&executor, "double(name) = 2").unwrap_err(),
+        "argument 1 of DOUBLE must be numeric, got STRING"
+    );
+    
+    // A registered function may replace a built-in.
+    executor.register_function(
+        "length",
+        ScalarFunction::new(
+            vec![ParamType::Exactly(DataType::String)],
+            ReturnType::Fixed(DataType::Int64),
+            |args| Some(Value::Int(args[0].as_string()?.len() as i64)),
+        ),
+    );
+    assert_eq!(truth_of(&executor, "LENGTH('héllo') = 6"), Some(true));
+}
+
+/// Ids, in order, of the rows of `table` that `cond` selects.
+fn stored_ids(executor: &Executor, table: &str, cond: &str) -> Vec<i64> {
+    let (plan, _) = select_plan(&format!("SELECT id FROM {} WHERE {} ORDER BY id", table, cond));
+    executor
+        .execute(Planner::new().to_physical(plan))
+        .unwrap()
+        .iter()
+        .map(|row| row.get_column("id").unwrap().as_int().unwrap())
+        .collect()
+}
+
+#[test]
+fn test_stored_table_survives_restart() {
+    use middb_core::{Config, Database};
+    
+    let dir = tempfile::TempDir::new().unwrap();
+    let conditions = ["age > 30", "name LIKE 'a%'", "age IS NULL", "id BETWEEN 2 AND 4"];
+    let planner = Planner::new();
+    let before: Vec<Vec<i64>> = {
+        let db = Arc::new(Database::open(Config::new(dir.path())).unwrap());
+        db.create_table(
+            TableSchemaBuilder::new("people")
+                .column("id", DataType::Int64, false)
+                .column("name", DataType::String, false)
+                .column("age", DataType::Int64, true)
+                .primary_key(vec!["id"])
+                .build(),
+        )
+        .unwrap();
+        let mut executor = Executor::with_database(db.clone()).unwrap();
+        let people = [(1, "ann", Some(34)), (2, "bob", None), (3, "al", Some(28)), (4, "cy", Some(51))];
+        let rows = people
+            .iter()
+            .map(|&(id, name, age)| {
+                Row::new_with_values(vec![
+                    ("id".to_string(), Value::Int(id)),
+                    ("name".to_string(), Value::String(name.to_string())),
+                    ("age".to_string(), age.map_or(Value::Null, Value::Int)),
+                ])
+            })
+            .collect();
+        let insert = planner.plan_insert("people".to_string(), rows);
+        assert_eq!(executor.run(planner.to_physical(insert)).unwrap(), ExecutionResult::Count(4));
+        
+        let update = planner.plan_update(
+            "people".to_string(),
+            vec![("age".to_string(), Expr::Literal(Value::Int(35)))],
+            Some(eq(col("name"), lit(Value::String("al".to_string())))),
+        );
+        assert_eq!(executor.run(planner.to_physical(update)).unwrap(), ExecutionResult::Count(1));
+        let delete = planner.plan_delete("people".to_string(), Some(eq(col("id"), lit(Value::Int(4)))));
+        assert_eq!(executor.run(planner.to_physical(delete)).unwrap(), ExecutionResult::Count(1));
+        
+        // A duplicate key fails the whole insert.
+        let rows = vec![
+            Row::new_with_values(vec![
+                ("id".to_string(), Value::Int(5)),
+                ("name".to_string(), Value::String("dee".to_string())),
+            ]),
+            Row::new_with_values(vec![
+                ("id".to_string(), Value::Int(1)),
+                ("name".to_string(), Value::String("eve".to_string())),
+            ]),
+        ];
+        let insert = planner.plan_insert("people".to_string(), rows);
+        let err = executor.run(planner.to_physical(insert)).unwrap_err();
+        assert_eq!(err, "constraint violation: duplicate key (id) in 'people'");
+        
+        let results = conditions.iter().map(|c| stored_ids(&executor, "people", c)).collect();
+        drop(executor);
+        Arc::try_unwrap(db).ok().unwrap().close().unwrap();
+        results
+    };
+    assert_eq!(before, vec![vec![1, 3], vec![1, 3], vec![2], vec![2, 3]]);
+    
+    let db = Arc::new(Database::open(Config::new(dir.path())).unwrap());
+    let executor = Executor::with_database(db).unwrap();
+    let after: Vec<Vec<i64>> = conditions.iter().map(|c| stored_ids(&executor, "people", c)).collect();
+    assert_eq!(after, before);
+}
+
+#[test]
+fn test_stored_keys_sort_by_primary_key() {
+    use crate::{StoredTable, TableProvider};
+    use middb_core::{Config, Database};
+    
+    let dir = tempfile::TempDir::new().unwrap();
+    let db = Arc::new(Database::open(Config::new(dir.path())).unwrap());
+    db.create_table(
+        TableSchemaBuilder::new("scores")
+            .column("team", DataType::String, false)
+            .column("points", DataType::Float64, false)
+            .column("id", DataType::Int64, false)
+            .primary_key(vec!["team", "points"])
+            .build(),
+    )
+    .unwrap();
+    let table = StoredTable::open(db.clone(), "scores").unwrap();
+    let row = |id: i64, team: &str, points: f64| {
+        Row::new_with_values(vec![
+            ("id".to_string(), Value::Int(id)),
+            ("team".to_string(), Value::String(team.to_string())),
+            ("points".to_string(), Value::Float(points)),
+        ])
+    };
+    // In key order: teams by string, then points numerically.
+    let rows = vec![
+        row(1, "", 0.0),
+        row(2, "a", -1e9),
+        row(3, "a", -2.5),
+        row(4, "a", 0.0),
+        row(5, "a", 1.5),
+        row(6, "a", 1e9),
+        row(7, "a\0", -3.0),
+        row(8, "ab", 0.0),
+        row(9, "b", -0.5),
+    ];
+    let keys: Vec<Vec<u8>> = rows.iter().map(|r| table.key(r).unwrap().unwrap()).collect();
+    for pair in keys.windows(2) {
+        assert!(pair[0] < pair[1], "{:?} >= {:?}", pair[0], pair[1]);
+    }
+    
+    // Writes land in any order; scans come back in key order.
+    let mut shuffled = rows.clone();
+    shuffled.reverse();
+    table.write(&[], &shuffled).unwrap();
+    let ids = |rows: Vec<Row>| -> Vec<i64> {
+        rows.iter().map(|r| r.get_column("id").unwrap().as_int().unwrap()).collect()
+    };
+    assert_eq!(ids(table.scan().unwrap()), (1..=9).collect::<Vec<_>>());
+    
+    // Key ranges read just the rows in them.
+    let a = [Value::String("a".to_string())];
+    let a_zero = [Value::String("a".to_string()), Value::Int(0)];
+    assert_eq!(ids(table.scan_range(Bound::Included(&a), Bound::Included(&a)).unwrap()), vec![2, 3, 4, 5, 6]);
+    assert_eq!(ids(table.scan_range(Bound::Excluded(&a), Bound::Unbounded).unwrap()), vec![7, 8, 9]);
+    assert_eq!(ids(table.scan_range(Bound::Included(&a_zero), Bound::Excluded(&a)).unwrap()), Vec::<i64>::new());
+    assert_eq!(ids(table.scan_range(Bound::Included(&a_zero), Bound::Included(&a)).unwrap()), vec![4, 5, 6]);
+    assert_eq!(ids(table.scan_range(Bound::Unbounded, Bound::Excluded(&a_zero)).unwrap()), vec![1, 2, 3]);
+}
+
+#[test]
+fn test_stored_table_without_primary_key() {
+    use middb_core::{Config, Database};
+    
+    let dir = tempfile::TempDir::new().unwrap();
+    let db = Arc::new(Database::open(Config::new(dir.path())).unwrap());
+    db.create_table(
+        TableSchemaBuilder::new("events")
+            .column("id", DataType::Int64, false)
+            .column("kind", DataType::String, false)
+            .build(),
+    )
+    .unwrap();
+    let mut executor = Executor::with_database(db.clone()).unwrap();
+    for (id, kind) in [(1, "click"), (1, "click"), (2, "view"), (3, "click")] {
+        executor
+            .insert(
+                "events",
+                Row::new_with_values(vec![
+                    ("id".to_string(), Value::Int(id)),
+                    ("kind".to_string(), Value::String(kind.to_string())),
+                ]),
+            )
+            .unwrap();
+    }
+    assert_eq!(stored_ids(&executor, "events", "kind = 'click'"), vec![1, 1, 3]);
+    
+    let planner = Planner::new();
+    let update = planner.plan_update(
+        "events".to_string(),
+        vec![("kind".to_string(), Expr::Literal(Value::String("tap".to_string())))],
+        Some(eq(col("id"), lit(Value::Int(1)))),
+    );
+    assert_eq!(executor.run(planner.to_physical(update)).unwrap(), ExecutionResult::Count(2));
+    let delete = planner.plan_delete("events".to_string(), Some(eq(col("id"), lit(Value::Int(3)))));
+    assert_eq!(executor.run(planner.to_physical(delete)).unwrap(), ExecutionResult::Count(1));
+    
+    // Rows are numbered on from the last one stored, across a reopen.
+    drop(executor);
+    let mut executor = Executor::with_database(db).unwrap();
+    executor
+        .insert(
+            "events",
+            Row::new_with_values(vec![
+                ("id".to_string(), Value::Int(4)),
+                ("kind".to_string(), Value::String("tap".to_string())),
+            ]),
+        )
+        .unwrap();
+    assert_eq!(stored_ids(&executor, "events", "kind = 'tap'"), vec![1, 1, 4]);
+    assert_eq!(stored_ids(&executor, "events", "kind = 'click'"), Vec::<i64>::new());
+    assert_eq!(stored_ids(&executor, "events", "id > 0"), vec![1, 1, 2, 4]);
+}
+
+#[test]
+fn test_streamed_results_match_collected() {
+    use middb_core::{Config, Database};
+    
+    let dir = tempfile::TempDir::new().unwrap();
+    let db = Arc::new(Database::open(Config::new(dir.path())).unwrap());
+    db.create_table(
+        TableSchemaBuilder::new("nums")
+            .column("id", DataType::Int64, false)
+            .column("grp", DataType::Int64, false)
+            .column("label", DataType::String, true)
+            .primary_key(vec!["id"])
+            .build(),
+    )
+    .unwrap();
+    db.create_table(
+        TableSchemaBuilder::new("groups")
+            .column("grp", DataType::Int64, false)
+            .column("name", DataType::String, false)
+            .primary_key(vec!["grp"])
+            .build(),
+    )
+    .unwrap();
+    
+    // The same rows stored in the database, read a page at a time, and
+    // held in memory.
+    let mut stored = Executor::with_database(db).unwrap();
+    let mut memory = Executor::new();
+    let mut nums = Table::new("nums".to_string());
+    for id in 0..300 {
+        let label = match id % 5 {
+            0 => Value::Null,
+            _ => Value::String(format!("n{}", id % 13)),
+        };
+        let row = Row::new_with_values(vec![
+            ("id".to_string(), Value::Int(id)),
+            ("grp".to_string(), Value::Int(id % 7)),
+            ("label".to_string(), label),
+        ]);
+        stored.insert("nums", row.clone()).unwrap();
+        nums.add_row(row);
+    }
+    let mut groups = Table::new("groups".to_string());
+    for grp in 0..7 {
+        let row = Row::new_with_values(vec![
+            ("grp".to_string(), Value::Int(grp)),
+            ("name".to_string(), Value::String(format!("g{}", grp))),
+        ]);
+        stored.insert("groups", row.clone()).unwrap();
+        groups.add_row(row);
+    }
+    memory.register_table("nums".to_string(), nums);
+    memory.register_table("groups".to_string(), groups);
+    
+    let planner = Planner::new();
+    let sql = |sql: &str| match select_plan(sql) {
+        (plan, Some(limit)) => planner.plan_limit(plan, limit),
+        (plan, None) => plan,
+    };
+    let join = |on: Expr| {
+        planner.plan_join(TableRef::new("nums"), TableRef::new("groups").with_alias("g"), on)
+    };
+    let plans = vec![
+        sql("SELECT * FROM nums"),
+        sql("SELECT id, label FROM nums WHERE grp = 3 AND id > 100"),
+        sql("SELECT * FROM nums WHERE label IS NULL LIMIT 7"),
+        sql("SELECT DISTINCT grp, label FROM nums"),
+        sql("SELECT * FROM nums ORDER BY label DESC, id LIMIT 40"),
+        sql("SELECT id FROM nums ORDER BY id DESC LIMIT 0"),
+        planner.plan_aggregate(
+            planner.plan("nums".to_string(), None),
+            vec!["grp".to_string()],
+            vec![AggExpr::count_star(), AggExpr::new(AggFunc::Max, "label")],
+        ),
+        join(eq(col("nums.grp"), col("g.grp"))),
+        planner.plan_limit(join(eq(col("nums.grp"), col("g.grp"))), 25),
+        join(Expr::BinaryOp {
+            op: BinaryOperator::Lt,
+            left: col("nums.grp"),
+            right: col("g.grp"),
+        }),
+    ];
+    for plan in plans {
+        let physical = planner.to_physical(plan);
+        let expected = memory.execute(physical.clone()).unwrap();
+        assert_eq!(stored.execute(physical.clone()).unwrap(), expected, "{:?}", physical);
+        
+        let mut stream = stored.stream(physical.clone()).unwrap();
+        let mut pulled = Vec::new();
+        while let Some(row) = stream.next() {
+            pulled.push(row.unwrap());
+        }
+        assert!(stream.next().is_none());
+        assert_eq!(pulled, expected, "{:?}", physical);
+    }
+}
+
+/// Rows handed out one at a time, counting how many were pulled.
+struct CountingTable {
+    rows: Vec<Row>,
+    pulled: Arc<std::sync::atomic::AtomicUsize>,
+}
+
+impl crate::TableProvider for CountingTable {
+    fn scan(&self) -> Result<Vec<Row>, String> {
+        self.stream()?.collect()
+    }
+    
+    fn write(&self, _: &[Row], _: &[Row]) -> Result<(), String> {
+        Err("read only".to_string())
+    }
+    
+    fn stream(&self) -> Result<Box<dyn crate::RowStream + '_>, String> {
+        let pulled = self.pulled.clone();
+        let rows = self.rows.iter().cloned().inspect(move |_| {
+            pulled.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        });
+        Ok(Box::new(crate::stream::Rows(rows)))
+    }
+}
+
+#[test]
+fn test_limit_stops_pulling_from_scan() {
+    use std::sync::atomic::Ordering;
+    
+    let pulled = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let mut executor = Executor::new();
+    let rows = (0..1000)
+        .map(|i| Row::new_with_values(vec![("id".to_string(), Value::Int(i))]))
+        .collect();
+    executor.register_provider("big", Box::new(CountingTable { rows, pulled: pulled.clone() }));
+    let planner = Planner::new();
+    let run = |sql: &str| {
+        pulled.store(0, Ordering::SeqCst);
+        let (plan, limit) = select_plan(sql);
+        let plan = planner.plan_limit(plan, limit.unwrap());
+        let rows = executor.execute(planner.to_physical(plan)).unwrap();
+        (rows.len(), pulled.load(Ordering::SeqCst))
+    };
+    
+    assert_eq!(run("SELECT * FROM big LIMIT 10"), (10, 10));
+    assert_eq!(run("SELECT id FROM big WHERE id >= 500 LIMIT 10"), (10, 510));
+    // A sort has to see every row before it can give the first.
+    assert_eq!(run("SELECT * FROM big ORDER BY id DESC LIMIT 10"), (10, 1000));
+}
+
+/// Run a CREATE TABLE or DROP TABLE statement.
+fn run_ddl(executor: &mut Executor, sql: &str) -> Result<ExecutionResult, String> {
+    let planner = Planner::new();
+    let plan = match sql::parse(sql).unwrap() {
+        Statement::CreateTable { schema, if_not_exists } => {
+            planner.plan_create_table(schema, if_not_exists)
+        }
+        Statement::DropTable { name, if_exists } => planner.plan_drop_table(name, if_exists),
+        other => panic!("expected DDL from {:?}, got {:?}", sql, other),
+    };
+    executor.run(planner.to_physical(plan))
+}
+
+fn run_select(executor: &Executor, sql: &str) -> Result<Vec<Row>, String> {
+    let (plan, _) = select_plan(sql);
+    executor.execute(Planner::new().to_physical(plan))
+}
+
+#[test]
+fn test_create_and_drop_table() {
+    let catalog = Arc::new(RwLock::new(Catalog::new()));
+    let mut executor = Executor::with_catalog(catalog.clone());
+    let create = "CREATE TABLE pets (id INT PRIMARY KEY, name TEXT NOT NULL)";
+    assert_eq!(run_ddl(&mut executor, create), Ok(ExecutionResult::Done));
+    assert!(catalog.read().unwrap().table_exists("pets"));
+    assert!(run_select(&executor, "SELECT * FROM pets").unwrap().is_empty());
+    
+    let pet = |id: i64, name: &str| {
+        Row::new_with_values(vec![
+            ("id".to_string(), Value::Int(id)),
+            ("name".to_string(), Value::String(name.to_string())),
+        ])
+    };
+    executor.insert("pets", pet(1, "rex")).unwrap();
+    executor.insert("pets", pet(2, "tom")).unwrap();
+    // The new table's schema is enforced like any other's.
+    let err = executor.insert("pets", pet(1, "dup")).unwrap_err();
+    assert_eq!(err, "constraint violation: duplicate key (id) in 'pets'");
+    assert_eq!(run_select(&executor, "SELECT name FROM pets WHERE id = 2").unwrap().len(), 1);
+    
+    // Creating it again fails, unless IF NOT EXISTS, which keeps the rows.
+    let err = run_ddl(&mut executor, create).unwrap_err();
+    assert_eq!(err, "table already exists: pets");
+    let again = "CREATE TABLE IF NOT EXISTS pets (other INT)";
+    assert_eq!(run_ddl(&mut executor, again), Ok(ExecutionResult::Done));
+    assert_eq!(run_select(&executor, "SELECT * FROM pets").unwrap().len(), 2);
+    
+    assert_eq!(run_ddl(&mut executor, "DROP TABLE pets"), Ok(ExecutionResult::Done));
+    assert!(!catalog.read().unwrap().table_exists("pets"));
+    let err = run_select(&executor, "SELECT * FROM pets").unwrap_err();
+    assert_eq!(err, "table not found: pets");
+    assert_eq!(run_ddl(&mut executor, "DROP TABLE pets").unwrap_err(), "table not found: pets");
+    assert_eq!(run_ddl(&mut executor, "DROP TABLE IF EXISTS pets"), Ok(ExecutionResult::Done));
+    
+    // A table created again under the name starts empty.
+    run_ddl(&mut executor, create).unwrap();
+    assert!(run_select(&executor, "SELECT * FROM pets").unwrap().is_empty());
+    
+    // Without a catalog there is nowhere to record a schema.
+    let mut bare = Executor::new();
+    let err = run_ddl(&mut bare, create).unwrap_err();
+    assert_eq!(err, "CREATE TABLE needs an executor with a catalog");
+    let err = run_ddl(&mut bare, "DROP TABLE IF EXISTS pets").unwrap_err();
+    assert_eq!(err, "DROP TABLE needs an executor with a catalog");
+    
+    assert!(sql::parse("DROP TABLE IF pets").is_err());
+    assert!(sql::parse("CREATE TABLE IF EXISTS pets (id INT)").is_err());
+}
+
+#[test]
+fn test_executor_columns() {
+    let catalog = Arc::new(RwLock::new(Catalog::new()));
+    let mut executor = Executor::with_catalog(catalog);
+    run_ddl(&mut executor, "CREATE TABLE pets (id INT, name TEXT, age INT)").unwrap();
+    let columns = |sql: &str| {
+        let (plan, _) = select_plan(sql);
+        executor.columns(&Planner::new().to_physical(plan))
+    };
+    
+    let names = |names: &[&str]| Some(names.iter().map(|n| n.to_string()).collect());
+    assert_eq!(columns("SELECT * FROM pets"), names(&["id", "name", "age"]));
+    assert_eq!(columns("SELECT age, id AS key FROM pets"), names(&["age", "key"]));
+    assert_eq!(columns("SELECT * FROM nowhere"), None);
+    
+    let row = Row::new_with_values(vec![
+        ("b".to_string(), Value::Int(1)),
+        ("a".to_string(), Value::Int(2)),
+    ]);
+    assert_eq!(row.column_names(), ["a", "b"]);
+}
+
+#[test]
+fn test_ddl_persists_in_database() {
+    use middb_core::{Config, Database};
+    
+    let dir = tempfile::TempDir::new().unwrap();
+    {
+        let db = Arc::new(Database::open(Config::new(dir.path())).unwrap());
+        let mut executor = Executor::with_database(db.clone()).unwrap();
+        run_ddl(&mut executor, "CREATE TABLE kept (id INT PRIMARY KEY)").unwrap();
+        run_ddl(&mut executor, "CREATE TABLE gone (id INT)").unwrap();
+        for id in 0..3 {
+            let row = Row::new_with_values(vec![("id".to_string(), Value::Int(id))]);
+            executor.insert("kept", row.clone()).unwrap();
+            executor.insert("gone", row).unwrap();
+        }
+        run_ddl(&mut executor, "DROP TABLE gone").unwrap();
+        drop(executor);
+        Arc::try_unwrap(db).ok().unwrap().close().unwrap();
+    }
+    
+    let db = Arc::new(Database::open(Config::new(dir.path())).unwrap());
+    assert_eq!(db.list_tables(), vec!["kept".to_string()]);
+    let mut executor = Executor::with_database(db.clone()).unwrap();
+    assert_eq!(stored_ids(&executor, "kept", "id >= 0"), vec![0, 1, 2]);
+    assert_eq!(run_select(&executor, "SELECT * FROM gone").unwrap_err(), "table not found: gone");
+    
+    // The dropped table's rows went with it.
+    run_ddl(&mut executor, "CREATE TABLE gone (id INT)").unwrap();
+    assert!(run_select(&executor, "SELECT * FROM gone").unwrap().is_empty());
+}
+
+fn csv_schema() -> middb_core::catalog::TableSchema {
+    TableSchemaBuilder::new("people")
+        .column("id", DataType::Int64, false)
+        .column("name", DataType::String, true)
+        .column("active", DataType::Bool, true)
+        .column("balance", DataType::Decimal { precision: 8, scale: 2 }, true)
+        .primary_key(vec!["id"])
+        .build()
+}
+
+#[test]
+fn test_csv_typed_fields() {
+    use crate::CsvOptions;
+    
+    let csv = "id,name,active,balance\n1,ann,true,12.5\n2,,FALSE,-0.75\r\n\n3,\"\",,100\n";
+    let table = Table::from_csv(csv.as_bytes(), &csv_schema(), CsvOptions::default()).unwrap();
+    let s = |v: &str| Value::String(v.to_string());
+    let d = |value: i128| Value::Decimal { value, scale: 2 };
+    let fields: Vec<Vec<Value>> = table
+        .rows
+        .iter()
+        .map(|r| ["id", "name", "active", "balance"].map(|c| r.get_column(c).unwrap()).to_vec())
+        .collect();
+    assert_eq!(
+        fields,
+        vec![
+            vec![Value::Int(1), s("ann"), Value::Bool(true), d(1250)],
+            // An empty field is NULL unless quoted.
+            vec![Value::Int(2), Value::Null, Value::Bool(false), d(-75)],
+            vec![Value::Int(3), s(""), Value::Null, d(10000)],
+        ]
+    );
+    
+    // Without a header, fields follow the schema; the delimiter and NULL
+    // token are configurable.
+    let opts = CsvOptions {
+        has_header: false,
+        delimiter: ';',
+        null_token: "NULL".to_string(),
+        lenient: false,
+    };
+    let table = Table::from_csv("7;NULL;true;NULL\n".as_bytes(), &csv_schema(), opts).unwrap();
+    assert_eq!(table.rows[0].get_column("name"), Some(Value::Null));
+    assert_eq!(table.rows[0].get_column("balance"), Some(Value::Null));
+    
+    // A header may name some columns, in any order.
+    let table = Table::from_csv("name,id\nbo,4\n".as_bytes(), &csv_schema(), CsvOptions::default())
+        .unwrap();
+    assert_eq!(table.rows[0].get_column("id"), Some(Value::Int(4)));
+    assert_eq!(table.rows[0].get_column("active"), None);
+}
+
+#[test]
+fn test_csv_quoting() {
+    use crate::CsvOptions;
+    
+    let csv = "id,name\n1,\"Smith, Ann\"\n2,\"say \"\"hi\"\"\"\n3,\"two\nlines\"\n4,plain\n";
+    let schema = TableSchemaBuilder::new("t")
+        .column("id", DataType::Int64, false)
+        .column("name", DataType::String, false)
+        .build();
+    let table = Table::from_csv(csv.as_bytes(), &schema, CsvOptions::default()).unwrap();
+    let names: Vec<Value> = table.rows.iter().map(|r| r.get_column("name").unwrap()).collect();
+    let s = |v: &str| Value::String(v.to_string());
+    assert_eq!(names, vec![s("Smith, Ann"), s("say \"hi\""), s("two\nlines"), s("plain")]);
+    
+    let err = Table::from_csv("id,name\n1,\"open\n".as_bytes(), &schema, CsvOptions::default())
+        .unwrap_err();
+    assert_eq!(err.to_string(), "line 2, column 2: unterminated quoted field");
+    let err = Table::from_csv("id,name\n1,\"a\"b\n".as_bytes(), &schema, CsvOptions::default())
+        .unwrap_err();
+    assert_eq!(err.to_string(), "line 2, column 2: unexpected text after a quoted field");
+}
+
+#[test]
+fn test_csv_errors_name_line_and_column() {
+    use crate::{CsvOptions, ImportError, RecordError};
+    
+    let schema = csv_schema();
+    let import = |csv: &str, lenient: bool| {
+        let opts = CsvOptions { lenient, ..CsvOptions::default() };
+        Table::from_csv(csv.as_bytes(), &schema, opts)
+    };
+    
+    // Lines count from the start of the file, across quoted newlines.
+    let csv = "id,name,active,balance\n1,\"a\nb\",true,1\n2,bo,maybe,1\n3,cy\n4,di,true,1.234\n";
+    let err = import(csv, false).unwrap_err();
+    assert_eq!(err.to_string(), "line 4, column 3: invalid BOOL 'maybe' for column 'active'");
+    
+    match import(csv, true).unwrap_err() {
+        ImportError::Records(errors) => assert_eq!(
+            errors,
+            vec![
+                RecordError {
+                    line: 4,
+                    column: Some(3),
+                    message: "invalid BOOL 'maybe' for column 'active'".to_string(),
+                },
+                RecordError {
+                    line: 5,
+                    column: None,
+                    message: "expected 4 fields, got 2".to_string(),
+                },
+                RecordError {
+                    line: 6,
+                    column: Some(4),
+                    message: "invalid DECIMAL(8, 2) '1.234' for column 'balance'".to_string(),
+                },
+            ]
+        ),
+        other => panic!("expected record errors, got {:?}", other),
+    }
+    
+    let err = import("id,name\nx,ann\n", false).unwrap_err();
+    assert_eq!(err.to_string(), "line 2, column 1: invalid INT64 'x' for column 'id'");
+    let err = import("id,name\n,ann\n", false).unwrap_err();
+    assert_eq!(err.to_string(), "line 2, column 1: column 'id' is NOT NULL");
+    let err = import("id,nickname\n", false).unwrap_err();
+    assert_eq!(err.to_string(), "line 1, column 2: column 'nickname' not found in table 'people'");
+}
+
+#[test]
+fn test_executor_import_csv() {
+    let catalog = Arc::new(RwLock::new(Catalog::new()));
+    catalog.write().unwrap().register_table(csv_schema()).unwrap();
+    let mut executor = Executor::with_catalog(catalog);
+    let dir = tempfile::TempDir::new().unwrap();
+    let path = dir.path().join("people.csv");
+    
+    std::fs::write(&path, "id,name,balance\n1,ann,5\n2,bo,\n").unwrap();
+    assert_eq!(executor.import_csv("people", &path), Ok(2));
+    assert_eq!(run_select(&executor, "SELECT * FROM people").unwrap().len(), 2);
+    
+    // A duplicate key rejects the whole file.
+    std::fs::write(&path, "id,name\n3,cy\n1,dup\n").unwrap();
+    let err = executor.import_csv("people", &path).unwrap_err();
+    assert_eq!(err, "constraint violation: duplicate key (id) in 'people'");
+    assert_eq!(run_select(&executor, "SELECT * FROM people").unwrap().len(), 2);
+    
+    assert!(executor.import_csv("people", dir.path().join("missing.csv")).is_err());
+    assert_eq!(executor.import_csv("nope", &path).unwrap_err(), "table not found: nope");
+}
+
+#[test]
+fn test_json_value_round_trip() {
+    use crate::json::{value_from_json, value_to_json};
+    
+    let values = vec![
+        Value::Null,
+        Value::Int(0),
+        Value::Int(i64::MIN),
+        Value::Int(i64::MAX),
+        Value::Float(2.5),
+        Value::Float(3.0),
+        Value::Float(-0.0),
+        Value::Float(f64::INFINITY),
+        Value::Float(f64::NEG_INFINITY),
+        Value::Bool(true),
+        Value::String("héllo \"quoted\"\n".to_string()),
+        Value::String(String::new()),
+        Value::Bytes(Vec::new()),
+        Value::Bytes(vec![0, 1, 0xFF]),
+        Value::Decimal { value: -1250, scale: 2 },
+        Value::Decimal { value: 5, scale: 3 },
+        Value::Decimal { value: 7, scale: 0 },
+        Value::Timestamp(1_700_000_000_000),
+    ];
+    for value in values {
+        let text = value_to_json(&value).to_string();
+        let parsed: serde_json::Value = serde_json::from_str(&text).unwrap();
+        let back = value_from_json(&parsed).unwrap();
+        assert_eq!(back, value, "{}", text);
+        // -0.0 == 0.0, so check the sign survived too.
+        if let (Value::Float(a), Value::Float(b)) = (&back, &value) {
+            assert_eq!(a.to_bits(), b.to_bits());
+        }
+    }
+    let nan = value_from_json(&value_to_json(&Value::Float(f64::NAN))).unwrap();
+    assert!(matches!(nan, Value::Float(f) if f.is_nan()));
+    
+    // Every byte, at every length modulo 3, survives base64.
+    let all: Vec<u8> = (0..=255).collect();
+    for len in [0, 1, 2, 3, 4, 5, 254, 255, 256] {
+        let bytes = Value::Bytes(all.iter().cycle().take(len).copied().collect());
+        assert_eq!(value_from_json(&value_to_json(&bytes)).unwrap(), bytes);
+    }
+    assert_eq!(value_to_json(&Value::Bytes(b"hi!?".to_vec())), serde_json::json!({ "$bytes": "aGkhPw==" }));
+    
+    for bad in [r#"{"$bytes": "a=b="}"#, r#"{"$bytes": "abc"}"#, r#"{"$decimal": "1.2.3"}"#, r#"[1]"#] {
+        assert!(value_from_json(&serde_json::from_str(bad).unwrap()).is_err(), "{}", bad);
+    }
+}
+
+#[test]
+fn test_result_set_json_is_stable() {
+    use crate::{rows_to_ndjson, ResultSet};
+    
+    let columns: Vec<String> = ["id", "name", "data", "price", "ok", "note"].map(String::from).to_vec();
+    let rows = vec![
+        Row::new_with_values(vec![
+            ("name".to_string(), Value::String("ann".to_string())),
+            ("id".to_string(), Value::Int(1)),
+            ("data".to_string(), Value::Bytes(vec![0, 1, 0xFF])),
+            ("price".to_string(), Value::Float(2.5)),
+            ("ok".to_string(), Value::Bool(true)),
+            ("note".to_string(), Value::Null),
+        ]),
+        // A column the row lacks comes out as null.
+        Row::new_with_values(vec![("id".to_string(), Value::Int(2))]),
+    ];
+    let set = ResultSet::new(columns.clone(), rows.clone());
+    assert_eq!(
+        set.to_json().to_string(),
+        r#"{"columns":["id","name","data","price","ok","note"],"rows":[[1,"ann",{"$bytes":"AAH/"},2.5,true,null],[2,null,null,null,null,null]]}"#
+    );
+    
+    let back = ResultSet::from_json(&set.to_json()).unwrap();
+    assert_eq!(back.columns, columns);
+    assert_eq!(back.rows[0], rows[0]);
+    assert_eq!(back.rows[1].get_column("name"), Some(Value::Null));
+    
+    assert_eq!(
+        rows[0].to_json().to_string(),
+        r#"{"data":{"$bytes":"AAH/"},"id":1,"name":"ann","note":null,"ok":true,"price":2.5}"#
+    );
+    
+    let mut out = Vec::new();
+    rows_to_ndjson(&mut out, &columns[..2], rows).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), "{\"id\":1,\"name\":\"ann\"}\n{\"id\":2,\"name\":null}\n");
+    
+    let err = ResultSet::from_json(&serde_json::json!({ "columns": ["a"], "rows": [[1, 2]] }));
+    assert_eq!(err.unwrap_err(), "row 0 must be an array of 1 values");
+    assert!(ResultSet::from_json(&serde_json::json!({ "rows": [] })).is_err());
+}
+
+#[test]
+fn test_union() {
+    let mut executor = Executor::with_catalog(Arc::new(RwLock::new(Catalog::new())));
+    run_ddl(&mut executor, "CREATE TABLE staff (id INT, name TEXT, pay INT)").unwrap();
+    run_ddl(&mut executor, "CREATE TABLE guests (gid INT, label TEXT, fee FLOAT)").unwrap();
+    let text = |s: &str| Value::String(s.to_string());
+    for (id, name, pay) in [(1, "ann", 10), (2, "bob", 20)] {
+        let row = vec![
+            ("id".to_string(), Value::Int(id)),
+            ("name".to_string(), text(name)),
+            ("pay".to_string(), Value::Int(pay)),
+        ];
+        executor.insert("staff", Row::new_with_values(row)).unwrap();
+    }
+    for (gid, label, fee) in [(2, "bob", 20.0), (3, "cy", 7.5)] {
+        let row = vec![
+            ("gid".to_string(), Value::Int(gid)),
+            ("label".to_string(), text(label)),
+            ("fee".to_string(), Value::Float(fee)),
+        ];
+        executor.insert("guests", Row::new_with_values(row)).unwrap();
+    }
+    let column = |rows: &[Row], name: &str| -> Vec<Value> {
+        rows.iter().map(|row| row.get_column(name).unwrap()).collect()
+    };
+    
+    let sql = "SELECT id, name FROM staff UNION ALL SELECT gid, label FROM guests";
+    let rows = run_select(&executor, sql).unwrap();
+    assert_eq!(column(&rows, "name"), [text("ann"), text("bob"), text("bob"), text("cy")]);
+    assert_eq!(column(&rows, "id"), [Value::Int(1), Value::Int(2), Value::Int(2), Value::Int(3)]);
+    let sql = "SELECT id, name FROM staff UNION SELECT gid, label FROM guests";
+    let rows = run_select(&executor, sql).unwrap();
+    assert_eq!(column(&rows, "name"), [text("ann"), text("bob"), text("cy")]);
+    
+    // INT64 and FLOAT64 widen to FLOAT64, so 20 and 20.0 are one row.
+    let sql = "SELECT id, pay FROM staff UNION SELECT gid, fee FROM guests ORDER BY pay";
+    let rows = run_select(&executor, sql).unwrap();
+    assert_eq!(
+        column(&rows, "pay"),
+        [Value::Float(7.5), Value::Float(10.0), Value::Float(20.0)]
+    );
+    
+    let err = run_select(&executor, "SELECT id FROM staff UNION SELECT gid, label FROM guests");
+    assert_eq!(err.unwrap_err(), "UNION inputs have different numbers of columns: 1 and 2");
+    let err = run_select(&executor, "SELECT name FROM staff UNION ALL SELECT fee FROM guests");
+    assert_eq!(err.unwrap_err(), "UNION column 'name' mixes types STRING and FLOAT64");
+}
+
+#[test]
+fn test_cost_based_access_path() {
+    let catalog = Arc::new(RwLock::new(Catalog::new()));
+    let mut executor = Executor::with_catalog(catalog.clone());
+    run_ddl(&mut executor, "CREATE TABLE nums (id INT, grp INT)").unwrap();
+    for id in 1..=100 {
+        let row = vec![("id".to_string(), Value::Int(id)), ("grp".to_string(), Value::Int(id % 4))];
+        executor.insert("nums", Row::new_with_values(row)).unwrap();
+    }
+    for (name, column) in [("nums_id", "id"), ("nums_grp", "grp")] {
+        executor
+            .create_index(IndexDef::new(name, "nums", vec![column], false))
+            .unwrap();
+    }
+    let index_of = |planner: &Planner, cond: &str| {
+        let (plan, _) = select_plan(&format!("SELECT * FROM nums WHERE {}", cond));
+        let mut plan = planner.to_physical(plan);
+        if let PhysicalPlan::Filter { input, .. } = plan {
+            plan = *input;
+        }
+        match plan {
+            PhysicalPlan::IndexScan { index, .. } => Some(index),
+            _ => None,
+        }
+    };
+    
+    // Without statistics, always a sequential scan.
+    let planner = Planner::with_catalog(catalog.clone());
+    assert_eq!(index_of(&planner, "id = 42"), None);
+    let explain = planner.explain(select_plan("SELECT * FROM nums WHERE id = 42").0);
+    assert_eq!(explain, "SeqScan nums filter (id = 42)\n");
+    
+    executor.analyze("nums").unwrap();
+    // One id in 100 distinct, or ids below 10 of 1..=100, read few rows.
+    assert_eq!(index_of(&planner, "id = 42").as_deref(), Some("nums_id"));
+    assert_eq!(index_of(&planner, "id < 10").as_deref(), Some("nums_id"));
+    assert_eq!(index_of(&planner, "grp = 1 AND id <= 5").as_deref(), Some("nums_id"));
+    // A quarter of the rows or more costs more through an index.
+    assert_eq!(index_of(&planner, "id > 10"), None);
+    assert_eq!(index_of(&planner, "grp = 1"), None);
+    
+    let explain = planner.explain(select_plan("SELECT id FROM nums WHERE id = 42").0);
+    assert_eq!(
+        explain,
+        "Project id\n  IndexScan nums using nums_id [42, 42] (rows=1 cost=4.0)\n"
+    );
+    let explain = planner.explain(select_plan("SELECT * FROM nums WHERE grp = 1").0);
+    assert_eq!(explain, "SeqScan nums filter (grp = 1) (rows=25 cost=100.0)\n");
+}
+
+#[test]
+fn test_in_and_exists_subqueries() {
+    let mut executor = items_executor();
+    run_ddl(&mut executor, "CREATE TABLE picks (item INT, note TEXT)").unwrap();
+    let pick = |item: Value| {
+        Row::new_with_values(vec![
+            ("item".to_string(), item),
+            ("note".to_string(), Value::String("x".to_string())),
+        ])
+    };
+    for item in [1, 3, 9] {
+        executor.insert("picks", pick(Value::Int(item))).unwrap();
+    }
+    
+    assert_eq!(ids_where(&executor, "id IN (SELECT item FROM picks)"), Ok(vec![1, 3]));
+    assert_eq!(ids_where(&executor, "id NOT IN (SELECT item FROM picks)"), Ok(vec![2, 4, 5]));
+    // qty is NULL for item 2, which neither IN nor NOT IN admits.
+    assert_eq!(ids_where(&executor, "qty IN (SELECT item FROM picks)"), Ok(vec![1]));
+    assert_eq!(ids_where(&executor, "qty NOT IN (SELECT item FROM picks)"), Ok(vec![3, 4, 5]));
+    let none = "(SELECT item FROM picks WHERE item > 100)";
+    assert_eq!(ids_where(&executor, &format!("qty NOT IN {}", none)), Ok(vec![1, 2, 3, 4, 5]));
+    
+    // Once the subquery yields a NULL, a value it lacks may still be it.
+    executor.insert("picks", pick(Value::Null)).unwrap();
+    assert_eq!(ids_where(&executor, "id IN (SELECT item FROM picks)"), Ok(vec![1, 3]));
+    assert_eq!(ids_where(&executor, "id NOT IN (SELECT item FROM picks)"), Ok(vec![]));
+    
+    assert_eq!(ids_where(&executor, "EXISTS (SELECT * FROM picks)"), Ok(vec![1, 2, 3, 4, 5]));
+    assert_eq!(ids_where(&executor, &format!("EXISTS {}", none)), Ok(vec![]));
+    assert_eq!(ids_where(&executor, &format!("NOT EXISTS {}", none)), Ok(vec![1, 2, 3, 4, 5]));
+    
+    assert_eq!(
+        ids_where(&executor, "EXISTS (SELECT * FROM picks WHERE item = qty)"),
+        Err("subquery refers to outer column 'qty'; only uncorrelated subqueries are supported"
+            .to_string())
+    );
+    assert_eq!(
+        ids_where(&executor, "id IN (SELECT item, note FROM picks)"),
+        Err("IN subquery must produce one column, got 2".to_string())
+    );
+    assert_eq!(
+        ids_where(&executor, "id IN (SELECT note FROM picks)"),
+        Err("incompatible types for IN: INT64 and STRING".to_string())
+    );
+}